@@ -16,6 +16,8 @@ fn run() -> Result<(), CliError> {
         token: cli.token,
         no_token: cli.no_token,
         gigacode: true,
+        output: cli.output,
+        field: cli.field,
     };
     let yolo = cli.yolo;
     let command = match cli.command {
@@ -10,7 +10,8 @@
  *   Claude  — Anthropic API (GET /v1/models?beta=true). Extracts API key from
  *             ANTHROPIC_API_KEY env. Falls back to aliases (default, sonnet, opus, haiku)
  *             on 401/403 or missing credentials.
- *   Codex   — Codex app-server JSON-RPC (model/list over stdio, paginated).
+ *   Codex   — Codex app-server JSON-RPC (model/list over stdio, paginated). Each model's
+ *             supported reasoning efforts are kept as its variants.
  *   OpenCode — OpenCode HTTP server (GET {base_url}/config/providers, fallback /provider).
  *             Model IDs formatted as {provider_id}/{model_id}.
  *   Cursor  — `cursor-agent models` CLI command. Parses the text output.
@@ -29,6 +30,7 @@ import * as readline from "node:readline";
 interface ModelEntry {
   id: string;
   name: string;
+  variants?: string[];
 }
 
 interface ModeEntry {
@@ -257,9 +259,14 @@ async function dumpCodex() {
         if (!modelId || seen.has(modelId)) continue;
         seen.add(modelId);
 
+        const variants = (item.supportedReasoningEfforts ?? [])
+          .map((effort: { reasoningEffort?: string }) => effort.reasoningEffort)
+          .filter((effort: string | undefined): effort is string => !!effort);
+
         models.push({
           id: modelId,
           name: item.displayName ?? modelId,
+          ...(variants.length > 0 ? { variants } : {}),
         });
 
         if (!defaultModel && item.isDefault) {
@@ -15,35 +15,65 @@ import {
 } from "acp-http-client";
 import type { SandboxAgentSpawnHandle, SandboxAgentSpawnOptions } from "./spawn.ts";
 import {
+  type AcpCheckpointInfo,
+  type AcpCheckpointListResponse,
+  type AcpCheckpointRequest,
+  type AcpCheckpointRestoreResponse,
+  type AcpCompactResponse,
+  type AcpForkRequest,
+  type AcpForkResponse,
   type AcpServerListResponse,
+  type AcpTurnListResponse,
+  type AcpUsageInfo,
   type AgentInfo,
   type AgentInstallRequest,
   type AgentInstallResponse,
   type AgentListResponse,
+  type AgentUninstallResponse,
+  type CapabilitiesResponse,
+  type DebugLogsQuery,
+  type DebugLogsResponse,
+  type ExecEvent,
+  type ExecRequest,
   type FsActionResponse,
+  type FsArchiveQuery,
   type FsDeleteQuery,
   type FsEntriesQuery,
   type FsEntry,
   type FsMoveRequest,
   type FsMoveResponse,
   type FsPathQuery,
+  type FsSearchEvent,
+  type FsSearchQuery,
   type FsStat,
   type FsUploadBatchQuery,
   type FsUploadBatchResponse,
+  type FsUploadChunkQuery,
+  type FsUploadCompleteResponse,
+  type FsUploadInitRequest,
+  type FsUploadStatus,
+  type FsWatchEvent,
+  type FsWatchQuery,
   type FsWriteResponse,
   type HealthResponse,
   InMemorySessionPersistDriver,
+  type InstructionTemplate,
+  type InstructionTemplateListResponse,
   type ListEventsRequest,
   type ListPage,
   type ListPageRequest,
   type McpConfigQuery,
   type McpServerConfig,
   type ProblemDetails,
+  type RateLimitEventsQuery,
+  type RateLimitEventsResponse,
   type SessionEvent,
   type SessionPersistDriver,
   type SessionRecord,
   type SkillsConfig,
   type SkillsConfigQuery,
+  type UnparsedEventsQuery,
+  type UnparsedEventsResponse,
 } from "./types.ts";
 
 const API_PREFIX = "/v1";
@@ -622,6 +652,10 @@ export class SandboxAgent {
     return this.requestJson("GET", `${API_PREFIX}/health`);
   }
 
+  async getCapabilities(): Promise<CapabilitiesResponse> {
+    return this.requestJson("GET", `${API_PREFIX}/capabilities`);
+  }
+
   async listAgents(options?: { config?: boolean }): Promise<AgentListResponse> {
     return this.requestJson("GET", `${API_PREFIX}/agents`, {
       query: options?.config ? { config: "true" } : undefined,
@@ -640,10 +674,72 @@ export class SandboxAgent {
     });
   }
 
+  async uninstallAgent(agent: string): Promise<AgentUninstallResponse> {
+    return this.requestJson("DELETE", `${API_PREFIX}/agents/${encodeURIComponent(agent)}`);
+  }
+
   async listAcpServers(): Promise<AcpServerListResponse> {
     return this.requestJson("GET", `${API_PREFIX}/acp`);
   }
 
+  async getAcpServerUsage(serverId: string): Promise<AcpUsageInfo> {
+    return this.requestJson("GET", `${API_PREFIX}/acp/${encodeURIComponent(serverId)}/usage`);
+  }
+
+  async compactAcpSession(serverId: string, sessionId: string): Promise<AcpCompactResponse> {
+    return this.requestJson(
+      "POST",
+      `${API_PREFIX}/acp/${encodeURIComponent(serverId)}/sessions/${encodeURIComponent(sessionId)}/compact`,
+    );
+  }
+
+  async listAcpTurns(serverId: string): Promise<AcpTurnListResponse> {
+    return this.requestJson("GET", `${API_PREFIX}/acp/${encodeURIComponent(serverId)}/turns`);
+  }
+
+  async getAcpTurnDiff(serverId: string, turnId: number): Promise<string> {
+    const response = await this.requestRaw(
+      "GET",
+      `${API_PREFIX}/acp/${encodeURIComponent(serverId)}/turns/${turnId}/diff`,
+      { accept: "text/x-diff" },
+    );
+    return await response.text();
+  }
+
+  async createAcpCheckpoint(
+    serverId: string,
+    request: AcpCheckpointRequest = {},
+  ): Promise<AcpCheckpointInfo> {
+    return this.requestJson(
+      "POST",
+      `${API_PREFIX}/acp/${encodeURIComponent(serverId)}/checkpoints`,
+      { body: request },
+    );
+  }
+
+  async listAcpCheckpoints(serverId: string): Promise<AcpCheckpointListResponse> {
+    return this.requestJson(
+      "GET",
+      `${API_PREFIX}/acp/${encodeURIComponent(serverId)}/checkpoints`,
+    );
+  }
+
+  async restoreAcpCheckpoint(
+    serverId: string,
+    checkpointId: string,
+  ): Promise<AcpCheckpointRestoreResponse> {
+    return this.requestJson(
+      "POST",
+      `${API_PREFIX}/acp/${encodeURIComponent(serverId)}/checkpoints/${encodeURIComponent(checkpointId)}/restore`,
+    );
+  }
+
+  async forkAcpServer(serverId: string, request: AcpForkRequest): Promise<AcpForkResponse> {
+    return this.requestJson("POST", `${API_PREFIX}/acp/${encodeURIComponent(serverId)}/fork`, {
+      body: request,
+    });
+  }
+
   async listFsEntries(query: FsEntriesQuery = {}): Promise<FsEntry[]> {
     return this.requestJson("GET", `${FS_PATH}/entries`, {
       query,
@@ -685,6 +781,52 @@ export class SandboxAgent {
     return this.requestJson("GET", `${FS_PATH}/stat`, { query });
   }
 
+  async *searchFs(query: FsSearchQuery, options: { signal?: AbortSignal } = {}): AsyncGenerator<FsSearchEvent> {
+    const response = await this.requestRaw("GET", `${FS_PATH}/search`, {
+      query,
+      accept: "application/x-ndjson",
+      signal: options.signal,
+    });
+    if (!response.body) {
+      return;
+    }
+
+    const reader = response.body.getReader();
+    const decoder = new TextDecoder();
+    let buffer = "";
+    while (true) {
+      const { done, value } = await reader.read();
+      buffer += decoder.decode(value, { stream: !done });
+      let newline = buffer.indexOf("\n");
+      while (newline >= 0) {
+        const line = buffer.slice(0, newline).trim();
+        buffer = buffer.slice(newline + 1);
+        if (line) {
+          yield JSON.parse(line) as FsSearchEvent;
+        }
+        newline = buffer.indexOf("\n");
+      }
+      if (done) {
+        return;
+      }
+    }
+  }
+
+  async *watchFs(query: FsWatchQuery = {}, options: { signal?: AbortSignal } = {}): AsyncGenerator<FsWatchEvent> {
+    const response = await this.requestRaw("GET", `${FS_PATH}/watch`, {
+      query,
+      accept: "text/event-stream",
+      signal: options.signal,
+    });
+    yield* readSseJson<FsWatchEvent>(response);
+  }
+
+  async downloadFsArchive(query: FsArchiveQuery): Promise<Uint8Array> {
+    const accept = query.format === "zip" ? "application/zip" : "application/gzip";
+    const response = await this.requestRaw("GET", `${FS_PATH}/archive`, { query, accept });
+    return new Uint8Array(await response.arrayBuffer());
+  }
+
   async uploadFsBatch(body: BodyInit, query?: FsUploadBatchQuery): Promise<FsUploadBatchResponse> {
     const response = await this.requestRaw("POST", `${FS_PATH}/upload-batch`, {
       query,
@@ -695,6 +837,65 @@ export class SandboxAgent {
     return (await response.json()) as FsUploadBatchResponse;
   }
 
+  async initFsUpload(request: FsUploadInitRequest): Promise<FsUploadStatus> {
+    return this.requestJson("POST", `${FS_PATH}/upload/init`, { body: request });
+  }
+
+  async getFsUpload(uploadId: string): Promise<FsUploadStatus> {
+    return this.requestJson("GET", `${FS_PATH}/upload/${encodeURIComponent(uploadId)}`);
+  }
+
+  async uploadFsChunk(uploadId: string, body: BodyInit, query: FsUploadChunkQuery): Promise<FsUploadStatus> {
+    const response = await this.requestRaw("PUT", `${FS_PATH}/upload/${encodeURIComponent(uploadId)}/chunk`, {
+      query,
+      rawBody: body,
+      contentType: "application/octet-stream",
+      accept: "application/json",
+    });
+    return (await response.json()) as FsUploadStatus;
+  }
+
+  async completeFsUpload(uploadId: string): Promise<FsUploadCompleteResponse> {
+    return this.requestJson("POST", `${FS_PATH}/upload/${encodeURIComponent(uploadId)}/complete`);
+  }
+
+  async abortFsUpload(uploadId: string): Promise<FsActionResponse> {
+    return this.requestJson("DELETE", `${FS_PATH}/upload/${encodeURIComponent(uploadId)}`);
+  }
+
+  async *exec(request: ExecRequest, options: { signal?: AbortSignal } = {}): AsyncGenerator<ExecEvent> {
+    const response = await this.requestRaw("POST", `${API_PREFIX}/exec`, {
+      body: request,
+      accept: "text/event-stream",
+      signal: options.signal,
+    });
+    yield* readSseJson<ExecEvent>(response);
+  }
+
+  async killExec(execId: string): Promise<void> {
+    await this.requestRaw("POST", `${API_PREFIX}/exec/${encodeURIComponent(execId)}/kill`);
+  }
+
+  async getDebugLogs(query: DebugLogsQuery = {}): Promise<DebugLogsResponse> {
+    return this.requestJson("GET", `${API_PREFIX}/debug/logs`, { query });
+  }
+
+  async getUnparsedEvents(query: UnparsedEventsQuery = {}): Promise<UnparsedEventsResponse> {
+    return this.requestJson("GET", `${API_PREFIX}/debug/unparsed`, { query });
+  }
+
+  async getRateLimitEvents(query: RateLimitEventsQuery = {}): Promise<RateLimitEventsResponse> {
+    return this.requestJson("GET", `${API_PREFIX}/debug/rate-limits`, { query });
+  }
+
+  proxyUrl(port: number, path = "/"): string {
+    return `${this.baseUrl}${API_PREFIX}/proxy/${port}${path.startsWith("/") ? path : `/${path}`}`;
+  }
+
+  async fetchProxy(port: number, path = "/", init: RequestInit = {}): Promise<Response> {
+    return this.fetcher(this.proxyUrl(port, path), { ...init, headers: this.buildHeaders(init.headers) });
+  }
+
   async getMcpConfig(query: McpConfigQuery): Promise<McpServerConfig> {
     return this.requestJson("GET", `${API_PREFIX}/config/mcp`, { query });
   }
@@ -719,6 +920,24 @@ export class SandboxAgent {
     await this.requestRaw("DELETE", `${API_PREFIX}/config/skills`, { query });
   }
 
+  async listInstructionTemplates(): Promise<InstructionTemplateListResponse> {
+    return this.requestJson("GET", `${API_PREFIX}/instructions/templates`);
+  }
+
+  async getInstructionTemplate(name: string): Promise<InstructionTemplate> {
+    return this.requestJson("GET", `${API_PREFIX}/instructions/templates/${encodeURIComponent(name)}`);
+  }
+
+  async setInstructionTemplate(name: string, content: string): Promise<InstructionTemplate> {
+    return this.requestJson("PUT", `${API_PREFIX}/instructions/templates/${encodeURIComponent(name)}`, {
+      body: { content },
+    });
+  }
+
+  async deleteInstructionTemplate(name: string): Promise<void> {
+    await this.requestRaw("DELETE", `${API_PREFIX}/instructions/templates/${encodeURIComponent(name)}`);
+  }
+
   private async getLiveConnection(agent: string): Promise<LiveAcpConnection> {
     const existing = this.liveConnections.get(agent);
     if (existing) {
@@ -967,6 +1186,38 @@ type RequestOptions = {
   signal?: AbortSignal;
 };
 
+/** Yield the JSON `data` payload of each SSE event in `response`. */
+async function* readSseJson<T>(response: Response): AsyncGenerator<T> {
+  if (!response.body) {
+    return;
+  }
+
+  const reader = response.body.getReader();
+  const decoder = new TextDecoder();
+  let buffer = "";
+  while (true) {
+    const { done, value } = await reader.read();
+    buffer += decoder.decode(value, { stream: !done });
+    let boundary = buffer.indexOf("\n\n");
+    while (boundary >= 0) {
+      const data = buffer
+        .slice(0, boundary)
+        .split("\n")
+        .filter((line) => line.startsWith("data:"))
+        .map((line) => line.slice(5).trimStart())
+        .join("\n");
+      buffer = buffer.slice(boundary + 2);
+      if (data) {
+        yield JSON.parse(data) as T;
+      }
+      boundary = buffer.indexOf("\n\n");
+    }
+    if (done) {
+      return;
+    }
+  }
+}
+
 /**
  * Auto-select and call `authenticate` based on the agent's advertised auth methods.
  * Prefers env-var-based methods that the server process already has configured.
@@ -25,38 +25,73 @@ export {
 } from "./types.ts";
 
 export type {
+  AcpCheckpointInfo,
+  AcpCheckpointListResponse,
+  AcpCheckpointRequest,
+  AcpCheckpointRestoreResponse,
+  AcpCompactResponse,
+  AcpContextWindowInfo,
   AcpEnvelope,
+  AcpForkRequest,
+  AcpForkResponse,
   AcpServerInfo,
   AcpServerListResponse,
+  AcpTurnInfo,
+  AcpTurnListResponse,
+  AcpUsageInfo,
   AgentInfo,
   AgentInstallRequest,
   AgentInstallResponse,
   AgentListResponse,
+  AgentUninstallResponse,
+  CapabilitiesResponse,
+  DebugLogRecord,
+  DebugLogsQuery,
+  DebugLogsResponse,
+  ExecEvent,
+  ExecRequest,
   FsActionResponse,
+  FsArchiveQuery,
   FsDeleteQuery,
   FsEntriesQuery,
   FsEntry,
   FsMoveRequest,
   FsMoveResponse,
   FsPathQuery,
+  FsSearchEvent,
+  FsSearchQuery,
   FsStat,
   FsUploadBatchQuery,
   FsUploadBatchResponse,
+  FsUploadChunkQuery,
+  FsUploadCompleteResponse,
+  FsUploadInitRequest,
+  FsUploadStatus,
+  FsWatchEvent,
+  FsWatchQuery,
   FsWriteResponse,
   HealthResponse,
   InMemorySessionPersistDriverOptions,
+  InstructionTemplate,
+  InstructionTemplateListResponse,
   ListEventsRequest,
   ListPage,
   ListPageRequest,
   McpConfigQuery,
   McpServerConfig,
   ProblemDetails,
+  RateLimitEvent,
+  RateLimitEventsQuery,
+  RateLimitEventsResponse,
   SessionEvent,
   SessionPersistDriver,
   SessionRecord,
   SkillsConfig,
   SkillsConfigQuery,
   UniversalEvent,
+  UnparsedEvent,
+  UnparsedEventsQuery,
+  UnparsedEventsResponse,
 } from "./types.ts";
 
 export type {
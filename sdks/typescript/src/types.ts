@@ -4,14 +4,30 @@ import type { components, operations } from "./generated/openapi.ts";
 export type ProblemDetails = components["schemas"]["ProblemDetails"];
 
 export type HealthResponse = JsonResponse<operations["get_v1_health"], 200>;
+export type CapabilitiesResponse = JsonResponse<operations["get_v1_capabilities"], 200>;
 export type AgentListResponse = JsonResponse<operations["get_v1_agents"], 200>;
 export type AgentInfo = components["schemas"]["AgentInfo"];
 export type AgentInstallRequest = JsonRequestBody<operations["post_v1_agent_install"]>;
 export type AgentInstallResponse = JsonResponse<operations["post_v1_agent_install"], 200>;
+export type AgentUninstallResponse = JsonResponse<operations["delete_v1_agent"], 200>;
 
 export type AcpEnvelope = components["schemas"]["AcpEnvelope"];
 export type AcpServerInfo = components["schemas"]["AcpServerInfo"];
 export type AcpServerListResponse = JsonResponse<operations["get_v1_acp_servers"], 200>;
+export type AcpForkRequest = JsonRequestBody<operations["post_v1_acp_fork"]>;
+export type AcpForkResponse = JsonResponse<operations["post_v1_acp_fork"], 200>;
+export type AcpUsageInfo = components["schemas"]["AcpUsageInfo"];
+export type AcpContextWindowInfo = components["schemas"]["AcpContextWindowInfo"];
+export type AcpCompactResponse = JsonResponse<operations["post_v1_acp_session_compact"], 200>;
+export type AcpTurnInfo = components["schemas"]["AcpTurnInfo"];
+export type AcpTurnListResponse = JsonResponse<operations["get_v1_acp_turns"], 200>;
+export type AcpCheckpointRequest = JsonRequestBody<operations["post_v1_acp_checkpoint"]>;
+export type AcpCheckpointInfo = JsonResponse<operations["post_v1_acp_checkpoint"], 201>;
+export type AcpCheckpointListResponse = JsonResponse<operations["get_v1_acp_checkpoints"], 200>;
+export type AcpCheckpointRestoreResponse = JsonResponse<
+  operations["post_v1_acp_checkpoint_restore"],
+  200
+>;
 
 export type FsEntriesQuery = QueryParams<operations["get_v1_fs_entries"]>;
 export type FsEntry = components["schemas"]["FsEntry"];
@@ -23,7 +39,29 @@ export type FsActionResponse = JsonResponse<operations["delete_v1_fs_entry"], 20
 export type FsMoveRequest = JsonRequestBody<operations["post_v1_fs_move"]>;
 export type FsMoveResponse = JsonResponse<operations["post_v1_fs_move"], 200>;
 export type FsStat = JsonResponse<operations["get_v1_fs_stat"], 200>;
+export type FsSearchQuery = QueryParams<operations["get_v1_fs_search"]>;
+export type FsArchiveQuery = QueryParams<operations["get_v1_fs_archive"]>;
+export type FsSearchEvent = components["schemas"]["FsSearchEvent"];
+export type FsWatchQuery = QueryParams<operations["get_v1_fs_watch"]>;
+export type FsWatchEvent = components["schemas"]["FsWatchEvent"];
 export type FsUploadBatchResponse = JsonResponse<operations["post_v1_fs_upload_batch"], 200>;
+export type FsUploadInitRequest = JsonRequestBody<operations["post_v1_fs_upload_init"]>;
+export type FsUploadStatus = JsonResponse<operations["post_v1_fs_upload_init"], 200>;
+export type FsUploadChunkQuery = QueryParams<operations["put_v1_fs_upload_chunk"]>;
+export type FsUploadCompleteResponse = JsonResponse<operations["post_v1_fs_upload_complete"], 200>;
+
+export type ExecRequest = JsonRequestBody<operations["post_v1_exec"]>;
+export type ExecEvent = components["schemas"]["ExecEvent"];
+
+export type DebugLogsQuery = QueryParams<operations["get_v1_debug_logs"]>;
+export type DebugLogRecord = components["schemas"]["DebugLogRecord"];
+export type DebugLogsResponse = JsonResponse<operations["get_v1_debug_logs"], 200>;
+export type UnparsedEventsQuery = QueryParams<operations["get_v1_debug_unparsed"]>;
+export type RateLimitEventsQuery = QueryParams<operations["get_v1_debug_rate_limits"]>;
+export type RateLimitEvent = components["schemas"]["RateLimitEvent"];
+export type RateLimitEventsResponse = JsonResponse<operations["get_v1_debug_rate_limits"], 200>;
+export type UnparsedEvent = components["schemas"]["UnparsedEvent"];
+export type UnparsedEventsResponse = JsonResponse<operations["get_v1_debug_unparsed"], 200>;
 
 export type McpConfigQuery = QueryParams<operations["get_v1_config_mcp"]>;
 export type McpServerConfig = components["schemas"]["McpServerConfig"];
@@ -31,6 +69,9 @@ export type McpServerConfig = components["schemas"]["McpServerConfig"];
 export type SkillsConfigQuery = QueryParams<operations["get_v1_config_skills"]>;
 export type SkillsConfig = components["schemas"]["SkillsConfig"];
 
+export type InstructionTemplate = components["schemas"]["InstructionTemplate"];
+export type InstructionTemplateListResponse = JsonResponse<operations["get_v1_instruction_templates"], 200>;
+
 export interface SessionRecord {
   id: string;
   agent: string;
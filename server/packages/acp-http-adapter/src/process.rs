@@ -11,11 +11,17 @@ use thiserror::Error;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, ChildStdin, Command};
 use tokio::sync::{broadcast, oneshot, Mutex};
-use tokio_stream::wrappers::BroadcastStream;
 
 use crate::registry::LaunchSpec;
 
-const RING_BUFFER_SIZE: usize = 1024;
+/// Number of recent events each runtime keeps for `Last-Event-ID` replay.
+pub const RING_BUFFER_SIZE: usize = 1024;
+
+/// SSE event name of the marker sent when a subscriber fell behind the live broadcast and
+/// was re-synced from the replay ring.
+pub const RESYNC_EVENT: &str = "stream.resync";
+/// Events [`AdapterRuntime::buffered_stream`] copies out of the ring per lock.
+const BUFFERED_PAGE_SIZE: usize = 64;
 
 #[derive(Debug, Error)]
 pub enum AdapterError {
@@ -37,12 +43,66 @@ pub enum AdapterError {
     Timeout,
 }
 
+/// Rewrites each message from the agent before it is matched to a request or buffered.
+pub type MessageFilter = Arc<dyn Fn(Value) -> Value + Send + Sync>;
+
+/// Picks how long to hold back each agent stdout line, for fault injection.
+#[cfg(feature = "test-utils")]
+pub type StdoutDelay = Arc<dyn Fn() -> Option<Duration> + Send + Sync>;
+
+#[cfg(feature = "test-utils")]
+#[derive(Clone, Default)]
+struct StdoutDelaySlot(Arc<std::sync::Mutex<Option<StdoutDelay>>>);
+
+#[cfg(feature = "test-utils")]
+impl StdoutDelaySlot {
+    fn next(&self) -> Option<Duration> {
+        let guard = self.0.lock().ok()?;
+        guard.as_ref().and_then(|delay| delay())
+    }
+}
+
+#[cfg(feature = "test-utils")]
+impl std::fmt::Debug for StdoutDelaySlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StdoutDelaySlot")
+    }
+}
+
 #[derive(Debug)]
 pub enum PostOutcome {
     Response(Value),
     Accepted,
 }
 
+/// What a live subscription yields: the next message, or a marker that the subscriber fell
+/// behind and the messages that follow were replayed from the ring.
+#[derive(Debug, Clone)]
+enum Live {
+    Message(StreamMessage),
+    /// `missed` messages after sequence `after` had already left the ring and are lost.
+    Resync {
+        after: u64,
+        missed: u64,
+    },
+}
+
+/// Lag recovered by subscribers that only take messages ([`AdapterRuntime::value_stream`]
+/// and [`AdapterRuntime::sequenced_stream`]), which have no way to pass a resync marker on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResyncStats {
+    /// How many times such a subscriber fell behind and was re-synced from the ring.
+    pub resyncs: u64,
+    /// Messages those subscribers lost because they had already left the ring.
+    pub missed: u64,
+}
+
+#[derive(Debug, Default)]
+struct ResyncCounter {
+    resyncs: AtomicU64,
+    missed: AtomicU64,
+}
+
 #[derive(Debug, Clone)]
 struct StreamMessage {
     sequence: u64,
@@ -53,20 +113,34 @@ struct StreamMessage {
 pub struct AdapterRuntime {
     stdin: Arc<Mutex<ChildStdin>>,
     child: Arc<Mutex<Child>>,
+    kill_signal: Mutex<Option<oneshot::Sender<()>>>,
     pending: Arc<Mutex<HashMap<String, oneshot::Sender<Value>>>>,
     sender: broadcast::Sender<StreamMessage>,
     ring: Arc<Mutex<VecDeque<StreamMessage>>>,
     sequence: Arc<AtomicU64>,
+    resyncs: Arc<ResyncCounter>,
     request_timeout: Duration,
     shutting_down: AtomicBool,
+    exited: Arc<AtomicBool>,
     spawned_at: Instant,
     first_stdout: Arc<AtomicBool>,
+    #[cfg(feature = "test-utils")]
+    stdout_delay: StdoutDelaySlot,
 }
 
 impl AdapterRuntime {
     pub async fn start(
         launch: LaunchSpec,
         request_timeout: Duration,
+    ) -> Result<Self, AdapterError> {
+        Self::start_with_filter(launch, request_timeout, None).await
+    }
+
+    /// Like `start`, passing every agent message through `filter` first.
+    pub async fn start_with_filter(
+        launch: LaunchSpec,
+        request_timeout: Duration,
+        filter: Option<MessageFilter>,
     ) -> Result<Self, AdapterError> {
         let spawn_start = Instant::now();
 
@@ -80,10 +154,14 @@ impl AdapterRuntime {
         for (key, value) in &launch.env {
             command.env(key, value);
         }
+        if let Some(cwd) = &launch.cwd {
+            command.current_dir(cwd);
+        }
 
         tracing::info!(
             program = ?launch.program,
             args = ?launch.args,
+            cwd = ?launch.cwd,
             "spawning agent process"
         );
 
@@ -109,22 +187,28 @@ impl AdapterRuntime {
         let stderr = child.stderr.take().ok_or(AdapterError::MissingStderr)?;
 
         let (sender, _rx) = broadcast::channel(512);
+        let (kill_tx, kill_rx) = oneshot::channel();
         let runtime = Self {
             stdin: Arc::new(Mutex::new(stdin)),
             child: Arc::new(Mutex::new(child)),
+            kill_signal: Mutex::new(Some(kill_tx)),
             pending: Arc::new(Mutex::new(HashMap::new())),
             sender,
             ring: Arc::new(Mutex::new(VecDeque::with_capacity(RING_BUFFER_SIZE))),
             sequence: Arc::new(AtomicU64::new(0)),
+            resyncs: Arc::default(),
             request_timeout,
             shutting_down: AtomicBool::new(false),
+            exited: Arc::new(AtomicBool::new(false)),
             spawned_at: spawn_start,
             first_stdout: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "test-utils")]
+            stdout_delay: StdoutDelaySlot::default(),
         };
 
-        runtime.spawn_stdout_loop(stdout);
+        runtime.spawn_stdout_loop(stdout, filter);
         runtime.spawn_stderr_loop(stderr);
-        runtime.spawn_exit_watcher();
+        runtime.spawn_exit_watcher(kill_rx);
 
         Ok(runtime)
     }
@@ -226,31 +310,87 @@ impl AdapterRuntime {
         }
     }
 
+    /// Returns the buffered events after `last_event_id` together with a live receiver and
+    /// the sequence the live stream must resume after. The receiver is created under the
+    /// ring lock, so every event is either replayed or received live, never both.
     async fn subscribe(
         &self,
         last_event_id: Option<u64>,
-    ) -> (Vec<(u64, Value)>, broadcast::Receiver<StreamMessage>) {
-        let replay = {
-            let ring = self.ring.lock().await;
-            ring.iter()
-                .filter(|message| {
-                    if let Some(last_event_id) = last_event_id {
-                        message.sequence > last_event_id
-                    } else {
-                        true
-                    }
-                })
-                .map(|message| (message.sequence, message.payload.clone()))
-                .collect::<Vec<_>>()
-        };
-        (replay, self.sender.subscribe())
+    ) -> (Vec<(u64, Value)>, broadcast::Receiver<StreamMessage>, u64) {
+        let ring = self.ring.lock().await;
+        let replay = replay_after(&ring, last_event_id);
+        let rx = self.sender.subscribe();
+        let cursor = replay
+            .last()
+            .map(|(sequence, _)| *sequence)
+            .or(last_event_id)
+            .unwrap_or(0);
+        (replay, rx, cursor)
+    }
+
+    /// Snapshot of the buffered `(sequence, payload)` events after `last_event_id`.
+    pub async fn buffered_events(&self, last_event_id: Option<u64>) -> Vec<(u64, Value)> {
+        let ring = self.ring.lock().await;
+        replay_after(&ring, last_event_id)
+    }
+
+    /// Sequence of the oldest buffered event after `last_event_id`. Anything between the two
+    /// has already left the ring.
+    pub async fn first_buffered_after(&self, last_event_id: Option<u64>) -> Option<u64> {
+        let ring = self.ring.lock().await;
+        let start = ring.partition_point(|message| message.sequence <= last_event_id.unwrap_or(0));
+        ring.get(start).map(|message| message.sequence)
+    }
+
+    /// Like [`AdapterRuntime::buffered_events`], but copied out of the ring a page at a time as
+    /// the stream is read. It ends at the last event published when it was created.
+    pub fn buffered_stream(
+        self: Arc<Self>,
+        last_event_id: Option<u64>,
+    ) -> impl Stream<Item = (u64, Value)> + Send + 'static {
+        let end = self.last_event_id();
+        stream::unfold(last_event_id.unwrap_or(0), move |cursor| {
+            let runtime = self.clone();
+            async move {
+                if cursor >= end {
+                    return None;
+                }
+                let page = {
+                    let ring = runtime.ring.lock().await;
+                    let start = ring.partition_point(|message| message.sequence <= cursor);
+                    ring.range(start..)
+                        .take_while(|message| message.sequence <= end)
+                        .take(BUFFERED_PAGE_SIZE)
+                        .map(|message| (message.sequence, message.payload.clone()))
+                        .collect::<Vec<_>>()
+                };
+                let next = page.last()?.0;
+                Some((stream::iter(page), next))
+            }
+        })
+        .flatten()
     }
 
     pub async fn sse_stream(
         self: Arc<Self>,
         last_event_id: Option<u64>,
     ) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
-        let (replay, rx) = self.subscribe(last_event_id).await;
+        self.filtered_sse_stream(last_event_id, |_| true).await
+    }
+
+    /// Like [`AdapterRuntime::sse_stream`] but only sends messages `keep` accepts. Skipped
+    /// messages still advance the cursor, so ids stay sequences and `Last-Event-ID` resumes
+    /// as usual; resync markers are always sent.
+    pub async fn filtered_sse_stream(
+        self: Arc<Self>,
+        last_event_id: Option<u64>,
+        keep: impl Fn(&Value) -> bool + Send + Sync + 'static,
+    ) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
+        let (replay, rx, cursor) = self.subscribe(last_event_id).await;
+        let replay = replay
+            .into_iter()
+            .filter(|(_, payload)| keep(payload))
+            .collect::<Vec<_>>();
         let replay_stream = stream::iter(replay.into_iter().map(|(sequence, payload)| {
             let event = Event::default()
                 .event("message")
@@ -259,17 +399,21 @@ impl AdapterRuntime {
             Ok(event)
         }));
 
-        let live_stream = BroadcastStream::new(rx).filter_map(|item| async move {
-            match item {
-                Ok(message) => {
-                    let event = Event::default()
+        let live_stream = live_after(self.ring.clone(), rx, cursor).filter_map(move |live| {
+            let event = match live {
+                Live::Message(message) => keep(&message.payload).then(|| {
+                    Event::default()
                         .event("message")
                         .id(message.sequence.to_string())
-                        .data(message.payload.to_string());
-                    Some(Ok(event))
-                }
-                Err(_) => None,
-            }
+                        .data(message.payload.to_string())
+                }),
+                Live::Resync { after, missed } => Some(
+                    Event::default()
+                        .event(RESYNC_EVENT)
+                        .data(json!({ "after": after, "missed": missed }).to_string()),
+                ),
+            };
+            async move { event.map(Ok) }
         });
 
         replay_stream.chain(live_stream)
@@ -282,17 +426,73 @@ impl AdapterRuntime {
         self: Arc<Self>,
         last_event_id: Option<u64>,
     ) -> impl Stream<Item = Value> + Send + 'static {
-        let (replay, rx) = self.subscribe(last_event_id).await;
+        let (replay, rx, cursor) = self.subscribe(last_event_id).await;
         let replay_stream = stream::iter(replay.into_iter().map(|(_sequence, payload)| payload));
-        let live_stream = BroadcastStream::new(rx).filter_map(|item| async move {
-            match item {
-                Ok(message) => Some(message.payload),
-                Err(_) => None,
-            }
-        });
+        let live_stream = live_messages(self.ring.clone(), rx, cursor, self.resyncs.clone())
+            .map(|message| message.payload);
         replay_stream.chain(live_stream)
     }
 
+    /// Like [`AdapterRuntime::value_stream`] but keeps each payload's sequence number.
+    pub async fn sequenced_stream(
+        self: Arc<Self>,
+        last_event_id: Option<u64>,
+    ) -> impl Stream<Item = (u64, Value)> + Send + 'static {
+        let (replay, rx, cursor) = self.subscribe(last_event_id).await;
+        let live_stream = live_messages(self.ring.clone(), rx, cursor, self.resyncs.clone())
+            .map(|message| (message.sequence, message.payload));
+        stream::iter(replay).chain(live_stream)
+    }
+
+    /// Publish `payloads` as history without sending them to the agent process, so
+    /// subscribers replay them like events this runtime emitted. Returns the last sequence.
+    pub async fn seed_history(&self, payloads: impl IntoIterator<Item = Value>) -> u64 {
+        let mut last = self.last_event_id();
+        for payload in payloads {
+            last = publish(&self.ring, &self.sequence, &self.sender, payload).await;
+        }
+        last
+    }
+
+    /// Sequence number of the most recent event published to SSE subscribers.
+    pub fn last_event_id(&self) -> u64 {
+        self.sequence.load(Ordering::SeqCst)
+    }
+
+    /// Resyncs seen so far by subscribers that only take messages.
+    pub fn resync_stats(&self) -> ResyncStats {
+        ResyncStats {
+            resyncs: self.resyncs.resyncs.load(Ordering::Relaxed),
+            missed: self.resyncs.missed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether the agent subprocess has exited (crashed, finished, or was killed).
+    pub fn has_exited(&self) -> bool {
+        self.exited.load(Ordering::SeqCst)
+    }
+
+    /// Delay agent stdout lines by what `delay` returns, from the next line on.
+    #[cfg(feature = "test-utils")]
+    pub fn set_stdout_delay(&self, delay: Option<StdoutDelay>) {
+        if let Ok(mut guard) = self.stdout_delay.0.lock() {
+            *guard = delay;
+        }
+    }
+
+    /// Kill the agent process as if it crashed. Unlike `shutdown`, pending requests are
+    /// left waiting and the exit is reported like any other.
+    #[cfg(feature = "test-utils")]
+    pub async fn crash(&self) {
+        tracing::warn!(
+            age_ms = self.spawned_at.elapsed().as_millis() as u64,
+            "fault injection: killing agent process"
+        );
+        if let Some(kill) = self.kill_signal.lock().await.take() {
+            let _ = kill.send(());
+        }
+    }
+
     pub async fn shutdown(&self) {
         if self.shutting_down.swap(true, Ordering::SeqCst) {
             return;
@@ -304,26 +504,26 @@ impl AdapterRuntime {
         );
 
         self.pending.lock().await.clear();
-        let mut child = self.child.lock().await;
-        match child.try_wait() {
-            Ok(Some(_)) => {}
-            Ok(None) => {
-                let _ = child.kill().await;
-                let _ = child.wait().await;
-            }
-            Err(_) => {
-                let _ = child.kill().await;
-            }
+        // The exit watcher owns the child while waiting on it, so ask it to
+        // kill the process instead of contending for the lock.
+        if let Some(kill) = self.kill_signal.lock().await.take() {
+            let _ = kill.send(());
         }
     }
 
-    fn spawn_stdout_loop(&self, stdout: tokio::process::ChildStdout) {
+    fn spawn_stdout_loop(
+        &self,
+        stdout: tokio::process::ChildStdout,
+        filter: Option<MessageFilter>,
+    ) {
         let pending = self.pending.clone();
         let sender = self.sender.clone();
         let ring = self.ring.clone();
         let sequence = self.sequence.clone();
         let spawned_at = self.spawned_at;
         let first_stdout = self.first_stdout.clone();
+        #[cfg(feature = "test-utils")]
+        let stdout_delay = self.stdout_delay.clone();
 
         tokio::spawn(async move {
             let mut lines = BufReader::new(stdout).lines();
@@ -335,6 +535,17 @@ impl AdapterRuntime {
                     continue;
                 }
 
+                #[cfg(feature = "test-utils")]
+                {
+                    if let Some(delay) = stdout_delay.next() {
+                        tracing::debug!(
+                            delay_ms = delay.as_millis() as u64,
+                            "fault injection: delaying agent stdout line"
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+
                 line_count += 1;
 
                 if !first_stdout.swap(true, Ordering::Relaxed) {
@@ -368,6 +579,10 @@ impl AdapterRuntime {
                         })
                     }
                 };
+                let payload = match &filter {
+                    Some(filter) => filter(payload),
+                    None => payload,
+                };
 
                 let is_response = payload.get("id").is_some() && payload.get("method").is_none();
                 if is_response {
@@ -385,19 +600,7 @@ impl AdapterRuntime {
                         // see it in order after preceding notifications. This lets the
                         // SSE translation task detect turn completion after all
                         // session/update events have been processed.
-                        let seq = sequence.fetch_add(1, Ordering::SeqCst) + 1;
-                        let message = StreamMessage {
-                            sequence: seq,
-                            payload,
-                        };
-                        {
-                            let mut guard = ring.lock().await;
-                            guard.push_back(message.clone());
-                            while guard.len() > RING_BUFFER_SIZE {
-                                guard.pop_front();
-                            }
-                        }
-                        let _ = sender.send(message);
+                        publish(&ring, &sequence, &sender, payload).await;
                         continue;
                     } else {
                         tracing::warn!(
@@ -418,21 +621,7 @@ impl AdapterRuntime {
                     "agent stdout: notification/event → SSE broadcast"
                 );
 
-                let seq = sequence.fetch_add(1, Ordering::SeqCst) + 1;
-                let message = StreamMessage {
-                    sequence: seq,
-                    payload,
-                };
-
-                {
-                    let mut guard = ring.lock().await;
-                    guard.push_back(message.clone());
-                    while guard.len() > RING_BUFFER_SIZE {
-                        guard.pop_front();
-                    }
-                }
-
-                let _ = sender.send(message);
+                publish(&ring, &sequence, &sender, payload).await;
             }
 
             tracing::info!(
@@ -468,19 +657,27 @@ impl AdapterRuntime {
         });
     }
 
-    fn spawn_exit_watcher(&self) {
+    fn spawn_exit_watcher(&self, kill_rx: oneshot::Receiver<()>) {
         let child = self.child.clone();
         let sender = self.sender.clone();
         let ring = self.ring.clone();
         let sequence = self.sequence.clone();
         let spawned_at = self.spawned_at;
         let pending = self.pending.clone();
+        let exited = self.exited.clone();
 
         tokio::spawn(async move {
             let status = {
                 let mut guard = child.lock().await;
-                guard.wait().await.ok()
+                tokio::select! {
+                    status = guard.wait() => status.ok(),
+                    _ = kill_rx => {
+                        let _ = guard.kill().await;
+                        guard.wait().await.ok()
+                    }
+                }
             };
+            exited.store(true, Ordering::SeqCst);
 
             let age_ms = spawned_at.elapsed().as_millis() as u64;
             let pending_count = pending.lock().await.len();
@@ -503,21 +700,7 @@ impl AdapterRuntime {
                     }
                 });
 
-                let seq = sequence.fetch_add(1, Ordering::SeqCst) + 1;
-                let message = StreamMessage {
-                    sequence: seq,
-                    payload,
-                };
-
-                {
-                    let mut guard = ring.lock().await;
-                    guard.push_back(message.clone());
-                    while guard.len() > RING_BUFFER_SIZE {
-                        guard.pop_front();
-                    }
-                }
-
-                let _ = sender.send(message);
+                publish(&ring, &sequence, &sender, payload).await;
             } else {
                 tracing::error!(
                     age_ms = age_ms,
@@ -562,6 +745,169 @@ impl AdapterRuntime {
     }
 }
 
+/// Appends `payload` to the replay ring and broadcasts it. Sequence assignment, ring
+/// insertion, and broadcast all happen under the ring lock, so sequences are strictly
+/// increasing in both the ring and the live stream.
+async fn publish(
+    ring: &Mutex<VecDeque<StreamMessage>>,
+    sequence: &AtomicU64,
+    sender: &broadcast::Sender<StreamMessage>,
+    payload: Value,
+) -> u64 {
+    let mut guard = ring.lock().await;
+    let seq = sequence.fetch_add(1, Ordering::SeqCst) + 1;
+    let message = StreamMessage {
+        sequence: seq,
+        payload,
+    };
+    guard.push_back(message.clone());
+    while guard.len() > RING_BUFFER_SIZE {
+        guard.pop_front();
+    }
+    let _ = sender.send(message);
+    seq
+}
+
+fn replay_after(ring: &VecDeque<StreamMessage>, last_event_id: Option<u64>) -> Vec<(u64, Value)> {
+    ring.iter()
+        .filter(|message| last_event_id.is_none_or(|last| message.sequence > last))
+        .map(|message| (message.sequence, message.payload.clone()))
+        .collect()
+}
+
+/// Live messages with a sequence greater than `cursor`, skipping anything already
+/// delivered so each sequence is yielded at most once. A subscriber that lags behind the
+/// broadcast is re-synced from `ring`: it gets a [`Live::Resync`] marker followed by the
+/// buffered messages it missed, then continues live.
+fn live_after(
+    ring: Arc<Mutex<VecDeque<StreamMessage>>>,
+    rx: broadcast::Receiver<StreamMessage>,
+    cursor: u64,
+) -> impl Stream<Item = Live> + Send + 'static {
+    let state = (rx, cursor, VecDeque::new());
+    stream::unfold(state, move |(mut rx, mut cursor, mut pending)| {
+        let ring = ring.clone();
+        async move {
+            while pending.is_empty() {
+                match rx.recv().await {
+                    Ok(message) if message.sequence > cursor => {
+                        cursor = message.sequence;
+                        pending.push_back(Live::Message(message));
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        let ring = ring.lock().await;
+                        let missed = ring
+                            .front()
+                            .map_or(0, |oldest| oldest.sequence.saturating_sub(cursor + 1));
+                        tracing::warn!(
+                            after = cursor,
+                            skipped = skipped,
+                            missed = missed,
+                            "subscriber lagged behind the event broadcast, re-syncing from the ring"
+                        );
+                        pending.push_back(Live::Resync {
+                            after: cursor,
+                            missed,
+                        });
+                        for message in ring.iter().filter(|message| message.sequence > cursor) {
+                            pending.push_back(Live::Message(message.clone()));
+                        }
+                        cursor = ring
+                            .back()
+                            .map_or(cursor, |newest| newest.sequence.max(cursor));
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+            let live = pending.pop_front()?;
+            Some((live, (rx, cursor, pending)))
+        }
+    })
+}
+
+/// [`live_after`] without the resync markers, for consumers that only want messages.
+/// Each marker is logged and counted in `resyncs` instead, since it cannot be passed on.
+fn live_messages(
+    ring: Arc<Mutex<VecDeque<StreamMessage>>>,
+    rx: broadcast::Receiver<StreamMessage>,
+    cursor: u64,
+    resyncs: Arc<ResyncCounter>,
+) -> impl Stream<Item = StreamMessage> + Send + 'static {
+    live_after(ring, rx, cursor).filter_map(move |live| {
+        let message = match live {
+            Live::Message(message) => Some(message),
+            Live::Resync { after, missed } => {
+                resyncs.resyncs.fetch_add(1, Ordering::Relaxed);
+                resyncs.missed.fetch_add(missed, Ordering::Relaxed);
+                tracing::warn!(
+                    after = after,
+                    missed = missed,
+                    "internal subscriber re-synced after lagging, events it missed are skipped"
+                );
+                None
+            }
+        };
+        async move { message }
+    })
+}
+
 fn id_key(value: &Value) -> String {
     serde_json::to_string(value).unwrap_or_else(|_| "null".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn message_only_subscribers_count_resyncs_and_missed_events() {
+        let ring = Arc::new(Mutex::new(VecDeque::new()));
+        let sequence = AtomicU64::new(0);
+        let (sender, rx) = broadcast::channel(2);
+        let resyncs = Arc::new(ResyncCounter::default());
+        for n in 0..(RING_BUFFER_SIZE as u64 + 3) {
+            publish(&ring, &sequence, &sender, json!({ "n": n })).await;
+        }
+
+        let mut live = Box::pin(live_messages(ring.clone(), rx, 0, resyncs.clone()));
+        let first = live.next().await.expect("re-synced message");
+
+        assert_eq!(first.sequence, 4);
+        assert_eq!(resyncs.resyncs.load(Ordering::Relaxed), 1);
+        assert_eq!(resyncs.missed.load(Ordering::Relaxed), 3);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn buffered_stream_pages_through_what_is_left_of_the_ring() {
+        let launch = LaunchSpec {
+            program: "cat".into(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            cwd: None,
+        };
+        let runtime = Arc::new(
+            AdapterRuntime::start(launch, Duration::from_secs(5))
+                .await
+                .expect("start cat"),
+        );
+        let total = RING_BUFFER_SIZE as u64 + 100;
+        runtime
+            .seed_history((1..=total).map(|n| json!({ "n": n })))
+            .await;
+
+        assert_eq!(runtime.first_buffered_after(None).await, Some(101));
+        assert_eq!(runtime.first_buffered_after(Some(500)).await, Some(501));
+        assert_eq!(runtime.first_buffered_after(Some(total)).await, None);
+        let events = runtime
+            .clone()
+            .buffered_stream(Some(200))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(events.len() as u64, total - 200);
+        assert_eq!(events[0], (201, json!({ "n": 201 })));
+        assert!(events.windows(2).all(|pair| pair[1].0 == pair[0].0 + 1));
+        runtime.shutdown().await;
+    }
+}
@@ -10,6 +10,8 @@ pub struct LaunchSpec {
     pub program: PathBuf,
     pub args: Vec<String>,
     pub env: HashMap<String, String>,
+    /// Working directory for the agent process; inherits the adapter's when unset.
+    pub cwd: Option<PathBuf>,
 }
 
 #[derive(Debug, Error)]
@@ -67,6 +69,7 @@ impl LaunchSpec {
                 program: PathBuf::from("npx"),
                 args,
                 env: npx.env,
+                cwd: None,
             });
         }
 
@@ -83,6 +86,7 @@ impl LaunchSpec {
                 program: PathBuf::from(&target.cmd),
                 args: target.args.clone(),
                 env: target.env.clone(),
+                cwd: None,
             });
         }
 
@@ -208,6 +208,11 @@ pub struct InstallResult {
     pub already_installed: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UninstallResult {
+    pub removed: Vec<PathBuf>,
+}
+
 #[derive(Debug, Clone)]
 pub struct InstallOptions {
     pub reinstall: bool,
@@ -251,6 +256,9 @@ pub struct AgentManager {
     install_dir: PathBuf,
     platform: Platform,
     registry_url: Url,
+    /// Binary whose `mock-agent` subcommand runs the mock agent; the current executable
+    /// when unset.
+    mock_agent_program: Option<PathBuf>,
 }
 
 impl AgentManager {
@@ -259,6 +267,7 @@ impl AgentManager {
             install_dir: install_dir.into(),
             platform: Platform::detect()?,
             registry_url: registry_url_from_env()?,
+            mock_agent_program: None,
         })
     }
 
@@ -270,9 +279,17 @@ impl AgentManager {
             install_dir: install_dir.into(),
             platform,
             registry_url,
+            mock_agent_program: None,
         }
     }
 
+    /// Run the mock agent as `program mock-agent`. Needed wherever the current executable is
+    /// not the sandbox-agent binary, such as test harnesses.
+    pub fn with_mock_agent_program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.mock_agent_program = Some(program.into());
+        self
+    }
+
     pub fn install_dir(&self) -> &Path {
         &self.install_dir
     }
@@ -358,6 +375,28 @@ impl AgentManager {
         })
     }
 
+    /// Remove the artifacts `install` manages for `agent`: the native binary, the ACP
+    /// launcher, and any extracted agent process files. Binaries resolved from PATH are
+    /// left alone. Returns the removed paths; empty when nothing was installed.
+    pub fn uninstall(&self, agent: AgentId) -> Result<UninstallResult, AgentError> {
+        let mut removed = Vec::new();
+
+        for path in [self.binary_path(agent), self.agent_process_path(agent)] {
+            if path.is_file() || path.is_symlink() {
+                fs::remove_file(&path)?;
+                removed.push(path);
+            }
+        }
+
+        let storage = self.agent_process_storage_dir(agent);
+        if storage.is_dir() {
+            fs::remove_dir_all(&storage)?;
+            removed.push(storage);
+        }
+
+        Ok(UninstallResult { removed })
+    }
+
     pub fn is_installed(&self, agent: AgentId) -> bool {
         let native_ok = !agent.native_required() || self.native_installed(agent);
         native_ok && self.agent_process_status(agent).is_some()
@@ -400,9 +439,14 @@ impl AgentManager {
         agent: AgentId,
     ) -> Result<AgentProcessLaunchSpec, AgentError> {
         if agent == AgentId::Mock {
+            // The mock agent is the `mock-agent` subcommand of the sandbox-agent binary.
+            let program = match &self.mock_agent_program {
+                Some(program) => program.clone(),
+                None => std::env::current_exe().unwrap_or_else(|_| self.agent_process_path(agent)),
+            };
             return Ok(AgentProcessLaunchSpec {
-                program: self.agent_process_path(agent),
-                args: Vec::new(),
+                program,
+                args: vec!["mock-agent".to_string()],
                 env: HashMap::new(),
                 source: InstallSource::Builtin,
                 version: Some("builtin".to_string()),
@@ -1594,6 +1638,55 @@ mod tests {
         assert_eq!(result.artifacts[0].source, InstallSource::Builtin);
     }
 
+    #[test]
+    fn mock_agent_runs_from_the_configured_program() {
+        let temp_dir = tempfile::tempdir().expect("create tempdir");
+        let manager = AgentManager::with_platform(temp_dir.path(), Platform::LinuxX64);
+        let spec = manager
+            .resolve_agent_process(AgentId::Mock)
+            .expect("resolve mock");
+        assert_eq!(spec.program, std::env::current_exe().expect("current exe"));
+        assert_eq!(spec.args, vec!["mock-agent".to_string()]);
+
+        let manager = manager.with_mock_agent_program("/opt/sandbox-agent");
+        let spec = manager
+            .resolve_agent_process(AgentId::Mock)
+            .expect("resolve mock");
+        assert_eq!(spec.program, PathBuf::from("/opt/sandbox-agent"));
+        assert_eq!(spec.args, vec!["mock-agent".to_string()]);
+    }
+
+    #[test]
+    fn uninstall_removes_managed_artifacts_only() {
+        let temp_dir = tempfile::tempdir().expect("create tempdir");
+        let manager = AgentManager::with_platform(temp_dir.path(), Platform::LinuxX64);
+
+        write_exec(
+            &manager.binary_path(AgentId::Codex),
+            "#!/usr/bin/env sh\nexit 0\n",
+        );
+        fs::create_dir_all(manager.agent_process_storage_dir(AgentId::Codex))
+            .expect("create storage dir");
+        write_exec(
+            &manager.agent_process_path(AgentId::Codex),
+            "#!/usr/bin/env sh\nexit 0\n",
+        );
+        write_exec(
+            &manager.binary_path(AgentId::Claude),
+            "#!/usr/bin/env sh\nexit 0\n",
+        );
+
+        let result = manager.uninstall(AgentId::Codex).expect("uninstall codex");
+        assert_eq!(result.removed.len(), 3);
+        assert!(!manager.binary_path(AgentId::Codex).exists());
+        assert!(!manager.agent_process_path(AgentId::Codex).exists());
+        assert!(!manager.agent_process_storage_dir(AgentId::Codex).exists());
+        assert!(manager.binary_path(AgentId::Claude).exists());
+
+        let again = manager.uninstall(AgentId::Codex).expect("uninstall again");
+        assert!(again.removed.is_empty());
+    }
+
     #[test]
     fn install_pi_skips_native_and_writes_fallback_npx_launcher() {
         let _env_lock = env_lock().lock().expect("env lock");
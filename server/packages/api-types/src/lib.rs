@@ -0,0 +1,2414 @@
+//! Request and response types of the sandbox-agent `/v1` HTTP API.
+//!
+//! The server derives its OpenAPI document from these types, and `sandbox-agent-client`
+//! uses them to decode responses, so both sides of the API share one definition.
+
+use std::collections::BTreeMap;
+
+use sandbox_agent_error::ProblemDetails;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Degraded,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthResponse {
+    /// `down` when a critical component is down, which is also answered with `503`;
+    /// `degraded` when any other component reports a problem.
+    pub status: HealthStatus,
+    pub uptime_ms: u64,
+    /// ACP servers currently running.
+    pub active_servers: usize,
+    /// ACP servers and running turns against their admission limits.
+    pub occupancy: AcpOccupancy,
+    /// Per-component detail, included with `verbose=true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub components: Option<HealthComponents>,
+}
+
+/// Admitted ACP servers and `session/prompt` turns. Limits are omitted when unset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpOccupancy {
+    pub servers: usize,
+    pub turns: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_servers: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<usize>,
+    /// Agents with a server, a running turn or a limit of their own.
+    #[serde(default)]
+    pub agents: Vec<AgentOccupancy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentOccupancy {
+    pub agent: String,
+    pub servers: usize,
+    pub turns: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_servers: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct HealthQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<bool>,
+}
+
+/// Readiness for traffic, as opposed to the liveness reported by `/v1/health`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessResponse {
+    /// Both checks below pass. Otherwise the response is a `503`.
+    pub ready: bool,
+    /// The daemon accepts connections.
+    pub serving: bool,
+    /// State left on disk by earlier runs, such as archived ACP servers, has been loaded.
+    pub persistence_loaded: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthComponents {
+    pub opencode_server: ManagedServerHealth,
+    /// Agent processes of running ACP servers, by agent.
+    pub agent_processes: Vec<AgentProcessHealth>,
+    pub credentials: Vec<CredentialHealth>,
+    /// Free space where workspaces and logs are written. Critical.
+    pub disks: Vec<DiskHealth>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedServerHealth {
+    /// `degraded` when the server exited and has not been started again.
+    pub status: HealthStatus,
+    /// The server is started on first use, so not running is healthy.
+    pub running: bool,
+    pub starts: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentProcessHealth {
+    pub agent: String,
+    /// `degraded` when an agent process exited while its server is still open.
+    pub status: HealthStatus,
+    pub running: usize,
+    pub exited: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialHealth {
+    pub provider: String,
+    pub available: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskHealth {
+    /// `workspace` or `logs`.
+    pub name: String,
+    pub path: String,
+    /// `down` below `SANDBOX_AGENT_HEALTH_MIN_FREE_MB` of free space.
+    pub status: HealthStatus,
+    /// Unset where free space cannot be measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerStatus {
+    Running,
+    Stopped,
+    /// Crashed too often to be restarted; see `POST /v1/agents/{agent}/server/restart`.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatusInfo {
+    /// `running` while any instance runs, else `failed` when one has failed.
+    pub status: ServerStatus,
+    /// Uptime of the longest running instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uptime_ms: Option<u64>,
+    /// The agent's ACP servers and, for OpenCode, the managed OpenCode servers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<ServerInstanceStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerInstanceStatus {
+    /// The ACP server id, or `opencode-server-<n>` for a managed OpenCode server.
+    pub id: String,
+    /// `stopped` once the process exited, `failed` once it crashed too often to be restarted.
+    pub status: ServerStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uptime_ms: Option<u64>,
+    /// OpenCode project directories routed to a managed OpenCode server.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directories: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentCapabilities {
+    pub plan_mode: bool,
+    pub permissions: bool,
+    pub questions: bool,
+    pub tool_calls: bool,
+    pub tool_results: bool,
+    pub text_messages: bool,
+    pub images: bool,
+    pub file_attachments: bool,
+    pub session_lifecycle: bool,
+    pub error_events: bool,
+    pub reasoning: bool,
+    pub status: bool,
+    pub command_execution: bool,
+    pub file_changes: bool,
+    pub mcp_tools: bool,
+    pub streaming_deltas: bool,
+    pub item_started: bool,
+    pub shared_process: bool,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentCapabilitiesSource {
+    /// The table compiled into the daemon.
+    #[default]
+    Builtin,
+    /// The table adjusted by probing the installed agent.
+    Probed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentCapabilitiesResponse {
+    pub agent: String,
+    pub capabilities: AgentCapabilities,
+    pub source: AgentCapabilitiesSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probed_at_ms: Option<i64>,
+    /// Capabilities set by the `SANDBOX_AGENT_AGENT_CAPABILITIES_FILE` overrides.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentInfo {
+    pub id: String,
+    pub installed: bool,
+    pub credentials_available: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub capabilities: AgentCapabilities,
+    #[serde(default)]
+    pub capabilities_source: AgentCapabilitiesSource,
+    /// Capabilities set by the `SANDBOX_AGENT_AGENT_CAPABILITIES_FILE` overrides.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capability_overrides: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_status: Option<ServerStatusInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_options: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentListResponse {
+    pub agents: Vec<AgentInfo>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AgentsQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_cache: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelCatalogSource {
+    /// Listed by the agent's backend at runtime.
+    Live,
+    /// The list compiled into the daemon.
+    Builtin,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentModel {
+    pub id: String,
+    pub name: String,
+    /// Context window in tokens, when the agent's backend reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u64>,
+    /// Reasoning levels the model can run at, when the agent's backend reports them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_variants: Option<Vec<String>>,
+}
+
+/// The models offered by an agent's `model` config option.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentModelsResponse {
+    pub agent: String,
+    pub source: ModelCatalogSource,
+    /// When a live list was fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched_at_ms: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+    pub models: Vec<AgentModel>,
+}
+
+/// One model of one agent in `GET /v1/models`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogModel {
+    pub agent: String,
+    pub id: String,
+    pub name: String,
+    /// The agent's default model.
+    pub default: bool,
+    pub source: ModelCatalogSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_variants: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelListResponse {
+    /// Models of every installed agent, grouped by agent.
+    pub models: Vec<CatalogModel>,
+}
+
+/// Settings shared by the top level of Codex's `config.toml` and each `[profiles.*]` table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CodexProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Key of a `modelProviders` entry, or a provider built into Codex such as `openai`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_provider: Option<String>,
+    /// `untrusted`, `on-failure`, `on-request` or `never`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_policy: Option<String>,
+    /// `read-only`, `workspace-write` or `danger-full-access`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_mode: Option<String>,
+    /// `minimal`, `low`, `medium` or `high`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_reasoning_effort: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CodexModelProvider {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Environment variable holding the provider's API key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_key: Option<String>,
+    /// `chat` or `responses`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wire_api: Option<String>,
+}
+
+/// The part of Codex's `config.toml` managed through `/v1/agents/codex/config`. Other keys
+/// in the file, and comments, are left as they are.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CodexConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_policy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_reasoning_effort: Option<String>,
+    /// Instructions Codex adds to its system prompt in every session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub developer_instructions: Option<String>,
+    /// Profile Codex uses when none is selected; must be a key of `profiles`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, CodexProfile>,
+    #[serde(default)]
+    pub model_providers: BTreeMap<String, CodexModelProvider>,
+}
+
+/// `GET /v1/agents/{agent}/config`: Codex's managed settings, or the managed OpenCode
+/// server's `/config` document as it returns it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(untagged)]
+pub enum AgentConfig {
+    Codex(CodexConfig),
+    Opencode(Value),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentInstallRequest {
+    pub reinstall: Option<bool>,
+    pub agent_version: Option<String>,
+    pub agent_process_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct AgentInstallArtifact {
+    pub kind: String,
+    pub path: String,
+    pub source: String,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct AgentInstallResponse {
+    pub already_installed: bool,
+    pub artifacts: Vec<AgentInstallArtifact>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct AgentUninstallResponse {
+    /// Paths removed from the install directory; empty when nothing was installed.
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsPathQuery {
+    pub path: String,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsEntriesQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub respect_gitignore: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_globs: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<bool>,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema,
+)]
+pub enum FsArchiveFormat {
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "zip")]
+    Zip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsArchiveQuery {
+    /// Directory to archive.
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<FsArchiveFormat>,
+    /// Skip paths ignored by git (default false, so build outputs are included).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub respect_gitignore: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_globs: Option<String>,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsSearchQuery {
+    /// Text to search for, or a regular expression when `regex` is set.
+    pub q: String,
+    /// File or directory to search; defaults to the workspace root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_sensitive: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub respect_gitignore: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_globs: Option<String>,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+/// One line of a `/v1/fs/search` response. Matches stream as they are found and a single
+/// `done` event closes the search.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FsSearchEvent {
+    #[serde(rename_all = "camelCase")]
+    Match {
+        path: String,
+        /// 1-based line number.
+        line: u64,
+        /// 1-based byte column of the first match on the line.
+        column: u64,
+        text: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Done {
+        matches: usize,
+        files_scanned: usize,
+        /// `maxResults` was reached before the walk finished.
+        truncated: bool,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsWatchQuery {
+    /// File or directory to watch (defaults to the workspace root).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Watch subdirectories too (default true).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recursive: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub respect_gitignore: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_globs: Option<String>,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+/// One `/v1/fs/watch` SSE event. Renames arrive as a `delete` of the old path and a `create`
+/// of the new one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FsWatchEvent {
+    #[serde(rename_all = "camelCase")]
+    Create { path: String, is_dir: bool },
+    #[serde(rename_all = "camelCase")]
+    Modify { path: String },
+    #[serde(rename_all = "camelCase")]
+    Delete { path: String },
+    /// Events were lost; re-list the watched tree.
+    Rescan,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsDeleteQuery {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recursive: Option<bool>,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsUploadBatchQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub respect_gitignore: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_globs: Option<String>,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FsEntryType {
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsEntry {
+    pub name: String,
+    pub path: String,
+    pub entry_type: FsEntryType,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_binary: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsStat {
+    pub path: String,
+    pub entry_type: FsEntryType,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsWriteResponse {
+    pub path: String,
+    pub bytes_written: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsMoveRequest {
+    pub from: String,
+    pub to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overwrite: Option<bool>,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsMoveResponse {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsActionResponse {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsUploadBatchResponse {
+    pub paths: Vec<String>,
+    pub truncated: bool,
+    #[serde(default)]
+    pub skipped: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsUploadInitRequest {
+    /// Destination file path.
+    pub path: String,
+    /// Total size in bytes. When set, chunks may not exceed it and completion requires it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Hex SHA-256 of the whole file, verified on completion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Replace an existing file (default false).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overwrite: Option<bool>,
+    /// Resolve paths inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsUploadStatus {
+    pub upload_id: String,
+    pub path: String,
+    /// Bytes received so far; the offset to resume from.
+    pub received_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsUploadChunkQuery {
+    /// Byte offset of this chunk. May not exceed the bytes received so far.
+    pub offset: u64,
+    /// Hex SHA-256 of this chunk.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FsUploadCompleteResponse {
+    pub path: String,
+    pub bytes_written: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecRequest {
+    /// Program to run. It is not passed through a shell; use `sh -c` for pipelines.
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Working directory, resolved like `/v1/fs` paths (default `.`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Resolve `cwd` inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    /// Extra environment variables on top of the daemon's own.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Kill the command after this long (default 600000, max 3600000).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Stop forwarding stdout and stderr after this many bytes in total (default 16 MiB).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_bytes: Option<u64>,
+    /// Address space limit for the command (`RLIMIT_AS`). Unix only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_bytes: Option<u64>,
+    /// CPU time limit for the command (`RLIMIT_CPU`). Unix only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cpu_seconds: Option<u64>,
+}
+
+/// One `/v1/exec` SSE event. A stream starts with `start` and ends with `exit`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ExecEvent {
+    #[serde(rename_all = "camelCase")]
+    Start {
+        exec_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pid: Option<u32>,
+        cwd: String,
+    },
+    /// Output as UTF-8; invalid bytes are replaced.
+    Stdout {
+        data: String,
+    },
+    Stderr {
+        data: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Exit {
+        /// Null when the command was ended by a signal.
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+        timed_out: bool,
+        /// Killed through `/v1/exec/{exec_id}/kill`, a closed stream, or server shutdown.
+        killed: bool,
+        /// `maxOutputBytes` was reached and later output was dropped.
+        output_truncated: bool,
+        duration_ms: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GithubCloneRequest {
+    /// Repository as `owner/name`.
+    pub repo: String,
+    /// Branch, tag or commit to check out; the default branch when unset.
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Token for private repositories; `GITHUB_TOKEN` or `GH_TOKEN` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Directory to clone into, which must be missing or empty. Defaults to the working
+    /// directory of `serverId`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Resolve `path` inside this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GithubCloneResponse {
+    pub repo: String,
+    pub path: String,
+    /// Checked out commit.
+    pub commit: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GithubPullRequestRequest {
+    /// Defaults to the first line of the session's first prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Defaults to a summary of the session transcript.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Branch to push; `sandbox-agent/{session_id}` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Branch to merge into; the checked out branch when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// Defaults to the title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft: Option<bool>,
+    /// `GITHUB_TOKEN` or `GH_TOKEN` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GithubPullRequestResponse {
+    pub repo: String,
+    pub number: u64,
+    pub url: String,
+    pub branch: String,
+    pub base: String,
+    /// Head commit of the pushed branch.
+    pub commit: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum GitOperation {
+    Push,
+    Pull,
+}
+
+/// Credentials for one push or pull. They are handed to git for that command only and never
+/// written to the repository's config.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GitCredentials {
+    /// Username sent with `token` over HTTPS (default `git`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Token or password for HTTPS remotes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Private key in OpenSSH format for SSH remotes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_key: Option<String>,
+    /// `known_hosts` lines to verify the SSH host against. Unset, the host key is accepted on
+    /// first use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub known_hosts: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GitSyncRequest {
+    /// Work in this ACP server's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    /// Repository directory, resolved like `/v1/fs` paths (default `.`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Remote name or URL (default `origin`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// Remote branch to push `HEAD` to or pull from. Unset, a push goes to the branch of the
+    /// same name and a pull follows the branch's upstream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Push with `--force-with-lease`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force: Option<bool>,
+    /// Pull with `--rebase` instead of `--ff-only`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rebase: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<GitCredentials>,
+    /// Kill git after this long (default 600000, max 3600000).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+/// One `/v1/git/push` or `/v1/git/pull` SSE event. A stream starts with `start` and ends
+/// with `done`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GitEvent {
+    /// `execId` can be passed to `/v1/exec/{exec_id}/kill`.
+    #[serde(rename_all = "camelCase")]
+    Start {
+        exec_id: String,
+        operation: GitOperation,
+        cwd: String,
+        remote: String,
+    },
+    /// A step git reports progress for, such as `Writing objects` or `Receiving objects`.
+    Progress {
+        phase: String,
+        percent: u8,
+        current: u64,
+        total: u64,
+    },
+    /// Any other line git printed.
+    Output { line: String },
+    #[serde(rename_all = "camelCase")]
+    Done {
+        success: bool,
+        /// Null when git was ended by a signal.
+        exit_code: Option<i32>,
+        timed_out: bool,
+        killed: bool,
+        duration_ms: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpPostQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_pool: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    /// Run the agent in this registered workspace instead of `directory`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace_id: Option<String>,
+    /// Record a workspace diff for `session/prompt` turns: for every turn when set on the
+    /// bootstrap POST, or for this request only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_turns: Option<bool>,
+    /// Shut the server down after this long without activity, overriding the daemon
+    /// default. Only read on the bootstrap POST; 0 disables the timeout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_ms: Option<u64>,
+    /// CPUs the agent process may use, e.g. `0.5`, overriding the daemon default. Only read
+    /// on the bootstrap POST; 0 removes the limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_limit: Option<f64>,
+    /// Memory limit for the agent process in MiB, overriding the daemon default. Only read
+    /// on the bootstrap POST; 0 removes the limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u64>,
+    /// Maximum number of processes the agent may run, overriding the daemon default. Only
+    /// read on the bootstrap POST; 0 removes the limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process_limit: Option<u64>,
+    /// Confine the agent process: `none`, `workspace` (writes limited to its working and
+    /// config directories) or `strict` (reads limited too). Linux only, overriding the daemon
+    /// default. Only read on the bootstrap POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_level: Option<String>,
+    /// Comma-separated domains and CIDRs the agent may reach through its egress proxy,
+    /// replacing the daemon list; empty allows everything not denied. Only read on the
+    /// bootstrap POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub egress_allow: Option<String>,
+    /// Comma-separated domains and CIDRs the agent may not reach, replacing the daemon list.
+    /// Only read on the bootstrap POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub egress_deny: Option<String>,
+    /// Extra webhook URL for this server's events. Only read on the bootstrap POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    /// Display title for the server. Only read on the bootstrap POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Comma-separated `key=value` labels. Only read on the bootstrap POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpServerListQuery {
+    /// Comma-separated `key=value` labels; only servers carrying all of them are listed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Only servers running this agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// Only servers created after this time, in Unix milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_after_ms: Option<i64>,
+    /// Servers to return per page (1-1000). Without it every match is returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// `nextCursor` from the previous page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<AcpServerSort>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<SortOrder>,
+    /// List archived servers instead of running ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum AcpServerSort {
+    #[default]
+    ServerId,
+    CreatedAt,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Changes to a server's title and labels. Omitted fields are left as they are.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpServerUpdateRequest {
+    /// New title; an empty string clears it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Labels to set, merged into the existing labels; a `null` value removes the label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, Option<String>>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpEventsExportQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<u64>,
+    /// Comma-separated JSON-RPC methods to include, or `response` for results and errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub types: Option<String>,
+    /// Comma-separated `sessionUpdate` kinds of `session/update` notifications to include.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kinds: Option<String>,
+    /// Drop the `_sandboxagent/*` notifications the daemon adds to the stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_synthetic: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpEventsStreamQuery {
+    /// Start after the event with this sequence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    /// Comma-separated JSON-RPC methods to include, or `response` for results and errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub types: Option<String>,
+    /// Comma-separated `sessionUpdate` kinds of `session/update` notifications to include.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kinds: Option<String>,
+    /// Drop the `_sandboxagent/*` notifications the daemon adds to the stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_synthetic: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpEventsSseQuery {
+    /// Comma-separated JSON-RPC methods to include, or `response` for results and errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub types: Option<String>,
+    /// Comma-separated `sessionUpdate` kinds of `session/update` notifications to include.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kinds: Option<String>,
+    /// Drop the `_sandboxagent/*` notifications the daemon adds to the stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_synthetic: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AcpTurnFileChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpTurnFileChange {
+    /// Path relative to `root`.
+    pub path: String,
+    pub change: AcpTurnFileChangeKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpTurnInfo {
+    pub turn_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Directory that was snapshotted.
+    pub root: String,
+    pub started_at_ms: i64,
+    pub ended_at_ms: i64,
+    pub files: Vec<AcpTurnFileChange>,
+    /// A snapshot hit the file limit, so the diff may be incomplete.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpTurnListResponse {
+    pub turns: Vec<AcpTurnInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AcpTaskStatus {
+    Pending,
+    InProgress,
+    Completed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpTask {
+    /// The agent's todo id, or the task's 1-based position when the agent gives none.
+    pub id: String,
+    pub text: String,
+    pub status: AcpTaskStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpTaskListResponse {
+    pub session_id: String,
+    pub tasks: Vec<AcpTask>,
+    /// Sequence of the event that last changed the list; absent when the agent sent none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AcpItemKind {
+    UserMessage,
+    AgentMessage,
+    Reasoning,
+    ToolCall,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AcpItemStatus {
+    InProgress,
+    Completed,
+    Failed,
+}
+
+/// One entry of a session transcript, folded from its ACP events.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpItem {
+    /// The tool call id for tool calls; `{kind}-{sequence}` otherwise.
+    pub item_id: String,
+    pub kind: AcpItemKind,
+    pub status: AcpItemStatus,
+    /// Text of a message or reasoning item, with its chunks joined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// ACP content blocks other than text, or a tool call's latest content.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_input: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_output: Option<Value>,
+    /// The sub-agent tool call that made this tool call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_tool_call_id: Option<String>,
+    /// Sequence of the item's first event; for prompts sent through the daemon, of the last
+    /// event before the prompt.
+    pub sequence: u64,
+    /// Sequence of the item's latest event.
+    pub updated_sequence: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpItemListResponse {
+    pub session_id: String,
+    pub items: Vec<AcpItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AcpSessionTurnStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// One `session/prompt` sent through the daemon, from the prompt to its response.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpSessionTurn {
+    /// `turn-{n}`, numbered in the order prompts were sent to the server.
+    pub turn_id: String,
+    pub status: AcpSessionTurnStatus,
+    pub started_at_ms: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ended_at_ms: Option<i64>,
+    /// The user message item of the prompt that started the turn.
+    pub prompt_item_id: String,
+    /// `stopReason` of the prompt's result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    /// `usage` of the prompt's result, as the agent reported it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Value>,
+    /// The prompt and the items the agent produced for it, in transcript order.
+    pub items: Vec<AcpItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpSessionTurnListResponse {
+    pub session_id: String,
+    pub turns: Vec<AcpSessionTurn>,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum AcpTranscriptFormat {
+    #[default]
+    Markdown,
+    Html,
+    Json,
+    /// Findings the agent reported, as a SARIF 2.1.0 log.
+    Sarif,
+    /// Findings the agent reported, as a JUnit XML report.
+    Junit,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpTranscriptExportQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<AcpTranscriptFormat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpChildItem {
+    pub tool_call_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Sub-agent type when the child is itself a sub-agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_type: Option<String>,
+    /// Sequence of the event that started the child.
+    pub sequence: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpItemChildrenResponse {
+    pub session_id: String,
+    pub item: AcpChildItem,
+    /// Tool calls the sub-agent made, in the order they started.
+    pub children: Vec<AcpChildItem>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpCheckpointRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpPlanDecisionRequest {
+    /// Permission option to select on the agent's plan review request instead of the first
+    /// option that allows (or rejects) it once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option_id: Option<String>,
+    /// Mode to switch an approved plan to when the agent is not waiting on a review request;
+    /// defaults to the mode the session had before it entered plan mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpCheckpointInfo {
+    pub checkpoint_id: String,
+    /// Directory that was checkpointed.
+    pub root: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub created_at_ms: i64,
+    pub file_count: usize,
+    pub total_bytes: u64,
+    /// The directory had more files than a checkpoint records, so restoring it only rewrites the
+    /// recorded files and deletes nothing.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpCheckpointListResponse {
+    pub checkpoints: Vec<AcpCheckpointInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpCheckpointRestoreResponse {
+    pub checkpoint_id: String,
+    pub root: String,
+    /// Files written back, relative to `root`.
+    pub restored: Vec<String>,
+    /// Files removed because the checkpoint did not have them, relative to `root`.
+    pub deleted: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalKind {
+    Permission,
+    Question,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalInfo {
+    pub server_id: String,
+    pub agent: String,
+    pub kind: ApprovalKind,
+    /// JSON-RPC id of the agent's request; pass it back unchanged when replying.
+    pub request_id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Event id of the request in the server's event stream.
+    pub sequence: u64,
+    pub requested_at_ms: i64,
+    pub age_ms: i64,
+    /// Tool call title for permissions, first question text for questions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// The request's JSON-RPC params, including permission `options`.
+    pub params: Value,
+    /// Held for the external approver, which answers it through `/v1/approvals/callback`.
+    #[serde(default)]
+    pub routed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalListResponse {
+    pub approvals: Vec<ApprovalInfo>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalDecision {
+    /// Allow once, or answer a question.
+    Once,
+    /// Allow for the rest of the session.
+    Always,
+    Reject,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalReply {
+    pub server_id: String,
+    pub request_id: Value,
+    pub reply: ApprovalDecision,
+    /// Permission option to select instead of the first option matching `reply`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option_id: Option<String>,
+    /// Answers for a question, one array of selected labels per question.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answers: Option<Value>,
+}
+
+/// An external approver's answer to a permission request routed to it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalCallbackRequest {
+    /// `token` of the `permission.escalated` event.
+    pub token: String,
+    pub reply: ApprovalDecision,
+    /// Permission option to select instead of the first option matching `reply`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalReplyRequest {
+    pub replies: Vec<ApprovalReply>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalReplyResult {
+    pub server_id: String,
+    pub request_id: Value,
+    pub ok: bool,
+    /// Why the reply was not delivered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProblemDetails>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalReplyResponse {
+    /// One result per reply, in request order.
+    pub results: Vec<ApprovalReplyResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditKind {
+    PermissionReply,
+    QuestionAnswer,
+    /// File writes, directory creation and uploads.
+    FsWrite,
+    FsDelete,
+    FsMove,
+    Exec,
+    /// Branches pushed and pull requests opened through `/scm/github/pr`.
+    PullRequest,
+    /// Pushes and pulls through `/v1/git`.
+    Git,
+    /// Connections of an agent refused by its egress policy.
+    EgressBlocked,
+    /// Managed agent servers started, stopped or restarted through `/v1/agents/{agent}/server`.
+    AgentServer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Position in the log, starting at 1.
+    pub id: u64,
+    pub timestamp_ms: i64,
+    pub kind: AuditKind,
+    /// `anonymous` without auth, `admin` for the `--token` token, `token:{name}` for named tokens,
+    /// `agent:{agent}` for blocked egress.
+    pub actor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Tool call title or question for replies, command line for exec.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// Selected permission option kind (`allow_once`, `reject_once`, ...), `cancelled`,
+    /// `answered` or `rejected`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<String>,
+    /// Affected path, or the working directory for exec.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Destination of a move.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Kind-specific details: request id and option or answers for replies, exec id and
+    /// arguments for exec, byte counts for writes.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub details: Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<AuditKind>,
+    /// Only entries at or after this time, in Unix milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since_ms: Option<i64>,
+    /// Only entries before this time, in Unix milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until_ms: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogResponse {
+    /// Matching entries, oldest first. With more matches than `limit`, the most recent ones.
+    pub entries: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpForkRequest {
+    /// Id of the new ACP server.
+    pub server_id: String,
+    /// Last event of the source to carry over; defaults to all buffered events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up_to_sequence: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpForkResponse {
+    pub server_id: String,
+    pub agent: String,
+    pub forked_from: String,
+    pub history_events: usize,
+    pub last_event_id: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpReplayRequest {
+    /// Id of the new ACP server.
+    pub server_id: String,
+    /// Agent for the new server; defaults to the source's agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// Model set with `session/set_model` on each replayed session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpReplayResponse {
+    pub server_id: String,
+    pub agent: String,
+    pub replayed_from: String,
+    /// Prompts that will be sent to the new server.
+    pub prompts: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpFanoutRequest {
+    /// Id of the fanout; its servers are labelled `fanout={fanoutId}`.
+    pub fanout_id: String,
+    /// ACP content blocks sent to every run as the `session/prompt` prompt.
+    pub prompt: Vec<Value>,
+    /// Working directory for runs that don't set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    pub runs: Vec<AcpFanoutRunRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpFanoutRunRequest {
+    pub agent: String,
+    /// Model set with `session/set_model` before the prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Id of the run's ACP server; defaults to `{fanoutId}-{n}`, counting from 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AcpFanoutRunStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpFanoutInfo {
+    pub fanout_id: String,
+    pub created_at_ms: i64,
+    pub runs: Vec<AcpFanoutRunInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpFanoutRunInfo {
+    pub server_id: String,
+    pub agent: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub status: AcpFanoutRunStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// `stopReason` of the prompt, once it completed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Time from the fanout's creation to the end of the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<i64>,
+    /// Usage of the run's server; absent once the server is deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<AcpUsageInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpRunRequest {
+    pub agent: String,
+    /// ACP content blocks sent as the `session/prompt` prompt.
+    pub prompt: Vec<Value>,
+    /// Model set with `session/set_model` before the prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    /// Id of the run's ACP server; defaults to `run-{millis}-{n}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    /// How long to wait for the turn; defaults to and is capped by the ACP request timeout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Keep the server running after the run instead of deleting it.
+    #[serde(default)]
+    pub keep_server: bool,
+    /// JSON Schema the reply must match; the parsed reply is returned as `structuredOutput`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<Value>,
+}
+
+/// Body `useChat` posts: the chat id and its messages. Only the last user message is sent to
+/// the session; the rest of the history already lives there.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpAiSdkChatRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// AI SDK UI messages, with `role` and `parts` (or AI SDK 4 `content`).
+    pub messages: Vec<Value>,
+}
+
+/// OpenAI Chat Completions request. Fields other than these, such as `temperature`, are
+/// accepted and ignored; the agent decides how to answer.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct ChatCompletionRequest {
+    /// `<agent>` or `<agent>:<model>`. Other values, or none, use the daemon's default agent
+    /// and model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Messages with `role` and `content`: a string, or `text` and `image_url` parts.
+    pub messages: Vec<Value>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<ChatCompletionStreamOptions>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct ChatCompletionStreamOptions {
+    /// Send a last chunk with `usage` and no choices before `[DONE]`.
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct ChatCompletionResponse {
+    pub id: String,
+    /// Always `chat.completion`.
+    pub object: String,
+    /// Unix time in seconds.
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChatCompletionChoice>,
+    pub usage: ChatCompletionUsage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct ChatCompletionChoice {
+    pub index: u32,
+    pub message: ChatCompletionMessage,
+    /// `stop`, `length` or `content_filter`, from the agent's stop reason.
+    pub finish_reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct ChatCompletionMessage {
+    /// Always `assistant`.
+    pub role: String,
+    pub content: String,
+    /// The agent's thoughts during the turn, if it shared any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct ChatCompletionUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AcpRunStatus {
+    Completed,
+    Failed,
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpRunDiff {
+    pub files: Vec<AcpTurnFileChange>,
+    /// Git-style unified diff of the turn's changes.
+    pub diff: String,
+    /// A snapshot hit the file limit, so the diff may be incomplete.
+    pub truncated: bool,
+}
+
+/// The reply of a turn with an `outputSchema`, parsed as JSON.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpStructuredOutput {
+    /// The reply's JSON value, when it matched the schema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    /// Why there is no output: the reply held no JSON or did not match the schema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpRunResponse {
+    pub server_id: String,
+    pub agent: String,
+    pub status: AcpRunStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// `stopReason` of the prompt, when it completed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The assistant's reply: the text of its message chunks during the turn.
+    pub message: String,
+    /// The `update` of each `session/update` notification for the session during the turn.
+    pub items: Vec<Value>,
+    /// Workspace changes made during the turn; absent unless the run completed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<AcpRunDiff>,
+    /// The parsed reply, when the run had an `outputSchema` and completed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_output: Option<AcpStructuredOutput>,
+    pub usage: AcpUsageInfo,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpFirehoseQuery {
+    /// Comma-separated agent ids to include.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agents: Option<String>,
+    /// Comma-separated JSON-RPC methods to include, or `response` for results and errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub types: Option<String>,
+    /// Comma-separated `sessionUpdate` kinds of `session/update` notifications to include.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kinds: Option<String>,
+    /// Drop the `_sandboxagent/*` notifications the daemon adds to the stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_synthetic: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpFirehoseEventData {
+    pub server_id: String,
+    pub agent: String,
+    pub sequence: u64,
+    /// JSON-RPC method of the message, or `response` for results and errors.
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub message: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpServerInfo {
+    pub server_id: String,
+    pub agent: String,
+    pub created_at_ms: i64,
+    pub last_event_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    pub usage: AcpUsageInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// The server has ended and is served read-only from the archive.
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ended_at_ms: Option<i64>,
+}
+
+/// Token counts summed over prompt turns, plus the cost agents reported.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpUsageInfo {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_read_tokens: u64,
+    pub cached_write_tokens: u64,
+    pub thought_tokens: u64,
+    pub total_tokens: u64,
+    /// Prompt turns that reported usage.
+    pub turns: u64,
+    /// Latest cumulative cost of each ACP session, summed per currency.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cost: Vec<AcpUsageCostInfo>,
+    /// Latest context window of each ACP session whose agent reports one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contexts: Vec<AcpContextWindowInfo>,
+}
+
+/// How full a session's context window is, from the agent's latest `usage_update`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpContextWindowInfo {
+    pub session_id: String,
+    /// Tokens in the context window.
+    pub used: u64,
+    /// Tokens the context window holds.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpCompactResponse {
+    pub session_id: String,
+    /// `stopReason` of the compaction turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    /// Context window before compaction, when the agent had reported one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_before: Option<AcpContextWindowInfo>,
+    /// Context window after compaction, when the agent reported one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_after: Option<AcpContextWindowInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpUsageCostInfo {
+    pub amount: f64,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpServerListResponse {
+    pub servers: Vec<AcpServerInfo>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warm_pool: Vec<AcpWarmPoolInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcpWarmPoolInfo {
+    pub agent: String,
+    pub idle: usize,
+    pub target: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesResponse {
+    pub schema_version: u32,
+    pub build: BuildInfo,
+    pub features: BTreeMap<String, FeatureInfo>,
+    pub limits: CapabilityLimits,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureInfo {
+    pub enabled: bool,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilityLimits {
+    pub acp_replay_buffer_events: usize,
+    pub acp_request_timeout_ms: u64,
+    /// Daemon default idle timeout for ACP servers; absent when servers never time out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acp_idle_timeout_ms: Option<u64>,
+    /// Daemon default CPU limit for agent processes; absent when unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acp_cpu_limit: Option<f64>,
+    /// Daemon default memory limit for agent processes in MiB; absent when unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acp_memory_limit_mb: Option<u64>,
+    /// Daemon default process limit for agents; absent when unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acp_process_limit: Option<u64>,
+    pub fs_preview_bytes: usize,
+    pub debug_log_records: usize,
+    /// Unparsed agent output lines allowed per turn before it fails; absent when unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acp_max_unparsed_per_turn: Option<u64>,
+    /// Daemon default turn timeout in milliseconds; absent when turns may run indefinitely.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acp_turn_timeout_ms: Option<u64>,
+    /// Requests per minute allowed per token; absent when rate limiting is off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_per_minute: Option<u32>,
+    /// Largest request body accepted by `/v1/fs` writes.
+    pub max_fs_body_bytes: u64,
+    /// Largest request body accepted by every other `/v1` endpoint, ACP messages included.
+    pub max_message_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugLogsQuery {
+    /// Least severe level to return: trace, debug, info, warn or error (default info).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    /// Newest records to return (default 500).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugLogRecord {
+    pub timestamp_ms: i64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    /// Structured fields recorded with the event.
+    pub fields: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugLogsResponse {
+    /// Oldest first.
+    pub records: Vec<DebugLogRecord>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnparsedEventsQuery {
+    /// Only lines from this ACP server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    /// Newest lines to return (default 50).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// An agent stdout line that is not JSON, as forwarded in `_adapter/invalid_stdout`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnparsedEvent {
+    pub server_id: String,
+    pub agent: String,
+    /// Sequence of the `_adapter/invalid_stdout` event in the server's stream.
+    pub sequence: u64,
+    pub timestamp_ms: i64,
+    /// Why the line could not be parsed.
+    pub error: String,
+    /// The line, cut to its first 4 KiB.
+    pub raw: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnparsedAgentCount {
+    pub agent: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnparsedServerCount {
+    pub server_id: String,
+    pub agent: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnparsedEventsResponse {
+    /// Unparsed lines from every agent since the daemon started.
+    pub total: u64,
+    /// Lines since the daemon started, per agent.
+    pub agents: Vec<UnparsedAgentCount>,
+    /// Lines of each open server that had any, or of the requested server.
+    pub servers: Vec<UnparsedServerCount>,
+    /// The newest lines, oldest first.
+    pub events: Vec<UnparsedEvent>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitEventsQuery {
+    /// Only rate limits hit by this ACP server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    /// Newest rate limits to return (default 50).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKind {
+    /// The provider throttled the agent (`429`, rate limit, too many requests).
+    RateLimit,
+    /// The provider was overloaded or unavailable (`529`, overloaded).
+    Overloaded,
+}
+
+/// An agent error that reported a provider rate limit, as sent in `_sandboxagent/rate_limited`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitEvent {
+    pub server_id: String,
+    pub agent: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub kind: RateLimitKind,
+    /// How long the provider asked to wait, when the error said.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+    pub message: String,
+    pub timestamp_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitAgentCount {
+    pub agent: String,
+    pub rate_limited: u64,
+    pub overloaded: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitServerCount {
+    pub server_id: String,
+    pub agent: String,
+    pub rate_limited: u64,
+    pub overloaded: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitEventsResponse {
+    /// Rate limits hit by every agent since the daemon started.
+    pub total: u64,
+    /// Rate limits since the daemon started, per agent.
+    pub agents: Vec<RateLimitAgentCount>,
+    /// Rate limits of each open server that hit any, or of the requested server.
+    pub servers: Vec<RateLimitServerCount>,
+    /// The newest rate limits, oldest first.
+    pub events: Vec<RateLimitEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct McpConfigQuery {
+    pub directory: String,
+    #[serde(rename = "mcpName", alias = "mcp_name")]
+    pub mcp_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillsConfigQuery {
+    pub directory: String,
+    #[serde(rename = "skillName", alias = "skill_name")]
+    pub skill_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillsConfig {
+    pub sources: Vec<SkillSource>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillSource {
+    #[serde(rename = "type")]
+    pub source_type: String,
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skills: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "ref")]
+    pub git_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subpath: Option<String>,
+    /// Expected SHA-256 of an `archive` source, in hex. The download is rejected when it
+    /// differs, and a cached copy is reused without downloading again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Install from the cached copy of this source when there is one, without checking for
+    /// updates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reuse: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledSkill {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub source: SkillSource,
+    /// Commit for `github` and `git` sources, `sha256:` digest for `archive` sources, or the
+    /// `version` in `SKILL.md` frontmatter when it sets one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub installed_at_ms: i64,
+    pub path: String,
+    /// Agents whose skill directory holds a copy: `claude`, `codex`, `opencode`.
+    #[serde(default)]
+    pub synced_to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillListResponse {
+    pub skills: Vec<InstalledSkill>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillInstallResponse {
+    pub installed: Vec<InstalledSkill>,
+    /// The source was installed from the skills cache instead of being fetched.
+    pub cached: bool,
+}
+
+/// An instruction file template that `session/new` can write into a session's directory.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionTemplate {
+    pub name: String,
+    pub content: String,
+    /// The `{{name}}` placeholders the template uses, sorted.
+    #[serde(default)]
+    pub variables: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionTemplateListResponse {
+    pub templates: Vec<InstructionTemplate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionTemplateRequest {
+    pub content: String,
+}
+
+/// An existing directory, or a git repository to clone, to register as a workspace.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceCreateRequest {
+    /// Letters, digits, `-`, `_` and `.`; generated when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Directory of the workspace, created if missing. With `gitUrl` it must be missing or
+    /// empty, and defaults to a directory named after the id in the workspaces directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Repository to clone: `https://`, `http://`, `ssh://`, `git://` or `user@host:path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_url: Option<String>,
+    /// Branch, tag or commit to check out after cloning.
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "ref")]
+    pub git_ref: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceInfo {
+    pub id: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Commit checked out when the repository was cloned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Cloned into the daemon's workspaces directory; deleting the workspace removes the clone.
+    #[serde(default)]
+    pub managed: bool,
+    pub created_at_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceListResponse {
+    pub workspaces: Vec<WorkspaceInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(untagged)]
+pub enum McpCommand {
+    Command(String),
+    CommandWithArgs(Vec<String>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum McpRemoteTransport {
+    Http,
+    Sse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct McpOAuthConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Grant used to acquire a token; defaults to `clientCredentials` when a client secret is
+    /// set and `deviceCode` otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow: Option<McpOAuthFlow>,
+    /// Token endpoint; discovered from the server's authorization server metadata when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_url: Option<String>,
+    /// Device authorization endpoint; discovered like `tokenUrl` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_authorization_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum McpOAuthFlow {
+    ClientCredentials,
+    DeviceCode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum McpOAuthState {
+    /// No token is stored.
+    None,
+    /// A device flow is waiting for the user to approve it.
+    Pending,
+    Authorized,
+    /// The last attempt to acquire or refresh a token failed.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct McpOAuthStatus {
+    pub url: String,
+    pub state: McpOAuthState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow: Option<McpOAuthFlow>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<i64>,
+    #[serde(default)]
+    pub refreshable: bool,
+    /// Code the user enters at `verificationUri` while a device flow is pending.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification_uri_complete: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(untagged)]
+pub enum McpOAuthConfigOrDisabled {
+    Config(McpOAuthConfig),
+    Disabled(bool),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum McpServerConfig {
+    #[serde(rename = "local", alias = "stdio")]
+    Local {
+        command: McpCommand,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            alias = "environment"
+        )]
+        env: Option<BTreeMap<String, String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enabled: Option<bool>,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            rename = "timeoutMs",
+            alias = "timeout"
+        )]
+        #[schema(rename = "timeoutMs")]
+        timeout_ms: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cwd: Option<String>,
+    },
+    #[serde(rename = "remote", alias = "http")]
+    Remote {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        headers: Option<BTreeMap<String, String>>,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            rename = "bearerTokenEnvVar",
+            alias = "bearerTokenEnvVar",
+            alias = "bearer_token_env_var"
+        )]
+        #[schema(rename = "bearerTokenEnvVar")]
+        bearer_token_env_var: Option<String>,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            rename = "envHeaders",
+            alias = "envHttpHeaders",
+            alias = "env_http_headers"
+        )]
+        #[schema(rename = "envHeaders")]
+        env_headers: Option<BTreeMap<String, String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        oauth: Option<McpOAuthConfigOrDisabled>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enabled: Option<bool>,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            rename = "timeoutMs",
+            alias = "timeout"
+        )]
+        #[schema(rename = "timeoutMs")]
+        timeout_ms: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transport: Option<McpRemoteTransport>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct AcpEnvelope {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Option<Value>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub params: Option<Value>,
+    #[serde(default)]
+    pub result: Option<Value>,
+    #[serde(default)]
+    pub error: Option<Value>,
+}
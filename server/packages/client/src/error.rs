@@ -0,0 +1,43 @@
+use reqwest::StatusCode;
+use sandbox_agent_error::ProblemDetails;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("invalid url '{url}': {reason}")]
+    InvalidUrl { url: String, reason: String },
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The server answered with a non-success status. `problem` is the RFC 7807 body the
+    /// server sends for its own errors; proxies and gateways may send something else.
+    #[error("{}", api_error_message(*status, problem.as_deref(), body))]
+    Api {
+        status: StatusCode,
+        problem: Option<Box<ProblemDetails>>,
+        body: String,
+    },
+    #[error("invalid response body: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+impl ClientError {
+    /// The HTTP status of an API error.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Api { status, .. } => Some(*status),
+            Self::Http(err) => err.status(),
+            _ => None,
+        }
+    }
+}
+
+fn api_error_message(status: StatusCode, problem: Option<&ProblemDetails>, body: &str) -> String {
+    match problem {
+        Some(problem) => match &problem.detail {
+            Some(detail) => format!("{status}: {}: {detail}", problem.title),
+            None => format!("{status}: {}", problem.title),
+        },
+        None if body.trim().is_empty() => status.to_string(),
+        None => format!("{status}: {}", body.trim()),
+    }
+}
@@ -0,0 +1,1227 @@
+//! Async client for the sandbox-agent `/v1` HTTP API.
+//!
+//! Requests and responses use the server's own types from `sandbox-agent-api-types`,
+//! re-exported as [`types`]. Streaming endpoints (ACP events, the firehose, fan-out
+//! events, exec output, filesystem watch and search) return an [`EventStream`] of typed
+//! items.
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use sandbox_agent_client::types::AcpServerListQuery;
+//! use sandbox_agent_client::SandboxAgentClient;
+//!
+//! # async fn example() -> Result<(), sandbox_agent_client::ClientError> {
+//! let client = SandboxAgentClient::new("http://127.0.0.1:2468")?.with_token("secret");
+//! let servers = client.list_acp_servers(&AcpServerListQuery::default()).await?;
+//! for server in servers.servers {
+//!     let mut events = client.acp_events(&server.server_id, None).await?;
+//!     while let Some(event) = events.next().await {
+//!         let event = event?;
+//!         println!("{} {:?}", event.sequence, event.message.method);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod error;
+mod stream;
+
+use futures::StreamExt;
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use sandbox_agent_error::ProblemDetails;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub use reqwest::Body;
+pub use sandbox_agent_api_types as types;
+
+pub use crate::error::ClientError;
+pub use crate::stream::{EventStream, SseEvent};
+
+use crate::stream::{ndjson_stream, sse_stream};
+use crate::types::*;
+
+/// One event of an ACP server, as streamed by `GET /v1/acp/{server_id}` and exported by
+/// `GET /v1/acp/{server_id}/events.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcpEvent {
+    /// Strictly increasing per server; pass the last one seen to
+    /// [`SandboxAgentClient::acp_events`] to resume after it.
+    pub sequence: u64,
+    pub message: AcpEnvelope,
+}
+
+#[derive(Debug, Clone)]
+pub struct SandboxAgentClient {
+    http: reqwest::Client,
+    base_url: Url,
+    token: Option<String>,
+}
+
+impl SandboxAgentClient {
+    /// A client for the server at `base_url`, such as `http://127.0.0.1:2468`. A path in the
+    /// URL is kept as a prefix, for servers mounted behind a reverse proxy.
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        let invalid = |reason: &str| ClientError::InvalidUrl {
+            url: base_url.to_string(),
+            reason: reason.to_string(),
+        };
+        let base_url = Url::parse(base_url).map_err(|err| invalid(&err.to_string()))?;
+        if base_url.cannot_be_a_base() || !matches!(base_url.scheme(), "http" | "https") {
+            return Err(invalid("expected an http or https url"));
+        }
+        Ok(Self {
+            http: reqwest::Client::new(),
+            base_url,
+            token: None,
+        })
+    }
+
+    /// Send `token` as a bearer token with every request.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Use `http` for requests, for example to set timeouts or TLS roots. Streaming
+    /// endpoints stay open for as long as the server sends events, so a total request
+    /// timeout also ends those streams.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    // Health and discovery
+
+    /// Server health. The `503` sent when a critical component is down is returned as a
+    /// response with status `down`, not as an error.
+    pub async fn health(&self, query: &HealthQuery) -> Result<HealthResponse, ClientError> {
+        self.json_or_unavailable(self.request(Method::GET, &["health"]).query(query))
+            .await
+    }
+
+    /// Whether the server is ready for traffic. The `503` sent before it is ready is
+    /// returned as a response with `ready: false`, not as an error.
+    pub async fn ready(&self) -> Result<ReadinessResponse, ClientError> {
+        self.json_or_unavailable(self.request(Method::GET, &["ready"]))
+            .await
+    }
+
+    pub async fn capabilities(&self) -> Result<CapabilitiesResponse, ClientError> {
+        self.json(self.request(Method::GET, &["capabilities"]))
+            .await
+    }
+
+    pub async fn openapi_spec(&self) -> Result<Value, ClientError> {
+        self.json(self.request(Method::GET, &["openapi.json"]))
+            .await
+    }
+
+    pub async fn asyncapi_spec(&self) -> Result<Value, ClientError> {
+        self.json(self.request(Method::GET, &["asyncapi.json"]))
+            .await
+    }
+
+    // Agents
+
+    pub async fn list_agents(&self, query: &AgentsQuery) -> Result<AgentListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["agents"]).query(query))
+            .await
+    }
+
+    pub async fn get_agent(
+        &self,
+        agent: &str,
+        query: &AgentsQuery,
+    ) -> Result<AgentInfo, ClientError> {
+        self.json(self.request(Method::GET, &["agents", agent]).query(query))
+            .await
+    }
+
+    pub async fn install_agent(
+        &self,
+        agent: &str,
+        request: &AgentInstallRequest,
+    ) -> Result<AgentInstallResponse, ClientError> {
+        self.json(
+            self.request(Method::POST, &["agents", agent, "install"])
+                .json(request),
+        )
+        .await
+    }
+
+    /// Fetch the agent's model list from its backend now, where it has one.
+    pub async fn refresh_agent_models(
+        &self,
+        agent: &str,
+    ) -> Result<AgentModelsResponse, ClientError> {
+        self.json(self.request(Method::POST, &["agents", agent, "models", "refresh"]))
+            .await
+    }
+
+    /// Probe the agent for its capabilities now, starting its server where that is needed.
+    pub async fn refresh_agent_capabilities(
+        &self,
+        agent: &str,
+    ) -> Result<AgentCapabilitiesResponse, ClientError> {
+        self.json(self.request(Method::POST, &["agents", agent, "capabilities", "refresh"]))
+            .await
+    }
+
+    /// Start the agent's managed servers after they were stopped or failed.
+    pub async fn start_agent_server(&self, agent: &str) -> Result<ServerStatusInfo, ClientError> {
+        self.json(self.request(Method::POST, &["agents", agent, "server", "start"]))
+            .await
+    }
+
+    /// Stop the agent's managed servers; they stay down until started again.
+    pub async fn stop_agent_server(&self, agent: &str) -> Result<ServerStatusInfo, ClientError> {
+        self.json(self.request(Method::POST, &["agents", agent, "server", "stop"]))
+            .await
+    }
+
+    /// Restart the agent's managed servers, resetting any that crashed too often.
+    pub async fn restart_agent_server(&self, agent: &str) -> Result<ServerStatusInfo, ClientError> {
+        self.json(self.request(Method::POST, &["agents", agent, "server", "restart"]))
+            .await
+    }
+
+    /// Models of every installed agent in one list.
+    pub async fn list_models(&self) -> Result<ModelListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["models"])).await
+    }
+
+    pub async fn uninstall_agent(
+        &self,
+        agent: &str,
+    ) -> Result<AgentUninstallResponse, ClientError> {
+        self.json(self.request(Method::DELETE, &["agents", agent]))
+            .await
+    }
+
+    pub async fn get_agent_config(&self, agent: &str) -> Result<AgentConfig, ClientError> {
+        self.json(self.request(Method::GET, &["agents", agent, "config"]))
+            .await
+    }
+
+    pub async fn set_agent_config(
+        &self,
+        agent: &str,
+        config: &CodexConfig,
+    ) -> Result<CodexConfig, ClientError> {
+        self.json(
+            self.request(Method::PUT, &["agents", agent, "config"])
+                .json(config),
+        )
+        .await
+    }
+
+    /// Apply a JSON merge patch to the agent's config and return the result.
+    pub async fn patch_agent_config(
+        &self,
+        agent: &str,
+        patch: &Value,
+    ) -> Result<Value, ClientError> {
+        self.json(
+            self.request(Method::PATCH, &["agents", agent, "config"])
+                .json(patch),
+        )
+        .await
+    }
+
+    // Filesystem
+
+    pub async fn list_fs_entries(
+        &self,
+        query: &FsEntriesQuery,
+    ) -> Result<Vec<FsEntry>, ClientError> {
+        self.json(self.request(Method::GET, &["fs", "entries"]).query(query))
+            .await
+    }
+
+    pub async fn read_fs_file(&self, query: &FsPathQuery) -> Result<Vec<u8>, ClientError> {
+        self.bytes(self.request(Method::GET, &["fs", "file"]).query(query))
+            .await
+    }
+
+    pub async fn write_fs_file(
+        &self,
+        query: &FsPathQuery,
+        body: impl Into<Body>,
+    ) -> Result<FsWriteResponse, ClientError> {
+        self.json(
+            self.request(Method::PUT, &["fs", "file"])
+                .query(query)
+                .body(body),
+        )
+        .await
+    }
+
+    pub async fn delete_fs_entry(
+        &self,
+        query: &FsDeleteQuery,
+    ) -> Result<FsActionResponse, ClientError> {
+        self.json(self.request(Method::DELETE, &["fs", "entry"]).query(query))
+            .await
+    }
+
+    pub async fn mkdir_fs(&self, query: &FsPathQuery) -> Result<FsActionResponse, ClientError> {
+        self.json(self.request(Method::POST, &["fs", "mkdir"]).query(query))
+            .await
+    }
+
+    pub async fn move_fs(&self, request: &FsMoveRequest) -> Result<FsMoveResponse, ClientError> {
+        self.json(self.request(Method::POST, &["fs", "move"]).json(request))
+            .await
+    }
+
+    pub async fn stat_fs(&self, query: &FsPathQuery) -> Result<FsStat, ClientError> {
+        self.json(self.request(Method::GET, &["fs", "stat"]).query(query))
+            .await
+    }
+
+    /// Matches as they are found, followed by a `done` event.
+    pub async fn search_fs(
+        &self,
+        query: &FsSearchQuery,
+    ) -> Result<EventStream<FsSearchEvent>, ClientError> {
+        let response = self
+            .send(self.request(Method::GET, &["fs", "search"]).query(query))
+            .await?;
+        Ok(ndjson_stream(response))
+    }
+
+    /// Changes under the watched path until the stream is dropped.
+    pub async fn watch_fs(
+        &self,
+        query: &FsWatchQuery,
+    ) -> Result<EventStream<FsWatchEvent>, ClientError> {
+        let response = self
+            .send(
+                self.request(Method::GET, &["fs", "watch"])
+                    .query(query)
+                    .header(reqwest::header::ACCEPT, "text/event-stream"),
+            )
+            .await?;
+        Ok(sse_data(response))
+    }
+
+    pub async fn download_fs_archive(
+        &self,
+        query: &FsArchiveQuery,
+    ) -> Result<Vec<u8>, ClientError> {
+        self.bytes(self.request(Method::GET, &["fs", "archive"]).query(query))
+            .await
+    }
+
+    /// Extract a tar archive into the target directory.
+    pub async fn upload_fs_batch(
+        &self,
+        query: &FsUploadBatchQuery,
+        archive: impl Into<Body>,
+    ) -> Result<FsUploadBatchResponse, ClientError> {
+        self.json(
+            self.request(Method::POST, &["fs", "upload-batch"])
+                .query(query)
+                .header(reqwest::header::CONTENT_TYPE, "application/x-tar")
+                .body(archive),
+        )
+        .await
+    }
+
+    pub async fn init_fs_upload(
+        &self,
+        request: &FsUploadInitRequest,
+    ) -> Result<FsUploadStatus, ClientError> {
+        self.json(
+            self.request(Method::POST, &["fs", "upload", "init"])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn get_fs_upload(&self, upload_id: &str) -> Result<FsUploadStatus, ClientError> {
+        self.json(self.request(Method::GET, &["fs", "upload", upload_id]))
+            .await
+    }
+
+    pub async fn upload_fs_chunk(
+        &self,
+        upload_id: &str,
+        query: &FsUploadChunkQuery,
+        chunk: impl Into<Body>,
+    ) -> Result<FsUploadStatus, ClientError> {
+        self.json(
+            self.request(Method::PUT, &["fs", "upload", upload_id, "chunk"])
+                .query(query)
+                .body(chunk),
+        )
+        .await
+    }
+
+    pub async fn complete_fs_upload(
+        &self,
+        upload_id: &str,
+    ) -> Result<FsUploadCompleteResponse, ClientError> {
+        self.json(self.request(Method::POST, &["fs", "upload", upload_id, "complete"]))
+            .await
+    }
+
+    pub async fn abort_fs_upload(&self, upload_id: &str) -> Result<FsActionResponse, ClientError> {
+        self.json(self.request(Method::DELETE, &["fs", "upload", upload_id]))
+            .await
+    }
+
+    // Processes
+
+    /// Run a command; the stream starts with `start` and ends with `exit`.
+    pub async fn exec(&self, request: &ExecRequest) -> Result<EventStream<ExecEvent>, ClientError> {
+        let response = self
+            .send(
+                self.request(Method::POST, &["exec"])
+                    .json(request)
+                    .header(reqwest::header::ACCEPT, "text/event-stream"),
+            )
+            .await?;
+        Ok(sse_data(response))
+    }
+
+    pub async fn kill_exec(&self, exec_id: &str) -> Result<(), ClientError> {
+        self.empty(self.request(Method::POST, &["exec", exec_id, "kill"]))
+            .await
+    }
+
+    /// Push `HEAD` to a remote, streaming git's progress.
+    pub async fn git_push(
+        &self,
+        request: &GitSyncRequest,
+    ) -> Result<EventStream<GitEvent>, ClientError> {
+        self.git_sync("push", request).await
+    }
+
+    /// Pull from a remote, streaming git's progress.
+    pub async fn git_pull(
+        &self,
+        request: &GitSyncRequest,
+    ) -> Result<EventStream<GitEvent>, ClientError> {
+        self.git_sync("pull", request).await
+    }
+
+    async fn git_sync(
+        &self,
+        operation: &str,
+        request: &GitSyncRequest,
+    ) -> Result<EventStream<GitEvent>, ClientError> {
+        let response = self
+            .send(
+                self.request(Method::POST, &["git", operation])
+                    .json(request)
+                    .header(reqwest::header::ACCEPT, "text/event-stream"),
+            )
+            .await?;
+        Ok(sse_data(response))
+    }
+
+    /// Clone a GitHub repository into an empty directory.
+    pub async fn clone_github_repo(
+        &self,
+        request: &GithubCloneRequest,
+    ) -> Result<GithubCloneResponse, ClientError> {
+        self.json(
+            self.request(Method::POST, &["scm", "github", "clone"])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn debug_logs(
+        &self,
+        query: &DebugLogsQuery,
+    ) -> Result<DebugLogsResponse, ClientError> {
+        self.json(self.request(Method::GET, &["debug", "logs"]).query(query))
+            .await
+    }
+
+    /// Counts and recent agent output lines that were not JSON.
+    pub async fn debug_unparsed(
+        &self,
+        query: &UnparsedEventsQuery,
+    ) -> Result<UnparsedEventsResponse, ClientError> {
+        self.json(
+            self.request(Method::GET, &["debug", "unparsed"])
+                .query(query),
+        )
+        .await
+    }
+
+    /// Counts and recent provider rate limits reported by agents.
+    pub async fn debug_rate_limits(
+        &self,
+        query: &RateLimitEventsQuery,
+    ) -> Result<RateLimitEventsResponse, ClientError> {
+        self.json(
+            self.request(Method::GET, &["debug", "rate-limits"])
+                .query(query),
+        )
+        .await
+    }
+
+    /// URL of `path` on a port in the sandbox, through `/v1/proxy/{port}`. Requests to it
+    /// need the same bearer token as the client.
+    pub fn proxy_url(&self, port: u16, path: &str) -> Result<Url, ClientError> {
+        let base = self.url(&["proxy", &port.to_string(), ""]);
+        base.join(path.trim_start_matches('/'))
+            .map_err(|err| ClientError::InvalidUrl {
+                url: format!("{base}{path}"),
+                reason: err.to_string(),
+            })
+    }
+
+    // Config
+
+    pub async fn get_mcp_config(
+        &self,
+        query: &McpConfigQuery,
+    ) -> Result<McpServerConfig, ClientError> {
+        self.json(self.request(Method::GET, &["config", "mcp"]).query(query))
+            .await
+    }
+
+    pub async fn set_mcp_config(
+        &self,
+        query: &McpConfigQuery,
+        config: &McpServerConfig,
+    ) -> Result<(), ClientError> {
+        self.empty(
+            self.request(Method::PUT, &["config", "mcp"])
+                .query(query)
+                .json(config),
+        )
+        .await
+    }
+
+    pub async fn delete_mcp_config(&self, query: &McpConfigQuery) -> Result<(), ClientError> {
+        self.empty(
+            self.request(Method::DELETE, &["config", "mcp"])
+                .query(query),
+        )
+        .await
+    }
+
+    pub async fn get_mcp_oauth(
+        &self,
+        query: &McpConfigQuery,
+    ) -> Result<McpOAuthStatus, ClientError> {
+        self.json(
+            self.request(Method::GET, &["config", "mcp", "oauth"])
+                .query(query),
+        )
+        .await
+    }
+
+    /// Acquire a token for a remote MCP entry, or start its device authorization; poll
+    /// [`Self::get_mcp_oauth`] until a pending device flow is authorized.
+    pub async fn authorize_mcp_oauth(
+        &self,
+        query: &McpConfigQuery,
+    ) -> Result<McpOAuthStatus, ClientError> {
+        self.json(
+            self.request(Method::POST, &["config", "mcp", "oauth"])
+                .query(query),
+        )
+        .await
+    }
+
+    pub async fn delete_mcp_oauth(&self, query: &McpConfigQuery) -> Result<(), ClientError> {
+        self.empty(
+            self.request(Method::DELETE, &["config", "mcp", "oauth"])
+                .query(query),
+        )
+        .await
+    }
+
+    pub async fn get_skills_config(
+        &self,
+        query: &SkillsConfigQuery,
+    ) -> Result<SkillsConfig, ClientError> {
+        self.json(
+            self.request(Method::GET, &["config", "skills"])
+                .query(query),
+        )
+        .await
+    }
+
+    pub async fn set_skills_config(
+        &self,
+        query: &SkillsConfigQuery,
+        config: &SkillsConfig,
+    ) -> Result<(), ClientError> {
+        self.empty(
+            self.request(Method::PUT, &["config", "skills"])
+                .query(query)
+                .json(config),
+        )
+        .await
+    }
+
+    pub async fn delete_skills_config(&self, query: &SkillsConfigQuery) -> Result<(), ClientError> {
+        self.empty(
+            self.request(Method::DELETE, &["config", "skills"])
+                .query(query),
+        )
+        .await
+    }
+
+    // Skills
+
+    pub async fn list_skills(&self) -> Result<SkillListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["skills"])).await
+    }
+
+    /// Install the skills in `source` and sync them into the agents' skill directories.
+    pub async fn install_skills(
+        &self,
+        source: &SkillSource,
+    ) -> Result<SkillInstallResponse, ClientError> {
+        self.json(
+            self.request(Method::POST, &["skills", "install"])
+                .json(source),
+        )
+        .await
+    }
+
+    pub async fn delete_skill(&self, name: &str) -> Result<(), ClientError> {
+        self.empty(self.request(Method::DELETE, &["skills", name]))
+            .await
+    }
+
+    // Instruction templates
+
+    pub async fn list_instruction_templates(
+        &self,
+    ) -> Result<InstructionTemplateListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["instructions", "templates"]))
+            .await
+    }
+
+    pub async fn get_instruction_template(
+        &self,
+        name: &str,
+    ) -> Result<InstructionTemplate, ClientError> {
+        self.json(self.request(Method::GET, &["instructions", "templates", name]))
+            .await
+    }
+
+    /// Create or replace the template `session/new` writes instruction files from.
+    pub async fn put_instruction_template(
+        &self,
+        name: &str,
+        request: &InstructionTemplateRequest,
+    ) -> Result<InstructionTemplate, ClientError> {
+        self.json(
+            self.request(Method::PUT, &["instructions", "templates", name])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn delete_instruction_template(&self, name: &str) -> Result<(), ClientError> {
+        self.empty(self.request(Method::DELETE, &["instructions", "templates", name]))
+            .await
+    }
+
+    // Workspaces
+
+    pub async fn list_workspaces(&self) -> Result<WorkspaceListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["workspaces"])).await
+    }
+
+    /// Register a directory, or clone a repository, as a workspace for ACP servers.
+    pub async fn create_workspace(
+        &self,
+        request: &WorkspaceCreateRequest,
+    ) -> Result<WorkspaceInfo, ClientError> {
+        self.json(self.request(Method::POST, &["workspaces"]).json(request))
+            .await
+    }
+
+    pub async fn get_workspace(&self, id: &str) -> Result<WorkspaceInfo, ClientError> {
+        self.json(self.request(Method::GET, &["workspaces", id]))
+            .await
+    }
+
+    pub async fn delete_workspace(&self, id: &str) -> Result<(), ClientError> {
+        self.empty(self.request(Method::DELETE, &["workspaces", id]))
+            .await
+    }
+
+    // ACP servers
+
+    pub async fn list_acp_servers(
+        &self,
+        query: &AcpServerListQuery,
+    ) -> Result<AcpServerListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["acp"]).query(query))
+            .await
+    }
+
+    /// Send a JSON-RPC envelope to the server, starting it from `query` on first use.
+    /// Requests return their response; notifications and responses return `None`.
+    pub async fn post_acp(
+        &self,
+        server_id: &str,
+        query: &AcpPostQuery,
+        envelope: &AcpEnvelope,
+    ) -> Result<Option<AcpEnvelope>, ClientError> {
+        let response = self
+            .send(
+                self.request(Method::POST, &["acp", server_id])
+                    .query(query)
+                    .json(envelope),
+            )
+            .await?;
+        if response.status() == StatusCode::ACCEPTED {
+            return Ok(None);
+        }
+        Ok(Some(response.json().await?))
+    }
+
+    /// The server's events after `last_event_id`, buffered ones first and then live ones.
+    /// The stream ends when the connection drops; call again with the last sequence seen
+    /// to resume without duplicates.
+    pub async fn acp_events(
+        &self,
+        server_id: &str,
+        last_event_id: Option<u64>,
+    ) -> Result<EventStream<AcpEvent>, ClientError> {
+        let mut request = self
+            .request(Method::GET, &["acp", server_id])
+            .header(reqwest::header::ACCEPT, "text/event-stream");
+        if let Some(last_event_id) = last_event_id {
+            request = request.header("last-event-id", last_event_id.to_string());
+        }
+        let response = self.send(request).await?;
+        Ok(sse_stream::<AcpEnvelope>(response)
+            .map(|event| {
+                let event = event?;
+                Ok(AcpEvent {
+                    sequence: event.id.and_then(|id| id.parse().ok()).unwrap_or_default(),
+                    message: event.data,
+                })
+            })
+            .boxed())
+    }
+
+    /// Recorded events after `after`, including those of archived servers.
+    pub async fn export_acp_events(
+        &self,
+        server_id: &str,
+        after: Option<u64>,
+    ) -> Result<Vec<AcpEvent>, ClientError> {
+        let query = AcpEventsExportQuery {
+            after,
+            ..Default::default()
+        };
+        let body = self
+            .send(
+                self.request(Method::GET, &["acp", server_id, "events.jsonl"])
+                    .query(&query),
+            )
+            .await?
+            .text()
+            .await?;
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(ClientError::from))
+            .collect()
+    }
+
+    /// The server's events after `offset` as newline-delimited JSON, buffered ones first and
+    /// then live ones, ending when the server ends. Unlike [`Self::acp_events`], a reader that
+    /// falls behind misses no events that are still buffered.
+    pub async fn stream_acp_events(
+        &self,
+        server_id: &str,
+        offset: Option<u64>,
+    ) -> Result<EventStream<AcpEvent>, ClientError> {
+        let query = AcpEventsStreamQuery {
+            offset,
+            ..Default::default()
+        };
+        let response = self
+            .send(
+                self.request(Method::GET, &["acp", server_id, "events", "ndjson"])
+                    .query(&query),
+            )
+            .await?;
+        Ok(ndjson_stream(response))
+    }
+
+    pub async fn update_acp_server(
+        &self,
+        server_id: &str,
+        request: &AcpServerUpdateRequest,
+    ) -> Result<AcpServerInfo, ClientError> {
+        self.json(
+            self.request(Method::PATCH, &["acp", server_id])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn close_acp_server(&self, server_id: &str) -> Result<(), ClientError> {
+        self.empty(self.request(Method::DELETE, &["acp", server_id]))
+            .await
+    }
+
+    pub async fn fork_acp_server(
+        &self,
+        server_id: &str,
+        request: &AcpForkRequest,
+    ) -> Result<AcpForkResponse, ClientError> {
+        self.json(
+            self.request(Method::POST, &["acp", server_id, "fork"])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn replay_acp_server(
+        &self,
+        server_id: &str,
+        request: &AcpReplayRequest,
+    ) -> Result<AcpReplayResponse, ClientError> {
+        self.json(
+            self.request(Method::POST, &["acp", server_id, "replay"])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn acp_usage(&self, server_id: &str) -> Result<AcpUsageInfo, ClientError> {
+        self.json(self.request(Method::GET, &["acp", server_id, "usage"]))
+            .await
+    }
+
+    pub async fn list_acp_turns(
+        &self,
+        server_id: &str,
+    ) -> Result<AcpTurnListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["acp", server_id, "turns"]))
+            .await
+    }
+
+    /// Unified diff of the files a turn changed.
+    pub async fn acp_turn_diff(
+        &self,
+        server_id: &str,
+        turn_id: u64,
+    ) -> Result<String, ClientError> {
+        let turn_id = turn_id.to_string();
+        let response = self
+            .send(self.request(Method::GET, &["acp", server_id, "turns", &turn_id, "diff"]))
+            .await?;
+        Ok(response.text().await?)
+    }
+
+    pub async fn create_acp_checkpoint(
+        &self,
+        server_id: &str,
+        request: &AcpCheckpointRequest,
+    ) -> Result<AcpCheckpointInfo, ClientError> {
+        self.json(
+            self.request(Method::POST, &["acp", server_id, "checkpoints"])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn list_acp_checkpoints(
+        &self,
+        server_id: &str,
+    ) -> Result<AcpCheckpointListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["acp", server_id, "checkpoints"]))
+            .await
+    }
+
+    pub async fn restore_acp_checkpoint(
+        &self,
+        server_id: &str,
+        checkpoint_id: &str,
+    ) -> Result<AcpCheckpointRestoreResponse, ClientError> {
+        self.json(self.request(
+            Method::POST,
+            &["acp", server_id, "checkpoints", checkpoint_id, "restore"],
+        ))
+        .await
+    }
+
+    /// The latest todo list of `session_id`.
+    pub async fn acp_session_tasks(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<AcpTaskListResponse, ClientError> {
+        self.json(self.request(
+            Method::GET,
+            &["acp", server_id, "sessions", session_id, "tasks"],
+        ))
+        .await
+    }
+
+    /// Messages, reasoning and tool calls of `session_id` in transcript order.
+    pub async fn acp_session_items(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<AcpItemListResponse, ClientError> {
+        self.json(self.request(
+            Method::GET,
+            &["acp", server_id, "sessions", session_id, "items"],
+        ))
+        .await
+    }
+
+    pub async fn acp_session_item(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        item_id: &str,
+    ) -> Result<AcpItem, ClientError> {
+        self.json(self.request(
+            Method::GET,
+            &["acp", server_id, "sessions", session_id, "items", item_id],
+        ))
+        .await
+    }
+
+    /// Prompt turns of `session_id` with their items, oldest first.
+    pub async fn acp_session_turns(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<AcpSessionTurnListResponse, ClientError> {
+        self.json(self.request(
+            Method::GET,
+            &["acp", server_id, "sessions", session_id, "turns"],
+        ))
+        .await
+    }
+
+    pub async fn acp_session_turn(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        turn_id: &str,
+    ) -> Result<AcpSessionTurn, ClientError> {
+        self.json(self.request(
+            Method::GET,
+            &["acp", server_id, "sessions", session_id, "turns", turn_id],
+        ))
+        .await
+    }
+
+    /// The transcript of `session_id` rendered in `format`.
+    pub async fn export_acp_session(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        format: AcpTranscriptFormat,
+    ) -> Result<String, ClientError> {
+        let query = AcpTranscriptExportQuery {
+            format: Some(format),
+        };
+        Ok(self
+            .send(
+                self.request(
+                    Method::GET,
+                    &["acp", server_id, "sessions", session_id, "export"],
+                )
+                .query(&query),
+            )
+            .await?
+            .text()
+            .await?)
+    }
+
+    /// Commit the server's working tree on a branch, push it and open a pull request
+    /// summarizing `session_id`.
+    pub async fn open_github_pull_request(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        request: &GithubPullRequestRequest,
+    ) -> Result<GithubPullRequestResponse, ClientError> {
+        self.json(
+            self.request(
+                Method::POST,
+                &[
+                    "acp", server_id, "sessions", session_id, "scm", "github", "pr",
+                ],
+            )
+            .json(request),
+        )
+        .await
+    }
+
+    /// The tool call `item_id` of `session_id` and the tool calls its sub-agent made.
+    pub async fn acp_item_children(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        item_id: &str,
+    ) -> Result<AcpItemChildrenResponse, ClientError> {
+        self.json(self.request(
+            Method::GET,
+            &[
+                "acp", server_id, "sessions", session_id, "items", item_id, "children",
+            ],
+        ))
+        .await
+    }
+
+    /// Approve the plan of `session_id`: allow the agent's review request, or leave plan mode.
+    pub async fn approve_acp_plan(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        request: &AcpPlanDecisionRequest,
+    ) -> Result<(), ClientError> {
+        self.empty(
+            self.request(
+                Method::POST,
+                &["acp", server_id, "sessions", session_id, "plan", "approve"],
+            )
+            .json(request),
+        )
+        .await
+    }
+
+    pub async fn reject_acp_plan(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        request: &AcpPlanDecisionRequest,
+    ) -> Result<(), ClientError> {
+        self.empty(
+            self.request(
+                Method::POST,
+                &["acp", server_id, "sessions", session_id, "plan", "reject"],
+            )
+            .json(request),
+        )
+        .await
+    }
+
+    /// Have the agent compact the session's context with its native command.
+    pub async fn compact_acp_session(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<AcpCompactResponse, ClientError> {
+        self.json(self.request(
+            Method::POST,
+            &["acp", server_id, "sessions", session_id, "compact"],
+        ))
+        .await
+    }
+
+    /// Events of every running server, filtered by the query's agents and types.
+    pub async fn firehose(
+        &self,
+        query: &AcpFirehoseQuery,
+    ) -> Result<EventStream<AcpFirehoseEventData>, ClientError> {
+        let response = self
+            .send(
+                self.request(Method::GET, &["events", "sse"])
+                    .query(query)
+                    .header(reqwest::header::ACCEPT, "text/event-stream"),
+            )
+            .await?;
+        Ok(sse_data(response))
+    }
+
+    // Approvals and audit
+
+    pub async fn list_approvals(&self) -> Result<ApprovalListResponse, ClientError> {
+        self.json(self.request(Method::GET, &["approvals"])).await
+    }
+
+    pub async fn reply_approvals(
+        &self,
+        request: &ApprovalReplyRequest,
+    ) -> Result<ApprovalReplyResponse, ClientError> {
+        self.json(
+            self.request(Method::POST, &["approvals", "reply"])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn approvals_callback(
+        &self,
+        request: &ApprovalCallbackRequest,
+    ) -> Result<(), ClientError> {
+        self.empty(
+            self.request(Method::POST, &["approvals", "callback"])
+                .json(request),
+        )
+        .await
+    }
+
+    pub async fn audit_log(&self, query: &AuditQuery) -> Result<AuditLogResponse, ClientError> {
+        self.json(self.request(Method::GET, &["audit"]).query(query))
+            .await
+    }
+
+    pub async fn get_blob(&self, blob_id: &str) -> Result<Vec<u8>, ClientError> {
+        self.bytes(self.request(Method::GET, &["blobs", blob_id]))
+            .await
+    }
+
+    // Fan-out and headless runs
+
+    pub async fn create_fanout(
+        &self,
+        request: &AcpFanoutRequest,
+    ) -> Result<AcpFanoutInfo, ClientError> {
+        self.json(self.request(Method::POST, &["fanout"]).json(request))
+            .await
+    }
+
+    pub async fn get_fanout(&self, fanout_id: &str) -> Result<AcpFanoutInfo, ClientError> {
+        self.json(self.request(Method::GET, &["fanout", fanout_id]))
+            .await
+    }
+
+    pub async fn delete_fanout(&self, fanout_id: &str) -> Result<(), ClientError> {
+        self.empty(self.request(Method::DELETE, &["fanout", fanout_id]))
+            .await
+    }
+
+    /// The merged events of the fan-out's runs.
+    pub async fn fanout_events(
+        &self,
+        fanout_id: &str,
+    ) -> Result<EventStream<AcpFirehoseEventData>, ClientError> {
+        let response = self
+            .send(
+                self.request(Method::GET, &["fanout", fanout_id, "events"])
+                    .header(reqwest::header::ACCEPT, "text/event-stream"),
+            )
+            .await?;
+        Ok(sse_data(response))
+    }
+
+    /// Run one prompt on a new server and wait for the turn to end.
+    pub async fn create_run(&self, request: &AcpRunRequest) -> Result<AcpRunResponse, ClientError> {
+        self.json(self.request(Method::POST, &["runs"]).json(request))
+            .await
+    }
+
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("base url was checked in new")
+            .pop_if_empty()
+            .push("v1")
+            .extend(segments);
+        url
+    }
+
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let request = self.http.request(method, self.url(segments));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(ClientError::Api {
+            status,
+            problem: serde_json::from_str::<ProblemDetails>(&body)
+                .ok()
+                .map(Box::new),
+            body,
+        })
+    }
+
+    async fn json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, ClientError> {
+        let body = self.send(request).await?.bytes().await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Like [`Self::json`], but decodes a `503` body as `T` too, for endpoints that answer
+    /// with their usual body while unavailable.
+    async fn json_or_unavailable<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, ClientError> {
+        match self.json(request).await {
+            Err(ClientError::Api {
+                status,
+                problem,
+                body,
+            }) if status == StatusCode::SERVICE_UNAVAILABLE => {
+                serde_json::from_str(&body).map_err(|_| ClientError::Api {
+                    status,
+                    problem,
+                    body,
+                })
+            }
+            result => result,
+        }
+    }
+
+    async fn bytes(&self, request: RequestBuilder) -> Result<Vec<u8>, ClientError> {
+        Ok(self.send(request).await?.bytes().await?.to_vec())
+    }
+
+    async fn empty(&self, request: RequestBuilder) -> Result<(), ClientError> {
+        self.send(request).await.map(drop)
+    }
+}
+
+fn sse_data<T>(response: Response) -> EventStream<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    sse_stream::<T>(response)
+        .map(|event| event.map(|event| event.data))
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_keep_the_base_path_and_encode_segments() {
+        let client = SandboxAgentClient::new("http://127.0.0.1:2468/sandbox/").unwrap();
+        assert_eq!(
+            client.url(&["acp", "build/1"]).as_str(),
+            "http://127.0.0.1:2468/sandbox/v1/acp/build%2F1"
+        );
+        assert_eq!(
+            client.proxy_url(3000, "/app?x=1").unwrap().as_str(),
+            "http://127.0.0.1:2468/sandbox/v1/proxy/3000/app?x=1"
+        );
+        assert!(matches!(
+            SandboxAgentClient::new("unix:/tmp/socket"),
+            Err(ClientError::InvalidUrl { .. })
+        ));
+    }
+}
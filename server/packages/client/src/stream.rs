@@ -0,0 +1,177 @@
+//! Decoding of the server's SSE and NDJSON response bodies into typed streams.
+
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+
+use crate::ClientError;
+
+/// A stream of decoded events. It ends when the server closes the response.
+pub type EventStream<T> = BoxStream<'static, Result<T, ClientError>>;
+
+/// One server-sent event with its `data` decoded as JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent<T> {
+    pub id: Option<String>,
+    pub event: Option<String>,
+    pub data: T,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct SseFrame {
+    pub id: Option<String>,
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Splits a byte stream into lines, keeping the unterminated tail for the next chunk.
+#[derive(Debug, Default)]
+struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let Some(end) = self.pending.iter().rposition(|byte| *byte == b'\n') else {
+            return Vec::new();
+        };
+        let complete = self.pending.drain(..=end).collect::<Vec<_>>();
+        String::from_utf8_lossy(&complete)
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            // `split` yields an empty string after the final newline.
+            .take(complete.iter().filter(|byte| **byte == b'\n').count())
+            .collect()
+    }
+}
+
+/// Incremental SSE parser. Comments, such as the server's keep-alives, and frames without
+/// data are dropped.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    lines: LineBuffer,
+    frame: SseFrame,
+    has_data: bool,
+}
+
+impl SseDecoder {
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<SseFrame> {
+        let mut frames = Vec::new();
+        for line in self.lines.push(chunk) {
+            if line.is_empty() {
+                let frame = std::mem::take(&mut self.frame);
+                if std::mem::take(&mut self.has_data) {
+                    frames.push(frame);
+                }
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line.as_str(), ""),
+            };
+            match field {
+                "id" => self.frame.id = Some(value.to_string()),
+                "event" => self.frame.event = Some(value.to_string()),
+                "data" => {
+                    if self.has_data {
+                        self.frame.data.push('\n');
+                    }
+                    self.frame.data.push_str(value);
+                    self.has_data = true;
+                }
+                _ => {}
+            }
+        }
+        frames
+    }
+}
+
+pub(crate) fn sse_stream<T>(response: reqwest::Response) -> EventStream<SseEvent<T>>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let mut decoder = SseDecoder::default();
+    response
+        .bytes_stream()
+        .flat_map(move |chunk| {
+            let items = match chunk {
+                Ok(chunk) => decoder
+                    .push(&chunk)
+                    .into_iter()
+                    .map(|frame| {
+                        Ok(SseEvent {
+                            data: serde_json::from_str(&frame.data)?,
+                            id: frame.id,
+                            event: frame.event,
+                        })
+                    })
+                    .collect(),
+                Err(err) => vec![Err(ClientError::from(err))],
+            };
+            stream::iter(items)
+        })
+        .boxed()
+}
+
+pub(crate) fn ndjson_stream<T>(response: reqwest::Response) -> EventStream<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let mut lines = LineBuffer::default();
+    response
+        .bytes_stream()
+        .flat_map(move |chunk| {
+            let items = match chunk {
+                Ok(chunk) => lines
+                    .push(&chunk)
+                    .into_iter()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| Ok(serde_json::from_str(&line)?))
+                    .collect(),
+                Err(err) => vec![Err(ClientError::from(err))],
+            };
+            stream::iter(items)
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_decoder_handles_split_chunks_and_comments() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder
+            .push(b": keep-alive\n\nid: 1\nevent: mes")
+            .is_empty());
+        let frames = decoder.push(b"sage\ndata: {\"a\":1}\r\n\r\nid: 2\ndata: [1,\ndata: 2]\n\n");
+        assert_eq!(
+            frames,
+            vec![
+                SseFrame {
+                    id: Some("1".to_string()),
+                    event: Some("message".to_string()),
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseFrame {
+                    id: Some("2".to_string()),
+                    event: None,
+                    data: "[1,\n2]".to_string(),
+                },
+            ]
+        );
+        assert!(decoder.push(b"id: 3\n").is_empty());
+    }
+
+    #[test]
+    fn line_buffer_keeps_the_unterminated_tail() {
+        let mut lines = LineBuffer::default();
+        assert!(lines.push(b"{\"a\"").is_empty());
+        assert_eq!(lines.push(b":1}\n\n{\"b\""), vec!["{\"a\":1}", ""]);
+        assert_eq!(lines.push(b":2}\n"), vec!["{\"b\":2}"]);
+    }
+}
@@ -22,6 +22,13 @@ pub enum ErrorType {
     ModeNotSupported,
     StreamError,
     Timeout,
+    NotFound,
+    NotADirectory,
+    AlreadyExists,
+    QuotaExceeded,
+    PayloadTooLarge,
+    RateLimited,
+    CapacityExceeded,
 }
 
 impl ErrorType {
@@ -42,6 +49,13 @@ impl ErrorType {
             Self::ModeNotSupported => "urn:sandbox-agent:error:mode_not_supported",
             Self::StreamError => "urn:sandbox-agent:error:stream_error",
             Self::Timeout => "urn:sandbox-agent:error:timeout",
+            Self::NotFound => "urn:sandbox-agent:error:not_found",
+            Self::NotADirectory => "urn:sandbox-agent:error:not_a_directory",
+            Self::AlreadyExists => "urn:sandbox-agent:error:already_exists",
+            Self::QuotaExceeded => "urn:sandbox-agent:error:quota_exceeded",
+            Self::PayloadTooLarge => "urn:sandbox-agent:error:payload_too_large",
+            Self::RateLimited => "urn:sandbox-agent:error:rate_limited",
+            Self::CapacityExceeded => "urn:sandbox-agent:error:capacity_exceeded",
         }
     }
 
@@ -62,6 +76,13 @@ impl ErrorType {
             Self::ModeNotSupported => "Mode Not Supported",
             Self::StreamError => "Stream Error",
             Self::Timeout => "Timeout",
+            Self::NotFound => "Not Found",
+            Self::NotADirectory => "Not A Directory",
+            Self::AlreadyExists => "Already Exists",
+            Self::QuotaExceeded => "Quota Exceeded",
+            Self::PayloadTooLarge => "Payload Too Large",
+            Self::RateLimited => "Rate Limited",
+            Self::CapacityExceeded => "Capacity Exceeded",
         }
     }
 
@@ -82,6 +103,13 @@ impl ErrorType {
             Self::ModeNotSupported => 400,
             Self::StreamError => 502,
             Self::Timeout => 504,
+            Self::NotFound => 404,
+            Self::NotADirectory => 400,
+            Self::AlreadyExists => 409,
+            Self::QuotaExceeded => 507,
+            Self::PayloadTooLarge => 413,
+            Self::RateLimited => 429,
+            Self::CapacityExceeded => 429,
         }
     }
 }
@@ -165,6 +193,23 @@ pub enum SandboxError {
     StreamError { message: String },
     #[error("timeout")]
     Timeout { message: Option<String> },
+    #[error("not found: {path}")]
+    NotFound { path: String },
+    #[error("not a directory: {path}")]
+    NotADirectory { path: String },
+    #[error("already exists: {path}")]
+    AlreadyExists { path: String },
+    #[error("quota exceeded: {path}")]
+    QuotaExceeded { path: String },
+    #[error("payload too large: request body exceeds {limit_bytes} bytes")]
+    PayloadTooLarge { limit_bytes: u64 },
+    #[error("rate limited")]
+    RateLimited { retry_after_secs: u64 },
+    #[error("capacity exceeded: {message}")]
+    CapacityExceeded {
+        message: String,
+        retry_after_secs: u64,
+    },
 }
 
 impl SandboxError {
@@ -185,6 +230,13 @@ impl SandboxError {
             Self::ModeNotSupported { .. } => ErrorType::ModeNotSupported,
             Self::StreamError { .. } => ErrorType::StreamError,
             Self::Timeout { .. } => ErrorType::Timeout,
+            Self::NotFound { .. } => ErrorType::NotFound,
+            Self::NotADirectory { .. } => ErrorType::NotADirectory,
+            Self::AlreadyExists { .. } => ErrorType::AlreadyExists,
+            Self::QuotaExceeded { .. } => ErrorType::QuotaExceeded,
+            Self::PayloadTooLarge { .. } => ErrorType::PayloadTooLarge,
+            Self::RateLimited { .. } => ErrorType::RateLimited,
+            Self::CapacityExceeded { .. } => ErrorType::CapacityExceeded,
         }
     }
 
@@ -284,6 +336,33 @@ impl SandboxError {
                 });
                 (None, None, details)
             }
+            Self::NotFound { path }
+            | Self::NotADirectory { path }
+            | Self::AlreadyExists { path }
+            | Self::QuotaExceeded { path } => {
+                let mut map = Map::new();
+                map.insert("path".to_string(), Value::String(path.clone()));
+                (None, None, Some(Value::Object(map)))
+            }
+            Self::PayloadTooLarge { limit_bytes } => {
+                let mut map = Map::new();
+                map.insert("limitBytes".to_string(), Value::from(*limit_bytes));
+                (None, None, Some(Value::Object(map)))
+            }
+            Self::RateLimited { retry_after_secs } => {
+                let mut map = Map::new();
+                map.insert("retryAfterSecs".to_string(), Value::from(*retry_after_secs));
+                (None, None, Some(Value::Object(map)))
+            }
+            Self::CapacityExceeded {
+                message,
+                retry_after_secs,
+            } => {
+                let mut map = Map::new();
+                map.insert("message".to_string(), Value::String(message.clone()));
+                map.insert("retryAfterSecs".to_string(), Value::from(*retry_after_secs));
+                (None, None, Some(Value::Object(map)))
+            }
         };
 
         AgentError {
@@ -295,6 +374,17 @@ impl SandboxError {
         }
     }
 
+    /// Seconds a client should wait before retrying, sent as `Retry-After`.
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            Self::RateLimited { retry_after_secs }
+            | Self::CapacityExceeded {
+                retry_after_secs, ..
+            } => Some(*retry_after_secs),
+            _ => None,
+        }
+    }
+
     pub fn to_problem_details(&self) -> ProblemDetails {
         let mut problem = ProblemDetails::new(self.error_type(), Some(self.to_string()));
         let agent_error = self.to_agent_error();
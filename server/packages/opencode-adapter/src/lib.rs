@@ -4,7 +4,7 @@ use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex as StdMutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -18,7 +18,7 @@ use axum::routing::{get, patch, post};
 use axum::{Json, Router};
 use futures::stream;
 use futures::{Stream, StreamExt};
-use sandbox_agent_opencode_server_manager::OpenCodeServerManager;
+use sandbox_agent_opencode_server_manager::{OpenCodeServerEvent, OpenCodeServerManager};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
@@ -78,6 +78,11 @@ pub trait AcpDispatch: Send + Sync + 'static {
     ) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send + '_>>;
 }
 
+/// Decides, by request path, whether a request proxied to the native OpenCode server
+/// fails with a 500 instead, for fault injection.
+#[cfg(feature = "test-utils")]
+pub type ProxyFault = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
 pub struct OpenCodeAdapterConfig {
     pub auth_token: Option<String>,
     pub sqlite_path: Option<String>,
@@ -91,6 +96,8 @@ pub struct OpenCodeAdapterConfig {
     /// Optional pre-built provider payload for `/provider` and `/config/providers`.
     /// When `None`, falls back to the hardcoded mock/amp/claude/codex list.
     pub provider_payload: Option<Value>,
+    #[cfg(feature = "test-utils")]
+    pub proxy_fault: Option<ProxyFault>,
 }
 
 impl Default for OpenCodeAdapterConfig {
@@ -104,6 +111,8 @@ impl Default for OpenCodeAdapterConfig {
             native_proxy_manager: None,
             acp_dispatch: None,
             provider_payload: None,
+            #[cfg(feature = "test-utils")]
+            proxy_fault: None,
         }
     }
 }
@@ -186,6 +195,8 @@ struct AdapterState {
     event_log: StdMutex<VecDeque<OpenCodeStreamEvent>>,
     next_event_id: AtomicU64,
     next_id: AtomicU64,
+    /// Managed server stops are forwarded as events once the first client subscribes.
+    forwarding_server_events: AtomicBool,
     /// Tracks which ACP server instances have been initialized (initialize + session/new sent).
     /// Key is the ACP server_id (e.g. "acp_ses_42"), value is the ACP sessionId from session/new.
     acp_initialized: Mutex<HashMap<String, String>>,
@@ -339,6 +350,45 @@ impl AdapterState {
         self.event_broadcaster.subscribe()
     }
 
+    /// Tell clients when a managed OpenCode server serving their directory stops, so they
+    /// reload what they fetched from it.
+    fn forward_server_events(self: &Arc<Self>) {
+        let Some(manager) = self.config.native_proxy_manager.as_ref() else {
+            return;
+        };
+        if self.forwarding_server_events.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let mut events = manager.subscribe();
+        let state = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                let Some(state) = state.upgrade() else {
+                    return;
+                };
+                let OpenCodeServerEvent::Stopped { keys, .. } = event else {
+                    continue;
+                };
+                for key in keys {
+                    let directory = if key.is_empty() {
+                        resolve_directory(&HeaderMap::new(), None)
+                    } else {
+                        key
+                    };
+                    state.emit_event(json!({
+                        "type": "server.instance.disposed",
+                        "properties": {"directory": directory},
+                    }));
+                }
+            }
+        });
+    }
+
     fn next_id(&self, prefix: &str) -> String {
         let value = self.next_id.fetch_add(1, Ordering::Relaxed);
         format!("{prefix}{value}")
@@ -689,6 +739,7 @@ pub fn build_opencode_router(config: OpenCodeAdapterConfig) -> Result<Router, St
         event_log: StdMutex::new(VecDeque::new()),
         next_event_id: AtomicU64::new(1),
         next_id: AtomicU64::new(runtime_unique_seed()),
+        forwarding_server_events: AtomicBool::new(false),
         acp_initialized: Mutex::new(HashMap::new()),
         acp_request_ids: Mutex::new(HashMap::new()),
         last_user_message_id: Mutex::new(HashMap::new()),
@@ -987,6 +1038,7 @@ async fn oc_event_subscribe(
     Query(query): Query<DirectoryQuery>,
 ) -> Sse<impl futures::Stream<Item = Result<Event, Infallible>>> {
     let _ = state.ensure_initialized().await;
+    state.forward_server_events();
 
     let directory = resolve_directory(&headers, query.directory.as_ref());
     let replay = state.buffered_events_after(parse_last_event_id(&headers));
@@ -4135,13 +4187,22 @@ fn normalize_proxy_base_url(value: String) -> Option<String> {
     }
 }
 
-async fn resolve_proxy_base_url(state: &Arc<AdapterState>, path: &str) -> Option<String> {
+async fn resolve_proxy_base_url(
+    state: &Arc<AdapterState>,
+    path: &str,
+    headers: &HeaderMap,
+) -> Option<String> {
     if let Some(base_url) = state.config.native_proxy_base_url.as_ref() {
         return Some(base_url.clone());
     }
 
     let manager = state.config.native_proxy_manager.as_ref()?;
-    match manager.ensure_server().await {
+    // The pool routes requests by the project directory they are for.
+    let directory = headers
+        .get("x-opencode-directory")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    match manager.ensure_server_for(directory).await {
         Ok(base_url) => Some(base_url),
         Err(err) => {
             warn!(path, error = ?err, "failed to lazily start native OpenCode sidecar");
@@ -4150,6 +4211,29 @@ async fn resolve_proxy_base_url(state: &Arc<AdapterState>, path: &str) -> Option
     }
 }
 
+#[cfg(feature = "test-utils")]
+fn injected_proxy_fault(state: &AdapterState, path: &str) -> Option<Response> {
+    let fault = state.config.proxy_fault.as_ref()?;
+    if !fault(path) {
+        return None;
+    }
+    warn!(
+        path,
+        "fault injection: failing native OpenCode proxy request"
+    );
+    Some(
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "data": {},
+                "errors": [{"message": "injected fault"}],
+                "success": false,
+            })),
+        )
+            .into_response(),
+    )
+}
+
 async fn proxy_native_opencode(
     state: &Arc<AdapterState>,
     method: reqwest::Method,
@@ -4157,7 +4241,11 @@ async fn proxy_native_opencode(
     headers: &HeaderMap,
     body: Option<Value>,
 ) -> Option<Response> {
-    let base_url = resolve_proxy_base_url(state, path).await?;
+    let base_url = resolve_proxy_base_url(state, path, headers).await?;
+    #[cfg(feature = "test-utils")]
+    if let Some(response) = injected_proxy_fault(state, path) {
+        return Some(response);
+    }
 
     let mut request = state
         .proxy_http_client
@@ -4232,7 +4320,11 @@ async fn proxy_native_opencode_json(
     headers: &HeaderMap,
     body: Option<Value>,
 ) -> Option<Result<(StatusCode, Value), Response>> {
-    let base_url = resolve_proxy_base_url(state, path).await?;
+    let base_url = resolve_proxy_base_url(state, path, headers).await?;
+    #[cfg(feature = "test-utils")]
+    if let Some(response) = injected_proxy_fault(state, path) {
+        return Some(Err(response));
+    }
 
     let mut request = state
         .proxy_http_client
@@ -1,13 +1,16 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
 use std::fs::{self, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
 use std::net::TcpListener;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Stdio};
 use std::sync::{Arc, Mutex as StdMutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use reqwest::Client;
 use sandbox_agent_agent_management::agents::{AgentId, AgentManager};
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
 use tokio::time::sleep;
 use tracing::warn;
 
@@ -15,11 +18,118 @@ const HEALTH_ENDPOINTS: [&str; 4] = ["health", "healthz", "app/agents", "agents"
 const HEALTH_ATTEMPTS: usize = 20;
 const HEALTH_DELAY_MS: u64 = 150;
 const MONITOR_DELAY_MS: u64 = 500;
+const EVENT_CHANNEL_SIZE: usize = 64;
+const POOL_ENV: &str = "SANDBOX_AGENT_OPENCODE_SERVER_POOL";
+const MAX_RESTARTS_ENV: &str = "SANDBOX_AGENT_OPENCODE_MAX_RESTARTS";
+const RESTART_WINDOW_ENV: &str = "SANDBOX_AGENT_OPENCODE_RESTART_WINDOW_SECS";
+
+/// How requests are spread over OpenCode servers. Requests are routed by key, the OpenCode
+/// project directory they are for; a key keeps its server until the daemon exits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpenCodeServerPool {
+    /// One server for every key.
+    #[default]
+    Shared,
+    /// A server of its own for every key.
+    PerDirectory,
+    /// Up to this many servers. New keys start a server until there are that many, then go
+    /// to the server with the fewest keys.
+    Fixed(usize),
+}
+
+impl OpenCodeServerPool {
+    /// `shared`, `per-directory` or `pool:<n>`.
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim() {
+            "shared" => Some(Self::Shared),
+            "per-directory" => Some(Self::PerDirectory),
+            other => other
+                .strip_prefix("pool:")?
+                .parse::<usize>()
+                .ok()
+                .filter(|size| *size > 0)
+                .map(Self::Fixed),
+        }
+    }
+
+    /// `SANDBOX_AGENT_OPENCODE_SERVER_POOL`, shared when unset or invalid.
+    pub fn from_env() -> Self {
+        let Ok(raw) = std::env::var(POOL_ENV) else {
+            return Self::Shared;
+        };
+        Self::parse(&raw).unwrap_or_else(|| {
+            warn!(value = %raw, "invalid {POOL_ENV}; using a shared OpenCode server");
+            Self::Shared
+        })
+    }
+}
+
+/// How crashed servers are restarted: after a backoff that doubles from `initial_backoff` up
+/// to `max_backoff`, with jitter, until more than `max_restarts` crashes within `window` mark
+/// the server failed. A failed server stays down until [`OpenCodeServerManager::restart`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_restarts: usize,
+    pub window: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(MONITOR_DELAY_MS),
+            max_backoff: Duration::from_secs(30),
+            max_restarts: 5,
+            window: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// The defaults with `SANDBOX_AGENT_OPENCODE_MAX_RESTARTS` and
+    /// `SANDBOX_AGENT_OPENCODE_RESTART_WINDOW_SECS` applied.
+    pub fn from_env() -> Self {
+        let env = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|raw| raw.trim().parse::<u64>().ok())
+        };
+        let defaults = Self::default();
+        Self {
+            max_restarts: env(MAX_RESTARTS_ENV)
+                .map(|max| max as usize)
+                .unwrap_or(defaults.max_restarts),
+            window: env(RESTART_WINDOW_ENV)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.window),
+            ..defaults
+        }
+    }
+
+    /// Delay before the restart following the `crashes`th crash in the window.
+    fn backoff(&self, crashes: usize) -> Duration {
+        let exponent = crashes.saturating_sub(1).min(16) as u32;
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        // Between half and all of the backoff, so servers that crashed together spread out.
+        let jitter = RandomState::new().build_hasher().finish() % 1000;
+        backoff / 2 + backoff.mul_f64(jitter as f64 / 2000.0)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct OpenCodeServerManagerConfig {
     pub log_dir: PathBuf,
     pub auto_restart: bool,
+    /// Start the server through this command, e.g. a resource limit wrapper; the server
+    /// binary and its arguments are appended.
+    pub command_prefix: Vec<String>,
+    pub pool: OpenCodeServerPool,
+    pub restart: RestartPolicy,
 }
 
 impl Default for OpenCodeServerManagerConfig {
@@ -27,10 +137,48 @@ impl Default for OpenCodeServerManagerConfig {
         Self {
             log_dir: default_log_dir(),
             auto_restart: true,
+            command_prefix: Vec::new(),
+            pool: OpenCodeServerPool::Shared,
+            restart: RestartPolicy::default(),
         }
     }
 }
 
+/// Snapshot of the managed servers for health reporting.
+#[derive(Debug, Clone)]
+pub struct OpenCodeServerStatus {
+    /// At least one server is running.
+    pub running: bool,
+    /// Times servers have been started, including restarts.
+    pub starts: u64,
+    /// Why a server that is not running stopped or failed to start.
+    pub last_error: Option<String>,
+    /// A server crashed too often and is no longer restarted.
+    pub failed: bool,
+    /// Every server the pool has routed requests to, in the order they were added.
+    pub instances: Vec<OpenCodeInstanceStatus>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenCodeInstanceStatus {
+    pub index: usize,
+    pub running: bool,
+    pub uptime: Option<Duration>,
+    pub starts: u64,
+    pub last_error: Option<String>,
+    pub failed: bool,
+    /// Routing keys assigned to this server; the empty key stands for requests without a
+    /// directory.
+    pub keys: Vec<String>,
+}
+
+/// A managed server started or stopped, with the routing keys (directories) it serves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpenCodeServerEvent {
+    Started { index: usize, keys: Vec<String> },
+    Stopped { index: usize, keys: Vec<String> },
+}
+
 #[derive(Debug, Clone)]
 pub struct OpenCodeServerManager {
     inner: Arc<Inner>,
@@ -43,14 +191,53 @@ struct Inner {
     config: OpenCodeServerManagerConfig,
     ensure_lock: Mutex<()>,
     state: Mutex<ManagerState>,
+    events: broadcast::Sender<OpenCodeServerEvent>,
 }
 
 #[derive(Debug, Default)]
 struct ManagerState {
-    server: Option<RunningServer>,
+    instances: Vec<Instance>,
+    /// Pool slot of every routing key seen so far.
+    routes: HashMap<String, usize>,
+    /// Starts over all slots; each started process is identified by the count at its start.
     restart_count: u64,
+    /// Set by `shutdown`; servers stay down, for requests too, until `start` or `restart`.
     shutdown_requested: bool,
+}
+
+impl ManagerState {
+    fn keys(&self, slot: usize) -> Vec<String> {
+        let mut keys = self
+            .routes
+            .iter()
+            .filter(|(_, route)| **route == slot)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    /// Take every running server, for the caller to kill.
+    fn take_servers(&mut self) -> Vec<(usize, Arc<StdMutex<Option<Child>>>)> {
+        self.instances
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(slot, instance)| {
+                instance.server.take().map(|server| (slot, server.child))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Instance {
+    server: Option<RunningServer>,
+    starts: u64,
     last_error: Option<String>,
+    /// Crashes and failed restarts within the restart window.
+    crashes: Vec<Instant>,
+    /// Tripped by too many crashes; requests are refused until a manual restart.
+    failed: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -58,6 +245,7 @@ struct RunningServer {
     base_url: String,
     child: Arc<StdMutex<Option<Child>>>,
     instance_id: u64,
+    started_at: Instant,
 }
 
 impl OpenCodeServerManager {
@@ -69,63 +257,240 @@ impl OpenCodeServerManager {
                 config,
                 ensure_lock: Mutex::new(()),
                 state: Mutex::new(ManagerState::default()),
+                events: broadcast::channel(EVENT_CHANNEL_SIZE).0,
             }),
         }
     }
 
+    /// Servers starting and stopping, including crashes and restarts.
+    pub fn subscribe(&self) -> broadcast::Receiver<OpenCodeServerEvent> {
+        self.inner.events.subscribe()
+    }
+
+    fn notify(&self, event: OpenCodeServerEvent) {
+        let _ = self.inner.events.send(event);
+    }
+
+    /// Base URL of the server for requests without a directory.
     pub async fn ensure_server(&self) -> Result<String, String> {
+        self.ensure_server_for("").await
+    }
+
+    /// Base URL of the server `key` is routed to, started if it is not running.
+    pub async fn ensure_server_for(&self, key: &str) -> Result<String, String> {
         let _guard = self.inner.ensure_lock.lock().await;
+        let slot = self.route(key).await;
+        self.ensure_slot(slot).await
+    }
 
-        if let Some(base_url) = self.running_base_url().await {
+    /// Callers hold `ensure_lock`.
+    async fn ensure_slot(&self, slot: usize) -> Result<String, String> {
+        if let Some(base_url) = self.running_base_url(slot).await {
             return Ok(base_url);
         }
+        {
+            let state = self.inner.state.lock().await;
+            if state.shutdown_requested {
+                return Err("OpenCode servers are stopped; start them with \
+                     POST /v1/agents/opencode/server/start"
+                    .to_string());
+            }
+            if state.instances[slot].failed {
+                return Err(format!(
+                    "OpenCode server {slot} crashed too often and was not restarted; \
+                     restart it with POST /v1/agents/opencode/server/restart"
+                ));
+            }
+        }
 
         let (base_url, child) = self.spawn_http_server().await?;
 
         if let Err(err) = self.wait_for_http_server(&base_url).await {
             kill_child(&child);
             let mut state = self.inner.state.lock().await;
-            state.last_error = Some(err.clone());
+            state.instances[slot].last_error = Some(err.clone());
             return Err(err);
         }
 
-        let instance_id = {
+        let (instance_id, keys) = {
             let mut state = self.inner.state.lock().await;
-            state.shutdown_requested = false;
             state.restart_count += 1;
             let instance_id = state.restart_count;
-            state.server = Some(RunningServer {
+            let instance = &mut state.instances[slot];
+            instance.starts += 1;
+            instance.server = Some(RunningServer {
                 base_url: base_url.clone(),
                 child: child.clone(),
                 instance_id,
+                started_at: Instant::now(),
             });
-            state.last_error = None;
-            instance_id
+            instance.last_error = None;
+            (instance_id, state.keys(slot))
         };
 
-        self.spawn_monitor_task(instance_id, child);
+        self.spawn_monitor_task(slot, instance_id, child);
+        self.notify(OpenCodeServerEvent::Started { index: slot, keys });
 
         Ok(base_url)
     }
 
+    /// Pool slot for `key`, assigning one the first time the key is seen.
+    async fn route(&self, key: &str) -> usize {
+        let mut state = self.inner.state.lock().await;
+        if let Some(slot) = state.routes.get(key) {
+            return *slot;
+        }
+        let slot = match self.inner.config.pool {
+            OpenCodeServerPool::Shared => 0,
+            OpenCodeServerPool::PerDirectory => state.instances.len(),
+            OpenCodeServerPool::Fixed(size) if state.instances.len() < size => {
+                state.instances.len()
+            }
+            OpenCodeServerPool::Fixed(size) => (0..size)
+                .min_by_key(|slot| state.routes.values().filter(|s| *s == slot).count())
+                .unwrap_or(0),
+        };
+        if slot >= state.instances.len() {
+            state.instances.resize_with(slot + 1, Instance::default);
+        }
+        state.routes.insert(key.to_string(), slot);
+        slot
+    }
+
+    pub async fn status(&self) -> OpenCodeServerStatus {
+        let slots = self.inner.state.lock().await.instances.len();
+        let mut running = Vec::with_capacity(slots);
+        for slot in 0..slots {
+            running.push(self.running_base_url(slot).await.is_some());
+        }
+
+        let state = self.inner.state.lock().await;
+        let instances = state
+            .instances
+            .iter()
+            .enumerate()
+            .map(|(index, instance)| {
+                let running = running.get(index).copied().unwrap_or(false);
+                OpenCodeInstanceStatus {
+                    index,
+                    running,
+                    uptime: instance
+                        .server
+                        .as_ref()
+                        .filter(|_| running)
+                        .map(|server| server.started_at.elapsed()),
+                    starts: instance.starts,
+                    last_error: instance.last_error.clone(),
+                    failed: instance.failed,
+                    keys: state.keys(index),
+                }
+            })
+            .collect::<Vec<_>>();
+        OpenCodeServerStatus {
+            running: instances.iter().any(|instance| instance.running),
+            starts: instances.iter().map(|instance| instance.starts).sum(),
+            last_error: instances
+                .iter()
+                .filter(|instance| !instance.running)
+                .find_map(|instance| instance.last_error.clone()),
+            failed: instances.iter().any(|instance| instance.failed),
+            instances,
+        }
+    }
+
+    /// Start every server that is not running, also those stopped by `shutdown` or marked
+    /// failed. Without servers yet, starts the one for requests without a directory.
+    pub async fn start(&self) -> Result<(), String> {
+        let _guard = self.inner.ensure_lock.lock().await;
+        self.start_all().await
+    }
+
+    /// Stop every server and start them again, resetting their crash counts.
+    pub async fn restart(&self) -> Result<(), String> {
+        let _guard = self.inner.ensure_lock.lock().await;
+        self.stop_all().await;
+        self.start_all().await
+    }
+
+    /// Stop every server. They stay down until `start` or `restart`; requests for them fail.
     pub async fn shutdown(&self) {
         let _guard = self.inner.ensure_lock.lock().await;
+        self.stop_all().await;
+        self.inner.state.lock().await.shutdown_requested = true;
+    }
 
-        let child = {
+    /// Callers hold `ensure_lock`.
+    async fn start_all(&self) -> Result<(), String> {
+        if self.inner.state.lock().await.instances.is_empty() {
+            self.route("").await;
+        }
+        let slots = {
             let mut state = self.inner.state.lock().await;
-            state.shutdown_requested = true;
-            state.server.take().map(|server| server.child)
+            state.shutdown_requested = false;
+            for instance in &mut state.instances {
+                if instance.server.is_none() {
+                    instance.failed = false;
+                    instance.crashes.clear();
+                }
+            }
+            state.instances.len()
         };
 
-        if let Some(child) = child {
+        let mut result = Ok(());
+        for slot in 0..slots {
+            if let Err(err) = self.ensure_slot(slot).await {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    /// Callers hold `ensure_lock`.
+    async fn stop_all(&self) {
+        let stopped = {
+            let mut state = self.inner.state.lock().await;
+            state
+                .take_servers()
+                .into_iter()
+                .map(|(slot, child)| (slot, child, state.keys(slot)))
+                .collect::<Vec<_>>()
+        };
+        for (slot, child, keys) in stopped {
             kill_child(&child);
+            self.notify(OpenCodeServerEvent::Stopped { index: slot, keys });
         }
     }
 
-    async fn running_base_url(&self) -> Option<String> {
+    /// Kill the running servers without requesting shutdown, so they are handled like crashes
+    /// and restarted when `auto_restart` is set. Returns whether a server was running.
+    #[cfg(feature = "test-utils")]
+    pub async fn kill_server(&self) -> bool {
+        let children = {
+            let state = self.inner.state.lock().await;
+            state
+                .instances
+                .iter()
+                .filter_map(|instance| instance.server.as_ref().map(|server| server.child.clone()))
+                .collect::<Vec<_>>()
+        };
+        let mut killed = false;
+        for child in children {
+            // Leave the child in place so the monitor task observes the exit.
+            let Ok(mut guard) = child.lock() else {
+                continue;
+            };
+            if let Some(child) = guard.as_mut() {
+                warn!("fault injection: killing OpenCode server");
+                killed |= child.kill().is_ok();
+            }
+        }
+        killed
+    }
+
+    async fn running_base_url(&self, slot: usize) -> Option<String> {
         let running = {
             let state = self.inner.state.lock().await;
-            state.server.clone()
+            state.instances.get(slot)?.server.clone()
         }?;
 
         if child_is_alive(&running.child) {
@@ -133,13 +498,16 @@ impl OpenCodeServerManager {
         }
 
         let mut state = self.inner.state.lock().await;
-        if state
+        let instance = &mut state.instances[slot];
+        if instance
             .server
             .as_ref()
             .map(|server| server.instance_id == running.instance_id)
             .unwrap_or(false)
         {
-            state.server = None;
+            instance.server = None;
+            let keys = state.keys(slot);
+            self.notify(OpenCodeServerEvent::Stopped { index: slot, keys });
         }
 
         None
@@ -163,13 +531,21 @@ impl OpenCodeServerManager {
     async fn spawn_http_server(&self) -> Result<(String, Arc<StdMutex<Option<Child>>>), String> {
         let agent_manager = self.inner.agent_manager.clone();
         let log_dir = self.inner.config.log_dir.clone();
+        let command_prefix = self.inner.config.command_prefix.clone();
 
         let (base_url, child) = tokio::task::spawn_blocking(move || {
             let path = agent_manager
                 .resolve_binary(AgentId::Opencode)
                 .map_err(|err| err.to_string())?;
             let port = find_available_port()?;
-            let mut command = Command::new(path);
+            let mut command = match command_prefix.split_first() {
+                Some((program, args)) => {
+                    let mut command = Command::new(program);
+                    command.args(args).arg(path);
+                    command
+                }
+                None => Command::new(path),
+            };
             let stderr = open_opencode_log(&log_dir).unwrap_or_else(|_| Stdio::null());
             command
                 .arg("serve")
@@ -187,7 +563,12 @@ impl OpenCodeServerManager {
         Ok((base_url, Arc::new(StdMutex::new(Some(child)))))
     }
 
-    fn spawn_monitor_task(&self, instance_id: u64, child: Arc<StdMutex<Option<Child>>>) {
+    fn spawn_monitor_task(
+        &self,
+        slot: usize,
+        instance_id: u64,
+        child: Arc<StdMutex<Option<Child>>>,
+    ) {
         let manager = self.clone();
         tokio::spawn(async move {
             loop {
@@ -206,7 +587,7 @@ impl OpenCodeServerManager {
                 };
 
                 if let Some(status) = status {
-                    manager.handle_process_exit(instance_id, status).await;
+                    manager.handle_process_exit(slot, instance_id, status).await;
                     return;
                 }
 
@@ -215,10 +596,12 @@ impl OpenCodeServerManager {
         });
     }
 
-    async fn handle_process_exit(&self, instance_id: u64, status: ExitStatus) {
+    async fn handle_process_exit(&self, slot: usize, instance_id: u64, status: ExitStatus) {
         let (should_restart, error_message) = {
             let mut state = self.inner.state.lock().await;
-            let Some(server) = state.server.as_ref() else {
+            let shutdown_requested = state.shutdown_requested;
+            let instance = &mut state.instances[slot];
+            let Some(server) = instance.server.as_ref() else {
                 return;
             };
             if server.instance_id != instance_id {
@@ -226,11 +609,12 @@ impl OpenCodeServerManager {
             }
 
             let message = format!("OpenCode server exited with status {:?}", status);
-            let shutdown_requested = state.shutdown_requested;
             if !shutdown_requested {
-                state.last_error = Some(message.clone());
+                instance.last_error = Some(message.clone());
             }
-            state.server = None;
+            instance.server = None;
+            let keys = state.keys(slot);
+            self.notify(OpenCodeServerEvent::Stopped { index: slot, keys });
 
             (
                 !shutdown_requested && self.inner.config.auto_restart,
@@ -238,22 +622,63 @@ impl OpenCodeServerManager {
             )
         };
 
-        if !should_restart {
-            return;
+        if should_restart {
+            self.spawn_restart(slot, error_message);
         }
+    }
 
+    /// Restart `slot` after the backoff for its crashes so far, retrying failed starts, until
+    /// it runs or the circuit breaker trips.
+    fn spawn_restart(&self, slot: usize, reason: String) {
         let manager = self.clone();
         tokio::spawn(async move {
-            sleep(Duration::from_millis(MONITOR_DELAY_MS)).await;
-            if let Err(err) = manager.ensure_server().await {
-                warn!(
-                    error = ?err,
-                    prior_exit = %error_message,
-                    "failed to restart OpenCode compat sidecar"
-                );
+            let mut reason = reason;
+            loop {
+                let Some(delay) = manager.record_crash(slot, &reason).await else {
+                    return;
+                };
+                sleep(delay).await;
+                let _guard = manager.inner.ensure_lock.lock().await;
+                {
+                    let state = manager.inner.state.lock().await;
+                    if state.shutdown_requested || state.instances[slot].failed {
+                        return;
+                    }
+                }
+                match manager.ensure_slot(slot).await {
+                    Ok(_) => return,
+                    Err(err) => {
+                        warn!(error = ?err, prior_exit = %reason, "failed to restart OpenCode compat sidecar");
+                        reason = err;
+                    }
+                }
             }
         });
     }
+
+    /// Count a crash of `slot` and return the backoff before restarting it, or `None` once
+    /// it crashed more than `max_restarts` times within the window.
+    async fn record_crash(&self, slot: usize, reason: &str) -> Option<Duration> {
+        let policy = self.inner.config.restart;
+        let mut state = self.inner.state.lock().await;
+        let instance = &mut state.instances[slot];
+        let now = Instant::now();
+        instance
+            .crashes
+            .retain(|crash| now.duration_since(*crash) < policy.window);
+        instance.crashes.push(now);
+        if instance.crashes.len() > policy.max_restarts {
+            instance.failed = true;
+            instance.last_error = Some(format!(
+                "{reason}; not restarted after {} crashes within {}s",
+                instance.crashes.len(),
+                policy.window.as_secs()
+            ));
+            warn!(slot, reason, "OpenCode server crashed too often; giving up");
+            return None;
+        }
+        Some(policy.backoff(instance.crashes.len()))
+    }
 }
 
 fn default_log_dir() -> PathBuf {
@@ -308,3 +733,97 @@ fn kill_child(child: &Arc<StdMutex<Option<Child>>>) {
         *guard = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(pool: OpenCodeServerPool) -> OpenCodeServerManager {
+        let agent_manager = AgentManager::new(std::env::temp_dir()).expect("agent manager");
+        OpenCodeServerManager::new(
+            Arc::new(agent_manager),
+            OpenCodeServerManagerConfig {
+                pool,
+                ..OpenCodeServerManagerConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn pool_parses_strategies() {
+        assert_eq!(
+            OpenCodeServerPool::parse("shared"),
+            Some(OpenCodeServerPool::Shared)
+        );
+        assert_eq!(
+            OpenCodeServerPool::parse("per-directory"),
+            Some(OpenCodeServerPool::PerDirectory)
+        );
+        assert_eq!(
+            OpenCodeServerPool::parse("pool:3"),
+            Some(OpenCodeServerPool::Fixed(3))
+        );
+        assert_eq!(OpenCodeServerPool::parse("pool:0"), None);
+        assert_eq!(OpenCodeServerPool::parse("per-session"), None);
+    }
+
+    #[test]
+    fn restart_backoff_doubles_up_to_the_cap() {
+        let policy = RestartPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            ..RestartPolicy::default()
+        };
+        for (crashes, full) in [(1, 1), (2, 2), (3, 4), (4, 8), (5, 10), (40, 10)] {
+            let backoff = policy.backoff(crashes);
+            let full = Duration::from_secs(full);
+            assert!(
+                backoff >= full / 2 && backoff <= full,
+                "{crashes}: {backoff:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn crashes_trip_the_circuit_breaker() {
+        let manager = manager(OpenCodeServerPool::Shared);
+        let slot = manager.route("").await;
+        for _ in 0..RestartPolicy::default().max_restarts {
+            assert!(manager.record_crash(slot, "exited").await.is_some());
+        }
+        assert!(manager.record_crash(slot, "exited").await.is_none());
+        let status = manager.status().await;
+        assert!(status.failed);
+        assert!(manager.ensure_server().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stopped_servers_stay_down_until_started() {
+        let manager = manager(OpenCodeServerPool::Shared);
+        manager.shutdown().await;
+        let err = manager.ensure_server().await.expect_err("stopped");
+        assert!(err.contains("server/start"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn keys_are_routed_by_strategy() {
+        let shared = manager(OpenCodeServerPool::Shared);
+        assert_eq!(shared.route("/a").await, 0);
+        assert_eq!(shared.route("/b").await, 0);
+
+        let isolated = manager(OpenCodeServerPool::PerDirectory);
+        assert_eq!(isolated.route("/a").await, 0);
+        assert_eq!(isolated.route("/b").await, 1);
+        assert_eq!(isolated.route("/a").await, 0);
+
+        let pooled = manager(OpenCodeServerPool::Fixed(2));
+        assert_eq!(pooled.route("/a").await, 0);
+        assert_eq!(pooled.route("/b").await, 1);
+        assert_eq!(pooled.route("/c").await, 0);
+        assert_eq!(pooled.route("/d").await, 1);
+        let status = pooled.status().await;
+        assert_eq!(status.instances.len(), 2);
+        assert_eq!(status.instances[1].keys, ["/b", "/d"]);
+        assert!(!status.running);
+    }
+}
@@ -0,0 +1,229 @@
+//! On-disk archive of ended ACP servers.
+//!
+//! A deleted or timed-out server drops its agent process and event buffer. With
+//! `SANDBOX_AGENT_ACP_ARCHIVE_DIR` set, the server's info and buffered events are written
+//! there first, so `GET /v1/acp?archived=true` and `GET /v1/acp/{server_id}/events.jsonl`
+//! keep serving them read-only:
+//!
+//! ```text
+//! {dir}/{base64url(server_id)}/events.jsonl   {"sequence": 1, "message": {...}} per line
+//! {dir}/{base64url(server_id)}/prompts.jsonl  {"sessionId": "...", "prompt": [...]} per line
+//! {dir}/{base64url(server_id)}/server.json    AcpServerInfo with "archived": true
+//! ```
+//!
+//! Archives older than `SANDBOX_AGENT_ACP_ARCHIVE_MAX_AGE_HOURS` are removed when the next
+//! server is archived.
+
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+
+use crate::router::{map_fs_error, AcpServerInfo};
+
+pub const ARCHIVE_DIR_ENV: &str = "SANDBOX_AGENT_ACP_ARCHIVE_DIR";
+pub const ARCHIVE_MAX_AGE_ENV: &str = "SANDBOX_AGENT_ACP_ARCHIVE_MAX_AGE_HOURS";
+const SERVER_FILE: &str = "server.json";
+const EVENTS_FILE: &str = "events.jsonl";
+const PROMPTS_FILE: &str = "prompts.jsonl";
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AcpArchive {
+    /// Archiving is off without a directory.
+    dir: Option<PathBuf>,
+    max_age: Option<Duration>,
+}
+
+impl AcpArchive {
+    pub(crate) fn from_env() -> Self {
+        let dir = std::env::var_os(ARCHIVE_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from);
+        let max_age = std::env::var(ARCHIVE_MAX_AGE_ENV)
+            .ok()
+            .and_then(|raw| raw.trim().parse::<u64>().ok())
+            .filter(|hours| *hours > 0)
+            .map(|hours| Duration::from_secs(hours * 3600));
+        Self { dir, max_age }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// Archive an ended server, replacing any earlier archive with the same id.
+    pub(crate) fn store(
+        &self,
+        mut info: AcpServerInfo,
+        events: Vec<(u64, Value)>,
+        prompts: Vec<Value>,
+    ) -> Result<(), SandboxError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        self.prune(dir);
+        info.archived = true;
+        let path = server_dir(dir, &info.server_id);
+        if path.exists() {
+            fs::remove_dir_all(&path).map_err(|err| map_fs_error(&path, err))?;
+        }
+        fs::create_dir_all(&path).map_err(|err| map_fs_error(&path, err))?;
+
+        write_lines(
+            &path.join(EVENTS_FILE),
+            events
+                .into_iter()
+                .map(|(sequence, message)| json!({ "sequence": sequence, "message": message })),
+        )?;
+        write_lines(&path.join(PROMPTS_FILE), prompts)?;
+
+        // Written last: an archive without server.json is incomplete and not listed.
+        let server_path = path.join(SERVER_FILE);
+        let body = serde_json::to_vec_pretty(&info).map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })?;
+        fs::write(&server_path, body).map_err(|err| map_fs_error(&server_path, err))
+    }
+
+    /// Drop archives that expired while the daemon was down and count the rest. Run once
+    /// at startup, before the daemon reports ready.
+    pub(crate) fn load(&self) -> usize {
+        let Some(dir) = &self.dir else {
+            return 0;
+        };
+        self.prune(dir);
+        self.list().len()
+    }
+
+    /// Every archived server, in no particular order.
+    pub(crate) fn list(&self) -> Vec<AcpServerInfo> {
+        let Some(dir) = &self.dir else {
+            return Vec::new();
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| read_server(&entry.path()))
+            .collect()
+    }
+
+    pub(crate) fn contains(&self, server_id: &str) -> bool {
+        self.dir
+            .as_deref()
+            .is_some_and(|dir| server_dir(dir, server_id).join(SERVER_FILE).is_file())
+    }
+
+    /// Remove the archive of `server_id`, if there is one.
+    pub(crate) fn remove(&self, server_id: &str) -> Result<(), SandboxError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let path = server_dir(dir, server_id);
+        match fs::remove_dir_all(&path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(map_fs_error(&path, err)),
+        }
+    }
+
+    /// Archived events of `server_id` with a sequence greater than `after`.
+    pub(crate) fn events(
+        &self,
+        server_id: &str,
+        after: Option<u64>,
+    ) -> Result<Vec<(u64, Value)>, SandboxError> {
+        let not_found = || SandboxError::SessionNotFound {
+            session_id: server_id.to_string(),
+        };
+        if !self.contains(server_id) {
+            return Err(not_found());
+        }
+        let dir = self.dir.as_deref().ok_or_else(not_found)?;
+        let after = after.unwrap_or(0);
+        Ok(read_lines(&server_dir(dir, server_id).join(EVENTS_FILE))?
+            .into_iter()
+            .filter_map(|mut event| {
+                let sequence = event.get("sequence")?.as_u64()?;
+                Some((sequence, event.get_mut("message")?.take()))
+            })
+            .filter(|(sequence, _)| *sequence > after)
+            .collect())
+    }
+
+    /// Prompts recorded for `server_id` before it ended.
+    pub(crate) fn prompts(&self, server_id: &str) -> Result<Vec<Value>, SandboxError> {
+        let not_found = || SandboxError::SessionNotFound {
+            session_id: server_id.to_string(),
+        };
+        if !self.contains(server_id) {
+            return Err(not_found());
+        }
+        let dir = self.dir.as_deref().ok_or_else(not_found)?;
+        let path = server_dir(dir, server_id).join(PROMPTS_FILE);
+        if !path.is_file() {
+            return Ok(Vec::new());
+        }
+        read_lines(&path)
+    }
+
+    /// The archived info of `server_id`.
+    pub(crate) fn server(&self, server_id: &str) -> Option<AcpServerInfo> {
+        read_server(&server_dir(self.dir.as_deref()?, server_id))
+    }
+
+    fn prune(&self, dir: &Path) {
+        let Some(max_age) = self.max_age else {
+            return;
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let now = SystemTime::now();
+        for entry in entries.flatten() {
+            let expired = fs::metadata(entry.path().join(SERVER_FILE))
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age > max_age);
+            if expired {
+                if let Err(err) = fs::remove_dir_all(entry.path()) {
+                    tracing::warn!(path = %entry.path().display(), error = %err, "acp_archive: failed to remove expired archive");
+                }
+            }
+        }
+    }
+}
+
+fn server_dir(dir: &Path, server_id: &str) -> PathBuf {
+    dir.join(URL_SAFE_NO_PAD.encode(server_id))
+}
+
+fn write_lines(path: &Path, lines: impl IntoIterator<Item = Value>) -> Result<(), SandboxError> {
+    let file = fs::File::create(path).map_err(|err| map_fs_error(path, err))?;
+    let mut writer = BufWriter::new(file);
+    for line in lines {
+        writeln!(writer, "{line}").map_err(|err| map_fs_error(path, err))?;
+    }
+    writer.flush().map_err(|err| map_fs_error(path, err))
+}
+
+fn read_lines(path: &Path) -> Result<Vec<Value>, SandboxError> {
+    let file = fs::File::open(path).map_err(|err| map_fs_error(path, err))?;
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+fn read_server(path: &Path) -> Option<AcpServerInfo> {
+    let body = fs::read(path.join(SERVER_FILE)).ok()?;
+    serde_json::from_slice(&body).ok()
+}
@@ -0,0 +1,255 @@
+//! Fan-out runs: one prompt sent to several agents at once.
+//!
+//! `POST /v1/fanout` starts an ACP server per run, labelled `fanout={fanoutId}`, opens a
+//! session on each and sends them the same prompt concurrently. `GET /v1/fanout/{id}`
+//! reports how each run ended, and `GET /v1/fanout/{id}/events` merges their event streams.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+
+use crate::acp_proxy_runtime::{AcpBootstrap, AcpProxyRuntime, AcpServerMetadata};
+use crate::router::{now_ms, AcpFanoutRunStatus};
+
+#[derive(Debug, Clone)]
+pub(crate) struct FanoutRunSpec {
+    pub server_id: String,
+    pub agent: AgentId,
+    pub model: Option<String>,
+    pub directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FanoutRunState {
+    pub status: AcpFanoutRunStatus,
+    pub session_id: Option<String>,
+    pub stop_reason: Option<String>,
+    pub error: Option<String>,
+    pub ended_at_ms: Option<i64>,
+}
+
+#[derive(Debug)]
+pub(crate) struct FanoutRun {
+    pub spec: FanoutRunSpec,
+    state: StdMutex<FanoutRunState>,
+}
+
+impl FanoutRun {
+    pub(crate) fn state(&self) -> FanoutRunState {
+        self.state
+            .lock()
+            .map(|state| state.clone())
+            .unwrap_or_else(|poisoned| poisoned.into_inner().clone())
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut FanoutRunState)) {
+        if let Ok(mut state) = self.state.lock() {
+            apply(&mut state);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Fanout {
+    pub id: String,
+    pub created_at_ms: i64,
+    pub runs: Vec<Arc<FanoutRun>>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct AcpFanouts {
+    fanouts: StdMutex<HashMap<String, Arc<Fanout>>>,
+}
+
+impl AcpFanouts {
+    pub(crate) fn get(&self, fanout_id: &str) -> Result<Arc<Fanout>, SandboxError> {
+        self.fanouts
+            .lock()
+            .ok()
+            .and_then(|fanouts| fanouts.get(fanout_id).cloned())
+            .ok_or_else(|| SandboxError::NotFound {
+                path: format!("/v1/fanout/{fanout_id}"),
+            })
+    }
+
+    pub(crate) fn remove(&self, fanout_id: &str) -> Option<Arc<Fanout>> {
+        self.fanouts.lock().ok()?.remove(fanout_id)
+    }
+
+    fn insert(&self, fanout: Arc<Fanout>) -> Result<(), SandboxError> {
+        let mut fanouts = self.fanouts.lock().map_err(|_| SandboxError::StreamError {
+            message: "fanout registry poisoned".to_string(),
+        })?;
+        if fanouts.contains_key(&fanout.id) {
+            return Err(SandboxError::Conflict {
+                message: format!("fanout '{}' already exists", fanout.id),
+            });
+        }
+        fanouts.insert(fanout.id.clone(), fanout);
+        Ok(())
+    }
+}
+
+/// Register the fanout and start its runs in the background.
+pub(crate) async fn start_fanout(
+    runtime: &AcpProxyRuntime,
+    fanout_id: &str,
+    prompt: Value,
+    specs: Vec<FanoutRunSpec>,
+) -> Result<Arc<Fanout>, SandboxError> {
+    if runtime.fanouts().get(fanout_id).is_ok() {
+        return Err(SandboxError::Conflict {
+            message: format!("fanout '{fanout_id}' already exists"),
+        });
+    }
+    let running = runtime.list_instances().await;
+    if let Some(taken) = specs
+        .iter()
+        .find(|spec| running.iter().any(|info| info.server_id == spec.server_id))
+    {
+        return Err(SandboxError::Conflict {
+            message: format!("server '{}' already exists", taken.server_id),
+        });
+    }
+
+    let now = now_ms();
+    let fanout = Arc::new(Fanout {
+        id: fanout_id.to_string(),
+        created_at_ms: now,
+        runs: specs
+            .into_iter()
+            .map(|spec| {
+                Arc::new(FanoutRun {
+                    spec,
+                    state: StdMutex::new(FanoutRunState {
+                        status: AcpFanoutRunStatus::Running,
+                        session_id: None,
+                        stop_reason: None,
+                        error: None,
+                        ended_at_ms: None,
+                    }),
+                })
+            })
+            .collect(),
+    });
+    runtime.fanouts().insert(fanout.clone())?;
+
+    for run in &fanout.runs {
+        let runtime = runtime.clone();
+        let run = run.clone();
+        let prompt = prompt.clone();
+        let fanout_id = fanout_id.to_string();
+        tokio::spawn(async move {
+            let outcome = drive_run(&runtime, &fanout_id, &run, prompt).await;
+            run.update(|state| {
+                state.ended_at_ms = Some(now_ms());
+                match outcome {
+                    Ok(stop_reason) => {
+                        state.status = AcpFanoutRunStatus::Completed;
+                        state.stop_reason = stop_reason;
+                    }
+                    Err(message) => {
+                        state.status = AcpFanoutRunStatus::Failed;
+                        state.error = Some(message);
+                    }
+                }
+            });
+        });
+    }
+    Ok(fanout)
+}
+
+/// Start the run's server, open a session, and send the prompt. Returns the stop reason.
+async fn drive_run(
+    runtime: &AcpProxyRuntime,
+    fanout_id: &str,
+    run: &FanoutRun,
+    prompt: Value,
+) -> Result<Option<String>, String> {
+    let spec = &run.spec;
+    let request = |id: u64, method: &str, params: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": format!("sandboxagent-fanout-{id}"),
+            "method": method,
+            "params": params,
+        })
+    };
+    let bootstrap = AcpBootstrap {
+        agent: Some(spec.agent),
+        directory: spec.directory.clone(),
+        metadata: AcpServerMetadata {
+            title: None,
+            labels: BTreeMap::from([("fanout".to_string(), fanout_id.to_string())]),
+        },
+        ..AcpBootstrap::default()
+    };
+    runtime
+        .client_request(
+            &spec.server_id,
+            bootstrap,
+            request(
+                1,
+                "initialize",
+                json!({ "protocolVersion": 1, "clientCapabilities": {} }),
+            ),
+        )
+        .await?;
+
+    let cwd = match &spec.directory {
+        Some(directory) => directory.clone(),
+        None => runtime
+            .workspace_root(&spec.server_id)
+            .await
+            .map_err(|err| err.to_string())?,
+    };
+    let created = runtime
+        .client_request(
+            &spec.server_id,
+            AcpBootstrap::default(),
+            request(
+                2,
+                "session/new",
+                json!({ "cwd": cwd.to_string_lossy(), "mcpServers": [] }),
+            ),
+        )
+        .await?;
+    let session_id = created
+        .pointer("/result/sessionId")
+        .cloned()
+        .ok_or_else(|| "session/new returned no sessionId".to_string())?;
+    run.update(|state| state.session_id = session_id.as_str().map(str::to_string));
+
+    if let Some(model) = &spec.model {
+        runtime
+            .client_request(
+                &spec.server_id,
+                AcpBootstrap::default(),
+                request(
+                    3,
+                    "session/set_model",
+                    json!({ "sessionId": session_id, "modelId": model }),
+                ),
+            )
+            .await?;
+    }
+    let response = runtime
+        .client_request(
+            &spec.server_id,
+            AcpBootstrap::default(),
+            request(
+                4,
+                "session/prompt",
+                json!({ "sessionId": session_id, "prompt": prompt }),
+            ),
+        )
+        .await?;
+    Ok(response
+        .pointer("/result/stopReason")
+        .and_then(Value::as_str)
+        .map(str::to_string))
+}
@@ -1,20 +1,72 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, OnceLock, Weak};
 use std::time::Duration;
 
-use acp_http_adapter::process::{AdapterError, AdapterRuntime, PostOutcome};
+use acp_http_adapter::process::{AdapterError, AdapterRuntime, MessageFilter, PostOutcome};
 use acp_http_adapter::registry::LaunchSpec;
 use axum::response::sse::Event;
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use sandbox_agent_agent_management::agents::{AgentId, AgentManager, InstallOptions};
+use sandbox_agent_api_types::{
+    AcpChildItem, AcpCompactResponse, AcpContextWindowInfo, AcpItem, AcpOccupancy, AcpSessionTurn,
+    AuditKind, RateLimitEventsResponse, RateLimitServerCount, UnparsedEventsResponse,
+    UnparsedServerCount,
+};
 use sandbox_agent_error::SandboxError;
 use sandbox_agent_opencode_adapter::{AcpDispatch, AcpDispatchResult, AcpPayloadStream};
-use serde_json::Value;
+use serde_json::{json, Value};
 use tokio::sync::{Mutex, RwLock};
 
+use crate::acp_archive::AcpArchive;
+use crate::acp_fanout::AcpFanouts;
+use crate::acp_runs::{message_chunk, session_update};
+use crate::acp_usage::{AcpContextWindow, AcpUsage};
+use crate::admission::{Admission, AdmissionSlot};
+use crate::agent_capabilities::AgentCapabilityProbe;
+use crate::agent_config::{
+    apply_session_config, apply_session_streaming, apply_session_system_prompt,
+    take_session_variant, variant_request, with_variant_outcome,
+};
+use crate::agent_sandbox::{self, Confinement, SandboxLevel};
+use crate::approval_routing::{ApprovalRouter, RouteToken};
+use crate::approvals::{PendingApproval, PendingApprovals};
+use crate::audit::{audit_entry, AuditLog};
+use crate::blobs::BlobStore;
+use crate::compaction;
+use crate::egress::{BlockedCallback, BlockedConnection, EgressPolicy, EgressProxy, EgressRule};
+use crate::event_filter::EventFilter;
+use crate::event_sink::EventSink;
+use crate::findings::{self, Finding};
+use crate::firehose::Firehose;
+use crate::instruction_files::{self, InstructionTemplates};
+use crate::mcp_oauth::{AuthFailure, McpOAuth};
+use crate::plans::{self, PlanState, Plans};
+use crate::prompt_attachments::write_inline_attachments;
+use crate::question_policy::{self, SessionQuestionPolicies};
+use crate::rate_limits::{RateLimitLog, ServerRateLimits};
+use crate::resource_limits::{self, Cgroups, ResourceLimits};
+use crate::router::now_ms;
+use crate::session_items::SessionItemLog;
+use crate::structured_output::{parse_output, take_output_schema};
+use crate::subagents::SubAgents;
+use crate::task_lists::{TaskList, TaskLists};
+use crate::turn_diff::{TurnLog, TurnRecord, WorkspaceSnapshot};
+use crate::turn_overrides::Selections;
+use crate::turn_retry::{self, RetryPolicy};
+use crate::turn_timeout::{self, SessionTimeouts, TimeoutOutcome};
+use crate::unparsed::{self, UnparsedLog};
+use crate::webhooks::Webhooks;
+
 const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 120_000;
+const WARM_POOL_ENV: &str = "SANDBOX_AGENT_ACP_WARM_POOL";
+const IDLE_TIMEOUT_ENV: &str = "SANDBOX_AGENT_ACP_IDLE_TIMEOUT_MS";
+const IDLE_REAP_INTERVAL: Duration = Duration::from_secs(1);
+/// Prompts kept per server for `/v1/acp/{server_id}/replay`; later prompts are not recorded.
+const MAX_RECORDED_PROMPTS: usize = 1000;
 
 #[derive(Debug, Clone)]
 pub struct AcpProxyRuntime {
@@ -29,6 +81,48 @@ struct AcpProxyRuntimeInner {
     instances: RwLock<HashMap<String, Arc<ProxyInstance>>>,
     instance_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
     install_locks: Mutex<HashMap<AgentId, Arc<Mutex<()>>>>,
+    warm_pool_targets: HashMap<AgentId, usize>,
+    warm_pool: Mutex<HashMap<AgentId, Vec<Arc<AdapterRuntime>>>>,
+    warm_pool_fill_lock: Mutex<()>,
+    firehose: Arc<Firehose>,
+    /// Daemon-wide idle timeout for ACP servers that don't set their own.
+    idle_timeout: Option<Duration>,
+    idle_reaper_started: AtomicBool,
+    /// Daemon-wide limits for agent processes; see `crate::resource_limits`.
+    resource_limits: ResourceLimits,
+    /// Detected the first time an agent is started with limits.
+    cgroups: OnceLock<Option<Cgroups>>,
+    /// Daemon-wide confinement for agent processes; see `crate::agent_sandbox`.
+    sandbox_level: SandboxLevel,
+    /// Daemon-wide egress policy for agent processes; see `crate::egress`.
+    egress: EgressPolicy,
+    /// Receives connections refused by egress policies.
+    audit: OnceLock<Arc<AuditLog>>,
+    /// Caps on ACP servers and running turns; see `crate::admission`.
+    admission: Arc<Admission>,
+    webhooks: Webhooks,
+    /// Holds matching permission requests for an external approver; see
+    /// `crate::approval_routing`.
+    approval_router: Option<Arc<ApprovalRouter>>,
+    /// Publishes every event to Kafka or NATS once started; see `crate::event_sink`.
+    event_sink: OnceLock<Arc<EventSink>>,
+    blobs: Arc<BlobStore>,
+    mcp_oauth: Arc<McpOAuth>,
+    /// Templates `session/new` writes instruction files from; see `crate::instruction_files`.
+    instruction_templates: InstructionTemplates,
+    agent_capabilities: AgentCapabilityProbe,
+    archive: Arc<AcpArchive>,
+    fanouts: AcpFanouts,
+    /// Agent output that could not be parsed; see `crate::unparsed`.
+    unparsed: Arc<UnparsedLog>,
+    /// Unparsed lines a turn may produce before `session/prompt` fails.
+    max_unparsed_per_turn: Option<u64>,
+    /// Provider rate limits agents reported; see `crate::rate_limits`.
+    rate_limits: Arc<RateLimitLog>,
+    /// Daemon-wide turn timeout for sessions that don't set their own.
+    turn_timeout: Option<Duration>,
+    #[cfg(feature = "test-utils")]
+    chaos: Option<Arc<crate::chaos::Chaos>>,
 }
 
 #[derive(Debug)]
@@ -37,6 +131,214 @@ struct ProxyInstance {
     agent: AgentId,
     runtime: Arc<AdapterRuntime>,
     created_at_ms: i64,
+    directory: Option<PathBuf>,
+    usage: Arc<StdMutex<AcpUsage>>,
+    /// Agent stdout lines that were not JSON.
+    unparsed: Arc<AtomicU64>,
+    /// Provider rate limits the agent reported.
+    rate_limits: ServerRateLimits,
+    approvals: Arc<StdMutex<PendingApprovals>>,
+    /// Snapshot the workspace around every `session/prompt`.
+    snapshot_turns: bool,
+    turns: StdMutex<TurnLog>,
+    /// Shut the server down after this long without POSTs or agent events.
+    idle_timeout: Option<Duration>,
+    /// Limits the agent process was started with.
+    resource_limits: ResourceLimits,
+    sandbox_level: SandboxLevel,
+    egress: EgressPolicy,
+    last_activity_ms: Arc<AtomicI64>,
+    /// POSTs still waiting on the agent; the server is never idle while one is open.
+    in_flight: AtomicUsize,
+    /// Sessions with a `session/prompt` waiting on the agent, and how many.
+    prompting: StdMutex<HashMap<String, usize>>,
+    /// Receives this server's webhook events in addition to the global webhook URLs.
+    webhook_url: Option<String>,
+    metadata: StdMutex<AcpServerMetadata>,
+    /// `session/prompt` params (`sessionId` and `prompt`) in the order clients sent them.
+    prompts: StdMutex<Vec<Value>>,
+    /// Model and mode of each session, to switch back after a per-turn override.
+    selections: StdMutex<Selections>,
+    /// Turn timeouts sessions were created with.
+    turn_timeouts: StdMutex<SessionTimeouts>,
+    /// Question timeouts sessions were created with.
+    question_policies: Arc<StdMutex<SessionQuestionPolicies>>,
+    /// Instruction files written for this server's sessions, removed when it ends.
+    instruction_files: StdMutex<Vec<PathBuf>>,
+    /// Plan mode state of each session.
+    plans: Arc<StdMutex<Plans>>,
+    /// Latest todo list of each session.
+    task_lists: Arc<StdMutex<TaskLists>>,
+    /// Tool calls of each session, linked to the sub-agents that made them.
+    subagents: Arc<StdMutex<SubAgents>>,
+    /// Transcript items of each session.
+    items: Arc<StdMutex<SessionItemLog>>,
+    /// Released when the server is removed.
+    _admission: AdmissionSlot,
+}
+
+/// Settings a server is created with that don't affect where its agent runs.
+#[derive(Debug, Clone, Default)]
+struct InstanceOptions {
+    snapshot_turns: bool,
+    idle_timeout: Option<Duration>,
+    webhook_url: Option<String>,
+    resource_limits: ResourceLimits,
+    sandbox_level: SandboxLevel,
+    egress: EgressPolicy,
+}
+
+impl ProxyInstance {
+    fn new(
+        server_id: &str,
+        agent: AgentId,
+        runtime: Arc<AdapterRuntime>,
+        directory: Option<PathBuf>,
+        options: InstanceOptions,
+        admission: AdmissionSlot,
+    ) -> Self {
+        let InstanceOptions {
+            snapshot_turns,
+            idle_timeout,
+            webhook_url,
+            resource_limits,
+            sandbox_level,
+            egress,
+        } = options;
+        let now = now_ms();
+        Self {
+            server_id: server_id.to_string(),
+            agent,
+            runtime,
+            created_at_ms: now,
+            directory,
+            usage: Arc::default(),
+            unparsed: Arc::default(),
+            rate_limits: ServerRateLimits::default(),
+            approvals: Arc::default(),
+            snapshot_turns,
+            turns: StdMutex::default(),
+            idle_timeout,
+            resource_limits,
+            sandbox_level,
+            egress,
+            last_activity_ms: Arc::new(AtomicI64::new(now)),
+            in_flight: AtomicUsize::new(0),
+            prompting: StdMutex::default(),
+            webhook_url,
+            metadata: StdMutex::default(),
+            prompts: StdMutex::default(),
+            selections: StdMutex::default(),
+            turn_timeouts: StdMutex::default(),
+            question_policies: Arc::default(),
+            instruction_files: StdMutex::default(),
+            plans: Arc::default(),
+            task_lists: Arc::default(),
+            subagents: Arc::default(),
+            items: Arc::default(),
+            _admission: admission,
+        }
+    }
+
+    /// Keep the instruction files written for a `session/new` when it created a session, and
+    /// remove them when it did not.
+    fn keep_instruction_files(&self, response: Value, written: Vec<PathBuf>) -> Value {
+        if response.get("result").is_none() {
+            instruction_files::remove_instruction_files(&written);
+            return response;
+        }
+        let response = instruction_files::with_instruction_files(response, &written);
+        if let Ok(mut files) = self.instruction_files.lock() {
+            files.extend(written);
+        }
+        response
+    }
+
+    /// Remove the instruction files written for this server's sessions.
+    fn remove_instruction_files(&self) {
+        if let Ok(mut files) = self.instruction_files.lock() {
+            instruction_files::remove_instruction_files(&files);
+            files.clear();
+        }
+    }
+
+    fn record_prompt(&self, payload: &Value) {
+        if let Ok(mut items) = self.items.lock() {
+            items.record_prompt(self.runtime.last_event_id(), payload, now_ms());
+        }
+        let (Some(session_id), Some(prompt)) = (
+            payload.pointer("/params/sessionId"),
+            payload.pointer("/params/prompt"),
+        ) else {
+            return;
+        };
+        if let Ok(mut prompts) = self.prompts.lock() {
+            if prompts.len() < MAX_RECORDED_PROMPTS {
+                prompts.push(json!({ "sessionId": session_id, "prompt": prompt }));
+            }
+        }
+    }
+
+    fn prompts(&self) -> Vec<Value> {
+        self.prompts
+            .lock()
+            .map(|prompts| prompts.clone())
+            .unwrap_or_default()
+    }
+
+    fn metadata(&self) -> AcpServerMetadata {
+        self.metadata
+            .lock()
+            .map(|metadata| metadata.clone())
+            .unwrap_or_default()
+    }
+
+    fn info(&self) -> AcpServerInstanceInfo {
+        AcpServerInstanceInfo {
+            server_id: self.server_id.clone(),
+            agent: self.agent,
+            created_at_ms: self.created_at_ms,
+            last_event_id: self.runtime.last_event_id(),
+            directory: self.directory.clone(),
+            usage: self.usage(),
+            metadata: self.metadata(),
+            exited: self.runtime.has_exited(),
+        }
+    }
+
+    fn touch(&self) {
+        self.last_activity_ms.store(now_ms(), Ordering::SeqCst);
+    }
+
+    /// Whether a session other than `session_id` has a turn running.
+    fn others_prompting(&self, session_id: &str) -> bool {
+        self.prompting
+            .lock()
+            .map(|prompting| prompting.keys().any(|other| other != session_id))
+            .unwrap_or(false)
+    }
+
+    fn is_idle(&self, now: i64) -> bool {
+        let Some(timeout) = self.idle_timeout else {
+            return false;
+        };
+        self.in_flight.load(Ordering::SeqCst) == 0
+            && now - self.last_activity_ms.load(Ordering::SeqCst) >= timeout.as_millis() as i64
+    }
+
+    fn usage(&self) -> AcpUsage {
+        self.usage
+            .lock()
+            .map(|usage| usage.clone())
+            .unwrap_or_default()
+    }
+
+    /// Directory the agent works in: the bootstrap directory, or the server's own.
+    fn workspace_root(&self) -> Option<PathBuf> {
+        self.directory
+            .clone()
+            .or_else(|| std::env::current_dir().ok())
+    }
 }
 
 #[derive(Debug)]
@@ -50,6 +352,126 @@ pub struct AcpServerInstanceInfo {
     pub server_id: String,
     pub agent: AgentId,
     pub created_at_ms: i64,
+    pub last_event_id: u64,
+    pub directory: Option<PathBuf>,
+    pub usage: AcpUsage,
+    pub metadata: AcpServerMetadata,
+    /// The agent process exited while the server is still open.
+    pub exited: bool,
+}
+
+/// Most labels a server can carry.
+pub const MAX_SERVER_LABELS: usize = 64;
+
+/// Title and labels clients attach to a server to attribute it to users and projects.
+#[derive(Debug, Clone, Default)]
+pub struct AcpServerMetadata {
+    pub title: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// One event from any ACP server, as fanned into the firehose channel.
+#[derive(Debug, Clone)]
+pub struct AcpFirehoseEvent {
+    pub server_id: String,
+    pub agent: AgentId,
+    pub sequence: u64,
+    pub message: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct AcpForkInfo {
+    pub server_id: String,
+    pub agent: AgentId,
+    pub forked_from: String,
+    pub history_events: usize,
+    pub last_event_id: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AcpReplayInfo {
+    pub server_id: String,
+    pub agent: AgentId,
+    pub replayed_from: String,
+    pub prompts: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct AcpWarmPoolInfo {
+    pub agent: AgentId,
+    pub idle: usize,
+    pub target: usize,
+}
+
+/// Options read from the query string of a POST to `/v1/acp/{server_id}`. Apart from
+/// `snapshot_turns`, they only apply to the POST that creates the server.
+#[derive(Debug, Default)]
+pub struct AcpBootstrap {
+    pub agent: Option<AgentId>,
+    pub from_pool: bool,
+    pub directory: Option<PathBuf>,
+    pub snapshot_turns: bool,
+    /// Overrides the daemon idle timeout; zero keeps the server until it is deleted.
+    pub idle_timeout: Option<Duration>,
+    /// Extra webhook URL for this server's events.
+    pub webhook_url: Option<String>,
+    pub metadata: AcpServerMetadata,
+    /// Overrides the daemon resource limits; a zero removes that limit.
+    pub resource_limits: ResourceLimits,
+    /// Overrides the daemon sandbox level.
+    pub sandbox_level: Option<SandboxLevel>,
+    /// Replace the daemon egress allow and deny lists; an empty list clears them.
+    pub egress_allow: Option<Vec<EgressRule>>,
+    pub egress_deny: Option<Vec<EgressRule>>,
+}
+
+/// Counts a POST as in flight until dropped, then records it as activity.
+struct InFlightGuard<'a>(&'a ProxyInstance);
+
+impl<'a> InFlightGuard<'a> {
+    fn new(instance: &'a ProxyInstance) -> Self {
+        instance.in_flight.fetch_add(1, Ordering::SeqCst);
+        instance.touch();
+        Self(instance)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Counts a session's `session/prompt` as running until dropped.
+struct PromptGuard<'a> {
+    instance: &'a ProxyInstance,
+    session_id: String,
+}
+
+impl<'a> PromptGuard<'a> {
+    fn new(instance: &'a ProxyInstance, session_id: &str) -> Self {
+        if let Ok(mut prompting) = instance.prompting.lock() {
+            *prompting.entry(session_id.to_string()).or_default() += 1;
+        }
+        Self {
+            instance,
+            session_id: session_id.to_string(),
+        }
+    }
+}
+
+impl Drop for PromptGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut prompting) = self.instance.prompting.lock() {
+            if let Some(count) = prompting.get_mut(&self.session_id) {
+                *count -= 1;
+                if *count == 0 {
+                    prompting.remove(&self.session_id);
+                }
+            }
+        }
+    }
 }
 
 pub type PinBoxSseStream =
@@ -71,6 +493,17 @@ impl AcpProxyRuntime {
             Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS),
         );
 
+        let warm_pool_targets = std::env::var(WARM_POOL_ENV)
+            .ok()
+            .map(|raw| parse_warm_pool_targets(&raw))
+            .unwrap_or_default();
+
+        let idle_timeout = std::env::var(IDLE_TIMEOUT_ENV)
+            .ok()
+            .and_then(|raw| raw.trim().parse::<u64>().ok())
+            .filter(|value| *value > 0)
+            .map(Duration::from_millis);
+
         Self {
             inner: Arc::new(AcpProxyRuntimeInner {
                 agent_manager,
@@ -79,31 +512,422 @@ impl AcpProxyRuntime {
                 instances: RwLock::new(HashMap::new()),
                 instance_locks: Mutex::new(HashMap::new()),
                 install_locks: Mutex::new(HashMap::new()),
+                warm_pool_targets,
+                warm_pool: Mutex::new(HashMap::new()),
+                warm_pool_fill_lock: Mutex::new(()),
+                firehose: Arc::default(),
+                idle_timeout,
+                idle_reaper_started: AtomicBool::new(false),
+                resource_limits: ResourceLimits::from_env(),
+                cgroups: OnceLock::new(),
+                // `run_server` refuses to start on an invalid level; anyone else gets the
+                // strictest one rather than none.
+                sandbox_level: SandboxLevel::from_env().unwrap_or_else(|error| {
+                    tracing::error!("{error}; confining agents at strict");
+                    SandboxLevel::Strict
+                }),
+                egress: EgressPolicy::from_env(),
+                audit: OnceLock::new(),
+                admission: Arc::new(Admission::from_env()),
+                webhooks: Webhooks::from_env(),
+                approval_router: ApprovalRouter::from_env().map(Arc::new),
+                event_sink: OnceLock::new(),
+                blobs: Arc::new(BlobStore::from_env()),
+                mcp_oauth: Arc::new(McpOAuth::from_env()),
+                instruction_templates: InstructionTemplates::from_env(),
+                agent_capabilities: AgentCapabilityProbe::from_env(),
+                archive: Arc::new(AcpArchive::from_env()),
+                fanouts: AcpFanouts::default(),
+                unparsed: Arc::default(),
+                max_unparsed_per_turn: unparsed::max_per_turn_from_env(),
+                rate_limits: Arc::default(),
+                turn_timeout: turn_timeout::default_from_env(),
+                #[cfg(feature = "test-utils")]
+                chaos: crate::chaos::configured(),
             }),
         }
     }
 
-    pub async fn list_instances(&self) -> Vec<AcpServerInstanceInfo> {
-        let mut infos = self
+    pub fn request_timeout(&self) -> Duration {
+        self.inner.request_timeout
+    }
+
+    pub(crate) fn blobs(&self) -> &BlobStore {
+        &self.inner.blobs
+    }
+
+    pub(crate) fn instruction_templates(&self) -> &InstructionTemplates {
+        &self.inner.instruction_templates
+    }
+
+    pub(crate) fn mcp_oauth(&self) -> &Arc<McpOAuth> {
+        &self.inner.mcp_oauth
+    }
+
+    pub(crate) fn agent_capabilities(&self) -> &AgentCapabilityProbe {
+        &self.inner.agent_capabilities
+    }
+
+    pub(crate) fn archive(&self) -> &Arc<AcpArchive> {
+        &self.inner.archive
+    }
+
+    pub(crate) fn fanouts(&self) -> &AcpFanouts {
+        &self.inner.fanouts
+    }
+
+    /// Turn timeout applied to sessions that don't set their own.
+    pub(crate) fn turn_timeout(&self) -> Option<Duration> {
+        self.inner.turn_timeout
+    }
+
+    /// Unparsed lines a turn may produce before `session/prompt` fails; `None` when unlimited.
+    pub(crate) fn max_unparsed_per_turn(&self) -> Option<u64> {
+        self.inner.max_unparsed_per_turn
+    }
+
+    /// Counts and the newest `limit` unparsed agent output lines, of `server_id` only when
+    /// given. Lines of servers that are gone stay in the daemon-wide counts and recent lines.
+    pub(crate) async fn unparsed_events(
+        &self,
+        server_id: Option<&str>,
+        limit: usize,
+    ) -> UnparsedEventsResponse {
+        let mut servers = self
             .inner
             .instances
             .read()
             .await
             .values()
-            .map(|instance| AcpServerInstanceInfo {
+            .filter(|instance| server_id.is_none_or(|id| instance.server_id == id))
+            .map(|instance| UnparsedServerCount {
                 server_id: instance.server_id.clone(),
-                agent: instance.agent,
-                created_at_ms: instance.created_at_ms,
+                agent: instance.agent.as_str().to_string(),
+                count: instance.unparsed.load(Ordering::Relaxed),
             })
+            .filter(|server| server_id.is_some() || server.count > 0)
+            .collect::<Vec<_>>();
+        servers.sort_by(|a, b| a.server_id.cmp(&b.server_id));
+        let agents = self.inner.unparsed.agents();
+        UnparsedEventsResponse {
+            total: agents.iter().map(|agent| agent.count).sum(),
+            agents,
+            servers,
+            events: self.inner.unparsed.recent(server_id, limit),
+        }
+    }
+
+    /// Counts and the newest `limit` provider rate limits, of `server_id` only when given.
+    /// Rate limits of servers that are gone stay in the daemon-wide counts and recent events.
+    pub(crate) async fn rate_limit_events(
+        &self,
+        server_id: Option<&str>,
+        limit: usize,
+    ) -> RateLimitEventsResponse {
+        let mut servers = self
+            .inner
+            .instances
+            .read()
+            .await
+            .values()
+            .filter(|instance| server_id.is_none_or(|id| instance.server_id == id))
+            .map(|instance| RateLimitServerCount {
+                server_id: instance.server_id.clone(),
+                agent: instance.agent.as_str().to_string(),
+                rate_limited: instance.rate_limits.rate_limited(),
+                overloaded: instance.rate_limits.overloaded(),
+            })
+            .filter(|server| server_id.is_some() || server.rate_limited + server.overloaded > 0)
+            .collect::<Vec<_>>();
+        servers.sort_by(|a, b| a.server_id.cmp(&b.server_id));
+        let agents = self.inner.rate_limits.agents();
+        RateLimitEventsResponse {
+            total: agents
+                .iter()
+                .map(|agent| agent.rate_limited + agent.overloaded)
+                .sum(),
+            agents,
+            servers,
+            events: self.inner.rate_limits.recent(server_id, limit),
+        }
+    }
+
+    /// Admitted servers and running turns against their caps, for `/v1/health`.
+    pub(crate) fn occupancy(&self) -> AcpOccupancy {
+        self.inner.admission.occupancy()
+    }
+
+    /// Limits applied to agent processes of servers that don't override them at bootstrap.
+    pub(crate) fn resource_limits(&self) -> ResourceLimits {
+        self.inner.resource_limits
+    }
+
+    /// Publish events of servers created from now on to `sink`.
+    pub(crate) fn set_event_sink(&self, sink: Arc<EventSink>) {
+        let _ = self.inner.event_sink.set(sink);
+    }
+
+    /// Record connections refused by egress policies of servers created from now on in `audit`.
+    pub(crate) fn set_audit_log(&self, audit: Arc<AuditLog>) {
+        let _ = self.inner.audit.set(audit);
+    }
+
+    pub(crate) fn event_sink_enabled(&self) -> bool {
+        self.inner.event_sink.get().is_some()
+    }
+
+    pub(crate) fn approval_routing_enabled(&self) -> bool {
+        self.inner.approval_router.is_some()
+    }
+
+    /// Idle timeout applied to ACP servers that don't override it at bootstrap.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.inner.idle_timeout
+    }
+
+    pub fn requires_preinstall(&self) -> bool {
+        self.inner.require_preinstall
+    }
+
+    pub fn warm_pool_configured(&self) -> bool {
+        !self.inner.warm_pool_targets.is_empty()
+    }
+
+    /// Events from every ACP server, numbered daemon-wide for `Last-Event-ID` resume.
+    pub(crate) fn firehose(&self) -> &Arc<Firehose> {
+        &self.inner.firehose
+    }
+
+    pub async fn list_instances(&self) -> Vec<AcpServerInstanceInfo> {
+        let mut infos = self
+            .inner
+            .instances
+            .read()
+            .await
+            .values()
+            .map(|instance| instance.info())
             .collect::<Vec<_>>();
         infos.sort_by(|left, right| left.server_id.cmp(&right.server_id));
         infos
     }
 
+    /// Agent of every running server and whether its agent process has exited.
+    pub(crate) async fn agent_processes(&self) -> Vec<(AgentId, bool)> {
+        self.inner
+            .instances
+            .read()
+            .await
+            .values()
+            .map(|instance| (instance.agent, instance.runtime.has_exited()))
+            .collect()
+    }
+
+    /// Set the server's title (cleared when `title` is empty) and merge `labels` into its
+    /// labels, removing those set to `None`.
+    pub async fn update_metadata(
+        &self,
+        server_id: &str,
+        title: Option<String>,
+        labels: BTreeMap<String, Option<String>>,
+    ) -> Result<AcpServerInstanceInfo, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        if let Ok(mut metadata) = instance.metadata.lock() {
+            let mut merged = metadata.labels.clone();
+            for (key, value) in labels {
+                match value {
+                    Some(value) => merged.insert(key, value),
+                    None => merged.remove(&key),
+                };
+            }
+            if merged.len() > MAX_SERVER_LABELS {
+                return Err(SandboxError::InvalidRequest {
+                    message: format!("a server can have at most {MAX_SERVER_LABELS} labels"),
+                });
+            }
+            metadata.labels = merged;
+            if let Some(title) = title {
+                metadata.title = Some(title).filter(|title| !title.is_empty());
+            }
+        }
+        Ok(instance.info())
+    }
+
+    pub async fn list_warm_pool(&self) -> Vec<AcpWarmPoolInfo> {
+        let pool = self.inner.warm_pool.lock().await;
+        let mut infos = self
+            .inner
+            .warm_pool_targets
+            .iter()
+            .map(|(agent, target)| AcpWarmPoolInfo {
+                agent: *agent,
+                idle: pool.get(agent).map(Vec::len).unwrap_or(0),
+                target: *target,
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by(|left, right| left.agent.as_str().cmp(right.agent.as_str()));
+        infos
+    }
+
+    /// Top up the warm pool so every configured agent has `target` idle agent
+    /// processes ready to be adopted by `POST /v1/acp/{server_id}?fromPool=true`.
+    pub async fn fill_warm_pool(&self) {
+        if self.inner.warm_pool_targets.is_empty() {
+            return;
+        }
+        if !self.inner.egress.is_empty() {
+            // Each agent gets an egress proxy that audits under its server id.
+            tracing::warn!("warm pool: disabled while an egress policy is configured");
+            return;
+        }
+        let _guard = self.inner.warm_pool_fill_lock.lock().await;
+
+        let mut targets = self
+            .inner
+            .warm_pool_targets
+            .iter()
+            .map(|(agent, target)| (*agent, *target))
+            .collect::<Vec<_>>();
+        targets.sort_by(|left, right| left.0.as_str().cmp(right.0.as_str()));
+
+        for (agent, target) in targets {
+            let missing = {
+                let mut pool = self.inner.warm_pool.lock().await;
+                let idle = pool.entry(agent).or_default();
+                idle.retain(|runtime| !runtime.has_exited());
+                target.saturating_sub(idle.len())
+            };
+
+            for _ in 0..missing {
+                match self
+                    .spawn_runtime(
+                        "<warm-pool>",
+                        agent,
+                        None,
+                        &self.inner.resource_limits,
+                        self.inner.sandbox_level,
+                        &EgressPolicy::default(),
+                    )
+                    .await
+                {
+                    Ok(runtime) => {
+                        self.inner
+                            .warm_pool
+                            .lock()
+                            .await
+                            .entry(agent)
+                            .or_default()
+                            .push(runtime);
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            agent = agent.as_str(),
+                            error = %err,
+                            "warm pool: failed to spawn idle agent process"
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Shut down idle warm-pool processes of `agent`, e.g. before its artifacts are removed.
+    pub async fn drain_warm_pool(&self, agent: AgentId) {
+        let idle = self
+            .inner
+            .warm_pool
+            .lock()
+            .await
+            .remove(&agent)
+            .unwrap_or_default();
+        for runtime in idle {
+            runtime.shutdown().await;
+        }
+    }
+
+    fn spawn_warm_pool_refill(&self) {
+        let runtime = self.clone();
+        tokio::spawn(async move {
+            runtime.fill_warm_pool().await;
+        });
+    }
+
+    /// Start the task that shuts down idle ACP servers, once per runtime. It holds only a weak
+    /// reference so it ends with the runtime.
+    fn spawn_idle_reaper(&self) {
+        if self.inner.idle_reaper_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let inner = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(IDLE_REAP_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(inner) = Weak::upgrade(&inner) else {
+                    break;
+                };
+                AcpProxyRuntime { inner }.reap_idle_instances().await;
+            }
+        });
+    }
+
+    async fn reap_idle_instances(&self) {
+        let now = now_ms();
+        let idle = {
+            let mut instances = self.inner.instances.write().await;
+            let ids = instances
+                .iter()
+                .filter(|(_, instance)| instance.is_idle(now))
+                .map(|(server_id, _)| server_id.clone())
+                .collect::<Vec<_>>();
+            ids.into_iter()
+                .filter_map(|server_id| instances.remove(&server_id))
+                .collect::<Vec<_>>()
+        };
+
+        for instance in idle {
+            let timeout_ms = instance.idle_timeout.unwrap_or_default().as_millis() as u64;
+            tracing::info!(
+                server_id = instance.server_id,
+                agent = instance.agent.as_str(),
+                idle_timeout_ms = timeout_ms,
+                "acp_proxy: shutting down idle server"
+            );
+            // Let open SSE streams see why the server went away before they close.
+            instance
+                .runtime
+                .seed_history([json!({
+                    "jsonrpc": "2.0",
+                    "method": "_sandboxagent/session/ended",
+                    "params": {
+                        "reason": "timeout",
+                        "terminated_by": "daemon",
+                        "message": format!("no activity for {timeout_ms} ms"),
+                    },
+                })])
+                .await;
+            self.archive_instance(&instance).await;
+            instance.remove_instruction_files();
+            instance.runtime.shutdown().await;
+        }
+    }
+
+    async fn take_from_warm_pool(&self, agent: AgentId) -> Option<Arc<AdapterRuntime>> {
+        let mut pool = self.inner.warm_pool.lock().await;
+        let idle = pool.get_mut(&agent)?;
+        while let Some(runtime) = idle.pop() {
+            if !runtime.has_exited() {
+                return Some(runtime);
+            }
+        }
+        None
+    }
+
     pub async fn post(
         &self,
         server_id: &str,
-        bootstrap_agent: Option<AgentId>,
+        bootstrap: AcpBootstrap,
         payload: Value,
     ) -> Result<ProxyPostOutcome, SandboxError> {
         let method: String = payload
@@ -114,80 +938,1035 @@ impl AcpProxyRuntime {
         let id: String = payload.get("id").map(|v| v.to_string()).unwrap_or_default();
 
         tracing::info!(
-            server_id = server_id,
-            method = method,
-            id = %id,
-            bootstrap_agent = ?bootstrap_agent,
-            "acp_proxy: POST received"
+            server_id = server_id,
+            method = method,
+            id = %id,
+            bootstrap_agent = ?bootstrap.agent,
+            from_pool = bootstrap.from_pool,
+            "acp_proxy: POST received"
+        );
+
+        let start = std::time::Instant::now();
+        let snapshot_turns = bootstrap.snapshot_turns;
+        let instance = self.get_or_create_instance(server_id, bootstrap).await?;
+        let _in_flight = InFlightGuard::new(&instance);
+        let _turn = (method == "session/prompt")
+            .then(|| self.inner.admission.admit_turn(instance.agent))
+            .transpose()?;
+        let instance_elapsed = start.elapsed();
+
+        tracing::debug!(
+            server_id = server_id,
+            agent = instance.agent.as_str(),
+            instance_ms = instance_elapsed.as_millis() as u64,
+            "acp_proxy: instance resolved"
+        );
+
+        let mut payload = with_default_session_cwd(payload, instance.directory.as_deref());
+        let variant = take_session_variant(instance.agent, &mut payload)?;
+        let turn_timeout = turn_timeout::take_turn_timeout(&mut payload)?;
+        let retry = turn_retry::take_retry_policy(&mut payload)?;
+        let question_policy = question_policy::take_question_policy(&mut payload)?;
+        let payload = apply_session_config(instance.agent, payload)?;
+        let payload = apply_session_streaming(instance.agent, payload)?;
+        let mut payload = apply_session_system_prompt(instance.agent, payload)?;
+        let instruction_requests = instruction_files::take_instruction_files(&mut payload)?;
+        let mut payload = write_inline_attachments(payload, instance.workspace_root().as_deref())?;
+        let overrides = match instance.selections.lock() {
+            Ok(selections) => selections.take_override(instance.agent, &mut payload)?,
+            Err(_) => None,
+        };
+        let output = take_output_schema(&mut payload)?.map(|schema| {
+            let session_id = payload
+                .pointer("/params/sessionId")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            (session_id, schema, instance.runtime.last_event_id())
+        });
+        let observed = matches!(
+            method.as_str(),
+            "session/new" | "session/load" | "session/set_model" | "session/set_mode"
+        )
+        .then(|| payload.clone());
+        // A client response answers a pending permission or question request.
+        let answered = payload
+            .get("method")
+            .is_none()
+            .then(|| payload.get("id").cloned())
+            .flatten();
+        let answer = answered.as_ref().map(|_| payload.clone());
+        if let Some(request_id) = &answered {
+            let routed = instance
+                .approvals
+                .lock()
+                .ok()
+                .and_then(|approvals| approvals.get(request_id))
+                .is_some_and(|approval| approval.routed());
+            if routed {
+                return Err(SandboxError::Conflict {
+                    message: format!(
+                        "permission request {request_id} is held for the external approver"
+                    ),
+                });
+            }
+        }
+        if method == "session/prompt" {
+            instance.record_prompt(&payload);
+            #[cfg(feature = "test-utils")]
+            if let Some(chaos) = &self.inner.chaos {
+                chaos.on_prompt(&instance.runtime);
+            }
+        }
+        if let Some(overrides) = &overrides {
+            if let Err(error) =
+                switch_session(&instance, &overrides.apply, &overrides.restore).await
+            {
+                return Ok(ProxyPostOutcome::Response(json!({
+                    "jsonrpc": "2.0",
+                    "id": payload.get("id"),
+                    "error": error,
+                })));
+            }
+        }
+        let unparsed_after = (method == "session/prompt")
+            .then_some(self.inner.max_unparsed_per_turn)
+            .flatten()
+            .map(|limit| (limit, instance.runtime.last_event_id()));
+        let turn = if method == "session/prompt" && (snapshot_turns || instance.snapshot_turns) {
+            start_turn_snapshot(&instance, &payload).await
+        } else {
+            None
+        };
+        // Added last, so tokens never reach recorded or observed copies of the request.
+        if matches!(method.as_str(), "session/new" | "session/load") {
+            let failures = self.inner.mcp_oauth.inject(&mut payload).await;
+            report_mcp_auth_failures(&instance, &payload, failures).await;
+        }
+        // Written last, so a rejected request never leaves files behind.
+        let written = instruction_files::write_instruction_files(
+            &self.inner.instruction_templates,
+            instance.agent,
+            server_id,
+            &payload,
+            &instruction_requests,
+        )?;
+        let turn_limit = match (method.as_str(), instance.turn_timeouts.lock()) {
+            ("session/prompt", Ok(timeouts)) => {
+                let session_id = payload
+                    .pointer("/params/sessionId")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                timeouts.resolve(session_id, turn_timeout, self.inner.turn_timeout)
+            }
+            _ => None,
+        };
+        let outcome = match retry {
+            Some(policy) => {
+                post_with_retries(
+                    &instance,
+                    &self.inner.rate_limits,
+                    payload,
+                    turn_limit,
+                    policy,
+                )
+                .await
+            }
+            None => post_once(&instance, &self.inner.rate_limits, payload, turn_limit).await,
+        };
+        let outcome = match outcome {
+            Ok(PostOutcome::Response(value)) if !written.is_empty() => Ok(PostOutcome::Response(
+                instance.keep_instruction_files(value, written),
+            )),
+            outcome => {
+                instruction_files::remove_instruction_files(&written);
+                outcome
+            }
+        };
+        if let Some(overrides) = &overrides {
+            if let Err(error) = switch_session(&instance, &overrides.restore, &[]).await {
+                tracing::warn!(
+                    server_id = server_id,
+                    error = %error,
+                    "acp_proxy: per-turn override not switched back"
+                );
+            }
+        }
+        match outcome {
+            Ok(PostOutcome::Response(value)) => {
+                if let Some(turn) = turn {
+                    finish_turn_snapshot(&instance, turn).await;
+                }
+                let total_ms = start.elapsed().as_millis() as u64;
+                tracing::info!(
+                    server_id = server_id,
+                    method = method,
+                    id = %id,
+                    total_ms = total_ms,
+                    "acp_proxy: POST → response"
+                );
+                if method == "initialize" {
+                    self.inner
+                        .agent_capabilities
+                        .record_initialize(instance.agent, &value);
+                }
+                let mut value = annotate_agent_error(instance.agent, value);
+                if let Some(variant) = variant.filter(|_| value.get("result").is_some()) {
+                    value = apply_variant(&instance, value, &variant).await;
+                }
+                if let Some((limit, after)) = unparsed_after {
+                    let count = instance
+                        .runtime
+                        .buffered_events(Some(after))
+                        .await
+                        .iter()
+                        .filter(|(_, event)| unparsed::is_unparsed(event))
+                        .count() as u64;
+                    value = unparsed::check_turn(value, count, limit);
+                }
+                if let Some((session_id, schema, after)) = &output {
+                    if value.get("result").is_some() {
+                        value =
+                            attach_structured_output(&instance, value, session_id, schema, *after)
+                                .await;
+                    }
+                }
+                if let (Some(request), Ok(mut selections)) = (&observed, instance.selections.lock())
+                {
+                    selections.observe(request, &value);
+                }
+                if let (Some(timeout), "session/new", Ok(mut timeouts)) =
+                    (turn_timeout, method.as_str(), instance.turn_timeouts.lock())
+                {
+                    timeouts.observe(timeout, &value);
+                }
+                if let (Some(policy), Ok(mut policies)) =
+                    (question_policy, instance.question_policies.lock())
+                {
+                    policies.observe(policy, &value);
+                }
+                if let Some(request) = &observed {
+                    let events = match instance.plans.lock() {
+                        Ok(mut plans) => plans.observe(request, &value),
+                        Err(_) => Vec::new(),
+                    };
+                    instance.runtime.seed_history(events).await;
+                }
+                Ok(ProxyPostOutcome::Response(value))
+            }
+            Ok(PostOutcome::Accepted) => {
+                if let (Some(request_id), Some(answer)) = (&answered, &answer) {
+                    let options = match instance.approvals.lock() {
+                        Ok(mut approvals) => {
+                            let options = approvals
+                                .get(request_id)
+                                .and_then(|approval| approval.params.get("options").cloned());
+                            approvals.resolve(request_id);
+                            options.unwrap_or_default()
+                        }
+                        Err(_) => Value::Null,
+                    };
+                    let decided = match instance.plans.lock() {
+                        Ok(mut plans) => plans.answer(answer, &options),
+                        Err(_) => None,
+                    };
+                    instance.runtime.seed_history(decided).await;
+                }
+                tracing::info!(
+                    server_id = server_id,
+                    method = method,
+                    "acp_proxy: POST → accepted"
+                );
+                Ok(ProxyPostOutcome::Accepted)
+            }
+            Err(err) => {
+                let total_ms = start.elapsed().as_millis() as u64;
+                tracing::error!(
+                    server_id = server_id,
+                    method = method,
+                    id = %id,
+                    total_ms = total_ms,
+                    error = %err,
+                    "acp_proxy: POST → error"
+                );
+                Err(map_adapter_error(err))
+            }
+        }
+    }
+
+    pub async fn sse(
+        &self,
+        server_id: &str,
+        last_event_id: Option<u64>,
+        filter: EventFilter,
+    ) -> Result<PinBoxSseStream, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let stream = instance
+            .runtime
+            .clone()
+            .filtered_sse_stream(last_event_id, move |message| filter.matches(message))
+            .await;
+        Ok(Box::pin(stream))
+    }
+
+    /// The server's buffered events followed by live ones, as (sequence, message).
+    pub async fn sequenced_events(
+        &self,
+        server_id: &str,
+    ) -> Result<(AgentId, impl Stream<Item = (u64, Value)> + Send + 'static), SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let events = instance.runtime.clone().sequenced_stream(None).await;
+        Ok((instance.agent, events))
+    }
+
+    /// The server's buffered events after `after` followed by live ones, ending with its
+    /// `_sandboxagent/session/ended` notification.
+    pub async fn event_log(
+        &self,
+        server_id: &str,
+        after: Option<u64>,
+    ) -> Result<impl Stream<Item = (u64, Value)> + Send + 'static, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let events = instance.runtime.clone().sequenced_stream(after).await;
+        Ok(events.scan(false, |ended, (sequence, message)| {
+            let event = (!*ended).then(|| {
+                *ended = message.get("method").and_then(Value::as_str)
+                    == Some("_sandboxagent/session/ended");
+                (sequence, message)
+            });
+            async move { event }
+        }))
+    }
+
+    /// Working directory the server was bootstrapped with, if any.
+    pub async fn directory(&self, server_id: &str) -> Result<Option<PathBuf>, SandboxError> {
+        Ok(self.get_instance(server_id).await?.directory.clone())
+    }
+
+    /// Token usage and reported cost accumulated from the server's events.
+    pub async fn usage(&self, server_id: &str) -> Result<AcpUsage, SandboxError> {
+        Ok(self.get_instance(server_id).await?.usage())
+    }
+
+    /// Have the agent compact the context of `session_id` with its native command, announcing
+    /// the turn on the server's streams; see `crate::compaction`.
+    pub(crate) async fn compact(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<AcpCompactResponse, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let Some(command) = compaction::command(instance.agent) else {
+            return Err(SandboxError::InvalidRequest {
+                message: format!(
+                    "agent '{}' has no native compaction",
+                    instance.agent.as_str()
+                ),
+            });
+        };
+        let before = instance.usage().context(session_id);
+        instance
+            .runtime
+            .seed_history([compaction::event(session_id, "started", before, None)])
+            .await;
+        let outcome = self
+            .post(
+                server_id,
+                AcpBootstrap::default(),
+                compaction::prompt(session_id, command),
+            )
+            .await;
+        let after = instance.usage().context(session_id);
+        let failure = match &outcome {
+            Ok(ProxyPostOutcome::Response(response)) => response.get("error").cloned(),
+            Ok(ProxyPostOutcome::Accepted) => None,
+            Err(error) => Some(json!({ "message": error.to_string() })),
+        };
+        let phase = if failure.is_some() {
+            "failed"
+        } else {
+            "completed"
+        };
+        instance
+            .runtime
+            .seed_history([compaction::event(
+                session_id,
+                phase,
+                after,
+                failure.as_ref(),
+            )])
+            .await;
+        let response = match outcome? {
+            ProxyPostOutcome::Response(response) => response,
+            ProxyPostOutcome::Accepted => Value::Null,
+        };
+        if let Some(error) = failure {
+            return Err(SandboxError::StreamError {
+                message: format!("the agent did not compact session '{session_id}': {error}"),
+            });
+        }
+        let info = |context: AcpContextWindow| AcpContextWindowInfo {
+            session_id: session_id.to_string(),
+            used: context.used,
+            size: context.size,
+        };
+        Ok(AcpCompactResponse {
+            session_id: session_id.to_string(),
+            stop_reason: response
+                .pointer("/result/stopReason")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            context_before: before.map(info),
+            context_after: after.map(info),
+        })
+    }
+
+    /// The latest todo list of `session_id`; `None` when its agent has sent none.
+    pub async fn task_list(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<Option<TaskList>, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let list = instance
+            .task_lists
+            .lock()
+            .ok()
+            .and_then(|task_lists| task_lists.get(session_id));
+        Ok(list)
+    }
+
+    /// Transcript items of `session_id`, with tool calls linked to their sub-agents.
+    pub async fn session_items(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<Vec<AcpItem>, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let items = instance
+            .items
+            .lock()
+            .map(|items| items.list(session_id))
+            .unwrap_or_default();
+        Ok(link_parents(&instance, session_id, items))
+    }
+
+    pub async fn session_item(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        item_id: &str,
+    ) -> Result<AcpItem, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let item = instance
+            .items
+            .lock()
+            .ok()
+            .and_then(|items| items.get(session_id, item_id));
+        let item = item.ok_or_else(|| SandboxError::NotFound {
+            path: format!("/v1/acp/{server_id}/sessions/{session_id}/items/{item_id}"),
+        })?;
+        Ok(link_parents(&instance, session_id, vec![item]).remove(0))
+    }
+
+    /// Turns of `session_id` started by prompts sent through the daemon, oldest first.
+    pub async fn session_turns(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<Vec<AcpSessionTurn>, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let turns = instance
+            .items
+            .lock()
+            .map(|items| items.turns(session_id))
+            .unwrap_or_default();
+        Ok(turns
+            .into_iter()
+            .map(|turn| link_turn_parents(&instance, session_id, turn))
+            .collect())
+    }
+
+    pub async fn session_turn(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        turn_id: &str,
+    ) -> Result<AcpSessionTurn, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let turn = instance
+            .items
+            .lock()
+            .ok()
+            .and_then(|items| items.turn(session_id, turn_id));
+        let turn = turn.ok_or_else(|| SandboxError::NotFound {
+            path: format!("/v1/acp/{server_id}/sessions/{session_id}/turns/{turn_id}"),
+        })?;
+        Ok(link_turn_parents(&instance, session_id, turn))
+    }
+
+    /// Findings the agent reported in `session_id`, for the SARIF and JUnit exports.
+    pub async fn session_findings(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<Vec<Finding>, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let findings = instance
+            .items
+            .lock()
+            .map(|items| findings::collect(&items.list(session_id), &items.outputs(session_id)))
+            .unwrap_or_default();
+        Ok(findings)
+    }
+
+    /// The tool call `item_id` of `session_id` and the tool calls its sub-agent made.
+    pub async fn item_children(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        item_id: &str,
+    ) -> Result<(AcpChildItem, Vec<AcpChildItem>), SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let children = instance
+            .subagents
+            .lock()
+            .ok()
+            .and_then(|subagents| subagents.children(session_id, item_id));
+        children.ok_or_else(|| SandboxError::NotFound {
+            path: format!("/v1/acp/{server_id}/sessions/{session_id}/items/{item_id}"),
+        })
+    }
+
+    /// Turns recorded while turn snapshots were enabled, oldest first.
+    pub async fn turns(&self, server_id: &str) -> Result<Vec<TurnRecord>, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let turns = instance.turns.lock().map(|turns| turns.list());
+        Ok(turns.unwrap_or_default())
+    }
+
+    /// Directory the server works in: its `directory`, or the daemon's working directory.
+    pub async fn workspace_root(&self, server_id: &str) -> Result<PathBuf, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        instance
+            .workspace_root()
+            .ok_or_else(|| SandboxError::InvalidRequest {
+                message: format!("ACP server {server_id} has no working directory"),
+            })
+    }
+
+    pub async fn turn(&self, server_id: &str, turn_id: u64) -> Result<TurnRecord, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let turn = instance
+            .turns
+            .lock()
+            .ok()
+            .and_then(|turns| turns.get(turn_id));
+        turn.ok_or_else(|| SandboxError::NotFound {
+            path: format!("/v1/acp/{server_id}/turns/{turn_id}"),
+        })
+    }
+
+    /// Permission and question requests still waiting on a client, from every server.
+    pub async fn pending_approvals(&self) -> Vec<(String, AgentId, PendingApproval)> {
+        let instances = self
+            .inner
+            .instances
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut pending = Vec::new();
+        for instance in instances {
+            let Ok(approvals) = instance.approvals.lock() else {
+                continue;
+            };
+            pending.extend(
+                approvals
+                    .list()
+                    .into_iter()
+                    .map(|approval| (instance.server_id.clone(), instance.agent, approval)),
+            );
+        }
+        pending.sort_by_key(|(_, _, approval)| approval.requested_at_ms);
+        pending
+    }
+
+    /// The routed request a `/v1/approvals/callback` token names, released so its answer can
+    /// be sent. Only a request still held under the token's nonce is released; hand the token
+    /// back to [`AcpProxyRuntime::restore_routed_approval`] if the answer cannot be sent.
+    pub async fn release_routed_approval(
+        &self,
+        token: &str,
+    ) -> Result<(RouteToken, PendingApproval), SandboxError> {
+        let router =
+            self.inner
+                .approval_router
+                .as_ref()
+                .ok_or_else(|| SandboxError::InvalidRequest {
+                    message: "approval routing is not configured".to_string(),
+                })?;
+        let token = router.verify(token)?;
+        let instance = self.get_instance(&token.server_id).await?;
+        let approval = instance
+            .approvals
+            .lock()
+            .ok()
+            .and_then(|mut approvals| approvals.release_route(&token.request_id, &token.nonce));
+        let approval = approval.ok_or_else(|| SandboxError::NotFound {
+            path: format!("/v1/approvals/{}/{}", token.server_id, token.request_id),
+        })?;
+        Ok((token, approval))
+    }
+
+    /// Hold a request released by [`AcpProxyRuntime::release_routed_approval`] again, so its
+    /// token still answers it.
+    pub async fn restore_routed_approval(&self, token: &RouteToken) {
+        if let Ok(instance) = self.get_instance(&token.server_id).await {
+            if let Ok(mut approvals) = instance.approvals.lock() {
+                approvals.route(&token.request_id, &token.nonce);
+            }
+        }
+    }
+
+    pub async fn pending_approval(
+        &self,
+        server_id: &str,
+        request_id: &Value,
+    ) -> Result<PendingApproval, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let approval = instance
+            .approvals
+            .lock()
+            .ok()
+            .and_then(|approvals| approvals.get(request_id));
+        approval.ok_or_else(|| SandboxError::NotFound {
+            path: format!("/v1/approvals/{server_id}/{request_id}"),
+        })
+    }
+
+    /// What a plan decision on `session_id` answers: the agent's review request, or the
+    /// plan mode the session is in.
+    pub async fn plan_state(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<PlanState, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        let state = instance
+            .plans
+            .lock()
+            .ok()
+            .and_then(|plans| plans.state(session_id));
+        state.ok_or_else(|| SandboxError::Conflict {
+            message: format!("session '{session_id}' has no plan to approve or reject"),
+        })
+    }
+
+    /// Emit `_sandboxagent/plan/rejected` for a plan the agent is not waiting on; the session
+    /// stays in plan mode.
+    pub async fn reject_plan(&self, server_id: &str, session_id: &str) -> Result<(), SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        instance
+            .runtime
+            .seed_history([plans::notification(
+                "rejected",
+                json!({ "sessionId": session_id }),
+            )])
+            .await;
+        Ok(())
+    }
+
+    pub async fn buffered_events(
+        &self,
+        server_id: &str,
+        last_event_id: Option<u64>,
+    ) -> Result<Vec<(u64, Value)>, SandboxError> {
+        let instance = self.get_instance(server_id).await?;
+        Ok(instance.runtime.buffered_events(last_event_id).await)
+    }
+
+    /// The buffered events of `server_id` after `after` as a stream read from the ring page by
+    /// page, with the sequence of the first of them.
+    pub async fn buffered_event_stream(
+        &self,
+        server_id: &str,
+        after: Option<u64>,
+    ) -> Result<
+        (
+            Option<u64>,
+            impl Stream<Item = (u64, Value)> + Send + 'static,
+        ),
+        SandboxError,
+    > {
+        let instance = self.get_instance(server_id).await?;
+        let first = instance.runtime.first_buffered_after(after).await;
+        Ok((first, instance.runtime.clone().buffered_stream(after)))
+    }
+
+    /// Start `target_id` with the same agent and directory as `source_id`, seeded with the
+    /// source's buffered events up to `up_to` (all of them when unset). The agent process
+    /// starts fresh; clients open a new ACP session on the fork using that history as context.
+    pub async fn fork(
+        &self,
+        source_id: &str,
+        target_id: &str,
+        up_to: Option<u64>,
+    ) -> Result<AcpForkInfo, SandboxError> {
+        let source = self.get_instance(source_id).await?;
+        let last_event_id = source.runtime.last_event_id();
+        if up_to.is_some_and(|up_to| up_to > last_event_id) {
+            return Err(SandboxError::InvalidRequest {
+                message: format!(
+                    "upToSequence exceeds the last event of server '{source_id}' ({last_event_id})"
+                ),
+            });
+        }
+        if self.inner.instances.read().await.contains_key(target_id) {
+            return Err(SandboxError::Conflict {
+                message: format!("server '{target_id}' already exists"),
+            });
+        }
+
+        let history = source
+            .runtime
+            .buffered_events(None)
+            .await
+            .into_iter()
+            .filter(|(sequence, _)| up_to.is_none_or(|up_to| *sequence <= up_to))
+            .map(|(_, payload)| payload)
+            .collect::<Vec<_>>();
+        let history_events = history.len();
+
+        let created = self
+            .create_instance(
+                target_id,
+                source.agent,
+                source.directory.clone(),
+                InstanceOptions {
+                    snapshot_turns: source.snapshot_turns,
+                    idle_timeout: source.idle_timeout,
+                    webhook_url: source.webhook_url.clone(),
+                    resource_limits: source.resource_limits,
+                    sandbox_level: source.sandbox_level,
+                    egress: source.egress.clone(),
+                },
+            )
+            .await?;
+        if let Ok(mut metadata) = created.metadata.lock() {
+            *metadata = source.metadata();
+        }
+        let seeded = created.runtime.seed_history(history).await;
+
+        {
+            let mut instances = self.inner.instances.write().await;
+            if instances.contains_key(target_id) {
+                drop(instances);
+                created.runtime.shutdown().await;
+                return Err(SandboxError::Conflict {
+                    message: format!("server '{target_id}' already exists"),
+                });
+            }
+            instances.insert(target_id.to_string(), created.clone());
+        }
+        self.spawn_firehose_forwarder(&created, Some(seeded)).await;
+
+        tracing::info!(
+            server_id = target_id,
+            forked_from = source_id,
+            history_events = history_events,
+            "acp_proxy: forked server"
+        );
+
+        Ok(AcpForkInfo {
+            server_id: target_id.to_string(),
+            agent: source.agent,
+            forked_from: source_id.to_string(),
+            history_events,
+            last_event_id: seeded,
+        })
+    }
+
+    /// Start `target_id` and send it the prompts recorded on `source_id`, which may be running
+    /// or archived, one at a time. Each source session is replayed into a new session, using
+    /// `agent` and `model` when given. Progress is reported on the target's event stream as
+    /// `_sandboxagent/replay/*` notifications.
+    pub async fn replay(
+        &self,
+        source_id: &str,
+        target_id: &str,
+        agent: Option<AgentId>,
+        model: Option<String>,
+    ) -> Result<AcpReplayInfo, SandboxError> {
+        let (source_agent, directory, metadata, prompts) =
+            match self.inner.instances.read().await.get(source_id).cloned() {
+                Some(source) => (
+                    source.agent,
+                    source.directory.clone(),
+                    source.metadata(),
+                    source.prompts(),
+                ),
+                None => self.archived_replay_source(source_id).await?,
+            };
+        if prompts.is_empty() {
+            return Err(SandboxError::InvalidRequest {
+                message: format!("server '{source_id}' has no recorded prompts to replay"),
+            });
+        }
+        if self.inner.instances.read().await.contains_key(target_id) {
+            return Err(SandboxError::Conflict {
+                message: format!("server '{target_id}' already exists"),
+            });
+        }
+
+        let agent = agent.unwrap_or(source_agent);
+        let target = self
+            .get_or_create_instance(
+                target_id,
+                AcpBootstrap {
+                    agent: Some(agent),
+                    directory,
+                    metadata,
+                    ..AcpBootstrap::default()
+                },
+            )
+            .await?;
+        let info = AcpReplayInfo {
+            server_id: target_id.to_string(),
+            agent,
+            replayed_from: source_id.to_string(),
+            prompts: prompts.len(),
+        };
+        tracing::info!(
+            server_id = target_id,
+            replayed_from = source_id,
+            agent = agent.as_str(),
+            prompts = prompts.len(),
+            "acp_proxy: replaying prompts"
         );
 
-        let start = std::time::Instant::now();
-        let instance = self
-            .get_or_create_instance(server_id, bootstrap_agent)
-            .await?;
-        let instance_elapsed = start.elapsed();
+        let runtime = self.clone();
+        let source_id = source_id.to_string();
+        tokio::spawn(async move {
+            let outcome = runtime.run_replay(&target, prompts, model).await;
+            let notification = match outcome {
+                Ok(total) => json!({
+                    "jsonrpc": "2.0",
+                    "method": "_sandboxagent/replay/completed",
+                    "params": { "replayedFrom": source_id, "total": total },
+                }),
+                Err(message) => json!({
+                    "jsonrpc": "2.0",
+                    "method": "_sandboxagent/replay/failed",
+                    "params": { "replayedFrom": source_id, "message": message },
+                }),
+            };
+            target.runtime.seed_history([notification]).await;
+        });
+        Ok(info)
+    }
 
-        tracing::debug!(
-            server_id = server_id,
-            agent = instance.agent.as_str(),
-            instance_ms = instance_elapsed.as_millis() as u64,
-            "acp_proxy: instance resolved"
-        );
+    async fn archived_replay_source(
+        &self,
+        source_id: &str,
+    ) -> Result<(AgentId, Option<PathBuf>, AcpServerMetadata, Vec<Value>), SandboxError> {
+        let archive = self.inner.archive.clone();
+        let server_id = source_id.to_string();
+        let (server, prompts) = tokio::task::spawn_blocking(move || {
+            archive
+                .prompts(&server_id)
+                .map(|prompts| (archive.server(&server_id), prompts))
+        })
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })??;
+        let server = server.ok_or_else(|| SandboxError::SessionNotFound {
+            session_id: source_id.to_string(),
+        })?;
+        let agent =
+            AgentId::parse(&server.agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+                agent: server.agent.clone(),
+            })?;
+        let metadata = AcpServerMetadata {
+            title: server.title,
+            labels: server.labels,
+        };
+        Ok((
+            agent,
+            server.directory.map(PathBuf::from),
+            metadata,
+            prompts,
+        ))
+    }
 
-        match instance.runtime.post(payload).await {
-            Ok(PostOutcome::Response(value)) => {
-                let total_ms = start.elapsed().as_millis() as u64;
-                tracing::info!(
-                    server_id = server_id,
-                    method = method,
-                    id = %id,
-                    total_ms = total_ms,
-                    "acp_proxy: POST → response"
-                );
-                let value = annotate_agent_error(instance.agent, value);
-                Ok(ProxyPostOutcome::Response(value))
-            }
-            Ok(PostOutcome::Accepted) => {
-                tracing::info!(
-                    server_id = server_id,
-                    method = method,
-                    "acp_proxy: POST → accepted"
-                );
-                Ok(ProxyPostOutcome::Accepted)
-            }
-            Err(err) => {
-                let total_ms = start.elapsed().as_millis() as u64;
-                tracing::error!(
-                    server_id = server_id,
-                    method = method,
-                    id = %id,
-                    total_ms = total_ms,
-                    error = %err,
-                    "acp_proxy: POST → error"
-                );
-                Err(map_adapter_error(err))
-            }
+    /// Send `prompts` to `target`, opening a new session for each source session. Returns the
+    /// number of prompts sent, or why the replay stopped.
+    async fn run_replay(
+        &self,
+        target: &ProxyInstance,
+        prompts: Vec<Value>,
+        model: Option<String>,
+    ) -> Result<usize, String> {
+        let total = prompts.len();
+        let mut next_id = 0u64;
+        let mut request = |method: &str, params: Value| {
+            next_id += 1;
+            json!({
+                "jsonrpc": "2.0",
+                "id": format!("sandboxagent-replay-{next_id}"),
+                "method": method,
+                "params": params,
+            })
+        };
+        self.client_request(
+            &target.server_id,
+            AcpBootstrap::default(),
+            request(
+                "initialize",
+                json!({ "protocolVersion": 1, "clientCapabilities": {} }),
+            ),
+        )
+        .await?;
+
+        let cwd = target
+            .workspace_root()
+            .map(|root| root.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut sessions = HashMap::<String, Value>::new();
+        for (index, prompt) in prompts.into_iter().enumerate() {
+            let source_session = prompt["sessionId"].to_string();
+            let session_id = match sessions.get(&source_session) {
+                Some(session_id) => session_id.clone(),
+                None => {
+                    let created = self
+                        .client_request(
+                            &target.server_id,
+                            AcpBootstrap::default(),
+                            request("session/new", json!({ "cwd": cwd, "mcpServers": [] })),
+                        )
+                        .await?;
+                    let session_id = created
+                        .pointer("/result/sessionId")
+                        .cloned()
+                        .ok_or_else(|| "session/new returned no sessionId".to_string())?;
+                    if let Some(model) = &model {
+                        self.client_request(
+                            &target.server_id,
+                            AcpBootstrap::default(),
+                            request(
+                                "session/set_model",
+                                json!({ "sessionId": session_id, "modelId": model }),
+                            ),
+                        )
+                        .await?;
+                    }
+                    sessions.insert(source_session, session_id.clone());
+                    session_id
+                }
+            };
+            let response = self
+                .client_request(
+                    &target.server_id,
+                    AcpBootstrap::default(),
+                    request(
+                        "session/prompt",
+                        json!({ "sessionId": session_id, "prompt": prompt["prompt"] }),
+                    ),
+                )
+                .await?;
+            target
+                .runtime
+                .seed_history([json!({
+                    "jsonrpc": "2.0",
+                    "method": "_sandboxagent/replay/progress",
+                    "params": {
+                        "index": index + 1,
+                        "total": total,
+                        "sessionId": session_id,
+                        "stopReason": response.pointer("/result/stopReason"),
+                    },
+                })])
+                .await;
         }
+        Ok(total)
     }
 
-    pub async fn sse(
+    /// Send a JSON-RPC request to `server_id` on behalf of the daemon. Failures and JSON-RPC
+    /// errors are returned as a message naming the method.
+    pub(crate) async fn client_request(
         &self,
         server_id: &str,
-        last_event_id: Option<u64>,
-    ) -> Result<PinBoxSseStream, SandboxError> {
-        let instance = self.get_instance(server_id).await?;
-        let stream = instance.runtime.clone().sse_stream(last_event_id).await;
-        Ok(Box::pin(stream))
+        bootstrap: AcpBootstrap,
+        payload: Value,
+    ) -> Result<Value, String> {
+        let method = payload["method"].as_str().unwrap_or_default().to_string();
+        match self.post(server_id, bootstrap, payload).await {
+            Ok(ProxyPostOutcome::Response(response)) => match response.get("error") {
+                Some(error) => Err(format!("{method} failed: {error}")),
+                None => Ok(response),
+            },
+            Ok(ProxyPostOutcome::Accepted) => Ok(Value::Null),
+            Err(err) => Err(format!("{method} failed: {err}")),
+        }
     }
 
     pub async fn delete(&self, server_id: &str) -> Result<(), SandboxError> {
         let removed = self.inner.instances.write().await.remove(server_id);
         if let Some(instance) = removed {
+            instance
+                .runtime
+                .seed_history([json!({
+                    "jsonrpc": "2.0",
+                    "method": "_sandboxagent/session/ended",
+                    "params": {
+                        "reason": "terminated",
+                        "terminated_by": "daemon",
+                        "message": "server deleted",
+                    },
+                })])
+                .await;
+            self.archive_instance(&instance).await;
+            instance.remove_instruction_files();
             instance.runtime.shutdown().await;
+        } else if self.inner.archive.is_enabled() {
+            // Deleting a server that already ended discards its archive.
+            let archive = self.inner.archive.clone();
+            let server_id = server_id.to_string();
+            tokio::task::spawn_blocking(move || archive.remove(&server_id))
+                .await
+                .map_err(|err| SandboxError::StreamError {
+                    message: err.to_string(),
+                })??;
         }
         Ok(())
     }
 
+    /// Write an ending server's info and event history to the archive, when enabled.
+    async fn archive_instance(&self, instance: &ProxyInstance) {
+        if !self.inner.archive.is_enabled() {
+            return;
+        }
+        let mut info = crate::router::acp_server_info(instance.info());
+        info.ended_at_ms = Some(now_ms());
+        let events = instance.runtime.buffered_events(None).await;
+        let prompts = instance.prompts();
+        let archive = self.inner.archive.clone();
+        let stored =
+            tokio::task::spawn_blocking(move || archive.store(info, events, prompts)).await;
+        if let Err(err) = stored
+            .map_err(|err| err.to_string())
+            .and_then(|stored| stored.map_err(|err| err.to_string()))
+        {
+            tracing::warn!(
+                server_id = instance.server_id,
+                error = %err,
+                "acp_proxy: failed to archive server"
+            );
+        }
+    }
+
     pub async fn shutdown_all(&self) {
         let instances = {
             let mut guard = self.inner.instances.write().await;
@@ -198,8 +1977,21 @@ impl AcpProxyRuntime {
         };
 
         for instance in instances {
+            self.archive_instance(&instance).await;
+            instance.remove_instruction_files();
             instance.runtime.shutdown().await;
         }
+
+        let idle = {
+            let mut pool = self.inner.warm_pool.lock().await;
+            pool.drain()
+                .flat_map(|(_, runtimes)| runtimes)
+                .collect::<Vec<_>>()
+        };
+
+        for runtime in idle {
+            runtime.shutdown().await;
+        }
     }
 
     async fn get_instance(&self, server_id: &str) -> Result<Arc<ProxyInstance>, SandboxError> {
@@ -217,19 +2009,23 @@ impl AcpProxyRuntime {
     async fn get_or_create_instance(
         &self,
         server_id: &str,
-        bootstrap_agent: Option<AgentId>,
+        bootstrap: AcpBootstrap,
     ) -> Result<Arc<ProxyInstance>, SandboxError> {
+        let AcpBootstrap {
+            agent: bootstrap_agent,
+            from_pool,
+            directory,
+            snapshot_turns,
+            idle_timeout,
+            webhook_url,
+            metadata,
+            resource_limits,
+            sandbox_level,
+            egress_allow,
+            egress_deny,
+        } = bootstrap;
         if let Some(existing) = self.inner.instances.read().await.get(server_id).cloned() {
-            if let Some(agent) = bootstrap_agent {
-                if agent != existing.agent {
-                    return Err(SandboxError::Conflict {
-                        message: format!(
-                            "server '{server_id}' already exists for agent '{}'; requested '{agent}'",
-                            existing.agent.as_str()
-                        ),
-                    });
-                }
-            }
+            ensure_instance_matches(server_id, &existing, bootstrap_agent, directory.as_deref())?;
             return Ok(existing);
         }
 
@@ -243,16 +2039,7 @@ impl AcpProxyRuntime {
         let _guard = lock.lock().await;
 
         if let Some(existing) = self.inner.instances.read().await.get(server_id).cloned() {
-            if let Some(agent) = bootstrap_agent {
-                if agent != existing.agent {
-                    return Err(SandboxError::Conflict {
-                        message: format!(
-                            "server '{server_id}' already exists for agent '{}'; requested '{agent}'",
-                            existing.agent.as_str()
-                        ),
-                    });
-                }
-            }
+            ensure_instance_matches(server_id, &existing, bootstrap_agent, directory.as_deref())?;
             return Ok(existing);
         }
 
@@ -262,21 +2049,204 @@ impl AcpProxyRuntime {
             ),
         })?;
 
-        let created = self.create_instance(server_id, agent).await?;
+        let idle_timeout = match idle_timeout {
+            Some(timeout) if timeout.is_zero() => None,
+            Some(timeout) => Some(timeout),
+            None => self.inner.idle_timeout,
+        };
+        let resource_limits = self.inner.resource_limits.with_overrides(resource_limits);
+        let sandbox_level = sandbox_level.unwrap_or(self.inner.sandbox_level);
+        let egress = self
+            .inner
+            .egress
+            .clone()
+            .with_overrides(egress_allow, egress_deny);
+        // Pooled processes were spawned in the daemon's working directory with its limits,
+        // without an egress proxy.
+        let adopt = from_pool
+            && directory.is_none()
+            && resource_limits == self.inner.resource_limits
+            && sandbox_level == self.inner.sandbox_level
+            && egress.is_empty();
+        let options = InstanceOptions {
+            snapshot_turns,
+            idle_timeout,
+            webhook_url,
+            resource_limits,
+            sandbox_level,
+            egress,
+        };
+        let created = if adopt {
+            self.adopt_or_create_instance(server_id, agent, options)
+                .await?
+        } else {
+            self.create_instance(server_id, agent, directory, options)
+                .await?
+        };
+        if let Ok(mut created_metadata) = created.metadata.lock() {
+            *created_metadata = metadata;
+        }
         self.inner
             .instances
             .write()
             .await
             .insert(server_id.to_string(), created.clone());
+        self.spawn_firehose_forwarder(&created, None).await;
+        if created.idle_timeout.is_some() {
+            self.spawn_idle_reaper();
+        }
 
         Ok(created)
     }
 
+    /// Forward every event of `instance` into the firehose channel and its usage totals.
+    /// The task ends when the runtime shuts down and its event stream closes.
+    async fn spawn_firehose_forwarder(&self, instance: &ProxyInstance, after: Option<u64>) {
+        let firehose = self.inner.firehose.clone();
+        let server_id = instance.server_id.clone();
+        let agent = instance.agent;
+        let usage = instance.usage.clone();
+        let unparsed = self.inner.unparsed.clone();
+        let unparsed_count = instance.unparsed.clone();
+        let approvals = instance.approvals.clone();
+        let question_policies = instance.question_policies.clone();
+        let approval_router = self.inner.approval_router.clone();
+        let plans = instance.plans.clone();
+        let task_lists = instance.task_lists.clone();
+        let subagents = instance.subagents.clone();
+        let items = instance.items.clone();
+        let runtime = instance.runtime.clone();
+        let last_activity_ms = instance.last_activity_ms.clone();
+        let mut webhooks = self
+            .inner
+            .webhooks
+            .spawn_delivery(instance.webhook_url.as_deref());
+        let event_sink = self.inner.event_sink.get().cloned();
+        let mut events = Box::pin(instance.runtime.clone().sequenced_stream(after).await);
+        tokio::spawn(async move {
+            while let Some((sequence, message)) = events.next().await {
+                last_activity_ms.store(now_ms(), Ordering::SeqCst);
+                if let Ok(mut usage) = usage.lock() {
+                    usage.record(&message);
+                }
+                if unparsed.record(&server_id, agent, sequence, &message) {
+                    unparsed_count.fetch_add(1, Ordering::Relaxed);
+                }
+                if let Ok(mut approvals) = approvals.lock() {
+                    approvals.record(sequence, &message, now_ms());
+                }
+                if let Some(router) = approval_router
+                    .as_ref()
+                    .filter(|router| router.matches(&message))
+                {
+                    router.escalate(&server_id, agent, sequence, &message, approvals.clone());
+                }
+                let timed_question = question_policies
+                    .lock()
+                    .ok()
+                    .and_then(|policies| policies.for_question(&message));
+                if let Some((request_id, policy)) = timed_question {
+                    tokio::spawn(question_policy::expire(
+                        runtime.clone(),
+                        approvals.clone(),
+                        request_id,
+                        policy,
+                    ));
+                }
+                let mut planned = match plans.lock() {
+                    Ok(mut plans) => plans.record(&message),
+                    Err(_) => Vec::new(),
+                };
+                if let Ok(mut task_lists) = task_lists.lock() {
+                    planned.extend(task_lists.record(sequence, &message));
+                }
+                if let Ok(mut subagents) = subagents.lock() {
+                    planned.extend(subagents.record(sequence, &message));
+                }
+                if let Ok(mut items) = items.lock() {
+                    items.record(sequence, &message, now_ms());
+                }
+                if !planned.is_empty() {
+                    runtime.seed_history(planned).await;
+                }
+                if let Some(webhooks) = webhooks.as_mut() {
+                    webhooks.push(&server_id, agent, sequence, &message);
+                }
+                if let Some(event_sink) = &event_sink {
+                    event_sink.push(&server_id, agent, sequence, &message);
+                }
+                firehose.publish(AcpFirehoseEvent {
+                    server_id: server_id.clone(),
+                    agent,
+                    sequence,
+                    message,
+                });
+            }
+        });
+    }
+
+    async fn adopt_or_create_instance(
+        &self,
+        server_id: &str,
+        agent: AgentId,
+        options: InstanceOptions,
+    ) -> Result<Arc<ProxyInstance>, SandboxError> {
+        let admission = self.inner.admission.admit_server(agent)?;
+        let Some(runtime) = self.take_from_warm_pool(agent).await else {
+            tracing::info!(
+                server_id = server_id,
+                agent = agent.as_str(),
+                "warm pool: no idle agent process available, cold starting"
+            );
+            self.spawn_warm_pool_refill();
+            drop(admission);
+            return self.create_instance(server_id, agent, None, options).await;
+        };
+
+        tracing::info!(
+            server_id = server_id,
+            agent = agent.as_str(),
+            "warm pool: adopted idle agent process"
+        );
+        self.spawn_warm_pool_refill();
+
+        Ok(Arc::new(ProxyInstance::new(
+            server_id, agent, runtime, None, options, admission,
+        )))
+    }
+
     async fn create_instance(
         &self,
         server_id: &str,
         agent: AgentId,
+        directory: Option<PathBuf>,
+        options: InstanceOptions,
     ) -> Result<Arc<ProxyInstance>, SandboxError> {
+        let admission = self.inner.admission.admit_server(agent)?;
+        let runtime = self
+            .spawn_runtime(
+                server_id,
+                agent,
+                directory.as_deref(),
+                &options.resource_limits,
+                options.sandbox_level,
+                &options.egress,
+            )
+            .await?;
+        Ok(Arc::new(ProxyInstance::new(
+            server_id, agent, runtime, directory, options, admission,
+        )))
+    }
+
+    async fn spawn_runtime(
+        &self,
+        server_id: &str,
+        agent: AgentId,
+        directory: Option<&Path>,
+        resource_limits: &ResourceLimits,
+        sandbox_level: SandboxLevel,
+        egress: &EgressPolicy,
+    ) -> Result<Arc<AdapterRuntime>, SandboxError> {
         let start = std::time::Instant::now();
         tracing::info!(
             server_id = server_id,
@@ -312,16 +2282,68 @@ impl AcpProxyRuntime {
             "create_instance: launch spec resolved, spawning"
         );
 
-        let runtime = AdapterRuntime::start(
+        let cgroup = if resource_limits.is_empty() {
+            None
+        } else {
+            self.limit_cgroup(server_id, resource_limits)
+        };
+        let confinement = self.confinement(agent, directory, sandbox_level)?;
+        let (program, args) = match limit_prefix(
+            server_id,
+            resource_limits,
+            cgroup.as_deref(),
+            confinement.as_ref(),
+        )? {
+            Some(mut prefix) => {
+                let program = PathBuf::from(prefix.remove(0));
+                prefix.push(launch.program.display().to_string());
+                prefix.extend(launch.args);
+                (program, prefix)
+            }
+            None => (launch.program, launch.args),
+        };
+        let mut env = launch.env;
+        let egress_proxy = if egress.is_empty() {
+            None
+        } else {
+            let on_blocked = self.egress_audit(server_id, agent);
+            let proxy = EgressProxy::start(egress.clone(), on_blocked)
+                .await
+                .map_err(|err| SandboxError::StreamError {
+                    message: format!("failed to start egress proxy: {err}"),
+                })?;
+            env.extend(proxy.env());
+            Some(proxy)
+        };
+
+        let blobs = self.inner.blobs.clone();
+        let filter: Option<MessageFilter> = blobs
+            .is_enabled()
+            .then(|| Arc::new(move |message| blobs.extract(message)) as MessageFilter);
+        let runtime = AdapterRuntime::start_with_filter(
             LaunchSpec {
-                program: launch.program,
-                args: launch.args,
-                env: launch.env,
+                program,
+                args,
+                env,
+                cwd: directory.map(Path::to_path_buf),
             },
             self.inner.request_timeout,
+            filter,
         )
         .await
         .map_err(map_adapter_error)?;
+        #[cfg(feature = "test-utils")]
+        if let Some(chaos) = &self.inner.chaos {
+            runtime.set_stdout_delay(chaos.stdout_delay());
+        }
+
+        let runtime = Arc::new(runtime);
+        if let Some(cgroup) = cgroup {
+            resource_limits::spawn_watch(cgroup, *resource_limits, runtime.clone());
+        }
+        if let Some(proxy) = egress_proxy {
+            proxy.stop_on_exit(runtime.clone());
+        }
 
         let total_ms = start.elapsed().as_millis() as u64;
         tracing::info!(
@@ -331,12 +2353,95 @@ impl AcpProxyRuntime {
             "create_instance: ready"
         );
 
-        Ok(Arc::new(ProxyInstance {
-            server_id: server_id.to_string(),
+        Ok(runtime)
+    }
+
+    /// The `limit-exec` prefix for the managed OpenCode server, which gets the daemon limits
+    /// as rlimits only: it runs for the daemon's lifetime and has no event stream to report
+    /// violations on.
+    pub(crate) fn managed_server_limit_prefix(&self) -> Vec<String> {
+        limit_prefix("<opencode>", &self.inner.resource_limits, None, None)
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    /// What `agent` running in `directory` is confined to at `level`. Fails when the level
+    /// asks for confinement this kernel can't provide, rather than starting the agent without.
+    fn confinement(
+        &self,
+        agent: AgentId,
+        directory: Option<&Path>,
+        level: SandboxLevel,
+    ) -> Result<Option<Confinement>, SandboxError> {
+        if level == SandboxLevel::None {
+            return Ok(None);
+        }
+        if agent_sandbox::landlock_abi().is_none() {
+            return Err(SandboxError::InvalidRequest {
+                message: format!(
+                    "sandboxLevel {} requires Landlock, which this system does not support",
+                    level.as_str()
+                ),
+            });
+        }
+        let working_dir = match directory {
+            Some(directory) => directory.to_path_buf(),
+            None => std::env::current_dir().map_err(|err| SandboxError::StreamError {
+                message: format!("failed to resolve the agent working directory: {err}"),
+            })?,
+        };
+        Ok(Confinement::for_agent(
+            level,
             agent,
-            runtime: Arc::new(runtime),
-            created_at_ms: now_ms(),
-        }))
+            &working_dir,
+            self.inner.agent_manager.install_dir(),
+        ))
+    }
+
+    /// A cgroup enforcing `limits` for `server_id`; `None` when cgroups v2 can't be used, in
+    /// which case `limit-exec` falls back to rlimits.
+    fn limit_cgroup(&self, server_id: &str, limits: &ResourceLimits) -> Option<PathBuf> {
+        let cgroups = self.inner.cgroups.get_or_init(Cgroups::detect).as_ref()?;
+        cgroups
+            .create(server_id, limits)
+            .inspect_err(|err| {
+                tracing::warn!(
+                    server_id = server_id,
+                    error = %err,
+                    "resource limits: failed to create cgroup, falling back to rlimits"
+                );
+            })
+            .ok()
+    }
+
+    /// Logs and audits connections of `server_id`'s agent refused by its egress policy.
+    fn egress_audit(&self, server_id: &str, agent: AgentId) -> BlockedCallback {
+        let audit = self.inner.audit.get().cloned();
+        let server_id = server_id.to_string();
+        Arc::new(move |blocked: BlockedConnection| {
+            let destination = format!("{}:{}", blocked.host, blocked.port);
+            tracing::warn!(
+                server_id = %server_id,
+                agent = agent.as_str(),
+                destination = %destination,
+                reason = %blocked.reason,
+                "egress: blocked connection"
+            );
+            let Some(audit) = &audit else {
+                return;
+            };
+            let mut entry = audit_entry(
+                AuditKind::EgressBlocked,
+                format!("agent:{}", agent.as_str()),
+            );
+            entry.server_id = Some(server_id.clone());
+            entry.action = Some(format!("{} {destination}", blocked.method));
+            entry.decision = Some("blocked".to_string());
+            entry.destination = Some(destination);
+            entry.details = json!({ "reason": blocked.reason });
+            audit.record(entry);
+        })
     }
 
     async fn ensure_installed(&self, agent: AgentId) -> Result<(), SandboxError> {
@@ -399,7 +2504,17 @@ impl AcpDispatch for AcpProxyRuntime {
         let server_id = server_id.to_string();
         let agent = bootstrap_agent.and_then(AgentId::parse);
         Box::pin(async move {
-            match self.post(&server_id, agent, payload).await {
+            match self
+                .post(
+                    &server_id,
+                    AcpBootstrap {
+                        agent,
+                        ..AcpBootstrap::default()
+                    },
+                    payload,
+                )
+                .await
+            {
                 Ok(ProxyPostOutcome::Response(value)) => Ok(AcpDispatchResult::Response(value)),
                 Ok(ProxyPostOutcome::Accepted) => Ok(AcpDispatchResult::Accepted),
                 Err(err) => Err(err.to_string()),
@@ -457,6 +2572,310 @@ fn map_adapter_error(err: AdapterError) -> SandboxError {
     }
 }
 
+/// Set the sub-agent each tool call item of `session_id` was made by.
+fn link_parents(
+    instance: &ProxyInstance,
+    session_id: &str,
+    mut items: Vec<AcpItem>,
+) -> Vec<AcpItem> {
+    let parents = instance
+        .subagents
+        .lock()
+        .map(|subagents| subagents.parents(session_id))
+        .unwrap_or_default();
+    for item in &mut items {
+        item.parent_tool_call_id = parents.get(&item.item_id).cloned();
+    }
+    items
+}
+
+fn link_turn_parents(
+    instance: &ProxyInstance,
+    session_id: &str,
+    mut turn: AcpSessionTurn,
+) -> AcpSessionTurn {
+    turn.items = link_parents(instance, session_id, std::mem::take(&mut turn.items));
+    turn
+}
+
+/// Switch the session a Codex `session/new` just created to the requested reasoning variant.
+async fn apply_variant(instance: &ProxyInstance, response: Value, variant: &str) -> Value {
+    let outcome = match variant_request(&response, variant) {
+        Ok((model_id, request)) => match instance.runtime.post(request).await {
+            Ok(PostOutcome::Response(reply)) => match reply.get("error") {
+                Some(error) => Err(format!("session/set_model failed: {error}")),
+                None => Ok(model_id),
+            },
+            Ok(PostOutcome::Accepted) => Ok(model_id),
+            Err(err) => Err(format!("session/set_model failed: {err}")),
+        },
+        Err(message) => Err(message),
+    };
+    if let Err(message) = &outcome {
+        tracing::warn!(
+            server_id = instance.server_id,
+            variant = variant,
+            error = %message,
+            "acp_proxy: reasoning variant not applied"
+        );
+    }
+    with_variant_outcome(response, outcome)
+}
+
+/// Tell the server's SSE streams which remote MCP servers of a `session/new` or `session/load`
+/// are started without their OAuth token, in the shape of the universal `error` event.
+async fn report_mcp_auth_failures(
+    instance: &ProxyInstance,
+    payload: &Value,
+    failures: Vec<AuthFailure>,
+) {
+    let session_id = payload.pointer("/params/sessionId").cloned();
+    let notifications = failures
+        .into_iter()
+        .map(|failure| {
+            tracing::warn!(
+                server_id = instance.server_id,
+                url = failure.url,
+                error = failure.error,
+                "acp_proxy: MCP server started without OAuth token"
+            );
+            json!({
+                "jsonrpc": "2.0",
+                "method": "_sandboxagent/error",
+                "params": {
+                    "sessionId": session_id,
+                    "message": format!("MCP server {} is not authorized: {}", failure.url, failure.error),
+                    "code": "mcp_auth_failed",
+                    "details": { "name": failure.name, "url": failure.url },
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    if !notifications.is_empty() {
+        instance.runtime.seed_history(notifications).await;
+    }
+}
+
+/// Parse the reply of a turn that asked for `schema`, emit it as
+/// `_sandboxagent/structured_output` and add it to the prompt result.
+async fn attach_structured_output(
+    instance: &ProxyInstance,
+    mut response: Value,
+    session_id: &str,
+    schema: &Value,
+    after: u64,
+) -> Value {
+    let mut reply = String::new();
+    for (_, event) in instance.runtime.buffered_events(Some(after)).await {
+        if let Some(text) = session_update(&event, session_id).and_then(message_chunk) {
+            reply.push_str(text);
+        }
+    }
+    let parsed = serde_json::to_value(parse_output(schema, &reply)).unwrap_or_default();
+    let mut params = parsed.clone();
+    params["sessionId"] = Value::String(session_id.to_string());
+    instance
+        .runtime
+        .seed_history([json!({
+            "jsonrpc": "2.0",
+            "method": "_sandboxagent/structured_output",
+            "params": params,
+        })])
+        .await;
+    if let Some(result) = response.get_mut("result").and_then(Value::as_object_mut) {
+        let meta = result.entry("_meta").or_insert_with(|| json!({}));
+        if let Some(meta) = meta.as_object_mut() {
+            let ours = meta.entry("sandboxagent.dev").or_insert_with(|| json!({}));
+            if let Some(ours) = ours.as_object_mut() {
+                ours.insert("structuredOutput".to_string(), parsed);
+            }
+        }
+    }
+    response
+}
+
+/// Send `payload`, bounding the turn by `turn_limit` when there is one. A response that
+/// reports a provider rate limit is counted and announced on the server's streams.
+async fn post_once(
+    instance: &ProxyInstance,
+    rate_limits: &RateLimitLog,
+    payload: Value,
+    turn_limit: Option<Duration>,
+) -> Result<PostOutcome, AdapterError> {
+    let session_id = payload
+        .pointer("/params/sessionId")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let _prompt = (payload.get("method").and_then(Value::as_str) == Some("session/prompt"))
+        .then(|| PromptGuard::new(instance, session_id.as_deref().unwrap_or_default()));
+    let outcome = match turn_limit {
+        Some(limit) => post_turn(instance, payload, limit).await,
+        None => instance.runtime.post(payload).await,
+    };
+    if let Ok(PostOutcome::Response(value)) = &outcome {
+        if let Some(notification) = rate_limits.record(
+            &instance.server_id,
+            instance.agent,
+            &instance.rate_limits,
+            session_id.as_deref(),
+            value,
+        ) {
+            instance.runtime.seed_history([notification]).await;
+        }
+    }
+    outcome
+}
+
+/// Send a `session/prompt` until it does not fail transiently or `policy` runs out of
+/// attempts, announcing each retry on the server's streams. Only agent error responses are
+/// retried; an `AdapterError` (the process exited or was restarted) is returned as is, since
+/// the session it ran is gone with it.
+async fn post_with_retries(
+    instance: &ProxyInstance,
+    rate_limits: &RateLimitLog,
+    payload: Value,
+    turn_limit: Option<Duration>,
+    policy: RetryPolicy,
+) -> Result<PostOutcome, AdapterError> {
+    let session_id = payload
+        .pointer("/params/sessionId")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let mut attempt = 1;
+    loop {
+        let outcome = post_once(instance, rate_limits, payload.clone(), turn_limit).await;
+        let error = match &outcome {
+            Ok(PostOutcome::Response(value)) if attempt < policy.max_attempts => {
+                turn_retry::transient_error(value).cloned()
+            }
+            _ => None,
+        };
+        let Some(error) = error else {
+            return outcome;
+        };
+        let delay = policy.delay(attempt);
+        attempt += 1;
+        tracing::warn!(
+            server_id = instance.server_id,
+            session_id = session_id,
+            attempt = attempt,
+            delay_ms = delay.as_millis() as u64,
+            "acp_proxy: turn failed transiently, retrying"
+        );
+        instance
+            .runtime
+            .seed_history([turn_retry::retry_event(
+                &session_id,
+                payload.get("id"),
+                attempt,
+                delay,
+                &error,
+            )])
+            .await;
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Send a `session/prompt` and wait at most `limit` for the turn to end. A turn that runs
+/// longer is cancelled; when the agent does not end it within the grace period its process is
+/// stopped. Either way the prompt is answered with a timeout error.
+async fn post_turn(
+    instance: &ProxyInstance,
+    payload: Value,
+    limit: Duration,
+) -> Result<PostOutcome, AdapterError> {
+    let session_id = payload
+        .pointer("/params/sessionId")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let id = payload.get("id").cloned();
+    let turn = instance.runtime.post(payload);
+    tokio::pin!(turn);
+    if let Ok(outcome) = tokio::time::timeout(limit, &mut turn).await {
+        return outcome;
+    }
+
+    tracing::warn!(
+        server_id = instance.server_id,
+        session_id = session_id,
+        timeout_ms = limit.as_millis() as u64,
+        "acp_proxy: turn timed out, cancelling"
+    );
+    let cancel = json!({
+        "jsonrpc": "2.0",
+        "method": "session/cancel",
+        "params": { "sessionId": session_id },
+    });
+    if let Err(err) = instance.runtime.post(cancel).await {
+        tracing::warn!(
+            server_id = instance.server_id,
+            error = %err,
+            "acp_proxy: failed to cancel timed-out turn"
+        );
+    }
+    let ended = tokio::time::timeout(turn_timeout::CANCEL_GRACE, &mut turn)
+        .await
+        .is_ok();
+    // Killing the process would end every other session's turn with it.
+    let outcome = if ended {
+        TimeoutOutcome::Cancelled
+    } else if instance.others_prompting(&session_id) {
+        TimeoutOutcome::Abandoned
+    } else {
+        TimeoutOutcome::Stopped
+    };
+    // Published first: the stream ends with the agent's exit.
+    instance
+        .runtime
+        .seed_history(turn_timeout::timeout_events(&session_id, limit, outcome))
+        .await;
+    match outcome {
+        TimeoutOutcome::Cancelled => {}
+        TimeoutOutcome::Stopped => {
+            tracing::warn!(
+                server_id = instance.server_id,
+                session_id = session_id,
+                "acp_proxy: agent did not end the timed-out turn, stopping its process"
+            );
+            instance.runtime.shutdown().await;
+        }
+        TimeoutOutcome::Abandoned => tracing::warn!(
+            server_id = instance.server_id,
+            session_id = session_id,
+            "acp_proxy: agent did not end the timed-out turn, keeping its process for other sessions"
+        ),
+    }
+    Ok(PostOutcome::Response(turn_timeout::timeout_response(
+        id.as_ref(),
+        limit,
+    )))
+}
+
+/// Send the daemon's `switches` to the agent in order. When one fails, the `undo` requests of
+/// those already made are sent and the agent's JSON-RPC error is returned.
+async fn switch_session(
+    instance: &ProxyInstance,
+    switches: &[Value],
+    undo: &[Value],
+) -> Result<(), Value> {
+    for (index, request) in switches.iter().enumerate() {
+        let error = match instance.runtime.post(request.clone()).await {
+            Ok(PostOutcome::Response(reply)) => reply.get("error").cloned(),
+            Ok(PostOutcome::Accepted) => None,
+            Err(err) => Some(json!({ "code": -32603, "message": err.to_string() })),
+        };
+        if let Some(error) = error {
+            for request in undo[..index.min(undo.len())].iter().rev() {
+                let _ = instance.runtime.post(request.clone()).await;
+            }
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
 /// Inspect JSON-RPC error responses from agent processes and add helpful hints
 /// when we can infer the root cause from a known error pattern.
 fn annotate_agent_error(agent: AgentId, mut value: Value) -> Value {
@@ -488,6 +2907,112 @@ fn annotate_agent_error(agent: AgentId, mut value: Value) -> Value {
     value
 }
 
+/// The `limit-exec` command line to start an agent through, or `None` when nothing needs
+/// enforcing. Without a cgroup only the memory and process limits apply, as rlimits.
+fn limit_prefix(
+    server_id: &str,
+    limits: &ResourceLimits,
+    cgroup: Option<&Path>,
+    confinement: Option<&Confinement>,
+) -> Result<Option<Vec<String>>, SandboxError> {
+    if cgroup.is_none() && limits.cpu.is_some() {
+        tracing::warn!(
+            server_id = server_id,
+            "resource limits: CPU limit needs cgroups v2, starting agent without it"
+        );
+    }
+    if cgroup.is_none()
+        && limits.memory_mb.is_none()
+        && limits.processes.is_none()
+        && confinement.is_none()
+    {
+        return Ok(None);
+    }
+    resource_limits::limit_exec_prefix(limits, cgroup, confinement)
+        .map(Some)
+        .map_err(|err| SandboxError::StreamError {
+            message: format!("failed to apply resource limits: {err}"),
+        })
+}
+
+fn ensure_instance_matches(
+    server_id: &str,
+    existing: &ProxyInstance,
+    agent: Option<AgentId>,
+    directory: Option<&Path>,
+) -> Result<(), SandboxError> {
+    if let Some(agent) = agent {
+        if agent != existing.agent {
+            return Err(SandboxError::Conflict {
+                message: format!(
+                    "server '{server_id}' already exists for agent '{}'; requested '{agent}'",
+                    existing.agent.as_str()
+                ),
+            });
+        }
+    }
+    if let Some(directory) = directory {
+        if existing.directory.as_deref() != Some(directory) {
+            return Err(SandboxError::Conflict {
+                message: format!(
+                    "server '{server_id}' already exists with a different directory; requested '{}'",
+                    directory.display()
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Fill in `params.cwd` for `session/new` requests that omit it, using the directory the
+/// server was bootstrapped with.
+fn with_default_session_cwd(mut payload: Value, directory: Option<&Path>) -> Value {
+    let Some(directory) = directory else {
+        return payload;
+    };
+    if payload.get("method").and_then(Value::as_str) != Some("session/new") {
+        return payload;
+    }
+    if let Some(params) = payload.get_mut("params").and_then(Value::as_object_mut) {
+        let missing = params
+            .get("cwd")
+            .and_then(Value::as_str)
+            .is_none_or(|cwd| cwd.is_empty());
+        if missing {
+            params.insert(
+                "cwd".to_string(),
+                Value::String(directory.to_string_lossy().into_owned()),
+            );
+        }
+    }
+    payload
+}
+
+/// Parse `SANDBOX_AGENT_ACP_WARM_POOL` values such as `claude=2,codex=1`.
+/// Unknown agents and malformed entries are skipped with a warning.
+fn parse_warm_pool_targets(raw: &str) -> HashMap<AgentId, usize> {
+    let mut targets = HashMap::new();
+    for entry in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (agent, count) = entry.split_once('=').unwrap_or((entry, "1"));
+        let Some(agent) = AgentId::parse(agent.trim()) else {
+            tracing::warn!(entry = entry, "warm pool: ignoring unknown agent");
+            continue;
+        };
+        let Ok(count) = count.trim().parse::<usize>() else {
+            tracing::warn!(entry = entry, "warm pool: ignoring invalid size");
+            continue;
+        };
+        if count > 0 {
+            targets.insert(agent, count);
+        }
+    }
+    targets
+}
+
 fn duration_from_env_ms(key: &str, default: Duration) -> Duration {
     match std::env::var(key) {
         Ok(raw) => raw
@@ -501,9 +3026,55 @@ fn duration_from_env_ms(key: &str, default: Duration) -> Duration {
     }
 }
 
-fn now_ms() -> i64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|duration| duration.as_millis() as i64)
-        .unwrap_or(0)
+struct TurnSnapshot {
+    root: PathBuf,
+    session_id: Option<String>,
+    started_at_ms: i64,
+    before: WorkspaceSnapshot,
+}
+
+async fn start_turn_snapshot(instance: &ProxyInstance, payload: &Value) -> Option<TurnSnapshot> {
+    let root = instance.workspace_root()?;
+    let session_id = payload
+        .pointer("/params/sessionId")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let started_at_ms = now_ms();
+    let capture_root = root.clone();
+    let before = tokio::task::spawn_blocking(move || WorkspaceSnapshot::capture(&capture_root))
+        .await
+        .ok()?;
+    Some(TurnSnapshot {
+        root,
+        session_id,
+        started_at_ms,
+        before,
+    })
+}
+
+async fn finish_turn_snapshot(instance: &ProxyInstance, turn: TurnSnapshot) {
+    let root = turn.root.clone();
+    let Ok(after) = tokio::task::spawn_blocking(move || WorkspaceSnapshot::capture(&root)).await
+    else {
+        return;
+    };
+    let (files, diff) = turn.before.diff(&after);
+    let record = TurnRecord {
+        turn_id: 0,
+        session_id: turn.session_id,
+        root: turn.root,
+        started_at_ms: turn.started_at_ms,
+        ended_at_ms: now_ms(),
+        files,
+        diff,
+        truncated: turn.before.truncated() || after.truncated(),
+    };
+    if let Ok(mut turns) = instance.turns.lock() {
+        let turn_id = turns.push(record);
+        tracing::debug!(
+            server_id = instance.server_id,
+            turn_id = turn_id,
+            "acp_proxy: recorded turn diff"
+        );
+    }
 }
@@ -0,0 +1,390 @@
+//! Headless runs: one prompt on a new server, waited on in a single request.
+//!
+//! `POST /v1/runs` starts a server with turn snapshots enabled, opens a session, sends the
+//! prompt and waits for the turn to end or time out. The result collects the assistant's
+//! reply, the session updates of the turn, the workspace diff and token usage, so CI jobs do
+//! not have to follow the event stream themselves.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::StreamExt;
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_api_types::AcpStructuredOutput;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::acp_proxy_runtime::{AcpBootstrap, AcpProxyRuntime, ProxyPostOutcome};
+use crate::acp_usage::AcpUsage;
+use crate::router::AcpRunStatus;
+use crate::turn_diff::TurnRecord;
+
+static RUN_COUNTER: AtomicU64 = AtomicU64::new(1);
+static PROMPT_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+/// How long to wait for a turn's last updates once the prompt has been answered.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Server id for a run that did not name one.
+pub(crate) fn next_run_server_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    let n = RUN_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("run-{millis}-{n}")
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RunSpec {
+    pub server_id: String,
+    pub agent: AgentId,
+    pub model: Option<String>,
+    pub directory: Option<PathBuf>,
+    pub prompt: Value,
+    pub output_schema: Option<Value>,
+    pub timeout: Duration,
+}
+
+#[derive(Debug)]
+pub(crate) struct RunReport {
+    pub status: AcpRunStatus,
+    pub session_id: Option<String>,
+    pub stop_reason: Option<String>,
+    pub error: Option<String>,
+    /// Text of the assistant's message chunks during the turn.
+    pub message: String,
+    /// `update` objects of the session's `session/update` notifications during the turn.
+    pub items: Vec<Value>,
+    pub turn: Option<TurnRecord>,
+    pub structured_output: Option<AcpStructuredOutput>,
+    pub usage: AcpUsage,
+    pub duration: Duration,
+}
+
+/// Start the run's server, send the prompt and wait for the turn to end. The server is left
+/// running; the caller decides whether to delete it.
+pub(crate) async fn run_prompt(
+    runtime: &AcpProxyRuntime,
+    spec: &RunSpec,
+) -> Result<RunReport, SandboxError> {
+    if runtime
+        .list_instances()
+        .await
+        .iter()
+        .any(|info| info.server_id == spec.server_id)
+    {
+        return Err(SandboxError::Conflict {
+            message: format!("server '{}' already exists", spec.server_id),
+        });
+    }
+
+    let started = Instant::now();
+    let mut report = RunReport {
+        status: AcpRunStatus::Failed,
+        session_id: None,
+        stop_reason: None,
+        error: None,
+        message: String::new(),
+        items: Vec::new(),
+        turn: None,
+        structured_output: None,
+        usage: AcpUsage::default(),
+        duration: Duration::ZERO,
+    };
+    let mut after = None;
+    if let Err(message) = drive_run(runtime, spec, &mut report, &mut after).await {
+        report.error = Some(message);
+    }
+
+    if let Some(session_id) = report.session_id.clone() {
+        let events = runtime
+            .buffered_events(&spec.server_id, after)
+            .await
+            .unwrap_or_default();
+        for (_, event) in events {
+            let Some(update) = session_update(&event, &session_id) else {
+                continue;
+            };
+            if let Some(text) = message_chunk(update) {
+                report.message.push_str(text);
+            }
+            report.items.push(update.clone());
+        }
+    }
+    if report.status == AcpRunStatus::Completed {
+        report.turn = runtime
+            .turns(&spec.server_id)
+            .await
+            .ok()
+            .and_then(|turns| turns.into_iter().last());
+    }
+    if let Ok(usage) = runtime.usage(&spec.server_id).await {
+        report.usage = usage;
+    }
+    report.duration = started.elapsed();
+    Ok(report)
+}
+
+async fn drive_run(
+    runtime: &AcpProxyRuntime,
+    spec: &RunSpec,
+    report: &mut RunReport,
+    after: &mut Option<u64>,
+) -> Result<(), String> {
+    let session_id = open_session(runtime, spec).await?;
+    report.session_id = Some(session_id.clone());
+
+    *after = runtime
+        .buffered_events(&spec.server_id, None)
+        .await
+        .ok()
+        .and_then(|events| events.last().map(|(sequence, _)| *sequence));
+    let mut params = json!({ "sessionId": session_id, "prompt": spec.prompt });
+    if let Some(schema) = &spec.output_schema {
+        params["_meta"] = json!({ "sandboxagent.dev": { "outputSchema": schema } });
+    }
+    let prompt = runtime.post(
+        &spec.server_id,
+        AcpBootstrap::default(),
+        run_request(4, "session/prompt", params),
+    );
+    let response = match tokio::time::timeout(spec.timeout, prompt).await {
+        Ok(Ok(ProxyPostOutcome::Response(response))) => response,
+        Ok(Ok(ProxyPostOutcome::Accepted)) => Value::Null,
+        Ok(Err(SandboxError::Timeout { .. })) | Err(_) => {
+            report.status = AcpRunStatus::TimedOut;
+            cancel(runtime, &spec.server_id, &session_id).await;
+            return Err(format!(
+                "turn did not finish within {} ms",
+                spec.timeout.as_millis()
+            ));
+        }
+        Ok(Err(err)) => return Err(format!("session/prompt failed: {err}")),
+    };
+    if let Some(error) = response.get("error") {
+        return Err(format!("session/prompt failed: {error}"));
+    }
+    report.status = AcpRunStatus::Completed;
+    report.structured_output = response
+        .pointer("/result/_meta/sandboxagent.dev/structuredOutput")
+        .and_then(|parsed| serde_json::from_value(parsed.clone()).ok());
+    report.stop_reason = response
+        .pointer("/result/stopReason")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Ok(())
+}
+
+/// Start the run's server and open a session in its workspace, with the run's model when it
+/// names one. Returns the session id.
+pub(crate) async fn open_session(
+    runtime: &AcpProxyRuntime,
+    spec: &RunSpec,
+) -> Result<String, String> {
+    let bootstrap = AcpBootstrap {
+        agent: Some(spec.agent),
+        directory: spec.directory.clone(),
+        snapshot_turns: true,
+        ..AcpBootstrap::default()
+    };
+    runtime
+        .client_request(
+            &spec.server_id,
+            bootstrap,
+            run_request(
+                1,
+                "initialize",
+                json!({ "protocolVersion": 1, "clientCapabilities": {} }),
+            ),
+        )
+        .await?;
+
+    let cwd = runtime
+        .workspace_root(&spec.server_id)
+        .await
+        .map_err(|err| err.to_string())?;
+    let created = runtime
+        .client_request(
+            &spec.server_id,
+            AcpBootstrap::default(),
+            run_request(
+                2,
+                "session/new",
+                json!({ "cwd": cwd.to_string_lossy(), "mcpServers": [] }),
+            ),
+        )
+        .await?;
+    let session_id = created
+        .pointer("/result/sessionId")
+        .and_then(Value::as_str)
+        .ok_or_else(|| "session/new returned no sessionId".to_string())?
+        .to_string();
+
+    if let Some(model) = &spec.model {
+        runtime
+            .client_request(
+                &spec.server_id,
+                AcpBootstrap::default(),
+                run_request(
+                    3,
+                    "session/set_model",
+                    json!({ "sessionId": session_id, "modelId": model }),
+                ),
+            )
+            .await?;
+    }
+    Ok(session_id)
+}
+
+fn run_request(id: u64, method: &str, params: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": format!("sandboxagent-run-{id}"),
+        "method": method,
+        "params": params,
+    })
+}
+
+/// What [`follow_prompt`] reports about a turn.
+#[derive(Debug)]
+pub(crate) enum TurnEvent {
+    /// The `update` of one of the session's `session/update` notifications.
+    Update(Value),
+    /// The `session/prompt` response (`Value::Null` when the agent only accepted it), or why
+    /// the prompt could not be sent.
+    Ended(Result<Value, SandboxError>),
+}
+
+/// Send `session/prompt` with `params` to a session and report the turn's updates as they
+/// arrive, then the response once the updates before it have been reported. Dropping the
+/// receiver cancels the turn.
+pub(crate) async fn follow_prompt(
+    runtime: Arc<AcpProxyRuntime>,
+    server_id: String,
+    session_id: String,
+    params: Value,
+) -> Result<mpsc::Receiver<TurnEvent>, SandboxError> {
+    let after = runtime
+        .buffered_events(&server_id, None)
+        .await?
+        .last()
+        .map(|(sequence, _)| *sequence);
+    let (_, events) = runtime.sequenced_events(&server_id).await?;
+    let n = PROMPT_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let request_id = format!("sandboxagent-prompt-{n}");
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": request_id,
+        "method": "session/prompt",
+        "params": params,
+    });
+
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let mut events = Box::pin(events.filter(move |(sequence, _)| {
+            std::future::ready(after.is_none_or(|after| *sequence > after))
+        }));
+        let post_runtime = runtime.clone();
+        let post_server_id = server_id.clone();
+        let post = async move {
+            post_runtime
+                .post(&post_server_id, AcpBootstrap::default(), request)
+                .await
+        };
+        tokio::pin!(post);
+
+        let outcome = loop {
+            tokio::select! {
+                outcome = &mut post => break Some(outcome),
+                Some((_, message)) = events.next() => {
+                    if let Some(update) = session_update(&message, &session_id) {
+                        if tx.send(TurnEvent::Update(update.clone())).await.is_err() {
+                            break None;
+                        }
+                    }
+                }
+                _ = tx.closed() => break None,
+            }
+        };
+        let Some(outcome) = outcome else {
+            cancel(&runtime, &server_id, &session_id).await;
+            // Let the daemon finish its bookkeeping for the cancelled turn.
+            let _ = post.await;
+            return;
+        };
+
+        // The agent's response follows the turn's updates in the event stream.
+        if outcome.is_ok() {
+            let drain = tokio::time::sleep(DRAIN_TIMEOUT);
+            tokio::pin!(drain);
+            loop {
+                tokio::select! {
+                    _ = &mut drain => break,
+                    event = events.next() => {
+                        let Some((_, message)) = event else {
+                            break;
+                        };
+                        if message.get("method").is_none()
+                            && message.get("id").and_then(Value::as_str) == Some(&request_id)
+                        {
+                            break;
+                        }
+                        if let Some(update) = session_update(&message, &session_id) {
+                            if tx.send(TurnEvent::Update(update.clone())).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let ended = outcome.map(|outcome| match outcome {
+            ProxyPostOutcome::Response(response) => response,
+            ProxyPostOutcome::Accepted => Value::Null,
+        });
+        let _ = tx.send(TurnEvent::Ended(ended)).await;
+    });
+    Ok(rx)
+}
+
+/// Ask the agent to stop a turn nobody waits for any more.
+pub(crate) async fn cancel(runtime: &AcpProxyRuntime, server_id: &str, session_id: &str) {
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "session/cancel",
+        "params": { "sessionId": session_id },
+    });
+    if let Err(err) = runtime
+        .post(server_id, AcpBootstrap::default(), notification)
+        .await
+    {
+        tracing::warn!(server_id = server_id, error = %err, "acp_runs: failed to cancel timed-out turn");
+    }
+}
+
+pub(crate) fn session_update<'a>(event: &'a Value, session_id: &str) -> Option<&'a Value> {
+    if event.get("method").and_then(Value::as_str) != Some("session/update") {
+        return None;
+    }
+    let params = event.get("params")?;
+    if params.get("sessionId").and_then(Value::as_str) != Some(session_id) {
+        return None;
+    }
+    params.get("update")
+}
+
+pub(crate) fn message_chunk(update: &Value) -> Option<&str> {
+    if update.get("sessionUpdate").and_then(Value::as_str) != Some("agent_message_chunk") {
+        return None;
+    }
+    let content = update.get("content")?;
+    if content.get("type").and_then(Value::as_str) != Some("text") {
+        return None;
+    }
+    content.get("text").and_then(Value::as_str)
+}
@@ -0,0 +1,139 @@
+//! Token usage and cost accounting for ACP servers.
+//!
+//! Usage is collected from two ACP shapes the agents emit:
+//! - `session/prompt` results carrying a `usage` object with per-turn token counts.
+//! - `session/update` notifications with `sessionUpdate: "usage_update"`, whose `cost`
+//!   is the session's running total as reported by the agent, and whose `used` and `size`
+//!   are the tokens in the session's context window and the window's size.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AcpUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_read_tokens: u64,
+    pub cached_write_tokens: u64,
+    pub thought_tokens: u64,
+    pub total_tokens: u64,
+    /// Prompt turns that reported usage.
+    pub turns: u64,
+    /// Latest cumulative cost reported per ACP session id.
+    session_costs: BTreeMap<String, AcpUsageCost>,
+    /// Latest context window reported per ACP session id.
+    session_contexts: BTreeMap<String, AcpContextWindow>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcpContextWindow {
+    /// Tokens in the context window.
+    pub used: u64,
+    /// Tokens the context window holds.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcpUsageCost {
+    pub amount: f64,
+    pub currency: String,
+}
+
+impl AcpUsage {
+    /// Fold one ACP envelope into the totals. Envelopes without usage are ignored.
+    pub fn record(&mut self, message: &Value) {
+        if let Some(usage) = message
+            .get("result")
+            .and_then(|result| result.get("usage"))
+            .filter(|usage| usage.is_object())
+        {
+            self.record_turn(usage);
+            return;
+        }
+
+        if message.get("method").and_then(Value::as_str) != Some("session/update") {
+            return;
+        }
+        let Some(params) = message.get("params") else {
+            return;
+        };
+        let Some(update) = params.get("update") else {
+            return;
+        };
+        if update.get("sessionUpdate").and_then(Value::as_str) != Some("usage_update") {
+            return;
+        }
+        let session_id = params
+            .get("sessionId")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        if let (Some(used), Some(size)) = (
+            update.get("used").and_then(Value::as_u64),
+            update.get("size").and_then(Value::as_u64),
+        ) {
+            self.session_contexts
+                .insert(session_id.clone(), AcpContextWindow { used, size });
+        }
+        let Some(cost) = update.get("cost") else {
+            return;
+        };
+        let (Some(amount), Some(currency)) = (
+            cost.get("amount").and_then(Value::as_f64),
+            cost.get("currency").and_then(Value::as_str),
+        ) else {
+            return;
+        };
+        self.session_costs.insert(
+            session_id,
+            AcpUsageCost {
+                amount,
+                currency: currency.to_string(),
+            },
+        );
+    }
+
+    /// Reported cost summed across sessions, one entry per currency.
+    pub fn costs(&self) -> Vec<AcpUsageCost> {
+        let mut totals = BTreeMap::<&str, f64>::new();
+        for cost in self.session_costs.values() {
+            *totals.entry(cost.currency.as_str()).or_default() += cost.amount;
+        }
+        totals
+            .into_iter()
+            .map(|(currency, amount)| AcpUsageCost {
+                amount,
+                currency: currency.to_string(),
+            })
+            .collect()
+    }
+
+    /// Latest context window reported for `session_id`.
+    pub fn context(&self, session_id: &str) -> Option<AcpContextWindow> {
+        self.session_contexts.get(session_id).copied()
+    }
+
+    /// Latest context window of each session that reported one, by session id.
+    pub fn contexts(&self) -> impl Iterator<Item = (&str, AcpContextWindow)> {
+        self.session_contexts
+            .iter()
+            .map(|(session_id, context)| (session_id.as_str(), *context))
+    }
+
+    fn record_turn(&mut self, usage: &Value) {
+        let count = |key: &str| usage.get(key).and_then(Value::as_u64).unwrap_or(0);
+        let input = count("inputTokens");
+        let output = count("outputTokens");
+        self.input_tokens += input;
+        self.output_tokens += output;
+        self.cached_read_tokens += count("cachedReadTokens");
+        self.cached_write_tokens += count("cachedWriteTokens");
+        self.thought_tokens += count("thoughtTokens");
+        self.total_tokens += usage
+            .get("totalTokens")
+            .and_then(Value::as_u64)
+            .unwrap_or(input + output);
+        self.turns += 1;
+    }
+}
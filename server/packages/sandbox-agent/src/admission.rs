@@ -0,0 +1,252 @@
+//! Admission control for ACP servers and `session/prompt` turns.
+//!
+//! Caps come from `SANDBOX_AGENT_ACP_MAX_SERVERS` and `SANDBOX_AGENT_ACP_MAX_TURNS`
+//! (daemon-wide), and `SANDBOX_AGENT_ACP_MAX_SERVERS_PER_AGENT` and
+//! `SANDBOX_AGENT_ACP_MAX_TURNS_PER_AGENT` (`claude=4,codex=2`). A server holds its slot from
+//! before its agent process is spawned until the server is removed; a turn holds one while its
+//! `session/prompt` POST is open. Requests over a cap fail with `429` and `Retry-After` instead
+//! of starting more agent processes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_api_types::{AcpOccupancy, AgentOccupancy};
+use sandbox_agent_error::SandboxError;
+
+const MAX_SERVERS_ENV: &str = "SANDBOX_AGENT_ACP_MAX_SERVERS";
+const MAX_SERVERS_PER_AGENT_ENV: &str = "SANDBOX_AGENT_ACP_MAX_SERVERS_PER_AGENT";
+const MAX_TURNS_ENV: &str = "SANDBOX_AGENT_ACP_MAX_TURNS";
+const MAX_TURNS_PER_AGENT_ENV: &str = "SANDBOX_AGENT_ACP_MAX_TURNS_PER_AGENT";
+/// Sent as `Retry-After` when a cap is reached; slots free up as turns end or servers go.
+const RETRY_AFTER_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SlotKind {
+    Server,
+    Turn,
+}
+
+impl SlotKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Server => "ACP servers",
+            Self::Turn => "running turns",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Limits {
+    global: Option<usize>,
+    per_agent: HashMap<AgentId, usize>,
+}
+
+impl Limits {
+    fn from_env(global: &str, per_agent: &str) -> Self {
+        Self {
+            global: std::env::var(global)
+                .ok()
+                .and_then(|raw| raw.trim().parse::<usize>().ok())
+                .filter(|limit| *limit > 0),
+            per_agent: std::env::var(per_agent)
+                .map(|raw| parse_agent_limits(&raw))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Admission {
+    servers: Limits,
+    turns: Limits,
+    occupied: Mutex<HashMap<(SlotKind, AgentId), usize>>,
+}
+
+/// An admitted server or turn; dropping it frees the slot.
+#[derive(Debug)]
+pub(crate) struct AdmissionSlot {
+    admission: Arc<Admission>,
+    kind: SlotKind,
+    agent: AgentId,
+}
+
+impl Admission {
+    pub(crate) fn from_env() -> Self {
+        Self {
+            servers: Limits::from_env(MAX_SERVERS_ENV, MAX_SERVERS_PER_AGENT_ENV),
+            turns: Limits::from_env(MAX_TURNS_ENV, MAX_TURNS_PER_AGENT_ENV),
+            occupied: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn admit_server(
+        self: &Arc<Self>,
+        agent: AgentId,
+    ) -> Result<AdmissionSlot, SandboxError> {
+        self.admit(SlotKind::Server, agent)
+    }
+
+    pub(crate) fn admit_turn(
+        self: &Arc<Self>,
+        agent: AgentId,
+    ) -> Result<AdmissionSlot, SandboxError> {
+        self.admit(SlotKind::Turn, agent)
+    }
+
+    fn admit(
+        self: &Arc<Self>,
+        kind: SlotKind,
+        agent: AgentId,
+    ) -> Result<AdmissionSlot, SandboxError> {
+        let limits = self.limits(kind);
+        let mut occupied = self.occupied.lock().expect("admission poisoned");
+        let total = occupied
+            .iter()
+            .filter(|((slot_kind, _), _)| *slot_kind == kind)
+            .map(|(_, count)| *count)
+            .sum::<usize>();
+        let for_agent = occupied.get(&(kind, agent)).copied().unwrap_or(0);
+        let exceeded = match (limits.global, limits.per_agent.get(&agent)) {
+            (Some(limit), _) if total >= limit => Some(format!("{limit} {}", kind.as_str())),
+            (_, Some(limit)) if for_agent >= *limit => {
+                Some(format!("{limit} {} for {}", kind.as_str(), agent.as_str()))
+            }
+            _ => None,
+        };
+        if let Some(limit) = exceeded {
+            return Err(SandboxError::CapacityExceeded {
+                message: format!("limit of {limit} reached"),
+                retry_after_secs: RETRY_AFTER_SECS,
+            });
+        }
+        *occupied.entry((kind, agent)).or_default() += 1;
+        Ok(AdmissionSlot {
+            admission: self.clone(),
+            kind,
+            agent,
+        })
+    }
+
+    fn limits(&self, kind: SlotKind) -> &Limits {
+        match kind {
+            SlotKind::Server => &self.servers,
+            SlotKind::Turn => &self.turns,
+        }
+    }
+
+    /// Current servers and turns against their caps, with an entry for every agent that has
+    /// either or a cap of its own.
+    pub(crate) fn occupancy(&self) -> AcpOccupancy {
+        let occupied = self.occupied.lock().expect("admission poisoned");
+        let count =
+            |kind: SlotKind, agent: AgentId| occupied.get(&(kind, agent)).copied().unwrap_or(0);
+        let agents = occupied
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|((_, agent), _)| *agent)
+            .chain(self.servers.per_agent.keys().copied())
+            .chain(self.turns.per_agent.keys().copied())
+            .map(|agent| (agent.as_str(), agent))
+            .collect::<BTreeMap<_, _>>();
+        let agents = agents
+            .into_values()
+            .map(|agent| AgentOccupancy {
+                agent: agent.as_str().to_string(),
+                servers: count(SlotKind::Server, agent),
+                turns: count(SlotKind::Turn, agent),
+                max_servers: self.servers.per_agent.get(&agent).copied(),
+                max_turns: self.turns.per_agent.get(&agent).copied(),
+            })
+            .collect::<Vec<_>>();
+        AcpOccupancy {
+            servers: agents.iter().map(|agent| agent.servers).sum(),
+            turns: agents.iter().map(|agent| agent.turns).sum(),
+            max_servers: self.servers.global,
+            max_turns: self.turns.global,
+            agents,
+        }
+    }
+}
+
+impl Drop for AdmissionSlot {
+    fn drop(&mut self) {
+        let mut occupied = self.admission.occupied.lock().expect("admission poisoned");
+        if let Some(count) = occupied.get_mut(&(self.kind, self.agent)) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+fn parse_agent_limits(raw: &str) -> HashMap<AgentId, usize> {
+    let mut limits = HashMap::new();
+    for entry in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let Some((agent, limit)) = entry.split_once('=') else {
+            tracing::warn!(entry = entry, "admission: ignoring entry without a limit");
+            continue;
+        };
+        let Some(agent) = AgentId::parse(agent.trim()) else {
+            tracing::warn!(entry = entry, "admission: ignoring unknown agent");
+            continue;
+        };
+        match limit.trim().parse::<usize>() {
+            Ok(limit) if limit > 0 => {
+                limits.insert(agent, limit);
+            }
+            _ => tracing::warn!(entry = entry, "admission: ignoring invalid limit"),
+        }
+    }
+    limits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_capped_globally_and_per_agent() {
+        let admission = Arc::new(Admission {
+            servers: Limits {
+                global: Some(3),
+                per_agent: parse_agent_limits("claude=2,nope=1,codex=0"),
+            },
+            ..Admission::default()
+        });
+        let first = admission.admit_server(AgentId::Claude).expect("first");
+        let _second = admission.admit_server(AgentId::Claude).expect("second");
+        let err = admission
+            .admit_server(AgentId::Claude)
+            .expect_err("over the agent cap");
+        assert_eq!(err.retry_after_secs(), Some(RETRY_AFTER_SECS));
+        let _codex = admission.admit_server(AgentId::Codex).expect("codex");
+        assert!(admission.admit_server(AgentId::Mock).is_err());
+
+        drop(first);
+        let _turn = admission
+            .admit_turn(AgentId::Mock)
+            .expect("turns are uncapped");
+        let _third = admission.admit_server(AgentId::Claude).expect("slot freed");
+
+        let occupancy = admission.occupancy();
+        assert_eq!(occupancy.servers, 3);
+        assert_eq!(occupancy.turns, 1);
+        assert_eq!(occupancy.max_servers, Some(3));
+        let agents = occupancy
+            .agents
+            .iter()
+            .map(|agent| (agent.agent.as_str(), agent.servers, agent.max_servers))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            agents,
+            [
+                ("claude", 2, Some(2)),
+                ("codex", 1, None),
+                ("mock", 0, None)
+            ]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+//! Capabilities of the installed agents for `GET /v1/agents`.
+//!
+//! The table compiled into the daemon describes the agent versions it was written against.
+//! Probes adjust it to the versions actually installed:
+//!
+//! - Every ACP agent: the `agentCapabilities` of its `initialize` response, recorded whenever a
+//!   client initializes one. `promptCapabilities.image` sets `images` and
+//!   `promptCapabilities.embeddedContext` sets `fileAttachments`.
+//! - Claude: `claude --help`. `planMode` is set when `--permission-mode` offers `plan`.
+//! - OpenCode: the managed OpenCode server's OpenAPI document at `GET /doc`. `permissions` and
+//!   `questions` are set when it serves `/permission` and `/question` routes.
+//!
+//! `POST /v1/agents/{agent}/capabilities/refresh` runs the CLI and server probes. Results are
+//! kept per agent until it is installed or uninstalled through the API, and are ignored once
+//! the agent reports a different version than the one probed.
+//!
+//! `SANDBOX_AGENT_AGENT_CAPABILITIES_FILE` names a JSON file of overrides applied last, read on
+//! every request so it can be edited while the daemon runs:
+//!
+//! ```json
+//! {"claude": {"planMode": true}, "codex": {"images": false}}
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use sandbox_agent_agent_management::agents::{AgentId, AgentManager};
+use sandbox_agent_api_types::{AgentCapabilities, AgentCapabilitiesSource};
+use sandbox_agent_error::SandboxError;
+use sandbox_agent_opencode_server_manager::OpenCodeServerManager;
+use serde_json::{Map, Value};
+
+use crate::router::now_ms;
+
+pub const CAPABILITIES_FILE_ENV: &str = "SANDBOX_AGENT_AGENT_CAPABILITIES_FILE";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Default)]
+struct Entry {
+    /// Probed values by `AgentCapabilities` field name, in camelCase.
+    fields: BTreeMap<String, bool>,
+    /// Version the CLI probe ran against; `None` when only `initialize` was observed.
+    version: Option<String>,
+    probed_at_ms: i64,
+}
+
+/// Capabilities of one agent after probes and overrides.
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedCapabilities {
+    pub(crate) capabilities: AgentCapabilities,
+    pub(crate) source: AgentCapabilitiesSource,
+    pub(crate) probed_at_ms: Option<i64>,
+    /// Fields set by the override file.
+    pub(crate) overrides: Vec<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct AgentCapabilityProbe {
+    entries: Mutex<HashMap<AgentId, Entry>>,
+    overrides_path: Option<PathBuf>,
+    client: reqwest::Client,
+}
+
+impl AgentCapabilityProbe {
+    pub(crate) fn from_env() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            overrides_path: std::env::var_os(CAPABILITIES_FILE_ENV)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Apply probed values and overrides for `agent` to its `builtin` capabilities. `version`
+    /// is the agent's current version, when known.
+    pub(crate) fn resolve(
+        &self,
+        agent: AgentId,
+        builtin: AgentCapabilities,
+        version: Option<&str>,
+    ) -> ResolvedCapabilities {
+        let Ok(Value::Object(mut fields)) = serde_json::to_value(&builtin) else {
+            return ResolvedCapabilities {
+                capabilities: builtin,
+                source: AgentCapabilitiesSource::Builtin,
+                probed_at_ms: None,
+                overrides: Vec::new(),
+            };
+        };
+        let entry = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(&agent)
+            .cloned()
+            .filter(|entry| match (&entry.version, version) {
+                (Some(probed), Some(current)) => probed == current,
+                _ => true,
+            });
+        let mut source = AgentCapabilitiesSource::Builtin;
+        let mut probed_at_ms = None;
+        if let Some(entry) = entry {
+            set_fields(&mut fields, &entry.fields);
+            source = AgentCapabilitiesSource::Probed;
+            probed_at_ms = Some(entry.probed_at_ms);
+        }
+        let overrides = set_fields(&mut fields, &self.overrides(agent));
+        let capabilities = serde_json::from_value(Value::Object(fields)).unwrap_or(builtin);
+        ResolvedCapabilities {
+            capabilities,
+            source,
+            probed_at_ms,
+            overrides,
+        }
+    }
+
+    /// Record the capabilities an agent reported in its `initialize` response.
+    pub(crate) fn record_initialize(&self, agent: AgentId, response: &Value) {
+        let Some(reported) = response.pointer("/result/agentCapabilities") else {
+            return;
+        };
+        let prompt = reported.get("promptCapabilities");
+        let flag = |name: &str| {
+            prompt
+                .and_then(|prompt| prompt.get(name))
+                .and_then(Value::as_bool)
+                .unwrap_or(false)
+        };
+        self.merge(
+            agent,
+            None,
+            [
+                ("images", flag("image")),
+                ("fileAttachments", flag("embeddedContext")),
+            ],
+        );
+    }
+
+    /// Run the CLI and server probes for `agent`. `Ok(false)` when it has none, or when the
+    /// OpenCode server is not running and `start_opencode` is false.
+    pub(crate) async fn refresh(
+        &self,
+        agent: AgentId,
+        manager: Arc<AgentManager>,
+        opencode: &OpenCodeServerManager,
+        start_opencode: bool,
+    ) -> Result<bool, SandboxError> {
+        match agent {
+            AgentId::Claude => {
+                let probed = tokio::task::spawn_blocking(move || {
+                    let path = manager.resolve_binary(agent).ok()?;
+                    let help = Command::new(&path).arg("--help").output().ok()?;
+                    let help = String::from_utf8_lossy(&help.stdout).into_owned();
+                    let version = manager.version(agent).ok().flatten();
+                    Some((help, version))
+                })
+                .await
+                .ok()
+                .flatten();
+                let Some((help, version)) = probed else {
+                    return Ok(false);
+                };
+                self.merge(agent, version, [("planMode", offers_plan_mode(&help))]);
+                Ok(true)
+            }
+            AgentId::Opencode => {
+                if !start_opencode && !opencode.status().await.running {
+                    return Ok(false);
+                }
+                let base_url =
+                    opencode
+                        .ensure_server()
+                        .await
+                        .map_err(|err| SandboxError::StreamError {
+                            message: format!("failed to start OpenCode server: {err}"),
+                        })?;
+                let failed = |err: reqwest::Error| SandboxError::StreamError {
+                    message: format!("OpenCode server /doc request failed: {err}"),
+                };
+                let response = self
+                    .client
+                    .get(format!("{base_url}/doc"))
+                    .send()
+                    .await
+                    .map_err(failed)?;
+                if !response.status().is_success() {
+                    return Err(SandboxError::StreamError {
+                        message: format!("OpenCode server /doc returned {}", response.status()),
+                    });
+                }
+                let doc: Value = response.json().await.map_err(failed)?;
+                let version =
+                    tokio::task::spawn_blocking(move || manager.version(agent).ok().flatten())
+                        .await
+                        .ok()
+                        .flatten();
+                let paths: Vec<&str> = doc
+                    .get("paths")
+                    .and_then(Value::as_object)
+                    .map(|paths| paths.keys().map(String::as_str).collect())
+                    .unwrap_or_default();
+                let serves = |prefix: &str| {
+                    paths.iter().any(|path| {
+                        path.starts_with(prefix) || path.contains(&format!("{prefix}s/"))
+                    })
+                };
+                self.merge(
+                    agent,
+                    version,
+                    [
+                        ("permissions", serves("/permission")),
+                        ("questions", serves("/question")),
+                    ],
+                );
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Drop probed values, for example after the agent was reinstalled.
+    pub(crate) fn forget(&self, agent: AgentId) {
+        self.entries.lock().unwrap().remove(&agent);
+    }
+
+    fn merge<const N: usize>(
+        &self,
+        agent: AgentId,
+        version: Option<String>,
+        fields: [(&str, bool); N],
+    ) {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(agent).or_default();
+        if entry.version.is_some() && version.is_some() && version != entry.version {
+            // A new version invalidates what was probed before.
+            *entry = Entry {
+                version,
+                ..Entry::default()
+            };
+        } else if version.is_some() {
+            // Fields observed from `initialize` carry no version and stay valid for this one.
+            entry.version = version;
+        }
+        for (name, value) in fields {
+            entry.fields.insert(name.to_string(), value);
+        }
+        entry.probed_at_ms = now_ms();
+    }
+
+    fn overrides(&self, agent: AgentId) -> BTreeMap<String, bool> {
+        let Some(path) = &self.overrides_path else {
+            return BTreeMap::new();
+        };
+        match read_overrides(path) {
+            Ok(mut overrides) => overrides.remove(agent.as_str()).unwrap_or_default(),
+            Err(err) => {
+                tracing::warn!(path = %path.display(), error = %err, "agent capability overrides ignored");
+                BTreeMap::new()
+            }
+        }
+    }
+}
+
+fn read_overrides(path: &Path) -> Result<HashMap<String, BTreeMap<String, bool>>, String> {
+    let raw = std::fs::read(path).map_err(|err| err.to_string())?;
+    serde_json::from_slice(&raw).map_err(|err| err.to_string())
+}
+
+/// Overwrite known fields of `target` and return their names; names that are not
+/// capabilities are skipped.
+fn set_fields(target: &mut Map<String, Value>, fields: &BTreeMap<String, bool>) -> Vec<String> {
+    let mut set = Vec::new();
+    for (name, value) in fields {
+        if let Some(field) = target.get_mut(name) {
+            *field = Value::Bool(*value);
+            set.push(name.clone());
+        }
+    }
+    set
+}
+
+/// Whether `claude --help` lists `plan` among the `--permission-mode` choices.
+fn offers_plan_mode(help: &str) -> bool {
+    help.lines()
+        .skip_while(|line| !line.contains("--permission-mode"))
+        .take(4)
+        .any(|line| {
+            line.split(|c: char| !c.is_ascii_alphanumeric())
+                .any(|word| word == "plan")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn builtin() -> AgentCapabilities {
+        serde_json::from_value(json!({
+            "planMode": false, "permissions": true, "questions": false, "toolCalls": true,
+            "toolResults": true, "textMessages": true, "images": false,
+            "fileAttachments": false, "sessionLifecycle": false, "errorEvents": false,
+            "reasoning": false, "status": false, "commandExecution": false,
+            "fileChanges": false, "mcpTools": true, "streamingDeltas": true,
+            "itemStarted": false, "sharedProcess": false
+        }))
+        .expect("capabilities")
+    }
+
+    #[test]
+    fn probes_and_overrides_layer_over_builtin() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("capabilities.json");
+        std::fs::write(&path, r#"{"claude": {"images": false, "unknown": true}}"#)
+            .expect("write overrides");
+        let probe = AgentCapabilityProbe {
+            overrides_path: Some(path),
+            ..AgentCapabilityProbe::from_env()
+        };
+
+        let resolved = probe.resolve(AgentId::Claude, builtin(), None);
+        assert_eq!(resolved.source, AgentCapabilitiesSource::Builtin);
+
+        probe.record_initialize(
+            AgentId::Claude,
+            &json!({"result": {"agentCapabilities": {"promptCapabilities": {"image": true, "embeddedContext": true}}}}),
+        );
+        probe.merge(
+            AgentId::Claude,
+            Some("2.0.1".to_string()),
+            [("planMode", true)],
+        );
+        let resolved = probe.resolve(AgentId::Claude, builtin(), Some("2.0.1"));
+        assert_eq!(resolved.source, AgentCapabilitiesSource::Probed);
+        assert!(resolved.capabilities.plan_mode);
+        assert!(resolved.capabilities.file_attachments);
+        assert!(
+            !resolved.capabilities.images,
+            "override wins over the probe"
+        );
+        assert_eq!(resolved.overrides, ["images"]);
+
+        let resolved = probe.resolve(AgentId::Claude, builtin(), Some("2.1.0"));
+        assert_eq!(resolved.source, AgentCapabilitiesSource::Builtin);
+        assert!(!resolved.capabilities.plan_mode);
+    }
+
+    #[test]
+    fn plan_mode_from_claude_help() {
+        let help = "Options:\n  --permission-mode <mode>  Permission mode to use for the session (choices: \"acceptEdits\", \"bypassPermissions\", \"default\", \"plan\")\n  --model <model>  Model\n";
+        assert!(offers_plan_mode(help));
+        assert!(!offers_plan_mode(
+            "Options:\n  --permission-mode <mode>  (choices: \"default\")\n"
+        ));
+    }
+}
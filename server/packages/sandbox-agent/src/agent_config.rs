@@ -0,0 +1,461 @@
+//! Per-session agent config passed through `session/new`.
+//!
+//! Clients put it under `params._meta["sandboxagent.dev"]`:
+//! - `agentConfig`: settings in the agent's own format.
+//! - `hooks`: Claude hooks in Claude's settings format, for example
+//!
+//! ```json
+//! {"PreToolUse": [{"matcher": "Bash", "hooks": [{"type": "command", "command": "/opt/guard.sh"}]}]}
+//! ```
+//!
+//! - `variant`: a Codex reasoning effort such as `high`.
+//! - `streaming`: `tokens` or `messages`, how finely a Claude session streams its replies.
+//! - `systemPrompt` and `appendSystemPrompt`: text that replaces or extends Claude's system
+//!   prompt.
+//!
+//! On Claude servers `agentConfig` and `hooks` are validated, merged into one settings document
+//! and handed to the Claude CLI as `--settings` through the ACP adapter's
+//! `_meta.claudeCode.options.extraArgs`. The Codex, OpenCode and Amp adapters only read config
+//! when their process starts, so other agents reject both fields; Codex and OpenCode config
+//! lives at `/v1/agents/{agent}/config`.
+//!
+//! Claude sessions stream token by token unless `streaming` is `messages`: the daemon sets the
+//! Claude SDK's `includePartialMessages` through `_meta.claudeCode.options`, so text and thinking
+//! arrive as `agent_message_chunk` and `agent_thought_chunk` updates while they are generated,
+//! as they do with Codex. A client that sets `includePartialMessages` itself keeps its value.
+//!
+//! `systemPrompt` and `appendSystemPrompt` become the Claude CLI's `--system-prompt` and
+//! `--append-system-prompt`, again through `extraArgs`. Codex and OpenCode take instructions only
+//! from their config (`developer_instructions` and `instructions`), so they reject both.
+//!
+//! codex-acp offers each model once per reasoning effort as `{model}/{effort}`, so a Codex
+//! `variant` becomes a `session/set_model` sent right after the session is created.
+
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Map, Value};
+
+/// Top-level keys of Claude's `settings.json` accepted in `agentConfig`.
+const CLAUDE_SETTINGS: &[&str] = &[
+    "apiKeyHelper",
+    "cleanupPeriodDays",
+    "env",
+    "includeCoAuthoredBy",
+    "permissions",
+    "hooks",
+    "disableAllHooks",
+    "model",
+    "statusLine",
+    "outputStyle",
+    "forceLoginMethod",
+    "forceLoginOrgUUID",
+    "enableAllProjectMcpServers",
+    "enabledMcpjsonServers",
+    "disabledMcpjsonServers",
+    "alwaysThinkingEnabled",
+    "awsAuthRefresh",
+    "awsCredentialExport",
+];
+
+const HOOK_EVENTS: &[&str] = &[
+    "PreToolUse",
+    "PostToolUse",
+    "Notification",
+    "UserPromptSubmit",
+    "Stop",
+    "SubagentStop",
+    "PreCompact",
+    "SessionStart",
+    "SessionEnd",
+];
+
+/// `session/new` fields that set Claude's system prompt, and the Claude CLI flag each becomes.
+const SYSTEM_PROMPT_FLAGS: &[(&str, &str)] = &[
+    ("systemPrompt", "system-prompt"),
+    ("appendSystemPrompt", "append-system-prompt"),
+];
+
+/// Reasoning efforts a Codex model can offer.
+const CODEX_EFFORTS: &[&str] = &["none", "minimal", "low", "medium", "high", "xhigh"];
+
+/// Move `session/new` agent config and hooks into the agent's native mechanism.
+pub(crate) fn apply_session_config(
+    agent: AgentId,
+    mut payload: Value,
+) -> Result<Value, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/new") {
+        return Ok(payload);
+    }
+    let field = |name: &str| {
+        payload
+            .pointer(&format!("/params/_meta/sandboxagent.dev/{name}"))
+            .filter(|value| !value.is_null())
+            .cloned()
+    };
+    let agent_config = field("agentConfig");
+    let hooks = field("hooks");
+    if agent_config.is_none() && hooks.is_none() {
+        return Ok(payload);
+    }
+    if agent != AgentId::Claude {
+        return Err(unsupported(agent, agent_config.is_some()));
+    }
+
+    let mut settings = match agent_config {
+        Some(Value::Object(settings)) => settings,
+        Some(_) => return Err(invalid("agentConfig must be an object".to_string())),
+        None => Map::new(),
+    };
+    validate_claude_settings(&settings)?;
+    if let Some(hooks) = hooks {
+        if settings.contains_key("hooks") {
+            return Err(invalid(
+                "hooks cannot be set both directly and in agentConfig".to_string(),
+            ));
+        }
+        settings.insert("hooks".to_string(), hooks);
+    }
+    if let Some(hooks) = settings.get("hooks") {
+        validate_hooks(hooks)?;
+    }
+
+    let meta = payload
+        .pointer_mut("/params/_meta")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| invalid("params._meta must be an object".to_string()))?;
+    let extra_args = object_at(meta, &["claudeCode", "options", "extraArgs"])?;
+    if extra_args.contains_key("settings") {
+        return Err(invalid(
+            "agentConfig and hooks cannot be combined with claudeCode.options.extraArgs.settings"
+                .to_string(),
+        ));
+    }
+    extra_args.insert(
+        "settings".to_string(),
+        Value::String(Value::Object(settings).to_string()),
+    );
+    Ok(payload)
+}
+
+/// Apply the `streaming` granularity of a `session/new`, which only Claude servers accept, and
+/// default Claude sessions to token streaming.
+pub(crate) fn apply_session_streaming(
+    agent: AgentId,
+    mut payload: Value,
+) -> Result<Value, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/new") {
+        return Ok(payload);
+    }
+    let streaming = match payload
+        .pointer_mut("/params/_meta/sandboxagent.dev")
+        .and_then(Value::as_object_mut)
+        .and_then(|ours| ours.remove("streaming"))
+    {
+        None | Some(Value::Null) => None,
+        Some(Value::String(streaming)) => Some(streaming),
+        Some(_) => return Err(invalid("streaming must be a string".to_string())),
+    };
+    let partial = match streaming.as_deref() {
+        None | Some("tokens") => true,
+        Some("messages") => false,
+        Some(other) => {
+            return Err(invalid(format!(
+                "unknown streaming '{other}'; expected tokens or messages"
+            )))
+        }
+    };
+    if agent != AgentId::Claude {
+        return match streaming {
+            Some(_) => Err(invalid(format!(
+                "streaming is only supported on claude servers, not {}",
+                agent.as_str()
+            ))),
+            None => Ok(payload),
+        };
+    }
+    let Some(params) = payload.get_mut("params").and_then(Value::as_object_mut) else {
+        return Ok(payload);
+    };
+    let meta = params
+        .entry("_meta")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| invalid("params._meta must be an object".to_string()))?;
+    let options = object_at(meta, &["claudeCode", "options"])?;
+    if streaming.is_some() || !options.contains_key("includePartialMessages") {
+        options.insert("includePartialMessages".to_string(), Value::Bool(partial));
+    }
+    Ok(payload)
+}
+
+/// Pass the `systemPrompt` and `appendSystemPrompt` of a `session/new`, which only Claude
+/// servers apply per session, to the Claude CLI.
+pub(crate) fn apply_session_system_prompt(
+    agent: AgentId,
+    mut payload: Value,
+) -> Result<Value, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/new") {
+        return Ok(payload);
+    }
+    let Some(ours) = payload
+        .pointer_mut("/params/_meta/sandboxagent.dev")
+        .and_then(Value::as_object_mut)
+    else {
+        return Ok(payload);
+    };
+    let mut prompts = Vec::new();
+    for (field, flag) in SYSTEM_PROMPT_FLAGS {
+        match ours.remove(*field) {
+            None | Some(Value::Null) => {}
+            Some(Value::String(text)) if !text.trim().is_empty() => {
+                prompts.push((*field, *flag, text))
+            }
+            Some(_) => return Err(invalid(format!("{field} must be a non-empty string"))),
+        }
+    }
+    let Some((field, _, _)) = prompts.first() else {
+        return Ok(payload);
+    };
+    if agent != AgentId::Claude {
+        let name = agent.as_str();
+        let hint = match agent {
+            AgentId::Codex => "; set developerInstructions with /v1/agents/codex/config",
+            AgentId::Opencode => "; set instructions with /v1/agents/opencode/config",
+            _ => "",
+        };
+        return Err(invalid(format!(
+            "{field} is only supported on claude servers, not {name}{hint}"
+        )));
+    }
+
+    let meta = payload
+        .pointer_mut("/params/_meta")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| invalid("params._meta must be an object".to_string()))?;
+    if meta.contains_key("systemPrompt") {
+        return Err(invalid(
+            "systemPrompt and appendSystemPrompt cannot be combined with _meta.systemPrompt"
+                .to_string(),
+        ));
+    }
+    let extra_args = object_at(meta, &["claudeCode", "options", "extraArgs"])?;
+    for (field, flag, text) in prompts {
+        if extra_args.contains_key(flag) {
+            return Err(invalid(format!(
+                "{field} cannot be combined with claudeCode.options.extraArgs.{flag}"
+            )));
+        }
+        extra_args.insert(flag.to_string(), Value::String(text));
+    }
+    Ok(payload)
+}
+
+/// Take the reasoning `variant` off a `session/new`, which only Codex servers accept.
+pub(crate) fn take_session_variant(
+    agent: AgentId,
+    payload: &mut Value,
+) -> Result<Option<String>, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/new") {
+        return Ok(None);
+    }
+    let Some(ours) = payload
+        .pointer_mut("/params/_meta/sandboxagent.dev")
+        .and_then(Value::as_object_mut)
+    else {
+        return Ok(None);
+    };
+    let variant = match ours.remove("variant") {
+        None | Some(Value::Null) => return Ok(None),
+        Some(Value::String(variant)) => variant,
+        Some(_) => return Err(invalid("variant must be a string".to_string())),
+    };
+    validate_variant(agent, &variant)?;
+    Ok(Some(variant))
+}
+
+/// Reject a reasoning `variant` on agents other than Codex or outside Codex's efforts.
+pub(crate) fn validate_variant(agent: AgentId, variant: &str) -> Result<(), SandboxError> {
+    if agent != AgentId::Codex {
+        return Err(invalid(format!(
+            "variant is only supported on codex servers, not {}",
+            agent.as_str()
+        )));
+    }
+    if !CODEX_EFFORTS.contains(&variant) {
+        return Err(invalid(format!(
+            "unknown variant '{variant}'; expected one of {}",
+            CODEX_EFFORTS.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// The codex-acp model id for `model`, with or without an effort, at `variant`.
+pub(crate) fn codex_variant_model(model: &str, variant: &str) -> String {
+    let model = model.split_once('/').map_or(model, |(model, _)| model);
+    format!("{model}/{variant}")
+}
+
+/// The model id and `session/set_model` request that switch the session `response` created
+/// to `variant`, or why the session's model has no such variant.
+pub(crate) fn variant_request(response: &Value, variant: &str) -> Result<(String, Value), String> {
+    let session_id = response
+        .pointer("/result/sessionId")
+        .and_then(Value::as_str)
+        .ok_or("session/new returned no sessionId")?;
+    let current = response
+        .pointer("/result/models/currentModelId")
+        .and_then(Value::as_str)
+        .ok_or("session/new did not report the session's model")?;
+    let model_id = codex_variant_model(current, variant);
+    let offered = response
+        .pointer("/result/models/availableModels")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .any(|entry| entry.get("modelId").and_then(Value::as_str) == Some(model_id.as_str()));
+    if !offered {
+        let model = model_id.split_once('/').map_or(current, |(model, _)| model);
+        return Err(format!("{model} has no {variant} reasoning variant"));
+    }
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": format!("sandboxagent-variant-{session_id}"),
+        "method": "session/set_model",
+        "params": { "sessionId": session_id, "modelId": model_id },
+    });
+    Ok((model_id, request))
+}
+
+/// Report on the `session/new` response whether the variant was applied: the new current
+/// model, or `_meta["sandboxagent.dev"].variantError`.
+pub(crate) fn with_variant_outcome(mut response: Value, outcome: Result<String, String>) -> Value {
+    let Some(result) = response.get_mut("result").and_then(Value::as_object_mut) else {
+        return response;
+    };
+    match outcome {
+        Ok(model_id) => {
+            if let Some(models) = result.get_mut("models").and_then(Value::as_object_mut) {
+                models.insert("currentModelId".to_string(), Value::String(model_id));
+            }
+        }
+        Err(message) => {
+            if let Ok(ours) = object_at(result, &["_meta", "sandboxagent.dev"]) {
+                ours.insert("variantError".to_string(), Value::String(message));
+            }
+        }
+    }
+    response
+}
+
+fn unsupported(agent: AgentId, agent_config: bool) -> SandboxError {
+    let name = agent.as_str();
+    if !agent_config {
+        return invalid(format!(
+            "hooks are only supported on claude servers, not {name}"
+        ));
+    }
+    let hint = match agent {
+        AgentId::Codex | AgentId::Opencode => {
+            format!("; manage {name} config with /v1/agents/{name}/config")
+        }
+        _ => String::new(),
+    };
+    invalid(format!(
+        "agentConfig is only supported on claude servers, not {name}{hint}"
+    ))
+}
+
+fn validate_claude_settings(settings: &Map<String, Value>) -> Result<(), SandboxError> {
+    for (key, value) in settings {
+        if !CLAUDE_SETTINGS.contains(&key.as_str()) {
+            return Err(invalid(format!(
+                "agentConfig.{key} is not a supported claude setting; expected one of {}",
+                CLAUDE_SETTINGS.join(", ")
+            )));
+        }
+        let valid = match key.as_str() {
+            "env" => value
+                .as_object()
+                .is_some_and(|env| env.values().all(Value::is_string)),
+            "permissions" | "statusLine" => value.is_object(),
+            "model" | "apiKeyHelper" | "outputStyle" | "forceLoginMethod" => value.is_string(),
+            "includeCoAuthoredBy"
+            | "disableAllHooks"
+            | "enableAllProjectMcpServers"
+            | "alwaysThinkingEnabled" => value.is_boolean(),
+            "enabledMcpjsonServers" | "disabledMcpjsonServers" => value
+                .as_array()
+                .is_some_and(|servers| servers.iter().all(Value::is_string)),
+            _ => true,
+        };
+        if !valid {
+            return Err(invalid(format!("agentConfig.{key} has the wrong type")));
+        }
+    }
+    Ok(())
+}
+
+fn validate_hooks(hooks: &Value) -> Result<(), SandboxError> {
+    let events = hooks
+        .as_object()
+        .ok_or_else(|| invalid("hooks must be an object keyed by hook event".to_string()))?;
+    for (event, matchers) in events {
+        if !HOOK_EVENTS.contains(&event.as_str()) {
+            return Err(invalid(format!("unknown hook event '{event}'")));
+        }
+        let matchers = matchers
+            .as_array()
+            .ok_or_else(|| invalid(format!("hooks.{event} must be an array")))?;
+        for matcher in matchers {
+            if matcher
+                .get("matcher")
+                .is_some_and(|matcher| !matcher.is_string())
+            {
+                return Err(invalid(format!("hooks.{event}[].matcher must be a string")));
+            }
+            let commands = matcher
+                .get("hooks")
+                .and_then(Value::as_array)
+                .filter(|commands| !commands.is_empty())
+                .ok_or_else(|| {
+                    invalid(format!("hooks.{event}[].hooks must be a non-empty array"))
+                })?;
+            for command in commands {
+                let valid = command.get("type").and_then(Value::as_str) == Some("command")
+                    && command
+                        .get("command")
+                        .and_then(Value::as_str)
+                        .is_some_and(|command| !command.trim().is_empty())
+                    && command
+                        .get("timeout")
+                        .is_none_or(|timeout| timeout.as_u64().is_some_and(|secs| secs > 0));
+                if !valid {
+                    return Err(invalid(format!(
+                        "hooks.{event}[].hooks[] must be {{\"type\": \"command\", \"command\": string, \"timeout\"?: seconds}}"
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The object at `path` under `root`, creating missing levels.
+fn object_at<'a>(
+    mut root: &'a mut Map<String, Value>,
+    path: &[&str],
+) -> Result<&'a mut Map<String, Value>, SandboxError> {
+    for key in path {
+        root = root
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .ok_or_else(|| invalid(format!("_meta.{} must be an object", path.join("."))))?;
+    }
+    Ok(root)
+}
+
+fn invalid(message: String) -> SandboxError {
+    SandboxError::InvalidRequest { message }
+}
@@ -0,0 +1,572 @@
+//! Landlock and seccomp confinement for agent processes on Linux, chosen per server with
+//! `sandboxLevel` (default `SANDBOX_AGENT_ACP_SANDBOX_LEVEL`).
+//!
+//! - `none`: no confinement.
+//! - `workspace`: the agent can read anywhere but only write to its working directory, its
+//!   config directories, the temp directory and `/dev`.
+//! - `strict`: reads are limited too, to those directories plus system directories, `PATH`
+//!   and the agent install directory.
+//!
+//! Both levels also install a seccomp filter that fails syscalls able to get around the
+//! confinement, like `mount`, `ptrace` or `open_by_handle_at`, with `EPERM`. Confined agents
+//! start through `limit-exec`, which restricts itself before it execs the agent, so the
+//! confinement covers everything the agent starts. Extra writable directories can be listed
+//! in `SANDBOX_AGENT_ACP_SANDBOX_PATHS`.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sandbox_agent_agent_management::agents::AgentId;
+
+pub(crate) const SANDBOX_LEVEL_ENV: &str = "SANDBOX_AGENT_ACP_SANDBOX_LEVEL";
+pub(crate) const SANDBOX_PATHS_ENV: &str = "SANDBOX_AGENT_ACP_SANDBOX_PATHS";
+
+/// Readable at `strict` for dynamic libraries, certificates, `/proc/self` and the like.
+const SYSTEM_READ_PATHS: &[&str] = &[
+    "/bin", "/sbin", "/usr", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/nix", "/proc", "/sys",
+    "/run",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SandboxLevel {
+    #[default]
+    None,
+    Workspace,
+    Strict,
+}
+
+impl SandboxLevel {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value {
+            "none" => Some(Self::None),
+            "workspace" => Some(Self::Workspace),
+            "strict" => Some(Self::Strict),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Workspace => "workspace",
+            Self::Strict => "strict",
+        }
+    }
+
+    /// The daemon default level. An unrecognised value is an error rather than `none`, so a
+    /// typo never leaves agents unconfined.
+    pub(crate) fn from_env() -> Result<Self, String> {
+        let Some(value) = std::env::var(SANDBOX_LEVEL_ENV)
+            .ok()
+            .filter(|value| !value.is_empty())
+        else {
+            return Ok(Self::None);
+        };
+        Self::parse(value.trim()).ok_or_else(|| {
+            format!("{SANDBOX_LEVEL_ENV} must be none, workspace or strict, not {value:?}")
+        })
+    }
+}
+
+/// What `limit-exec` confines an agent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Confinement {
+    /// Limit reads to `read` and `write` as well as writes.
+    pub(crate) strict: bool,
+    pub(crate) read: Vec<PathBuf>,
+    pub(crate) write: Vec<PathBuf>,
+}
+
+impl Confinement {
+    /// The confinement for `agent` running in `working_dir`; `None` at level `none`.
+    pub(crate) fn for_agent(
+        level: SandboxLevel,
+        agent: AgentId,
+        working_dir: &Path,
+        install_dir: &Path,
+    ) -> Option<Self> {
+        if level == SandboxLevel::None {
+            return None;
+        }
+        let home = dirs::home_dir();
+        let mut write = vec![
+            working_dir.to_path_buf(),
+            std::env::temp_dir(),
+            PathBuf::from("/dev"),
+        ];
+        if let Some(home) = &home {
+            write.extend(agent_config_paths(agent, home));
+        }
+        if let Some(extra) = std::env::var_os(SANDBOX_PATHS_ENV) {
+            write.extend(std::env::split_paths(&extra).filter(|path| path.is_absolute()));
+        }
+
+        let strict = level == SandboxLevel::Strict;
+        let mut read = Vec::new();
+        if strict {
+            read.extend(SYSTEM_READ_PATHS.iter().map(PathBuf::from));
+            read.push(install_dir.to_path_buf());
+            if let Some(exe_dir) = std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(Path::to_path_buf))
+            {
+                read.push(exe_dir);
+            }
+            if let Some(path) = std::env::var_os("PATH") {
+                for dir in std::env::split_paths(&path).filter(|dir| dir.is_absolute()) {
+                    // Runtimes under `~/.nvm/versions/node/*/bin` keep their modules next to
+                    // `bin`; a `bin` directly in the home directory doesn't open up all of it.
+                    if let Some(prefix) = dir
+                        .parent()
+                        .filter(|_| dir.ends_with("bin"))
+                        .filter(|prefix| Some(*prefix) != home.as_deref())
+                    {
+                        read.push(prefix.to_path_buf());
+                    }
+                    read.push(dir);
+                }
+            }
+        }
+        read.sort();
+        read.dedup();
+        write.sort();
+        write.dedup();
+        Some(Self {
+            strict,
+            read,
+            write,
+        })
+    }
+
+    /// `limit-exec` flags that recreate this confinement.
+    pub(crate) fn args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--sandbox-level".to_string(),
+            if self.strict { "strict" } else { "workspace" }.to_string(),
+        ];
+        for path in &self.read {
+            args.push("--sandbox-read".to_string());
+            args.push(path.display().to_string());
+        }
+        for path in &self.write {
+            args.push("--sandbox-write".to_string());
+            args.push(path.display().to_string());
+        }
+        args
+    }
+}
+
+/// Where each agent keeps its settings, credentials and session state.
+fn agent_config_paths(agent: AgentId, home: &Path) -> Vec<PathBuf> {
+    let env_or = |name: &str, fallback: PathBuf| {
+        std::env::var_os(name)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or(fallback)
+    };
+    match agent {
+        AgentId::Claude => vec![
+            env_or("CLAUDE_CONFIG_DIR", home.join(".claude")),
+            home.join(".claude.json"),
+        ],
+        AgentId::Codex => vec![env_or("CODEX_HOME", home.join(".codex"))],
+        AgentId::Opencode => vec![
+            env_or("OPENCODE_CONFIG_DIR", home.join(".config/opencode")),
+            home.join(".local/share/opencode"),
+            home.join(".local/state/opencode"),
+            home.join(".cache/opencode"),
+        ],
+        AgentId::Amp => vec![
+            home.join(".config/amp"),
+            home.join(".local/share/amp"),
+            home.join(".cache/amp"),
+        ],
+        AgentId::Pi => vec![home.join(".pi")],
+        AgentId::Cursor => vec![home.join(".cursor"), home.join(".config/cursor")],
+        AgentId::Codebuff => vec![home.join(".config/manicode")],
+        AgentId::Mock => Vec::new(),
+    }
+}
+
+/// The Landlock ABI version of the running kernel, or `None` without Landlock.
+#[cfg(target_os = "linux")]
+pub(crate) fn landlock_abi() -> Option<u32> {
+    // SAFETY: with a null attribute and the version flag the kernel only returns the ABI.
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<landlock::RulesetAttr>(),
+            0_usize,
+            landlock::CREATE_RULESET_VERSION,
+        )
+    };
+    u32::try_from(abi).ok().filter(|abi| *abi > 0)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn landlock_abi() -> Option<u32> {
+    None
+}
+
+/// Restrict this process, and everything it execs, to `confinement`.
+#[cfg(target_os = "linux")]
+pub(crate) fn confine(confinement: &Confinement) -> io::Result<()> {
+    let abi = landlock_abi().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Landlock is not supported by this kernel",
+        )
+    })?;
+    landlock::restrict(confinement, abi)?;
+    seccomp::install()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn confine(_confinement: &Confinement) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "agent sandboxing requires Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+mod landlock {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::Confinement;
+
+    pub(super) const CREATE_RULESET_VERSION: u32 = 1;
+    const RULE_PATH_BENEATH: u32 = 1;
+
+    const EXECUTE: u64 = 1 << 0;
+    const WRITE_FILE: u64 = 1 << 1;
+    const READ_FILE: u64 = 1 << 2;
+    const READ_DIR: u64 = 1 << 3;
+    const REMOVE_DIR: u64 = 1 << 4;
+    const REMOVE_FILE: u64 = 1 << 5;
+    const MAKE_CHAR: u64 = 1 << 6;
+    const MAKE_DIR: u64 = 1 << 7;
+    const MAKE_REG: u64 = 1 << 8;
+    const MAKE_SOCK: u64 = 1 << 9;
+    const MAKE_FIFO: u64 = 1 << 10;
+    const MAKE_BLOCK: u64 = 1 << 11;
+    const MAKE_SYM: u64 = 1 << 12;
+    /// ABI 2.
+    const REFER: u64 = 1 << 13;
+    /// ABI 3.
+    const TRUNCATE: u64 = 1 << 14;
+
+    const READ: u64 = EXECUTE | READ_FILE | READ_DIR;
+    /// Rights that apply to a file rather than a directory's entries.
+    const FILE_RIGHTS: u64 = EXECUTE | WRITE_FILE | READ_FILE | TRUNCATE;
+
+    #[repr(C)]
+    pub(super) struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    fn write_rights(abi: u32) -> u64 {
+        let mut rights = WRITE_FILE
+            | REMOVE_DIR
+            | REMOVE_FILE
+            | MAKE_CHAR
+            | MAKE_DIR
+            | MAKE_REG
+            | MAKE_SOCK
+            | MAKE_FIFO
+            | MAKE_BLOCK
+            | MAKE_SYM;
+        if abi >= 2 {
+            rights |= REFER;
+        }
+        if abi >= 3 {
+            rights |= TRUNCATE;
+        }
+        rights
+    }
+
+    pub(super) fn restrict(confinement: &Confinement, abi: u32) -> io::Result<()> {
+        let write = write_rights(abi);
+        let handled = if confinement.strict {
+            READ | write
+        } else {
+            write
+        };
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        // SAFETY: `attr` outlives the call and its size is passed along.
+        let ruleset = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0_u32,
+            )
+        };
+        if ruleset < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ruleset = ruleset as libc::c_int;
+        let result = add_rules(ruleset, confinement, handled, write).and_then(|()| {
+            // SAFETY: plain prctl and syscall calls on a ruleset fd this function owns.
+            unsafe {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                    || libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0_u32) != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+        // SAFETY: `ruleset` is an fd this function opened.
+        unsafe { libc::close(ruleset) };
+        result
+    }
+
+    fn add_rules(
+        ruleset: libc::c_int,
+        confinement: &Confinement,
+        handled: u64,
+        write: u64,
+    ) -> io::Result<()> {
+        let rules = confinement
+            .read
+            .iter()
+            .map(|path| (path, READ))
+            .chain(confinement.write.iter().map(|path| (path, READ | write)));
+        for (path, access) in rules {
+            add_rule(ruleset, path, access & handled)?;
+        }
+        Ok(())
+    }
+
+    /// Allow `access` beneath `path`. Paths that don't exist are skipped.
+    fn add_rule(ruleset: libc::c_int, path: &Path, access: u64) -> io::Result<()> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        // SAFETY: `c_path` is a valid NUL-terminated string.
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => Ok(()),
+                _ => Err(err),
+            };
+        }
+        let allowed_access = if path.is_dir() {
+            access
+        } else {
+            access & FILE_RIGHTS
+        };
+        if allowed_access == 0 {
+            // SAFETY: `fd` was opened above.
+            unsafe { libc::close(fd) };
+            return Ok(());
+        }
+        let attr = PathBeneathAttr {
+            allowed_access,
+            parent_fd: fd,
+        };
+        // SAFETY: `attr` outlives the call; `fd` is open until closed below.
+        let added = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset,
+                RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0_u32,
+            )
+        };
+        let result = if added == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        };
+        // SAFETY: `fd` was opened above.
+        unsafe { libc::close(fd) };
+        result
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod seccomp {
+    use std::io;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    /// Syscalls that could undo or sidestep the Landlock rules, or reach into other processes.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const DENIED: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_name_to_handle_at,
+        libc::SYS_fsopen,
+        libc::SYS_fsconfig,
+        libc::SYS_fsmount,
+        libc::SYS_fspick,
+        libc::SYS_move_mount,
+        libc::SYS_open_tree,
+        libc::SYS_setns,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_reboot,
+        libc::SYS_acct,
+        libc::SYS_quotactl,
+    ];
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    pub(super) fn install() -> io::Result<()> {
+        let mut filter = filter();
+        let program = libc::sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_mut_ptr(),
+        };
+        // SAFETY: `program` points at `filter`, which outlives the call; no_new_privs was set
+        // before the Landlock ruleset was applied.
+        let installed = unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &program as *const libc::sock_fprog,
+            )
+        };
+        if installed != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Landlock already confines the filesystem on other architectures; they get no filter.
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub(super) fn install() -> io::Result<()> {
+        Ok(())
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn filter() -> Vec<libc::sock_filter> {
+        let statement = |code: u32, k: u32| libc::sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let jump = |k: u32, jt: u8, jf: u8| libc::sock_filter {
+            code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+            jt,
+            jf,
+            k,
+        };
+        let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+        let ret = libc::BPF_RET | libc::BPF_K;
+        // Offsets into `struct seccomp_data`.
+        let (nr_offset, arch_offset) = (0, 4);
+
+        let mut filter = vec![
+            statement(load, arch_offset),
+            jump(AUDIT_ARCH, 1, 0),
+            statement(ret, libc::SECCOMP_RET_KILL_PROCESS),
+            statement(load, nr_offset),
+        ];
+        // x32 syscalls share the x86_64 arch value but carry this bit in their number.
+        #[cfg(target_arch = "x86_64")]
+        filter.push(libc::sock_filter {
+            code: (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16,
+            jt: DENIED.len() as u8 + 1,
+            jf: 0,
+            k: 0x4000_0000,
+        });
+        for (index, nr) in DENIED.iter().enumerate() {
+            filter.push(jump(*nr as u32, (DENIED.len() - index) as u8, 0));
+        }
+        filter.push(statement(ret, libc::SECCOMP_RET_ALLOW));
+        filter.push(statement(ret, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_round_trip_and_none_is_unconfined() {
+        for level in [
+            SandboxLevel::None,
+            SandboxLevel::Workspace,
+            SandboxLevel::Strict,
+        ] {
+            assert_eq!(SandboxLevel::parse(level.as_str()), Some(level));
+        }
+        assert_eq!(SandboxLevel::parse("paranoid"), None);
+        assert!(Confinement::for_agent(
+            SandboxLevel::None,
+            AgentId::Codex,
+            Path::new("/work"),
+            Path::new("/agents"),
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn workspace_only_limits_writes_and_strict_adds_reads() {
+        let workspace = Confinement::for_agent(
+            SandboxLevel::Workspace,
+            AgentId::Mock,
+            Path::new("/work"),
+            Path::new("/agents"),
+        )
+        .expect("confinement");
+        assert!(!workspace.strict);
+        assert!(workspace.read.is_empty());
+        assert!(workspace.write.contains(&PathBuf::from("/work")));
+        assert!(workspace.write.contains(&PathBuf::from("/dev")));
+
+        let strict = Confinement::for_agent(
+            SandboxLevel::Strict,
+            AgentId::Mock,
+            Path::new("/work"),
+            Path::new("/agents"),
+        )
+        .expect("confinement");
+        assert!(strict.strict);
+        assert!(strict.read.contains(&PathBuf::from("/agents")));
+        assert!(strict.read.contains(&PathBuf::from("/usr")));
+        assert_eq!(strict.write, workspace.write);
+
+        let args = strict.args();
+        assert_eq!(args[..2], ["--sandbox-level", "strict"]);
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["--sandbox-write", "/work"]));
+    }
+}
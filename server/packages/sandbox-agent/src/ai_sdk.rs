@@ -0,0 +1,320 @@
+//! Vercel AI SDK streams for `useChat`.
+//!
+//! `POST /v1/acp/{serverId}/sessions/{sessionId}/messages/ai-sdk` prompts an existing session
+//! with the last user message of a `useChat` request and answers with the turn as an AI SDK UI
+//! message stream: SSE `data:` chunks such as `text-delta` and `tool-input-available`, ending
+//! with `[DONE]`. Chunks are translated from the session's `session/update` notifications, so
+//! Next.js frontends need no translation proxy.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures::{stream, Stream, StreamExt};
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::acp_proxy_runtime::AcpProxyRuntime;
+use crate::acp_runs::{follow_prompt, TurnEvent};
+use crate::prompt_attachments::url_attachment;
+
+/// Response header `useChat` expects on a UI message stream.
+pub(crate) const UI_MESSAGE_STREAM_HEADER: &str = "x-vercel-ai-ui-message-stream";
+static MESSAGE_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+/// ACP prompt content for the last user message of a `useChat` request. Text parts become text
+/// blocks and file parts resource links, with `data:` URLs sent as inline attachments.
+pub(crate) fn prompt_from_messages(messages: &[Value]) -> Result<Vec<Value>, SandboxError> {
+    let message = messages
+        .iter()
+        .rev()
+        .find(|message| message.get("role").and_then(Value::as_str) == Some("user"))
+        .ok_or_else(|| invalid("messages has no user message"))?;
+    let mut prompt = Vec::new();
+    if let Some(text) = message.get("content").and_then(Value::as_str) {
+        prompt.push(json!({ "type": "text", "text": text }));
+    }
+    let parts = message.get("parts").and_then(Value::as_array);
+    for part in parts.into_iter().flatten() {
+        match part.get("type").and_then(Value::as_str) {
+            Some("text") => {
+                if let Some(text) = part.get("text").and_then(Value::as_str) {
+                    prompt.push(json!({ "type": "text", "text": text }));
+                }
+            }
+            Some("file") => {
+                let Some(url) = part.get("url").and_then(Value::as_str) else {
+                    continue;
+                };
+                prompt.push(url_attachment(
+                    url,
+                    part.get("filename").and_then(Value::as_str),
+                    part.get("mediaType").and_then(Value::as_str),
+                ));
+            }
+            _ => {}
+        }
+    }
+    if prompt.is_empty() {
+        return Err(invalid("the last user message has no text or file parts"));
+    }
+    Ok(prompt)
+}
+
+/// Send `prompt` to the session and stream the turn as UI message chunks. The stream ends after
+/// `finish` or `error`; when the client goes away first, the turn is cancelled.
+pub(crate) async fn prompt_stream(
+    runtime: Arc<AcpProxyRuntime>,
+    server_id: String,
+    session_id: String,
+    prompt: Vec<Value>,
+) -> Result<impl Stream<Item = Value> + Send + 'static, SandboxError> {
+    let n = MESSAGE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut chunks = UiMessageStream::new(format!("{session_id}-{n}"));
+    let start = chunks.start();
+    let params = json!({ "sessionId": session_id, "prompt": prompt });
+    let turn = follow_prompt(runtime, server_id, session_id, params).await?;
+    let turn = ReceiverStream::new(turn).flat_map(move |event| stream::iter(chunks.turn(event)));
+    Ok(stream::iter(start).chain(turn))
+}
+
+/// Turns `session/update`s into UI message chunks, opening and closing text and reasoning
+/// parts as the agent switches between them. Tool calls are reported as dynamic tools, since
+/// the client does not define them.
+#[derive(Debug)]
+pub(crate) struct UiMessageStream {
+    message_id: String,
+    /// Kind (`text` or `reasoning`) and id of the part receiving deltas.
+    open: Option<(&'static str, String)>,
+    parts: u64,
+    tools: HashSet<String>,
+    finished_tools: HashSet<String>,
+}
+
+impl UiMessageStream {
+    pub(crate) fn new(message_id: String) -> Self {
+        Self {
+            message_id,
+            open: None,
+            parts: 0,
+            tools: HashSet::new(),
+            finished_tools: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn start(&self) -> Vec<Value> {
+        vec![
+            json!({ "type": "start", "messageId": self.message_id }),
+            json!({ "type": "start-step" }),
+        ]
+    }
+
+    pub(crate) fn update(&mut self, update: &Value) -> Vec<Value> {
+        let mut chunks = Vec::new();
+        match update.get("sessionUpdate").and_then(Value::as_str) {
+            Some("agent_message_chunk") => self.delta("text", update, &mut chunks),
+            Some("agent_thought_chunk") => self.delta("reasoning", update, &mut chunks),
+            Some("tool_call" | "tool_call_update") => self.tool(update, &mut chunks),
+            _ => {}
+        }
+        chunks
+    }
+
+    pub(crate) fn turn(&mut self, event: TurnEvent) -> Vec<Value> {
+        match event {
+            TurnEvent::Update(update) => self.update(&update),
+            TurnEvent::Ended(Ok(response)) => match response.get("error") {
+                Some(error) => self.error(
+                    error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or("session/prompt failed"),
+                ),
+                None => self.finish(
+                    response
+                        .pointer("/result/stopReason")
+                        .and_then(Value::as_str),
+                ),
+            },
+            TurnEvent::Ended(Err(err)) => self.error(&err.to_string()),
+        }
+    }
+
+    pub(crate) fn finish(&mut self, stop_reason: Option<&str>) -> Vec<Value> {
+        let mut chunks = Vec::new();
+        self.close(&mut chunks);
+        chunks.push(json!({ "type": "finish-step" }));
+        chunks.push(match stop_reason {
+            Some(stop_reason) => {
+                json!({ "type": "finish", "messageMetadata": { "stopReason": stop_reason } })
+            }
+            None => json!({ "type": "finish" }),
+        });
+        chunks
+    }
+
+    pub(crate) fn error(&mut self, message: &str) -> Vec<Value> {
+        let mut chunks = Vec::new();
+        self.close(&mut chunks);
+        chunks.push(json!({ "type": "error", "errorText": message }));
+        chunks
+    }
+
+    fn delta(&mut self, kind: &'static str, update: &Value, chunks: &mut Vec<Value>) {
+        let content = update.get("content");
+        if content.and_then(|content| content.get("type")?.as_str()) != Some("text") {
+            return;
+        }
+        let Some(text) = content.and_then(|content| content.get("text")?.as_str()) else {
+            return;
+        };
+        if self.open.as_ref().map(|(open, _)| *open) != Some(kind) {
+            self.close(chunks);
+            self.parts += 1;
+            let id = format!("{}-{}", self.message_id, self.parts);
+            chunks.push(json!({ "type": format!("{kind}-start"), "id": id }));
+            self.open = Some((kind, id));
+        }
+        if let Some((_, id)) = &self.open {
+            chunks.push(json!({ "type": format!("{kind}-delta"), "id": id, "delta": text }));
+        }
+    }
+
+    fn tool(&mut self, update: &Value, chunks: &mut Vec<Value>) {
+        let Some(tool_call_id) = update.get("toolCallId").and_then(Value::as_str) else {
+            return;
+        };
+        self.close(chunks);
+        if self.tools.insert(tool_call_id.to_string()) {
+            let name = update
+                .get("title")
+                .or_else(|| update.get("kind"))
+                .and_then(Value::as_str)
+                .unwrap_or("tool");
+            chunks.push(json!({
+                "type": "tool-input-available",
+                "toolCallId": tool_call_id,
+                "toolName": name,
+                "input": update.get("rawInput").cloned().unwrap_or_else(|| json!({})),
+                "dynamic": true,
+            }));
+        }
+        let status = update.get("status").and_then(Value::as_str);
+        if !matches!(status, Some("completed" | "failed"))
+            || !self.finished_tools.insert(tool_call_id.to_string())
+        {
+            return;
+        }
+        let text = tool_text(update);
+        chunks.push(if status == Some("completed") {
+            json!({
+                "type": "tool-output-available",
+                "toolCallId": tool_call_id,
+                "output": update.get("rawOutput").cloned().unwrap_or(Value::String(text)),
+                "dynamic": true,
+            })
+        } else {
+            json!({
+                "type": "tool-output-error",
+                "toolCallId": tool_call_id,
+                "errorText": if text.is_empty() { "tool call failed".to_string() } else { text },
+                "dynamic": true,
+            })
+        });
+    }
+
+    fn close(&mut self, chunks: &mut Vec<Value>) {
+        if let Some((kind, id)) = self.open.take() {
+            chunks.push(json!({ "type": format!("{kind}-end"), "id": id }));
+        }
+    }
+}
+
+/// Text of a tool call's `content` blocks.
+fn tool_text(update: &Value) -> String {
+    let blocks = update.get("content").and_then(Value::as_array);
+    blocks
+        .into_iter()
+        .flatten()
+        .filter_map(|block| block.pointer("/content/text").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn invalid(message: &str) -> SandboxError {
+    SandboxError::InvalidRequest {
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_takes_the_last_user_message() {
+        let messages = vec![
+            json!({ "role": "user", "parts": [{ "type": "text", "text": "first" }] }),
+            json!({ "role": "assistant", "parts": [{ "type": "text", "text": "reply" }] }),
+            json!({ "role": "user", "parts": [
+                { "type": "text", "text": "look at this" },
+                { "type": "file", "mediaType": "image/png", "filename": "shot.png", "url": "data:image/png;base64,iVBORw0KGgo=" },
+                { "type": "step-start" }
+            ] }),
+        ];
+        assert_eq!(
+            prompt_from_messages(&messages).unwrap(),
+            vec![
+                json!({ "type": "text", "text": "look at this" }),
+                json!({
+                    "type": "resource_link",
+                    "name": "shot.png",
+                    "mimeType": "image/png",
+                    "_meta": { "sandboxagent.dev": { "data": "data:image/png;base64,iVBORw0KGgo=" } }
+                }),
+            ]
+        );
+        assert_eq!(
+            prompt_from_messages(&[json!({ "role": "user", "content": "hi" })]).unwrap(),
+            vec![json!({ "type": "text", "text": "hi" })]
+        );
+        assert!(prompt_from_messages(&[json!({ "role": "assistant", "content": "hi" })]).is_err());
+    }
+
+    #[test]
+    fn updates_become_ui_message_chunks() {
+        let mut stream = UiMessageStream::new("m".to_string());
+        let text = |text: &str| json!({ "sessionUpdate": "agent_message_chunk", "content": { "type": "text", "text": text } });
+        let thought = json!({ "sessionUpdate": "agent_thought_chunk", "content": { "type": "text", "text": "hmm" } });
+        let mut chunks = Vec::new();
+        for update in [
+            thought,
+            text("Hel"),
+            text("lo"),
+            json!({ "sessionUpdate": "tool_call", "toolCallId": "t1", "title": "Read file", "rawInput": { "path": "a" }, "status": "pending" }),
+            json!({ "sessionUpdate": "tool_call_update", "toolCallId": "t1", "status": "completed",
+                    "content": [{ "type": "content", "content": { "type": "text", "text": "contents" } }] }),
+            json!({ "sessionUpdate": "plan", "entries": [] }),
+        ] {
+            chunks.extend(stream.update(&update));
+        }
+        chunks.extend(stream.finish(Some("end_turn")));
+        assert_eq!(
+            chunks,
+            vec![
+                json!({ "type": "reasoning-start", "id": "m-1" }),
+                json!({ "type": "reasoning-delta", "id": "m-1", "delta": "hmm" }),
+                json!({ "type": "reasoning-end", "id": "m-1" }),
+                json!({ "type": "text-start", "id": "m-2" }),
+                json!({ "type": "text-delta", "id": "m-2", "delta": "Hel" }),
+                json!({ "type": "text-delta", "id": "m-2", "delta": "lo" }),
+                json!({ "type": "text-end", "id": "m-2" }),
+                json!({ "type": "tool-input-available", "toolCallId": "t1", "toolName": "Read file", "input": { "path": "a" }, "dynamic": true }),
+                json!({ "type": "tool-output-available", "toolCallId": "t1", "output": "contents", "dynamic": true }),
+                json!({ "type": "finish-step" }),
+                json!({ "type": "finish", "messageMetadata": { "stopReason": "end_turn" } }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Routing of permission requests to an external approver, such as a Slack or Jira workflow.
+//!
+//! With `SANDBOX_AGENT_APPROVAL_ROUTE_URL` and `SANDBOX_AGENT_APPROVAL_ROUTE_SECRET` set, a
+//! `session/request_permission` whose tool call kind is listed in
+//! `SANDBOX_AGENT_APPROVAL_ROUTE_ACTIONS` (comma separated, `*` for every kind; `execute` and
+//! `delete` by default) is POSTed to the URL as a `permission.escalated` event and held: clients
+//! can no longer answer it, and only a POST to `/v1/approvals/callback` carrying the event's
+//! `token` can. Deliveries are signed like webhooks, with the route secret. Tokens name the
+//! server and request, a random nonce the held request keeps, and when they were issued, and
+//! carry an HMAC-SHA256 signature of all four under the same secret. A token only answers the
+//! request it was issued for while that request is still held under its nonce, so it works
+//! once, never for a later request that reuses the id, and not after a daemon restart. It
+//! expires `SANDBOX_AGENT_APPROVAL_ROUTE_TOKEN_TTL_SECS` (a day by default) after being issued.
+//!
+//! A request whose delivery fails is released, so clients can answer it as usual.
+
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::approvals::PendingApprovals;
+use crate::router::{now_ms, random_id};
+use crate::webhooks;
+
+const ROUTE_URL_ENV: &str = "SANDBOX_AGENT_APPROVAL_ROUTE_URL";
+const ROUTE_SECRET_ENV: &str = "SANDBOX_AGENT_APPROVAL_ROUTE_SECRET";
+const ROUTE_ACTIONS_ENV: &str = "SANDBOX_AGENT_APPROVAL_ROUTE_ACTIONS";
+const ROUTE_TOKEN_TTL_ENV: &str = "SANDBOX_AGENT_APPROVAL_ROUTE_TOKEN_TTL_SECS";
+const DEFAULT_ACTIONS: &[&str] = &["execute", "delete"];
+const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const CALLBACK_PATH: &str = "/v1/approvals/callback";
+
+#[derive(Debug)]
+pub(crate) struct ApprovalRouter {
+    url: String,
+    secret: String,
+    /// Tool call kinds to route; `*` routes every permission request.
+    actions: Vec<String>,
+    token_ttl: Duration,
+    client: reqwest::Client,
+}
+
+impl ApprovalRouter {
+    /// The router configured in the environment; `None` unless both the URL and the secret
+    /// are set.
+    pub(crate) fn from_env() -> Option<Self> {
+        let url = std::env::var(ROUTE_URL_ENV)
+            .ok()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())?;
+        if let Err(err) = webhooks::validate_url(&url) {
+            tracing::warn!(error = %err, "approval routing: ignoring invalid URL");
+            return None;
+        }
+        let Some(secret) = std::env::var(ROUTE_SECRET_ENV)
+            .ok()
+            .filter(|secret| !secret.is_empty())
+        else {
+            tracing::warn!("approval routing: {ROUTE_SECRET_ENV} is not set; routing is off");
+            return None;
+        };
+        let actions = std::env::var(ROUTE_ACTIONS_ENV).ok();
+        let mut router = Self::new(url, secret, actions.as_deref());
+        if let Some(secs) = std::env::var(ROUTE_TOKEN_TTL_ENV)
+            .ok()
+            .and_then(|raw| raw.trim().parse::<u64>().ok())
+        {
+            router.token_ttl = Duration::from_secs(secs);
+        }
+        Some(router)
+    }
+
+    fn new(url: String, secret: String, actions: Option<&str>) -> Self {
+        let actions = match actions {
+            Some(raw) => raw
+                .split(',')
+                .map(str::trim)
+                .filter(|action| !action.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => DEFAULT_ACTIONS
+                .iter()
+                .map(|action| action.to_string())
+                .collect(),
+        };
+        Self {
+            url,
+            secret,
+            actions,
+            token_ttl: DEFAULT_TOKEN_TTL,
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Whether `message` is a permission request this router holds for the approver.
+    pub(crate) fn matches(&self, message: &Value) -> bool {
+        if message.get("method").and_then(Value::as_str) != Some("session/request_permission") {
+            return false;
+        }
+        let kind = message
+            .pointer("/params/toolCall/kind")
+            .and_then(Value::as_str);
+        self.actions
+            .iter()
+            .any(|action| action == "*" || Some(action.as_str()) == kind)
+    }
+
+    /// Hold the permission request `message` and send it to the approver. The hold is released
+    /// when the delivery fails.
+    pub(crate) fn escalate(
+        &self,
+        server_id: &str,
+        agent: AgentId,
+        sequence: u64,
+        message: &Value,
+        approvals: Arc<StdMutex<PendingApprovals>>,
+    ) {
+        let Some(request_id) = message.get("id").filter(|id| !id.is_null()).cloned() else {
+            return;
+        };
+        let nonce = random_id();
+        if let Ok(mut approvals) = approvals.lock() {
+            approvals.route(&request_id, &nonce);
+        }
+        let event = json!({
+            "type": "permission.escalated",
+            "serverId": server_id,
+            "agent": agent.as_str(),
+            "sequence": sequence,
+            "sessionId": message.pointer("/params/sessionId"),
+            "timestampMs": now_ms(),
+            "data": message,
+            "token": self.token(server_id, &request_id, &nonce),
+            "callbackPath": CALLBACK_PATH,
+        });
+        let client = self.client.clone();
+        let url = self.url.clone();
+        let secret = self.secret.clone();
+        let server_id = server_id.to_string();
+        tokio::spawn(async move {
+            if webhooks::deliver(&client, &url, Some(&secret), &event).await {
+                return;
+            }
+            tracing::warn!(
+                server_id = server_id,
+                request_id = %request_id,
+                "approval routing: delivery failed; releasing the request to clients"
+            );
+            if let Ok(mut approvals) = approvals.lock() {
+                approvals.release_route(&request_id, &nonce);
+            }
+        });
+    }
+
+    /// `{payload}.{signature}`: the server and request ids, the escalation nonce and the issue
+    /// time, and their HMAC-SHA256, both base64url encoded.
+    pub(crate) fn token(&self, server_id: &str, request_id: &Value, nonce: &str) -> String {
+        self.token_issued_at(server_id, request_id, nonce, now_ms())
+    }
+
+    fn token_issued_at(
+        &self,
+        server_id: &str,
+        request_id: &Value,
+        nonce: &str,
+        issued_at_ms: i64,
+    ) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(
+            json!({
+                "serverId": server_id,
+                "requestId": request_id,
+                "nonce": nonce,
+                "iat": issued_at_ms,
+            })
+            .to_string(),
+        );
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// What a token this router signed names, if it has not expired.
+    pub(crate) fn verify(&self, token: &str) -> Result<RouteToken, SandboxError> {
+        self.verify_at(token, now_ms())
+    }
+
+    fn verify_at(&self, token: &str, now_ms: i64) -> Result<RouteToken, SandboxError> {
+        let invalid = || SandboxError::TokenInvalid {
+            message: Some("invalid approval token".to_string()),
+        };
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|raw| serde_json::from_slice::<Value>(&raw).ok())
+            .ok_or_else(invalid)?;
+        let text = |key: &str| {
+            payload
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or_else(invalid)
+        };
+        let server_id = text("serverId")?;
+        let nonce = text("nonce")?;
+        let request_id = payload.get("requestId").cloned().ok_or_else(invalid)?;
+        let issued_at_ms = payload
+            .get("iat")
+            .and_then(Value::as_i64)
+            .ok_or_else(invalid)?;
+        if now_ms.saturating_sub(issued_at_ms) > self.token_ttl.as_millis() as i64 {
+            return Err(SandboxError::TokenInvalid {
+                message: Some("approval token expired".to_string()),
+            });
+        }
+        Ok(RouteToken {
+            server_id,
+            request_id,
+            nonce,
+        })
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("hmac accepts any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// The request an approval token answers.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RouteToken {
+    pub server_id: String,
+    pub request_id: Value,
+    /// Must match the nonce the request is held under.
+    pub nonce: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission(kind: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 4,
+            "method": "session/request_permission",
+            "params": { "sessionId": "s-1", "toolCall": { "kind": kind, "title": "rm -rf target" } },
+        })
+    }
+
+    #[test]
+    fn routes_listed_kinds_and_verifies_its_tokens() {
+        let router = ApprovalRouter::new("http://approver".to_string(), "s3cret".to_string(), None);
+        assert!(router.matches(&permission("execute")));
+        assert!(!router.matches(&permission("read")));
+        let every = ApprovalRouter::new(
+            "http://approver".to_string(),
+            "s3cret".to_string(),
+            Some("*"),
+        );
+        assert!(every.matches(&permission("read")));
+        assert!(!every.matches(&json!({ "method": "session/update" })));
+
+        let token = router.token("main", &json!(4), "n-1");
+        assert_eq!(
+            router.verify(&token).expect("valid"),
+            RouteToken {
+                server_id: "main".to_string(),
+                request_id: json!(4),
+                nonce: "n-1".to_string(),
+            }
+        );
+        let (payload, _) = token.split_once('.').expect("signed");
+        let forged = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(
+                json!({ "serverId": "main", "requestId": 5, "nonce": "n-1", "iat": now_ms() })
+                    .to_string()
+            ),
+            token.split_once('.').expect("signed").1
+        );
+        assert!(router.verify(&forged).is_err());
+        assert!(router.verify(payload).is_err());
+        let other = ApprovalRouter::new("http://approver".to_string(), "other".to_string(), None);
+        assert!(other.verify(&token).is_err());
+    }
+
+    #[test]
+    fn tokens_expire() {
+        let router = ApprovalRouter::new("http://approver".to_string(), "s3cret".to_string(), None);
+        let issued_at_ms = now_ms() - DEFAULT_TOKEN_TTL.as_millis() as i64 - 1;
+        let stale = router.token_issued_at("main", &json!(4), "n-1", issued_at_ms);
+        assert!(matches!(
+            router.verify(&stale),
+            Err(SandboxError::TokenInvalid { message: Some(message) }) if message == "approval token expired"
+        ));
+        let fresh = router.token_issued_at("main", &json!(4), "n-1", issued_at_ms + 2);
+        assert!(router.verify_at(&fresh, issued_at_ms + 2).is_ok());
+    }
+}
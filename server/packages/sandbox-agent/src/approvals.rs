@@ -0,0 +1,232 @@
+//! Permission and question requests an ACP server's agent is still waiting on.
+//!
+//! Requests are recorded from two agent-to-client JSON-RPC requests:
+//! - `session/request_permission`, answered with an ACP `outcome`.
+//! - `_sandboxagent/session/request_question`, answered with `_meta` answers.
+//!
+//! A request stays pending until a client POSTs a JSON-RPC response with its `id`, whether
+//! through `/v1/approvals/reply` or directly to `/v1/acp/{server_id}`. Requests routed to an
+//! external approver are answered through `/v1/approvals/callback` instead.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalKind {
+    Permission,
+    Question,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    /// Allow this once, or answer a question.
+    Once,
+    /// Allow this and similar requests for the rest of the session.
+    Always,
+    Reject,
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingApproval {
+    pub kind: ApprovalKind,
+    pub request_id: Value,
+    pub session_id: Option<String>,
+    pub sequence: u64,
+    pub requested_at_ms: i64,
+    pub params: Value,
+    /// Nonce of the escalation holding this request for the external approver, set while it is
+    /// held; see `crate::approval_routing`.
+    pub route_nonce: Option<String>,
+}
+
+impl PendingApproval {
+    /// Whether the request is held for the external approver.
+    pub fn routed(&self) -> bool {
+        self.route_nonce.is_some()
+    }
+
+    /// Short description of what is being asked: the tool call title for permissions, the
+    /// first question's text for questions.
+    pub fn action(&self) -> Option<String> {
+        let action = match self.kind {
+            ApprovalKind::Permission => self.params.get("toolCall").and_then(|tool_call| {
+                tool_call
+                    .get("title")
+                    .or_else(|| tool_call.get("kind"))
+                    .and_then(Value::as_str)
+            }),
+            ApprovalKind::Question => self
+                .params
+                .get("questions")
+                .and_then(|questions| questions.get(0))
+                .and_then(|question| question.get("question"))
+                .and_then(Value::as_str),
+        };
+        action.map(str::to_string)
+    }
+
+    /// The JSON-RPC response that answers this request with `decision`. `option_id` picks a
+    /// specific permission option; otherwise the first option of the matching kind is used.
+    pub fn response(
+        &self,
+        decision: ApprovalDecision,
+        option_id: Option<&str>,
+        answers: Option<Value>,
+    ) -> Value {
+        let result = match self.kind {
+            ApprovalKind::Permission => {
+                let option_id = option_id
+                    .map(str::to_string)
+                    .or_else(|| self.option_for(decision));
+                match option_id {
+                    Some(option_id) => {
+                        json!({ "outcome": { "outcome": "selected", "optionId": option_id } })
+                    }
+                    None => json!({ "outcome": { "outcome": "cancelled" } }),
+                }
+            }
+            ApprovalKind::Question if decision == ApprovalDecision::Reject => {
+                json!({ "outcome": "rejected" })
+            }
+            ApprovalKind::Question => json!({
+                "outcome": "selected",
+                "_meta": { "sandboxagent.dev": { "answers": answers.unwrap_or(json!([])) } },
+            }),
+        };
+        json!({ "jsonrpc": "2.0", "id": self.request_id, "result": result })
+    }
+
+    /// How `response` answered this request, for the audit log: the selected permission
+    /// option's kind (or its id when the kind is unknown), `cancelled`, `answered`,
+    /// `rejected` or `error`, plus the option id or answers.
+    pub fn reply_decision(&self, response: &Value) -> (String, Value) {
+        let Some(result) = response.get("result") else {
+            return (
+                "error".to_string(),
+                response.get("error").cloned().unwrap_or_default(),
+            );
+        };
+        match self.kind {
+            ApprovalKind::Permission => {
+                let outcome = result.get("outcome").unwrap_or(&Value::Null);
+                match outcome.get("optionId").and_then(Value::as_str) {
+                    Some(option_id) => {
+                        let kind = self
+                            .params
+                            .get("options")
+                            .and_then(Value::as_array)
+                            .and_then(|options| {
+                                options.iter().find(|option| {
+                                    option.get("optionId").and_then(Value::as_str)
+                                        == Some(option_id)
+                                })
+                            })
+                            .and_then(|option| option.get("kind"))
+                            .and_then(Value::as_str)
+                            .unwrap_or(option_id);
+                        (kind.to_string(), json!({ "optionId": option_id }))
+                    }
+                    None => ("cancelled".to_string(), Value::Null),
+                }
+            }
+            ApprovalKind::Question => {
+                if result.get("outcome").and_then(Value::as_str) == Some("rejected") {
+                    return ("rejected".to_string(), Value::Null);
+                }
+                let answers = result
+                    .pointer("/_meta/sandboxagent.dev/answers")
+                    .cloned()
+                    .unwrap_or_default();
+                ("answered".to_string(), json!({ "answers": answers }))
+            }
+        }
+    }
+
+    fn option_for(&self, decision: ApprovalDecision) -> Option<String> {
+        let kinds: &[&str] = match decision {
+            ApprovalDecision::Once => &["allow_once", "allow_always"],
+            ApprovalDecision::Always => &["allow_always", "allow_once"],
+            ApprovalDecision::Reject => &["reject_once", "reject_always"],
+        };
+        let options = self.params.get("options")?.as_array()?;
+        kinds.iter().find_map(|kind| {
+            options
+                .iter()
+                .find(|option| option.get("kind").and_then(Value::as_str) == Some(kind))
+                .and_then(|option| option.get("optionId"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PendingApprovals {
+    /// Keyed by the JSON text of the request id, so `1` and `"1"` stay distinct.
+    entries: HashMap<String, PendingApproval>,
+}
+
+impl PendingApprovals {
+    /// Record `message` if it is a permission or question request from the agent.
+    pub fn record(&mut self, sequence: u64, message: &Value, now_ms: i64) {
+        let kind = match message.get("method").and_then(Value::as_str) {
+            Some("session/request_permission") => ApprovalKind::Permission,
+            Some("_sandboxagent/session/request_question") => ApprovalKind::Question,
+            _ => return,
+        };
+        let Some(request_id) = message.get("id").filter(|id| !id.is_null()) else {
+            return;
+        };
+        let params = message.get("params").cloned().unwrap_or_default();
+        self.entries.insert(
+            request_id.to_string(),
+            PendingApproval {
+                kind,
+                request_id: request_id.clone(),
+                session_id: params
+                    .get("sessionId")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                sequence,
+                requested_at_ms: now_ms,
+                params,
+                route_nonce: None,
+            },
+        );
+    }
+
+    /// Drop the request a client answered with a JSON-RPC response carrying `request_id`.
+    pub fn resolve(&mut self, request_id: &Value) {
+        self.entries.remove(&request_id.to_string());
+    }
+
+    /// Hold the request for the external approver under the escalation's `nonce`.
+    pub fn route(&mut self, request_id: &Value, nonce: &str) {
+        if let Some(approval) = self.entries.get_mut(&request_id.to_string()) {
+            approval.route_nonce = Some(nonce.to_string());
+        }
+    }
+
+    /// Release the request to clients, and take it, if it is held under `nonce`. A request
+    /// that was never routed, or was routed by another escalation, is left alone.
+    pub fn release_route(&mut self, request_id: &Value, nonce: &str) -> Option<PendingApproval> {
+        let approval = self.entries.get_mut(&request_id.to_string())?;
+        if approval.route_nonce.as_deref() != Some(nonce) {
+            return None;
+        }
+        approval.route_nonce = None;
+        Some(approval.clone())
+    }
+
+    pub fn get(&self, request_id: &Value) -> Option<PendingApproval> {
+        self.entries.get(&request_id.to_string()).cloned()
+    }
+
+    /// Pending requests, oldest first.
+    pub fn list(&self) -> Vec<PendingApproval> {
+        let mut pending = self.entries.values().cloned().collect::<Vec<_>>();
+        pending.sort_by_key(|approval| approval.sequence);
+        pending
+    }
+}
@@ -0,0 +1,175 @@
+//! Append-only audit log of permission replies, question answers, filesystem mutations and
+//! exec invocations.
+//!
+//! Entries are appended as JSON lines to `--audit-log`, or `audit.jsonl` in the user data
+//! directory, and read back by `GET /v1/audit`. The daemon never rewrites or truncates the
+//! file, so entries survive restarts and ids keep counting from the last line.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use sandbox_agent_error::SandboxError;
+use serde_json::Value;
+
+use crate::router::{map_fs_error, now_ms, AuditEntry, AuditKind, AuditQuery};
+
+pub const AUDIT_DEFAULT_LIMIT: usize = 500;
+pub const AUDIT_MAX_LIMIT: usize = 10_000;
+
+pub fn default_audit_log_path() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("sandbox-agent").join("audit.jsonl"))
+        .unwrap_or_else(|| {
+            PathBuf::from(".")
+                .join(".sandbox-agent")
+                .join("audit.jsonl")
+        })
+}
+
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    /// Opened on the first write, so a daemon that never mutates anything leaves no file.
+    writer: Mutex<Option<AuditWriter>>,
+}
+
+#[derive(Debug)]
+struct AuditWriter {
+    file: File,
+    next_id: u64,
+}
+
+/// An entry of `kind` by `actor`; `AuditLog::record` fills in its id and time.
+pub(crate) fn audit_entry(kind: AuditKind, actor: impl Into<String>) -> AuditEntry {
+    AuditEntry {
+        id: 0,
+        timestamp_ms: 0,
+        kind,
+        actor: actor.into(),
+        server_id: None,
+        session_id: None,
+        action: None,
+        decision: None,
+        path: None,
+        destination: None,
+        details: Value::Null,
+    }
+}
+
+impl AuditLog {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            writer: Mutex::new(None),
+        }
+    }
+
+    /// Append `entry` with the next id and the current time. The change being audited has
+    /// already happened, so a failed write is logged rather than returned.
+    pub(crate) fn record(&self, mut entry: AuditEntry) {
+        let mut writer = self.writer.lock().expect("audit log poisoned");
+        if writer.is_none() {
+            match open_writer(&self.path) {
+                Ok(opened) => *writer = Some(opened),
+                Err(err) => {
+                    tracing::error!(
+                        path = %self.path.display(),
+                        error = %err,
+                        "audit: failed to open log"
+                    );
+                    return;
+                }
+            }
+        }
+        let Some(writer) = writer.as_mut() else {
+            return;
+        };
+        entry.id = writer.next_id;
+        entry.timestamp_ms = now_ms();
+        let mut line = serde_json::to_string(&entry).unwrap_or_default();
+        line.push('\n');
+        match writer.file.write_all(line.as_bytes()) {
+            Ok(()) => writer.next_id += 1,
+            Err(err) => {
+                tracing::error!(
+                    path = %self.path.display(),
+                    error = %err,
+                    "audit: failed to write entry"
+                );
+            }
+        }
+    }
+
+    /// Entries matching `query`, oldest first, keeping the most recent `limit`.
+    pub(crate) fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, SandboxError> {
+        let limit = query
+            .limit
+            .unwrap_or(AUDIT_DEFAULT_LIMIT)
+            .clamp(1, AUDIT_MAX_LIMIT);
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(map_fs_error(&self.path, err)),
+        };
+        let mut entries = VecDeque::with_capacity(limit);
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|err| map_fs_error(&self.path, err))?;
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else {
+                continue;
+            };
+            if !matches(&entry, query) {
+                continue;
+            }
+            if entries.len() == limit {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+        Ok(entries.into())
+    }
+}
+
+fn matches(entry: &AuditEntry, query: &AuditQuery) -> bool {
+    if query.server_id.is_some() && entry.server_id != query.server_id {
+        return false;
+    }
+    if query.session_id.is_some() && entry.session_id != query.session_id {
+        return false;
+    }
+    if query.kind.is_some_and(|kind| entry.kind != kind) {
+        return false;
+    }
+    if query
+        .since_ms
+        .is_some_and(|since| entry.timestamp_ms < since)
+    {
+        return false;
+    }
+    if query
+        .until_ms
+        .is_some_and(|until| entry.timestamp_ms >= until)
+    {
+        return false;
+    }
+    true
+}
+
+fn open_writer(path: &Path) -> std::io::Result<AuditWriter> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let last_id = BufReader::new(File::open(path)?)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<AuditEntry>(&line).ok())
+        .last()
+        .map_or(0, |entry| entry.id);
+    Ok(AuditWriter {
+        file,
+        next_id: last_id + 1,
+    })
+}
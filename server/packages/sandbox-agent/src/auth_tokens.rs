@@ -0,0 +1,163 @@
+//! Named bearer tokens with scopes, checked by `require_token` on every protected route.
+//!
+//! Tokens are loaded from `--token-file` or, when no file is given, from the JSON in
+//! `SANDBOX_AGENT_TOKENS`. Both use the same document:
+//!
+//! ```json
+//! {"tokens": [{"name": "dashboard", "token": "...", "scopes": ["read-only"]}]}
+//! ```
+//!
+//! Every scope allows reads, except of files, config, the audit log and debug output, which can
+//! hold credentials. The `--token` token keeps full access.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use axum::http::Method;
+use serde::{Deserialize, Serialize};
+
+pub const TOKENS_ENV: &str = "SANDBOX_AGENT_TOKENS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TokenScope {
+    /// `GET` and `HEAD` requests, except the port proxy, the Swagger UI, `/v1/fs`, `/v1/config`,
+    /// `/v1/audit` and `/v1/debug`.
+    #[serde(rename = "read-only")]
+    ReadOnly,
+    /// Create, prompt, fork, delete and restore ACP servers, answer pending approvals, and
+    /// drive `/opencode` sessions.
+    #[serde(rename = "sessions:write")]
+    SessionsWrite,
+    /// Read, write, move and delete files under `/v1/fs`.
+    #[serde(rename = "fs:write")]
+    FsWrite,
+    /// Everything, including agent installs, config, commands and the port proxy.
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl TokenScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenScope::ReadOnly => "read-only",
+            TokenScope::SessionsWrite => "sessions:write",
+            TokenScope::FsWrite => "fs:write",
+            TokenScope::Admin => "admin",
+        }
+    }
+
+    /// Whether a token holding this scope may make a request that needs `required`.
+    pub fn grants(self, required: TokenScope) -> bool {
+        self == TokenScope::Admin || required == TokenScope::ReadOnly || self == required
+    }
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<TokenScope>,
+}
+
+impl ApiToken {
+    pub fn allows(&self, required: TokenScope) -> bool {
+        self.scopes.iter().any(|scope| scope.grants(required))
+    }
+}
+
+/// Who made a request, set by `require_token`: `admin` for the `--token` token and
+/// `token:{name}` for named tokens. Requests are counted against it for rate limits and
+/// recorded with it in the audit log.
+#[derive(Debug, Clone)]
+pub struct RequestActor(pub String);
+
+impl RequestActor {
+    /// Actor for requests made while auth is disabled.
+    pub const ANONYMOUS: &'static str = "anonymous";
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenFile {
+    tokens: Vec<ApiToken>,
+}
+
+/// Load tokens from `path`, or from `SANDBOX_AGENT_TOKENS` when no path is given.
+pub fn load_tokens(path: Option<&Path>) -> Result<Vec<ApiToken>, String> {
+    let raw = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?,
+        None => match std::env::var(TOKENS_ENV) {
+            Ok(raw) => raw,
+            Err(_) => return Ok(Vec::new()),
+        },
+    };
+    parse_tokens(&raw)
+}
+
+pub fn parse_tokens(raw: &str) -> Result<Vec<ApiToken>, String> {
+    let file: TokenFile = serde_json::from_str(raw).map_err(|err| err.to_string())?;
+    let mut names = HashSet::new();
+    let mut secrets = HashSet::new();
+    for token in &file.tokens {
+        if token.name.trim().is_empty() {
+            return Err("token name must not be empty".to_string());
+        }
+        if token.token.is_empty() {
+            return Err(format!("token '{}' has an empty secret", token.name));
+        }
+        if token.scopes.is_empty() {
+            return Err(format!("token '{}' has no scopes", token.name));
+        }
+        if !names.insert(token.name.as_str()) {
+            return Err(format!("duplicate token name '{}'", token.name));
+        }
+        if !secrets.insert(token.token.as_str()) {
+            return Err(format!(
+                "token '{}' reuses another token's secret",
+                token.name
+            ));
+        }
+    }
+    Ok(file.tokens)
+}
+
+/// Scope a request needs, from its method and full path (including `/v1` or `/opencode`).
+pub fn required_scope(method: &Method, path: &str) -> TokenScope {
+    if path.starts_with("/v1/proxy/")
+        || path == "/v1/docs"
+        || path.starts_with("/v1/config/")
+        || path == "/v1/audit"
+        || path.starts_with("/v1/debug/")
+    {
+        return TokenScope::Admin;
+    }
+    // Any file the daemon can read is reachable here, token files and credentials included.
+    if path.starts_with("/v1/fs/") {
+        return TokenScope::FsWrite;
+    }
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return TokenScope::ReadOnly;
+    }
+    if path == "/v1/acp"
+        || path.starts_with("/v1/acp/")
+        || path == "/v1/fanout"
+        || path.starts_with("/v1/fanout/")
+        || path == "/v1/runs"
+        || path == "/v1/chat/completions"
+        || path.starts_with("/v1/approvals")
+        || path.starts_with("/opencode")
+    {
+        TokenScope::SessionsWrite
+    } else if path.starts_with("/v1/git/") || path.starts_with("/v1/scm/") {
+        TokenScope::FsWrite
+    } else {
+        TokenScope::Admin
+    }
+}
@@ -0,0 +1,170 @@
+//! Blob store for large binary content in agent messages.
+//!
+//! Agents send screenshots and other binary output base64-encoded inside ACP messages: the
+//! `data` of `image` and `audio` content blocks, and the `blob` of embedded resources. Values
+//! longer than `SANDBOX_AGENT_BLOB_INLINE_LIMIT_BYTES` are decoded into the store before the
+//! message is buffered or delivered, replaced by an empty string, and referenced from
+//! `_meta["sandboxagent.dev"]`:
+//!
+//! ```json
+//! {"type": "image", "mimeType": "image/png", "data": "",
+//!  "_meta": {"sandboxagent.dev": {"blobId": "9f86d0...", "blobUrl": "/v1/blobs/9f86d0...", "size": 482113}}}
+//! ```
+//!
+//! Blobs are named by the SHA-256 of their content, so repeated screenshots are stored once,
+//! and are served by `GET /v1/blobs/{id}`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::router::map_fs_error;
+
+pub const BLOB_DIR_ENV: &str = "SANDBOX_AGENT_BLOB_DIR";
+pub const BLOB_INLINE_LIMIT_ENV: &str = "SANDBOX_AGENT_BLOB_INLINE_LIMIT_BYTES";
+const DEFAULT_INLINE_LIMIT_BYTES: usize = 64 * 1024;
+
+static STAGING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn default_blob_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("sandbox-agent").join("blobs"))
+        .unwrap_or_else(|| PathBuf::from(".").join(".sandbox-agent").join("blobs"))
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BlobStore {
+    dir: PathBuf,
+    /// Base64 values up to this many bytes stay inline; 0 disables extraction.
+    inline_limit: usize,
+}
+
+impl BlobStore {
+    pub(crate) fn from_env() -> Self {
+        let dir = std::env::var_os(BLOB_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(default_blob_dir);
+        let inline_limit = std::env::var(BLOB_INLINE_LIMIT_ENV)
+            .ok()
+            .and_then(|raw| raw.trim().parse().ok())
+            .unwrap_or(DEFAULT_INLINE_LIMIT_BYTES);
+        Self { dir, inline_limit }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.inline_limit > 0
+    }
+
+    /// Move oversized binary content in `message` into the store. Values that fail to decode
+    /// or store are left inline.
+    pub(crate) fn extract(&self, mut message: Value) -> Value {
+        if self.is_enabled() {
+            self.extract_in(&mut message);
+        }
+        message
+    }
+
+    fn extract_in(&self, value: &mut Value) {
+        match value {
+            Value::Array(items) => items.iter_mut().for_each(|item| self.extract_in(item)),
+            Value::Object(object) => {
+                let field = match object.get("type").and_then(Value::as_str) {
+                    Some("image" | "audio") => Some("data"),
+                    _ if object.contains_key("blob") => Some("blob"),
+                    _ => None,
+                };
+                if let Some(field) = field {
+                    self.extract_field(object, field);
+                }
+                object
+                    .iter_mut()
+                    .filter(|(key, _)| key.as_str() != "_meta")
+                    .for_each(|(_, child)| self.extract_in(child));
+            }
+            _ => {}
+        }
+    }
+
+    fn extract_field(&self, object: &mut Map<String, Value>, field: &str) {
+        let Some(encoded) = object
+            .get(field)
+            .and_then(Value::as_str)
+            .filter(|encoded| encoded.len() > self.inline_limit)
+        else {
+            return;
+        };
+        let Ok(bytes) = STANDARD.decode(encoded) else {
+            return;
+        };
+        let mime = object.get("mimeType").and_then(Value::as_str);
+        let id = match self.store(&bytes, mime) {
+            Ok(id) => id,
+            Err(err) => {
+                tracing::warn!(error = %err, "blobs: failed to store content; leaving it inline");
+                return;
+            }
+        };
+        object.insert(field.to_string(), Value::String(String::new()));
+        let meta = object
+            .entry("_meta")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Some(meta) = meta.as_object_mut() {
+            meta.insert(
+                "sandboxagent.dev".to_string(),
+                json!({
+                    "blobId": id,
+                    "blobUrl": format!("/v1/blobs/{id}"),
+                    "size": bytes.len(),
+                }),
+            );
+        }
+    }
+
+    /// Write `bytes` under their SHA-256, keeping the MIME type beside them.
+    fn store(&self, bytes: &[u8], mime: Option<&str>) -> Result<String, SandboxError> {
+        let id = format!("{:x}", Sha256::digest(bytes));
+        let path = self.dir.join(&id);
+        if path.is_file() {
+            return Ok(id);
+        }
+        fs::create_dir_all(&self.dir).map_err(|err| map_fs_error(&self.dir, err))?;
+        if let Some(mime) = mime {
+            let mime_path = self.dir.join(format!("{id}.mime"));
+            fs::write(&mime_path, mime).map_err(|err| map_fs_error(&mime_path, err))?;
+        }
+        let n = STAGING_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let staging = self.dir.join(format!(".{id}.{}.{n}", std::process::id()));
+        fs::write(&staging, bytes).map_err(|err| map_fs_error(&staging, err))?;
+        fs::rename(&staging, &path).map_err(|err| map_fs_error(&path, err))?;
+        Ok(id)
+    }
+
+    /// The blob's bytes and MIME type, if one was recorded.
+    pub(crate) fn read(&self, id: &str) -> Result<(Vec<u8>, Option<String>), SandboxError> {
+        let not_found = || SandboxError::NotFound {
+            path: format!("/v1/blobs/{id}"),
+        };
+        let valid = id.len() == 64
+            && id
+                .bytes()
+                .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'));
+        if !valid {
+            return Err(not_found());
+        }
+        let path = self.dir.join(id);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(not_found()),
+            Err(err) => return Err(map_fs_error(&path, err)),
+        };
+        let mime = fs::read_to_string(self.dir.join(format!("{id}.mime"))).ok();
+        Ok((bytes, mime))
+    }
+}
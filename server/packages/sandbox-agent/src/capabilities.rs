@@ -0,0 +1,513 @@
+//! Registry of optional subsystems reported by `GET /v1/capabilities`.
+//!
+//! Add a variant here when a new route family or runtime mode lands so clients
+//! can detect it; features missing from the map are unsupported by the build.
+
+use crate::router::AppState;
+
+/// Bumped whenever the shape of the capabilities document changes.
+pub(crate) const CAPABILITIES_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Feature {
+    Acp,
+    AcpEventsExport,
+    AcpEventsStream,
+    AcpWarmPool,
+    AcpFork,
+    AcpReplay,
+    AcpFanout,
+    AcpRuns,
+    AiSdkStream,
+    ChatCompletions,
+    AcpUsage,
+    AcpTurnDiffs,
+    AcpCheckpoints,
+    AcpWebhooks,
+    AcpLabels,
+    AcpArchive,
+    AgentConfig,
+    Approvals,
+    AuditLog,
+    Blobs,
+    ClaudeHooks,
+    CodexConfig,
+    ReasoningVariants,
+    StreamingGranularity,
+    SystemPrompts,
+    TurnOverrides,
+    TurnTimeouts,
+    TurnRetries,
+    QuestionTimeouts,
+    ApprovalRouting,
+    StructuredOutput,
+    PlanReview,
+    ContextCompaction,
+    TaskLists,
+    SubAgents,
+    SessionItems,
+    SessionTurns,
+    TranscriptExport,
+    FindingsExport,
+    EventsFirehose,
+    EventSink,
+    AcpResourceLimits,
+    AgentSandbox,
+    EgressPolicy,
+    AcpAdmissionLimits,
+    DebugLogs,
+    UnparsedEvents,
+    RateLimitEvents,
+    HealthDetail,
+    Readiness,
+    ModelCatalog,
+    AgentCapabilityProbe,
+    Models,
+    Exec,
+    GitSync,
+    GithubScm,
+    Proxy,
+    LazyInstall,
+    Fs,
+    FsUploadBatch,
+    FsUploadChunked,
+    FsRoots,
+    FsSearch,
+    FsArchive,
+    FsWatch,
+    InlineAttachments,
+    McpConfig,
+    McpOAuth,
+    McpServer,
+    SkillsConfig,
+    Skills,
+    InstructionFiles,
+    Workspaces,
+    OpencodeCompat,
+    InspectorUi,
+    ApiDocs,
+    Auth,
+    AuthScopes,
+}
+
+impl Feature {
+    pub(crate) const ALL: [Feature; 78] = [
+        Feature::Acp,
+        Feature::AcpEventsExport,
+        Feature::AcpEventsStream,
+        Feature::AcpWarmPool,
+        Feature::AcpFork,
+        Feature::AcpReplay,
+        Feature::AcpFanout,
+        Feature::AcpRuns,
+        Feature::AiSdkStream,
+        Feature::ChatCompletions,
+        Feature::AcpUsage,
+        Feature::AcpTurnDiffs,
+        Feature::AcpCheckpoints,
+        Feature::AcpWebhooks,
+        Feature::AcpLabels,
+        Feature::AcpArchive,
+        Feature::AgentConfig,
+        Feature::Approvals,
+        Feature::AuditLog,
+        Feature::Blobs,
+        Feature::ClaudeHooks,
+        Feature::CodexConfig,
+        Feature::ReasoningVariants,
+        Feature::StreamingGranularity,
+        Feature::SystemPrompts,
+        Feature::TurnOverrides,
+        Feature::TurnTimeouts,
+        Feature::TurnRetries,
+        Feature::QuestionTimeouts,
+        Feature::ApprovalRouting,
+        Feature::StructuredOutput,
+        Feature::PlanReview,
+        Feature::ContextCompaction,
+        Feature::TaskLists,
+        Feature::SubAgents,
+        Feature::SessionItems,
+        Feature::SessionTurns,
+        Feature::TranscriptExport,
+        Feature::FindingsExport,
+        Feature::EventsFirehose,
+        Feature::EventSink,
+        Feature::AcpResourceLimits,
+        Feature::AgentSandbox,
+        Feature::EgressPolicy,
+        Feature::AcpAdmissionLimits,
+        Feature::DebugLogs,
+        Feature::UnparsedEvents,
+        Feature::RateLimitEvents,
+        Feature::HealthDetail,
+        Feature::Readiness,
+        Feature::ModelCatalog,
+        Feature::AgentCapabilityProbe,
+        Feature::Models,
+        Feature::Exec,
+        Feature::GitSync,
+        Feature::GithubScm,
+        Feature::Proxy,
+        Feature::LazyInstall,
+        Feature::Fs,
+        Feature::FsUploadBatch,
+        Feature::FsUploadChunked,
+        Feature::FsRoots,
+        Feature::FsSearch,
+        Feature::FsArchive,
+        Feature::FsWatch,
+        Feature::InlineAttachments,
+        Feature::McpConfig,
+        Feature::McpOAuth,
+        Feature::McpServer,
+        Feature::SkillsConfig,
+        Feature::Skills,
+        Feature::InstructionFiles,
+        Feature::Workspaces,
+        Feature::OpencodeCompat,
+        Feature::InspectorUi,
+        Feature::ApiDocs,
+        Feature::Auth,
+        Feature::AuthScopes,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Feature::Acp => "acp",
+            Feature::AcpEventsExport => "acpEventsExport",
+            Feature::AcpEventsStream => "acpEventsStream",
+            Feature::AcpWarmPool => "acpWarmPool",
+            Feature::AcpFork => "acpFork",
+            Feature::AcpReplay => "acpReplay",
+            Feature::AcpFanout => "acpFanout",
+            Feature::AcpRuns => "acpRuns",
+            Feature::AiSdkStream => "aiSdkStream",
+            Feature::ChatCompletions => "chatCompletions",
+            Feature::AcpUsage => "acpUsage",
+            Feature::AcpTurnDiffs => "acpTurnDiffs",
+            Feature::AcpCheckpoints => "acpCheckpoints",
+            Feature::AcpWebhooks => "acpWebhooks",
+            Feature::AcpLabels => "acpLabels",
+            Feature::AcpArchive => "acpArchive",
+            Feature::AgentConfig => "agentConfig",
+            Feature::Approvals => "approvals",
+            Feature::AuditLog => "auditLog",
+            Feature::Blobs => "blobs",
+            Feature::ClaudeHooks => "claudeHooks",
+            Feature::CodexConfig => "codexConfig",
+            Feature::ReasoningVariants => "reasoningVariants",
+            Feature::StreamingGranularity => "streamingGranularity",
+            Feature::SystemPrompts => "systemPrompts",
+            Feature::TurnOverrides => "turnOverrides",
+            Feature::TurnTimeouts => "turnTimeouts",
+            Feature::TurnRetries => "turnRetries",
+            Feature::QuestionTimeouts => "questionTimeouts",
+            Feature::ApprovalRouting => "approvalRouting",
+            Feature::StructuredOutput => "structuredOutput",
+            Feature::PlanReview => "planReview",
+            Feature::ContextCompaction => "contextCompaction",
+            Feature::TaskLists => "taskLists",
+            Feature::SubAgents => "subAgents",
+            Feature::SessionItems => "sessionItems",
+            Feature::SessionTurns => "sessionTurns",
+            Feature::TranscriptExport => "transcriptExport",
+            Feature::FindingsExport => "findingsExport",
+            Feature::EventsFirehose => "eventsFirehose",
+            Feature::EventSink => "eventSink",
+            Feature::AcpResourceLimits => "acpResourceLimits",
+            Feature::AgentSandbox => "agentSandbox",
+            Feature::EgressPolicy => "egressPolicy",
+            Feature::AcpAdmissionLimits => "acpAdmissionLimits",
+            Feature::DebugLogs => "debugLogs",
+            Feature::UnparsedEvents => "unparsedEvents",
+            Feature::RateLimitEvents => "rateLimitEvents",
+            Feature::HealthDetail => "healthDetail",
+            Feature::Readiness => "readiness",
+            Feature::ModelCatalog => "modelCatalog",
+            Feature::AgentCapabilityProbe => "agentCapabilityProbe",
+            Feature::Models => "models",
+            Feature::Exec => "exec",
+            Feature::GitSync => "gitSync",
+            Feature::GithubScm => "githubScm",
+            Feature::Proxy => "proxy",
+            Feature::LazyInstall => "lazyInstall",
+            Feature::Fs => "fs",
+            Feature::FsUploadBatch => "fsUploadBatch",
+            Feature::FsUploadChunked => "fsUploadChunked",
+            Feature::FsRoots => "fsRoots",
+            Feature::FsSearch => "fsSearch",
+            Feature::FsArchive => "fsArchive",
+            Feature::FsWatch => "fsWatch",
+            Feature::InlineAttachments => "inlineAttachments",
+            Feature::McpConfig => "mcpConfig",
+            Feature::McpOAuth => "mcpOAuth",
+            Feature::McpServer => "mcpServer",
+            Feature::SkillsConfig => "skillsConfig",
+            Feature::Skills => "skills",
+            Feature::InstructionFiles => "instructionFiles",
+            Feature::Workspaces => "workspaces",
+            Feature::OpencodeCompat => "opencodeCompat",
+            Feature::InspectorUi => "inspectorUi",
+            Feature::ApiDocs => "apiDocs",
+            Feature::Auth => "auth",
+            Feature::AuthScopes => "authScopes",
+        }
+    }
+
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            Feature::Acp => "ACP JSON-RPC proxy at /v1/acp/{server_id}",
+            Feature::AcpEventsExport => "JSONL event export at /v1/acp/{server_id}/events.jsonl",
+            Feature::AcpEventsStream => {
+                "Live NDJSON event stream at /v1/acp/{server_id}/events/ndjson"
+            }
+            Feature::AcpWarmPool => "Pre-spawned agent processes via SANDBOX_AGENT_ACP_WARM_POOL",
+            Feature::AcpFork => "Fork a server with its event history at /v1/acp/{server_id}/fork",
+            Feature::AcpReplay => {
+                "Replay a server's prompts into a new server at /v1/acp/{server_id}/replay"
+            }
+            Feature::AcpFanout => "Run one prompt on several agents concurrently at /v1/fanout",
+            Feature::AcpRuns => "Blocking prompt runs with a structured result at /v1/runs",
+            Feature::AiSdkStream => {
+                "Prompts answered as a Vercel AI SDK UI message stream at /v1/acp/{server_id}/sessions/{session_id}/messages/ai-sdk"
+            }
+            Feature::ChatCompletions => {
+                "OpenAI-compatible chat completions on a new agent server at /v1/chat/completions"
+            }
+            Feature::AcpUsage => {
+                "Token usage and reported cost per server at /v1/acp/{server_id}/usage"
+            }
+            Feature::AcpTurnDiffs => {
+                "Per-turn workspace diffs at /v1/acp/{server_id}/turns with snapshotTurns"
+            }
+            Feature::AcpCheckpoints => {
+                "Workspace checkpoints and restore at /v1/acp/{server_id}/checkpoints"
+            }
+            Feature::AcpWebhooks => {
+                "Signed webhook POSTs for session lifecycle, permission and question requests, and errors"
+            }
+            Feature::AcpLabels => "Server titles and labels, set with PATCH /v1/acp/{server_id}",
+            Feature::AcpArchive => "Ended servers kept read-only in SANDBOX_AGENT_ACP_ARCHIVE_DIR",
+            Feature::AgentConfig => {
+                "Per-session agent settings from session/new _meta agentConfig (claude only)"
+            }
+            Feature::Approvals => {
+                "Pending permission and question requests with bulk replies at /v1/approvals"
+            }
+            Feature::AuditLog => {
+                "Append-only log of approval replies, file changes and exec runs at /v1/audit"
+            }
+            Feature::Blobs => "Large binary content in ACP messages served from /v1/blobs/{id}",
+            Feature::ClaudeHooks => "Claude hooks from session/new _meta passed to the Claude CLI",
+            Feature::CodexConfig => "Codex config.toml profiles and providers at /v1/agents/codex/config",
+            Feature::ReasoningVariants => {
+                "Codex reasoning effort per session from session/new _meta variant"
+            }
+            Feature::StreamingGranularity => {
+                "Claude replies streamed per token or per message from session/new _meta streaming"
+            }
+            Feature::SystemPrompts => {
+                "Claude system prompt replaced or extended per session from session/new _meta"
+            }
+            Feature::TurnOverrides => {
+                "Model, variant and agentMode for one turn from session/prompt _meta"
+            }
+            Feature::TurnTimeouts => {
+                "Turns cancelled after a daemon, session or prompt turnTimeoutMs"
+            }
+            Feature::TurnRetries => {
+                "Prompts re-sent after transient agent errors from session/prompt _meta retry"
+            }
+            Feature::QuestionTimeouts => {
+                "Pending questions rejected or answered after a session's questionPolicy timeout"
+            }
+            Feature::ApprovalRouting => {
+                "Permission requests for configured tool kinds held for an external approver"
+            }
+            Feature::StructuredOutput => {
+                "Replies parsed against an outputSchema from session/prompt _meta or /v1/runs"
+            }
+            Feature::PlanReview => {
+                "_sandboxagent/plan/* events and /v1/acp/{id}/sessions/{session}/plan/approve|reject"
+            }
+            Feature::ContextCompaction => {
+                "Context window per session in usage and /v1/acp/{id}/sessions/{session}/compact"
+            }
+            Feature::TaskLists => {
+                "Agent todo lists as _sandboxagent/tasks/updated and /v1/acp/{id}/sessions/{session}/tasks"
+            }
+            Feature::SubAgents => {
+                "Sub-agent tool calls linked to their parent at /v1/acp/{id}/sessions/{session}/items/{item}/children"
+            }
+            Feature::SessionItems => {
+                "Session transcripts as message, reasoning and tool call items at /v1/acp/{id}/sessions/{session}/items"
+            }
+            Feature::SessionTurns => {
+                "Prompt turns with their status, usage and items at /v1/acp/{id}/sessions/{session}/turns"
+            }
+            Feature::TranscriptExport => {
+                "Markdown, HTML or JSON transcripts at /v1/acp/{id}/sessions/{session}/export"
+            }
+            Feature::FindingsExport => {
+                "Agent findings as SARIF or JUnit XML at /v1/acp/{id}/sessions/{session}/export"
+            }
+            Feature::EventsFirehose => "Events from every ACP server at /v1/events/sse",
+            Feature::EventSink => "Every ACP server event published to Kafka or NATS via --event-sink",
+            Feature::AcpResourceLimits => {
+                "CPU, memory and process limits for agent processes, set per server at bootstrap"
+            }
+            Feature::AgentSandbox => {
+                "Landlock and seccomp confinement of agent processes with sandboxLevel at bootstrap"
+            }
+            Feature::EgressPolicy => {
+                "Domain and CIDR allow and deny lists for agent network access through a local proxy"
+            }
+            Feature::AcpAdmissionLimits => {
+                "Caps on concurrent ACP servers and running turns, answered with 429 and Retry-After"
+            }
+            Feature::DebugLogs => "Recent daemon log records at /v1/debug/logs",
+            Feature::UnparsedEvents => {
+                "Counts and recent agent output lines that were not JSON at /v1/debug/unparsed"
+            }
+            Feature::RateLimitEvents => {
+                "Provider rate limits as _sandboxagent/rate_limited, counted at /v1/debug/rate-limits"
+            }
+            Feature::HealthDetail => {
+                "Component status at /v1/health?verbose=true, with 503 when disk space runs out"
+            }
+            Feature::Readiness => "Readiness probe at /v1/ready, separate from /v1/health liveness",
+            Feature::ModelCatalog => {
+                "Live Claude and OpenCode model lists, refreshed at /v1/agents/{agent}/models/refresh"
+            }
+            Feature::AgentCapabilityProbe => {
+                "Agent capabilities probed from the agent, refreshed at /v1/agents/{agent}/capabilities/refresh"
+            }
+            Feature::Models => "Models of every installed agent in one list at /v1/models",
+            Feature::Exec => "Run commands with streamed output at /v1/exec",
+            Feature::GitSync => {
+                "git push and pull with per-request credentials and streamed progress at /v1/git"
+            }
+            Feature::GithubScm => {
+                "Clone GitHub repositories and open pull requests from sessions under /v1/scm/github"
+            }
+            Feature::Proxy => "HTTP and WebSocket proxy to --proxy-port ports at /v1/proxy/{port}",
+            Feature::LazyInstall => "Agents are installed on first bootstrap",
+            Feature::Fs => "Filesystem endpoints under /v1/fs",
+            Feature::FsUploadBatch => "Tar upload at /v1/fs/upload-batch",
+            Feature::FsUploadChunked => "Resumable chunked upload under /v1/fs/upload",
+            Feature::FsRoots => "Filesystem access confined to --fs-root directories",
+            Feature::FsSearch => "Streaming content search at /v1/fs/search",
+            Feature::FsArchive => "Directory download as tar.gz or zip at /v1/fs/archive",
+            Feature::FsWatch => "File change notifications over SSE at /v1/fs/watch",
+            Feature::InlineAttachments => {
+                "Base64 resource_link data in session/prompt saved to the workspace"
+            }
+            Feature::McpConfig => "MCP server config at /v1/config/mcp",
+            Feature::McpOAuth => {
+                "OAuth tokens for remote MCP servers at /v1/config/mcp/oauth, added to ACP sessions"
+            }
+            Feature::McpServer => {
+                "The daemon's files, commands, git and sessions as MCP tools at /v1/mcp and on stdio"
+            }
+            Feature::SkillsConfig => "Skills config at /v1/config/skills",
+            Feature::Skills => {
+                "Skill install and removal at /v1/skills, synced into Claude, Codex and OpenCode"
+            }
+            Feature::InstructionFiles => {
+                "AGENTS.md and CLAUDE.md written from /v1/instructions/templates per session"
+            }
+            Feature::Workspaces => {
+                "Directories and git clones registered at /v1/workspaces, used with workspaceId"
+            }
+            Feature::OpencodeCompat => "OpenCode-compatible API under /opencode",
+            Feature::InspectorUi => "Inspector UI embedded at /ui",
+            Feature::ApiDocs => "OpenAPI, AsyncAPI and Swagger UI under /v1",
+            Feature::Auth => "Bearer token required on /v1",
+            Feature::AuthScopes => "Named bearer tokens limited to scopes",
+        }
+    }
+
+    pub(crate) fn enabled(self, state: &AppState) -> bool {
+        match self {
+            Feature::AcpWarmPool => state.acp_proxy().warm_pool_configured(),
+            Feature::LazyInstall => !state.acp_proxy().requires_preinstall(),
+            Feature::InspectorUi => crate::ui::is_enabled(),
+            Feature::Auth => state.auth().is_enabled(),
+            Feature::AuthScopes => !state.auth().tokens.is_empty(),
+            Feature::FsRoots => !state.fs_roots().is_empty(),
+            Feature::Proxy => state.port_proxy().is_enabled(),
+            Feature::Blobs => state.acp_proxy().blobs().is_enabled(),
+            Feature::AcpArchive => state.acp_proxy().archive().is_enabled(),
+            Feature::EventSink => state.acp_proxy().event_sink_enabled(),
+            Feature::ApprovalRouting => state.acp_proxy().approval_routing_enabled(),
+            Feature::AcpResourceLimits => cfg!(unix),
+            Feature::AgentSandbox => crate::agent_sandbox::landlock_abi().is_some(),
+            Feature::Acp
+            | Feature::AcpEventsExport
+            | Feature::AcpEventsStream
+            | Feature::AcpFork
+            | Feature::AcpReplay
+            | Feature::EgressPolicy
+            | Feature::AcpAdmissionLimits
+            | Feature::AcpFanout
+            | Feature::AcpRuns
+            | Feature::AiSdkStream
+            | Feature::ChatCompletions
+            | Feature::AcpUsage
+            | Feature::AcpTurnDiffs
+            | Feature::AcpCheckpoints
+            | Feature::AcpWebhooks
+            | Feature::AcpLabels
+            | Feature::AgentConfig
+            | Feature::Approvals
+            | Feature::AuditLog
+            | Feature::ClaudeHooks
+            | Feature::CodexConfig
+            | Feature::ReasoningVariants
+            | Feature::StreamingGranularity
+            | Feature::SystemPrompts
+            | Feature::TurnOverrides
+            | Feature::TurnTimeouts
+            | Feature::TurnRetries
+            | Feature::QuestionTimeouts
+            | Feature::StructuredOutput
+            | Feature::PlanReview
+            | Feature::ContextCompaction
+            | Feature::TaskLists
+            | Feature::SubAgents
+            | Feature::SessionItems
+            | Feature::SessionTurns
+            | Feature::TranscriptExport
+            | Feature::FindingsExport
+            | Feature::EventsFirehose
+            | Feature::DebugLogs
+            | Feature::UnparsedEvents
+            | Feature::RateLimitEvents
+            | Feature::HealthDetail
+            | Feature::Readiness
+            | Feature::ModelCatalog
+            | Feature::AgentCapabilityProbe
+            | Feature::Models
+            | Feature::Exec
+            | Feature::GitSync
+            | Feature::GithubScm
+            | Feature::Fs
+            | Feature::FsUploadBatch
+            | Feature::FsUploadChunked
+            | Feature::FsSearch
+            | Feature::FsArchive
+            | Feature::FsWatch
+            | Feature::InlineAttachments
+            | Feature::McpConfig
+            | Feature::McpOAuth
+            | Feature::McpServer
+            | Feature::SkillsConfig
+            | Feature::Skills
+            | Feature::InstructionFiles
+            | Feature::Workspaces
+            | Feature::OpencodeCompat
+            | Feature::ApiDocs => true,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Fault injection for exercising restart and error paths, built with the `test-utils` feature.
+//!
+//! Every fault is off unless its rate (0.0 to 1.0) is set:
+//!
+//! - `SANDBOX_AGENT_CHAOS_STDOUT_DELAY_RATE`: share of agent stdout lines held back for up to
+//!   `SANDBOX_AGENT_CHAOS_MAX_DELAY_MS` (500 by default).
+//! - `SANDBOX_AGENT_CHAOS_DROP_RATE`: share of `session/prompt` requests during which the agent
+//!   process is killed, and of native OpenCode requests during which the shared OpenCode
+//!   server is killed, after up to the max delay.
+//! - `SANDBOX_AGENT_CHAOS_OPENCODE_500_RATE`: share of native OpenCode requests answered with
+//!   a 500 instead of being proxied.
+//!
+//! `SANDBOX_AGENT_CHAOS_SEED` fixes the random choices so a failing run can be repeated.
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use acp_http_adapter::process::{AdapterRuntime, StdoutDelay};
+use sandbox_agent_opencode_adapter::ProxyFault;
+use sandbox_agent_opencode_server_manager::OpenCodeServerManager;
+
+const STDOUT_DELAY_RATE_ENV: &str = "SANDBOX_AGENT_CHAOS_STDOUT_DELAY_RATE";
+const DROP_RATE_ENV: &str = "SANDBOX_AGENT_CHAOS_DROP_RATE";
+const OPENCODE_500_RATE_ENV: &str = "SANDBOX_AGENT_CHAOS_OPENCODE_500_RATE";
+const MAX_DELAY_ENV: &str = "SANDBOX_AGENT_CHAOS_MAX_DELAY_MS";
+const SEED_ENV: &str = "SANDBOX_AGENT_CHAOS_SEED";
+const DEFAULT_MAX_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub(crate) struct Chaos {
+    stdout_delay_rate: f64,
+    drop_rate: f64,
+    opencode_500_rate: f64,
+    max_delay: Duration,
+    rng: Mutex<u64>,
+}
+
+/// The daemon's fault injection settings, or `None` when no fault is enabled.
+pub(crate) fn configured() -> Option<Arc<Chaos>> {
+    static CHAOS: OnceLock<Option<Arc<Chaos>>> = OnceLock::new();
+    CHAOS
+        .get_or_init(|| Chaos::from_env().map(Arc::new))
+        .clone()
+}
+
+impl Chaos {
+    fn from_env() -> Option<Self> {
+        let seed = std::env::var(SEED_ENV)
+            .ok()
+            .and_then(|raw| raw.trim().parse::<u64>().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_nanos() as u64)
+                    .unwrap_or_default()
+            });
+        let max_delay = std::env::var(MAX_DELAY_ENV)
+            .ok()
+            .and_then(|raw| raw.trim().parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_MAX_DELAY);
+        let chaos = Self::new(
+            rate_from_env(STDOUT_DELAY_RATE_ENV),
+            rate_from_env(DROP_RATE_ENV),
+            rate_from_env(OPENCODE_500_RATE_ENV),
+            max_delay,
+            seed,
+        )?;
+        tracing::warn!(
+            stdout_delay_rate = chaos.stdout_delay_rate,
+            drop_rate = chaos.drop_rate,
+            opencode_500_rate = chaos.opencode_500_rate,
+            max_delay_ms = chaos.max_delay.as_millis() as u64,
+            seed = seed,
+            "fault injection enabled"
+        );
+        Some(chaos)
+    }
+
+    fn new(
+        stdout_delay_rate: f64,
+        drop_rate: f64,
+        opencode_500_rate: f64,
+        max_delay: Duration,
+        seed: u64,
+    ) -> Option<Self> {
+        if stdout_delay_rate <= 0.0 && drop_rate <= 0.0 && opencode_500_rate <= 0.0 {
+            return None;
+        }
+        Some(Self {
+            stdout_delay_rate,
+            drop_rate,
+            opencode_500_rate,
+            max_delay,
+            // xorshift gets stuck at zero.
+            rng: Mutex::new(seed.max(1)),
+        })
+    }
+
+    /// Hook that holds back a share of agent stdout lines, or `None` when that fault is off.
+    pub(crate) fn stdout_delay(self: &Arc<Self>) -> Option<StdoutDelay> {
+        if self.stdout_delay_rate <= 0.0 {
+            return None;
+        }
+        let chaos = self.clone();
+        Some(Arc::new(move || {
+            chaos
+                .roll(chaos.stdout_delay_rate)
+                .then(|| chaos.random_delay())
+        }))
+    }
+
+    /// Maybe kill the agent process partway through the turn `session/prompt` starts.
+    pub(crate) fn on_prompt(&self, runtime: &Arc<AdapterRuntime>) {
+        if !self.roll(self.drop_rate) {
+            return;
+        }
+        let delay = self.random_delay();
+        let runtime = runtime.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            runtime.crash().await;
+        });
+    }
+
+    /// Hook for the OpenCode adapter that fails a share of native OpenCode requests and
+    /// kills the shared OpenCode server during others.
+    pub(crate) fn proxy_fault(self: &Arc<Self>, manager: Arc<OpenCodeServerManager>) -> ProxyFault {
+        let chaos = self.clone();
+        Arc::new(move |_path: &str| {
+            if chaos.roll(chaos.drop_rate) {
+                let delay = chaos.random_delay();
+                let manager = manager.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    manager.kill_server().await;
+                });
+            }
+            chaos.roll(chaos.opencode_500_rate)
+        })
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < rate
+    }
+
+    fn random_delay(&self) -> Duration {
+        let max_ms = self.max_delay.as_millis() as u64;
+        Duration::from_millis(self.next_u64() % (max_ms + 1))
+    }
+
+    /// xorshift64*; the faults need repeatability, not quality.
+    fn next_u64(&self) -> u64 {
+        let mut state = match self.rng.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+fn rate_from_env(key: &str) -> f64 {
+    let Ok(raw) = std::env::var(key) else {
+        return 0.0;
+    };
+    match raw.trim().parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => rate,
+        _ => {
+            tracing::warn!(key = key, value = %raw, "fault injection: ignoring invalid rate");
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chaos_is_off_without_rates() {
+        assert!(Chaos::new(0.0, 0.0, 0.0, DEFAULT_MAX_DELAY, 7).is_none());
+    }
+
+    #[test]
+    fn rolls_follow_the_rate_and_repeat_with_the_seed() {
+        let rolls = |seed| {
+            let chaos = Chaos::new(0.25, 0.0, 0.0, DEFAULT_MAX_DELAY, seed).unwrap();
+            (0..4000).map(|_| chaos.roll(0.25)).collect::<Vec<_>>()
+        };
+        let first = rolls(42);
+        assert_eq!(first, rolls(42));
+        let hits = first.iter().filter(|hit| **hit).count();
+        assert!((800..1200).contains(&hits), "{hits} hits");
+
+        let chaos = Chaos::new(1.0, 0.0, 0.0, Duration::from_millis(20), 42).unwrap();
+        assert!((0..100).all(|_| chaos.roll(1.0) && chaos.random_delay().as_millis() <= 20));
+        assert!(!chaos.roll(0.0));
+    }
+}
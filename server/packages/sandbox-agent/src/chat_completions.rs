@@ -0,0 +1,475 @@
+//! OpenAI-compatible chat completions.
+//!
+//! `POST /v1/chat/completions` runs each request as one prompt turn on a new server, like
+//! `/v1/runs`, and answers in the Chat Completions format: a `chat.completion`, or with
+//! `stream: true` SSE `chat.completion.chunk`s ending with `[DONE]`, translated from the
+//! session's `session/update` notifications as they arrive. `model` picks the agent as
+//! `<agent>` or `<agent>:<model>`; any other value uses the default agent and model, from
+//! `--chat-agent` and `--chat-model` (or `SANDBOX_AGENT_CHAT_AGENT` and
+//! `SANDBOX_AGENT_CHAT_MODEL`).
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::Stream;
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_api_types::{
+    AcpRunStatus, ChatCompletionChoice, ChatCompletionMessage, ChatCompletionResponse,
+    ChatCompletionUsage,
+};
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::acp_proxy_runtime::AcpProxyRuntime;
+use crate::acp_runs::{follow_prompt, open_session, run_prompt, RunSpec, TurnEvent};
+use crate::acp_usage::AcpUsage;
+use crate::prompt_attachments::url_attachment;
+
+pub const CHAT_AGENT_ENV: &str = "SANDBOX_AGENT_CHAT_AGENT";
+pub const CHAT_MODEL_ENV: &str = "SANDBOX_AGENT_CHAT_MODEL";
+
+/// Agent and model for requests whose `model` does not name an agent.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChatDefaults {
+    agent: Option<AgentId>,
+    model: Option<String>,
+}
+
+impl ChatDefaults {
+    pub(crate) fn from_env() -> Self {
+        let var = |name| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        Self {
+            agent: var(CHAT_AGENT_ENV).and_then(|agent| AgentId::parse(agent.trim())),
+            model: var(CHAT_MODEL_ENV),
+        }
+    }
+
+    pub(crate) fn with_agent(mut self, agent: AgentId) -> Self {
+        self.agent = Some(agent);
+        self
+    }
+
+    pub(crate) fn with_model(mut self, model: String) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// Agent and model for a request's `model`. The default model only applies to the default
+    /// agent.
+    pub(crate) fn resolve(
+        &self,
+        requested: Option<&str>,
+    ) -> Result<(AgentId, Option<String>), SandboxError> {
+        let requested = requested.map(str::trim).unwrap_or_default();
+        let (agent, model) = match requested.split_once(':') {
+            Some((agent, model)) => (agent, Some(model).filter(|model| !model.is_empty())),
+            None => (requested, None),
+        };
+        if let Some(agent) = AgentId::parse(agent) {
+            let model = model.map(str::to_string).or_else(|| {
+                (self.agent == Some(agent))
+                    .then(|| self.model.clone())
+                    .flatten()
+            });
+            return Ok((agent, model));
+        }
+        match self.agent {
+            Some(agent) => Ok((agent, self.model.clone())),
+            None => Err(invalid(
+                "model must name an agent, as `<agent>` or `<agent>:<model>`, when no default agent is configured",
+            )),
+        }
+    }
+}
+
+/// ACP prompt for a chat. The last message must be from the user and is sent as its own
+/// blocks. Each request starts a new session, so earlier messages, system messages included,
+/// are sent first as a transcript in one text block.
+pub(crate) fn prompt_from_chat(messages: &[Value]) -> Result<Vec<Value>, SandboxError> {
+    let (last, history) = messages
+        .split_last()
+        .ok_or_else(|| invalid("messages must not be empty"))?;
+    if role(last) != Some("user") {
+        return Err(invalid("the last message must be a user message"));
+    }
+    let transcript: Vec<String> = history
+        .iter()
+        .filter_map(|message| {
+            let text = message_text(message);
+            if text.is_empty() {
+                return None;
+            }
+            let speaker = match role(message) {
+                Some("system" | "developer") => "System",
+                Some("assistant") => "Assistant",
+                Some("tool") => "Tool",
+                _ => "User",
+            };
+            Some(format!("{speaker}: {text}"))
+        })
+        .collect();
+
+    let mut prompt = Vec::new();
+    if !transcript.is_empty() {
+        let text = format!(
+            "Earlier messages in this conversation:\n\n{}",
+            transcript.join("\n\n")
+        );
+        prompt.push(json!({ "type": "text", "text": text }));
+    }
+    let blocks = content_blocks(last);
+    if blocks.is_empty() {
+        return Err(invalid(
+            "the last user message has no text or image_url parts",
+        ));
+    }
+    prompt.extend(blocks);
+    Ok(prompt)
+}
+
+/// Run the prompt on a new server, delete it and answer with a `chat.completion`.
+pub(crate) async fn completion(
+    runtime: &AcpProxyRuntime,
+    spec: &RunSpec,
+    model: String,
+) -> Result<ChatCompletionResponse, SandboxError> {
+    let report = run_prompt(runtime, spec).await;
+    delete_server(runtime, &spec.server_id).await;
+    let report = report?;
+    match report.status {
+        AcpRunStatus::Completed => {}
+        AcpRunStatus::TimedOut => {
+            return Err(SandboxError::Timeout {
+                message: report.error,
+            })
+        }
+        AcpRunStatus::Failed => {
+            return Err(SandboxError::StreamError {
+                message: report
+                    .error
+                    .unwrap_or_else(|| "session/prompt failed".to_string()),
+            })
+        }
+    }
+    let reasoning: String = report
+        .items
+        .iter()
+        .filter_map(|update| match delta(update)? {
+            Delta::Reasoning(text) => Some(text),
+            Delta::Content(_) => None,
+        })
+        .collect();
+    Ok(ChatCompletionResponse {
+        id: completion_id(&spec.server_id),
+        object: "chat.completion".to_string(),
+        created: now_secs(),
+        model,
+        choices: vec![ChatCompletionChoice {
+            index: 0,
+            message: ChatCompletionMessage {
+                role: "assistant".to_string(),
+                content: report.message,
+                reasoning_content: Some(reasoning).filter(|text| !text.is_empty()),
+            },
+            finish_reason: finish_reason(report.stop_reason.as_deref()).to_string(),
+        }],
+        usage: usage(&report.usage),
+    })
+}
+
+/// Start the run's server, send its prompt and stream the turn as `chat.completion.chunk`s.
+/// The server is deleted when the turn ends or the client goes away, which cancels the turn.
+pub(crate) async fn completion_stream(
+    runtime: Arc<AcpProxyRuntime>,
+    spec: RunSpec,
+    model: String,
+    include_usage: bool,
+) -> Result<impl Stream<Item = Value> + Send + 'static, SandboxError> {
+    let server_id = spec.server_id.clone();
+    let turn = match open_session(&runtime, &spec).await {
+        Ok(session_id) => {
+            let params = json!({ "sessionId": session_id, "prompt": spec.prompt });
+            follow_prompt(runtime.clone(), server_id.clone(), session_id, params).await
+        }
+        Err(message) => Err(SandboxError::StreamError { message }),
+    };
+    let mut turn = match turn {
+        Ok(turn) => turn,
+        Err(err) => {
+            delete_server(&runtime, &server_id).await;
+            return Err(err);
+        }
+    };
+
+    let chunks = ChatChunks {
+        id: completion_id(&server_id),
+        created: now_secs(),
+        model,
+    };
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let mut open = tx
+            .send(chunks.delta(json!({ "role": "assistant", "content": "" })))
+            .await
+            .is_ok();
+        while open {
+            let event = tokio::select! {
+                event = turn.recv() => event,
+                _ = tx.closed() => None,
+            };
+            let last = match event {
+                Some(TurnEvent::Update(update)) => {
+                    let Some(delta) = delta(&update) else {
+                        continue;
+                    };
+                    open = tx.send(chunks.delta(delta.into_json())).await.is_ok();
+                    continue;
+                }
+                Some(TurnEvent::Ended(Ok(response))) => match response.get("error") {
+                    Some(error) => vec![chunks.error(
+                        error
+                            .get("message")
+                            .and_then(Value::as_str)
+                            .unwrap_or("session/prompt failed"),
+                    )],
+                    None => {
+                        let stop_reason = response
+                            .pointer("/result/stopReason")
+                            .and_then(Value::as_str);
+                        let mut last = vec![chunks.finish(finish_reason(stop_reason))];
+                        if include_usage {
+                            let totals = runtime.usage(&server_id).await.unwrap_or_default();
+                            last.push(chunks.usage(usage(&totals)));
+                        }
+                        last
+                    }
+                },
+                Some(TurnEvent::Ended(Err(err))) => vec![chunks.error(&err.to_string())],
+                None => break,
+            };
+            for chunk in last {
+                if tx.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+            break;
+        }
+        drop(turn);
+        delete_server(&runtime, &server_id).await;
+    });
+    Ok(ReceiverStream::new(rx))
+}
+
+/// Builds the chunks of one streamed completion.
+struct ChatChunks {
+    id: String,
+    created: u64,
+    model: String,
+}
+
+impl ChatChunks {
+    fn chunk(&self, choices: Value) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": choices,
+        })
+    }
+
+    fn delta(&self, delta: Value) -> Value {
+        self.chunk(json!([{ "index": 0, "delta": delta, "finish_reason": null }]))
+    }
+
+    fn finish(&self, finish_reason: &str) -> Value {
+        self.chunk(json!([{ "index": 0, "delta": {}, "finish_reason": finish_reason }]))
+    }
+
+    fn usage(&self, usage: ChatCompletionUsage) -> Value {
+        let mut chunk = self.chunk(json!([]));
+        chunk["usage"] = json!(usage);
+        chunk
+    }
+
+    fn error(&self, message: &str) -> Value {
+        json!({ "error": { "message": message, "type": "server_error" } })
+    }
+}
+
+enum Delta {
+    Content(String),
+    Reasoning(String),
+}
+
+impl Delta {
+    fn into_json(self) -> Value {
+        let (field, text) = match self {
+            Delta::Content(text) => ("content", text),
+            Delta::Reasoning(text) => ("reasoning_content", text),
+        };
+        let mut delta = Map::new();
+        delta.insert(field.to_string(), Value::String(text));
+        Value::Object(delta)
+    }
+}
+
+/// Text the agent wrote or thought in a `session/update`.
+fn delta(update: &Value) -> Option<Delta> {
+    let kind = update.get("sessionUpdate").and_then(Value::as_str)?;
+    let content = update.get("content")?;
+    if content.get("type").and_then(Value::as_str) != Some("text") {
+        return None;
+    }
+    let text = content.get("text").and_then(Value::as_str)?.to_string();
+    match kind {
+        "agent_message_chunk" => Some(Delta::Content(text)),
+        "agent_thought_chunk" => Some(Delta::Reasoning(text)),
+        _ => None,
+    }
+}
+
+/// Chat Completions `finish_reason` for an ACP stop reason.
+fn finish_reason(stop_reason: Option<&str>) -> &'static str {
+    match stop_reason {
+        Some("max_tokens" | "max_turn_requests") => "length",
+        Some("refusal") => "content_filter",
+        _ => "stop",
+    }
+}
+
+fn usage(usage: &AcpUsage) -> ChatCompletionUsage {
+    let total_tokens = if usage.total_tokens > 0 {
+        usage.total_tokens
+    } else {
+        usage.input_tokens + usage.output_tokens
+    };
+    ChatCompletionUsage {
+        prompt_tokens: usage.input_tokens,
+        completion_tokens: usage.output_tokens,
+        total_tokens,
+    }
+}
+
+fn role(message: &Value) -> Option<&str> {
+    message.get("role").and_then(Value::as_str)
+}
+
+/// Prompt blocks for a message's `content`: a string, or `text` and `image_url` parts.
+fn content_blocks(message: &Value) -> Vec<Value> {
+    match message.get("content") {
+        Some(Value::String(text)) => vec![json!({ "type": "text", "text": text })],
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| match part.get("type").and_then(Value::as_str) {
+                Some("text") => {
+                    let text = part.get("text").and_then(Value::as_str)?;
+                    Some(json!({ "type": "text", "text": text }))
+                }
+                Some("image_url") => {
+                    let url = part.pointer("/image_url/url").and_then(Value::as_str)?;
+                    Some(url_attachment(url, None, None))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Text of a message's `content`, for the transcript of earlier messages.
+fn message_text(message: &Value) -> String {
+    content_blocks(message)
+        .iter()
+        .filter_map(|block| block.get("text").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn completion_id(server_id: &str) -> String {
+    format!("chatcmpl-{server_id}")
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+async fn delete_server(runtime: &AcpProxyRuntime, server_id: &str) {
+    if let Err(err) = runtime.delete(server_id).await {
+        tracing::warn!(server_id = server_id, error = %err, "chat_completions: failed to delete server");
+    }
+}
+
+fn invalid(message: &str) -> SandboxError {
+    SandboxError::InvalidRequest {
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_names_the_agent_or_falls_back_to_the_default() {
+        let none = ChatDefaults::default();
+        assert_eq!(
+            none.resolve(Some("codex:gpt-5")).unwrap(),
+            (AgentId::Codex, Some("gpt-5".to_string()))
+        );
+        assert_eq!(none.resolve(Some("mock")).unwrap(), (AgentId::Mock, None));
+        assert!(none.resolve(Some("gpt-4o")).is_err());
+
+        let defaults = ChatDefaults::default()
+            .with_agent(AgentId::Claude)
+            .with_model("sonnet".to_string());
+        assert_eq!(
+            defaults.resolve(Some("gpt-4o")).unwrap(),
+            (AgentId::Claude, Some("sonnet".to_string()))
+        );
+        assert_eq!(
+            defaults.resolve(Some("claude")).unwrap(),
+            (AgentId::Claude, Some("sonnet".to_string()))
+        );
+        assert_eq!(
+            defaults.resolve(Some("mock")).unwrap(),
+            (AgentId::Mock, None)
+        );
+    }
+
+    #[test]
+    fn history_is_sent_as_a_transcript_before_the_last_message() {
+        let messages = vec![
+            json!({ "role": "system", "content": "Be terse." }),
+            json!({ "role": "user", "content": "hi" }),
+            json!({ "role": "assistant", "content": "hello" }),
+            json!({ "role": "user", "content": [
+                { "type": "text", "text": "what is this?" },
+                { "type": "image_url", "image_url": { "url": "https://example.com/a.png" } }
+            ] }),
+        ];
+        assert_eq!(
+            prompt_from_chat(&messages).unwrap(),
+            vec![
+                json!({ "type": "text", "text": "Earlier messages in this conversation:\n\nSystem: Be terse.\n\nUser: hi\n\nAssistant: hello" }),
+                json!({ "type": "text", "text": "what is this?" }),
+                json!({ "type": "resource_link", "uri": "https://example.com/a.png", "name": "https://example.com/a.png" }),
+            ]
+        );
+        assert_eq!(
+            prompt_from_chat(&[json!({ "role": "user", "content": "hi" })]).unwrap(),
+            vec![json!({ "type": "text", "text": "hi" })]
+        );
+        assert!(prompt_from_chat(&[json!({ "role": "assistant", "content": "hi" })]).is_err());
+        assert!(prompt_from_chat(&[]).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+//! Workspace checkpoints for ACP servers.
+//!
+//! A checkpoint records every file in the server directory, using the same walk as turn
+//! snapshots: `.git` and gitignored paths are skipped and symlinks are not followed. File
+//! contents are stored once as content-addressed blobs under `objects/`, so checkpoints that
+//! share files share storage, and each checkpoint is a JSON manifest mapping paths to blobs.
+//! Manifests are written to disk, so checkpoints survive a server restart.
+//!
+//! Restoring rewrites files whose content differs from the checkpoint and removes files the
+//! checkpoint did not have. Ignored paths are left alone in both directions.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use sandbox_agent_error::SandboxError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::router::{map_fs_error, now_ms};
+use crate::turn_diff::{sha256_file, workspace_files};
+
+static CHECKPOINT_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+pub fn default_checkpoint_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("sandbox-agent").join("checkpoints"))
+        .unwrap_or_else(|| {
+            PathBuf::from(".")
+                .join(".sandbox-agent")
+                .join("checkpoints")
+        })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CheckpointFile {
+    /// Path relative to the checkpoint root, `/`-separated.
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+    #[serde(default)]
+    pub mode: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CheckpointManifest {
+    pub checkpoint_id: String,
+    pub server_id: String,
+    pub root: PathBuf,
+    #[serde(default)]
+    pub label: Option<String>,
+    pub created_at_ms: i64,
+    pub files: Vec<CheckpointFile>,
+    /// The walk hit the file limit, so files beyond it were not recorded.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CheckpointRestore {
+    /// Files written back from the checkpoint.
+    pub restored: Vec<String>,
+    /// Files removed because the checkpoint did not have them.
+    pub deleted: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CheckpointStore {
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Record the current state of `root` for `server_id`.
+    pub(crate) fn create(
+        &self,
+        server_id: &str,
+        root: &Path,
+        label: Option<String>,
+    ) -> Result<CheckpointManifest, SandboxError> {
+        if !root.is_dir() {
+            return Err(SandboxError::NotFound {
+                path: root.display().to_string(),
+            });
+        }
+        let (entries, truncated) = workspace_files(root);
+        let mut files = Vec::with_capacity(entries.len());
+        for (relative, path) in entries {
+            // Files that vanish mid-walk are simply not part of the checkpoint.
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let (sha256, size) = self.store_blob(&path)?;
+            files.push(CheckpointFile {
+                path: relative,
+                sha256,
+                size,
+                mode: file_mode(&metadata),
+            });
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let created_at_ms = now_ms();
+        let n = CHECKPOINT_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let manifest = CheckpointManifest {
+            checkpoint_id: format!("cp_{created_at_ms}_{n}"),
+            server_id: server_id.to_string(),
+            root: root.to_path_buf(),
+            label,
+            created_at_ms,
+            files,
+            truncated,
+        };
+
+        let server_dir = self.server_dir(server_id);
+        fs::create_dir_all(&server_dir).map_err(|err| map_fs_error(&server_dir, err))?;
+        let path = server_dir.join(format!("{}.json", manifest.checkpoint_id));
+        let json =
+            serde_json::to_vec_pretty(&manifest).map_err(|err| SandboxError::StreamError {
+                message: format!("encode checkpoint: {err}"),
+            })?;
+        fs::write(&path, json).map_err(|err| map_fs_error(&path, err))?;
+        Ok(manifest)
+    }
+
+    /// Checkpoints of `server_id`, oldest first.
+    pub(crate) fn list(&self, server_id: &str) -> Result<Vec<CheckpointManifest>, SandboxError> {
+        let server_dir = self.server_dir(server_id);
+        let entries = match fs::read_dir(&server_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(map_fs_error(&server_dir, err)),
+        };
+        let mut manifests = entries
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| read_manifest(&entry.path()))
+            .filter(|manifest| manifest.server_id == server_id)
+            .collect::<Vec<_>>();
+        manifests.sort_by(|a, b| {
+            a.created_at_ms
+                .cmp(&b.created_at_ms)
+                .then_with(|| a.checkpoint_id.cmp(&b.checkpoint_id))
+        });
+        Ok(manifests)
+    }
+
+    pub(crate) fn get(
+        &self,
+        server_id: &str,
+        checkpoint_id: &str,
+    ) -> Result<CheckpointManifest, SandboxError> {
+        let not_found = || SandboxError::NotFound {
+            path: format!("/v1/acp/{server_id}/checkpoints/{checkpoint_id}"),
+        };
+        if checkpoint_id.is_empty()
+            || !checkpoint_id
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        {
+            return Err(not_found());
+        }
+        let path = self
+            .server_dir(server_id)
+            .join(format!("{checkpoint_id}.json"));
+        read_manifest(&path)
+            .filter(|manifest| manifest.server_id == server_id)
+            .ok_or_else(not_found)
+    }
+
+    /// Put the checkpoint root back into the recorded state.
+    pub(crate) fn restore(
+        &self,
+        manifest: &CheckpointManifest,
+    ) -> Result<CheckpointRestore, SandboxError> {
+        let root = &manifest.root;
+        fs::create_dir_all(root).map_err(|err| map_fs_error(root, err))?;
+        let mut outcome = CheckpointRestore::default();
+
+        let mut recorded = std::collections::HashSet::new();
+        for file in &manifest.files {
+            let Some(target) = checkpoint_target(root, &file.path) else {
+                continue;
+            };
+            recorded.insert(file.path.as_str());
+            let unchanged = fs::symlink_metadata(&target)
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() == file.size)
+                && sha256_file(&target).as_deref() == Some(file.sha256.as_str());
+            if !unchanged {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err))?;
+                }
+                // Replace whatever took the file's place; copying onto a symlink would write
+                // through it.
+                if let Ok(metadata) = fs::symlink_metadata(&target) {
+                    if metadata.is_dir() {
+                        fs::remove_dir_all(&target).map_err(|err| map_fs_error(&target, err))?;
+                    } else if metadata.file_type().is_symlink() {
+                        fs::remove_file(&target).map_err(|err| map_fs_error(&target, err))?;
+                    }
+                }
+                let blob = self.blob_path(&file.sha256);
+                fs::copy(&blob, &target).map_err(|err| match err.kind() {
+                    std::io::ErrorKind::NotFound if !blob.exists() => SandboxError::StreamError {
+                        message: format!("checkpoint blob missing: {}", file.sha256),
+                    },
+                    _ => map_fs_error(&target, err),
+                })?;
+                outcome.restored.push(file.path.clone());
+            }
+            set_file_mode(&target, file.mode);
+        }
+
+        // Without the full file list, deleting would remove files the checkpoint never saw.
+        if !manifest.truncated {
+            let (current, _) = workspace_files(root);
+            for (relative, path) in current {
+                if recorded.contains(relative.as_str()) {
+                    continue;
+                }
+                fs::remove_file(&path).map_err(|err| map_fs_error(&path, err))?;
+                outcome.deleted.push(relative);
+            }
+            outcome.deleted.sort();
+        }
+        Ok(outcome)
+    }
+
+    fn server_dir(&self, server_id: &str) -> PathBuf {
+        let name = server_id
+            .chars()
+            .map(|ch| {
+                if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+                    ch
+                } else {
+                    '-'
+                }
+            })
+            .collect::<String>();
+        self.dir.join("servers").join(name)
+    }
+
+    fn blob_path(&self, sha256: &str) -> PathBuf {
+        self.dir
+            .join("objects")
+            .join(&sha256[..2])
+            .join(&sha256[2..])
+    }
+
+    /// Copy `path` into the blob store, returning its checksum and size.
+    fn store_blob(&self, path: &Path) -> Result<(String, u64), SandboxError> {
+        let objects = self.dir.join("objects");
+        fs::create_dir_all(&objects).map_err(|err| map_fs_error(&objects, err))?;
+        let n = CHECKPOINT_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let staging = objects.join(format!(".blob.{}.{n}", std::process::id()));
+
+        let copied = (|| {
+            let mut source = fs::File::open(path).map_err(|err| map_fs_error(path, err))?;
+            let mut target =
+                fs::File::create(&staging).map_err(|err| map_fs_error(&staging, err))?;
+            let mut hasher = Sha256::new();
+            let mut size = 0u64;
+            let mut buffer = vec![0u8; 64 * 1024];
+            loop {
+                let read = source
+                    .read(&mut buffer)
+                    .map_err(|err| map_fs_error(path, err))?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                target
+                    .write_all(&buffer[..read])
+                    .map_err(|err| map_fs_error(&staging, err))?;
+                size += read as u64;
+            }
+            Ok::<_, SandboxError>((format!("{:x}", hasher.finalize()), size))
+        })();
+
+        let (sha256, size) = match copied {
+            Ok(copied) => copied,
+            Err(err) => {
+                let _ = fs::remove_file(&staging);
+                return Err(err);
+            }
+        };
+        let blob = self.blob_path(&sha256);
+        if blob.exists() {
+            let _ = fs::remove_file(&staging);
+        } else {
+            if let Some(parent) = blob.parent() {
+                fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err))?;
+            }
+            fs::rename(&staging, &blob).map_err(|err| map_fs_error(&blob, err))?;
+        }
+        Ok((sha256, size))
+    }
+}
+
+fn read_manifest(path: &Path) -> Option<CheckpointManifest> {
+    let bytes = fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Absolute path for a manifest entry, refusing anything that would leave `root`.
+fn checkpoint_target(root: &Path, relative: &str) -> Option<PathBuf> {
+    let mut target = root.to_path_buf();
+    for part in relative.split('/') {
+        if part.is_empty() || part == "." || part == ".." {
+            return None;
+        }
+        target.push(part);
+    }
+    Some(target)
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: Option<u32>) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Some(mode) = mode {
+        let _ = fs::set_permissions(path, fs::Permissions::from_mode(mode));
+    }
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(_path: &Path, _mode: Option<u32>) {}
@@ -1,21 +1,30 @@
 use std::collections::HashMap;
-use std::io::Write;
+use std::io::{BufRead, IsTerminal, Write};
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::process::Command as ProcessCommand;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use acp_http_adapter::process::RESYNC_EVENT;
 use clap::{Args, Parser, Subcommand};
 
-mod build_version {
+pub(crate) mod build_version {
     include!(concat!(env!("OUT_DIR"), "/version.rs"));
 }
 
+use crate::agent_sandbox::Confinement;
+pub use crate::cli_output::OutputFormat;
+use crate::cli_output::{render, TableSpec};
+use crate::event_sink::parse_target;
+use crate::request_limits::{RequestLimits, DEFAULT_MAX_FS_BODY_BYTES, DEFAULT_MAX_MESSAGE_BYTES};
 use crate::router::{
-    build_router_with_state, shutdown_servers, AppState, AuthConfig, BrandingMode,
+    build_router_with_state, load_persisted_state, prewarm_servers, shutdown_servers,
+    start_event_sink, start_model_catalog_refresh, AppState, AuthConfig, BrandingMode,
 };
 use crate::server_logs::ServerLogs;
 use crate::telemetry;
+use crate::tls::TlsOptions;
 use crate::ui;
 use reqwest::blocking::Client as HttpClient;
 use reqwest::Method;
@@ -28,14 +37,43 @@ use serde::Serialize;
 use serde_json::{json, Value};
 use thiserror::Error;
 use tower_http::cors::{Any, CorsLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
 const API_PREFIX: &str = "/v1";
-const ACP_EXTENSION_AGENT_LIST_METHOD: &str = "_sandboxagent/agent/list";
 const ACP_EXTENSION_AGENT_INSTALL_METHOD: &str = "_sandboxagent/agent/install";
 const DEFAULT_HOST: &str = "127.0.0.1";
 const DEFAULT_PORT: u16 = 2468;
 const LOGS_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const TURN_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+const STREAM_RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+const ACP_BRIDGE_STREAM_RETRY: u32 = 5;
+const ACP_BRIDGE_REPLY_WAIT: Duration = Duration::from_secs(1);
+
+const AGENTS_TABLE: TableSpec = TableSpec {
+    rows: "/agents",
+    columns: &[
+        ("AGENT", "/id"),
+        ("INSTALLED", "/installed"),
+        ("CREDENTIALS", "/credentialsAvailable"),
+        ("VERSION", "/version"),
+        ("STATUS", "/serverStatus/status"),
+    ],
+};
+const SERVERS_TABLE: TableSpec = TableSpec {
+    rows: "/servers",
+    columns: &[
+        ("SERVER", "/serverId"),
+        ("AGENT", "/agent"),
+        ("TITLE", "/title"),
+        ("CREATED_AT_MS", "/createdAtMs"),
+        ("LAST_EVENT", "/lastEventId"),
+        ("DIRECTORY", "/directory"),
+    ],
+};
+const MODELS_TABLE: TableSpec = TableSpec {
+    rows: "/models",
+    columns: &[("MODEL", "/id"), ("NAME", "/name"), ("CURRENT", "/current")],
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "sandbox-agent", bin_name = "sandbox-agent")]
@@ -50,6 +88,14 @@ pub struct SandboxAgentCli {
 
     #[arg(long, short = 'n', global = true)]
     no_token: bool,
+
+    /// Format of JSON results.
+    #[arg(long, short = 'o', global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+
+    /// jq-style path to print from the result, e.g. `.agents[].id`.
+    #[arg(long, global = true)]
+    field: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -65,6 +111,14 @@ pub struct GigacodeCli {
     #[arg(long, short = 'n', global = true)]
     pub no_token: bool,
 
+    /// Format of JSON results.
+    #[arg(long, short = 'o', global = true, value_enum, default_value_t)]
+    pub output: OutputFormat,
+
+    /// jq-style path to print from the result, e.g. `.agents[].id`.
+    #[arg(long, global = true)]
+    pub field: Option<String>,
+
     #[arg(long, global = true)]
     pub yolo: bool,
 }
@@ -72,7 +126,7 @@ pub struct GigacodeCli {
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Run the sandbox agent HTTP server.
-    Server(ServerArgs),
+    Server(Box<ServerArgs>),
     /// Call the HTTP API without writing client code.
     Api(ApiArgs),
     /// EXPERIMENTAL: OpenCode compatibility layer (disabled until ACP Phase 7).
@@ -83,6 +137,46 @@ pub enum Command {
     InstallAgent(InstallAgentArgs),
     /// Inspect locally discovered credentials.
     Credentials(CredentialsArgs),
+    /// Serve the daemon's MCP tools on stdio, for agents that only launch local MCP servers.
+    Mcp(McpArgs),
+    /// Speak ACP on stdio for an agent run by the daemon, for editors that launch ACP agents.
+    Acp(AcpBridgeArgs),
+    /// Run the scriptable mock ACP agent on stdio. Spawned for `agent=mock`.
+    #[command(hide = true)]
+    MockAgent,
+    /// Exec a command under resource limits. Wraps agent processes started with limits.
+    #[command(hide = true)]
+    LimitExec(LimitExecArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct LimitExecArgs {
+    /// Join this cgroup v2 directory before exec.
+    #[arg(long)]
+    cgroup: Option<PathBuf>,
+
+    /// Limit the data segment to this many MiB when no cgroup is given.
+    #[arg(long = "memory-mb")]
+    memory_mb: Option<u64>,
+
+    /// Limit the number of processes when no cgroup is given.
+    #[arg(long)]
+    processes: Option<u64>,
+
+    /// Confine the filesystem with Landlock and seccomp: `workspace` or `strict`.
+    #[arg(long = "sandbox-level", value_parser = ["workspace", "strict"])]
+    sandbox_level: Option<String>,
+
+    /// Readable at `strict`. Repeatable.
+    #[arg(long = "sandbox-read", requires = "sandbox_level")]
+    sandbox_read: Vec<PathBuf>,
+
+    /// Writable when confined. Repeatable.
+    #[arg(long = "sandbox-write", requires = "sandbox_level")]
+    sandbox_write: Vec<PathBuf>,
+
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
 }
 
 #[derive(Args, Debug)]
@@ -107,6 +201,88 @@ pub struct ServerArgs {
 
     #[arg(long = "no-telemetry")]
     no_telemetry: bool,
+
+    /// Confine filesystem access to this directory. Repeatable; relative paths resolve
+    /// against the first root.
+    #[arg(long = "fs-root")]
+    fs_root: Vec<PathBuf>,
+
+    /// Store workspace checkpoints in this directory instead of the user data directory.
+    #[arg(long = "checkpoint-dir")]
+    checkpoint_dir: Option<PathBuf>,
+
+    /// Append the audit log to this JSONL file instead of the user data directory.
+    #[arg(long = "audit-log")]
+    audit_log: Option<PathBuf>,
+
+    /// Cache skill sources fetched by /v1/skills/install in this directory instead of the user
+    /// data directory.
+    #[arg(long = "skills-cache-dir")]
+    skills_cache_dir: Option<PathBuf>,
+
+    /// Keep the workspace registry and cloned workspaces in this directory instead of the user
+    /// data directory.
+    #[arg(long = "workspaces-dir")]
+    workspaces_dir: Option<PathBuf>,
+
+    /// Answer /v1/chat/completions requests whose `model` names no agent with this agent.
+    /// Defaults to `SANDBOX_AGENT_CHAT_AGENT`.
+    #[arg(long = "chat-agent", value_name = "AGENT")]
+    chat_agent: Option<String>,
+
+    /// Model for /v1/chat/completions requests answered by the default chat agent. Defaults to
+    /// `SANDBOX_AGENT_CHAT_MODEL`.
+    #[arg(long = "chat-model", value_name = "MODEL")]
+    chat_model: Option<String>,
+
+    /// Publish every ACP server event to `kafka://host:port[,host:port]` or
+    /// `nats://[user:pass@]host:port`. Defaults to `SANDBOX_AGENT_EVENT_SINK`.
+    #[arg(long = "event-sink", value_name = "URL")]
+    event_sink: Option<String>,
+
+    /// Kafka topic or NATS subject for `--event-sink`, with optional `{agent}` and `{serverId}`
+    /// placeholders. Defaults to `SANDBOX_AGENT_EVENT_SINK_TOPIC`, then `sandbox-agent.events`.
+    #[arg(long = "event-sink-topic", value_name = "TEMPLATE")]
+    event_sink_topic: Option<String>,
+
+    /// Keep events not yet acknowledged by the broker in this directory instead of the user
+    /// data directory.
+    #[arg(long = "event-sink-outbox", value_name = "DIR")]
+    event_sink_outbox: Option<PathBuf>,
+
+    /// Allow /v1/proxy to reach this port or `start-end` range on 127.0.0.1. Repeatable; the
+    /// proxy is off when no ports are allowed.
+    #[arg(long = "proxy-port", value_parser = crate::port_proxy::parse_port_range)]
+    proxy_port: Vec<RangeInclusive<u16>>,
+
+    /// Load named bearer tokens with scopes from this JSON file. Without it they are read
+    /// from `SANDBOX_AGENT_TOKENS`.
+    #[arg(long = "token-file")]
+    token_file: Option<PathBuf>,
+
+    /// Serve HTTPS with this PEM certificate chain. Requires `--tls-key`.
+    #[arg(long = "tls-cert", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for `--tls-cert`.
+    #[arg(long = "tls-key", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Require client certificates signed by a CA in this PEM file (mTLS).
+    #[arg(long = "tls-client-ca", requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// Allow each bearer token this many /v1 requests per minute. Off by default.
+    #[arg(long = "rate-limit", value_name = "REQUESTS_PER_MINUTE", value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit: Option<u32>,
+
+    /// Largest request body accepted by /v1/fs writes.
+    #[arg(long = "max-fs-body-bytes", default_value_t = DEFAULT_MAX_FS_BODY_BYTES)]
+    max_fs_body_bytes: u64,
+
+    /// Largest request body accepted by other /v1 endpoints, including ACP messages.
+    #[arg(long = "max-message-bytes", default_value_t = DEFAULT_MAX_MESSAGE_BYTES)]
+    max_message_bytes: u64,
 }
 
 #[derive(Args, Debug)]
@@ -199,6 +375,8 @@ pub enum ApiCommand {
     Agents(AgentsArgs),
     /// Send and stream raw ACP JSON-RPC envelopes.
     Acp(AcpArgs),
+    /// Run one prompt on a new server and wait for the result.
+    Runs(RunsArgs),
 }
 
 #[derive(Subcommand, Debug)]
@@ -220,8 +398,12 @@ pub struct AgentsArgs {
 pub enum AgentsCommand {
     /// List all agents and install status.
     List(ClientArgs),
+    /// List the models an agent offers.
+    Models(ApiAgentModelsArgs),
     /// Install or reinstall an agent.
     Install(ApiInstallAgentArgs),
+    /// Remove an installed agent. Fails while ACP servers are running it.
+    Uninstall(ApiUninstallAgentArgs),
 }
 
 #[derive(Args, Debug)]
@@ -232,14 +414,81 @@ pub struct AcpArgs {
 
 #[derive(Subcommand, Debug)]
 pub enum AcpCommand {
+    /// List ACP servers.
+    List(AcpListArgs),
     /// Send one ACP JSON-RPC envelope to /v1/acp/{server_id}.
     Post(AcpPostArgs),
     /// Stream ACP JSON-RPC envelopes from /v1/acp/{server_id} SSE.
     Stream(AcpStreamArgs),
+    /// Print a server's events one line each, optionally following new ones.
+    Events(AcpEventsArgs),
     /// Close an ACP server stream.
     Close(AcpCloseArgs),
 }
 
+#[derive(Args, Debug)]
+pub struct RunsArgs {
+    #[command(subcommand)]
+    command: RunsCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RunsCommand {
+    /// POST /v1/runs and print the result. Exits non-zero unless the turn ended with end_turn.
+    Create(RunsCreateArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct RunsCreateArgs {
+    #[arg(long)]
+    agent: String,
+    /// Prompt text; read from stdin when neither --prompt nor --prompt-file is given.
+    #[arg(long)]
+    prompt: Option<String>,
+    #[arg(long = "prompt-file")]
+    prompt_file: Option<PathBuf>,
+    #[arg(long)]
+    model: Option<String>,
+    #[arg(long)]
+    directory: Option<String>,
+    #[arg(long = "server-id")]
+    server_id: Option<String>,
+    #[arg(long = "timeout-ms")]
+    timeout_ms: Option<u64>,
+    /// Keep the server running after the run.
+    #[arg(long = "keep-server")]
+    keep_server: bool,
+    /// JSON Schema file the reply must match; the parsed reply is printed as structuredOutput.
+    #[arg(long = "output-schema")]
+    output_schema: Option<PathBuf>,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct McpArgs {
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AcpBridgeArgs {
+    /// Agent to run, such as `claude` or `codex`.
+    #[arg(long)]
+    agent: String,
+    /// ACP server to create or join; a new one by default.
+    #[arg(long = "server-id")]
+    server_id: Option<String>,
+    /// Working directory for the agent process and the default session/new cwd.
+    #[arg(long)]
+    directory: Option<String>,
+    /// Keep the server running after the editor disconnects.
+    #[arg(long = "keep-server")]
+    keep_server: bool,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
 #[derive(Args, Debug, Clone)]
 pub struct ClientArgs {
     #[arg(long, short = 'e')]
@@ -259,6 +508,37 @@ pub struct ApiInstallAgentArgs {
     client: ClientArgs,
 }
 
+#[derive(Args, Debug)]
+pub struct ApiAgentModelsArgs {
+    agent: String,
+    /// Fetch the model list from the agent's backend first.
+    #[arg(long)]
+    refresh: bool,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ApiUninstallAgentArgs {
+    agent: String,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AcpListArgs {
+    #[arg(long)]
+    agent: Option<String>,
+    /// `key=value` label the servers must carry; repeat for several.
+    #[arg(long)]
+    label: Vec<String>,
+    /// List archived servers instead of running ones.
+    #[arg(long)]
+    archived: bool,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
 #[derive(Args, Debug)]
 pub struct AcpPostArgs {
     #[arg(long = "server-id")]
@@ -269,6 +549,10 @@ pub struct AcpPostArgs {
     json: Option<String>,
     #[arg(long = "json-file")]
     json_file: Option<PathBuf>,
+    #[arg(long, conflicts_with = "stream")]
+    wait: bool,
+    #[arg(long)]
+    stream: bool,
     #[command(flatten)]
     client: ClientArgs,
 }
@@ -279,6 +563,36 @@ pub struct AcpStreamArgs {
     server_id: String,
     #[arg(long = "last-event-id")]
     last_event_id: Option<u64>,
+    /// Reconnect attempts after a dropped stream; 0 exits on the first disconnect.
+    #[arg(long, default_value_t = 5)]
+    retry: u32,
+    /// Delay before the first reconnect, doubled on each consecutive failure.
+    #[arg(long = "retry-delay-ms", default_value_t = 500)]
+    retry_delay_ms: u64,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AcpEventsArgs {
+    #[arg(long = "server-id")]
+    server_id: String,
+    /// Keep printing new events as they arrive, reconnecting when the stream drops.
+    #[arg(long, short = 'f')]
+    follow: bool,
+    /// Only print events after this sequence.
+    #[arg(long = "last-event-id")]
+    last_event_id: Option<u64>,
+    /// Reconnect attempts after a dropped stream; 0 exits on the first disconnect.
+    #[arg(long, default_value_t = 5)]
+    retry: u32,
+    /// Delay before the first reconnect, doubled on each consecutive failure.
+    #[arg(long = "retry-delay-ms", default_value_t = 500)]
+    retry_delay_ms: u64,
+    /// Never color the output. Colors are also off when stdout is not a terminal or NO_COLOR
+    /// is set.
+    #[arg(long = "no-color")]
+    no_color: bool,
     #[command(flatten)]
     client: ClientArgs,
 }
@@ -336,6 +650,10 @@ pub enum CliError {
     InvalidCorsMethod(String),
     #[error("invalid cors header: {0}")]
     InvalidCorsHeader(String),
+    #[error("invalid tokens: {0}")]
+    InvalidTokens(String),
+    #[error("invalid tls config: {0}")]
+    InvalidTls(String),
     #[error("http error: {0}")]
     Http(#[from] reqwest::Error),
     #[error("io error: {0}")]
@@ -346,12 +664,16 @@ pub enum CliError {
     Server(String),
     #[error("unexpected http status: {0}")]
     HttpStatus(reqwest::StatusCode),
+    #[error("turn ended with stop reason: {0}")]
+    TurnEnded(String),
 }
 
 pub struct CliConfig {
     pub token: Option<String>,
     pub no_token: bool,
     pub gigacode: bool,
+    pub output: OutputFormat,
+    pub field: Option<String>,
 }
 
 pub fn run_sandbox_agent() -> Result<(), CliError> {
@@ -360,12 +682,16 @@ pub fn run_sandbox_agent() -> Result<(), CliError> {
         command,
         token,
         no_token,
+        output,
+        field,
     } = cli;
 
     let config = CliConfig {
         token,
         no_token,
         gigacode: false,
+        output,
+        field,
     };
 
     if let Err(err) = init_logging(&command) {
@@ -383,12 +709,13 @@ pub fn init_logging(command: &Command) -> Result<(), CliError> {
 
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
     tracing_subscriber::registry()
-        .with(filter)
         .with(
             tracing_logfmt::builder()
                 .layer()
-                .with_writer(std::io::stderr),
+                .with_writer(std::io::stderr)
+                .with_filter(filter),
         )
+        .with(crate::debug_logs::layer())
         .init();
     Ok(())
 }
@@ -399,17 +726,39 @@ pub fn run_command(command: &Command, cli: &CliConfig) -> Result<(), CliError> {
         Command::Api(subcommand) => run_api(&subcommand.command, cli),
         Command::Opencode(args) => run_opencode(cli, args),
         Command::Daemon(subcommand) => run_daemon(&subcommand.command, cli),
-        Command::InstallAgent(args) => install_agent_local(args),
-        Command::Credentials(subcommand) => run_credentials(&subcommand.command),
+        Command::InstallAgent(args) => install_agent_local(args, cli),
+        Command::Credentials(subcommand) => run_credentials(&subcommand.command, cli),
+        Command::Mcp(args) => run_mcp(args, cli),
+        Command::Acp(args) => run_acp_bridge(args, cli),
+        Command::MockAgent => crate::mock_agent::run().map_err(CliError::from),
+        Command::LimitExec(args) => run_limit_exec(args),
     }
 }
 
+fn run_limit_exec(args: &LimitExecArgs) -> Result<(), CliError> {
+    let confinement = args.sandbox_level.as_deref().map(|level| Confinement {
+        strict: level == "strict",
+        read: args.sandbox_read.clone(),
+        write: args.sandbox_write.clone(),
+    });
+    Err(CliError::from(crate::resource_limits::exec_limited(
+        args.cgroup.as_deref(),
+        args.memory_mb,
+        args.processes,
+        confinement.as_ref(),
+        &args.command,
+    )))
+}
+
 fn run_server(cli: &CliConfig, server: &ServerArgs) -> Result<(), CliError> {
     let auth = if let Some(token) = cli.token.clone() {
         AuthConfig::with_token(token)
     } else {
         AuthConfig::disabled()
     };
+    let tokens = crate::auth_tokens::load_tokens(server.token_file.as_deref())
+        .map_err(CliError::InvalidTokens)?;
+    let auth = auth.with_tokens(tokens);
 
     let branding = if cli.gigacode {
         BrandingMode::Gigacode
@@ -417,20 +766,92 @@ fn run_server(cli: &CliConfig, server: &ServerArgs) -> Result<(), CliError> {
         BrandingMode::SandboxAgent
     };
 
+    crate::agent_sandbox::SandboxLevel::from_env().map_err(CliError::Server)?;
+
     let agent_manager = AgentManager::new(default_install_dir())
         .map_err(|err| CliError::Server(err.to_string()))?;
-    let state = Arc::new(AppState::with_branding(auth, agent_manager, branding));
+    let mut fs_roots = Vec::with_capacity(server.fs_root.len());
+    for root in &server.fs_root {
+        let canonical = std::fs::canonicalize(root).map_err(|err| {
+            CliError::Server(format!("invalid --fs-root {}: {err}", root.display()))
+        })?;
+        if !canonical.is_dir() {
+            return Err(CliError::Server(format!(
+                "invalid --fs-root {}: not a directory",
+                root.display()
+            )));
+        }
+        fs_roots.push(canonical);
+    }
+    let mut state = AppState::with_branding(auth, agent_manager, branding)
+        .with_fs_roots(fs_roots)
+        .with_proxy_ports(server.proxy_port.clone())
+        .with_request_limits(RequestLimits {
+            rate_limit_per_minute: server.rate_limit,
+            max_fs_body_bytes: server.max_fs_body_bytes,
+            max_message_bytes: server.max_message_bytes,
+        });
+    if let Some(dir) = server.checkpoint_dir.clone() {
+        state = state.with_checkpoint_dir(dir);
+    }
+    if let Some(path) = server.audit_log.clone() {
+        state = state.with_audit_log(path);
+    }
+    if let Some(dir) = server.skills_cache_dir.clone() {
+        state = state.with_skills_cache_dir(dir);
+    }
+    if let Some(dir) = server.workspaces_dir.clone() {
+        state = state.with_workspaces_dir(dir);
+    }
+    if let Some(agent) = server.chat_agent.as_deref() {
+        let agent = AgentId::parse(agent).ok_or_else(|| {
+            CliError::Server(format!("invalid --chat-agent: unsupported agent {agent}"))
+        })?;
+        state = state.with_chat_agent(agent);
+    }
+    if let Some(model) = server.chat_model.clone() {
+        state = state.with_chat_model(model);
+    }
+    if let Some(url) = server.event_sink.as_deref() {
+        let target = parse_target(url)
+            .map_err(|err| CliError::Server(format!("invalid --event-sink: {err}")))?;
+        state = state.with_event_sink(target);
+    }
+    if let Some(topic) = server.event_sink_topic.clone() {
+        state = state.with_event_sink_topic(topic);
+    }
+    if let Some(dir) = server.event_sink_outbox.clone() {
+        state = state.with_event_sink_outbox(dir);
+    }
+    let state = Arc::new(state);
     let (mut router, state) = build_router_with_state(state);
 
     let cors = build_cors_layer(server)?;
     router = router.layer(cors);
 
+    let tls_config = match (&server.tls_cert, &server.tls_key) {
+        (Some(cert), Some(key)) => Some(
+            crate::tls::load_server_config(&TlsOptions {
+                cert: cert.clone(),
+                key: key.clone(),
+                client_ca: server.tls_client_ca.clone(),
+            })
+            .map_err(CliError::InvalidTls)?,
+        ),
+        _ => None,
+    };
+    let scheme = if tls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+
     let addr = format!("{}:{}", server.host, server.port);
     let display_host = match server.host.as_str() {
         "0.0.0.0" | "::" => "localhost",
         other => other,
     };
-    let inspector_url = format!("http://{}:{}/ui", display_host, server.port);
+    let inspector_url = format!("{scheme}://{}:{}/ui", display_host, server.port);
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
@@ -445,19 +866,35 @@ fn run_server(cli: &CliConfig, server: &ServerArgs) -> Result<(), CliError> {
         }
 
         let listener = tokio::net::TcpListener::bind(&addr).await?;
-        tracing::info!(addr = %addr, "server listening");
+        tracing::info!(addr = %addr, scheme = scheme, "server listening");
         if ui::is_enabled() {
             tracing::info!(url = %inspector_url, "inspector ui available");
         }
 
+        start_event_sink(&state)
+            .map_err(|err| CliError::Server(format!("failed to open event sink outbox: {err}")))?;
+        state.mark_serving();
+        start_model_catalog_refresh(&state);
+        let startup_state = state.clone();
+        tokio::spawn(async move {
+            load_persisted_state(&startup_state).await;
+            prewarm_servers(&startup_state).await;
+        });
+
         let shutdown_state = state.clone();
-        axum::serve(listener, router)
-            .with_graceful_shutdown(async move {
-                let _ = tokio::signal::ctrl_c().await;
-                shutdown_servers(&shutdown_state).await;
-            })
-            .await
-            .map_err(|err| CliError::Server(err.to_string()))
+        let shutdown = async move {
+            let _ = tokio::signal::ctrl_c().await;
+            shutdown_servers(&shutdown_state).await;
+        };
+        match tls_config {
+            Some(config) => crate::tls::serve(listener, config, router, shutdown).await,
+            None => {
+                axum::serve(listener, router)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }
+        }
+        .map_err(|err| CliError::Server(err.to_string()))
     })
 }
 
@@ -465,6 +902,7 @@ fn run_api(command: &ApiCommand, cli: &CliConfig) -> Result<(), CliError> {
     match command {
         ApiCommand::Agents(subcommand) => run_agents(&subcommand.command, cli),
         ApiCommand::Acp(subcommand) => run_acp(&subcommand.command, cli),
+        ApiCommand::Runs(subcommand) => run_runs(&subcommand.command, cli),
     }
 }
 
@@ -472,8 +910,26 @@ fn run_agents(command: &AgentsCommand, cli: &CliConfig) -> Result<(), CliError>
     match command {
         AgentsCommand::List(args) => {
             let ctx = ClientContext::new(cli, args)?;
-            let result = call_acp_extension(&ctx, ACP_EXTENSION_AGENT_LIST_METHOD, json!({}))?;
-            write_stdout_line(&serde_json::to_string_pretty(&result)?)
+            let response = ctx.get(&format!("{API_PREFIX}/agents"))?;
+            let result = json_response(response)?;
+            print_result(cli, &result, Some(&AGENTS_TABLE))
+        }
+        AgentsCommand::Models(args) => {
+            let agent = args.agent.trim();
+            if agent.is_empty() || agent.contains('/') {
+                return Err(CliError::Server(format!("invalid agent: {}", args.agent)));
+            }
+            let ctx = ClientContext::new(cli, &args.client)?;
+            if args.refresh {
+                let response = ctx.post(
+                    &format!("{API_PREFIX}/agents/{agent}/models/refresh"),
+                    &json!({}),
+                )?;
+                json_response(response)?;
+            }
+            let response = ctx.get(&format!("{API_PREFIX}/agents/{agent}?config=true"))?;
+            let info = json_response(response)?;
+            print_result(cli, &agent_models(agent, &info), Some(&MODELS_TABLE))
         }
         AgentsCommand::Install(args) => {
             let ctx = ClientContext::new(cli, &args.client)?;
@@ -493,11 +949,55 @@ fn run_agents(command: &AgentsCommand, cli: &CliConfig) -> Result<(), CliError>
                 ACP_EXTENSION_AGENT_INSTALL_METHOD,
                 Value::Object(params),
             )?;
-            write_stdout_line(&serde_json::to_string_pretty(&result)?)
+            print_result(cli, &result, None)
+        }
+        AgentsCommand::Uninstall(args) => {
+            let agent = args.agent.trim();
+            if agent.is_empty() || agent.contains('/') {
+                return Err(CliError::Server(format!("invalid agent: {}", args.agent)));
+            }
+            let ctx = ClientContext::new(cli, &args.client)?;
+            let response = ctx.delete(&format!("{API_PREFIX}/agents/{agent}"))?;
+            print_json_or_empty(cli, response)
         }
     }
 }
 
+/// `{agent, currentModel, models: [{id, name, current}]}` from the agent's `model` config
+/// option.
+fn agent_models(agent: &str, info: &Value) -> Value {
+    let option = info
+        .get("configOptions")
+        .and_then(Value::as_array)
+        .and_then(|options| {
+            options
+                .iter()
+                .find(|option| option.get("category").and_then(Value::as_str) == Some("model"))
+        });
+    let current = option.and_then(|option| option.get("currentValue"));
+    let models = option
+        .and_then(|option| option.get("options"))
+        .and_then(Value::as_array)
+        .map(|models| {
+            models
+                .iter()
+                .map(|model| {
+                    json!({
+                        "id": model.get("value"),
+                        "name": model.get("name"),
+                        "current": current.is_some() && model.get("value") == current,
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    json!({
+        "agent": agent,
+        "currentModel": current,
+        "models": models,
+    })
+}
+
 fn call_acp_extension(ctx: &ClientContext, method: &str, params: Value) -> Result<Value, CliError> {
     let server_id = unique_cli_server_id("cli-ext");
     let initialize_path = build_acp_server_path(&server_id, Some("mock"))?;
@@ -557,24 +1057,90 @@ fn call_acp_extension(ctx: &ClientContext, method: &str, params: Value) -> Resul
 
 fn run_acp(command: &AcpCommand, cli: &CliConfig) -> Result<(), CliError> {
     match command {
-        AcpCommand::Post(args) => {
+        AcpCommand::List(args) => {
             let ctx = ClientContext::new(cli, &args.client)?;
+            let mut query = Vec::new();
+            if let Some(agent) = &args.agent {
+                query.push(("agent", agent.clone()));
+            }
+            if !args.label.is_empty() {
+                query.push(("label", args.label.join(",")));
+            }
+            if args.archived {
+                query.push(("archived", "true".to_string()));
+            }
+            let response = ctx
+                .request(Method::GET, &format!("{API_PREFIX}/acp"))
+                .query(&query)
+                .send()?;
+            let result = json_response(response)?;
+            print_result(cli, &result, Some(&SERVERS_TABLE))
+        }
+        AcpCommand::Post(args) => {
             let payload = load_json_payload(args.json.as_deref(), args.json_file.as_deref())?;
             let path = build_acp_server_path(&args.server_id, args.agent.as_deref())?;
+            if args.wait || args.stream {
+                // A prompt turn lasts as long as the agent works on it.
+                let ctx = ClientContext::streaming(cli, &args.client)?;
+                return run_acp_prompt_turn(&ctx, &args.server_id, &path, payload, args.stream);
+            }
+            let ctx = ClientContext::new(cli, &args.client)?;
             let response = ctx.post(&path, &payload)?;
-            print_json_or_empty(response)
+            print_json_or_empty(cli, response)
         }
         AcpCommand::Stream(args) => {
-            let ctx = ClientContext::new(cli, &args.client)?;
+            let ctx = ClientContext::streaming(cli, &args.client)?;
             let path = build_acp_server_path(&args.server_id, None)?;
             let request = ctx
                 .request(Method::GET, &path)
                 .header("accept", "text/event-stream");
-
-            let request = apply_last_event_id_header(request, args.last_event_id);
-
+            let retry_delay = Duration::from_millis(args.retry_delay_ms);
+            run_acp_stream(
+                request,
+                args.last_event_id,
+                args.retry,
+                retry_delay,
+                StreamRender::Raw,
+            )
+        }
+        AcpCommand::Events(args) => {
+            let color = !args.no_color
+                && std::env::var_os("NO_COLOR").is_none()
+                && std::io::stdout().is_terminal();
+            let path = build_acp_server_path(&args.server_id, None)?;
+            if args.follow {
+                let ctx = ClientContext::streaming(cli, &args.client)?;
+                let request = ctx
+                    .request(Method::GET, &path)
+                    .header("accept", "text/event-stream");
+                let retry_delay = Duration::from_millis(args.retry_delay_ms);
+                return run_acp_stream(
+                    request,
+                    args.last_event_id,
+                    args.retry,
+                    retry_delay,
+                    StreamRender::Compact { color },
+                );
+            }
+            let ctx = ClientContext::new(cli, &args.client)?;
+            let mut request = ctx.request(Method::GET, &format!("{path}/events.jsonl"));
+            if let Some(after) = args.last_event_id {
+                request = request.query(&[("after", after)]);
+            }
             let response = request.send()?;
-            print_text_response(response)
+            let status = response.status();
+            let text = response.text()?;
+            if !status.is_success() {
+                print_error_body(&text)?;
+                return Err(CliError::HttpStatus(status));
+            }
+            for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                let event: Value = serde_json::from_str(line)?;
+                let sequence = event.get("sequence").and_then(Value::as_u64).unwrap_or(0);
+                let message = event.get("message").unwrap_or(&Value::Null);
+                write_stdout_line(&event_line(sequence, message, color))?;
+            }
+            Ok(())
         }
         AcpCommand::Close(args) => {
             let ctx = ClientContext::new(cli, &args.client)?;
@@ -585,6 +1151,511 @@ fn run_acp(command: &AcpCommand, cli: &CliConfig) -> Result<(), CliError> {
     }
 }
 
+fn run_runs(command: &RunsCommand, cli: &CliConfig) -> Result<(), CliError> {
+    match command {
+        RunsCommand::Create(args) => {
+            let text = match (&args.prompt, &args.prompt_file) {
+                (Some(prompt), _) => prompt.clone(),
+                (None, Some(path)) => std::fs::read_to_string(path)?,
+                (None, None) => std::io::read_to_string(std::io::stdin())?,
+            };
+            if text.trim().is_empty() {
+                return Err(CliError::Server("prompt must not be empty".to_string()));
+            }
+            let mut body = serde_json::Map::new();
+            body.insert("agent".to_string(), Value::String(args.agent.clone()));
+            body.insert(
+                "prompt".to_string(),
+                json!([{ "type": "text", "text": text }]),
+            );
+            if let Some(model) = &args.model {
+                body.insert("model".to_string(), Value::String(model.clone()));
+            }
+            if let Some(directory) = &args.directory {
+                body.insert("directory".to_string(), Value::String(directory.clone()));
+            }
+            if let Some(server_id) = &args.server_id {
+                body.insert("serverId".to_string(), Value::String(server_id.clone()));
+            }
+            if let Some(timeout_ms) = args.timeout_ms {
+                body.insert("timeoutMs".to_string(), Value::from(timeout_ms));
+            }
+            if args.keep_server {
+                body.insert("keepServer".to_string(), Value::Bool(true));
+            }
+            if let Some(path) = &args.output_schema {
+                let schema: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+                body.insert("outputSchema".to_string(), schema);
+            }
+
+            // The request blocks for the whole turn.
+            let ctx = ClientContext::streaming(cli, &args.client)?;
+            let response = ctx.post(&format!("{API_PREFIX}/runs"), &Value::Object(body))?;
+            let result = json_response(response)?;
+            print_result(cli, &result, None)?;
+            run_outcome(&result)
+        }
+    }
+}
+
+/// Maps a `/v1/runs` result to the exit status: only a turn that ended with `end_turn` succeeds.
+fn run_outcome(result: &Value) -> Result<(), CliError> {
+    let error = || {
+        result
+            .get("error")
+            .and_then(Value::as_str)
+            .unwrap_or("no error reported")
+            .to_string()
+    };
+    match result.get("status").and_then(Value::as_str) {
+        Some("completed") => match result.get("stopReason").and_then(Value::as_str) {
+            Some("end_turn") | None => Ok(()),
+            Some(reason) => Err(CliError::TurnEnded(reason.to_string())),
+        },
+        Some("timedOut") => Err(CliError::Server(format!("run timed out: {}", error()))),
+        _ => Err(CliError::Server(format!("run failed: {}", error()))),
+    }
+}
+
+enum StreamMessage {
+    Done(Result<(), CliError>),
+    Interrupted,
+}
+
+/// How `run_acp_stream` prints events: as the raw SSE frames, or one line each.
+#[derive(Debug, Clone, Copy)]
+enum StreamRender {
+    Raw,
+    Compact { color: bool },
+}
+
+/// Follows an ACP SSE stream until it ends or Ctrl-C is pressed, printing each event as it
+/// arrives and reconnecting from the last complete event when the connection drops.
+fn run_acp_stream(
+    request: reqwest::blocking::RequestBuilder,
+    last_event_id: Option<u64>,
+    retry: u32,
+    retry_delay: Duration,
+    render: StreamRender,
+) -> Result<(), CliError> {
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    let signal_tx = tx.clone();
+    std::thread::spawn(move || {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        else {
+            return;
+        };
+        if runtime.block_on(tokio::signal::ctrl_c()).is_ok() {
+            let _ = signal_tx.send(StreamMessage::Interrupted);
+        }
+    });
+
+    std::thread::spawn(move || {
+        let result = follow_acp_stream(request, last_event_id, retry, retry_delay, render);
+        let _ = tx.send(StreamMessage::Done(result));
+    });
+
+    match rx.recv() {
+        Ok(StreamMessage::Done(result)) => result,
+        Ok(StreamMessage::Interrupted) | Err(_) => Ok(()),
+    }
+}
+
+fn follow_acp_stream(
+    request: reqwest::blocking::RequestBuilder,
+    mut last_event_id: Option<u64>,
+    retry: u32,
+    retry_delay: Duration,
+    render: StreamRender,
+) -> Result<(), CliError> {
+    let mut failures = 0u32;
+    loop {
+        let attempt = request
+            .try_clone()
+            .ok_or_else(|| CliError::Server("stream request cannot be retried".to_string()))?;
+        let disconnect = match apply_last_event_id_header(attempt, last_event_id).send() {
+            Ok(response) if response.status().is_success() => {
+                match copy_sse_events(response, &mut last_event_id, &mut failures, render) {
+                    Ok(()) => None,
+                    Err(err) => Some(CliError::from(err)),
+                }
+            }
+            Ok(response) => {
+                let status = response.status();
+                print_error_body(&response.text()?)?;
+                return Err(CliError::HttpStatus(status));
+            }
+            Err(err) => Some(CliError::from(err)),
+        };
+
+        if failures >= retry {
+            return disconnect.map_or(Ok(()), Err);
+        }
+        failures += 1;
+        let reason = disconnect.map_or_else(|| "stream closed".to_string(), |err| err.to_string());
+        let resume = last_event_id.map_or_else(|| "start".to_string(), |id| format!("event {id}"));
+        write_stderr_line(&format!(
+            "{reason}; reconnecting from {resume} ({failures}/{retry})"
+        ))?;
+        std::thread::sleep(stream_retry_delay(retry_delay, failures));
+    }
+}
+
+/// Copies complete SSE frames to stdout. An event's id is only recorded once its terminating
+/// blank line arrives, so a frame cut off by a disconnect is replayed rather than skipped.
+fn copy_sse_events(
+    response: reqwest::blocking::Response,
+    last_event_id: &mut Option<u64>,
+    failures: &mut u32,
+    render: StreamRender,
+) -> std::io::Result<()> {
+    let mut frame = String::new();
+    let mut frame_id = None;
+    let mut resync = false;
+    let mut data = String::new();
+    for line in std::io::BufReader::new(response).lines() {
+        let line = line?;
+        if !line.is_empty() {
+            if let Some(id) = line.strip_prefix("id:") {
+                frame_id = id.trim().parse::<u64>().ok();
+            } else if let Some(name) = line.strip_prefix("event:") {
+                resync = name.trim() == RESYNC_EVENT;
+            } else if let Some(chunk) = line.strip_prefix("data:") {
+                data.push_str(chunk.trim_start());
+            }
+            frame.push_str(&line);
+            frame.push('\n');
+            continue;
+        }
+        if frame.is_empty() {
+            continue;
+        }
+        frame.push('\n');
+        let text = match render {
+            StreamRender::Raw => Some(frame.clone()),
+            // Heartbeat comments, resync markers and frames without JSON data have nothing
+            // to show.
+            StreamRender::Compact { color } => serde_json::from_str::<Value>(&data)
+                .ok()
+                .filter(|_| !resync)
+                .map(|message| event_line(frame_id.unwrap_or(0), &message, color) + "\n"),
+        };
+        if let Some(text) = text {
+            let mut out = std::io::stdout().lock();
+            out.write_all(text.as_bytes())?;
+            out.flush()?;
+        }
+        frame.clear();
+        data.clear();
+        resync = false;
+        if let Some(id) = frame_id.take() {
+            *last_event_id = Some(id);
+            *failures = 0;
+        }
+    }
+    Ok(())
+}
+
+fn stream_retry_delay(base: Duration, failures: u32) -> Duration {
+    base.saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(STREAM_RETRY_MAX_DELAY)
+}
+
+enum TurnMessage {
+    Event { id: u64, payload: Value },
+    Finished(Result<(reqwest::StatusCode, String), CliError>),
+}
+
+/// Posts a `session/prompt` request while following the server's SSE stream, printing the
+/// assistant's text for that session and mapping the turn's stop reason to the exit status.
+fn run_acp_prompt_turn(
+    ctx: &ClientContext,
+    server_id: &str,
+    path: &str,
+    payload: Value,
+    stream: bool,
+) -> Result<(), CliError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/prompt")
+        || payload.get("id").is_none()
+    {
+        return Err(CliError::Server(
+            "--wait and --stream require a session/prompt request with an id".to_string(),
+        ));
+    }
+    let session_id = payload
+        .pointer("/params/sessionId")
+        .and_then(Value::as_str)
+        .ok_or_else(|| CliError::Server("session/prompt requires params.sessionId".to_string()))?
+        .to_string();
+
+    let stream_path = build_acp_server_path(server_id, None)?;
+    let start_event_id = current_acp_event_id(ctx, server_id)?;
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    let sse_request = apply_last_event_id_header(
+        ctx.request(Method::GET, &stream_path)
+            .header("accept", "text/event-stream"),
+        start_event_id,
+    );
+    let sse_tx = tx.clone();
+    std::thread::spawn(move || {
+        let Ok(response) = sse_request.send() else {
+            return;
+        };
+        if !response.status().is_success() {
+            return;
+        }
+        let mut event_id = None;
+        let mut resync = false;
+        for line in std::io::BufReader::new(response).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if let Some(id) = line.strip_prefix("id:") {
+                event_id = id.trim().parse::<u64>().ok();
+            } else if let Some(name) = line.strip_prefix("event:") {
+                resync = name.trim() == RESYNC_EVENT;
+            } else if let Some(data) = line.strip_prefix("data:") {
+                if resync {
+                    continue;
+                }
+                let (Some(id), Ok(payload)) = (event_id, serde_json::from_str(data.trim())) else {
+                    continue;
+                };
+                if sse_tx.send(TurnMessage::Event { id, payload }).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    let post_request = ctx.request(Method::POST, path).json(&payload);
+    std::thread::spawn(move || {
+        let result = post_request
+            .send()
+            .and_then(|response| {
+                let status = response.status();
+                response.text().map(|text| (status, text))
+            })
+            .map_err(CliError::from);
+        let _ = tx.send(TurnMessage::Finished(result));
+    });
+
+    let mut reply = String::new();
+    let mut last_seen = start_event_id.unwrap_or(0);
+    let mut finished = None;
+    let mut drain_until = None;
+    loop {
+        let message = match (drain_until, finished.is_some()) {
+            (Some(target), _) if last_seen >= target => break,
+            (_, true) => match rx.recv_timeout(TURN_DRAIN_TIMEOUT) {
+                Ok(message) => message,
+                Err(_) => break,
+            },
+            _ => match rx.recv() {
+                Ok(message) => message,
+                Err(_) => break,
+            },
+        };
+        match message {
+            TurnMessage::Event { id, payload } => {
+                last_seen = last_seen.max(id);
+                if let Some(text) = assistant_text_delta(&payload, &session_id) {
+                    if stream {
+                        write_stdout(text)?;
+                    } else {
+                        reply.push_str(text);
+                    }
+                }
+            }
+            TurnMessage::Finished(result) => {
+                finished = Some(result);
+                drain_until = current_acp_event_id(ctx, server_id)?;
+                if drain_until.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+
+    if stream && last_seen > start_event_id.unwrap_or(0) {
+        write_stdout("\n")?;
+    } else if !reply.is_empty() {
+        write_stdout_line(&reply)?;
+    }
+
+    let Some(result) = finished else {
+        return Err(CliError::Server(
+            "prompt request ended without a response".to_string(),
+        ));
+    };
+    let (status, text) = result?;
+    if !status.is_success() {
+        print_error_body(&text)?;
+        return Err(CliError::HttpStatus(status));
+    }
+    let response: Value = serde_json::from_str(&text)?;
+    turn_outcome(&response)
+}
+
+fn current_acp_event_id(ctx: &ClientContext, server_id: &str) -> Result<Option<u64>, CliError> {
+    let response = ctx.get(&format!("{API_PREFIX}/acp"))?;
+    let status = response.status();
+    let text = response.text()?;
+    if !status.is_success() {
+        print_error_body(&text)?;
+        return Err(CliError::HttpStatus(status));
+    }
+    let parsed: Value = serde_json::from_str(&text)?;
+    Ok(parsed
+        .get("servers")
+        .and_then(Value::as_array)
+        .and_then(|servers| {
+            servers
+                .iter()
+                .find(|server| server.get("serverId").and_then(Value::as_str) == Some(server_id))
+        })
+        .and_then(|server| server.get("lastEventId"))
+        .and_then(Value::as_u64))
+}
+
+fn assistant_text_delta<'a>(payload: &'a Value, session_id: &str) -> Option<&'a str> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/update") {
+        return None;
+    }
+    let params = payload.get("params")?;
+    if params.get("sessionId").and_then(Value::as_str) != Some(session_id) {
+        return None;
+    }
+    let update = params.get("update")?;
+    if update.get("sessionUpdate").and_then(Value::as_str) != Some("agent_message_chunk") {
+        return None;
+    }
+    let content = update.get("content")?;
+    if content.get("type").and_then(Value::as_str) != Some("text") {
+        return None;
+    }
+    content.get("text").and_then(Value::as_str)
+}
+
+/// One line for an ACP event: its sequence, a label for its type, and a short summary. With
+/// `color`, the label is colored by type: responses green (red for errors), requests yellow,
+/// tool calls cyan, thoughts dim, daemon notifications magenta and other notifications blue.
+fn event_line(sequence: u64, message: &Value, color: bool) -> String {
+    const MAX_DETAIL_CHARS: usize = 160;
+
+    let method = message.get("method").and_then(Value::as_str);
+    let params = message.get("params").unwrap_or(&Value::Null);
+    let (label, detail, code) = match method {
+        None => match message.get("error") {
+            Some(error) => (
+                "error".to_string(),
+                error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| error.to_string()),
+                "31",
+            ),
+            None => (
+                "response".to_string(),
+                message
+                    .pointer("/result/stopReason")
+                    .and_then(Value::as_str)
+                    .map(|reason| format!("stopReason={reason}"))
+                    .unwrap_or_default(),
+                "32",
+            ),
+        },
+        Some("session/update") => {
+            let update = params.get("update").unwrap_or(&Value::Null);
+            let kind = update
+                .get("sessionUpdate")
+                .and_then(Value::as_str)
+                .unwrap_or("update");
+            let text = |pointer: &str| {
+                update
+                    .pointer(pointer)
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            match kind {
+                "agent_message_chunk" | "user_message_chunk" => {
+                    (kind.to_string(), text("/content/text"), "0")
+                }
+                "agent_thought_chunk" => (kind.to_string(), text("/content/text"), "2"),
+                "tool_call" | "tool_call_update" => {
+                    let status = text("/status");
+                    let title = text("/title");
+                    let detail = [title, status]
+                        .into_iter()
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    (kind.to_string(), detail, "36")
+                }
+                _ => (kind.to_string(), String::new(), "34"),
+            }
+        }
+        Some(method) if method.starts_with("_sandboxagent/") => {
+            (method.to_string(), compact_json(params), "35")
+        }
+        Some(method) if message.get("id").is_some() => {
+            let detail = params
+                .pointer("/toolCall/title")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| compact_json(params));
+            (method.to_string(), detail, "33")
+        }
+        Some(method) => (method.to_string(), compact_json(params), "34"),
+    };
+
+    let mut detail = detail.split_whitespace().collect::<Vec<_>>().join(" ");
+    if detail.chars().count() > MAX_DETAIL_CHARS {
+        detail = detail
+            .chars()
+            .take(MAX_DETAIL_CHARS - 1)
+            .collect::<String>()
+            + "…";
+    }
+    let label = format!("{label:<20}");
+    let label = if color && code != "0" {
+        format!("\x1b[{code}m{label}\x1b[0m")
+    } else {
+        label
+    };
+    format!("{sequence:>6}  {label}  {detail}")
+        .trim_end()
+        .to_string()
+}
+
+fn compact_json(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Object(object) if object.is_empty() => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn turn_outcome(response: &Value) -> Result<(), CliError> {
+    if let Some(error) = response.get("error") {
+        write_stderr_line(&serde_json::to_string_pretty(error)?)?;
+        return Err(CliError::Server("session/prompt failed".to_string()));
+    }
+    match response
+        .pointer("/result/stopReason")
+        .and_then(Value::as_str)
+    {
+        Some("end_turn") | None => Ok(()),
+        Some(reason) => Err(CliError::TurnEnded(reason.to_string())),
+    }
+}
+
 fn run_opencode(cli: &CliConfig, args: &OpencodeArgs) -> Result<(), CliError> {
     let token = cli.token.as_deref();
     crate::daemon::ensure_running(cli, &args.host, args.port, token)?;
@@ -648,7 +1719,7 @@ fn run_daemon(command: &DaemonCommand, cli: &CliConfig) -> Result<(), CliError>
     }
 }
 
-fn run_credentials(command: &CredentialsCommand) -> Result<(), CliError> {
+fn run_credentials(command: &CredentialsCommand, cli: &CliConfig) -> Result<(), CliError> {
     match command {
         CredentialsCommand::Extract(args) => {
             let mut options = CredentialExtractionOptions::new();
@@ -672,9 +1743,7 @@ fn run_credentials(command: &CredentialsCommand) -> Result<(), CliError> {
             }
 
             let output = credentials_to_output(credentials, args.reveal);
-            let pretty = serde_json::to_string_pretty(&output)?;
-            write_stdout_line(&pretty)?;
-            Ok(())
+            print_result(cli, &serde_json::to_value(output)?, None)
         }
         CredentialsCommand::ExtractEnv(args) => {
             let mut options = CredentialExtractionOptions::new();
@@ -725,7 +1794,7 @@ fn load_json_payload(
     }
 }
 
-fn install_agent_local(args: &InstallAgentArgs) -> Result<(), CliError> {
+fn install_agent_local(args: &InstallAgentArgs, cli: &CliConfig) -> Result<(), CliError> {
     let agent_id = AgentId::parse(&args.agent)
         .ok_or_else(|| CliError::Server(format!("unsupported agent: {}", args.agent)))?;
 
@@ -753,7 +1822,7 @@ fn install_agent_local(args: &InstallAgentArgs) -> Result<(), CliError> {
         })).collect::<Vec<_>>()
     });
 
-    write_stdout_line(&serde_json::to_string_pretty(&output)?)
+    print_result(cli, &output, None)
 }
 
 #[derive(Serialize)]
@@ -976,7 +2045,7 @@ fn build_acp_server_path(
     Ok(path)
 }
 
-fn default_server_log_dir() -> PathBuf {
+pub(crate) fn default_server_log_dir() -> PathBuf {
     if let Ok(dir) = std::env::var("SANDBOX_AGENT_LOG_DIR") {
         return PathBuf::from(dir);
     }
@@ -1045,6 +2114,310 @@ fn build_cors_layer(server: &ServerArgs) -> Result<CorsLayer, CliError> {
     Ok(cors)
 }
 
+/// Forward newline-delimited MCP messages from stdin to `POST /v1/mcp` and write the replies
+/// to stdout. Failed requests are answered with a JSON-RPC error so the client never waits.
+fn run_mcp(args: &McpArgs, cli: &CliConfig) -> Result<(), CliError> {
+    let ctx = ClientContext::streaming(cli, &args.client)?;
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let id = serde_json::from_str::<Value>(&line)
+            .ok()
+            .and_then(|message| message.get("id").cloned());
+        let failure = match ctx
+            .request(Method::POST, "/v1/mcp")
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(line)
+            .send()
+        {
+            Ok(response) if response.status() == reqwest::StatusCode::ACCEPTED => continue,
+            Ok(response) if response.status().is_success() => {
+                write_stdout_line(response.text()?.trim_end())?;
+                continue;
+            }
+            Ok(response) => format!("sandbox-agent returned {}", response.status()),
+            Err(err) => format!("sandbox-agent is unreachable: {err}"),
+        };
+        write_stderr_line(&failure)?;
+        if let Some(id) = id {
+            let reply = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32603, "message": failure },
+            });
+            write_stdout_line(&reply.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Bridges an editor's stdio ACP connection to `/v1/acp/{server}`. Each message from stdin is
+/// posted there; replies, and the agent's notifications and requests from the server's SSE
+/// stream, are written to stdout. The first message creates the server, which is closed at EOF
+/// unless `--keep-server` is set.
+fn run_acp_bridge(args: &AcpBridgeArgs, cli: &CliConfig) -> Result<(), CliError> {
+    let ctx = ClientContext::streaming(cli, &args.client)?;
+    let server_id = args
+        .server_id
+        .clone()
+        .unwrap_or_else(|| unique_cli_server_id("acp-stdio"));
+    let path = build_acp_server_path(&server_id, None)?;
+    let bootstrap_path = build_acp_server_path(&server_id, Some(&args.agent))?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let writer = std::thread::spawn(move || write_acp_bridge_output(rx));
+
+    let mut streaming = false;
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                write_stderr_line(&format!("ignoring invalid ACP message: {err}"))?;
+                continue;
+            }
+        };
+        let mut request = ctx.request(Method::POST, &bootstrap_path).json(&message);
+        if let Some(directory) = &args.directory {
+            request = request.query(&[("directory", directory)]);
+        }
+        // A prompt holds its request open for the whole turn, while cancellations and
+        // permission replies keep arriving.
+        if streaming && message.get("method").is_some() && message.get("id").is_some() {
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                if let Some(reply) = acp_bridge_reply(&message, post_acp_bridge_message(request)) {
+                    let _ = tx.send(reply);
+                }
+            });
+            continue;
+        }
+        let result = post_acp_bridge_message(request);
+        if result.is_ok() && !streaming {
+            streaming = true;
+            let stream = ctx
+                .request(Method::GET, &path)
+                .header("accept", "text/event-stream");
+            let tx = tx.clone();
+            std::thread::spawn(move || follow_acp_bridge_stream(stream, tx));
+        }
+        if let Some(reply) = acp_bridge_reply(&message, result) {
+            let _ = tx.send(reply);
+        }
+    }
+    let _ = tx.send(AcpBridgeMessage::Closed);
+    let _ = writer.join();
+
+    if streaming && !args.keep_server {
+        let response = ctx.delete(&path)?;
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND {
+            write_stderr_line(&format!(
+                "failed to close ACP server {server_id}: {}",
+                response.status()
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// The daemon's JSON reply to one posted message, `None` when it was accepted without one.
+fn post_acp_bridge_message(
+    request: reqwest::blocking::RequestBuilder,
+) -> Result<Option<Value>, String> {
+    let response = request
+        .send()
+        .map_err(|err| format!("sandbox-agent is unreachable: {err}"))?;
+    let status = response.status();
+    if status == reqwest::StatusCode::ACCEPTED {
+        return Ok(None);
+    }
+    let text = response
+        .text()
+        .map_err(|err| format!("sandbox-agent reply failed: {err}"))?;
+    if !status.is_success() {
+        let detail = serde_json::from_str::<Value>(&text)
+            .ok()
+            .and_then(|problem| problem.get("detail")?.as_str().map(str::to_string));
+        return Err(match detail {
+            Some(detail) => format!("sandbox-agent returned {status}: {detail}"),
+            None => format!("sandbox-agent returned {status}"),
+        });
+    }
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|err| format!("sandbox-agent returned invalid JSON: {err}"))
+}
+
+/// What to write back for a posted message. A failed request is answered with a JSON-RPC
+/// error so the editor never waits; other failures are only reported on stderr.
+fn acp_bridge_reply(
+    message: &Value,
+    result: Result<Option<Value>, String>,
+) -> Option<AcpBridgeMessage> {
+    match result {
+        Ok(reply) => reply.map(AcpBridgeMessage::Reply),
+        Err(failure) => {
+            let _ = write_stderr_line(&failure);
+            let id = message.get("method").and(message.get("id"))?;
+            Some(AcpBridgeMessage::Failure(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32603, "message": failure },
+            })))
+        }
+    }
+}
+
+/// Follows the server's SSE stream for the bridge, resuming after the last event when the
+/// connection drops. Ends when the server is gone or reconnecting keeps failing.
+fn follow_acp_bridge_stream(
+    request: reqwest::blocking::RequestBuilder,
+    tx: std::sync::mpsc::Sender<AcpBridgeMessage>,
+) {
+    let mut last_event_id = None;
+    let mut failures = 0u32;
+    loop {
+        let Some(attempt) = request.try_clone() else {
+            return;
+        };
+        match apply_last_event_id_header(attempt, last_event_id).send() {
+            Ok(response) if response.status().is_success() => {
+                let mut event_id = None;
+                let mut resync = false;
+                for line in std::io::BufReader::new(response).lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    if let Some(id) = line.strip_prefix("id:") {
+                        event_id = id.trim().parse::<u64>().ok();
+                    } else if let Some(name) = line.strip_prefix("event:") {
+                        // Resync markers are followed by the replayed messages themselves.
+                        resync = name.trim() == RESYNC_EVENT;
+                    } else if let Some(data) = line.strip_prefix("data:") {
+                        if resync {
+                            continue;
+                        }
+                        let Ok(payload) = serde_json::from_str(data.trim()) else {
+                            continue;
+                        };
+                        if tx.send(AcpBridgeMessage::Streamed(payload)).is_err() {
+                            return;
+                        }
+                        if let Some(id) = event_id.take() {
+                            last_event_id = Some(id);
+                            failures = 0;
+                        }
+                    }
+                }
+            }
+            Ok(response) => {
+                let _ = write_stderr_line(&format!("ACP stream returned {}", response.status()));
+                return;
+            }
+            Err(_) => {}
+        }
+        if failures >= ACP_BRIDGE_STREAM_RETRY {
+            let _ = write_stderr_line("ACP stream closed");
+            return;
+        }
+        failures += 1;
+        std::thread::sleep(stream_retry_delay(Duration::from_millis(500), failures));
+    }
+}
+
+enum AcpBridgeMessage {
+    /// A message from the server's SSE stream.
+    Streamed(Value),
+    /// The daemon's reply to a request from the editor.
+    Reply(Value),
+    /// A JSON-RPC error for a request the daemon did not answer.
+    Failure(Value),
+    /// Stdin reached EOF; write what is held and stop.
+    Closed,
+}
+
+/// Holds each reply until the SSE stream has carried the agent's own response to the same
+/// request, so the last `session/update`s of a turn reach the editor before its result.
+#[derive(Default)]
+struct AcpReplyOrder {
+    streamed: std::collections::HashSet<String>,
+    held: Vec<(String, Value, std::time::Instant)>,
+}
+
+impl AcpReplyOrder {
+    /// The messages to write now, in order.
+    fn push(&mut self, message: AcpBridgeMessage, now: std::time::Instant) -> Vec<Value> {
+        match message {
+            // Notifications and the agent's requests go straight through.
+            AcpBridgeMessage::Streamed(payload) if payload.get("method").is_some() => {
+                vec![payload]
+            }
+            AcpBridgeMessage::Streamed(payload) => {
+                let Some(key) = payload.get("id").map(Value::to_string) else {
+                    return Vec::new();
+                };
+                match self.held.iter().position(|(held, ..)| *held == key) {
+                    Some(index) => vec![self.held.remove(index).1],
+                    None => {
+                        self.streamed.insert(key);
+                        Vec::new()
+                    }
+                }
+            }
+            AcpBridgeMessage::Reply(reply) => {
+                let key = reply.get("id").map(Value::to_string).unwrap_or_default();
+                if self.streamed.remove(&key) {
+                    return vec![reply];
+                }
+                self.held.push((key, reply, now + ACP_BRIDGE_REPLY_WAIT));
+                Vec::new()
+            }
+            AcpBridgeMessage::Failure(reply) => vec![reply],
+            AcpBridgeMessage::Closed => std::mem::take(&mut self.held)
+                .into_iter()
+                .map(|(_, reply, _)| reply)
+                .collect(),
+        }
+    }
+
+    /// Releases held replies whose response never showed up on the stream, such as ones the
+    /// daemon answered itself.
+    fn expire(&mut self, now: std::time::Instant) -> Vec<Value> {
+        let (expired, held) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, _, deadline)| *deadline <= now);
+        self.held = held;
+        expired.into_iter().map(|(_, reply, _)| reply).collect()
+    }
+}
+
+fn write_acp_bridge_output(rx: std::sync::mpsc::Receiver<AcpBridgeMessage>) {
+    let mut order = AcpReplyOrder::default();
+    loop {
+        let ready = match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(AcpBridgeMessage::Closed) => {
+                for message in order.push(AcpBridgeMessage::Closed, std::time::Instant::now()) {
+                    let _ = write_stdout_line(&message.to_string());
+                }
+                return;
+            }
+            Ok(message) => order.push(message, std::time::Instant::now()),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Vec::new(),
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return,
+        };
+        let expired = order.expire(std::time::Instant::now());
+        for message in ready.into_iter().chain(expired) {
+            if write_stdout_line(&message.to_string()).is_err() {
+                return;
+            }
+        }
+    }
+}
+
 struct ClientContext {
     endpoint: String,
     token: Option<String>,
@@ -1053,6 +2426,19 @@ struct ClientContext {
 
 impl ClientContext {
     fn new(cli: &CliConfig, args: &ClientArgs) -> Result<Self, CliError> {
+        Ok(Self::with_client(cli, args, HttpClient::builder().build()?))
+    }
+
+    /// Client without the default request timeout, for long-lived SSE connections.
+    fn streaming(cli: &CliConfig, args: &ClientArgs) -> Result<Self, CliError> {
+        Ok(Self::with_client(
+            cli,
+            args,
+            HttpClient::builder().timeout(None::<Duration>).build()?,
+        ))
+    }
+
+    fn with_client(cli: &CliConfig, args: &ClientArgs, client: HttpClient) -> Self {
         let endpoint = args
             .endpoint
             .clone()
@@ -1062,12 +2448,11 @@ impl ClientContext {
         } else {
             cli.token.clone()
         };
-        let client = HttpClient::builder().build()?;
-        Ok(Self {
+        Self {
             endpoint,
             token,
             client,
-        })
+        }
     }
 
     fn url(&self, path: &str) -> String {
@@ -1117,7 +2502,10 @@ fn print_json_response<T: serde::de::DeserializeOwned + Serialize>(
     Ok(())
 }
 
-fn print_json_or_empty(response: reqwest::blocking::Response) -> Result<(), CliError> {
+fn print_json_or_empty(
+    cli: &CliConfig,
+    response: reqwest::blocking::Response,
+) -> Result<(), CliError> {
     let status = response.status();
     let text = response.text()?;
 
@@ -1131,22 +2519,31 @@ fn print_json_or_empty(response: reqwest::blocking::Response) -> Result<(), CliE
     }
 
     if let Ok(value) = serde_json::from_str::<Value>(&text) {
-        write_stdout_line(&serde_json::to_string_pretty(&value)?)
+        print_result(cli, &value, None)
+    } else if cli.output == OutputFormat::Quiet {
+        Ok(())
     } else {
         write_stdout_line(&text)
     }
 }
 
-fn print_text_response(response: reqwest::blocking::Response) -> Result<(), CliError> {
+/// The JSON body of a successful response; error bodies are printed to stderr.
+fn json_response(response: reqwest::blocking::Response) -> Result<Value, CliError> {
     let status = response.status();
     let text = response.text()?;
-
     if !status.is_success() {
         print_error_body(&text)?;
         return Err(CliError::HttpStatus(status));
     }
+    Ok(serde_json::from_str(&text)?)
+}
 
-    write_stdout(&text)
+/// Print a JSON result in the format chosen with `--output` and `--field`.
+fn print_result(cli: &CliConfig, value: &Value, table: Option<&TableSpec>) -> Result<(), CliError> {
+    match render(value, cli.output, cli.field.as_deref(), table).map_err(CliError::Server)? {
+        Some(text) => write_stdout_line(&text),
+        None => Ok(()),
+    }
 }
 
 fn print_empty_response(response: reqwest::blocking::Response) -> Result<(), CliError> {
@@ -1219,4 +2616,168 @@ mod tests {
             .expect("build request");
         assert!(request.headers().get("last-event-id").is_none());
     }
+
+    #[test]
+    fn assistant_text_delta_filters_by_session_and_kind() {
+        let chunk = json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": {
+                "sessionId": "s-1",
+                "update": {
+                    "sessionUpdate": "agent_message_chunk",
+                    "content": {"type": "text", "text": "hello"}
+                }
+            }
+        });
+        assert_eq!(assistant_text_delta(&chunk, "s-1"), Some("hello"));
+        assert_eq!(assistant_text_delta(&chunk, "s-2"), None);
+
+        let thought = json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": {
+                "sessionId": "s-1",
+                "update": {
+                    "sessionUpdate": "agent_thought_chunk",
+                    "content": {"type": "text", "text": "hmm"}
+                }
+            }
+        });
+        assert_eq!(assistant_text_delta(&thought, "s-1"), None);
+    }
+
+    #[test]
+    fn event_line_summarizes_by_type() {
+        let chunk = json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": {
+                "sessionId": "s-1",
+                "update": {
+                    "sessionUpdate": "agent_message_chunk",
+                    "content": {"type": "text", "text": "hello\nworld"}
+                }
+            }
+        });
+        assert_eq!(
+            event_line(7, &chunk, false),
+            "     7  agent_message_chunk   hello world"
+        );
+
+        let tool = json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": {
+                "sessionId": "s-1",
+                "update": {"sessionUpdate": "tool_call", "title": "Run tests", "status": "pending"}
+            }
+        });
+        assert_eq!(
+            event_line(8, &tool, true),
+            "     8  \x1b[36mtool_call           \x1b[0m  Run tests pending"
+        );
+
+        let done = json!({"jsonrpc": "2.0", "id": 3, "result": {"stopReason": "end_turn"}});
+        assert_eq!(
+            event_line(9, &done, false),
+            "     9  response              stopReason=end_turn"
+        );
+
+        let failed =
+            json!({"jsonrpc": "2.0", "id": 4, "error": {"code": -32000, "message": "boom"}});
+        assert_eq!(
+            event_line(10, &failed, false),
+            "    10  error                 boom"
+        );
+    }
+
+    #[test]
+    fn acp_post_rejects_wait_with_stream() {
+        let args = [
+            "sandbox-agent",
+            "api",
+            "acp",
+            "post",
+            "--server-id",
+            "s-1",
+            "--json",
+            "{}",
+            "--wait",
+            "--stream",
+        ];
+        let err = SandboxAgentCli::try_parse_from(args).expect_err("flags conflict");
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        assert!(SandboxAgentCli::try_parse_from(&args[..args.len() - 1]).is_ok());
+    }
+
+    #[test]
+    fn turn_outcome_maps_stop_reason() {
+        assert!(turn_outcome(&json!({"result": {"stopReason": "end_turn"}})).is_ok());
+        assert!(matches!(
+            turn_outcome(&json!({"result": {"stopReason": "cancelled"}})),
+            Err(CliError::TurnEnded(reason)) if reason == "cancelled"
+        ));
+    }
+
+    #[test]
+    fn run_outcome_maps_status_and_stop_reason() {
+        assert!(run_outcome(&json!({"status": "completed", "stopReason": "end_turn"})).is_ok());
+        assert!(matches!(
+            run_outcome(&json!({"status": "completed", "stopReason": "max_tokens"})),
+            Err(CliError::TurnEnded(reason)) if reason == "max_tokens"
+        ));
+        assert!(matches!(
+            run_outcome(&json!({"status": "timedOut", "error": "turn did not finish"})),
+            Err(CliError::Server(message)) if message == "run timed out: turn did not finish"
+        ));
+        assert!(run_outcome(&json!({"status": "failed"})).is_err());
+    }
+
+    #[test]
+    fn stream_retry_delay_doubles_up_to_cap() {
+        let base = Duration::from_millis(500);
+        assert_eq!(stream_retry_delay(base, 1), Duration::from_millis(500));
+        assert_eq!(stream_retry_delay(base, 3), Duration::from_secs(2));
+        assert_eq!(stream_retry_delay(base, 40), STREAM_RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn acp_bridge_replies_follow_the_streamed_response() {
+        let now = std::time::Instant::now();
+        let mut order = AcpReplyOrder::default();
+        let update = json!({"jsonrpc": "2.0", "method": "session/update", "params": {}});
+        let reply = json!({"jsonrpc": "2.0", "id": 2, "result": {"stopReason": "end_turn"}});
+
+        // The POST reply arrives first and waits for the stream to catch up.
+        assert!(order
+            .push(AcpBridgeMessage::Reply(reply.clone()), now)
+            .is_empty());
+        assert_eq!(
+            order.push(AcpBridgeMessage::Streamed(update.clone()), now),
+            vec![update]
+        );
+        assert_eq!(
+            order.push(AcpBridgeMessage::Streamed(reply.clone()), now),
+            vec![reply.clone()]
+        );
+
+        // Streamed first, the reply goes straight out.
+        let reply = json!({"jsonrpc": "2.0", "id": "3", "result": {}});
+        assert!(order
+            .push(AcpBridgeMessage::Streamed(reply.clone()), now)
+            .is_empty());
+        assert_eq!(
+            order.push(AcpBridgeMessage::Reply(reply.clone()), now),
+            vec![reply]
+        );
+
+        // A reply the agent never sent is released after the wait.
+        let reply = json!({"jsonrpc": "2.0", "id": 4, "result": {}});
+        assert!(order
+            .push(AcpBridgeMessage::Reply(reply.clone()), now)
+            .is_empty());
+        assert!(order.expire(now).is_empty());
+        assert_eq!(order.expire(now + ACP_BRIDGE_REPLY_WAIT), vec![reply]);
+    }
 }
@@ -0,0 +1,434 @@
+//! Output formats for CLI commands that print JSON results.
+//!
+//! `--output` picks JSON (the default), YAML, an aligned table or nothing at all, and
+//! `--field` narrows the result with a jq-style path first: `.agents[].id`,
+//! `.servers[0].serverId`, `.labels["team.name"]`. Strings selected by `--field` are printed
+//! raw, one per line, so they can be used in shell scripts directly.
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Yaml,
+    Table,
+    /// Print nothing; only the exit status reports the outcome.
+    Quiet,
+}
+
+/// Rows and columns of a command's table view, as JSON pointers.
+pub(crate) struct TableSpec {
+    /// Pointer to the array of rows in the result.
+    pub rows: &'static str,
+    /// Header and pointer into each row for every column.
+    pub columns: &'static [(&'static str, &'static str)],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(i64),
+    Iterate,
+}
+
+/// Render `value` for stdout, applying `field` first. Returns `None` when nothing should be
+/// printed.
+pub(crate) fn render(
+    value: &Value,
+    format: OutputFormat,
+    field: Option<&str>,
+    table: Option<&TableSpec>,
+) -> Result<Option<String>, String> {
+    if format == OutputFormat::Quiet {
+        return Ok(None);
+    }
+    let Some(field) = field else {
+        return Ok(Some(match (format, table) {
+            (OutputFormat::Table, Some(table)) => {
+                let rows = value
+                    .pointer(table.rows)
+                    .and_then(Value::as_array)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                render_table(rows, table.columns)
+            }
+            _ => render_value(value, format),
+        }));
+    };
+
+    let steps = parse_field(field)?;
+    let iterates = steps.contains(&Step::Iterate);
+    let selected = select(value, &steps);
+    if format == OutputFormat::Table {
+        let value = if iterates {
+            Value::Array(selected)
+        } else {
+            selected.into_iter().next().unwrap_or(Value::Null)
+        };
+        return Ok(Some(render_value(&value, format)));
+    }
+    let lines = selected
+        .iter()
+        .map(|value| match value {
+            Value::String(text) => text.clone(),
+            other => render_value(other, format),
+        })
+        .collect::<Vec<_>>();
+    Ok(Some(lines.join("\n")))
+}
+
+fn render_value(value: &Value, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Yaml => to_yaml(value),
+        OutputFormat::Table => generic_table(value),
+        OutputFormat::Json | OutputFormat::Quiet => {
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        }
+    }
+}
+
+fn parse_field(field: &str) -> Result<Vec<Step>, String> {
+    let invalid = || format!("invalid --field path: {field}");
+    let mut steps = Vec::new();
+    let mut rest = field.trim();
+    if rest == "." {
+        return Ok(steps);
+    }
+    // The leading dot is optional: `agents[0].id` reads as `.agents[0].id`.
+    if let Some(stripped) = rest.strip_prefix('.') {
+        rest = stripped;
+    }
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            let inner = after[..end].trim();
+            steps.push(if inner.is_empty() {
+                Step::Iterate
+            } else if let Some(key) = inner
+                .strip_prefix('"')
+                .and_then(|inner| inner.strip_suffix('"'))
+            {
+                Step::Key(key.to_string())
+            } else {
+                Step::Index(inner.parse().map_err(|_| invalid())?)
+            });
+            rest = &after[end + 1..];
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            steps.push(Step::Key(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        if let Some(after) = rest.strip_prefix('.') {
+            if after.is_empty() || after.starts_with('.') {
+                return Err(invalid());
+            }
+            rest = after;
+        }
+    }
+    Ok(steps)
+}
+
+/// Apply `steps` to `value`. Missing keys and indexes select `null`, as jq's `?` does.
+fn select(value: &Value, steps: &[Step]) -> Vec<Value> {
+    let mut current = vec![value.clone()];
+    for step in steps {
+        current = current
+            .into_iter()
+            .flat_map(|value| match (step, value) {
+                (Step::Key(key), Value::Object(mut object)) => {
+                    vec![object.remove(key).unwrap_or(Value::Null)]
+                }
+                (Step::Index(index), Value::Array(mut items)) => {
+                    let len = items.len() as i64;
+                    let index = if *index < 0 { len + index } else { *index };
+                    if (0..len).contains(&index) {
+                        vec![items.swap_remove(index as usize)]
+                    } else {
+                        vec![Value::Null]
+                    }
+                }
+                (Step::Iterate, Value::Array(items)) => items,
+                (Step::Iterate, Value::Object(object)) => object.into_values().collect(),
+                _ => vec![Value::Null],
+            })
+            .collect();
+    }
+    current
+}
+
+/// Block-style YAML. Strings that would read as another type are double-quoted.
+pub(crate) fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(object) if !object.is_empty() => write_yaml_mapping(&mut out, value, 0),
+        Value::Array(items) if !items.is_empty() => write_yaml_sequence(&mut out, value, 0),
+        scalar => {
+            out.push_str(&yaml_scalar(scalar));
+            out.push('\n');
+        }
+    }
+    out.truncate(out.trim_end_matches('\n').len());
+    out
+}
+
+fn write_yaml_mapping(out: &mut String, value: &Value, indent: usize) {
+    let Value::Object(object) = value else {
+        return;
+    };
+    for (index, (key, child)) in object.iter().enumerate() {
+        // The first key of a sequence item continues the `- ` line.
+        if index > 0 || !out.ends_with("- ") {
+            out.push_str(&" ".repeat(indent));
+        }
+        out.push_str(&yaml_string(key));
+        out.push(':');
+        write_yaml_child(out, child, indent);
+    }
+}
+
+fn write_yaml_sequence(out: &mut String, value: &Value, indent: usize) {
+    let Value::Array(items) = value else {
+        return;
+    };
+    for item in items {
+        out.push_str(&" ".repeat(indent));
+        out.push_str("- ");
+        match item {
+            Value::Object(object) if !object.is_empty() => {
+                write_yaml_mapping(out, item, indent + 2)
+            }
+            Value::Array(nested) if !nested.is_empty() => {
+                out.pop();
+                out.push('\n');
+                write_yaml_sequence(out, item, indent + 2);
+            }
+            scalar => {
+                out.push_str(&yaml_scalar(scalar));
+                out.push('\n');
+            }
+        }
+    }
+}
+
+fn write_yaml_child(out: &mut String, child: &Value, indent: usize) {
+    match child {
+        Value::Object(object) if !object.is_empty() => {
+            out.push('\n');
+            write_yaml_mapping(out, child, indent + 2);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_yaml_sequence(out, child, indent);
+        }
+        scalar => {
+            out.push(' ');
+            out.push_str(&yaml_scalar(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(value) => value.to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => yaml_string(text),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+    }
+}
+
+fn yaml_string(text: &str) -> String {
+    let reserved = matches!(
+        text.to_ascii_lowercase().as_str(),
+        "" | "~" | "null" | "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n"
+    );
+    let plain = !reserved
+        && text.parse::<f64>().is_err()
+        && !text.starts_with(|ch: char| ch.is_whitespace() || "-?:,[]{}#&*!|>'\"%@`".contains(ch))
+        && !text.ends_with(char::is_whitespace)
+        && !text.contains(": ")
+        && !text.contains(" #")
+        && !text.ends_with(':')
+        && !text.chars().any(char::is_control);
+    if plain {
+        text.to_string()
+    } else {
+        // A JSON string is a valid YAML double-quoted scalar.
+        Value::String(text.to_string()).to_string()
+    }
+}
+
+/// Rows of `value` as a table: an array of objects gets one column per key, anything else a
+/// KEY/VALUE listing.
+fn generic_table(value: &Value) -> String {
+    match value {
+        Value::Array(items) if items.iter().all(Value::is_object) && !items.is_empty() => {
+            let mut keys = Vec::<String>::new();
+            for item in items.iter().filter_map(Value::as_object) {
+                for key in item.keys() {
+                    if !keys.contains(key) {
+                        keys.push(key.clone());
+                    }
+                }
+            }
+            let headers = keys.iter().map(|key| header_for(key)).collect::<Vec<_>>();
+            let rows = items
+                .iter()
+                .map(|item| keys.iter().map(|key| cell(item.get(key))).collect())
+                .collect::<Vec<_>>();
+            format_table(&headers, &rows)
+        }
+        Value::Array(items) => {
+            let rows = items
+                .iter()
+                .map(|item| vec![cell(Some(item))])
+                .collect::<Vec<_>>();
+            format_table(&["VALUE".to_string()], &rows)
+        }
+        Value::Object(object) => {
+            let rows = object
+                .iter()
+                .map(|(key, value)| vec![key.clone(), cell(Some(value))])
+                .collect::<Vec<_>>();
+            format_table(&["KEY".to_string(), "VALUE".to_string()], &rows)
+        }
+        scalar => cell(Some(scalar)),
+    }
+}
+
+fn render_table(rows: &[Value], columns: &[(&str, &str)]) -> String {
+    let headers = columns
+        .iter()
+        .map(|(header, _)| header.to_string())
+        .collect::<Vec<_>>();
+    let rows = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|(_, pointer)| cell(row.pointer(pointer)))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    format_table(&headers, &rows)
+}
+
+/// `createdAtMs` becomes `CREATED_AT_MS`.
+fn header_for(key: &str) -> String {
+    let mut header = String::new();
+    for (index, ch) in key.chars().enumerate() {
+        if ch.is_uppercase() && index > 0 {
+            header.push('_');
+        }
+        header.extend(ch.to_uppercase());
+    }
+    header
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => "-".to_string(),
+        Some(Value::String(text)) => text.replace(['\n', '\t'], " "),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn format_table(headers: &[String], rows: &[Vec<String>]) -> String {
+    let mut widths = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    std::iter::once(headers)
+        .chain(rows.iter().map(Vec::as_slice))
+        .map(|cells| {
+            let line = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn field_paths_select_like_jq() {
+        let value = json!({
+            "agents": [{"id": "claude"}, {"id": "codex"}],
+            "labels": {"team.name": "infra"}
+        });
+        let pick = |field: &str| select(&value, &parse_field(field).expect("parse"));
+        assert_eq!(pick(".agents[].id"), vec![json!("claude"), json!("codex")]);
+        assert_eq!(pick("agents[-1].id"), vec![json!("codex")]);
+        assert_eq!(pick(r#".labels["team.name"]"#), vec![json!("infra")]);
+        assert_eq!(pick(".missing.key"), vec![Value::Null]);
+        assert_eq!(pick("."), vec![value.clone()]);
+        assert!(parse_field(".agents..id").is_err());
+        assert!(parse_field(".agents[x]").is_err());
+    }
+
+    #[test]
+    fn field_strings_print_raw() {
+        let value = json!({"agents": [{"id": "claude"}, {"id": "codex"}]});
+        let out = render(&value, OutputFormat::Json, Some(".agents[].id"), None);
+        assert_eq!(out, Ok(Some("claude\ncodex".to_string())));
+        assert_eq!(render(&value, OutputFormat::Quiet, None, None), Ok(None));
+    }
+
+    #[test]
+    fn yaml_quotes_ambiguous_strings() {
+        let value = json!({
+            "id": "codex",
+            "installed": true,
+            "version": "1.0",
+            "note": "yes",
+            "path": null,
+            "models": [{"id": "gpt-5", "current": false}, "a: b"],
+            "empty": []
+        });
+        assert_eq!(
+            to_yaml(&value),
+            "empty: []\nid: codex\ninstalled: true\nmodels:\n- current: false\n  id: gpt-5\n\
+             - \"a: b\"\nnote: \"yes\"\npath: null\nversion: \"1.0\""
+        );
+    }
+
+    #[test]
+    fn tables_align_columns() {
+        let rows = [
+            json!({"id": "claude", "installed": true}),
+            json!({"id": "codex", "installed": false, "version": "0.9"}),
+        ];
+        let table = render_table(
+            &rows,
+            &[
+                ("ID", "/id"),
+                ("INSTALLED", "/installed"),
+                ("VERSION", "/version"),
+            ],
+        );
+        assert_eq!(
+            table,
+            "ID      INSTALLED  VERSION\nclaude  true       -\ncodex   false      0.9"
+        );
+        assert_eq!(header_for("createdAtMs"), "CREATED_AT_MS");
+    }
+}
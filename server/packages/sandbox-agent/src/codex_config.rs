@@ -0,0 +1,280 @@
+//! Structured access to Codex's `config.toml`.
+//!
+//! Only the top-level model and policy settings, `developer_instructions`, `[profiles.*]` and
+//! `[model_providers.*]` are read and written. Edits go through `toml_edit`, so every other key
+//! (`mcp_servers`, for example) and all comments survive a PUT.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sandbox_agent_error::SandboxError;
+use toml_edit::{value, DocumentMut, Item, Table, TableLike, Value};
+
+use crate::router::{map_fs_error, CodexConfig, CodexModelProvider, CodexProfile};
+
+const APPROVAL_POLICIES: &[&str] = &["untrusted", "on-failure", "on-request", "never"];
+const SANDBOX_MODES: &[&str] = &["read-only", "workspace-write", "danger-full-access"];
+const REASONING_EFFORTS: &[&str] = &["minimal", "low", "medium", "high"];
+const WIRE_APIS: &[&str] = &["chat", "responses"];
+
+/// `$CODEX_HOME/config.toml`, or `~/.codex/config.toml`.
+pub(crate) fn codex_config_path() -> PathBuf {
+    let home = std::env::var_os("CODEX_HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            dirs::home_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join(".codex")
+        });
+    home.join("config.toml")
+}
+
+pub(crate) fn read_codex_config(path: &Path) -> Result<CodexConfig, SandboxError> {
+    let document = read_document(path)?;
+    let root = document.as_table();
+    let defaults = read_profile(root);
+    Ok(CodexConfig {
+        model: defaults.model,
+        model_provider: defaults.model_provider,
+        approval_policy: defaults.approval_policy,
+        sandbox_mode: defaults.sandbox_mode,
+        model_reasoning_effort: defaults.model_reasoning_effort,
+        developer_instructions: get_str(root, "developer_instructions"),
+        profile: get_str(root, "profile"),
+        profiles: read_tables(root, "profiles", read_profile),
+        model_providers: read_tables(root, "model_providers", |table| CodexModelProvider {
+            name: get_str(table, "name"),
+            base_url: get_str(table, "base_url"),
+            env_key: get_str(table, "env_key"),
+            wire_api: get_str(table, "wire_api"),
+        }),
+    })
+}
+
+/// Replace the managed settings in the file at `path` with `config`. Profiles and providers
+/// missing from `config` are removed; keys this API does not manage are kept.
+pub(crate) fn write_codex_config(path: &Path, config: &CodexConfig) -> Result<(), SandboxError> {
+    validate(config)?;
+    let mut document = read_document(path)?;
+    let root = document.as_table_mut();
+
+    write_profile(root, &config_defaults(config));
+    set_str(
+        root,
+        "developer_instructions",
+        config.developer_instructions.as_deref(),
+    );
+    set_str(root, "profile", config.profile.as_deref());
+    write_tables(root, "profiles", &config.profiles, write_profile)?;
+    write_tables(
+        root,
+        "model_providers",
+        &config.model_providers,
+        |table, provider| {
+            set_str(table, "name", provider.name.as_deref());
+            set_str(table, "base_url", provider.base_url.as_deref());
+            set_str(table, "env_key", provider.env_key.as_deref());
+            set_str(table, "wire_api", provider.wire_api.as_deref());
+        },
+    )?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err))?;
+    }
+    // Write beside the target and rename, so Codex never reads a half-written file.
+    let staged = path.with_extension("toml.tmp");
+    fs::write(&staged, document.to_string()).map_err(|err| map_fs_error(&staged, err))?;
+    fs::rename(&staged, path).map_err(|err| map_fs_error(path, err))
+}
+
+fn validate(config: &CodexConfig) -> Result<(), SandboxError> {
+    validate_profile("", &config_defaults(config))?;
+    for (name, profile) in &config.profiles {
+        validate_name("profiles", name)?;
+        validate_profile(&format!("profiles.{name}."), profile)?;
+    }
+    if let Some(profile) = &config.profile {
+        if !config.profiles.contains_key(profile) {
+            return Err(invalid(format!(
+                "profile '{profile}' is not defined in profiles"
+            )));
+        }
+    }
+    for (name, provider) in &config.model_providers {
+        validate_name("modelProviders", name)?;
+        if let Some(base_url) = &provider.base_url {
+            let valid = reqwest::Url::parse(base_url)
+                .is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+            if !valid {
+                return Err(invalid(format!(
+                    "modelProviders.{name}.baseUrl must be an http(s) URL"
+                )));
+            }
+        }
+        check_one_of(
+            &format!("modelProviders.{name}.wireApi"),
+            provider.wire_api.as_deref(),
+            WIRE_APIS,
+        )?;
+    }
+    Ok(())
+}
+
+fn validate_profile(prefix: &str, profile: &CodexProfile) -> Result<(), SandboxError> {
+    check_one_of(
+        &format!("{prefix}approvalPolicy"),
+        profile.approval_policy.as_deref(),
+        APPROVAL_POLICIES,
+    )?;
+    check_one_of(
+        &format!("{prefix}sandboxMode"),
+        profile.sandbox_mode.as_deref(),
+        SANDBOX_MODES,
+    )?;
+    check_one_of(
+        &format!("{prefix}modelReasoningEffort"),
+        profile.model_reasoning_effort.as_deref(),
+        REASONING_EFFORTS,
+    )
+}
+
+fn validate_name(field: &str, name: &str) -> Result<(), SandboxError> {
+    if name.trim().is_empty() {
+        return Err(invalid(format!("{field} keys must not be empty")));
+    }
+    Ok(())
+}
+
+fn check_one_of(field: &str, value: Option<&str>, allowed: &[&str]) -> Result<(), SandboxError> {
+    match value {
+        Some(value) if !allowed.contains(&value) => Err(invalid(format!(
+            "{field} must be one of {}, got '{value}'",
+            allowed.join(", ")
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The top-level settings, which apply when no profile overrides them.
+fn config_defaults(config: &CodexConfig) -> CodexProfile {
+    CodexProfile {
+        model: config.model.clone(),
+        model_provider: config.model_provider.clone(),
+        approval_policy: config.approval_policy.clone(),
+        sandbox_mode: config.sandbox_mode.clone(),
+        model_reasoning_effort: config.model_reasoning_effort.clone(),
+    }
+}
+
+fn read_document(path: &Path) -> Result<DocumentMut, SandboxError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(map_fs_error(path, err)),
+    };
+    text.parse::<DocumentMut>()
+        .map_err(|err| invalid(format!("invalid {}: {err}", path.display())))
+}
+
+fn read_profile(table: &dyn TableLike) -> CodexProfile {
+    CodexProfile {
+        model: get_str(table, "model"),
+        model_provider: get_str(table, "model_provider"),
+        approval_policy: get_str(table, "approval_policy"),
+        sandbox_mode: get_str(table, "sandbox_mode"),
+        model_reasoning_effort: get_str(table, "model_reasoning_effort"),
+    }
+}
+
+fn write_profile(table: &mut dyn TableLike, profile: &CodexProfile) {
+    set_str(table, "model", profile.model.as_deref());
+    set_str(table, "model_provider", profile.model_provider.as_deref());
+    set_str(table, "approval_policy", profile.approval_policy.as_deref());
+    set_str(table, "sandbox_mode", profile.sandbox_mode.as_deref());
+    set_str(
+        table,
+        "model_reasoning_effort",
+        profile.model_reasoning_effort.as_deref(),
+    );
+}
+
+fn read_tables<T>(
+    root: &dyn TableLike,
+    key: &str,
+    read: impl Fn(&dyn TableLike) -> T,
+) -> BTreeMap<String, T> {
+    let Some(tables) = root.get(key).and_then(Item::as_table_like) else {
+        return Default::default();
+    };
+    tables
+        .iter()
+        .filter_map(|(name, item)| Some((name.to_string(), read(item.as_table_like()?))))
+        .collect()
+}
+
+/// Make `root.key` hold exactly the tables in `values`, updating existing tables in place.
+fn write_tables<T>(
+    root: &mut dyn TableLike,
+    key: &str,
+    values: &BTreeMap<String, T>,
+    write: impl Fn(&mut dyn TableLike, &T),
+) -> Result<(), SandboxError> {
+    if values.is_empty() {
+        root.remove(key);
+        return Ok(());
+    }
+    if root.get(key).is_none() {
+        let mut parent = Table::new();
+        parent.set_implicit(true);
+        root.insert(key, Item::Table(parent));
+    }
+    let tables = root
+        .get_mut(key)
+        .and_then(Item::as_table_like_mut)
+        .ok_or_else(|| invalid(format!("{key} in config.toml is not a table")))?;
+    let stale = tables
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .filter(|name| !values.contains_key(name))
+        .collect::<Vec<_>>();
+    for name in stale {
+        tables.remove(&name);
+    }
+    for (name, value) in values {
+        if tables.get(name).and_then(Item::as_table_like).is_none() {
+            tables.insert(name, Item::Table(Table::new()));
+        }
+        if let Some(table) = tables.get_mut(name).and_then(Item::as_table_like_mut) {
+            write(table, value);
+        }
+    }
+    Ok(())
+}
+
+fn get_str(table: &dyn TableLike, key: &str) -> Option<String> {
+    table.get(key).and_then(Item::as_str).map(str::to_string)
+}
+
+fn set_str(table: &mut dyn TableLike, key: &str, text: Option<&str>) {
+    let Some(text) = text else {
+        table.remove(key);
+        return;
+    };
+    // Update existing values in place so comments around them are kept.
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = Value::from(text);
+            *existing.decor_mut() = decor;
+        }
+        None => {
+            table.insert(key, value(text));
+        }
+    }
+}
+
+fn invalid(message: String) -> SandboxError {
+    SandboxError::InvalidRequest { message }
+}
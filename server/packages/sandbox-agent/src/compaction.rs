@@ -0,0 +1,54 @@
+//! Native context compaction, behind `POST /v1/acp/{server_id}/sessions/{session_id}/compact`.
+//!
+//! Claude, Codex and OpenCode summarize a session's history into a smaller context when sent
+//! their `/compact` slash command as a prompt. The daemon sends it as a turn of the session and
+//! brackets the turn with `_sandboxagent/session/compaction` notifications: `phase: "started"`,
+//! then `"completed"` or `"failed"`, each with the context window the agent last reported.
+
+use sandbox_agent_agent_management::agents::AgentId;
+use serde_json::{json, Value};
+
+use crate::acp_usage::AcpContextWindow;
+
+/// The prompt that makes `agent` compact its context, if it can.
+pub(crate) fn command(agent: AgentId) -> Option<&'static str> {
+    match agent {
+        // The mock echoes it back, which lets tests drive the endpoint.
+        AgentId::Claude | AgentId::Codex | AgentId::Opencode | AgentId::Mock => Some("/compact"),
+        AgentId::Amp | AgentId::Pi | AgentId::Cursor | AgentId::Codebuff => None,
+    }
+}
+
+/// The `session/prompt` that sends `command` to `session_id`.
+pub(crate) fn prompt(session_id: &str, command: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": format!("sandboxagent-compact-{session_id}"),
+        "method": "session/prompt",
+        "params": {
+            "sessionId": session_id,
+            "prompt": [{ "type": "text", "text": command }],
+        },
+    })
+}
+
+/// Notification that compaction of `session_id` reached `phase`.
+pub(crate) fn event(
+    session_id: &str,
+    phase: &str,
+    context: Option<AcpContextWindow>,
+    error: Option<&Value>,
+) -> Value {
+    let mut params = json!({ "sessionId": session_id, "phase": phase });
+    if let Some(context) = context {
+        params["context"] = json!({ "used": context.used, "size": context.size });
+    }
+    if let Some(error) = error {
+        params["error"] = error.clone();
+    }
+    json!({
+        "jsonrpc": "2.0",
+        "method": "_sandboxagent/session/compaction",
+        "params": params,
+    })
+}
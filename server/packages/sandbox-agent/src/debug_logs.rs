@@ -0,0 +1,166 @@
+//! In-memory ring buffer of recent daemon log records behind `GET /v1/debug/logs`.
+//!
+//! [`layer`] runs next to the stderr logger with its own filter, so debug records from this
+//! workspace's crates are kept even when stderr only shows info. The filter can be replaced with
+//! `SANDBOX_AGENT_LOG_BUFFER` using `RUST_LOG` syntax. Only the newest
+//! [`LOG_BUFFER_CAPACITY`] records are kept.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::{LazyLock, Mutex};
+
+use serde_json::Value;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+use crate::router::{now_ms, DebugLogRecord};
+
+pub const LOG_BUFFER_CAPACITY: usize = 2000;
+const DEFAULT_BUFFER_FILTER: &str = "info,sandbox_agent=debug,acp_http_adapter=debug";
+
+static BUFFER: LazyLock<Mutex<VecDeque<BufferedRecord>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(LOG_BUFFER_CAPACITY)));
+
+struct BufferedRecord {
+    level: Level,
+    record: DebugLogRecord,
+}
+
+/// Layer that copies log events into the buffer read by `/v1/debug/logs`.
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let filter = std::env::var("SANDBOX_AGENT_LOG_BUFFER")
+        .ok()
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| EnvFilter::new(DEFAULT_BUFFER_FILTER));
+    LogBufferLayer.with_filter(filter)
+}
+
+/// The newest `limit` records at `min_level` or more severe, oldest first.
+pub(crate) fn recent(min_level: Level, limit: usize) -> Vec<DebugLogRecord> {
+    let buffer = BUFFER.lock().expect("log buffer poisoned");
+    let mut records: Vec<DebugLogRecord> = buffer
+        .iter()
+        .rev()
+        .filter(|buffered| buffered.level <= min_level)
+        .take(limit)
+        .map(|buffered| buffered.record.clone())
+        .collect();
+    records.reverse();
+    records
+}
+
+struct LogBufferLayer;
+
+impl<S> Layer<S> for LogBufferLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanFields(visitor.fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            let mut visitor = FieldVisitor {
+                message: None,
+                fields: std::mem::take(fields),
+            };
+            values.record(&mut visitor);
+            *fields = visitor.fields;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        // Fields from enclosing spans, innermost last so it wins over outer spans.
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    visitor.fields.extend(fields.clone());
+                }
+            }
+        }
+        event.record(&mut visitor);
+        let record = DebugLogRecord {
+            timestamp_ms: now_ms(),
+            level: metadata.level().as_str().to_ascii_lowercase(),
+            target: metadata.target().to_string(),
+            message: visitor.message.unwrap_or_default(),
+            fields: visitor.fields,
+        };
+
+        let mut buffer = BUFFER.lock().expect("log buffer poisoned");
+        if buffer.len() == LOG_BUFFER_CAPACITY {
+            buffer.pop_front();
+        }
+        buffer.push_back(BufferedRecord {
+            level: *metadata.level(),
+            record,
+        });
+    }
+}
+
+/// Fields recorded on a span, merged into the records of events inside it.
+struct SpanFields(BTreeMap<String, Value>);
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: BTreeMap<String, Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = Some(match value {
+                Value::String(text) => text,
+                other => other.to_string(),
+            });
+        } else {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::from(format!("{value:?}")));
+    }
+}
@@ -0,0 +1,492 @@
+//! Egress policy for agent processes: allowed and denied domains and CIDRs, enforced by a
+//! forward proxy on `127.0.0.1` that the agent is pointed at through `HTTP_PROXY`,
+//! `HTTPS_PROXY` and friends.
+//!
+//! Daemon defaults come from `SANDBOX_AGENT_ACP_EGRESS_ALLOW` and
+//! `SANDBOX_AGENT_ACP_EGRESS_DENY` (comma separated); `egressAllow` and `egressDeny` on the
+//! bootstrap POST replace them for one server. A destination is blocked when it matches a deny
+//! rule, or when there are allow rules and it matches none. `example.com` matches the domain and
+//! its subdomains, `*.example.com` only subdomains. CIDR rules match the addresses a host name
+//! resolves to, and the proxy connects only to addresses it checked.
+//!
+//! Each server with a policy gets its own proxy, which stops when the agent exits. Blocked
+//! connections get a `403` and are passed to a callback, which writes them to the audit log.
+//! Programs that ignore the proxy variables are not filtered.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use acp_http_adapter::process::AdapterRuntime;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+pub(crate) const EGRESS_ALLOW_ENV: &str = "SANDBOX_AGENT_ACP_EGRESS_ALLOW";
+pub(crate) const EGRESS_DENY_ENV: &str = "SANDBOX_AGENT_ACP_EGRESS_DENY";
+
+/// Largest request head the proxy reads before giving up on a connection.
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const EXIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Request headers that only concern the hop to the proxy.
+const PROXY_HEADERS: [&str; 4] = [
+    "connection",
+    "keep-alive",
+    "proxy-authorization",
+    "proxy-connection",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EgressPolicy {
+    allow: Vec<EgressRule>,
+    deny: Vec<EgressRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EgressRule {
+    /// Lowercase domain; with `subdomains_only`, written as `*.domain`.
+    Domain {
+        domain: String,
+        subdomains_only: bool,
+    },
+    Cidr {
+        network: IpAddr,
+        prefix: u8,
+    },
+}
+
+/// A connection the policy refused.
+#[derive(Debug, Clone)]
+pub(crate) struct BlockedConnection {
+    pub(crate) method: String,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) reason: String,
+}
+
+pub(crate) type BlockedCallback = Arc<dyn Fn(BlockedConnection) + Send + Sync>;
+
+impl EgressPolicy {
+    pub(crate) fn from_env() -> Self {
+        let rules = |name: &str| {
+            let raw = std::env::var(name).unwrap_or_default();
+            parse_rules(&raw).unwrap_or_else(|err| {
+                tracing::warn!("{name} ignored: {err}");
+                Vec::new()
+            })
+        };
+        Self {
+            allow: rules(EGRESS_ALLOW_ENV),
+            deny: rules(EGRESS_DENY_ENV),
+        }
+    }
+
+    /// This policy with its allow or deny list replaced where an override is given.
+    pub(crate) fn with_overrides(
+        self,
+        allow: Option<Vec<EgressRule>>,
+        deny: Option<Vec<EgressRule>>,
+    ) -> Self {
+        Self {
+            allow: allow.unwrap_or(self.allow),
+            deny: deny.unwrap_or(self.deny),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Why a connection to `host`, resolving to `addrs`, is blocked; `None` when allowed.
+    fn blocked(&self, host: &str, addrs: &[IpAddr]) -> Option<String> {
+        if let Some(rule) = self
+            .deny
+            .iter()
+            .find(|rule| rule.matches_host(host) || addrs.iter().any(|addr| rule.contains(*addr)))
+        {
+            return Some(format!("denied by {rule}"));
+        }
+        if self.allow.is_empty() || self.allow.iter().any(|rule| rule.matches_host(host)) {
+            return None;
+        }
+        let allowed_addrs = !addrs.is_empty()
+            && addrs
+                .iter()
+                .all(|addr| self.allow.iter().any(|rule| rule.contains(*addr)));
+        (!allowed_addrs).then(|| "not in the allow list".to_string())
+    }
+}
+
+/// Comma-separated domains and CIDRs; an empty string is an empty list.
+pub(crate) fn parse_rules(raw: &str) -> Result<Vec<EgressRule>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(EgressRule::parse)
+        .collect()
+}
+
+impl EgressRule {
+    fn parse(raw: &str) -> Result<Self, String> {
+        if let Some((network, prefix)) = raw.split_once('/') {
+            let network = network
+                .parse::<IpAddr>()
+                .map_err(|_| format!("invalid CIDR {raw:?}"))?;
+            let max = if network.is_ipv4() { 32 } else { 128 };
+            let prefix = prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| format!("invalid CIDR {raw:?}"))?;
+            return Ok(Self::Cidr { network, prefix });
+        }
+        if let Ok(addr) = raw.trim_matches(['[', ']']).parse::<IpAddr>() {
+            let prefix = if addr.is_ipv4() { 32 } else { 128 };
+            return Ok(Self::Cidr {
+                network: addr,
+                prefix,
+            });
+        }
+        let (domain, subdomains_only) = match raw.strip_prefix("*.") {
+            Some(domain) => (domain, true),
+            None => (raw.trim_start_matches('.'), false),
+        };
+        let valid = !domain.is_empty()
+            && domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+        if !valid {
+            return Err(format!("invalid domain {raw:?}"));
+        }
+        Ok(Self::Domain {
+            domain: domain.to_ascii_lowercase(),
+            subdomains_only,
+        })
+    }
+
+    fn matches_host(&self, host: &str) -> bool {
+        match self {
+            Self::Domain {
+                domain,
+                subdomains_only,
+            } => {
+                let host = host.trim_end_matches('.').to_ascii_lowercase();
+                let subdomain = host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|rest| rest.ends_with('.'));
+                subdomain || (!subdomains_only && host == *domain)
+            }
+            Self::Cidr { .. } => host
+                .trim_matches(['[', ']'])
+                .parse::<IpAddr>()
+                .is_ok_and(|addr| self.contains(addr)),
+        }
+    }
+
+    fn contains(&self, addr: IpAddr) -> bool {
+        let Self::Cidr { network, prefix } = self else {
+            return false;
+        };
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+            IpAddr::V4(_) => addr,
+        };
+        match (network, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(*prefix)).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for EgressRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Domain {
+                domain,
+                subdomains_only: true,
+            } => write!(f, "*.{domain}"),
+            Self::Domain { domain, .. } => f.write_str(domain),
+            Self::Cidr { network, prefix } => write!(f, "{network}/{prefix}"),
+        }
+    }
+}
+
+/// A running proxy; dropping it stops accepting connections.
+#[derive(Debug)]
+pub(crate) struct EgressProxy {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl EgressProxy {
+    pub(crate) async fn start(
+        policy: EgressPolicy,
+        on_blocked: BlockedCallback,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let policy = Arc::new(policy);
+        let task = tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                let policy = policy.clone();
+                let on_blocked = on_blocked.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = serve(client, &policy, &on_blocked).await {
+                        tracing::debug!(error = %err, "egress proxy: connection failed");
+                    }
+                });
+            }
+        });
+        Ok(Self { addr, task })
+    }
+
+    /// Variables that point HTTP clients in the agent at this proxy.
+    pub(crate) fn env(&self) -> HashMap<String, String> {
+        let url = format!("http://{}", self.addr);
+        let mut env = HashMap::new();
+        for name in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY"] {
+            env.insert(name.to_string(), url.clone());
+            env.insert(name.to_ascii_lowercase(), url.clone());
+        }
+        // An inherited NO_PROXY would let those hosts skip the policy.
+        env.insert("NO_PROXY".to_string(), String::new());
+        env.insert("no_proxy".to_string(), String::new());
+        // Node only reads the proxy variables for `fetch` when asked to.
+        env.insert("NODE_USE_ENV_PROXY".to_string(), "1".to_string());
+        env
+    }
+
+    /// Keep the proxy running until `runtime`'s agent exits.
+    pub(crate) fn stop_on_exit(self, runtime: Arc<AdapterRuntime>) {
+        tokio::spawn(async move {
+            while !runtime.has_exited() {
+                tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+            }
+            drop(self);
+        });
+    }
+}
+
+impl Drop for EgressProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Handle one client connection: a `CONNECT` tunnel or a plain HTTP request in absolute form.
+async fn serve(
+    mut client: TcpStream,
+    policy: &EgressPolicy,
+    on_blocked: &BlockedCallback,
+) -> io::Result<()> {
+    let mut buffer = Vec::new();
+    let head_end = loop {
+        if let Some(end) = find_head_end(&buffer) {
+            break end;
+        }
+        if buffer.len() > MAX_HEAD_BYTES {
+            return respond(&mut client, "431 Request Header Fields Too Large", "").await;
+        }
+        let mut chunk = [0_u8; 8192];
+        let read = client.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let body = buffer[head_end..].to_vec();
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return respond(&mut client, "400 Bad Request", "malformed request line").await;
+    };
+
+    let tunnel = method.eq_ignore_ascii_case("CONNECT");
+    let (authority, path, default_port) = if tunnel {
+        (target, "", 443)
+    } else if let Some(rest) = target.strip_prefix("http://") {
+        match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..], 80),
+            None => (rest, "/", 80),
+        }
+    } else {
+        return respond(
+            &mut client,
+            "400 Bad Request",
+            "the egress proxy only accepts CONNECT and http:// requests",
+        )
+        .await;
+    };
+    let Some((host, port)) = split_authority(authority, default_port) else {
+        return respond(&mut client, "400 Bad Request", "invalid host").await;
+    };
+
+    let addrs = match tokio::net::lookup_host((host.trim_matches(['[', ']']), port)).await {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    let ips = addrs.iter().map(SocketAddr::ip).collect::<Vec<_>>();
+    if let Some(reason) = policy.blocked(host, &ips) {
+        on_blocked(BlockedConnection {
+            method: method.to_string(),
+            host: host.to_string(),
+            port,
+            reason: reason.clone(),
+        });
+        let message = format!("{host}:{port} blocked by egress policy: {reason}");
+        return respond(&mut client, "403 Forbidden", &message).await;
+    }
+    if addrs.is_empty() {
+        return respond(&mut client, "502 Bad Gateway", "could not resolve host").await;
+    }
+    let upstream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&addrs[..])).await;
+    let Ok(Ok(mut upstream)) = upstream else {
+        return respond(&mut client, "502 Bad Gateway", "could not connect to host").await;
+    };
+
+    if tunnel {
+        client
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await?;
+    } else {
+        // One request per connection: a later request on it could name another host.
+        let mut forwarded = format!("{method} {path} {version}\r\n");
+        for line in lines.filter(|line| !line.is_empty()) {
+            let name = line.split(':').next().unwrap_or_default().trim();
+            if !PROXY_HEADERS
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header))
+            {
+                forwarded.push_str(line);
+                forwarded.push_str("\r\n");
+            }
+        }
+        forwarded.push_str("Connection: close\r\n\r\n");
+        upstream.write_all(forwarded.as_bytes()).await?;
+    }
+    upstream.write_all(&body).await?;
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+fn find_head_end(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|position| position + 4)
+}
+
+/// `host` and port of `host[:port]` or `[v6]:port`.
+fn split_authority(authority: &str, default_port: u16) -> Option<(&str, u16)> {
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+        _ => (authority, default_port),
+    };
+    (!host.is_empty()).then_some((host, port))
+}
+
+async fn respond(client: &mut TcpStream, status: &str, message: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+        message.len()
+    );
+    client.write_all(response.as_bytes()).await?;
+    client.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &str, deny: &str) -> EgressPolicy {
+        EgressPolicy::default().with_overrides(
+            Some(parse_rules(allow).expect("allow rules")),
+            Some(parse_rules(deny).expect("deny rules")),
+        )
+    }
+
+    #[test]
+    fn rules_parse_domains_wildcards_and_cidrs() {
+        let rules = parse_rules("Example.com, *.npmjs.org,10.0.0.0/8,::1").expect("rules");
+        let shown = rules.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            shown,
+            ["example.com", "*.npmjs.org", "10.0.0.0/8", "::1/128"]
+        );
+        assert!(parse_rules("10.0.0.0/33").is_err());
+        assert!(parse_rules("exa mple.com").is_err());
+        assert!(parse_rules("").expect("empty").is_empty());
+    }
+
+    #[test]
+    fn deny_wins_and_allow_lists_are_exclusive() {
+        let policy = policy("github.com,*.npmjs.org,10.0.0.0/8", "gist.github.com");
+        assert_eq!(policy.blocked("api.github.com", &[]), None);
+        assert_eq!(policy.blocked("github.com", &[]), None);
+        assert!(policy.blocked("gist.github.com", &[]).is_some());
+        assert_eq!(policy.blocked("registry.npmjs.org", &[]), None);
+        assert!(policy.blocked("npmjs.org", &[]).is_some());
+        assert!(policy.blocked("evilgithub.com", &[]).is_some());
+
+        let internal = "10.1.2.3".parse().expect("ip");
+        assert_eq!(policy.blocked("intranet.local", &[internal]), None);
+        assert_eq!(policy.blocked("10.1.2.3", &[internal]), None);
+        let public = "8.8.8.8".parse().expect("ip");
+        assert!(policy
+            .blocked("intranet.local", &[internal, public])
+            .is_some());
+    }
+
+    #[test]
+    fn deny_cidrs_apply_to_resolved_addresses() {
+        let policy = policy("", "169.254.0.0/16,127.0.0.0/8");
+        let metadata = "169.254.169.254".parse().expect("ip");
+        assert!(policy.blocked("metadata.internal", &[metadata]).is_some());
+        let mapped = "::ffff:127.0.0.1".parse().expect("ip");
+        assert!(policy.blocked("localhost", &[mapped]).is_some());
+        let public = "93.184.216.34".parse().expect("ip");
+        assert_eq!(policy.blocked("example.com", &[public]), None);
+    }
+
+    #[tokio::test]
+    async fn proxy_refuses_blocked_tunnels_and_reports_them() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let proxy = EgressProxy::start(
+            policy("", "blocked.example"),
+            Arc::new(move |blocked| {
+                let _ = sender.send(blocked);
+            }),
+        )
+        .await
+        .expect("start proxy");
+
+        let mut client = TcpStream::connect(proxy.addr).await.expect("connect");
+        client
+            .write_all(b"CONNECT blocked.example:443 HTTP/1.1\r\nHost: blocked.example:443\r\n\r\n")
+            .await
+            .expect("write");
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.expect("read");
+        assert!(response.starts_with("HTTP/1.1 403"));
+
+        let blocked = receiver.recv().await.expect("blocked connection");
+        assert_eq!(blocked.host, "blocked.example");
+        assert_eq!(blocked.port, 443);
+        assert_eq!(blocked.method, "CONNECT");
+    }
+}
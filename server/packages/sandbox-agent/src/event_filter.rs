@@ -0,0 +1,128 @@
+//! Query filters shared by the event streams, so clients that only want a few kinds of event
+//! don't receive every token delta.
+//!
+//! - `types`: JSON-RPC methods, or `response` for results and errors.
+//! - `kinds`: `sessionUpdate` kinds of `session/update` notifications, e.g. `tool_call` or
+//!   `plan`. Other messages are not affected.
+//! - `excludeSynthetic`: drops the `_sandboxagent/*` notifications the daemon adds to the
+//!   stream. Extension requests from agents, like `_sandboxagent/session/request_question`,
+//!   are kept.
+//!
+//! A message must pass every filter that is set.
+
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+pub(crate) struct EventFilter {
+    types: Option<Vec<String>>,
+    kinds: Option<Vec<String>>,
+    exclude_synthetic: bool,
+}
+
+impl EventFilter {
+    pub(crate) fn new(types: Option<&str>, kinds: Option<&str>, exclude_synthetic: bool) -> Self {
+        Self {
+            types: types.map(split_csv),
+            kinds: kinds.map(split_csv),
+            exclude_synthetic,
+        }
+    }
+
+    pub(crate) fn matches(&self, message: &Value) -> bool {
+        let type_ = message_type(message);
+        if self
+            .types
+            .as_ref()
+            .is_some_and(|types| !types.iter().any(|allowed| allowed == type_))
+        {
+            return false;
+        }
+        if type_ == "session/update" {
+            let kind = message
+                .pointer("/params/update/sessionUpdate")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            if self
+                .kinds
+                .as_ref()
+                .is_some_and(|kinds| !kinds.iter().any(|allowed| allowed == kind))
+            {
+                return false;
+            }
+        }
+        !(self.exclude_synthetic && is_synthetic(message))
+    }
+}
+
+/// The JSON-RPC method of `message`, or `response` for results and errors.
+pub(crate) fn message_type(message: &Value) -> &str {
+    message
+        .get("method")
+        .and_then(Value::as_str)
+        .unwrap_or("response")
+}
+
+fn is_synthetic(message: &Value) -> bool {
+    message.get("id").is_none() && message_type(message).starts_with("_sandboxagent/")
+}
+
+fn split_csv(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn update(kind: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": "s-1", "update": { "sessionUpdate": kind } }
+        })
+    }
+
+    #[test]
+    fn kinds_only_filter_session_updates() {
+        let filter = EventFilter::new(None, Some("tool_call, plan"), false);
+        assert!(filter.matches(&update("tool_call")));
+        assert!(!filter.matches(&update("agent_message_chunk")));
+        assert!(filter.matches(&json!({ "jsonrpc": "2.0", "id": 2, "result": {} })));
+    }
+
+    #[test]
+    fn types_and_kinds_must_both_match() {
+        let filter = EventFilter::new(Some("session/update,response"), Some("plan"), false);
+        assert!(filter.matches(&update("plan")));
+        assert!(!filter.matches(&update("tool_call")));
+        assert!(filter.matches(&json!({ "jsonrpc": "2.0", "id": 2, "error": {} })));
+        assert!(!filter.matches(&json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/request_permission",
+            "params": {}
+        })));
+    }
+
+    #[test]
+    fn exclude_synthetic_keeps_agent_extension_requests() {
+        let filter = EventFilter::new(None, None, true);
+        assert!(!filter.matches(&json!({
+            "jsonrpc": "2.0",
+            "method": "_sandboxagent/session/ended",
+            "params": {}
+        })));
+        assert!(filter.matches(&json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "_sandboxagent/session/request_question",
+            "params": {}
+        })));
+        assert!(filter.matches(&update("agent_message_chunk")));
+    }
+}
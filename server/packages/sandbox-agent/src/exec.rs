@@ -0,0 +1,322 @@
+//! One-shot commands behind `/v1/exec`.
+//!
+//! A command runs without a shell, with stdin closed and stdout/stderr piped back to the caller
+//! as they are produced. Every run is bounded by a wall-clock timeout and a cap on forwarded
+//! output; on Unix the caller may also set `RLIMIT_AS` and `RLIMIT_CPU` for the child. Commands
+//! start in their own process group, so killing a run also stops whatever it spawned. Output
+//! past the cap is still read and discarded so the child never blocks on a full pipe.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use sandbox_agent_error::SandboxError;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, Notify};
+
+use crate::router::ExecEvent;
+
+/// Maximum number of commands running at once.
+const MAX_CONCURRENT_EXECS: usize = 16;
+pub(crate) const EXEC_DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+pub(crate) const EXEC_MAX_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+pub(crate) const EXEC_DEFAULT_MAX_OUTPUT_BYTES: u64 = 16 * 1024 * 1024;
+const EXEC_READ_CHUNK_BYTES: usize = 16 * 1024;
+/// How long to keep reading pipes after the command exits; a background child that inherited
+/// them would otherwise hold the stream open.
+const EXEC_PIPE_DRAIN_GRACE: Duration = Duration::from_secs(2);
+
+static EXEC_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+fn next_exec_id() -> String {
+    let n = EXEC_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("exec_{n}")
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ExecSpec {
+    pub command: String,
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    pub env: HashMap<String, String>,
+    pub timeout: Duration,
+    pub max_output_bytes: u64,
+    pub max_memory_bytes: Option<u64>,
+    pub max_cpu_seconds: Option<u64>,
+}
+
+pub(crate) struct ExecStarted {
+    pub id: String,
+    pub pid: Option<u32>,
+    /// `stdout`/`stderr` events followed by exactly one `exit` event.
+    pub events: mpsc::Receiver<ExecEvent>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ExecManager {
+    runs: Arc<StdMutex<HashMap<String, Arc<Notify>>>>,
+}
+
+impl ExecManager {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn `spec` and start forwarding its output. Dropping the returned receiver kills the
+    /// command.
+    pub(crate) fn start(&self, spec: ExecSpec) -> Result<ExecStarted, SandboxError> {
+        let mut runs = self.runs.lock().expect("exec registry poisoned");
+        if runs.len() >= MAX_CONCURRENT_EXECS {
+            return Err(SandboxError::Conflict {
+                message: format!("maximum of {MAX_CONCURRENT_EXECS} concurrent commands reached"),
+            });
+        }
+
+        let mut command = Command::new(&spec.command);
+        command
+            .args(&spec.args)
+            .current_dir(&spec.cwd)
+            .envs(&spec.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        confine(&mut command, spec.max_memory_bytes, spec.max_cpu_seconds);
+
+        let mut child = command.spawn().map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => SandboxError::InvalidRequest {
+                message: format!("command not found: {}", spec.command),
+            },
+            _ => SandboxError::StreamError {
+                message: format!("failed to spawn '{}': {err}", spec.command),
+            },
+        })?;
+        let pid = child.id();
+        let id = next_exec_id();
+        let kill = Arc::new(Notify::new());
+        runs.insert(id.clone(), kill.clone());
+        drop(runs);
+        tracing::info!(exec_id = %id, command = %spec.command, pid = ?pid, "exec started");
+
+        let (tx, rx) = mpsc::channel(64);
+        let budget = Arc::new(OutputBudget {
+            remaining: AtomicU64::new(spec.max_output_bytes),
+            truncated: AtomicBool::new(false),
+        });
+        let stdout = tokio::spawn(pump(
+            child.stdout.take(),
+            |data| ExecEvent::Stdout { data },
+            tx.clone(),
+            budget.clone(),
+        ));
+        let stderr = tokio::spawn(pump(
+            child.stderr.take(),
+            |data| ExecEvent::Stderr { data },
+            tx.clone(),
+            budget.clone(),
+        ));
+
+        let runs = self.runs.clone();
+        let exec_id = id.clone();
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let stop = tokio::select! {
+                status = child.wait() => Stop::Exited(status),
+                _ = tokio::time::sleep(spec.timeout) => Stop::TimedOut,
+                _ = kill.notified() => Stop::Killed,
+                _ = tx.closed() => Stop::Killed,
+            };
+            let (status, timed_out, killed) = match stop {
+                Stop::Exited(status) => (status, false, false),
+                Stop::TimedOut => (terminate(&mut child).await, true, false),
+                Stop::Killed => (terminate(&mut child).await, false, true),
+            };
+            for mut pipe in [stdout, stderr] {
+                if tokio::time::timeout(EXEC_PIPE_DRAIN_GRACE, &mut pipe)
+                    .await
+                    .is_err()
+                {
+                    pipe.abort();
+                }
+            }
+
+            let (exit_code, signal) = match &status {
+                Ok(status) => (status.code(), exit_signal(status)),
+                Err(_) => (None, None),
+            };
+            runs.lock()
+                .expect("exec registry poisoned")
+                .remove(&exec_id);
+            tracing::info!(exec_id = %exec_id, exit_code = ?exit_code, timed_out, killed, "exec finished");
+            let _ = tx
+                .send(ExecEvent::Exit {
+                    exit_code,
+                    signal,
+                    timed_out,
+                    killed,
+                    output_truncated: budget.truncated.load(Ordering::Relaxed),
+                    duration_ms: started.elapsed().as_millis() as u64,
+                })
+                .await;
+        });
+
+        Ok(ExecStarted {
+            id,
+            pid,
+            events: rx,
+        })
+    }
+
+    /// Kill a running command. Its stream still ends with an `exit` event.
+    pub(crate) fn kill(&self, id: &str) -> Result<(), SandboxError> {
+        let runs = self.runs.lock().expect("exec registry poisoned");
+        let kill = runs.get(id).ok_or_else(|| SandboxError::NotFound {
+            path: format!("/v1/exec/{id}"),
+        })?;
+        kill.notify_one();
+        Ok(())
+    }
+
+    /// Kill every running command. Called on server shutdown.
+    pub(crate) fn shutdown(&self) {
+        for kill in self.runs.lock().expect("exec registry poisoned").values() {
+            kill.notify_one();
+        }
+    }
+}
+
+enum Stop {
+    Exited(std::io::Result<ExitStatus>),
+    TimedOut,
+    Killed,
+}
+
+/// Bytes left to forward across stdout and stderr.
+struct OutputBudget {
+    remaining: AtomicU64,
+    truncated: AtomicBool,
+}
+
+impl OutputBudget {
+    fn take(&self, wanted: u64) -> u64 {
+        let previous = self
+            .remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                Some(left.saturating_sub(wanted))
+            })
+            .unwrap_or(0);
+        let granted = previous.min(wanted);
+        if granted < wanted {
+            self.truncated.store(true, Ordering::Relaxed);
+        }
+        granted
+    }
+}
+
+async fn pump<R>(
+    reader: Option<R>,
+    event: fn(String) -> ExecEvent,
+    tx: mpsc::Sender<ExecEvent>,
+    budget: Arc<OutputBudget>,
+) where
+    R: AsyncRead + Unpin,
+{
+    let Some(mut reader) = reader else {
+        return;
+    };
+    let mut buffer = vec![0u8; EXEC_READ_CHUNK_BYTES];
+    let mut pending = Vec::new();
+    loop {
+        let read = match reader.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        let granted = budget.take(read as u64) as usize;
+        if granted == 0 {
+            continue;
+        }
+        pending.extend_from_slice(&buffer[..granted]);
+        let text = take_utf8(&mut pending);
+        if !text.is_empty() && tx.send(event(text)).await.is_err() {
+            return;
+        }
+    }
+    if !pending.is_empty() {
+        let _ = tx
+            .send(event(String::from_utf8_lossy(&pending).into_owned()))
+            .await;
+    }
+}
+
+/// Drain the decodable prefix of `pending`, leaving a character split across reads for the next
+/// chunk. Invalid bytes are replaced.
+fn take_utf8(pending: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => pending.len(),
+    };
+    let rest = pending.split_off(complete);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    text
+}
+
+#[cfg(unix)]
+fn confine(command: &mut Command, max_memory_bytes: Option<u64>, max_cpu_seconds: Option<u64>) {
+    command.process_group(0);
+    if max_memory_bytes.is_none() && max_cpu_seconds.is_none() {
+        return;
+    }
+    let rlimit = |value: u64| libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    // SAFETY: the hook only calls setrlimit, which is async-signal-safe, and allocates nothing.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(bytes) = max_memory_bytes {
+                if libc::setrlimit(libc::RLIMIT_AS, &rlimit(bytes)) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(seconds) = max_cpu_seconds {
+                if libc::setrlimit(libc::RLIMIT_CPU, &rlimit(seconds)) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Kill the command and everything in its process group, then reap it.
+async fn terminate(child: &mut Child) -> std::io::Result<ExitStatus> {
+    #[cfg(unix)]
+    {
+        if let Some(pid) = child.id() {
+            // SAFETY: signalling a process group we created; a stale id only yields ESRCH.
+            unsafe {
+                libc::kill(-(pid as i32), libc::SIGKILL);
+            }
+        }
+    }
+    let _ = child.start_kill();
+    child.wait().await
+}
+
+#[cfg(unix)]
+fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
@@ -0,0 +1,300 @@
+//! Review findings of a session, exported as SARIF or JUnit XML.
+//!
+//! Findings come from two places:
+//! - Structured output: a turn's parsed output that is an array of findings, or an object
+//!   whose `findings` array holds them. A finding has a `message` and optionally a `severity`,
+//!   `path`, `line`, `endLine` and `ruleId`.
+//! - Tagged lines in agent messages: `[error] src/lib.rs:12: message`, where the tag is
+//!   `error`, `warning` or `note` and the `path:line: ` location is optional.
+//!
+//! Severities other than `error`, `warning` and `note` map by their usual meaning (`critical`
+//! and `high` are errors, `low` and `info` are notes); findings without one are warnings.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use sandbox_agent_api_types::{AcpItem, AcpItemKind};
+use serde_json::{json, Value};
+
+use crate::transcript::escape;
+
+/// Rule id of findings that do not name one.
+const DEFAULT_RULE: &str = "finding";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Level {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Level {
+    fn parse(severity: &str) -> Option<Self> {
+        match severity.to_ascii_lowercase().as_str() {
+            "error" | "critical" | "high" => Some(Self::Error),
+            "warning" | "warn" | "medium" => Some(Self::Warning),
+            "note" | "info" | "low" => Some(Self::Note),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Finding {
+    pub(crate) rule_id: String,
+    pub(crate) level: Level,
+    pub(crate) message: String,
+    pub(crate) path: Option<String>,
+    pub(crate) line: Option<u64>,
+    pub(crate) end_line: Option<u64>,
+}
+
+/// Findings in the structured `outputs` of a session's turns, then in its agent messages.
+pub(crate) fn collect(items: &[AcpItem], outputs: &[Value]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for output in outputs {
+        let list = match output {
+            Value::Array(list) => list,
+            Value::Object(object) => match object.get("findings") {
+                Some(Value::Array(list)) => list,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        findings.extend(list.iter().filter_map(structured_finding));
+    }
+    for item in items {
+        if item.kind != AcpItemKind::AgentMessage {
+            continue;
+        }
+        let text = item.text.as_deref().unwrap_or_default();
+        findings.extend(text.lines().filter_map(tagged_finding));
+    }
+    findings
+}
+
+fn structured_finding(value: &Value) -> Option<Finding> {
+    let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+    Some(Finding {
+        rule_id: string("ruleId").unwrap_or_else(|| DEFAULT_RULE.to_string()),
+        level: string("severity")
+            .and_then(|severity| Level::parse(&severity))
+            .unwrap_or(Level::Warning),
+        message: string("message")?,
+        path: string("path"),
+        line: value.get("line").and_then(Value::as_u64),
+        end_line: value.get("endLine").and_then(Value::as_u64),
+    })
+}
+
+/// A `[severity] path:line: message` line.
+fn tagged_finding(line: &str) -> Option<Finding> {
+    let rest = line.trim().strip_prefix('[')?;
+    let (tag, rest) = rest.split_once(']')?;
+    let level = match tag {
+        "error" => Level::Error,
+        "warning" => Level::Warning,
+        "note" => Level::Note,
+        _ => return None,
+    };
+    let rest = rest.trim();
+    let location = rest.split_once(": ").and_then(|(location, message)| {
+        let (path, line) = location.rsplit_once(':')?;
+        let line = line.parse::<u64>().ok()?;
+        (!path.is_empty() && !path.contains(char::is_whitespace))
+            .then(|| (path.to_string(), line, message))
+    });
+    let (path, line, message) = match location {
+        Some((path, line, message)) => (Some(path), Some(line), message),
+        None => (None, None, rest),
+    };
+    (!message.is_empty()).then(|| Finding {
+        rule_id: DEFAULT_RULE.to_string(),
+        level,
+        message: message.to_string(),
+        path,
+        line,
+        end_line: None,
+    })
+}
+
+/// A SARIF 2.1.0 log with one run, as GitHub code scanning takes it.
+pub(crate) fn sarif(findings: &[Finding]) -> Value {
+    let rules = findings
+        .iter()
+        .map(|finding| finding.rule_id.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|id| json!({ "id": id }))
+        .collect::<Vec<_>>();
+    let results = findings
+        .iter()
+        .map(|finding| {
+            let mut result = json!({
+                "ruleId": finding.rule_id,
+                "level": finding.level.as_str(),
+                "message": { "text": finding.message },
+            });
+            if let Some(path) = &finding.path {
+                let mut location = json!({ "artifactLocation": { "uri": path } });
+                if let Some(line) = finding.line {
+                    location["region"] = json!({
+                        "startLine": line,
+                        "endLine": finding.end_line.unwrap_or(line),
+                    });
+                }
+                result["locations"] = json!([{ "physicalLocation": location }]);
+            }
+            result
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "sandbox-agent",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://sandboxagent.dev",
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// A JUnit report with one test case per finding. Errors and warnings fail their case; notes
+/// pass with the message as output.
+pub(crate) fn junit(session_id: &str, findings: &[Finding]) -> String {
+    let failures = findings
+        .iter()
+        .filter(|finding| finding.level != Level::Note)
+        .count();
+    let suite = escape(&format!("session {session_id}"));
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuites name=\"sandbox-agent\" tests=\"{tests}\" failures=\"{failures}\">\n\
+         <testsuite name=\"{suite}\" tests=\"{tests}\" failures=\"{failures}\" errors=\"0\" skipped=\"0\">\n",
+        tests = findings.len(),
+    );
+    for finding in findings {
+        let detail = match (&finding.path, finding.line) {
+            (Some(path), Some(line)) => format!("{path}:{line}: {}", finding.message),
+            (Some(path), None) => format!("{path}: {}", finding.message),
+            (None, _) => finding.message.clone(),
+        };
+        let _ = writeln!(
+            out,
+            "<testcase classname=\"{}\" name=\"{}\">",
+            escape(finding.path.as_deref().unwrap_or("sandbox-agent")),
+            escape(&format!("{}: {}", finding.rule_id, finding.message))
+        );
+        let detail = escape(&detail);
+        match finding.level {
+            Level::Note => {
+                let _ = writeln!(out, "<system-out>{detail}</system-out>");
+            }
+            level => {
+                let _ = writeln!(
+                    out,
+                    "<failure type=\"{}\" message=\"{}\">{detail}</failure>",
+                    level.as_str(),
+                    escape(&finding.message)
+                );
+            }
+        }
+        out.push_str("</testcase>\n");
+    }
+    out.push_str("</testsuite>\n</testsuites>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use sandbox_agent_api_types::AcpItemStatus;
+
+    use super::*;
+
+    fn message(kind: AcpItemKind, text: &str) -> AcpItem {
+        AcpItem {
+            item_id: "agent_message-1".to_string(),
+            kind,
+            status: AcpItemStatus::Completed,
+            text: Some(text.to_string()),
+            content: Vec::new(),
+            title: None,
+            tool_kind: None,
+            raw_input: None,
+            raw_output: None,
+            parent_tool_call_id: None,
+            sequence: 1,
+            updated_sequence: 1,
+        }
+    }
+
+    #[test]
+    fn findings_come_from_structured_output_and_tagged_lines() {
+        let outputs = [
+            json!({ "findings": [
+                { "message": "SQL injection", "severity": "critical", "path": "src/db.rs", "line": 40, "ruleId": "sqli" },
+                { "severity": "low" },
+            ] }),
+            json!("not findings"),
+        ];
+        let items = [
+            message(AcpItemKind::UserMessage, "[error] a.rs:1: from the prompt"),
+            message(
+                AcpItemKind::AgentMessage,
+                "Review done.\n[warning] src/main.rs:12: unwrap on user input\n[note] Consider: more tests\n[todo] not a finding",
+            ),
+        ];
+        let findings = collect(&items, &outputs);
+        assert_eq!(findings.len(), 3);
+        assert_eq!(findings[0].level, Level::Error);
+        assert_eq!(findings[0].rule_id, "sqli");
+        assert_eq!(
+            findings[1],
+            Finding {
+                rule_id: DEFAULT_RULE.to_string(),
+                level: Level::Warning,
+                message: "unwrap on user input".to_string(),
+                path: Some("src/main.rs".to_string()),
+                line: Some(12),
+                end_line: None,
+            }
+        );
+        assert_eq!(findings[2].message, "Consider: more tests");
+        assert!(findings[2].path.is_none());
+
+        let log = sarif(&findings);
+        let results = &log["runs"][0]["results"];
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"],
+            json!({ "artifactLocation": { "uri": "src/db.rs" }, "region": { "startLine": 40, "endLine": 40 } })
+        );
+        assert!(results[2].get("locations").is_none());
+        assert_eq!(
+            log["runs"][0]["tool"]["driver"]["rules"],
+            json!([{ "id": "finding" }, { "id": "sqli" }])
+        );
+
+        let report = junit("s-1", &findings);
+        assert!(report.contains("<testsuite name=\"session s-1\" tests=\"3\" failures=\"2\""));
+        assert!(report.contains(
+            "<testcase classname=\"src/main.rs\" name=\"finding: unwrap on user input\">\n\
+             <failure type=\"warning\" message=\"unwrap on user input\">\
+             src/main.rs:12: unwrap on user input</failure>\n</testcase>\n"
+        ));
+        assert!(report.contains("<system-out>Consider: more tests</system-out>"));
+    }
+}
@@ -0,0 +1,191 @@
+//! The `/v1/events/sse` firehose: events from every ACP server, numbered in the order they
+//! were published. The most recent events are kept so a reconnecting subscriber can resume
+//! after its `Last-Event-ID`, and so one that falls behind the live broadcast can be re-synced
+//! instead of silently losing events.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use futures::{stream, Stream};
+use tokio::sync::broadcast;
+
+use crate::acp_proxy_runtime::AcpFirehoseEvent;
+
+/// Events kept for `Last-Event-ID` resume and lag re-sync.
+const RECENT_CAPACITY: usize = 1024;
+const BROADCAST_CAPACITY: usize = 1024;
+
+#[derive(Debug)]
+pub(crate) struct Firehose {
+    recent: StdMutex<Recent>,
+    sender: broadcast::Sender<(u64, AcpFirehoseEvent)>,
+}
+
+#[derive(Debug, Default)]
+struct Recent {
+    last_id: u64,
+    events: VecDeque<(u64, AcpFirehoseEvent)>,
+}
+
+/// What a firehose subscription yields.
+#[derive(Debug, Clone)]
+pub(crate) enum FirehoseItem {
+    Event(u64, AcpFirehoseEvent),
+    /// The subscriber fell behind after event `after`; `missed` events were no longer kept
+    /// and the kept ones follow.
+    Resync {
+        after: u64,
+        missed: u64,
+    },
+}
+
+impl Default for Firehose {
+    fn default() -> Self {
+        Self {
+            recent: StdMutex::new(Recent::default()),
+            sender: broadcast::channel(BROADCAST_CAPACITY).0,
+        }
+    }
+}
+
+impl Firehose {
+    /// Number `event`, keep it and broadcast it. Numbering, keeping and broadcasting happen
+    /// under one lock, so ids are strictly increasing for every subscriber.
+    pub(crate) fn publish(&self, event: AcpFirehoseEvent) {
+        let mut recent = self.recent.lock().expect("firehose lock");
+        recent.last_id += 1;
+        let id = recent.last_id;
+        recent.events.push_back((id, event.clone()));
+        while recent.events.len() > RECENT_CAPACITY {
+            recent.events.pop_front();
+        }
+        let _ = self.sender.send((id, event));
+    }
+
+    /// Kept events after `after` followed by live ones; without `after`, live events only.
+    /// An `after` newer than the last event (as after a daemon restart) resumes from the start
+    /// of the live stream.
+    pub(crate) fn stream(
+        self: &Arc<Self>,
+        after: Option<u64>,
+    ) -> impl Stream<Item = FirehoseItem> + Send + 'static {
+        let (pending, rx, cursor) = {
+            let recent = self.recent.lock().expect("firehose lock");
+            let cursor = after.map_or(recent.last_id, |after| after.min(recent.last_id));
+            (
+                recent.after(cursor).collect::<VecDeque<_>>(),
+                self.sender.subscribe(),
+                recent.last_id,
+            )
+        };
+        let firehose = self.clone();
+        stream::unfold(
+            (rx, cursor, pending),
+            move |(mut rx, mut cursor, mut pending)| {
+                let firehose = firehose.clone();
+                async move {
+                    while pending.is_empty() {
+                        match rx.recv().await {
+                            Ok((id, event)) if id > cursor => {
+                                cursor = id;
+                                pending.push_back(FirehoseItem::Event(id, event));
+                            }
+                            Ok(_) => {}
+                            Err(broadcast::error::RecvError::Lagged(_)) => {
+                                let recent = firehose.recent.lock().expect("firehose lock");
+                                let missed = recent
+                                    .events
+                                    .front()
+                                    .map_or(0, |(oldest, _)| oldest.saturating_sub(cursor + 1));
+                                pending.push_back(FirehoseItem::Resync {
+                                    after: cursor,
+                                    missed,
+                                });
+                                pending.extend(recent.after(cursor));
+                                cursor = cursor.max(recent.last_id);
+                            }
+                            Err(broadcast::error::RecvError::Closed) => return None,
+                        }
+                    }
+                    let item = pending.pop_front()?;
+                    Some((item, (rx, cursor, pending)))
+                }
+            },
+        )
+    }
+}
+
+impl Recent {
+    fn after(&self, cursor: u64) -> impl Iterator<Item = FirehoseItem> + '_ {
+        self.events
+            .iter()
+            .filter(move |(id, _)| *id > cursor)
+            .map(|(id, event)| FirehoseItem::Event(*id, event.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use sandbox_agent_agent_management::agents::AgentId;
+    use serde_json::json;
+
+    fn event(sequence: u64) -> AcpFirehoseEvent {
+        AcpFirehoseEvent {
+            server_id: "s".to_string(),
+            agent: AgentId::Mock,
+            sequence,
+            message: json!({ "jsonrpc": "2.0", "method": "session/update" }),
+        }
+    }
+
+    fn ids(items: &[FirehoseItem]) -> Vec<u64> {
+        items
+            .iter()
+            .filter_map(|item| match item {
+                FirehoseItem::Event(id, _) => Some(*id),
+                FirehoseItem::Resync { .. } => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn resumes_after_last_event_id_then_follows_live_events() {
+        let firehose = Arc::new(Firehose::default());
+        for sequence in 1..=3 {
+            firehose.publish(event(sequence));
+        }
+        let resumed = firehose.stream(Some(1));
+        let live = firehose.stream(None);
+        firehose.publish(event(4));
+
+        let resumed = resumed.take(3).collect::<Vec<_>>().await;
+        assert_eq!(ids(&resumed), vec![2, 3, 4]);
+        let live = live.take(1).collect::<Vec<_>>().await;
+        assert_eq!(ids(&live), vec![4]);
+
+        // An id from before a restart is newer than anything published since.
+        let stale = firehose.stream(Some(99));
+        firehose.publish(event(5));
+        assert_eq!(ids(&stale.take(1).collect::<Vec<_>>().await), vec![5]);
+    }
+
+    #[tokio::test]
+    async fn lagging_subscriber_is_resynced_from_recent_events() {
+        let firehose = Arc::new(Firehose::default());
+        let lagging = firehose.stream(None);
+        let total = (RECENT_CAPACITY + BROADCAST_CAPACITY) as u64;
+        for sequence in 1..=total {
+            firehose.publish(event(sequence));
+        }
+
+        let items = lagging.take(RECENT_CAPACITY + 1).collect::<Vec<_>>().await;
+        let missed = total - RECENT_CAPACITY as u64;
+        assert!(matches!(
+            items[0],
+            FirehoseItem::Resync { after: 0, missed: m } if m == missed
+        ));
+        assert_eq!(ids(&items), (missed + 1..=total).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,401 @@
+//! Chunked, resumable file uploads behind `/v1/fs/upload`.
+//!
+//! Each upload stages its bytes in a hidden file next to the destination, so completing it is a
+//! rename on the same filesystem. Chunks are written at an explicit offset that may not skip
+//! ahead of what has been received; resending from an earlier offset truncates the staged data
+//! first. A client that lost track of progress reads the upload's `received` count and resumes
+//! from there. Upload state lives in memory, so uploads do not survive a server restart; the
+//! staging files they leave behind are removed when the next upload into the same directory
+//! starts. Chunks are written on the blocking pool, off the async workers.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use axum::body::Bytes;
+use futures::{Stream, StreamExt};
+use sandbox_agent_error::SandboxError;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::router::{map_fs_error, random_id};
+
+/// Uploads idle for longer than this are discarded, with their staged bytes, when the next
+/// upload starts.
+const UPLOAD_IDLE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const UPLOAD_ID_PREFIX: &str = "upload_";
+const STAGING_SUFFIX: &str = ".upload";
+
+/// Unguessable, so one client cannot write into another's upload by counting.
+fn next_upload_id() -> String {
+    format!("{UPLOAD_ID_PREFIX}{}", random_id())
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FsUploadSnapshot {
+    pub id: String,
+    pub destination: PathBuf,
+    pub received: u64,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FsUploadCompleted {
+    pub destination: PathBuf,
+    pub bytes_written: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug)]
+struct FsUpload {
+    id: String,
+    destination: PathBuf,
+    staging: PathBuf,
+    size: Option<u64>,
+    sha256: Option<String>,
+    overwrite: bool,
+    received: u64,
+    updated_at: Instant,
+}
+
+impl FsUpload {
+    fn snapshot(&self) -> FsUploadSnapshot {
+        FsUploadSnapshot {
+            id: self.id.clone(),
+            destination: self.destination.clone(),
+            received: self.received,
+            size: self.size,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct FsUploadManager {
+    uploads: StdMutex<HashMap<String, Arc<Mutex<FsUpload>>>>,
+}
+
+impl FsUploadManager {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start an upload to `destination`, whose parent directory must already exist.
+    pub(crate) fn begin(
+        &self,
+        destination: PathBuf,
+        size: Option<u64>,
+        sha256: Option<String>,
+        overwrite: bool,
+    ) -> Result<FsUploadSnapshot, SandboxError> {
+        self.discard_idle();
+
+        let sha256 = sha256.map(|value| normalize_sha256(&value)).transpose()?;
+        match fs::metadata(&destination) {
+            Ok(metadata) if metadata.is_dir() => {
+                return Err(SandboxError::InvalidRequest {
+                    message: format!("destination is a directory: {}", destination.display()),
+                });
+            }
+            Ok(_) if !overwrite => {
+                return Err(SandboxError::AlreadyExists {
+                    path: destination.display().to_string(),
+                });
+            }
+            _ => {}
+        }
+
+        self.sweep_orphaned_staging(&destination);
+        let id = next_upload_id();
+        let file_name = destination
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| SandboxError::InvalidRequest {
+                message: format!("destination has no file name: {}", destination.display()),
+            })?;
+        let staging = destination.with_file_name(format!(".{file_name}.{id}{STAGING_SUFFIX}"));
+        fs::File::create(&staging).map_err(|err| map_fs_error(&staging, err))?;
+
+        let upload = FsUpload {
+            id: id.clone(),
+            destination,
+            staging,
+            size,
+            sha256,
+            overwrite,
+            received: 0,
+            updated_at: Instant::now(),
+        };
+        let snapshot = upload.snapshot();
+        self.uploads
+            .lock()
+            .expect("upload registry poisoned")
+            .insert(id, Arc::new(Mutex::new(upload)));
+        Ok(snapshot)
+    }
+
+    pub(crate) async fn status(&self, id: &str) -> Result<FsUploadSnapshot, SandboxError> {
+        let upload = self.get(id)?;
+        let upload = upload.lock().await;
+        Ok(upload.snapshot())
+    }
+
+    /// Write `chunk` at `offset`. When `sha256` is given the chunk is checked against it and
+    /// rejected, leaving the upload at `offset`, if it does not match.
+    pub(crate) async fn write_chunk<S, E>(
+        &self,
+        id: &str,
+        offset: u64,
+        sha256: Option<&str>,
+        mut chunk: S,
+    ) -> Result<FsUploadSnapshot, SandboxError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let expected = sha256.map(normalize_sha256).transpose()?;
+        let upload = self.get(id)?;
+        let mut upload = upload.lock().await;
+        if offset > upload.received {
+            return Err(SandboxError::Conflict {
+                message: format!(
+                    "chunk offset {offset} is past the {} bytes received",
+                    upload.received
+                ),
+            });
+        }
+
+        let staging = upload.staging.clone();
+        let mut file = blocking({
+            let staging = staging.clone();
+            move || {
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .open(&staging)
+                    .map_err(|err| map_fs_error(&staging, err))?;
+                file.set_len(offset)
+                    .map_err(|err| map_fs_error(&staging, err))?;
+                file.seek(SeekFrom::Start(offset))
+                    .map_err(|err| map_fs_error(&staging, err))?;
+                Ok(file)
+            }
+        })
+        .await?;
+        // Everything from `offset` on is rewritten, so the upload only advances on success.
+        upload.received = offset;
+        upload.updated_at = Instant::now();
+
+        let size = upload.size;
+        let mut hasher = Sha256::new();
+        let mut written = 0u64;
+        let outcome = async {
+            while let Some(frame) = chunk.next().await {
+                let frame = frame.map_err(|err| SandboxError::StreamError {
+                    message: format!("chunk body: {err}"),
+                })?;
+                written += frame.len() as u64;
+                if let Some(size) = size {
+                    if offset + written > size {
+                        return Err(SandboxError::InvalidRequest {
+                            message: format!(
+                                "chunk extends past the declared size of {size} bytes"
+                            ),
+                        });
+                    }
+                }
+                hasher.update(&frame);
+                let staging = staging.clone();
+                file = blocking(move || {
+                    file.write_all(&frame)
+                        .map_err(|err| map_fs_error(&staging, err))?;
+                    Ok(file)
+                })
+                .await?;
+            }
+            let actual = format!("{:x}", hasher.finalize());
+            match expected {
+                Some(expected) if expected != actual => Err(SandboxError::InvalidRequest {
+                    message: format!("chunk sha256 mismatch: expected {expected}, got {actual}"),
+                }),
+                _ => Ok(()),
+            }
+        }
+        .await;
+
+        match outcome {
+            Ok(()) => {
+                upload.received = offset + written;
+                upload.updated_at = Instant::now();
+                Ok(upload.snapshot())
+            }
+            Err(err) => {
+                // The file may have been lost with a failed write, so truncate through a new one.
+                let _ = blocking(move || {
+                    fs::OpenOptions::new()
+                        .write(true)
+                        .open(&staging)
+                        .and_then(|file| file.set_len(offset))
+                        .map_err(|err| map_fs_error(&staging, err))
+                })
+                .await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Verify the staged bytes against the declared size and checksum and move them into place.
+    pub(crate) async fn complete(&self, id: &str) -> Result<FsUploadCompleted, SandboxError> {
+        let upload = self.get(id)?;
+        let upload = upload.lock().await;
+        if let Some(size) = upload.size {
+            if upload.received != size {
+                return Err(SandboxError::Conflict {
+                    message: format!(
+                        "upload is incomplete: received {} of {size} bytes",
+                        upload.received
+                    ),
+                });
+            }
+        }
+
+        let staging = upload.staging.clone();
+        let actual = tokio::task::spawn_blocking(move || sha256_file(&staging))
+            .await
+            .map_err(|err| SandboxError::StreamError {
+                message: err.to_string(),
+            })??;
+        if let Some(expected) = &upload.sha256 {
+            if *expected != actual {
+                return Err(SandboxError::InvalidRequest {
+                    message: format!("upload sha256 mismatch: expected {expected}, got {actual}"),
+                });
+            }
+        }
+        if !upload.overwrite && upload.destination.exists() {
+            return Err(SandboxError::AlreadyExists {
+                path: upload.destination.display().to_string(),
+            });
+        }
+        fs::rename(&upload.staging, &upload.destination)
+            .map_err(|err| map_fs_error(&upload.destination, err))?;
+        self.remove(id);
+
+        Ok(FsUploadCompleted {
+            destination: upload.destination.clone(),
+            bytes_written: upload.received,
+            sha256: actual,
+        })
+    }
+
+    /// Drop the upload and its staged bytes.
+    pub(crate) async fn abort(&self, id: &str) -> Result<PathBuf, SandboxError> {
+        let upload = self.get(id)?;
+        let upload = upload.lock().await;
+        let _ = fs::remove_file(&upload.staging);
+        self.remove(id);
+        Ok(upload.destination.clone())
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<Mutex<FsUpload>>, SandboxError> {
+        self.uploads
+            .lock()
+            .expect("upload registry poisoned")
+            .get(id)
+            .cloned()
+            .ok_or_else(|| SandboxError::NotFound {
+                path: id.to_string(),
+            })
+    }
+
+    fn remove(&self, id: &str) {
+        self.uploads
+            .lock()
+            .expect("upload registry poisoned")
+            .remove(id);
+    }
+
+    /// Remove staging files in `destination`'s directory whose upload this process does not
+    /// know, left by uploads that were in progress when an earlier server stopped.
+    fn sweep_orphaned_staging(&self, destination: &Path) {
+        let Some(entries) = destination.parent().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return;
+        };
+        let uploads = self.uploads.lock().expect("upload registry poisoned");
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(id) = staging_upload_id(&name) else {
+                continue;
+            };
+            if uploads.contains_key(id) || !entry.file_type().is_ok_and(|kind| kind.is_file()) {
+                continue;
+            }
+            let path = entry.path();
+            tracing::info!(path = %path.display(), "removing orphaned upload staging file");
+            let _ = fs::remove_file(&path);
+        }
+    }
+
+    fn discard_idle(&self) {
+        let mut uploads = self.uploads.lock().expect("upload registry poisoned");
+        uploads.retain(|_, upload| {
+            // An upload that is busy is not idle.
+            let Ok(upload) = upload.try_lock() else {
+                return true;
+            };
+            if upload.updated_at.elapsed() < UPLOAD_IDLE_TTL {
+                return true;
+            }
+            tracing::info!(upload_id = %upload.id, "discarding idle upload");
+            let _ = fs::remove_file(&upload.staging);
+            false
+        });
+    }
+}
+
+/// The upload id of a staging file named `.{file}.{id}.upload`, as [`FsUploadManager`] names
+/// them; ids contain no dots.
+fn staging_upload_id(name: &str) -> Option<&str> {
+    let stem = name.strip_prefix('.')?.strip_suffix(STAGING_SUFFIX)?;
+    let (_, id) = stem.rsplit_once('.')?;
+    id.starts_with(UPLOAD_ID_PREFIX).then_some(id)
+}
+
+/// Run file I/O on the blocking pool.
+async fn blocking<T: Send + 'static>(
+    io: impl FnOnce() -> Result<T, SandboxError> + Send + 'static,
+) -> Result<T, SandboxError> {
+    tokio::task::spawn_blocking(io)
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })?
+}
+
+fn normalize_sha256(value: &str) -> Result<String, SandboxError> {
+    let value = value.trim().to_ascii_lowercase();
+    if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(SandboxError::InvalidRequest {
+            message: "sha256 must be 64 hex characters".to_string(),
+        });
+    }
+    Ok(value)
+}
+
+fn sha256_file(path: &Path) -> Result<String, SandboxError> {
+    let mut file = fs::File::open(path).map_err(|err| map_fs_error(path, err))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|err| map_fs_error(path, err))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
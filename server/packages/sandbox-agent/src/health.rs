@@ -0,0 +1,242 @@
+//! Component checks behind `GET /v1/health` (liveness) and `GET /v1/ready` (readiness).
+//!
+//! Only disk space is critical: without it the daemon can neither write workspaces nor
+//! logs, so a disk below `SANDBOX_AGENT_HEALTH_MIN_FREE_MB` turns the response into a `503`.
+//! Everything else can at most mark the server `degraded`.
+//!
+//! Readiness only flips once at startup: the daemon is ready after it accepts connections
+//! and has loaded what earlier runs persisted.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use sandbox_agent_agent_credentials::{
+    extract_all_credentials, CredentialExtractionOptions, ProviderCredentials,
+};
+use sandbox_agent_api_types::{
+    AgentProcessHealth, CredentialHealth, DiskHealth, HealthComponents, HealthResponse,
+    HealthStatus, ManagedServerHealth, ReadinessResponse,
+};
+
+use crate::router::AppState;
+
+const MIN_FREE_ENV: &str = "SANDBOX_AGENT_HEALTH_MIN_FREE_MB";
+const DEFAULT_MIN_FREE_MB: u64 = 100;
+
+#[derive(Debug, Default)]
+pub(crate) struct Readiness {
+    serving: AtomicBool,
+    persistence_loaded: AtomicBool,
+}
+
+impl Readiness {
+    pub(crate) fn mark_serving(&self) {
+        self.serving.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn mark_persistence_loaded(&self) {
+        self.persistence_loaded.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn check(&self) -> ReadinessResponse {
+        let serving = self.serving.load(Ordering::Acquire);
+        let persistence_loaded = self.persistence_loaded.load(Ordering::Acquire);
+        ReadinessResponse {
+            ready: serving && persistence_loaded,
+            serving,
+            persistence_loaded,
+        }
+    }
+}
+
+pub(crate) async fn check(state: &AppState, verbose: bool) -> HealthResponse {
+    let processes = state.acp_proxy().agent_processes().await;
+    let opencode = state.opencode_server_manager().status().await;
+    let disk_paths = vec![
+        ("workspace", workspace_dir(state)),
+        ("logs", crate::cli::default_server_log_dir()),
+    ];
+    let (credentials, disks) = tokio::task::spawn_blocking(move || {
+        let credentials = extract_all_credentials(&CredentialExtractionOptions::new());
+        let credentials = [
+            ("anthropic".to_string(), credentials.anthropic),
+            ("openai".to_string(), credentials.openai),
+        ]
+        .into_iter()
+        .chain(
+            credentials
+                .other
+                .into_iter()
+                .map(|(provider, credential)| (provider, Some(credential))),
+        )
+        .map(|(provider, credential)| credential_health(provider, credential))
+        .collect::<Vec<_>>();
+        let min_free = min_free_bytes();
+        let disks = disk_paths
+            .into_iter()
+            .map(|(name, path)| disk_health(name, &path, min_free))
+            .collect::<Vec<_>>();
+        (credentials, disks)
+    })
+    .await
+    .unwrap_or_default();
+
+    let active_servers = processes.len();
+    let mut by_agent = BTreeMap::<&'static str, (usize, usize)>::new();
+    for (agent, exited) in processes {
+        let counts = by_agent.entry(agent.as_str()).or_default();
+        if exited {
+            counts.1 += 1;
+        } else {
+            counts.0 += 1;
+        }
+    }
+    let agent_processes = by_agent
+        .into_iter()
+        .map(|(agent, (running, exited))| AgentProcessHealth {
+            agent: agent.to_string(),
+            status: if exited > 0 {
+                HealthStatus::Degraded
+            } else {
+                HealthStatus::Ok
+            },
+            running,
+            exited,
+        })
+        .collect::<Vec<_>>();
+    let opencode_server = ManagedServerHealth {
+        // Set while a server that should run is down, also next to running pool members.
+        status: if opencode.last_error.is_some() {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Ok
+        },
+        running: opencode.running,
+        starts: opencode.starts,
+        last_error: opencode.last_error,
+    };
+
+    let status = if disks.iter().any(|disk| disk.status == HealthStatus::Down) {
+        HealthStatus::Down
+    } else if opencode_server.status != HealthStatus::Ok
+        || agent_processes
+            .iter()
+            .any(|process| process.status != HealthStatus::Ok)
+    {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Ok
+    };
+
+    HealthResponse {
+        status,
+        uptime_ms: state.uptime().as_millis() as u64,
+        active_servers,
+        occupancy: state.acp_proxy().occupancy(),
+        components: verbose.then_some(HealthComponents {
+            opencode_server,
+            agent_processes,
+            credentials,
+            disks,
+        }),
+    }
+}
+
+/// Where ACP servers work by default: the first filesystem root, else the daemon's directory.
+fn workspace_dir(state: &AppState) -> PathBuf {
+    state
+        .fs_roots()
+        .first()
+        .cloned()
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn credential_health(
+    provider: String,
+    credential: Option<ProviderCredentials>,
+) -> CredentialHealth {
+    CredentialHealth {
+        provider,
+        available: credential.is_some(),
+        source: credential.map(|credential| credential.source),
+    }
+}
+
+fn min_free_bytes() -> u64 {
+    std::env::var(MIN_FREE_ENV)
+        .ok()
+        .and_then(|raw| raw.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MIN_FREE_MB)
+        .saturating_mul(1024 * 1024)
+}
+
+fn disk_health(name: &str, path: &Path, min_free: u64) -> DiskHealth {
+    let space = disk_space(path);
+    let status = match space {
+        Some((available, _)) if available < min_free => HealthStatus::Down,
+        _ => HealthStatus::Ok,
+    };
+    DiskHealth {
+        name: name.to_string(),
+        path: path.to_string_lossy().into_owned(),
+        status,
+        available_bytes: space.map(|(available, _)| available),
+        total_bytes: space.map(|(_, total)| total),
+    }
+}
+
+/// `(available, total)` bytes of the filesystem holding `path`, or of its nearest existing
+/// ancestor when it has not been created yet.
+#[cfg(unix)]
+fn disk_space(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+    let c_path = std::ffi::CString::new(existing.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let fragment = stat.f_frsize as u64;
+    Some((
+        (stat.f_bavail as u64).saturating_mul(fragment),
+        (stat.f_blocks as u64).saturating_mul(fragment),
+    ))
+}
+
+#[cfg(not(unix))]
+fn disk_space(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready_once_serving_and_loaded() {
+        let readiness = Readiness::default();
+        readiness.mark_persistence_loaded();
+        assert!(!readiness.check().ready);
+        readiness.mark_serving();
+        assert!(readiness.check().ready);
+    }
+
+    #[test]
+    fn disk_below_minimum_is_down() {
+        let dir = std::env::temp_dir()
+            .join("sandbox-agent-health-missing")
+            .join("logs");
+        let disk = disk_health("logs", &dir, 0);
+        assert_eq!(disk.status, HealthStatus::Ok);
+        if cfg!(unix) {
+            assert!(disk.available_bytes.is_some());
+            assert_eq!(
+                disk_health("logs", &dir, u64::MAX).status,
+                HealthStatus::Down
+            );
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! Instruction files written into a session's working directory from daemon-managed templates.
+//!
+//! Templates are Markdown files in the instructions directory (`SANDBOX_AGENT_INSTRUCTIONS_DIR`,
+//! by default `sandbox-agent/instructions` in the data directory), one `{name}.md` each, managed
+//! at `/v1/instructions/templates`. A `session/new` asks for files with
+//! `_meta["sandboxagent.dev"].instructionFiles`:
+//!
+//! ```json
+//! [{"template": "guardrails", "path": "AGENTS.md", "variables": {"team": "payments"}}]
+//! ```
+//!
+//! `path` is relative to the session's `cwd` and defaults to the file the agent reads:
+//! `CLAUDE.md` for Claude and `AGENTS.md` for every other agent. Each `{{name}}` in the template
+//! is replaced with `variables.name`, or with the built-in `agent`, `serverId` or `cwd`; a
+//! placeholder without a value is an error. The files are written before the agent sees the
+//! `session/new`, never over an existing file, and removed again when the session is not
+//! created or when its server ends.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_api_types::InstructionTemplate;
+use sandbox_agent_error::SandboxError;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::router::map_fs_error;
+
+pub const INSTRUCTIONS_DIR_ENV: &str = "SANDBOX_AGENT_INSTRUCTIONS_DIR";
+const TEMPLATE_EXTENSION: &str = "md";
+/// Instruction files one `session/new` may ask for.
+const MAX_FILES: usize = 16;
+
+pub fn default_instructions_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("sandbox-agent").join("instructions"))
+        .unwrap_or_else(|| {
+            PathBuf::from(".")
+                .join(".sandbox-agent")
+                .join("instructions")
+        })
+}
+
+#[derive(Debug)]
+pub(crate) struct InstructionTemplates {
+    dir: PathBuf,
+}
+
+impl InstructionTemplates {
+    pub(crate) fn from_env() -> Self {
+        let dir = std::env::var_os(INSTRUCTIONS_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(default_instructions_dir);
+        Self { dir }
+    }
+
+    pub(crate) fn list(&self) -> Result<Vec<InstructionTemplate>, SandboxError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(map_fs_error(&self.dir, err)),
+        };
+        let mut names = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+            .filter_map(|entry| {
+                let path = entry.path();
+                (path.extension()? == TEMPLATE_EXTENSION)
+                    .then(|| path.file_stem()?.to_str().map(str::to_string))
+                    .flatten()
+            })
+            .filter(|name| validate_name(name).is_ok())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.iter().map(|name| self.get(name)).collect()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Result<InstructionTemplate, SandboxError> {
+        validate_name(name)?;
+        let path = self.path(name);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(not_found(name)),
+            Err(err) => return Err(map_fs_error(&path, err)),
+        };
+        Ok(template(name, content))
+    }
+
+    /// Create or replace the template `name`.
+    pub(crate) fn put(
+        &self,
+        name: &str,
+        content: String,
+    ) -> Result<InstructionTemplate, SandboxError> {
+        validate_name(name)?;
+        fs::create_dir_all(&self.dir).map_err(|err| map_fs_error(&self.dir, err))?;
+        let path = self.path(name);
+        let staged = path.with_extension("md.tmp");
+        fs::write(&staged, &content).map_err(|err| map_fs_error(&staged, err))?;
+        fs::rename(&staged, &path).map_err(|err| map_fs_error(&path, err))?;
+        Ok(template(name, content))
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> Result<(), SandboxError> {
+        validate_name(name)?;
+        let path = self.path(name);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(not_found(name)),
+            Err(err) => Err(map_fs_error(&path, err)),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.{TEMPLATE_EXTENSION}"))
+    }
+}
+
+/// An instruction file a `session/new` asks for.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct InstructionFileRequest {
+    template: String,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    variables: BTreeMap<String, String>,
+}
+
+/// Take `instructionFiles` off a `session/new`.
+pub(crate) fn take_instruction_files(
+    payload: &mut Value,
+) -> Result<Vec<InstructionFileRequest>, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/new") {
+        return Ok(Vec::new());
+    }
+    let files = match payload
+        .pointer_mut("/params/_meta/sandboxagent.dev")
+        .and_then(Value::as_object_mut)
+        .and_then(|ours| ours.remove("instructionFiles"))
+    {
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(files) => files,
+    };
+    let files: Vec<InstructionFileRequest> = serde_json::from_value(files).map_err(|err| {
+        invalid(format!(
+            "instructionFiles must be a list of {{template, path?, variables?}}: {err}"
+        ))
+    })?;
+    if files.len() > MAX_FILES {
+        return Err(invalid(format!(
+            "instructionFiles may list at most {MAX_FILES} files"
+        )));
+    }
+    Ok(files)
+}
+
+/// Render `files` and write them into the `cwd` of the `session/new` `payload` of a `server_id`
+/// running `agent`. Returns the files written; nothing is left behind when one fails.
+pub(crate) fn write_instruction_files(
+    templates: &InstructionTemplates,
+    agent: AgentId,
+    server_id: &str,
+    payload: &Value,
+    files: &[InstructionFileRequest],
+) -> Result<Vec<PathBuf>, SandboxError> {
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let cwd = payload
+        .pointer("/params/cwd")
+        .and_then(Value::as_str)
+        .map(Path::new)
+        .filter(|cwd| cwd.is_absolute())
+        .ok_or_else(|| invalid("instructionFiles need an absolute session cwd".to_string()))?;
+
+    let mut rendered = Vec::with_capacity(files.len());
+    for file in files {
+        let template = templates.get(&file.template).map_err(|err| match err {
+            SandboxError::SessionNotFound { .. } => {
+                invalid(format!("unknown instruction template '{}'", file.template))
+            }
+            err => err,
+        })?;
+        let relative = file.path.as_deref().unwrap_or(default_path(agent));
+        let target = cwd.join(relative_path(relative)?);
+        if target.exists() || rendered.iter().any(|(path, _)| *path == target) {
+            return Err(SandboxError::Conflict {
+                message: format!(
+                    "{} already exists; instruction files never replace a file",
+                    target.display()
+                ),
+            });
+        }
+        let mut variables = BTreeMap::from([
+            ("agent".to_string(), agent.as_str().to_string()),
+            ("serverId".to_string(), server_id.to_string()),
+            ("cwd".to_string(), cwd.display().to_string()),
+        ]);
+        variables.extend(file.variables.clone());
+        let content = render(&template.content, &variables).map_err(|name| {
+            invalid(format!(
+                "template '{}' uses {{{{{name}}}}}, which has no value",
+                file.template
+            ))
+        })?;
+        rendered.push((target, content));
+    }
+
+    let mut written = Vec::with_capacity(rendered.len());
+    for (target, content) in rendered {
+        let result = match target.parent() {
+            Some(parent) => fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err)),
+            None => Ok(()),
+        }
+        .and_then(|()| fs::write(&target, content).map_err(|err| map_fs_error(&target, err)));
+        if let Err(err) = result {
+            remove_instruction_files(&written);
+            return Err(err);
+        }
+        written.push(target);
+    }
+    Ok(written)
+}
+
+/// Remove instruction files written for a session.
+pub(crate) fn remove_instruction_files(files: &[PathBuf]) {
+    for file in files {
+        match fs::remove_file(file) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => tracing::warn!(
+                path = %file.display(),
+                error = %err,
+                "instruction_files: failed to remove instruction file"
+            ),
+        }
+    }
+}
+
+/// Report the instruction files written for a created session on its `session/new` response.
+pub(crate) fn with_instruction_files(mut response: Value, files: &[PathBuf]) -> Value {
+    let Some(result) = response.get_mut("result").and_then(Value::as_object_mut) else {
+        return response;
+    };
+    let meta = result
+        .entry("_meta")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Some(meta) = meta.as_object_mut() {
+        let ours = meta
+            .entry("sandboxagent.dev")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Some(ours) = ours.as_object_mut() {
+            ours.insert(
+                "instructionFiles".to_string(),
+                files
+                    .iter()
+                    .map(|file| Value::String(file.display().to_string()))
+                    .collect(),
+            );
+        }
+    }
+    response
+}
+
+/// The instruction file `agent` reads from its working directory.
+fn default_path(agent: AgentId) -> &'static str {
+    match agent {
+        AgentId::Claude => "CLAUDE.md",
+        _ => "AGENTS.md",
+    }
+}
+
+fn relative_path(path: &str) -> Result<PathBuf, SandboxError> {
+    let relative = Path::new(path);
+    let valid = !path.is_empty()
+        && relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if valid {
+        Ok(relative.to_path_buf())
+    } else {
+        Err(invalid(format!(
+            "instruction file path '{path}' must be relative and stay in the session cwd"
+        )))
+    }
+}
+
+fn template(name: &str, content: String) -> InstructionTemplate {
+    InstructionTemplate {
+        name: name.to_string(),
+        variables: placeholders(&content).into_iter().collect(),
+        content,
+    }
+}
+
+/// Split `template` into literal text and `{{name}}` placeholders, calling `visit` for each.
+fn scan<'a>(template: &'a str, mut visit: impl FnMut(&'a str, Option<&'a str>)) {
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + length].trim();
+        let end = start + 2 + length + 2;
+        if is_variable_name(name) {
+            visit(&rest[..start], Some(name));
+        } else {
+            visit(&rest[..end], None);
+        }
+        rest = &rest[end..];
+    }
+    visit(rest, None);
+}
+
+fn placeholders(template: &str) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    scan(template, |_, name| {
+        if let Some(name) = name {
+            names.insert(name.to_string());
+        }
+    });
+    names
+}
+
+/// `template` with its placeholders replaced, or the first placeholder without a value.
+fn render(template: &str, variables: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut missing = None;
+    scan(template, |text, name| {
+        rendered.push_str(text);
+        if let Some(name) = name {
+            match variables.get(name) {
+                Some(value) => rendered.push_str(value),
+                None => {
+                    missing.get_or_insert_with(|| name.to_string());
+                }
+            }
+        }
+    });
+    match missing {
+        Some(name) => Err(name),
+        None => Ok(rendered),
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+fn validate_name(name: &str) -> Result<(), SandboxError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid(format!(
+            "invalid instruction template name '{name}'"
+        )))
+    }
+}
+
+fn not_found(name: &str) -> SandboxError {
+    SandboxError::SessionNotFound {
+        session_id: format!("instruction-template:{name}"),
+    }
+}
+
+fn invalid(message: String) -> SandboxError {
+    SandboxError::InvalidRequest { message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn placeholders_are_rendered_from_variables() {
+        let template = "Team {{ team }} owns {{cwd}}. Keep {{ not a name }} and {{team}}.";
+        assert_eq!(
+            placeholders(template).into_iter().collect::<Vec<_>>(),
+            ["cwd", "team"]
+        );
+        let variables = BTreeMap::from([
+            ("team".to_string(), "payments".to_string()),
+            ("cwd".to_string(), "/work".to_string()),
+        ]);
+        assert_eq!(
+            render(template, &variables).expect("rendered"),
+            "Team payments owns /work. Keep {{ not a name }} and payments."
+        );
+        assert_eq!(
+            render("{{team}} {{region}}", &variables),
+            Err("region".to_string())
+        );
+    }
+
+    #[test]
+    fn instruction_files_are_written_once_and_removed() {
+        let root = tempfile::tempdir().expect("tempdir");
+        let templates = InstructionTemplates {
+            dir: root.path().join("templates"),
+        };
+        templates
+            .put("guardrails", "Agent {{agent}} serves {{team}}.".to_string())
+            .expect("put");
+        let cwd = root.path().join("work");
+        fs::create_dir_all(&cwd).expect("cwd");
+        let mut payload = json!({
+            "method": "session/new",
+            "params": {
+                "cwd": cwd,
+                "_meta": { "sandboxagent.dev": { "instructionFiles": [
+                    { "template": "guardrails", "variables": { "team": "payments" } }
+                ] } },
+            },
+        });
+        let files = take_instruction_files(&mut payload).expect("valid");
+        let written = write_instruction_files(&templates, AgentId::Claude, "s", &payload, &files)
+            .expect("written");
+        assert_eq!(written, [cwd.join("CLAUDE.md")]);
+        assert_eq!(
+            fs::read_to_string(&written[0]).expect("read"),
+            "Agent claude serves payments."
+        );
+        assert!(matches!(
+            write_instruction_files(&templates, AgentId::Claude, "s", &payload, &files),
+            Err(SandboxError::Conflict { .. })
+        ));
+
+        remove_instruction_files(&written);
+        assert!(!written[0].exists());
+        assert!(relative_path("../AGENTS.md").is_err());
+        assert!(relative_path("/etc/AGENTS.md").is_err());
+    }
+}
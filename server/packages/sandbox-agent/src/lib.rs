@@ -1,10 +1,69 @@
 //! Sandbox agent core utilities.
 
+mod acp_archive;
+mod acp_fanout;
 mod acp_proxy_runtime;
+mod acp_runs;
+mod acp_usage;
+mod admission;
+mod agent_capabilities;
+mod agent_config;
+mod agent_sandbox;
+mod ai_sdk;
+mod approval_routing;
+mod approvals;
+mod audit;
+pub mod auth_tokens;
+mod blobs;
+mod capabilities;
+mod chat_completions;
+#[cfg(feature = "test-utils")]
+mod chaos;
+mod checkpoints;
+mod codex_config;
+mod compaction;
 pub mod cli;
+mod cli_output;
 pub mod daemon;
+pub mod debug_logs;
+mod egress;
+mod event_filter;
+mod event_sink;
+mod exec;
+mod findings;
+mod firehose;
+mod fs_upload;
+mod git_sync;
+mod health;
+mod instruction_files;
+mod mcp_oauth;
+mod mcp_server;
+mod mock_agent;
+mod model_catalog;
+mod plans;
+mod port_proxy;
+mod prompt_attachments;
+mod question_policy;
+mod rate_limits;
+pub mod request_limits;
+mod resource_limits;
 pub mod router;
+mod scm_github;
 pub mod server_logs;
+mod session_items;
+mod skills;
+mod structured_output;
+mod subagents;
+mod task_lists;
 pub mod telemetry;
 pub mod terminal;
+mod tls;
+mod transcript;
+mod turn_diff;
+mod turn_overrides;
+mod turn_retry;
+mod turn_timeout;
 pub mod ui;
+mod unparsed;
+mod webhooks;
+mod workspaces;
@@ -0,0 +1,785 @@
+//! OAuth tokens for remote MCP servers.
+//!
+//! A remote entry in `mcp.json` may carry an `oauth` config. `POST /v1/config/mcp/oauth`
+//! acquires a token for it with the client credentials grant, or starts a device authorization
+//! grant (RFC 8628) that is polled in the background until the user approves it. Token and
+//! device endpoints come from the config or from the authorization server metadata at
+//! `{origin}/.well-known/oauth-authorization-server` (RFC 8414).
+//!
+//! Tokens are keyed by the MCP server URL and stored in `tokens.json` under
+//! `SANDBOX_AGENT_MCP_OAUTH_DIR`, readable only by the daemon's user. When a client creates or
+//! loads an ACP session, remote `mcpServers` with a stored token get an `Authorization` header,
+//! refreshed first when the token expires within a minute. Failures are sent to the server's
+//! SSE streams as `_sandboxagent/error` notifications and the session starts without the header.
+//!
+//! Only servers configured for the session's directory get a token: the request's `cwd` must
+//! have an `mcp.json` remote entry with an `oauth` config and the same URL. A client naming a
+//! stored URL in its own `mcpServers` gets nothing.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::Url;
+use sandbox_agent_api_types::{
+    McpOAuthConfig, McpOAuthConfigOrDisabled, McpOAuthFlow, McpOAuthState, McpOAuthStatus,
+    McpServerConfig,
+};
+use sandbox_agent_error::SandboxError;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::router::{config_dir, map_fs_error, now_ms, read_named_config_map};
+
+pub const MCP_OAUTH_DIR_ENV: &str = "SANDBOX_AGENT_MCP_OAUTH_DIR";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Tokens expiring sooner than this are refreshed before they are handed to an agent.
+const REFRESH_SKEW_MS: i64 = 60_000;
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+pub fn default_mcp_oauth_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("sandbox-agent").join("mcp-oauth"))
+        .unwrap_or_else(|| PathBuf::from(".").join(".sandbox-agent").join("mcp-oauth"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredToken {
+    flow: McpOAuthFlow,
+    client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_secret: Option<String>,
+    token_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at_ms: Option<i64>,
+}
+
+impl StoredToken {
+    fn is_fresh(&self, now: i64) -> bool {
+        self.expires_at_ms
+            .is_none_or(|expires| expires - REFRESH_SKEW_MS > now)
+    }
+
+    fn is_refreshable(&self) -> bool {
+        self.refresh_token.is_some()
+            || (self.flow == McpOAuthFlow::ClientCredentials && self.client_secret.is_some())
+    }
+
+    fn update(&mut self, response: TokenResponse) {
+        self.access_token = response.access_token;
+        // Servers that don't rotate refresh tokens leave it out of the refresh response.
+        if response.refresh_token.is_some() {
+            self.refresh_token = response.refresh_token;
+        }
+        if response.scope.is_some() {
+            self.scope = response.scope;
+        }
+        self.expires_at_ms = response
+            .expires_in
+            .map(|seconds| now_ms() + seconds.saturating_mul(1000));
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Attempt {
+    Pending(DeviceAuthorization),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    expires_at_ms: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    expires_in: Option<i64>,
+    #[serde(default)]
+    scope: Option<String>,
+}
+
+#[derive(Debug)]
+enum TokenError {
+    /// The authorization server answered with an OAuth `error` code.
+    OAuth(String, Option<String>),
+    Transport(String),
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OAuth(code, Some(description)) => write!(f, "{code}: {description}"),
+            Self::OAuth(code, None) => f.write_str(code),
+            Self::Transport(message) => f.write_str(message),
+        }
+    }
+}
+
+/// A remote MCP server in a `session/new` or `session/load` whose token could not be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AuthFailure {
+    pub name: Option<String>,
+    pub url: String,
+    pub error: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct McpOAuth {
+    path: PathBuf,
+    tokens: Mutex<BTreeMap<String, StoredToken>>,
+    attempts: Mutex<HashMap<String, Attempt>>,
+}
+
+impl McpOAuth {
+    pub(crate) fn from_env() -> Self {
+        let dir = std::env::var_os(MCP_OAUTH_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(default_mcp_oauth_dir);
+        Self::new(dir.join("tokens.json"))
+    }
+
+    fn new(path: PathBuf) -> Self {
+        let tokens = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+                tracing::warn!(path = %path.display(), error = %err, "mcp_oauth: ignoring unreadable token store");
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            path,
+            tokens: Mutex::new(tokens),
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Acquire a token for the MCP server at `url`, or start a device authorization.
+    pub(crate) async fn authorize(
+        self: &Arc<Self>,
+        url: &str,
+        config: &McpOAuthConfig,
+    ) -> Result<McpOAuthStatus, SandboxError> {
+        let client_id = config
+            .client_id
+            .clone()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| SandboxError::InvalidRequest {
+                message: "oauth.clientId is required".to_string(),
+            })?;
+        let flow = config.flow.unwrap_or(if config.client_secret.is_some() {
+            McpOAuthFlow::ClientCredentials
+        } else {
+            McpOAuthFlow::DeviceCode
+        });
+        if flow == McpOAuthFlow::ClientCredentials && config.client_secret.is_none() {
+            return Err(SandboxError::InvalidRequest {
+                message: "the clientCredentials flow needs oauth.clientSecret".to_string(),
+            });
+        }
+
+        let discovered = if config.token_url.is_none()
+            || (flow == McpOAuthFlow::DeviceCode && config.device_authorization_url.is_none())
+        {
+            discover(url).await?
+        } else {
+            Value::Null
+        };
+        let endpoint = |configured: &Option<String>, field: &str| {
+            configured
+                .clone()
+                .or_else(|| {
+                    discovered
+                        .get(field)
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                })
+                .ok_or_else(|| SandboxError::InvalidRequest {
+                    message: format!("the authorization server metadata has no {field}"),
+                })
+        };
+        let token_url = endpoint(&config.token_url, "token_endpoint")?;
+        let mut token = StoredToken {
+            flow,
+            client_id,
+            client_secret: config.client_secret.clone(),
+            token_url,
+            scope: config.scope.clone(),
+            access_token: String::new(),
+            refresh_token: None,
+            expires_at_ms: None,
+        };
+
+        self.set_attempt(url, None);
+        match flow {
+            McpOAuthFlow::ClientCredentials => {
+                let response = request_token(&token, &client_credentials_form(&token))
+                    .await
+                    .map_err(|err| SandboxError::StreamError {
+                        message: format!("token request failed: {err}"),
+                    })?;
+                token.update(response);
+                self.store(url, token)?;
+            }
+            McpOAuthFlow::DeviceCode => {
+                let device_url = endpoint(
+                    &config.device_authorization_url,
+                    "device_authorization_endpoint",
+                )?;
+                let (device, interval) = start_device_authorization(&device_url, &token).await?;
+                self.set_attempt(url, Some(Attempt::Pending(device.clone())));
+                tokio::spawn(
+                    self.clone()
+                        .poll_device(url.to_string(), token, device, interval),
+                );
+            }
+        }
+        Ok(self.status(url))
+    }
+
+    pub(crate) fn status(&self, url: &str) -> McpOAuthStatus {
+        let token = self.token(url);
+        let attempt = self.attempts.lock().ok().and_then(|a| a.get(url).cloned());
+        let mut status = McpOAuthStatus {
+            url: url.to_string(),
+            state: if token.is_some() {
+                McpOAuthState::Authorized
+            } else {
+                McpOAuthState::None
+            },
+            flow: token.as_ref().map(|token| token.flow),
+            scope: token.as_ref().and_then(|token| token.scope.clone()),
+            expires_at_ms: token.as_ref().and_then(|token| token.expires_at_ms),
+            refreshable: token.as_ref().is_some_and(StoredToken::is_refreshable),
+            user_code: None,
+            verification_uri: None,
+            verification_uri_complete: None,
+            error: None,
+        };
+        match attempt {
+            Some(Attempt::Pending(device)) => {
+                status.state = McpOAuthState::Pending;
+                status.flow = Some(McpOAuthFlow::DeviceCode);
+                status.expires_at_ms = Some(device.expires_at_ms);
+                status.user_code = Some(device.user_code);
+                status.verification_uri = Some(device.verification_uri);
+                status.verification_uri_complete = device.verification_uri_complete;
+            }
+            Some(Attempt::Failed(error)) => {
+                status.state = McpOAuthState::Failed;
+                status.error = Some(error);
+            }
+            None => {}
+        }
+        status
+    }
+
+    /// Drop the stored token for `url` and stop a pending device authorization.
+    pub(crate) fn forget(&self, url: &str) -> Result<(), SandboxError> {
+        self.set_attempt(url, None);
+        let mut tokens = self.tokens.lock().map_err(poisoned)?;
+        if tokens.remove(url).is_some() {
+            self.persist(&tokens)?;
+        }
+        Ok(())
+    }
+
+    /// A current access token for `url`, refreshed when it is about to expire. `None` when no
+    /// token is stored.
+    pub(crate) async fn bearer(&self, url: &str) -> Result<Option<String>, String> {
+        let Some(mut token) = self.token(url) else {
+            return Ok(None);
+        };
+        if token.is_fresh(now_ms()) {
+            return Ok(Some(token.access_token));
+        }
+        let form = match (&token.refresh_token, token.flow) {
+            (Some(refresh_token), _) => vec![
+                ("grant_type", "refresh_token".to_string()),
+                ("refresh_token", refresh_token.clone()),
+            ],
+            (None, McpOAuthFlow::ClientCredentials) if token.client_secret.is_some() => {
+                client_credentials_form(&token)
+            }
+            _ => {
+                let error =
+                    "the token expired and cannot be refreshed; authorize again".to_string();
+                self.set_attempt(url, Some(Attempt::Failed(error.clone())));
+                return Err(error);
+            }
+        };
+        match request_token(&token, &form).await {
+            Ok(response) => {
+                token.update(response);
+                let access_token = token.access_token.clone();
+                self.store(url, token).map_err(|err| err.to_string())?;
+                Ok(Some(access_token))
+            }
+            Err(err) => {
+                let error = format!("token refresh failed: {err}");
+                self.set_attempt(url, Some(Attempt::Failed(error.clone())));
+                Err(error)
+            }
+        }
+    }
+
+    /// Add an `Authorization` header to the remote `mcpServers` of a `session/new` or
+    /// `session/load` request that have a stored token and no such header, and that the
+    /// `mcp.json` of the request's `cwd` configures for OAuth.
+    pub(crate) async fn inject(&self, payload: &mut Value) -> Vec<AuthFailure> {
+        let mut failures = Vec::new();
+        let configured = payload
+            .pointer("/params/cwd")
+            .and_then(Value::as_str)
+            .map(oauth_urls_configured_in)
+            .unwrap_or_default();
+        if configured.is_empty() {
+            return failures;
+        }
+        let Some(servers) = payload
+            .pointer_mut("/params/mcpServers")
+            .and_then(Value::as_array_mut)
+        else {
+            return failures;
+        };
+        for server in servers {
+            let Some(url) = server
+                .get("url")
+                .and_then(Value::as_str)
+                .filter(|url| configured.iter().any(|configured| configured == url))
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            let has_authorization =
+                server
+                    .get("headers")
+                    .and_then(Value::as_array)
+                    .is_some_and(|headers| {
+                        headers.iter().any(|header| {
+                            header
+                                .get("name")
+                                .and_then(Value::as_str)
+                                .is_some_and(|name| name.eq_ignore_ascii_case("authorization"))
+                        })
+                    });
+            if has_authorization {
+                continue;
+            }
+            match self.bearer(&url).await {
+                Ok(Some(token)) => {
+                    let header =
+                        json!({ "name": "Authorization", "value": format!("Bearer {token}") });
+                    match server.get_mut("headers").and_then(Value::as_array_mut) {
+                        Some(headers) => headers.push(header),
+                        None => server["headers"] = json!([header]),
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => failures.push(AuthFailure {
+                    name: server
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    url,
+                    error,
+                }),
+            }
+        }
+        failures
+    }
+
+    async fn poll_device(
+        self: Arc<Self>,
+        url: String,
+        mut token: StoredToken,
+        device: DeviceAuthorization,
+        mut interval: Duration,
+    ) {
+        let form = vec![
+            ("grant_type", DEVICE_CODE_GRANT.to_string()),
+            ("device_code", device.device_code.clone()),
+            ("client_id", token.client_id.clone()),
+        ];
+        loop {
+            tokio::time::sleep(interval).await;
+            // A newer authorization or `forget` replaced this one.
+            let current = self.attempts.lock().ok().and_then(|a| a.get(&url).cloned());
+            if !matches!(current, Some(Attempt::Pending(ref pending)) if pending.device_code == device.device_code)
+            {
+                return;
+            }
+            if now_ms() >= device.expires_at_ms {
+                self.set_attempt(
+                    &url,
+                    Some(Attempt::Failed("the device code expired".to_string())),
+                );
+                return;
+            }
+            match request_token(&token, &form).await {
+                Ok(response) => {
+                    token.update(response);
+                    self.set_attempt(&url, None);
+                    if let Err(err) = self.store(&url, token) {
+                        self.set_attempt(&url, Some(Attempt::Failed(err.to_string())));
+                    }
+                    return;
+                }
+                Err(TokenError::OAuth(code, _)) if code == "authorization_pending" => {}
+                Err(TokenError::OAuth(code, _)) if code == "slow_down" => {
+                    interval += Duration::from_secs(DEFAULT_POLL_INTERVAL_SECS);
+                }
+                Err(err) => {
+                    tracing::warn!(url = %url, error = %err, "mcp_oauth: device authorization failed");
+                    self.set_attempt(&url, Some(Attempt::Failed(err.to_string())));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn token(&self, url: &str) -> Option<StoredToken> {
+        self.tokens.lock().ok()?.get(url).cloned()
+    }
+
+    fn set_attempt(&self, url: &str, attempt: Option<Attempt>) {
+        if let Ok(mut attempts) = self.attempts.lock() {
+            match attempt {
+                Some(attempt) => attempts.insert(url.to_string(), attempt),
+                None => attempts.remove(url),
+            };
+        }
+    }
+
+    fn store(&self, url: &str, token: StoredToken) -> Result<(), SandboxError> {
+        let mut tokens = self.tokens.lock().map_err(poisoned)?;
+        tokens.insert(url.to_string(), token);
+        self.persist(&tokens)?;
+        drop(tokens);
+        self.set_attempt(url, None);
+        Ok(())
+    }
+
+    fn persist(&self, tokens: &BTreeMap<String, StoredToken>) -> Result<(), SandboxError> {
+        if let Some(parent) = self.path.parent() {
+            let mut builder = fs::DirBuilder::new();
+            builder.recursive(true);
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+            builder
+                .create(parent)
+                .map_err(|err| map_fs_error(parent, err))?;
+        }
+        let body = serde_json::to_vec_pretty(tokens).map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })?;
+        // Write beside the store and rename, so a crash never leaves a truncated file.
+        let staged = self.path.with_extension("json.tmp");
+        write_private(&staged, &body).map_err(|err| map_fs_error(&staged, err))?;
+        fs::rename(&staged, &self.path).map_err(|err| map_fs_error(&self.path, err))
+    }
+}
+
+fn client_credentials_form(token: &StoredToken) -> Vec<(&'static str, String)> {
+    let mut form = vec![("grant_type", "client_credentials".to_string())];
+    if let Some(scope) = &token.scope {
+        form.push(("scope", scope.clone()));
+    }
+    form
+}
+
+/// Authorization server metadata for the MCP server at `url`.
+async fn discover(url: &str) -> Result<Value, SandboxError> {
+    let parsed = Url::parse(url).map_err(|err| SandboxError::InvalidRequest {
+        message: format!("invalid MCP server url: {err}"),
+    })?;
+    let metadata_url = format!(
+        "{}/.well-known/oauth-authorization-server",
+        parsed.origin().ascii_serialization()
+    );
+    let failed = |message: String| SandboxError::StreamError {
+        message: format!("OAuth discovery at {metadata_url} failed: {message}"),
+    };
+    let response = http_client()
+        .map_err(failed)?
+        .get(&metadata_url)
+        .send()
+        .await
+        .map_err(|err| failed(err.to_string()))?;
+    if !response.status().is_success() {
+        return Err(failed(response.status().to_string()));
+    }
+    response
+        .json::<Value>()
+        .await
+        .map_err(|err| failed(err.to_string()))
+}
+
+async fn start_device_authorization(
+    device_url: &str,
+    token: &StoredToken,
+) -> Result<(DeviceAuthorization, Duration), SandboxError> {
+    let failed = |message: String| SandboxError::StreamError {
+        message: format!("device authorization failed: {message}"),
+    };
+    let mut form = vec![("client_id", token.client_id.clone())];
+    if let Some(scope) = &token.scope {
+        form.push(("scope", scope.clone()));
+    }
+    let response = http_client()
+        .map_err(failed)?
+        .post(device_url)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|err| failed(err.to_string()))?;
+    let status = response.status();
+    let reply = response.json::<Value>().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(failed(
+            oauth_error(&reply).map_or(status.to_string(), |err| err.to_string()),
+        ));
+    }
+    let field = |name: &str| reply.get(name).and_then(Value::as_str).map(str::to_string);
+    let (Some(device_code), Some(user_code)) = (field("device_code"), field("user_code")) else {
+        return Err(failed(
+            "the response has no device_code or user_code".to_string(),
+        ));
+    };
+    let expires_in = reply
+        .get("expires_in")
+        .and_then(Value::as_i64)
+        .unwrap_or(600);
+    let interval = reply
+        .get("interval")
+        .and_then(Value::as_u64)
+        .unwrap_or(DEFAULT_POLL_INTERVAL_SECS)
+        .max(1);
+    let device = DeviceAuthorization {
+        device_code,
+        user_code,
+        verification_uri: field("verification_uri")
+            .or_else(|| field("verification_url"))
+            .unwrap_or_default(),
+        verification_uri_complete: field("verification_uri_complete"),
+        expires_at_ms: now_ms() + expires_in * 1000,
+    };
+    Ok((device, Duration::from_secs(interval)))
+}
+
+async fn request_token(
+    token: &StoredToken,
+    form: &[(&str, String)],
+) -> Result<TokenResponse, TokenError> {
+    let mut request = http_client()
+        .map_err(TokenError::Transport)?
+        .post(&token.token_url)
+        .header(reqwest::header::ACCEPT, "application/json");
+    // The client secret goes in the form body (`client_secret_post`), which public clients
+    // without a secret skip.
+    let mut form = form.to_vec();
+    if !form.iter().any(|(key, _)| *key == "client_id") {
+        form.push(("client_id", token.client_id.clone()));
+    }
+    if let Some(secret) = &token.client_secret {
+        form.push(("client_secret", secret.clone()));
+    }
+    request = request.form(&form);
+    let response = request
+        .send()
+        .await
+        .map_err(|err| TokenError::Transport(err.to_string()))?;
+    let status = response.status();
+    let reply = response.json::<Value>().await.unwrap_or_default();
+    if let Some(err) = oauth_error(&reply) {
+        return Err(err);
+    }
+    if !status.is_success() {
+        return Err(TokenError::Transport(status.to_string()));
+    }
+    serde_json::from_value(reply)
+        .map_err(|err| TokenError::Transport(format!("invalid token response: {err}")))
+}
+
+fn oauth_error(reply: &Value) -> Option<TokenError> {
+    let code = reply.get("error").and_then(Value::as_str)?;
+    let description = reply
+        .get("error_description")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Some(TokenError::OAuth(code.to_string(), description))
+}
+
+fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, contents)
+}
+
+fn poisoned<T>(_: T) -> SandboxError {
+    SandboxError::StreamError {
+        message: "MCP OAuth token store lock poisoned".to_string(),
+    }
+}
+
+/// URLs of the remote `mcp.json` entries with an `oauth` config in `cwd`.
+fn oauth_urls_configured_in(cwd: &str) -> Vec<String> {
+    let cwd = Path::new(cwd);
+    if !cwd.is_absolute() {
+        return Vec::new();
+    }
+    let entries: BTreeMap<String, McpServerConfig> = match read_named_config_map(
+        &config_dir(cwd).join("mcp.json"),
+    ) {
+        Ok(entries) => entries,
+        Err(error) => {
+            tracing::warn!(cwd = %cwd.display(), error = %error, "mcp_oauth: ignoring unreadable mcp.json");
+            return Vec::new();
+        }
+    };
+    entries
+        .into_values()
+        .filter_map(|entry| match entry {
+            McpServerConfig::Remote {
+                url,
+                oauth: Some(McpOAuthConfigOrDisabled::Config(_)),
+                ..
+            } => Some(url),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(access_token: &str, expires_at_ms: Option<i64>) -> StoredToken {
+        StoredToken {
+            flow: McpOAuthFlow::ClientCredentials,
+            client_id: "client".to_string(),
+            client_secret: None,
+            token_url: "http://127.0.0.1:9/token".to_string(),
+            scope: None,
+            access_token: access_token.to_string(),
+            refresh_token: None,
+            expires_at_ms,
+        }
+    }
+
+    #[tokio::test]
+    async fn injects_stored_tokens_into_remote_servers() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let oauth = McpOAuth::new(dir.path().join("tokens.json"));
+        oauth
+            .store("https://docs.example/mcp", stored("fresh", None))
+            .expect("store");
+        oauth
+            .store("https://old.example/mcp", stored("stale", Some(now_ms())))
+            .expect("store");
+        oauth
+            .store("https://elsewhere.example/mcp", stored("other", None))
+            .expect("store");
+
+        // Only `elsewhere` is configured for another directory.
+        let cwd = tempfile::tempdir().expect("cwd");
+        fs::create_dir_all(config_dir(cwd.path())).expect("config dir");
+        fs::write(
+            config_dir(cwd.path()).join("mcp.json"),
+            json!({
+                "docs": {"type": "remote", "url": "https://docs.example/mcp", "oauth": {"clientId": "c"}},
+                "old": {"type": "remote", "url": "https://old.example/mcp", "oauth": {"clientId": "c"}},
+                "other": {"type": "remote", "url": "https://other.example/sse"},
+            })
+            .to_string(),
+        )
+        .expect("write mcp.json");
+        let servers = json!([
+            {"type": "http", "name": "docs", "url": "https://docs.example/mcp", "headers": []},
+            {"type": "http", "name": "mine", "url": "https://docs.example/mcp",
+             "headers": [{"name": "authorization", "value": "Bearer own"}]},
+            {"type": "http", "name": "old", "url": "https://old.example/mcp", "headers": []},
+            {"type": "sse", "name": "other", "url": "https://other.example/sse"},
+            {"type": "http", "name": "borrowed", "url": "https://elsewhere.example/mcp", "headers": []},
+            {"name": "local", "command": "docs-mcp", "args": [], "env": []},
+        ]);
+
+        let mut unconfigured = json!({"params": {
+            "cwd": dir.path().to_string_lossy(),
+            "mcpServers": servers.clone(),
+        }});
+        assert!(oauth.inject(&mut unconfigured).await.is_empty());
+        assert_eq!(unconfigured["params"]["mcpServers"], servers);
+
+        let mut payload = json!({"params": {
+            "cwd": cwd.path().to_string_lossy(),
+            "mcpServers": servers,
+        }});
+        let failures = oauth.inject(&mut payload).await;
+
+        let servers = payload["params"]["mcpServers"].as_array().unwrap();
+        assert_eq!(
+            servers[0]["headers"],
+            json!([{"name": "Authorization", "value": "Bearer fresh"}])
+        );
+        assert_eq!(servers[1]["headers"].as_array().unwrap().len(), 1);
+        assert_eq!(servers[2]["headers"], json!([]));
+        assert!(servers[3].get("headers").is_none());
+        assert_eq!(servers[4]["headers"], json!([]));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].name.as_deref(), Some("old"));
+        assert_eq!(
+            oauth.status("https://old.example/mcp").state,
+            McpOAuthState::Failed
+        );
+
+        // Tokens survive a restart.
+        let reloaded = McpOAuth::new(dir.path().join("tokens.json"));
+        assert_eq!(
+            reloaded.status("https://docs.example/mcp").state,
+            McpOAuthState::Authorized
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.path().join("tokens.json"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        reloaded.forget("https://docs.example/mcp").expect("forget");
+        assert_eq!(
+            McpOAuth::new(dir.path().join("tokens.json"))
+                .status("https://docs.example/mcp")
+                .state,
+            McpOAuthState::None
+        );
+    }
+}
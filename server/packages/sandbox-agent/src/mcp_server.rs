@@ -0,0 +1,496 @@
+//! The daemon as an MCP server, so agents can use the sandbox through MCP tools.
+//!
+//! `POST /v1/mcp` speaks MCP's streamable HTTP transport without sessions or server-initiated
+//! streams: every request gets a JSON response and notifications get `202`. `sandbox-agent mcp`
+//! bridges stdio to it for agents that only launch local MCP servers.
+//!
+//! Tools go through the same path resolution, exec limits and audit log as the HTTP API:
+//! `read_file`, `write_file`, `exec`, `git_diff` and `session_events`. Failures are returned as
+//! tool results with `isError` set, so the model sees them; only protocol errors are JSON-RPC
+//! errors.
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use sandbox_agent_api_types::{AuditKind, ExecEvent};
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+
+use crate::audit::audit_entry;
+use crate::exec::{ExecSpec, EXEC_DEFAULT_TIMEOUT, EXEC_MAX_TIMEOUT};
+use crate::router::{map_fs_error, resolve_scoped_fs_path, AppState};
+
+/// Newest first; the client's version is echoed when it is one of these.
+const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+/// File content and command output past this many bytes are cut off, to keep tool results
+/// within a model's context.
+const MAX_TOOL_OUTPUT_BYTES: usize = 256 * 1024;
+const DEFAULT_EVENT_LIMIT: usize = 100;
+const MAX_EVENT_LIMIT: usize = 1000;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Answer one JSON-RPC message from an MCP client; `None` for notifications.
+pub(crate) async fn handle(state: &AppState, actor: String, message: Value) -> Option<Value> {
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        // Responses to server requests are never expected, since the server sends none.
+        return id.map(|id| error(id, INVALID_REQUEST, "expected a JSON-RPC request"));
+    };
+    let id = id?;
+    let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+    let result = match method {
+        "initialize" => Ok(initialize(&params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools() })),
+        "tools/call" => call_tool(state, actor, &params).await,
+        _ => Err((METHOD_NOT_FOUND, format!("unknown method: {method}"))),
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => error(id, code, &message),
+    })
+}
+
+pub(crate) fn parse_error(err: serde_json::Error) -> Value {
+    error(Value::Null, PARSE_ERROR, &format!("invalid JSON: {err}"))
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = requested
+        .filter(|version| PROTOCOL_VERSIONS.contains(version))
+        .unwrap_or(PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": { "listChanged": false } },
+        "serverInfo": { "name": "sandbox-agent", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "Tools of the sandbox this agent runs in. Relative paths resolve \
+            against the first filesystem root the daemon was started with, or its home \
+            directory when there is none, and against an ACP server's working directory \
+            when serverId is set.",
+    })
+}
+
+fn tools() -> Value {
+    let server_id = json!({
+        "type": "string",
+        "description": "Resolve paths inside this ACP server's working directory"
+    });
+    json!([
+        {
+            "name": "read_file",
+            "description": "Read a text file.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string" },
+                    "serverId": server_id,
+                },
+                "required": ["path"],
+            },
+            "annotations": { "readOnlyHint": true },
+        },
+        {
+            "name": "write_file",
+            "description": "Write a text file, creating parent directories and replacing existing content.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string" },
+                    "content": { "type": "string" },
+                    "serverId": server_id,
+                },
+                "required": ["path", "content"],
+            },
+            "annotations": { "destructiveHint": true },
+        },
+        {
+            "name": "exec",
+            "description": "Run a command without a shell and return its output and exit code.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "command": { "type": "string" },
+                    "args": { "type": "array", "items": { "type": "string" } },
+                    "cwd": { "type": "string" },
+                    "env": { "type": "object", "additionalProperties": { "type": "string" } },
+                    "timeoutMs": { "type": "integer", "minimum": 1 },
+                    "serverId": server_id,
+                },
+                "required": ["command"],
+            },
+            "annotations": { "destructiveHint": true, "openWorldHint": true },
+        },
+        {
+            "name": "git_diff",
+            "description": "Show uncommitted changes of a git repository, or its changes against a ref.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Repository directory, default ." },
+                    "staged": { "type": "boolean", "description": "Only staged changes" },
+                    "ref": { "type": "string", "description": "Compare the working tree against this commit or branch" },
+                    "serverId": server_id,
+                },
+            },
+            "annotations": { "readOnlyHint": true },
+        },
+        {
+            "name": "session_events",
+            "description": "Read the buffered ACP messages of an ACP server, optionally of one session.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "serverId": { "type": "string" },
+                    "sessionId": { "type": "string" },
+                    "after": { "type": "integer", "minimum": 0, "description": "Only events with a greater sequence" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_EVENT_LIMIT },
+                },
+                "required": ["serverId"],
+            },
+            "annotations": { "readOnlyHint": true },
+        },
+    ])
+}
+
+async fn call_tool(
+    state: &AppState,
+    actor: String,
+    params: &Value,
+) -> Result<Value, (i64, String)> {
+    let name = params
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let arguments = params
+        .get("arguments")
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    let output = match name {
+        "read_file" => read_file(state, &arguments).await,
+        "write_file" => write_file(state, actor, &arguments).await,
+        "exec" => exec(state, actor, &arguments).await,
+        "git_diff" => git_diff(state, &arguments).await,
+        "session_events" => session_events(state, &arguments).await,
+        _ => return Err((INVALID_PARAMS, format!("unknown tool: {name}"))),
+    };
+    Ok(match output {
+        Ok(ToolOutput::Text(text)) => json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": false,
+        }),
+        Ok(ToolOutput::Failed(text)) => json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": true,
+        }),
+        Ok(ToolOutput::Json(value)) => json!({
+            "content": [{ "type": "text", "text": value.to_string() }],
+            "structuredContent": value,
+            "isError": false,
+        }),
+        Err(err) => json!({
+            "content": [{ "type": "text", "text": err.to_string() }],
+            "isError": true,
+        }),
+    })
+}
+
+enum ToolOutput {
+    Text(String),
+    /// The tool ran but did not succeed, such as a command exiting non-zero.
+    Failed(String),
+    Json(Value),
+}
+
+async fn read_file(state: &AppState, arguments: &Value) -> Result<ToolOutput, SandboxError> {
+    let path = string(arguments, "path")?;
+    let target = resolve_scoped_fs_path(state, optional(arguments, "serverId"), path).await?;
+    let bytes = fs::read(&target).map_err(|err| map_fs_error(&target, err))?;
+    if bytes.contains(&0) {
+        return Ok(ToolOutput::Failed(format!(
+            "{} is a binary file ({} bytes)",
+            target.display(),
+            bytes.len()
+        )));
+    }
+    Ok(ToolOutput::Text(truncated(&bytes)))
+}
+
+async fn write_file(
+    state: &AppState,
+    actor: String,
+    arguments: &Value,
+) -> Result<ToolOutput, SandboxError> {
+    let path = string(arguments, "path")?;
+    let content = string(arguments, "content")?;
+    let server_id = optional(arguments, "serverId");
+    let target = resolve_scoped_fs_path(state, server_id, path).await?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err))?;
+    }
+    fs::write(&target, content).map_err(|err| map_fs_error(&target, err))?;
+    let mut entry = audit_entry(AuditKind::FsWrite, actor);
+    entry.server_id = server_id.map(str::to_string);
+    entry.path = Some(target.to_string_lossy().to_string());
+    entry.details = json!({ "bytesWritten": content.len(), "mcpTool": "write_file" });
+    state.audit().record(entry);
+    Ok(ToolOutput::Text(format!(
+        "wrote {} bytes to {}",
+        content.len(),
+        target.display()
+    )))
+}
+
+async fn exec(
+    state: &AppState,
+    actor: String,
+    arguments: &Value,
+) -> Result<ToolOutput, SandboxError> {
+    let command = string(arguments, "command")?;
+    let args = match arguments.get("args") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(args) => serde_json::from_value::<Vec<String>>(args.clone()).map_err(|_| {
+            SandboxError::InvalidRequest {
+                message: "args must be an array of strings".to_string(),
+            }
+        })?,
+    };
+    let env = match arguments.get("env") {
+        None | Some(Value::Null) => HashMap::new(),
+        Some(env) => {
+            serde_json::from_value::<HashMap<String, String>>(env.clone()).map_err(|_| {
+                SandboxError::InvalidRequest {
+                    message: "env must be an object of strings".to_string(),
+                }
+            })?
+        }
+    };
+    let timeout = match arguments.get("timeoutMs").and_then(Value::as_u64) {
+        Some(0) => {
+            return Err(SandboxError::InvalidRequest {
+                message: "timeoutMs must be greater than 0".to_string(),
+            })
+        }
+        Some(ms) => Duration::from_millis(ms).min(EXEC_MAX_TIMEOUT),
+        None => EXEC_DEFAULT_TIMEOUT,
+    };
+    let server_id = optional(arguments, "serverId");
+    let cwd = optional(arguments, "cwd").unwrap_or(".");
+    let cwd = resolve_scoped_fs_path(state, server_id, cwd).await?;
+
+    let mut entry = audit_entry(AuditKind::Exec, actor);
+    entry.server_id = server_id.map(str::to_string);
+    entry.action = Some(
+        std::iter::once(command)
+            .chain(args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    entry.path = Some(cwd.to_string_lossy().to_string());
+    let env_names = env.keys().cloned().collect::<Vec<_>>();
+    let spec = ExecSpec {
+        command: command.to_string(),
+        args: args.clone(),
+        cwd,
+        env,
+        timeout,
+        max_output_bytes: MAX_TOOL_OUTPUT_BYTES as u64,
+        max_memory_bytes: None,
+        max_cpu_seconds: None,
+    };
+    let (output, success) = run(state, spec, |exec_id| {
+        entry.details =
+            json!({ "execId": exec_id, "args": args, "env": env_names, "mcpTool": "exec" });
+        state.audit().record(entry);
+    })
+    .await?;
+    Ok(if success {
+        ToolOutput::Text(output)
+    } else {
+        ToolOutput::Failed(output)
+    })
+}
+
+async fn git_diff(state: &AppState, arguments: &Value) -> Result<ToolOutput, SandboxError> {
+    let path = optional(arguments, "path").unwrap_or(".");
+    let cwd = resolve_scoped_fs_path(state, optional(arguments, "serverId"), path).await?;
+    let mut args = vec!["diff".to_string(), "--no-color".to_string()];
+    if arguments.get("staged").and_then(Value::as_bool) == Some(true) {
+        args.push("--cached".to_string());
+    }
+    if let Some(git_ref) = optional(arguments, "ref") {
+        if git_ref.is_empty() || git_ref.starts_with('-') {
+            return Err(SandboxError::InvalidRequest {
+                message: "ref must not be empty or start with '-'".to_string(),
+            });
+        }
+        args.push(git_ref.to_string());
+    }
+    args.push("--".to_string());
+    let spec = ExecSpec {
+        command: "git".to_string(),
+        args,
+        cwd,
+        env: HashMap::new(),
+        timeout: Duration::from_secs(60),
+        max_output_bytes: MAX_TOOL_OUTPUT_BYTES as u64,
+        max_memory_bytes: None,
+        max_cpu_seconds: None,
+    };
+    let (output, success) = run(state, spec, |_| {}).await?;
+    Ok(match (success, output.is_empty()) {
+        (true, true) => ToolOutput::Text("no changes".to_string()),
+        (true, false) => ToolOutput::Text(output),
+        (false, _) => ToolOutput::Failed(output),
+    })
+}
+
+async fn session_events(state: &AppState, arguments: &Value) -> Result<ToolOutput, SandboxError> {
+    let server_id = string(arguments, "serverId")?;
+    let session_id = optional(arguments, "sessionId");
+    let after = arguments.get("after").and_then(Value::as_u64);
+    let limit = arguments
+        .get("limit")
+        .and_then(Value::as_u64)
+        .map_or(DEFAULT_EVENT_LIMIT, |limit| limit as usize)
+        .clamp(1, MAX_EVENT_LIMIT);
+    let events = state
+        .acp_proxy()
+        .buffered_events(server_id, after)
+        .await?
+        .into_iter()
+        .filter(|(_, message)| {
+            session_id.is_none()
+                || message.pointer("/params/sessionId").and_then(Value::as_str) == session_id
+        })
+        .collect::<Vec<_>>();
+    // The latest events are the useful ones; earlier ones can be paged with `after`.
+    let skipped = events.len().saturating_sub(limit);
+    let events = events
+        .into_iter()
+        .skip(skipped)
+        .map(|(sequence, message)| json!({ "sequence": sequence, "message": message }))
+        .collect::<Vec<_>>();
+    Ok(ToolOutput::Json(json!({
+        "serverId": server_id,
+        "events": events,
+        "omitted": skipped,
+    })))
+}
+
+/// Run `spec` to completion; returns stdout and stderr interleaved as produced, with the exit
+/// status appended when the command failed.
+async fn run(
+    state: &AppState,
+    spec: ExecSpec,
+    started: impl FnOnce(&str),
+) -> Result<(String, bool), SandboxError> {
+    let mut run = state.exec().start(spec)?;
+    started(&run.id);
+    let mut output = String::new();
+    while let Some(event) = run.events.recv().await {
+        match event {
+            ExecEvent::Stdout { data } | ExecEvent::Stderr { data } => output.push_str(&data),
+            ExecEvent::Exit {
+                exit_code,
+                signal,
+                timed_out,
+                output_truncated,
+                ..
+            } => {
+                if output_truncated {
+                    output.push_str("\n[output truncated]");
+                }
+                let success = exit_code == Some(0);
+                if timed_out {
+                    output.push_str("\n[timed out]");
+                } else if let Some(signal) = signal.filter(|_| !success) {
+                    output.push_str(&format!("\n[killed by signal {signal}]"));
+                } else if !success {
+                    output.push_str(&format!("\n[exit code {}]", exit_code.unwrap_or(-1)));
+                }
+                return Ok((output, success));
+            }
+            ExecEvent::Start { .. } => {}
+        }
+    }
+    Err(SandboxError::StreamError {
+        message: "command output ended without an exit status".to_string(),
+    })
+}
+
+fn truncated(bytes: &[u8]) -> String {
+    if bytes.len() <= MAX_TOOL_OUTPUT_BYTES {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    format!(
+        "{}\n[truncated: showing {MAX_TOOL_OUTPUT_BYTES} of {} bytes]",
+        String::from_utf8_lossy(&bytes[..MAX_TOOL_OUTPUT_BYTES]),
+        bytes.len()
+    )
+}
+
+fn string<'a>(arguments: &'a Value, field: &str) -> Result<&'a str, SandboxError> {
+    arguments
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| SandboxError::InvalidRequest {
+            message: format!("{field} is required"),
+        })
+}
+
+fn optional<'a>(arguments: &'a Value, field: &str) -> Option<&'a str> {
+    arguments.get(field).and_then(Value::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_protocol_version() {
+        assert_eq!(
+            initialize(&json!({"protocolVersion": "2025-03-26"}))["protocolVersion"],
+            "2025-03-26"
+        );
+        assert_eq!(
+            initialize(&json!({"protocolVersion": "1999-01-01"}))["protocolVersion"],
+            PROTOCOL_VERSIONS[0]
+        );
+        let names = tools()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "read_file",
+                "write_file",
+                "exec",
+                "git_diff",
+                "session_events"
+            ]
+        );
+    }
+
+    #[test]
+    fn truncates_large_output() {
+        assert_eq!(truncated(b"short"), "short");
+        let long = vec![b'a'; MAX_TOOL_OUTPUT_BYTES + 10];
+        assert!(truncated(&long).ends_with(&format!(
+            "[truncated: showing {MAX_TOOL_OUTPUT_BYTES} of {} bytes]",
+            MAX_TOOL_OUTPUT_BYTES + 10
+        )));
+    }
+}
@@ -0,0 +1,615 @@
+//! Scriptable ACP agent behind `agent=mock`, run as the hidden `mock-agent` subcommand.
+//!
+//! Without a script, a prompt is echoed back as one message chunk and the turn ends with
+//! `end_turn`. A test can declare what a turn does instead with a `mockScript` list under
+//! `_meta["sandboxagent.dev"]`, on `session/prompt` for that turn or on `session/new` for
+//! every turn of the session:
+//!
+//! ```json
+//! {"type": "message", "text": "Working on it"}
+//! {"type": "thought", "text": "Considering options"}
+//! {"type": "toolCall", "toolCallId": "t1", "title": "Run tests", "kind": "execute"}
+//! {"type": "toolCallUpdate", "toolCallId": "t1", "status": "completed", "text": "ok"}
+//! {"type": "update", "update": {"sessionUpdate": "plan", "entries": []}}
+//! {"type": "permission", "title": "Run tests"}
+//! {"type": "permission", "title": "Ready to code?", "plan": "1. Fix the bug"}
+//! {"type": "question", "question": "Which branch?", "options": ["main", "dev"]}
+//! {"type": "delay", "ms": 250}
+//! {"type": "delay", "ms": 10000, "ignoreCancel": true}
+//! {"type": "raw", "line": "not json"}
+//! {"type": "error", "code": -32000, "message": "model overloaded"}
+//! {"type": "exit", "code": 1}
+//! {"type": "stop", "stopReason": "max_tokens"}
+//! ```
+//!
+//! `permission` and `question` send a request to the client and wait for its response; a
+//! `permission` with a `plan` asks to leave plan mode like Claude's ExitPlanMode tool.
+//! `error` fails the prompt, `exit` ends the process, and `stop` ends the turn; a script that
+//! runs out ends it with `end_turn`. `session/cancel` interrupts a waiting step and ends the
+//! turn with `cancelled`, except a `delay` with `ignoreCancel`, which plays a hung agent.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+const META_KEY: &str = "sandboxagent.dev";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", deny_unknown_fields)]
+pub enum MockStep {
+    Message {
+        text: String,
+    },
+    Thought {
+        text: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ToolCall {
+        #[serde(default)]
+        tool_call_id: Option<String>,
+        title: String,
+        #[serde(default)]
+        kind: Option<String>,
+        #[serde(default)]
+        status: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    ToolCallUpdate {
+        tool_call_id: String,
+        #[serde(default)]
+        status: Option<String>,
+        #[serde(default)]
+        text: Option<String>,
+    },
+    Update {
+        update: Value,
+    },
+    #[serde(rename_all = "camelCase")]
+    Permission {
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default)]
+        tool_call_id: Option<String>,
+        #[serde(default)]
+        plan: Option<String>,
+    },
+    Question {
+        question: String,
+        #[serde(default)]
+        options: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Delay {
+        ms: u64,
+        #[serde(default)]
+        ignore_cancel: bool,
+    },
+    Raw {
+        line: String,
+    },
+    Error {
+        #[serde(default = "default_error_code")]
+        code: i64,
+        message: String,
+    },
+    Exit {
+        #[serde(default)]
+        code: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    Stop {
+        stop_reason: String,
+    },
+}
+
+fn default_error_code() -> i64 {
+    -32000
+}
+
+/// Run the mock agent on stdin and stdout until stdin closes.
+pub fn run() -> std::io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match serde_json::from_str::<Value>(&line) {
+                Ok(message) => {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => eprintln!("mock-agent: ignoring invalid JSON line: {err}"),
+            }
+        }
+    });
+    MockAgent::new(receiver, std::io::stdout().lock()).serve()
+}
+
+struct MockAgent<W> {
+    incoming: Receiver<Value>,
+    out: W,
+    /// Messages that arrived while a turn was waiting, handled once it ends.
+    queued: VecDeque<Value>,
+    /// Default script of each session, from `session/new`.
+    sessions: HashMap<String, Option<Vec<MockStep>>>,
+    next_session: u64,
+    next_request: u64,
+}
+
+enum TurnEnd {
+    Stop(String),
+    Error { code: i64, message: String },
+}
+
+enum Wait {
+    Response,
+    Elapsed,
+    Cancelled,
+    Closed,
+}
+
+impl<W: Write> MockAgent<W> {
+    fn new(incoming: Receiver<Value>, out: W) -> Self {
+        Self {
+            incoming,
+            out,
+            queued: VecDeque::new(),
+            sessions: HashMap::new(),
+            next_session: 1,
+            next_request: 1,
+        }
+    }
+
+    fn serve(mut self) -> std::io::Result<()> {
+        loop {
+            let message = match self.queued.pop_front() {
+                Some(message) => message,
+                None => match self.incoming.recv() {
+                    Ok(message) => message,
+                    Err(_) => return Ok(()),
+                },
+            };
+            self.handle(message)?;
+        }
+    }
+
+    fn handle(&mut self, message: Value) -> std::io::Result<()> {
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // A response to a request the turn stopped waiting for.
+            return Ok(());
+        };
+        let Some(id) = message.get("id").cloned() else {
+            return Ok(());
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        match method {
+            "initialize" => self.respond(
+                &id,
+                json!({
+                    "protocolVersion": 1,
+                    "agentCapabilities": {
+                        "loadSession": false,
+                        "promptCapabilities": { "image": false, "audio": false, "embeddedContext": false },
+                    },
+                    "authMethods": [],
+                    "agentInfo": { "name": "mock", "version": env!("CARGO_PKG_VERSION") },
+                }),
+            ),
+            "session/new" => {
+                let script = match script_from(&params) {
+                    Ok(script) => script,
+                    Err(message) => return self.respond_error(&id, -32602, &message),
+                };
+                let session_id = format!("mock-session-{}", self.next_session);
+                self.next_session += 1;
+                self.sessions.insert(session_id.clone(), script);
+                self.respond(&id, json!({ "sessionId": session_id }))
+            }
+            "session/prompt" => self.prompt(&id, &params),
+            "authenticate" | "session/set_mode" | "session/set_model"
+            | "session/set_config_option" => self.respond(&id, json!({})),
+            _ => self.respond_error(&id, -32601, &format!("method not found: {method}")),
+        }
+    }
+
+    fn prompt(&mut self, id: &Value, params: &Value) -> std::io::Result<()> {
+        let Some(session_id) = params.get("sessionId").and_then(Value::as_str) else {
+            return self.respond_error(id, -32602, "sessionId is required");
+        };
+        let Some(session_script) = self.sessions.get(session_id).cloned() else {
+            return self.respond_error(id, -32602, &format!("unknown session: {session_id}"));
+        };
+        let script = match script_from(params) {
+            Ok(script) => script.or(session_script),
+            Err(message) => return self.respond_error(id, -32602, &message),
+        };
+        let steps = script.unwrap_or_else(|| {
+            let text = prompt_text(params);
+            vec![MockStep::Message {
+                text: if text.is_empty() {
+                    "OK".to_string()
+                } else {
+                    text
+                },
+            }]
+        });
+        match self.play(session_id, &steps)? {
+            TurnEnd::Stop(stop_reason) => self.respond(id, json!({ "stopReason": stop_reason })),
+            TurnEnd::Error { code, message } => self.respond_error(id, code, &message),
+        }
+    }
+
+    fn play(&mut self, session_id: &str, steps: &[MockStep]) -> std::io::Result<TurnEnd> {
+        let cancelled = || Ok(TurnEnd::Stop("cancelled".to_string()));
+        for step in steps {
+            match step {
+                MockStep::Message { text } => self.update(
+                    session_id,
+                    json!({ "sessionUpdate": "agent_message_chunk", "content": { "type": "text", "text": text } }),
+                )?,
+                MockStep::Thought { text } => self.update(
+                    session_id,
+                    json!({ "sessionUpdate": "agent_thought_chunk", "content": { "type": "text", "text": text } }),
+                )?,
+                MockStep::ToolCall {
+                    tool_call_id,
+                    title,
+                    kind,
+                    status,
+                } => {
+                    let tool_call_id = tool_call_id
+                        .clone()
+                        .unwrap_or_else(|| format!("mock-tool-{}", self.next_request));
+                    self.update(
+                        session_id,
+                        json!({
+                            "sessionUpdate": "tool_call",
+                            "toolCallId": tool_call_id,
+                            "title": title,
+                            "kind": kind.as_deref().unwrap_or("other"),
+                            "status": status.as_deref().unwrap_or("pending"),
+                        }),
+                    )?
+                }
+                MockStep::ToolCallUpdate {
+                    tool_call_id,
+                    status,
+                    text,
+                } => {
+                    let mut update = json!({
+                        "sessionUpdate": "tool_call_update",
+                        "toolCallId": tool_call_id,
+                    });
+                    if let Some(status) = status {
+                        update["status"] = json!(status);
+                    }
+                    if let Some(text) = text {
+                        update["content"] = json!([
+                            { "type": "content", "content": { "type": "text", "text": text } }
+                        ]);
+                    }
+                    self.update(session_id, update)?
+                }
+                MockStep::Update { update } => self.update(session_id, update.clone())?,
+                MockStep::Permission {
+                    title,
+                    tool_call_id,
+                    plan,
+                } => {
+                    let mut params = json!({
+                        "sessionId": session_id,
+                        "toolCall": {
+                            "toolCallId": tool_call_id.clone().unwrap_or_else(|| format!("mock-tool-{}", self.next_request)),
+                            "title": title.as_deref().unwrap_or("Mock action"),
+                        },
+                        "options": [
+                            { "optionId": "allow-once", "name": "Allow once", "kind": "allow_once" },
+                            { "optionId": "allow-always", "name": "Always allow", "kind": "allow_always" },
+                            { "optionId": "reject-once", "name": "Reject", "kind": "reject_once" },
+                        ],
+                    });
+                    if let Some(plan) = plan {
+                        params["toolCall"]["rawInput"] = json!({ "plan": plan });
+                    }
+                    match self.request(session_id, "session/request_permission", params)? {
+                        Wait::Response => {}
+                        Wait::Cancelled | Wait::Elapsed | Wait::Closed => return cancelled(),
+                    }
+                }
+                MockStep::Question { question, options } => {
+                    let params = json!({
+                        "sessionId": session_id,
+                        "questions": [{
+                            "question": question,
+                            "header": "Question",
+                            "options": options.iter().map(|label| json!({ "label": label })).collect::<Vec<_>>(),
+                            "multiple": false,
+                            "custom": options.is_empty(),
+                        }],
+                    });
+                    match self.request(session_id, "_sandboxagent/session/request_question", params)? {
+                        Wait::Response => {}
+                        Wait::Cancelled | Wait::Elapsed | Wait::Closed => return cancelled(),
+                    }
+                }
+                MockStep::Delay { ms, ignore_cancel } => {
+                    let deadline = Instant::now() + Duration::from_millis(*ms);
+                    loop {
+                        match self.wait(session_id, None, Some(deadline)) {
+                            Wait::Elapsed | Wait::Response => break,
+                            Wait::Cancelled if *ignore_cancel => {}
+                            Wait::Cancelled | Wait::Closed => return cancelled(),
+                        }
+                    }
+                }
+                MockStep::Raw { line } => self.write_line(line)?,
+                MockStep::Error { code, message } => {
+                    return Ok(TurnEnd::Error {
+                        code: *code,
+                        message: message.clone(),
+                    })
+                }
+                MockStep::Exit { code } => {
+                    self.out.flush()?;
+                    std::process::exit(*code);
+                }
+                MockStep::Stop { stop_reason } => return Ok(TurnEnd::Stop(stop_reason.clone())),
+            }
+        }
+        Ok(TurnEnd::Stop("end_turn".to_string()))
+    }
+
+    /// Send an agent-to-client request and wait for its response.
+    fn request(&mut self, session_id: &str, method: &str, params: Value) -> std::io::Result<Wait> {
+        let id = json!(format!("mock-request-{}", self.next_request));
+        self.next_request += 1;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))?;
+        Ok(self.wait(session_id, Some(&id), None))
+    }
+
+    /// Wait for the response to `request_id`, `deadline`, or a cancel of `session_id`.
+    /// Anything else that arrives meanwhile is queued.
+    fn wait(
+        &mut self,
+        session_id: &str,
+        request_id: Option<&Value>,
+        deadline: Option<Instant>,
+    ) -> Wait {
+        loop {
+            let message = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match self.incoming.recv_timeout(timeout) {
+                        Ok(message) => message,
+                        Err(RecvTimeoutError::Timeout) => return Wait::Elapsed,
+                        Err(RecvTimeoutError::Disconnected) => return Wait::Closed,
+                    }
+                }
+                None => match self.incoming.recv() {
+                    Ok(message) => message,
+                    Err(_) => return Wait::Closed,
+                },
+            };
+            let method = message.get("method").and_then(Value::as_str);
+            if method.is_none() && request_id.is_some() && message.get("id") == request_id {
+                return Wait::Response;
+            }
+            if method == Some("session/cancel")
+                && message.pointer("/params/sessionId").and_then(Value::as_str) == Some(session_id)
+            {
+                return Wait::Cancelled;
+            }
+            self.queued.push_back(message);
+        }
+    }
+
+    fn update(&mut self, session_id: &str, update: Value) -> std::io::Result<()> {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": session_id, "update": update },
+        }))
+    }
+
+    fn respond(&mut self, id: &Value, result: Value) -> std::io::Result<()> {
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    fn respond_error(&mut self, id: &Value, code: i64, message: &str) -> std::io::Result<()> {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }))
+    }
+
+    fn send(&mut self, message: &Value) -> std::io::Result<()> {
+        self.write_line(&message.to_string())
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        writeln!(self.out, "{line}")?;
+        self.out.flush()
+    }
+}
+
+/// The `mockScript` of a request's `_meta`, if it has one.
+fn script_from(params: &Value) -> Result<Option<Vec<MockStep>>, String> {
+    let Some(script) = params.pointer(&format!("/_meta/{META_KEY}/mockScript")) else {
+        return Ok(None);
+    };
+    serde_json::from_value(script.clone())
+        .map(Some)
+        .map_err(|err| format!("invalid mockScript: {err}"))
+}
+
+fn prompt_text(params: &Value) -> String {
+    params
+        .get("prompt")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|block| block.get("text").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_agent(messages: Vec<Value>) -> Vec<Value> {
+        let (sender, receiver) = mpsc::channel();
+        for message in messages {
+            sender.send(message).unwrap();
+        }
+        drop(sender);
+        let mut out = Vec::new();
+        MockAgent::new(receiver, &mut out).serve().unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap_or_else(|_| json!({ "raw": line })))
+            .collect()
+    }
+
+    fn new_session(script: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0", "id": 1, "method": "session/new",
+            "params": { "cwd": "/", "mcpServers": [], "_meta": { "sandboxagent.dev": { "mockScript": script } } },
+        })
+    }
+
+    fn prompt(id: u64, text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0", "id": id, "method": "session/prompt",
+            "params": { "sessionId": "mock-session-1", "prompt": [{ "type": "text", "text": text }] },
+        })
+    }
+
+    #[test]
+    fn prompt_without_script_echoes_text() {
+        let out = run_agent(vec![
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "session/new", "params": { "cwd": "/", "mcpServers": [] } }),
+            prompt(2, "hello"),
+        ]);
+        assert_eq!(out[0]["result"]["sessionId"], "mock-session-1");
+        assert_eq!(out[1]["params"]["update"]["content"]["text"], "hello");
+        assert_eq!(
+            out[2],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": { "stopReason": "end_turn" } })
+        );
+    }
+
+    #[test]
+    fn script_waits_for_permission_and_emits_raw_lines() {
+        let out = run_agent(vec![
+            new_session(json!([
+                { "type": "permission", "title": "Run tests" },
+                { "type": "raw", "line": "not json" },
+                { "type": "message", "text": "done" },
+                { "type": "stop", "stopReason": "max_tokens" },
+            ])),
+            prompt(2, "go"),
+            json!({ "jsonrpc": "2.0", "id": "mock-request-1", "result": { "outcome": { "outcome": "selected", "optionId": "allow-once" } } }),
+        ]);
+        assert_eq!(out[1]["method"], "session/request_permission");
+        assert_eq!(out[1]["params"]["toolCall"]["title"], "Run tests");
+        assert_eq!(out[2], json!({ "raw": "not json" }));
+        assert_eq!(out[3]["params"]["update"]["content"]["text"], "done");
+        assert_eq!(out[4]["result"]["stopReason"], "max_tokens");
+    }
+
+    #[test]
+    fn cancel_interrupts_a_delay_and_error_fails_the_prompt() {
+        let out = run_agent(vec![
+            new_session(json!([{ "type": "delay", "ms": 60000 }])),
+            prompt(2, "slow"),
+            json!({ "jsonrpc": "2.0", "method": "session/cancel", "params": { "sessionId": "mock-session-1" } }),
+            json!({
+                "jsonrpc": "2.0", "id": 3, "method": "session/prompt",
+                "params": {
+                    "sessionId": "mock-session-1",
+                    "prompt": [],
+                    "_meta": { "sandboxagent.dev": { "mockScript": [{ "type": "error", "message": "boom" }] } },
+                },
+            }),
+        ]);
+        assert_eq!(out[1]["result"]["stopReason"], "cancelled");
+        assert_eq!(
+            out[2]["error"],
+            json!({ "code": -32000, "message": "boom" })
+        );
+    }
+
+    #[test]
+    fn tool_calls_and_questions_follow_the_script_and_queue_other_requests() {
+        let out = run_agent(vec![
+            new_session(json!([
+                { "type": "thought", "text": "thinking" },
+                { "type": "toolCall", "toolCallId": "t-1", "title": "rm -rf target", "kind": "delete" },
+                { "type": "question", "question": "Proceed?", "options": ["yes", "no"] },
+                { "type": "toolCallUpdate", "toolCallId": "t-1", "status": "completed", "text": "removed" },
+                { "type": "update", "update": { "sessionUpdate": "plan", "entries": [] } },
+            ])),
+            prompt(2, "clean up"),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "session/new", "params": { "cwd": "/", "mcpServers": [] } }),
+            json!({ "jsonrpc": "2.0", "id": "mock-request-1", "result": { "outcome": { "outcome": "selected" } } }),
+        ]);
+        assert_eq!(
+            out[1]["params"]["update"]["sessionUpdate"],
+            "agent_thought_chunk"
+        );
+        assert_eq!(
+            out[2]["params"]["update"],
+            json!({
+                "sessionUpdate": "tool_call",
+                "toolCallId": "t-1",
+                "title": "rm -rf target",
+                "kind": "delete",
+                "status": "pending",
+            })
+        );
+        assert_eq!(out[3]["method"], "_sandboxagent/session/request_question");
+        assert_eq!(
+            out[3]["params"]["questions"][0]["options"],
+            json!([{ "label": "yes" }, { "label": "no" }])
+        );
+        assert_eq!(out[4]["params"]["update"]["status"], "completed");
+        assert_eq!(
+            out[4]["params"]["update"]["content"][0]["content"]["text"],
+            "removed"
+        );
+        assert_eq!(out[5]["params"]["update"]["sessionUpdate"], "plan");
+        assert_eq!(out[6]["result"]["stopReason"], "end_turn");
+        // The session/new that arrived mid-turn is answered once the turn ends.
+        assert_eq!(out[7]["id"], 3);
+        assert_eq!(out[7]["result"]["sessionId"], "mock-session-2");
+    }
+
+    #[test]
+    fn unknown_methods_and_sessions_are_errors() {
+        let out = run_agent(vec![
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "session/load", "params": {} }),
+            prompt(2, "hello"),
+        ]);
+        assert_eq!(out[0]["error"]["code"], -32601);
+        assert_eq!(out[1]["error"]["code"], -32602);
+        assert_eq!(
+            out[1]["error"]["message"],
+            "unknown session: mock-session-1"
+        );
+    }
+
+    #[test]
+    fn invalid_script_is_rejected() {
+        let out = run_agent(vec![new_session(json!([{ "type": "dance" }]))]);
+        assert_eq!(out[0]["error"]["code"], -32602);
+    }
+}
@@ -0,0 +1,471 @@
+//! Live model lists for the `model` config option of `GET /v1/agents?config=true` and for
+//! `GET /v1/models`.
+//!
+//! The lists compiled in from `scripts/agent-configs/resources/` go stale as providers ship
+//! new models. Where an agent's backend lists its models cheaply, the catalog fetches them:
+//!
+//! - Claude: Anthropic's `GET /v1/models`, with the Anthropic credentials the daemon finds.
+//!   Claude's aliases (`default`, `opus`, ...) stay first in the list.
+//! - OpenCode: the managed OpenCode server's `GET /config/providers`, once that server runs.
+//!
+//! A background task fetches a list again once it is older than
+//! `SANDBOX_AGENT_MODEL_CATALOG_TTL_SECS` (3600 by default, `0` turns the task off), and
+//! `POST /v1/agents/{agent}/models/refresh` fetches one on demand. A failed fetch keeps the
+//! last list; agents without a fetched list keep the compiled-in one.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use sandbox_agent_agent_credentials::{
+    extract_all_credentials, AuthType, CredentialExtractionOptions,
+};
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_api_types::{AgentModel, AgentModelsResponse, ModelCatalogSource};
+use sandbox_agent_error::SandboxError;
+use sandbox_agent_opencode_server_manager::OpenCodeServerManager;
+use serde_json::{json, Value};
+
+use crate::router::now_ms;
+
+const TTL_ENV: &str = "SANDBOX_AGENT_MODEL_CATALOG_TTL_SECS";
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+/// How often the background task looks for lists to fetch again.
+const REFRESH_TICK: Duration = Duration::from_secs(60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+const ANTHROPIC_MODELS_URL: &str = "https://api.anthropic.com/v1/models?limit=1000";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const CLAUDE_ALIASES: [(&str, &str); 4] = [
+    ("default", "Default (recommended)"),
+    ("opus", "Opus"),
+    ("sonnet", "Sonnet"),
+    ("haiku", "Haiku"),
+];
+
+/// Default model, if the source names one, and the models.
+type ModelList = (Option<String>, Vec<AgentModel>);
+
+#[derive(Debug, Clone)]
+struct Entry {
+    default_model: Option<String>,
+    models: Vec<AgentModel>,
+    fetched_at_ms: i64,
+}
+
+#[derive(Debug)]
+pub(crate) struct ModelCatalog {
+    /// `None` when background refresh is off.
+    ttl: Option<Duration>,
+    entries: Mutex<HashMap<AgentId, Entry>>,
+    /// Last background fetch per agent, successful or not.
+    attempts: Mutex<HashMap<AgentId, Instant>>,
+    refresh_started: AtomicBool,
+    client: reqwest::Client,
+}
+
+impl ModelCatalog {
+    pub(crate) fn from_env() -> Self {
+        let ttl = match std::env::var(TTL_ENV)
+            .ok()
+            .and_then(|raw| raw.trim().parse::<u64>().ok())
+        {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(DEFAULT_TTL),
+        };
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            attempts: Mutex::new(HashMap::new()),
+            refresh_started: AtomicBool::new(false),
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Agents whose models can be listed at runtime.
+    pub(crate) fn is_live(agent: AgentId) -> bool {
+        matches!(agent, AgentId::Claude | AgentId::Opencode)
+    }
+
+    /// Swap the fetched list of `agent`, if there is one, into its builtin config options.
+    pub(crate) fn apply(&self, agent: AgentId, options: &mut [Value]) {
+        let Some(entry) = self.entry(agent) else {
+            return;
+        };
+        let Some(option) = options
+            .iter_mut()
+            .find(|option| option.get("id").and_then(Value::as_str) == Some("model"))
+        else {
+            return;
+        };
+        option["options"] = entry
+            .models
+            .iter()
+            .map(|model| json!({ "value": model.id, "name": model.name }))
+            .collect();
+        if let Some(default_model) = entry.default_model {
+            option["currentValue"] = Value::String(default_model);
+        }
+    }
+
+    /// The models `agent` offers: the fetched list, else the one in its builtin `options`.
+    pub(crate) fn models(&self, agent: AgentId, options: &[Value]) -> AgentModelsResponse {
+        if let Some(entry) = self.entry(agent) {
+            return AgentModelsResponse {
+                agent: agent.as_str().to_string(),
+                source: ModelCatalogSource::Live,
+                fetched_at_ms: Some(entry.fetched_at_ms),
+                default_model: entry.default_model,
+                models: entry.models,
+            };
+        }
+        let option = options
+            .iter()
+            .find(|option| option.get("id").and_then(Value::as_str) == Some("model"));
+        AgentModelsResponse {
+            agent: agent.as_str().to_string(),
+            source: ModelCatalogSource::Builtin,
+            fetched_at_ms: None,
+            default_model: option
+                .and_then(|option| option.get("currentValue"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            models: option
+                .and_then(|option| option.get("options"))
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| {
+                    let id = entry.get("value")?.as_str()?.to_string();
+                    let name = entry.get("name").and_then(Value::as_str).unwrap_or(&id);
+                    let reasoning_variants = entry
+                        .pointer("/_meta/sandboxagent.dev/reasoningVariants")
+                        .and_then(|variants| serde_json::from_value(variants.clone()).ok());
+                    Some(AgentModel {
+                        name: name.to_string(),
+                        id,
+                        reasoning_variants,
+                        ..AgentModel::default()
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Fetch the list of `agent` now. `Ok(false)` when there is nothing to fetch from: the
+    /// agent has no live source, Claude has no Anthropic credentials, or the OpenCode server
+    /// is not running and `start_opencode` is false.
+    pub(crate) async fn refresh(
+        &self,
+        agent: AgentId,
+        opencode: &OpenCodeServerManager,
+        start_opencode: bool,
+    ) -> Result<bool, SandboxError> {
+        let fetched = match agent {
+            AgentId::Claude => self.fetch_claude().await?,
+            AgentId::Opencode => self.fetch_opencode(opencode, start_opencode).await?,
+            _ => None,
+        };
+        let Some((default_model, models)) = fetched else {
+            return Ok(false);
+        };
+        self.entries.lock().unwrap().insert(
+            agent,
+            Entry {
+                default_model,
+                models,
+                fetched_at_ms: now_ms(),
+            },
+        );
+        Ok(true)
+    }
+
+    /// Start the background refresh, once. It holds only a weak reference, so it ends with
+    /// the catalog.
+    pub(crate) fn spawn_refresh(self: &Arc<Self>, opencode: Arc<OpenCodeServerManager>) {
+        let Some(ttl) = self.ttl else {
+            return;
+        };
+        if self.refresh_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let catalog = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_TICK);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(catalog) = Weak::upgrade(&catalog) else {
+                    break;
+                };
+                catalog.refresh_due(ttl, &opencode).await;
+            }
+        });
+    }
+
+    async fn refresh_due(&self, ttl: Duration, opencode: &OpenCodeServerManager) {
+        for agent in AgentId::all()
+            .iter()
+            .copied()
+            .filter(|agent| Self::is_live(*agent))
+        {
+            let due = self
+                .attempts
+                .lock()
+                .unwrap()
+                .get(&agent)
+                .is_none_or(|attempt| attempt.elapsed() >= ttl);
+            if !due {
+                continue;
+            }
+            match self.refresh(agent, opencode, false).await {
+                Ok(false) => continue,
+                Ok(true) => tracing::debug!(agent = agent.as_str(), "model catalog refreshed"),
+                Err(err) => tracing::warn!(
+                    agent = agent.as_str(),
+                    error = %err,
+                    "model catalog refresh failed"
+                ),
+            }
+            self.attempts.lock().unwrap().insert(agent, Instant::now());
+        }
+    }
+
+    fn entry(&self, agent: AgentId) -> Option<Entry> {
+        self.entries.lock().unwrap().get(&agent).cloned()
+    }
+
+    async fn fetch_claude(&self) -> Result<Option<ModelList>, SandboxError> {
+        let credentials = tokio::task::spawn_blocking(|| {
+            extract_all_credentials(&CredentialExtractionOptions::new()).anthropic
+        })
+        .await
+        .ok()
+        .flatten();
+        let Some(credentials) = credentials else {
+            return Ok(None);
+        };
+        let mut request = self
+            .client
+            .get(ANTHROPIC_MODELS_URL)
+            .header("anthropic-version", ANTHROPIC_VERSION);
+        request = match credentials.auth_type {
+            AuthType::ApiKey => request.header("x-api-key", &credentials.api_key),
+            AuthType::Oauth => request.bearer_auth(&credentials.api_key),
+        };
+        let body = send_json("Anthropic", request).await?;
+        let models = CLAUDE_ALIASES
+            .iter()
+            .map(|(id, name)| AgentModel {
+                id: id.to_string(),
+                name: name.to_string(),
+                ..AgentModel::default()
+            })
+            .chain(parse_anthropic_models(&body))
+            .collect();
+        Ok(Some((None, models)))
+    }
+
+    async fn fetch_opencode(
+        &self,
+        opencode: &OpenCodeServerManager,
+        start_server: bool,
+    ) -> Result<Option<ModelList>, SandboxError> {
+        if !start_server && !opencode.status().await.running {
+            return Ok(None);
+        }
+        let base_url = opencode
+            .ensure_server()
+            .await
+            .map_err(|err| SandboxError::StreamError {
+                message: format!("failed to start OpenCode server: {err}"),
+            })?;
+        let body = send_json(
+            "OpenCode server",
+            self.client.get(format!("{base_url}/config/providers")),
+        )
+        .await?;
+        parse_opencode_providers(&body)
+            .map(Some)
+            .ok_or_else(|| SandboxError::StreamError {
+                message: "OpenCode server listed no providers".to_string(),
+            })
+    }
+}
+
+async fn send_json(source: &str, request: reqwest::RequestBuilder) -> Result<Value, SandboxError> {
+    let failed = |err: reqwest::Error| SandboxError::StreamError {
+        message: format!("{source} model list request failed: {err}"),
+    };
+    let response = request.send().await.map_err(failed)?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(SandboxError::StreamError {
+            message: format!("{source} model list returned {status}"),
+        });
+    }
+    response.json().await.map_err(failed)
+}
+
+/// `{"data": [{"id", "display_name"}]}`, sorted by id.
+fn parse_anthropic_models(body: &Value) -> Vec<AgentModel> {
+    let mut models = body
+        .get("data")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|model| {
+            let id = model.get("id")?.as_str()?.to_string();
+            let name = model
+                .get("display_name")
+                .and_then(Value::as_str)
+                .unwrap_or(&id)
+                .to_string();
+            Some(AgentModel {
+                id,
+                name,
+                ..AgentModel::default()
+            })
+        })
+        .collect::<Vec<_>>();
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    models
+}
+
+/// `{"providers": [{"id", "name", "models": {key: {"id", "name", "limit", "variants"}}}],
+/// "default": {provider: model}}` as `provider/model` ids, sorted, with the first listed
+/// provider's default as the default.
+fn parse_opencode_providers(body: &Value) -> Option<ModelList> {
+    let providers = body
+        .get("providers")
+        .or_else(|| body.get("all"))?
+        .as_array()?;
+    let defaults = body.get("default");
+    let mut default_model = None;
+    let mut models = Vec::new();
+    for provider in providers {
+        let Some(provider_id) = provider.get("id").and_then(Value::as_str) else {
+            continue;
+        };
+        if default_model.is_none() {
+            default_model = defaults
+                .and_then(|defaults| defaults.get(provider_id))
+                .and_then(Value::as_str)
+                .map(|model| format!("{provider_id}/{model}"));
+        }
+        let provider_name = provider
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or(provider_id);
+        for (key, model) in provider
+            .get("models")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let model_id = model.get("id").and_then(Value::as_str).unwrap_or(key);
+            let model_name = model
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or(model_id);
+            models.push(AgentModel {
+                id: format!("{provider_id}/{model_id}"),
+                name: format!("{provider_name}/{model_name}"),
+                context_window: model.pointer("/limit/context").and_then(Value::as_u64),
+                reasoning_variants: model
+                    .get("variants")
+                    .and_then(Value::as_object)
+                    .filter(|variants| !variants.is_empty())
+                    .map(|variants| variants.keys().cloned().collect()),
+            });
+        }
+    }
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    Some((
+        default_model.or_else(|| models.first().map(|m| m.id.clone())),
+        models,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opencode_providers_become_prefixed_models() {
+        let body = json!({
+            "providers": [
+                {"id": "openai", "name": "OpenAI", "models": {"gpt-5": {
+                    "id": "gpt-5",
+                    "name": "GPT-5",
+                    "limit": {"context": 400000, "output": 128000},
+                    "variants": {"high": {}, "low": {}}
+                }}},
+                {"id": "anthropic", "models": {"claude-sonnet-4-5": {}}}
+            ],
+            "default": {"anthropic": "claude-sonnet-4-5"}
+        });
+        let (default_model, models) = parse_opencode_providers(&body).unwrap();
+        assert_eq!(
+            default_model.as_deref(),
+            Some("anthropic/claude-sonnet-4-5")
+        );
+        assert_eq!(
+            models,
+            vec![
+                AgentModel {
+                    id: "anthropic/claude-sonnet-4-5".to_string(),
+                    name: "anthropic/claude-sonnet-4-5".to_string(),
+                    ..AgentModel::default()
+                },
+                AgentModel {
+                    id: "openai/gpt-5".to_string(),
+                    name: "OpenAI/GPT-5".to_string(),
+                    context_window: Some(400000),
+                    reasoning_variants: Some(vec!["high".to_string(), "low".to_string()]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn fetched_list_replaces_builtin_model_option() {
+        let catalog = ModelCatalog::from_env();
+        let mut options = vec![json!({
+            "id": "model",
+            "currentValue": "old",
+            "options": [{"value": "old", "name": "Old"}]
+        })];
+        assert_eq!(
+            catalog.models(AgentId::Opencode, &options).source,
+            ModelCatalogSource::Builtin
+        );
+
+        catalog.entries.lock().unwrap().insert(
+            AgentId::Opencode,
+            Entry {
+                default_model: Some("openai/gpt-5".to_string()),
+                models: vec![AgentModel {
+                    id: "openai/gpt-5".to_string(),
+                    name: "OpenAI/GPT-5".to_string(),
+                    ..AgentModel::default()
+                }],
+                fetched_at_ms: 1,
+            },
+        );
+        catalog.apply(AgentId::Opencode, &mut options);
+        assert_eq!(options[0]["currentValue"], "openai/gpt-5");
+        assert_eq!(
+            options[0]["options"],
+            json!([{"value": "openai/gpt-5", "name": "OpenAI/GPT-5"}])
+        );
+        assert_eq!(
+            catalog.models(AgentId::Opencode, &options).source,
+            ModelCatalogSource::Live
+        );
+    }
+}
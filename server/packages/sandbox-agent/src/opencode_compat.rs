@@ -91,13 +91,7 @@ impl OpenCodeCompatConfig {
     }
 
     fn now_ms(&self) -> i64 {
-        if let Some(value) = self.fixed_time_ms {
-            return value;
-        }
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_millis() as i64)
-            .unwrap_or(0)
+        self.fixed_time_ms.unwrap_or_else(crate::router::now_ms)
     }
 }
 
@@ -0,0 +1,303 @@
+//! Plan mode lifecycle of the sessions on an ACP server.
+//!
+//! Agents plan in a `plan` session mode: Claude's plan mode, where the agent asks to leave
+//! through an ExitPlanMode permission request carrying the plan, and Codex's plan
+//! collaboration mode, which publishes the plan as ACP `plan` updates and ends when the
+//! session switches to another mode. The daemon follows both through `current_mode_update`s
+//! and the `session/new`, `session/load` and `session/set_mode` calls clients make, and emits:
+//! - `_sandboxagent/plan/started` when a session enters plan mode.
+//! - `_sandboxagent/plan/updated` with the ACP plan `entries` or the plan `text`, plus
+//!   `reviewRequestId` when the agent waits on a decision.
+//! - `_sandboxagent/plan/approved` when the review request is allowed or the session leaves
+//!   plan mode, and `_sandboxagent/plan/rejected` when it is rejected.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+/// The mode id Claude and Codex use for planning.
+pub(crate) const PLAN_MODE: &str = "plan";
+
+#[derive(Debug, Clone, Default)]
+struct SessionPlan {
+    mode: Option<String>,
+    /// The mode the session had before it entered plan mode.
+    previous_mode: Option<String>,
+    planning: bool,
+    /// Id of the ExitPlanMode permission request waiting on a decision.
+    review: Option<Value>,
+}
+
+/// A session's plan, as the plan endpoints need it.
+#[derive(Debug, Clone)]
+pub(crate) enum PlanState {
+    /// The agent waits on this permission request.
+    Review { request_id: Value },
+    /// The session plans in plan mode; it leaves by switching to another mode.
+    Planning { previous_mode: Option<String> },
+}
+
+/// The plan of every session on one ACP server.
+#[derive(Debug, Default)]
+pub(crate) struct Plans {
+    sessions: HashMap<String, SessionPlan>,
+}
+
+impl Plans {
+    /// Follow an agent message; returns the lifecycle notifications it causes.
+    pub(crate) fn record(&mut self, message: &Value) -> Vec<Value> {
+        let params = message.get("params").unwrap_or(&Value::Null);
+        let Some(session_id) = params.get("sessionId").and_then(Value::as_str) else {
+            return Vec::new();
+        };
+        match message.get("method").and_then(Value::as_str) {
+            Some("session/update") => {
+                let update = params.get("update").unwrap_or(&Value::Null);
+                match update.get("sessionUpdate").and_then(Value::as_str) {
+                    Some("current_mode_update") => match update.get("currentModeId") {
+                        Some(Value::String(mode)) => self.switch_mode(session_id, mode),
+                        _ => Vec::new(),
+                    },
+                    Some("plan") if self.planning(session_id) => vec![notification(
+                        "updated",
+                        json!({
+                            "sessionId": session_id,
+                            "entries": update.get("entries").cloned().unwrap_or(json!([])),
+                        }),
+                    )],
+                    _ => Vec::new(),
+                }
+            }
+            Some("session/request_permission") => {
+                let Some(text) = params
+                    .pointer("/toolCall/rawInput/plan")
+                    .and_then(Value::as_str)
+                else {
+                    return Vec::new();
+                };
+                let Some(request_id) = message.get("id").filter(|id| !id.is_null()) else {
+                    return Vec::new();
+                };
+                let mut events = self.start(session_id);
+                self.sessions
+                    .entry(session_id.to_string())
+                    .or_default()
+                    .review = Some(request_id.clone());
+                events.push(notification(
+                    "updated",
+                    json!({
+                        "sessionId": session_id,
+                        "text": text,
+                        "reviewRequestId": request_id,
+                    }),
+                ));
+                events
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Follow the mode `request` set, once the agent answered it with `response`.
+    pub(crate) fn observe(&mut self, request: &Value, response: &Value) -> Vec<Value> {
+        let Some(result) = response.get("result") else {
+            return Vec::new();
+        };
+        let Some(session_id) = result
+            .get("sessionId")
+            .or_else(|| request.pointer("/params/sessionId"))
+            .and_then(Value::as_str)
+        else {
+            return Vec::new();
+        };
+        let mode = match request.get("method").and_then(Value::as_str) {
+            Some("session/new" | "session/load") => result.pointer("/modes/currentModeId"),
+            Some("session/set_mode") => request.pointer("/params/modeId"),
+            _ => None,
+        };
+        match mode.and_then(Value::as_str) {
+            Some(mode) => self.switch_mode(session_id, mode),
+            None => Vec::new(),
+        }
+    }
+
+    /// Follow a client's JSON-RPC `response`; returns `approved` or `rejected` when it answers
+    /// a review request.
+    pub(crate) fn answer(&mut self, response: &Value, options: &Value) -> Option<Value> {
+        let request_id = response.get("id")?;
+        let (session_id, plan) = self
+            .sessions
+            .iter_mut()
+            .find(|(_, plan)| plan.review.as_ref() == Some(request_id))?;
+        plan.review = None;
+        let option_id = response
+            .pointer("/result/outcome/optionId")
+            .and_then(Value::as_str);
+        let kind = option_id.and_then(|option_id| {
+            options
+                .as_array()?
+                .iter()
+                .find(|option| option.get("optionId").and_then(Value::as_str) == Some(option_id))?
+                .get("kind")?
+                .as_str()
+        });
+        let params = json!({ "sessionId": session_id, "optionId": option_id });
+        if kind.is_some_and(|kind| kind.starts_with("allow")) {
+            plan.planning = false;
+            Some(notification("approved", params))
+        } else {
+            Some(notification("rejected", params))
+        }
+    }
+
+    /// What a plan decision on `session_id` answers; `None` when the session is not planning.
+    pub(crate) fn state(&self, session_id: &str) -> Option<PlanState> {
+        let plan = self.sessions.get(session_id)?;
+        match &plan.review {
+            Some(request_id) => Some(PlanState::Review {
+                request_id: request_id.clone(),
+            }),
+            None if plan.planning => Some(PlanState::Planning {
+                previous_mode: plan.previous_mode.clone(),
+            }),
+            None => None,
+        }
+    }
+
+    fn planning(&self, session_id: &str) -> bool {
+        self.sessions
+            .get(session_id)
+            .is_some_and(|plan| plan.planning)
+    }
+
+    fn start(&mut self, session_id: &str) -> Vec<Value> {
+        let plan = self.sessions.entry(session_id.to_string()).or_default();
+        if plan.planning {
+            return Vec::new();
+        }
+        plan.planning = true;
+        plan.previous_mode = plan.mode.clone().filter(|mode| mode != PLAN_MODE);
+        vec![notification(
+            "started",
+            json!({ "sessionId": session_id, "previousMode": plan.previous_mode }),
+        )]
+    }
+
+    fn switch_mode(&mut self, session_id: &str, mode: &str) -> Vec<Value> {
+        let events = if mode == PLAN_MODE {
+            self.start(session_id)
+        } else {
+            let plan = self.sessions.entry(session_id.to_string()).or_default();
+            let left = std::mem::take(&mut plan.planning);
+            plan.review = None;
+            match left {
+                true => vec![notification(
+                    "approved",
+                    json!({ "sessionId": session_id, "mode": mode }),
+                )],
+                false => Vec::new(),
+            }
+        };
+        self.sessions
+            .entry(session_id.to_string())
+            .or_default()
+            .mode = Some(mode.to_string());
+        events
+    }
+}
+
+/// A `_sandboxagent/plan/{event}` notification.
+pub(crate) fn notification(event: &str, params: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": format!("_sandboxagent/plan/{event}"),
+        "params": params,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn methods(events: &[Value]) -> Vec<&str> {
+        events
+            .iter()
+            .map(|event| event["method"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn claude_exit_plan_mode_request_is_a_review() {
+        let mut plans = Plans::default();
+        let options = json!([
+            { "optionId": "acceptEdits", "kind": "allow_always" },
+            { "optionId": "default", "kind": "allow_once" },
+            { "optionId": "plan", "kind": "reject_once" },
+        ]);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "session/request_permission",
+            "params": {
+                "sessionId": "s-1",
+                "toolCall": { "title": "Ready to code?", "rawInput": { "plan": "1. Fix it" } },
+                "options": options,
+            },
+        });
+        let events = plans.record(&request);
+        assert_eq!(
+            methods(&events),
+            ["_sandboxagent/plan/started", "_sandboxagent/plan/updated"]
+        );
+        assert_eq!(events[1]["params"]["text"], "1. Fix it");
+        assert!(matches!(
+            plans.state("s-1"),
+            Some(PlanState::Review { request_id }) if request_id == json!(7)
+        ));
+
+        let rejected = plans.answer(
+            &json!({ "id": 7, "result": { "outcome": { "outcome": "selected", "optionId": "plan" } } }),
+            &options,
+        );
+        assert_eq!(rejected.unwrap()["method"], "_sandboxagent/plan/rejected");
+        assert!(matches!(
+            plans.state("s-1"),
+            Some(PlanState::Planning { .. })
+        ));
+
+        plans.record(&json!({ "id": 8, "method": "session/request_permission", "params": request["params"] }));
+        let approved = plans.answer(
+            &json!({ "id": 8, "result": { "outcome": { "outcome": "selected", "optionId": "default" } } }),
+            &options,
+        );
+        assert_eq!(approved.unwrap()["params"]["optionId"], "default");
+        assert!(plans.state("s-1").is_none());
+    }
+
+    #[test]
+    fn leaving_plan_mode_approves_the_plan() {
+        let mut plans = Plans::default();
+        let events = plans.observe(
+            &json!({ "method": "session/new", "params": {} }),
+            &json!({ "result": { "sessionId": "s-1", "modes": { "currentModeId": "auto" } } }),
+        );
+        assert!(events.is_empty());
+        let update = |update: Value| json!({ "method": "session/update", "params": { "sessionId": "s-1", "update": update } });
+        assert!(plans
+            .record(&update(json!({ "sessionUpdate": "plan", "entries": [] })))
+            .is_empty());
+
+        let events = plans.record(&update(
+            json!({ "sessionUpdate": "current_mode_update", "currentModeId": "plan" }),
+        ));
+        assert_eq!(events[0]["params"]["previousMode"], "auto");
+        let events = plans.record(&update(json!({ "sessionUpdate": "plan", "entries": [] })));
+        assert_eq!(methods(&events), ["_sandboxagent/plan/updated"]);
+
+        let events = plans.observe(
+            &json!({ "method": "session/set_mode", "params": { "sessionId": "s-1", "modeId": "auto" } }),
+            &json!({ "result": {} }),
+        );
+        assert_eq!(methods(&events), ["_sandboxagent/plan/approved"]);
+        assert!(plans.state("s-1").is_none());
+    }
+}
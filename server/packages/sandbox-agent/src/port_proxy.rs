@@ -0,0 +1,274 @@
+//! Reverse proxy behind `/v1/proxy/{port}` for servers started inside the sandbox.
+//!
+//! Requests go to `127.0.0.1:{port}` only when the port is in the `--proxy-port` allowlist;
+//! with no allowlist the proxy is off. Bodies stream in both directions, redirects and
+//! compressed bodies are passed through untouched, and WebSocket upgrades are bridged frame by
+//! frame so dev-server hot reload keeps working. The daemon's bearer token is never forwarded.
+
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode};
+use axum::response::Response;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use sandbox_agent_error::SandboxError;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame as UpstreamCloseFrame;
+use tokio_tungstenite::tungstenite::Message as UpstreamMessage;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+const PROXY_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const PROXY_CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+/// Headers that describe a single connection and must not be forwarded.
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+pub(crate) type UpstreamSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug)]
+pub(crate) struct PortProxy {
+    allowed: Vec<RangeInclusive<u16>>,
+    client: reqwest::Client,
+}
+
+impl PortProxy {
+    pub(crate) fn new(allowed: Vec<RangeInclusive<u16>>) -> Self {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .connect_timeout(PROXY_CONNECT_TIMEOUT)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        Self { allowed, client }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.allowed.is_empty()
+    }
+
+    pub(crate) fn check_port(&self, port: u16) -> Result<(), SandboxError> {
+        if self.allowed.iter().any(|range| range.contains(&port)) {
+            return Ok(());
+        }
+        Err(SandboxError::PermissionDenied {
+            message: Some(format!("port {port} is not in the proxy allowlist")),
+        })
+    }
+
+    /// Forward `request` to `127.0.0.1:{port}{target}` and stream the response back.
+    pub(crate) async fn forward(
+        &self,
+        port: u16,
+        target: &str,
+        prefix: &str,
+        request: Request<Body>,
+    ) -> Result<Response, SandboxError> {
+        let (parts, body) = request.into_parts();
+        let method =
+            reqwest::Method::from_bytes(parts.method.as_str().as_bytes()).map_err(|_| {
+                SandboxError::InvalidRequest {
+                    message: format!("unsupported method: {}", parts.method),
+                }
+            })?;
+
+        let mut upstream = self
+            .client
+            .request(method, format!("http://127.0.0.1:{port}{target}"));
+        for (name, value) in forwarded_headers(&parts.headers) {
+            upstream = upstream.header(name.as_str(), value.as_bytes());
+        }
+        if let Some(host) = parts.headers.get(header::HOST) {
+            upstream = upstream.header("x-forwarded-host", host.as_bytes());
+        }
+        upstream = upstream.header("x-forwarded-prefix", prefix);
+        let body = body.into_data_stream().map_err(std::io::Error::other);
+        upstream = upstream.body(reqwest::Body::wrap_stream(body));
+
+        let response = upstream
+            .send()
+            .await
+            .map_err(|err| SandboxError::StreamError {
+                message: format!("proxy request to port {port} failed: {err}"),
+            })?;
+
+        let status =
+            StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+        let mut headers = HeaderMap::new();
+        for (name, value) in response.headers() {
+            if is_hop_by_hop(name.as_str()) {
+                continue;
+            }
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_str().as_bytes()),
+                HeaderValue::from_bytes(value.as_bytes()),
+            ) {
+                headers.append(name, value);
+            }
+        }
+
+        let mut proxied = Response::new(Body::from_stream(response.bytes_stream()));
+        *proxied.status_mut() = status;
+        *proxied.headers_mut() = headers;
+        Ok(proxied)
+    }
+}
+
+/// Open a WebSocket to `127.0.0.1:{port}{target}`, offering the subprotocols the client asked
+/// for. Returns the socket and the subprotocol the upstream server picked.
+pub(crate) async fn connect_websocket(
+    port: u16,
+    target: &str,
+    headers: &HeaderMap,
+) -> Result<(UpstreamSocket, Option<String>), SandboxError> {
+    let url = format!("ws://127.0.0.1:{port}{target}");
+    let mut request =
+        url.as_str()
+            .into_client_request()
+            .map_err(|err| SandboxError::InvalidRequest {
+                message: format!("invalid proxy target {target}: {err}"),
+            })?;
+    for name in [
+        header::SEC_WEBSOCKET_PROTOCOL,
+        header::ORIGIN,
+        header::COOKIE,
+    ] {
+        if let Some(value) = headers.get(&name) {
+            request.headers_mut().insert(name, value.clone());
+        }
+    }
+
+    let (socket, response) = tokio::time::timeout(
+        PROXY_CONNECT_TIMEOUT,
+        tokio_tungstenite::connect_async(request),
+    )
+    .await
+    .map_err(|_| SandboxError::Timeout {
+        message: Some(format!("WebSocket connection to port {port} timed out")),
+    })?
+    .map_err(|err| SandboxError::StreamError {
+        message: format!("WebSocket connection to port {port} failed: {err}"),
+    })?;
+    let protocol = response
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    Ok((socket, protocol))
+}
+
+/// Relay frames between the client and the upstream server until either side closes.
+pub(crate) async fn bridge_websocket(client: WebSocket, upstream: UpstreamSocket) {
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+
+    let to_upstream = async {
+        while let Some(Ok(message)) = client_rx.next().await {
+            let close = matches!(message, Message::Close(_));
+            if upstream_tx
+                .send(to_upstream_message(message))
+                .await
+                .is_err()
+                || close
+            {
+                break;
+            }
+        }
+        let _ = upstream_tx.close().await;
+    };
+    let to_client = async {
+        while let Some(Ok(message)) = upstream_rx.next().await {
+            let Some(message) = to_client_message(message) else {
+                continue;
+            };
+            let close = matches!(message, Message::Close(_));
+            if client_tx.send(message).await.is_err() || close {
+                break;
+            }
+        }
+        let _ = client_tx.close().await;
+    };
+
+    // Once the client side ends, give the upstream a moment to answer its close frame.
+    tokio::pin!(to_client);
+    tokio::select! {
+        _ = to_upstream => {
+            let _ = tokio::time::timeout(PROXY_CLOSE_GRACE, &mut to_client).await;
+        }
+        _ = &mut to_client => {}
+    }
+}
+
+fn forwarded_headers(headers: &HeaderMap) -> impl Iterator<Item = (&HeaderName, &HeaderValue)> {
+    headers.iter().filter(|(name, _)| {
+        !is_hop_by_hop(name.as_str()) && *name != header::HOST && *name != header::AUTHORIZATION
+    })
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP_HEADERS
+        .iter()
+        .any(|hop| name.eq_ignore_ascii_case(hop))
+}
+
+fn to_upstream_message(message: Message) -> UpstreamMessage {
+    match message {
+        Message::Text(text) => UpstreamMessage::Text(text),
+        Message::Binary(data) => UpstreamMessage::Binary(data),
+        Message::Ping(data) => UpstreamMessage::Ping(data),
+        Message::Pong(data) => UpstreamMessage::Pong(data),
+        Message::Close(frame) => UpstreamMessage::Close(frame.map(|frame| UpstreamCloseFrame {
+            code: CloseCode::from(frame.code),
+            reason: frame.reason,
+        })),
+    }
+}
+
+fn to_client_message(message: UpstreamMessage) -> Option<Message> {
+    Some(match message {
+        UpstreamMessage::Text(text) => Message::Text(text),
+        UpstreamMessage::Binary(data) => Message::Binary(data),
+        UpstreamMessage::Ping(data) => Message::Ping(data),
+        UpstreamMessage::Pong(data) => Message::Pong(data),
+        UpstreamMessage::Close(frame) => Message::Close(frame.map(|frame| CloseFrame {
+            code: frame.code.into(),
+            reason: frame.reason,
+        })),
+        UpstreamMessage::Frame(_) => return None,
+    })
+}
+
+/// Parse a `--proxy-port` value: a single port or an inclusive `start-end` range.
+pub(crate) fn parse_port_range(value: &str) -> Result<RangeInclusive<u16>, String> {
+    let parse = |part: &str| {
+        part.trim()
+            .parse::<u16>()
+            .ok()
+            .filter(|port| *port != 0)
+            .ok_or_else(|| format!("invalid port '{part}'"))
+    };
+    match value.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            if start > end {
+                return Err(format!("invalid port range '{value}'"));
+            }
+            Ok(start..=end)
+        }
+        None => {
+            let port = parse(value)?;
+            Ok(port..=port)
+        }
+    }
+}
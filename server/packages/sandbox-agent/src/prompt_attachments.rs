@@ -0,0 +1,200 @@
+//! Inline attachments in `session/prompt`.
+//!
+//! A `resource_link` block may carry its content instead of a `uri`, so browser clients can
+//! attach screenshots without uploading them first:
+//!
+//! ```json
+//! {"type": "resource_link", "name": "screenshot.png", "mimeType": "image/png",
+//!  "_meta": {"sandboxagent.dev": {"data": "iVBORw0KGgo..."}}}
+//! ```
+//!
+//! `data` is base64, optionally as a `data:` URL. The daemon writes it under
+//! `.sandbox-agent/attachments/` in the server's working directory and forwards the block with
+//! a `file://` uri and `size`, without the data.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Map, Value};
+
+use crate::router::map_fs_error;
+
+const ATTACHMENTS_DIR: &str = ".sandbox-agent/attachments";
+
+/// Write inline `resource_link` data in a `session/prompt` to files under `root` and point
+/// the blocks at them.
+pub(crate) fn write_inline_attachments(
+    mut payload: Value,
+    root: Option<&Path>,
+) -> Result<Value, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/prompt") {
+        return Ok(payload);
+    }
+    let Some(blocks) = payload
+        .pointer_mut("/params/prompt")
+        .and_then(Value::as_array_mut)
+    else {
+        return Ok(payload);
+    };
+    for block in blocks.iter_mut().filter_map(Value::as_object_mut) {
+        if block.get("type").and_then(Value::as_str) != Some("resource_link") {
+            continue;
+        }
+        let Some(data) = take_inline_data(block) else {
+            continue;
+        };
+        let root = root.ok_or_else(|| {
+            invalid("inline attachments need a server with a working directory".to_string())
+        })?;
+        write_attachment(block, &data, root)?;
+    }
+    Ok(payload)
+}
+
+/// A `resource_link` block for an attachment given by URL, as chat frontends send them.
+/// `data:` URLs become inline attachments; without a `name` the file is named after its type.
+pub(crate) fn url_attachment(url: &str, name: Option<&str>, mime_type: Option<&str>) -> Value {
+    let mut link = json!({ "type": "resource_link" });
+    if url.starts_with("data:") {
+        link["_meta"] = json!({ "sandboxagent.dev": { "data": url } });
+    } else {
+        link["uri"] = json!(url);
+        link["name"] = json!(url);
+    }
+    if let Some(name) = name {
+        link["name"] = json!(name);
+    }
+    if let Some(mime_type) = mime_type {
+        link["mimeType"] = json!(mime_type);
+    }
+    link
+}
+
+/// Remove `_meta["sandboxagent.dev"].data` from `block`, dropping objects it leaves empty.
+fn take_inline_data(block: &mut Map<String, Value>) -> Option<Value> {
+    let meta = block.get_mut("_meta")?.as_object_mut()?;
+    let ours = meta.get_mut("sandboxagent.dev")?.as_object_mut()?;
+    let data = ours.remove("data")?;
+    if ours.is_empty() {
+        meta.remove("sandboxagent.dev");
+    }
+    if meta.is_empty() {
+        block.remove("_meta");
+    }
+    Some(data)
+}
+
+fn write_attachment(
+    block: &mut Map<String, Value>,
+    data: &Value,
+    root: &Path,
+) -> Result<(), SandboxError> {
+    if block
+        .get("uri")
+        .and_then(Value::as_str)
+        .is_some_and(|uri| !uri.is_empty())
+    {
+        return Err(invalid(
+            "resource_link attachments take either a uri or inline data, not both".to_string(),
+        ));
+    }
+    let data = data
+        .as_str()
+        .ok_or_else(|| invalid("inline attachment data must be a base64 string".to_string()))?;
+    // Accept `data:image/png;base64,...` as produced by `FileReader.readAsDataURL`.
+    let (data_url_mime, encoded) = match data.strip_prefix("data:") {
+        Some(rest) => {
+            let (header, encoded) = rest
+                .split_once(',')
+                .ok_or_else(|| invalid("inline attachment data URL has no payload".to_string()))?;
+            let mime = header
+                .strip_suffix(";base64")
+                .ok_or_else(|| invalid("inline attachment data URLs must be base64".to_string()))?;
+            (Some(mime.to_string()), encoded)
+        }
+        None => (None, data),
+    };
+    let bytes = STANDARD
+        .decode(encoded.trim())
+        .map_err(|err| invalid(format!("inline attachment data is not valid base64: {err}")))?;
+
+    let mime = block
+        .get("mimeType")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .or(data_url_mime)
+        .filter(|mime| !mime.is_empty());
+    let name = block
+        .get("name")
+        .and_then(Value::as_str)
+        .map(sanitize_name)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| format!("attachment.{}", extension_for(mime.as_deref())));
+
+    let dir = root.join(ATTACHMENTS_DIR);
+    fs::create_dir_all(&dir).map_err(|err| map_fs_error(&dir, err))?;
+    let path = unique_path(&dir, &name);
+    fs::write(&path, &bytes).map_err(|err| map_fs_error(&path, err))?;
+
+    let uri = reqwest::Url::from_file_path(&path)
+        .map_err(|_| invalid(format!("cannot build a file uri for {}", path.display())))?;
+    block.insert("uri".to_string(), Value::String(uri.to_string()));
+    block.insert("name".to_string(), Value::String(name));
+    block.insert("size".to_string(), Value::from(bytes.len()));
+    if let Some(mime) = mime {
+        block.insert("mimeType".to_string(), Value::String(mime));
+    }
+    Ok(())
+}
+
+/// `name` reduced to its final component with only portable file name characters.
+fn sanitize_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    name.chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '_') {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+fn extension_for(mime: Option<&str>) -> &'static str {
+    match mime {
+        Some("image/png") => "png",
+        Some("image/jpeg") => "jpg",
+        Some("image/gif") => "gif",
+        Some("image/webp") => "webp",
+        Some("image/svg+xml") => "svg",
+        Some("application/pdf") => "pdf",
+        Some("application/json") => "json",
+        Some("text/csv") => "csv",
+        Some("text/markdown") => "md",
+        Some(mime) if mime.starts_with("text/") => "txt",
+        _ => "bin",
+    }
+}
+
+/// `{dir}/{millis}-{n}-{name}`, so attachments with the same name never overwrite each other.
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!("{millis}-{n}-{name}"))
+}
+
+fn invalid(message: String) -> SandboxError {
+    SandboxError::InvalidRequest { message }
+}
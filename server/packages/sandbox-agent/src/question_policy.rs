@@ -0,0 +1,256 @@
+//! Timeouts for questions an agent asks with `_sandboxagent/session/request_question`.
+//!
+//! A `session/new` may set `_meta["sandboxagent.dev"].questionPolicy`:
+//!
+//! ```json
+//! {"timeoutMs": 300000, "onTimeout": "answer", "answers": [["yes"]]}
+//! ```
+//!
+//! When a question of the session is still pending after `timeoutMs`, the daemon answers it
+//! itself: `reject` rejects it, `default` picks the option marked `default` (or the first
+//! option) of each question, and `answer` answers with `answers`. The server's streams then
+//! receive `_sandboxagent/question/resolved` with `timedOut: true`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use acp_http_adapter::process::AdapterRuntime;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+
+use crate::approvals::{ApprovalDecision, PendingApprovals};
+
+const QUESTION_METHOD: &str = "_sandboxagent/session/request_question";
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum QuestionTimeoutAction {
+    Reject,
+    Default,
+    Answer(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct QuestionPolicy {
+    pub(crate) timeout: Duration,
+    pub(crate) on_timeout: QuestionTimeoutAction,
+}
+
+impl QuestionPolicy {
+    /// Answers to `params.questions` for the `default` action: one option label per question,
+    /// or none when a question has no options.
+    fn default_answers(params: &Value) -> Value {
+        let answers = params
+            .get("questions")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .map(|question| {
+                let options = question
+                    .get("options")
+                    .and_then(Value::as_array)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                let option = options
+                    .iter()
+                    .find(|option| option.get("default").and_then(Value::as_bool) == Some(true))
+                    .or_else(|| options.first());
+                option
+                    .and_then(|option| option.get("label"))
+                    .map(|label| vec![label.clone()])
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        Value::Array(answers.into_iter().map(Value::Array).collect())
+    }
+}
+
+/// Take `questionPolicy` off a `session/new`.
+pub(crate) fn take_question_policy(
+    payload: &mut Value,
+) -> Result<Option<QuestionPolicy>, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/new") {
+        return Ok(None);
+    }
+    let Some(policy) = payload
+        .pointer_mut("/params/_meta/sandboxagent.dev")
+        .and_then(Value::as_object_mut)
+        .and_then(|ours| ours.remove("questionPolicy"))
+    else {
+        return Ok(None);
+    };
+    if policy.is_null() {
+        return Ok(None);
+    }
+    let invalid = |message: &str| SandboxError::InvalidRequest {
+        message: format!("questionPolicy: {message}"),
+    };
+    let timeout = policy
+        .get("timeoutMs")
+        .and_then(Value::as_u64)
+        .filter(|timeout| *timeout > 0)
+        .map(Duration::from_millis)
+        .ok_or_else(|| invalid("timeoutMs must be a positive integer"))?;
+    let on_timeout = match policy.get("onTimeout").and_then(Value::as_str) {
+        None | Some("reject") => QuestionTimeoutAction::Reject,
+        Some("default") => QuestionTimeoutAction::Default,
+        Some("answer") => match policy.get("answers") {
+            Some(answers @ Value::Array(_)) => QuestionTimeoutAction::Answer(answers.clone()),
+            _ => return Err(invalid("answers must be an array when onTimeout is answer")),
+        },
+        Some(_) => return Err(invalid("onTimeout must be reject, default or answer")),
+    };
+    Ok(Some(QuestionPolicy {
+        timeout,
+        on_timeout,
+    }))
+}
+
+/// Question policies the sessions of one ACP server were created with.
+#[derive(Debug, Default)]
+pub(crate) struct SessionQuestionPolicies {
+    sessions: HashMap<String, QuestionPolicy>,
+}
+
+impl SessionQuestionPolicies {
+    /// Remember `policy` for the session the `session/new` `response` created.
+    pub(crate) fn observe(&mut self, policy: QuestionPolicy, response: &Value) {
+        if let Some(session_id) = response
+            .pointer("/result/sessionId")
+            .and_then(Value::as_str)
+        {
+            self.sessions.insert(session_id.to_string(), policy);
+        }
+    }
+
+    /// The policy for `message` when it is a question from a session that has one.
+    pub(crate) fn for_question(&self, message: &Value) -> Option<(Value, QuestionPolicy)> {
+        if message.get("method").and_then(Value::as_str) != Some(QUESTION_METHOD) {
+            return None;
+        }
+        let request_id = message.get("id").filter(|id| !id.is_null())?;
+        let session_id = message
+            .pointer("/params/sessionId")
+            .and_then(Value::as_str)?;
+        let policy = self.sessions.get(session_id)?;
+        Some((request_id.clone(), policy.clone()))
+    }
+}
+
+/// Answer the question `request_id` with the policy's action once its timeout runs out, unless
+/// a client answered it first.
+pub(crate) async fn expire(
+    runtime: Arc<AdapterRuntime>,
+    approvals: Arc<StdMutex<PendingApprovals>>,
+    request_id: Value,
+    policy: QuestionPolicy,
+) {
+    tokio::time::sleep(policy.timeout).await;
+    let Some(question) = approvals
+        .lock()
+        .ok()
+        .and_then(|approvals| approvals.get(&request_id))
+    else {
+        return;
+    };
+    let (response, outcome) = match &policy.on_timeout {
+        QuestionTimeoutAction::Reject => (
+            question.response(ApprovalDecision::Reject, None, None),
+            "rejected",
+        ),
+        QuestionTimeoutAction::Default => (
+            question.response(
+                ApprovalDecision::Once,
+                None,
+                Some(QuestionPolicy::default_answers(&question.params)),
+            ),
+            "answered",
+        ),
+        QuestionTimeoutAction::Answer(answers) => (
+            question.response(ApprovalDecision::Once, None, Some(answers.clone())),
+            "answered",
+        ),
+    };
+    if let Err(err) = runtime.post(response.clone()).await {
+        tracing::warn!(error = %err, "acp_proxy: timed out question not answered");
+        return;
+    }
+    if let Ok(mut approvals) = approvals.lock() {
+        approvals.resolve(&request_id);
+    }
+    let answers = response.pointer("/result/_meta/sandboxagent.dev/answers");
+    runtime
+        .seed_history([json!({
+            "jsonrpc": "2.0",
+            "method": "_sandboxagent/question/resolved",
+            "params": {
+                "sessionId": question.session_id,
+                "requestId": request_id,
+                "outcome": outcome,
+                "answers": answers,
+                "timedOut": true,
+                "timeoutMs": policy.timeout.as_millis() as u64,
+            },
+        })])
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_parse_and_pick_default_options() {
+        let mut request = json!({
+            "method": "session/new",
+            "params": { "_meta": { "sandboxagent.dev": {
+                "questionPolicy": { "timeoutMs": 1000, "onTimeout": "default" },
+            } } },
+        });
+        let policy = take_question_policy(&mut request)
+            .expect("valid")
+            .expect("policy");
+        assert_eq!(policy.timeout, Duration::from_secs(1));
+        assert_eq!(policy.on_timeout, QuestionTimeoutAction::Default);
+        assert!(request
+            .pointer("/params/_meta/sandboxagent.dev/questionPolicy")
+            .is_none());
+
+        for invalid in [
+            json!({ "timeoutMs": 0 }),
+            json!({ "timeoutMs": 1000, "onTimeout": "wait" }),
+            json!({ "timeoutMs": 1000, "onTimeout": "answer" }),
+        ] {
+            let mut request = json!({
+                "method": "session/new",
+                "params": { "_meta": { "sandboxagent.dev": { "questionPolicy": invalid } } },
+            });
+            assert!(take_question_policy(&mut request).is_err());
+        }
+
+        let mut policies = SessionQuestionPolicies::default();
+        policies.observe(policy, &json!({ "result": { "sessionId": "s-1" } }));
+        let question = |session_id: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": 7,
+                "method": QUESTION_METHOD,
+                "params": { "sessionId": session_id, "questions": [] },
+            })
+        };
+        assert_eq!(
+            policies.for_question(&question("s-1")).map(|(id, _)| id),
+            Some(json!(7))
+        );
+        assert!(policies.for_question(&question("s-2")).is_none());
+
+        let params = json!({ "questions": [
+            { "question": "Proceed?", "options": [{ "label": "yes" }, { "label": "no", "default": true }] },
+            { "question": "Why?", "options": [] },
+        ] });
+        assert_eq!(
+            QuestionPolicy::default_answers(&params),
+            json!([["no"], []])
+        );
+    }
+}
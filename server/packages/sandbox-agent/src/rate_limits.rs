@@ -0,0 +1,277 @@
+//! Provider rate limits reported by agents, behind `GET /v1/debug/rate-limits`.
+//!
+//! Agents pass a provider's `429` or overloaded error on as a JSON-RPC error whose wording
+//! differs per agent. When a response carries one, the daemon adds an
+//! `_sandboxagent/rate_limited` notification with its kind and, when the error said, how long
+//! to wait. Each one is counted per agent for the daemon's lifetime and per server while the
+//! server is open, and the newest [`RATE_LIMIT_CAPACITY`] are kept.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_api_types::{RateLimitAgentCount, RateLimitEvent, RateLimitKind};
+use serde_json::{json, Value};
+
+use crate::router::now_ms;
+use crate::turn_timeout::TURN_TIMEOUT_CODE;
+
+pub const RATE_LIMIT_CAPACITY: usize = 256;
+/// Lowercase fragments of errors that report a rate limit.
+const RATE_LIMIT_PATTERNS: &[&str] = &["rate limit", "rate_limit", "too many requests"];
+/// Lowercase fragments of errors that report an overloaded provider.
+const OVERLOADED_PATTERNS: &[&str] = &["overloaded"];
+/// Error data fields holding seconds to wait.
+const RETRY_AFTER_SECS_KEYS: &[&str] = &[
+    "retryAfter",
+    "retry_after",
+    "retry-after",
+    "retryAfterSeconds",
+];
+/// Error data fields holding milliseconds to wait.
+const RETRY_AFTER_MS_KEYS: &[&str] = &["retryAfterMs", "retry_after_ms"];
+/// Wording that puts the wait into an error message.
+const RETRY_AFTER_PHRASES: &[&str] = &["retry after ", "retry-after: ", "try again in "];
+
+/// Rate limits of one server.
+#[derive(Debug, Default)]
+pub(crate) struct ServerRateLimits {
+    rate_limited: AtomicU64,
+    overloaded: AtomicU64,
+}
+
+impl ServerRateLimits {
+    pub(crate) fn rate_limited(&self) -> u64 {
+        self.rate_limited.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn overloaded(&self) -> u64 {
+        self.overloaded.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RateLimitLog {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Rate-limited and overloaded errors per agent.
+    agents: BTreeMap<&'static str, (u64, u64)>,
+    recent: VecDeque<RateLimitEvent>,
+}
+
+impl RateLimitLog {
+    /// Count `response` of `server_id` when it reports a rate limit, and return the
+    /// notification to publish for it.
+    pub(crate) fn record(
+        &self,
+        server_id: &str,
+        agent: AgentId,
+        server: &ServerRateLimits,
+        session_id: Option<&str>,
+        response: &Value,
+    ) -> Option<Value> {
+        let error = response.get("error")?;
+        let kind = classify(error)?;
+        let event = RateLimitEvent {
+            server_id: server_id.to_string(),
+            agent: agent.as_str().to_string(),
+            session_id: session_id.map(str::to_string),
+            kind,
+            retry_after_ms: retry_after_ms(error),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            timestamp_ms: now_ms(),
+        };
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "_sandboxagent/rate_limited",
+            "params": {
+                "sessionId": event.session_id,
+                "kind": event.kind,
+                "retryAfterMs": event.retry_after_ms,
+                "message": event.message,
+                "error": error,
+            },
+        });
+        let mut inner = self.inner.lock().expect("rate limit log poisoned");
+        let counts = inner.agents.entry(agent.as_str()).or_default();
+        match kind {
+            RateLimitKind::RateLimit => {
+                counts.0 += 1;
+                server.rate_limited.fetch_add(1, Ordering::Relaxed);
+            }
+            RateLimitKind::Overloaded => {
+                counts.1 += 1;
+                server.overloaded.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        inner.recent.push_back(event);
+        while inner.recent.len() > RATE_LIMIT_CAPACITY {
+            inner.recent.pop_front();
+        }
+        Some(notification)
+    }
+
+    /// Rate limits since the daemon started, per agent.
+    pub(crate) fn agents(&self) -> Vec<RateLimitAgentCount> {
+        let inner = self.inner.lock().expect("rate limit log poisoned");
+        inner
+            .agents
+            .iter()
+            .map(|(agent, (rate_limited, overloaded))| RateLimitAgentCount {
+                agent: agent.to_string(),
+                rate_limited: *rate_limited,
+                overloaded: *overloaded,
+            })
+            .collect()
+    }
+
+    /// The newest `limit` rate limits, of `server_id` only when given, oldest first.
+    pub(crate) fn recent(&self, server_id: Option<&str>, limit: usize) -> Vec<RateLimitEvent> {
+        let inner = self.inner.lock().expect("rate limit log poisoned");
+        let mut events = inner
+            .recent
+            .iter()
+            .rev()
+            .filter(|event| server_id.is_none_or(|id| event.server_id == id))
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+        events.reverse();
+        events
+    }
+}
+
+/// The kind of rate limit a JSON-RPC `error` reports, if any.
+pub(crate) fn classify(error: &Value) -> Option<RateLimitKind> {
+    if error.pointer("/data/code").and_then(Value::as_str) == Some(TURN_TIMEOUT_CODE) {
+        return None;
+    }
+    let text = error.to_string().to_lowercase();
+    let has_status = |status: &str| {
+        text.split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| word == status)
+    };
+    let mentions = |patterns: &[&str]| patterns.iter().any(|pattern| text.contains(pattern));
+    if has_status("429") || mentions(RATE_LIMIT_PATTERNS) {
+        Some(RateLimitKind::RateLimit)
+    } else if has_status("529") || mentions(OVERLOADED_PATTERNS) {
+        Some(RateLimitKind::Overloaded)
+    } else {
+        None
+    }
+}
+
+/// How long `error` asks to wait, from its data or its message.
+fn retry_after_ms(error: &Value) -> Option<u64> {
+    if let Some(data) = error.get("data") {
+        if let Some(ms) = find_number(data, RETRY_AFTER_MS_KEYS) {
+            return Some(ms as u64);
+        }
+        if let Some(secs) = find_number(data, RETRY_AFTER_SECS_KEYS) {
+            return Some((secs * 1000.0) as u64);
+        }
+    }
+    let message = error.get("message")?.as_str()?.to_lowercase();
+    RETRY_AFTER_PHRASES.iter().find_map(|phrase| {
+        let rest = &message[message.find(phrase)? + phrase.len()..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let value = rest[..end].parse::<f64>().ok()?;
+        let unit = rest[end..].trim_start();
+        Some(
+            if unit.starts_with("ms") || unit.starts_with("millisecond") {
+                value as u64
+            } else {
+                (value * 1000.0) as u64
+            },
+        )
+    })
+}
+
+/// The first of `keys` anywhere in `value` holding a non-negative number or numeric string.
+fn find_number(value: &Value, keys: &[&str]) -> Option<f64> {
+    let map = value.as_object()?;
+    keys.iter()
+        .filter_map(|key| map.get(*key))
+        .find_map(|found| match found {
+            Value::Number(number) => number.as_f64(),
+            Value::String(text) => text.trim().parse::<f64>().ok(),
+            _ => None,
+        })
+        .filter(|number| *number >= 0.0)
+        .or_else(|| map.values().find_map(|nested| find_number(nested, keys)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_errors_are_classified_with_their_wait() {
+        let rate_limited = json!({
+            "code": -32000,
+            "message": "API Error: 429 Too Many Requests",
+            "data": { "headers": { "retry-after": "30" } },
+        });
+        assert_eq!(classify(&rate_limited), Some(RateLimitKind::RateLimit));
+        assert_eq!(retry_after_ms(&rate_limited), Some(30_000));
+
+        let overloaded = json!({
+            "code": -32603,
+            "message": "Overloaded, please try again in 2.5 seconds",
+        });
+        assert_eq!(classify(&overloaded), Some(RateLimitKind::Overloaded));
+        assert_eq!(retry_after_ms(&overloaded), Some(2_500));
+
+        let other = json!({ "code": -32000, "message": "prompt is 4290 tokens too long" });
+        assert_eq!(classify(&other), None);
+    }
+
+    #[test]
+    fn rate_limits_are_counted_per_agent_and_server() {
+        let log = RateLimitLog::default();
+        let server = ServerRateLimits::default();
+        let response = |message: &str| json!({ "id": 1, "error": { "message": message } });
+        assert!(log
+            .record(
+                "a",
+                AgentId::Claude,
+                &server,
+                None,
+                &json!({ "result": {} })
+            )
+            .is_none());
+        let notification = log
+            .record(
+                "a",
+                AgentId::Claude,
+                &server,
+                Some("s"),
+                &response("rate_limit_error"),
+            )
+            .expect("rate limited");
+        assert_eq!(notification["params"]["kind"], "rate_limit");
+        log.record(
+            "a",
+            AgentId::Claude,
+            &server,
+            Some("s"),
+            &response("overloaded_error"),
+        );
+
+        assert_eq!((server.rate_limited(), server.overloaded()), (1, 1));
+        let agents = log.agents();
+        assert_eq!(agents[0].agent, "claude");
+        assert_eq!((agents[0].rate_limited, agents[0].overloaded), (1, 1));
+        assert_eq!(log.recent(Some("a"), 1)[0].kind, RateLimitKind::Overloaded);
+    }
+}
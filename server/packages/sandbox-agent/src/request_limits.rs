@@ -0,0 +1,161 @@
+//! Per-token rate limiting and request body size limits for `/v1`.
+//!
+//! Rate limits are a token bucket per bearer token: each token may burst up to
+//! `--rate-limit` requests and regains that many per minute. Without auth, all clients
+//! share one bucket. Body limits apply to every `/v1` request: writes under `/v1/fs` use
+//! `--max-fs-body-bytes`, everything else (ACP messages included) `--max-message-bytes`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::extract::{OriginalUri, State};
+use axum::http::{header, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sandbox_agent_error::SandboxError;
+
+use crate::auth_tokens::RequestActor;
+use crate::router::{ApiError, AppState};
+
+pub const DEFAULT_MAX_FS_BODY_BYTES: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_MAX_MESSAGE_BYTES: u64 = 2 * 1024 * 1024;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// Buckets untouched for this long are full again and can be dropped.
+const BUCKET_IDLE_EVICTION: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    /// Requests per minute per token; `None` disables rate limiting.
+    pub rate_limit_per_minute: Option<u32>,
+    pub max_fs_body_bytes: u64,
+    pub max_message_bytes: u64,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            rate_limit_per_minute: None,
+            max_fs_body_bytes: DEFAULT_MAX_FS_BODY_BYTES,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute: per_minute.max(1),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take one request from `key`'s bucket, or return how long until one is available.
+    fn acquire(&self, key: &str) -> Result<(), Duration> {
+        let capacity = f64::from(self.per_minute);
+        let per_sec = capacity / RATE_LIMIT_WINDOW.as_secs_f64();
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limiter poisoned");
+        buckets.retain(|_, bucket| now.duration_since(bucket.updated) < BUCKET_IDLE_EVICTION);
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+    }
+}
+
+pub(crate) async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(limiter) = state.rate_limiter() else {
+        return next.run(request).await;
+    };
+    let key = request
+        .extensions()
+        .get::<RequestActor>()
+        .map(|actor| actor.0.as_str())
+        .unwrap_or(RequestActor::ANONYMOUS);
+    match limiter.acquire(key) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after_secs = wait.as_secs() + 1;
+            ApiError::from(SandboxError::RateLimited { retry_after_secs }).into_response()
+        }
+    }
+}
+
+pub(crate) async fn limit_body(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let limits = state.request_limits();
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path())
+        .unwrap_or_else(|| request.uri().path());
+    let is_fs_write = path.starts_with("/v1/fs/")
+        && !matches!(
+            *request.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS
+        );
+    let limit = if is_fs_write {
+        limits.max_fs_body_bytes
+    } else {
+        limits.max_message_bytes
+    };
+    let too_large = || ApiError::from(SandboxError::PayloadTooLarge { limit_bytes: limit });
+
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > limit) {
+        return too_large().into_response();
+    }
+
+    // Bodies without a length are cut off at the limit while they are read.
+    let (parts, body) = request.into_parts();
+    let limited = http_body_util::Limited::new(body, usize::try_from(limit).unwrap_or(usize::MAX));
+    let response = next
+        .run(Request::from_parts(parts, Body::new(limited)))
+        .await;
+
+    // Extractors reject over-long bodies with a plain-text 413; answer with problem details.
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_problem(&response) {
+        return too_large().into_response();
+    }
+    response
+}
+
+fn is_problem(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/problem+json"))
+}
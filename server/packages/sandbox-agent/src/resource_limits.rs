@@ -0,0 +1,468 @@
+//! CPU, memory and process-count limits for agent processes.
+//!
+//! Daemon defaults come from `SANDBOX_AGENT_ACP_CPU_LIMIT` (cores),
+//! `SANDBOX_AGENT_ACP_MEMORY_LIMIT_MB` and `SANDBOX_AGENT_ACP_PROCESS_LIMIT`; the bootstrap POST
+//! of a server can override each. Limited agents start through the hidden `limit-exec`
+//! subcommand, which applies the limits to itself and then execs the agent.
+//!
+//! With cgroups v2 each server gets its own cgroup with `cpu.max`, `memory.max` and `pids.max`,
+//! covering the agent and everything it starts. Out-of-memory kills and refused forks are
+//! reported on the server's stream as `_sandboxagent/error` with code `resource_limit_exceeded`.
+//! Without a usable cgroup v2 hierarchy, `limit-exec` falls back to `RLIMIT_DATA` and
+//! `RLIMIT_NPROC`, which apply per process (`RLIMIT_NPROC` per user), cannot limit CPU, and
+//! report nothing.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use acp_http_adapter::process::AdapterRuntime;
+use serde_json::{json, Value};
+
+use crate::agent_sandbox::Confinement;
+
+pub(crate) const CPU_LIMIT_ENV: &str = "SANDBOX_AGENT_ACP_CPU_LIMIT";
+pub(crate) const MEMORY_LIMIT_ENV: &str = "SANDBOX_AGENT_ACP_MEMORY_LIMIT_MB";
+pub(crate) const PROCESS_LIMIT_ENV: &str = "SANDBOX_AGENT_ACP_PROCESS_LIMIT";
+pub(crate) const LIMIT_EXCEEDED_CODE: &str = "resource_limit_exceeded";
+const LIMIT_EXEC_COMMAND: &str = "limit-exec";
+const CGROUP_CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+/// The daemon moves into this leaf when it has to delegate controllers from its own cgroup.
+const DAEMON_CGROUP: &str = "sandbox-agent";
+const CPU_PERIOD_US: u64 = 100_000;
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+static NEXT_CGROUP: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// CPU cores, e.g. `0.5` for half a core.
+    pub cpu: Option<f64>,
+    pub memory_mb: Option<u64>,
+    pub processes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub(crate) fn from_env() -> Self {
+        Self {
+            cpu: env_value(CPU_LIMIT_ENV),
+            memory_mb: env_value(MEMORY_LIMIT_ENV),
+            processes: env_value(PROCESS_LIMIT_ENV),
+        }
+        .normalized()
+    }
+
+    /// These limits with each one `overrides` sets replaced; an override of zero removes it.
+    pub(crate) fn with_overrides(self, overrides: ResourceLimits) -> Self {
+        Self {
+            cpu: overrides.cpu.or(self.cpu),
+            memory_mb: overrides.memory_mb.or(self.memory_mb),
+            processes: overrides.processes.or(self.processes),
+        }
+        .normalized()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.cpu.is_none() && self.memory_mb.is_none() && self.processes.is_none()
+    }
+
+    fn normalized(self) -> Self {
+        Self {
+            cpu: self.cpu.filter(|cpu| cpu.is_finite() && *cpu > 0.0),
+            memory_mb: self.memory_mb.filter(|mb| *mb > 0),
+            processes: self.processes.filter(|count| *count > 0),
+        }
+    }
+}
+
+fn env_value<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok()?.trim().parse().ok()
+}
+
+/// The cgroup v2 directory agent cgroups are created in, with the cpu, memory and pids
+/// controllers delegated to its children.
+#[derive(Debug)]
+pub(crate) struct Cgroups {
+    parent: PathBuf,
+}
+
+impl Cgroups {
+    /// The daemon's own cgroup, when it is on a cgroup v2 hierarchy that offers every
+    /// controller and the daemon may write to it.
+    pub(crate) fn detect() -> Option<Self> {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+        let mount = mountinfo.lines().find_map(|line| {
+            let (fields, fs) = line.split_once(" - ")?;
+            fs.starts_with("cgroup2 ")
+                .then(|| fields.split(' ').nth(4).map(PathBuf::from))?
+        })?;
+        let own = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+        let own = own.lines().find_map(|line| line.strip_prefix("0::"))?;
+        let dir = mount.join(own.trim_start_matches('/'));
+        match Self::prepare(dir.clone(), std::process::id()) {
+            Ok(cgroups) => Some(cgroups),
+            Err(err) => {
+                tracing::warn!(
+                    cgroup = %dir.display(),
+                    error = %err,
+                    "resource limits: cgroup v2 unavailable, falling back to rlimits"
+                );
+                None
+            }
+        }
+    }
+
+    fn prepare(dir: PathBuf, pid: u32) -> io::Result<Self> {
+        let available = std::fs::read_to_string(dir.join("cgroup.controllers"))?;
+        if let Some(missing) = CGROUP_CONTROLLERS
+            .iter()
+            .find(|controller| !available.split_whitespace().any(|c| c == **controller))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{missing} controller is not available"),
+            ));
+        }
+        let enabled = std::fs::read_to_string(dir.join("cgroup.subtree_control"))?;
+        if !CGROUP_CONTROLLERS
+            .iter()
+            .all(|controller| enabled.split_whitespace().any(|c| c == *controller))
+        {
+            // A cgroup that holds processes cannot delegate controllers, so move out first.
+            let leaf = dir.join(DAEMON_CGROUP);
+            std::fs::create_dir_all(&leaf)?;
+            std::fs::write(leaf.join("cgroup.procs"), pid.to_string())?;
+            std::fs::write(dir.join("cgroup.subtree_control"), "+cpu +memory +pids")?;
+        }
+        Ok(Self { parent: dir })
+    }
+
+    /// Create a cgroup for `server_id` enforcing `limits`.
+    pub(crate) fn create(&self, server_id: &str, limits: &ResourceLimits) -> io::Result<PathBuf> {
+        let name = server_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let dir = self.parent.join(format!(
+            "agent-{name}-{}",
+            NEXT_CGROUP.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir(&dir)?;
+        let result = write_limits(&dir, limits);
+        if result.is_err() {
+            let _ = std::fs::remove_dir(&dir);
+        }
+        result.map(|()| dir)
+    }
+}
+
+fn write_limits(dir: &Path, limits: &ResourceLimits) -> io::Result<()> {
+    if let Some(cpu) = limits.cpu {
+        let quota = ((cpu * CPU_PERIOD_US as f64).round() as u64).max(1_000);
+        std::fs::write(dir.join("cpu.max"), format!("{quota} {CPU_PERIOD_US}"))?;
+    }
+    if let Some(mb) = limits.memory_mb {
+        std::fs::write(dir.join("memory.max"), (mb * 1024 * 1024).to_string())?;
+        // Without swap accounting the file is missing; the memory limit still holds.
+        let _ = std::fs::write(dir.join("memory.swap.max"), "0");
+    }
+    if let Some(count) = limits.processes {
+        std::fs::write(dir.join("pids.max"), count.to_string())?;
+    }
+    Ok(())
+}
+
+/// The command line that starts a program through `limit-exec`, up to and including `--`.
+/// `limit-exec` joins `cgroup` or, without one, applies `limits` as rlimits, then applies
+/// `confinement`, before it execs the program that follows.
+pub(crate) fn limit_exec_prefix(
+    limits: &ResourceLimits,
+    cgroup: Option<&Path>,
+    confinement: Option<&Confinement>,
+) -> io::Result<Vec<String>> {
+    let exe = std::env::current_exe()?;
+    let mut prefix = vec![exe.display().to_string(), LIMIT_EXEC_COMMAND.to_string()];
+    match cgroup {
+        Some(cgroup) => {
+            prefix.push("--cgroup".to_string());
+            prefix.push(cgroup.display().to_string());
+        }
+        None => {
+            if let Some(mb) = limits.memory_mb {
+                prefix.push("--memory-mb".to_string());
+                prefix.push(mb.to_string());
+            }
+            if let Some(count) = limits.processes {
+                prefix.push("--processes".to_string());
+                prefix.push(count.to_string());
+            }
+        }
+    }
+    if let Some(confinement) = confinement {
+        prefix.extend(confinement.args());
+    }
+    prefix.push("--".to_string());
+    Ok(prefix)
+}
+
+/// Body of `limit-exec`: join `cgroup` or apply the rlimits, confine the process, then
+/// replace it with `command`. Only returns on failure.
+#[cfg(unix)]
+pub(crate) fn exec_limited(
+    cgroup: Option<&Path>,
+    memory_mb: Option<u64>,
+    processes: Option<u64>,
+    confinement: Option<&Confinement>,
+    command: &[String],
+) -> io::Error {
+    use std::os::unix::process::CommandExt;
+
+    if let Some(cgroup) = cgroup {
+        if let Err(err) = std::fs::write(cgroup.join("cgroup.procs"), "0") {
+            return err;
+        }
+    }
+    // RLIMIT_DATA rather than RLIMIT_AS: Node-based agents reserve far more address space
+    // than they ever use and would not start under an address-space limit.
+    let limits = [
+        (libc::RLIMIT_DATA, memory_mb.map(|mb| mb * 1024 * 1024)),
+        (libc::RLIMIT_NPROC, processes),
+    ];
+    for (resource, value) in limits {
+        let Some(value) = value else {
+            continue;
+        };
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        // SAFETY: setrlimit only reads the struct passed by reference.
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return io::Error::last_os_error();
+        }
+    }
+    // Last, as the cgroup can't be joined once the filesystem is confined.
+    if let Err(err) = confinement.map_or(Ok(()), crate::agent_sandbox::confine) {
+        return err;
+    }
+    let Some((program, args)) = command.split_first() else {
+        return io::Error::new(io::ErrorKind::InvalidInput, "missing command");
+    };
+    std::process::Command::new(program).args(args).exec()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn exec_limited(
+    _cgroup: Option<&Path>,
+    _memory_mb: Option<u64>,
+    _processes: Option<u64>,
+    _confinement: Option<&Confinement>,
+    _command: &[String],
+) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "resource limits require a Unix platform",
+    )
+}
+
+/// Report limit violations in `cgroup` on `runtime`'s stream until the agent exits, then
+/// kill anything left in the cgroup and remove it.
+pub(crate) fn spawn_watch(cgroup: PathBuf, limits: ResourceLimits, runtime: Arc<AdapterRuntime>) {
+    tokio::spawn(async move {
+        let mut seen = LimitEvents::read(&cgroup);
+        loop {
+            let exited = runtime.has_exited();
+            let now = LimitEvents::read(&cgroup);
+            let violations = violations(seen, now, &limits);
+            if !violations.is_empty() {
+                runtime.seed_history(violations).await;
+            }
+            seen = now;
+            if exited {
+                break;
+            }
+            tokio::time::sleep(WATCH_INTERVAL).await;
+        }
+        let _ = std::fs::write(cgroup.join("cgroup.kill"), "1");
+        // Killed processes take a moment to leave the cgroup.
+        for _ in 0..20 {
+            if std::fs::remove_dir(&cgroup).is_ok() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        tracing::warn!(cgroup = %cgroup.display(), "resource limits: cgroup not removed");
+    });
+}
+
+/// Counters of the cgroup events that mean a limit was hit.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct LimitEvents {
+    oom_kills: u64,
+    refused_forks: u64,
+}
+
+impl LimitEvents {
+    fn read(cgroup: &Path) -> Self {
+        let counter = |file: &str, key: &str| {
+            std::fs::read_to_string(cgroup.join(file))
+                .ok()
+                .and_then(|events| {
+                    events.lines().find_map(|line| {
+                        let (name, value) = line.split_once(' ')?;
+                        (name == key).then(|| value.trim().parse().ok())?
+                    })
+                })
+                .unwrap_or_default()
+        };
+        Self {
+            oom_kills: counter("memory.events", "oom_kill"),
+            refused_forks: counter("pids.events", "max"),
+        }
+    }
+}
+
+fn violations(before: LimitEvents, after: LimitEvents, limits: &ResourceLimits) -> Vec<Value> {
+    let mut notifications = Vec::new();
+    if after.oom_kills > before.oom_kills {
+        let limit = limits.memory_mb.unwrap_or_default();
+        notifications.push(limit_exceeded(
+            "memory",
+            limit,
+            after.oom_kills - before.oom_kills,
+            format!("agent process killed for exceeding the {limit} MB memory limit"),
+        ));
+    }
+    if after.refused_forks > before.refused_forks {
+        let limit = limits.processes.unwrap_or_default();
+        notifications.push(limit_exceeded(
+            "processes",
+            limit,
+            after.refused_forks - before.refused_forks,
+            format!("agent could not start a process: limit of {limit} processes reached"),
+        ));
+    }
+    notifications
+}
+
+fn limit_exceeded(resource: &str, limit: u64, count: u64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "_sandboxagent/error",
+        "params": {
+            "message": message,
+            "code": LIMIT_EXCEEDED_CODE,
+            "details": { "resource": resource, "limit": limit, "count": count },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_replace_defaults_and_zero_removes_them() {
+        let defaults = ResourceLimits {
+            cpu: Some(2.0),
+            memory_mb: Some(1024),
+            processes: None,
+        };
+        let limits = defaults.with_overrides(ResourceLimits {
+            cpu: Some(0.0),
+            memory_mb: Some(512),
+            processes: Some(64),
+        });
+        assert_eq!(
+            limits,
+            ResourceLimits {
+                cpu: None,
+                memory_mb: Some(512),
+                processes: Some(64),
+            }
+        );
+        assert!(ResourceLimits::default()
+            .with_overrides(ResourceLimits::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn prepare_delegates_controllers_and_create_writes_limits() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let root = dir.path();
+        std::fs::write(root.join("cgroup.controllers"), "cpuset cpu io memory pids").unwrap();
+        std::fs::write(root.join("cgroup.subtree_control"), "").unwrap();
+
+        let cgroups = Cgroups::prepare(root.to_path_buf(), 42).expect("prepare");
+        assert_eq!(
+            std::fs::read_to_string(root.join(DAEMON_CGROUP).join("cgroup.procs")).unwrap(),
+            "42"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("cgroup.subtree_control")).unwrap(),
+            "+cpu +memory +pids"
+        );
+
+        let limits = ResourceLimits {
+            cpu: Some(0.5),
+            memory_mb: Some(256),
+            processes: Some(32),
+        };
+        let cgroup = cgroups.create("main/server 1", &limits).expect("create");
+        let name = cgroup.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("agent-main_server_1-"), "{name}");
+        let read = |file: &str| std::fs::read_to_string(cgroup.join(file)).unwrap();
+        assert_eq!(read("cpu.max"), "50000 100000");
+        assert_eq!(read("memory.max"), (256 * 1024 * 1024).to_string());
+        assert_eq!(read("pids.max"), "32");
+    }
+
+    #[test]
+    fn prepare_rejects_hierarchies_without_every_controller() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(dir.path().join("cgroup.controllers"), "cpu pids").unwrap();
+        std::fs::write(dir.path().join("cgroup.subtree_control"), "").unwrap();
+        let err = Cgroups::prepare(dir.path().to_path_buf(), 1).expect_err("memory is missing");
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn new_oom_kills_and_refused_forks_are_reported() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(
+            dir.path().join("memory.events"),
+            "low 0\nhigh 0\nmax 12\noom 2\noom_kill 1\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("pids.events"), "max 3\n").unwrap();
+        let events = LimitEvents::read(dir.path());
+        assert_eq!(
+            events,
+            LimitEvents {
+                oom_kills: 1,
+                refused_forks: 3
+            }
+        );
+
+        let limits = ResourceLimits {
+            cpu: None,
+            memory_mb: Some(256),
+            processes: Some(8),
+        };
+        let reported = violations(LimitEvents::default(), events, &limits);
+        assert_eq!(reported.len(), 2);
+        assert_eq!(reported[0]["params"]["code"], LIMIT_EXCEEDED_CODE);
+        assert_eq!(reported[0]["params"]["details"]["resource"], "memory");
+        assert_eq!(reported[1]["params"]["details"]["count"], 3);
+        assert!(violations(events, events, &limits).is_empty());
+    }
+}
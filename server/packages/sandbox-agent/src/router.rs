@@ -1,18 +1,22 @@
 use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::io::Cursor;
+use std::ops::RangeInclusive;
 use std::path::{Path as StdPath, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use axum::body::Bytes;
-use axum::extract::{Path, Query, State};
-use axum::http::{header, HeaderMap, Request, StatusCode};
+use acp_http_adapter::process::RESYNC_EVENT;
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, OriginalUri, Path, Query, State, WebSocketUpgrade};
+use axum::http::{header, HeaderMap, HeaderValue, Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::sse::KeepAlive;
 use axum::response::{IntoResponse, Response, Sse};
-use axum::routing::{delete, get, post};
-use axum::{Json, Router};
+use axum::routing::{any, delete, get, post, put};
+use axum::{Extension, Json, Router};
+use futures::stream::{self, Stream, StreamExt};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use sandbox_agent_agent_management::agents::{
     AgentId, AgentManager, InstallOptions, InstallResult, InstallSource, InstalledArtifactKind,
 };
@@ -21,26 +25,74 @@ use sandbox_agent_agent_management::credentials::{
 };
 use sandbox_agent_error::{ErrorType, ProblemDetails, SandboxError};
 use sandbox_agent_opencode_adapter::{build_opencode_router, OpenCodeAdapterConfig};
-use sandbox_agent_opencode_server_manager::{OpenCodeServerManager, OpenCodeServerManagerConfig};
-use schemars::JsonSchema;
+use sandbox_agent_opencode_server_manager::{
+    OpenCodeServerManager, OpenCodeServerManagerConfig, OpenCodeServerPool, OpenCodeServerStatus,
+    RestartPolicy,
+};
 use serde::de::DeserializeOwned;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use serde_json::{json, Value};
 use tar::Archive;
 use tower_http::trace::TraceLayer;
 use tracing::Span;
-use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa::{Modify, OpenApi};
 
-use crate::acp_proxy_runtime::{AcpProxyRuntime, ProxyPostOutcome};
+use crate::acp_fanout::{start_fanout, Fanout, FanoutRunSpec};
+use crate::acp_proxy_runtime::{
+    AcpBootstrap, AcpFirehoseEvent, AcpProxyRuntime, AcpServerInstanceInfo, AcpServerMetadata,
+    ProxyPostOutcome, MAX_SERVER_LABELS,
+};
+use crate::acp_runs::{next_run_server_id, run_prompt, RunReport, RunSpec};
+use crate::acp_usage::AcpUsage;
+use crate::agent_capabilities::ResolvedCapabilities;
+use crate::agent_sandbox::SandboxLevel;
+use crate::ai_sdk::{prompt_from_messages, prompt_stream, UI_MESSAGE_STREAM_HEADER};
+use crate::approvals;
+use crate::audit::{audit_entry, default_audit_log_path, AuditLog};
+use crate::auth_tokens::{required_scope, ApiToken, RequestActor, TokenScope};
+use crate::capabilities::{Feature, CAPABILITIES_SCHEMA_VERSION};
+use crate::chat_completions::{completion, completion_stream, prompt_from_chat, ChatDefaults};
+use crate::checkpoints::{default_checkpoint_dir, CheckpointStore};
+use crate::codex_config::{codex_config_path, read_codex_config, write_codex_config};
+use crate::debug_logs::LOG_BUFFER_CAPACITY;
+use crate::egress;
+use crate::event_filter::EventFilter;
+use crate::event_sink::{EventSink, EventSinkConfig, EventSinkTarget};
+use crate::exec::{
+    ExecManager, ExecSpec, EXEC_DEFAULT_MAX_OUTPUT_BYTES, EXEC_DEFAULT_TIMEOUT, EXEC_MAX_TIMEOUT,
+};
+use crate::findings;
+use crate::firehose::FirehoseItem;
+use crate::fs_upload::FsUploadManager;
+use crate::git_sync::{self, ProgressParser};
+use crate::mcp_server;
+use crate::model_catalog::ModelCatalog;
+use crate::plans::PlanState;
+use crate::port_proxy::{bridge_websocket, connect_websocket, PortProxy};
+use crate::request_limits::{self, RateLimiter, RequestLimits};
+use crate::resource_limits::ResourceLimits;
+use crate::scm_github;
+use crate::skills::SkillStore;
+use crate::transcript;
 use crate::ui;
+use crate::workspaces::{default_workspaces_dir, WorkspaceRegistry};
 
 mod support;
-mod types;
 use self::support::*;
-pub use self::types::*;
+pub(crate) use self::support::{acp_server_info, map_fs_error, resolve_scoped_fs_path};
+pub use sandbox_agent_api_types::*;
 
 const APPLICATION_JSON: &str = "application/json";
 const TEXT_EVENT_STREAM: &str = "text/event-stream";
+const APPLICATION_NDJSON: &str = "application/x-ndjson";
+/// Sequence of the first event an `events.jsonl` export holds.
+const FIRST_SEQUENCE_HEADER: &str = "x-sandbox-agent-first-sequence";
+/// Set on an `events.jsonl` export that starts later than requested, because older events
+/// had already left the buffer.
+const TRUNCATED_HEADER: &str = "x-sandbox-agent-truncated";
+const DEBUG_LOGS_DEFAULT_LIMIT: usize = 500;
+const UNPARSED_DEFAULT_LIMIT: usize = 50;
+const RATE_LIMITS_DEFAULT_LIMIT: usize = 50;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum BrandingMode {
@@ -80,6 +132,21 @@ pub struct AppState {
     pub(crate) branding: BrandingMode,
     version_cache: Mutex<HashMap<AgentId, CachedAgentVersion>>,
     pub(crate) terminal_manager: Arc<crate::terminal::TerminalManager>,
+    fs_roots: Vec<PathBuf>,
+    fs_uploads: FsUploadManager,
+    checkpoints: CheckpointStore,
+    audit: Arc<AuditLog>,
+    exec: ExecManager,
+    port_proxy: PortProxy,
+    request_limits: RequestLimits,
+    rate_limiter: Option<RateLimiter>,
+    started_at: Instant,
+    readiness: crate::health::Readiness,
+    model_catalog: Arc<ModelCatalog>,
+    skills: SkillStore,
+    workspaces: WorkspaceRegistry,
+    chat_defaults: ChatDefaults,
+    event_sink: EventSinkConfig,
 }
 
 impl AppState {
@@ -99,6 +166,9 @@ impl AppState {
             OpenCodeServerManagerConfig {
                 log_dir: default_opencode_server_log_dir(),
                 auto_restart: true,
+                command_prefix: acp_proxy.managed_server_limit_prefix(),
+                pool: OpenCodeServerPool::from_env(),
+                restart: RestartPolicy::from_env(),
             },
         ));
         Self {
@@ -109,13 +179,138 @@ impl AppState {
             branding,
             version_cache: Mutex::new(HashMap::new()),
             terminal_manager: Arc::new(crate::terminal::TerminalManager::new()),
+            fs_roots: Vec::new(),
+            fs_uploads: FsUploadManager::new(),
+            checkpoints: CheckpointStore::new(default_checkpoint_dir()),
+            audit: Arc::new(AuditLog::new(default_audit_log_path())),
+            exec: ExecManager::new(),
+            port_proxy: PortProxy::new(Vec::new()),
+            request_limits: RequestLimits::default(),
+            rate_limiter: None,
+            started_at: Instant::now(),
+            readiness: crate::health::Readiness::default(),
+            model_catalog: Arc::new(ModelCatalog::from_env()),
+            skills: SkillStore::from_env(),
+            workspaces: WorkspaceRegistry::new(default_workspaces_dir()),
+            chat_defaults: ChatDefaults::from_env(),
+            event_sink: EventSinkConfig::from_env(),
         }
     }
 
+    /// Confine /v1/fs, ACP server directories, and config directories to `roots`.
+    /// Relative paths resolve against the first root. An empty list leaves paths unrestricted.
+    pub fn with_fs_roots(mut self, roots: Vec<PathBuf>) -> Self {
+        self.fs_roots = roots
+            .into_iter()
+            .map(|root| fs::canonicalize(&root).unwrap_or(root))
+            .collect();
+        self
+    }
+
+    /// Store workspace checkpoints under `dir` instead of the user data directory.
+    pub fn with_checkpoint_dir(mut self, dir: PathBuf) -> Self {
+        self.checkpoints = CheckpointStore::new(dir);
+        self
+    }
+
+    /// Append the audit log to this JSONL file instead of the user data directory.
+    pub fn with_audit_log(mut self, path: PathBuf) -> Self {
+        self.audit = Arc::new(AuditLog::new(path));
+        self
+    }
+
+    /// Cache fetched skill sources in this directory instead of the user data directory.
+    pub fn with_skills_cache_dir(mut self, dir: PathBuf) -> Self {
+        self.skills = self.skills.with_cache_dir(dir);
+        self
+    }
+
+    /// Keep the workspace registry and cloned workspaces in this directory instead of the
+    /// user data directory.
+    pub fn with_workspaces_dir(mut self, dir: PathBuf) -> Self {
+        self.workspaces = WorkspaceRegistry::new(dir);
+        self
+    }
+
+    /// Answer `/v1/chat/completions` requests whose `model` names no agent with this agent.
+    pub fn with_chat_agent(mut self, agent: AgentId) -> Self {
+        self.chat_defaults = self.chat_defaults.with_agent(agent);
+        self
+    }
+
+    /// Model for `/v1/chat/completions` requests answered by the default chat agent.
+    pub fn with_chat_model(mut self, model: String) -> Self {
+        self.chat_defaults = self.chat_defaults.with_model(model);
+        self
+    }
+
+    /// Publish every ACP server event to this Kafka or NATS broker.
+    pub fn with_event_sink(mut self, target: EventSinkTarget) -> Self {
+        self.event_sink = self.event_sink.with_target(target);
+        self
+    }
+
+    /// Kafka topic or NATS subject for published events; may use `{agent}` and `{serverId}`.
+    pub fn with_event_sink_topic(mut self, topic: String) -> Self {
+        self.event_sink = self.event_sink.with_topic(topic);
+        self
+    }
+
+    /// Keep unpublished events in this directory instead of the user data directory.
+    pub fn with_event_sink_outbox(mut self, dir: PathBuf) -> Self {
+        self.event_sink = self.event_sink.with_outbox_dir(dir);
+        self
+    }
+
+    /// Allow `/v1/proxy/{port}` to reach these ports on 127.0.0.1. An empty list disables the proxy.
+    pub fn with_proxy_ports(mut self, ports: Vec<RangeInclusive<u16>>) -> Self {
+        self.port_proxy = PortProxy::new(ports);
+        self
+    }
+
+    /// Rate limit requests per token and cap request body sizes on `/v1`.
+    pub fn with_request_limits(mut self, limits: RequestLimits) -> Self {
+        self.rate_limiter = limits.rate_limit_per_minute.map(RateLimiter::new);
+        self.request_limits = limits;
+        self
+    }
+
+    pub(crate) fn fs_roots(&self) -> &[PathBuf] {
+        &self.fs_roots
+    }
+
+    pub(crate) fn fs_uploads(&self) -> &FsUploadManager {
+        &self.fs_uploads
+    }
+
+    pub(crate) fn checkpoints(&self) -> &CheckpointStore {
+        &self.checkpoints
+    }
+
+    pub(crate) fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub(crate) fn exec(&self) -> &ExecManager {
+        &self.exec
+    }
+
+    pub(crate) fn port_proxy(&self) -> &PortProxy {
+        &self.port_proxy
+    }
+
     pub(crate) fn acp_proxy(&self) -> Arc<AcpProxyRuntime> {
         self.acp_proxy.clone()
     }
 
+    pub(crate) fn request_limits(&self) -> &RequestLimits {
+        &self.request_limits
+    }
+
+    pub(crate) fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
     pub(crate) fn auth(&self) -> &AuthConfig {
         &self.auth
     }
@@ -128,6 +323,35 @@ impl AppState {
         self.opencode_server_manager.clone()
     }
 
+    pub(crate) fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub(crate) fn model_catalog(&self) -> &Arc<ModelCatalog> {
+        &self.model_catalog
+    }
+
+    pub(crate) fn skills(&self) -> &SkillStore {
+        &self.skills
+    }
+
+    pub(crate) fn workspaces(&self) -> &WorkspaceRegistry {
+        &self.workspaces
+    }
+
+    pub(crate) fn chat_defaults(&self) -> &ChatDefaults {
+        &self.chat_defaults
+    }
+
+    pub(crate) fn readiness(&self) -> &crate::health::Readiness {
+        &self.readiness
+    }
+
+    /// Report the daemon as serving on `/v1/ready`, once its listener accepts connections.
+    pub fn mark_serving(&self) {
+        self.readiness.mark_serving();
+    }
+
     pub(crate) fn purge_version_cache(&self, agent: AgentId) {
         self.version_cache.lock().unwrap().remove(&agent);
     }
@@ -142,16 +366,65 @@ fn default_opencode_server_log_dir() -> PathBuf {
 
 #[derive(Debug, Clone)]
 pub struct AuthConfig {
+    /// Token with full access, from `--token`.
     pub token: Option<String>,
+    /// Named tokens limited to their scopes.
+    pub tokens: Vec<ApiToken>,
 }
 
 impl AuthConfig {
     pub fn disabled() -> Self {
-        Self { token: None }
+        Self {
+            token: None,
+            tokens: Vec::new(),
+        }
     }
 
     pub fn with_token(token: String) -> Self {
-        Self { token: Some(token) }
+        Self {
+            token: Some(token),
+            tokens: Vec::new(),
+        }
+    }
+
+    pub fn with_tokens(mut self, tokens: Vec<ApiToken>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.token.is_some() || !self.tokens.is_empty()
+    }
+
+    /// Check `bearer` against the configured tokens for a request that needs `required`.
+    /// Returns the named token that matched, or `None` for the `--token` token.
+    pub(crate) fn authorize(
+        &self,
+        bearer: Option<&str>,
+        required: TokenScope,
+    ) -> Result<Option<&ApiToken>, SandboxError> {
+        let Some(bearer) = bearer else {
+            return Err(SandboxError::TokenInvalid {
+                message: Some("missing or invalid bearer token".to_string()),
+            });
+        };
+        if self.token.as_deref() == Some(bearer) {
+            return Ok(None);
+        }
+        let Some(token) = self.tokens.iter().find(|token| token.token == bearer) else {
+            return Err(SandboxError::TokenInvalid {
+                message: Some("missing or invalid bearer token".to_string()),
+            });
+        };
+        if token.allows(required) {
+            return Ok(Some(token));
+        }
+        Err(SandboxError::PermissionDenied {
+            message: Some(format!(
+                "token '{}' does not have the {required} scope",
+                token.name
+            )),
+        })
     }
 }
 
@@ -160,57 +433,254 @@ pub fn build_router(state: AppState) -> Router {
 }
 
 pub fn build_router_with_state(shared: Arc<AppState>) -> (Router, Arc<AppState>) {
+    shared.acp_proxy().set_audit_log(shared.audit.clone());
     let mut v1_router = Router::new()
         .route("/health", get(get_v1_health))
+        .route("/ready", get(get_v1_ready))
+        .route("/capabilities", get(get_v1_capabilities))
+        .route("/openapi.json", get(get_v1_openapi))
+        .route("/asyncapi.json", get(get_v1_asyncapi))
+        .route("/docs", get(get_v1_docs))
         .route("/agents", get(get_v1_agents))
-        .route("/agents/:agent", get(get_v1_agent))
+        .route("/agents/:agent", get(get_v1_agent).delete(delete_v1_agent))
         .route("/agents/:agent/install", post(post_v1_agent_install))
+        .route(
+            "/agents/:agent/models/refresh",
+            post(post_v1_agent_models_refresh),
+        )
+        .route(
+            "/agents/:agent/capabilities/refresh",
+            post(post_v1_agent_capabilities_refresh),
+        )
+        .route(
+            "/agents/:agent/server/start",
+            post(post_v1_agent_server_start),
+        )
+        .route(
+            "/agents/:agent/server/stop",
+            post(post_v1_agent_server_stop),
+        )
+        .route(
+            "/agents/:agent/server/restart",
+            post(post_v1_agent_server_restart),
+        )
+        .route("/models", get(get_v1_models))
+        .route(
+            "/agents/:agent/config",
+            get(get_v1_agent_config)
+                .put(put_v1_agent_config)
+                .patch(patch_v1_agent_config),
+        )
         .route("/fs/entries", get(get_v1_fs_entries))
         .route("/fs/file", get(get_v1_fs_file).put(put_v1_fs_file))
         .route("/fs/entry", delete(delete_v1_fs_entry))
         .route("/fs/mkdir", post(post_v1_fs_mkdir))
         .route("/fs/move", post(post_v1_fs_move))
         .route("/fs/stat", get(get_v1_fs_stat))
+        .route("/fs/search", get(get_v1_fs_search))
+        .route("/fs/archive", get(get_v1_fs_archive))
+        .route("/fs/watch", get(get_v1_fs_watch))
         .route("/fs/upload-batch", post(post_v1_fs_upload_batch))
+        .route("/fs/upload/init", post(post_v1_fs_upload_init))
+        .route(
+            "/fs/upload/:upload_id",
+            get(get_v1_fs_upload).delete(delete_v1_fs_upload),
+        )
+        .route("/fs/upload/:upload_id/chunk", put(put_v1_fs_upload_chunk))
+        .route(
+            "/fs/upload/:upload_id/complete",
+            post(post_v1_fs_upload_complete),
+        )
+        .route("/exec", post(post_v1_exec))
+        .route("/exec/:exec_id/kill", post(post_v1_exec_kill))
+        .route("/git/push", post(post_v1_git_push))
+        .route("/git/pull", post(post_v1_git_pull))
+        .route("/scm/github/clone", post(post_v1_scm_github_clone))
+        .route("/debug/logs", get(get_v1_debug_logs))
+        .route("/debug/unparsed", get(get_v1_debug_unparsed))
+        .route("/debug/rate-limits", get(get_v1_debug_rate_limits))
+        .route("/mcp", post(post_v1_mcp))
+        .route("/proxy/:port", any(proxy_v1_port))
+        .route("/proxy/:port/", any(proxy_v1_port))
+        .route("/proxy/:port/*path", any(proxy_v1_port))
         .route(
             "/config/mcp",
             get(get_v1_config_mcp)
                 .put(put_v1_config_mcp)
                 .delete(delete_v1_config_mcp),
         )
+        .route(
+            "/config/mcp/oauth",
+            get(get_v1_config_mcp_oauth)
+                .post(post_v1_config_mcp_oauth)
+                .delete(delete_v1_config_mcp_oauth),
+        )
         .route(
             "/config/skills",
             get(get_v1_config_skills)
                 .put(put_v1_config_skills)
                 .delete(delete_v1_config_skills),
         )
+        .route("/skills", get(get_v1_skills))
+        .route("/skills/install", post(post_v1_skills_install))
+        .route("/skills/:name", delete(delete_v1_skill))
+        .route("/instructions/templates", get(get_v1_instruction_templates))
+        .route(
+            "/instructions/templates/:name",
+            get(get_v1_instruction_template)
+                .put(put_v1_instruction_template)
+                .delete(delete_v1_instruction_template),
+        )
+        .route(
+            "/workspaces",
+            get(get_v1_workspaces).post(post_v1_workspaces),
+        )
+        .route(
+            "/workspaces/:id",
+            get(get_v1_workspace).delete(delete_v1_workspace),
+        )
         .route("/acp", get(get_v1_acp_servers))
         .route(
             "/acp/:server_id",
-            post(post_v1_acp).get(get_v1_acp).delete(delete_v1_acp),
+            post(post_v1_acp)
+                .get(get_v1_acp)
+                .patch(patch_v1_acp)
+                .delete(delete_v1_acp),
+        )
+        .route("/acp/:server_id/events.jsonl", get(get_v1_acp_events_jsonl))
+        .route(
+            "/acp/:server_id/events/ndjson",
+            get(get_v1_acp_events_ndjson),
+        )
+        .route("/acp/:server_id/fork", post(post_v1_acp_fork))
+        .route("/acp/:server_id/replay", post(post_v1_acp_replay))
+        .route("/acp/:server_id/usage", get(get_v1_acp_usage))
+        .route("/acp/:server_id/turns", get(get_v1_acp_turns))
+        .route(
+            "/acp/:server_id/turns/:turn_id/diff",
+            get(get_v1_acp_turn_diff),
+        )
+        .route(
+            "/acp/:server_id/checkpoints",
+            post(post_v1_acp_checkpoint).get(get_v1_acp_checkpoints),
+        )
+        .route(
+            "/acp/:server_id/checkpoints/:checkpoint_id/restore",
+            post(post_v1_acp_checkpoint_restore),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/tasks",
+            get(get_v1_acp_session_tasks),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/messages/ai-sdk",
+            post(post_v1_acp_session_ai_sdk),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/items",
+            get(get_v1_acp_session_items),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/export",
+            get(get_v1_acp_session_export),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/scm/github/pr",
+            post(post_v1_acp_github_pr),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/items/:item_id",
+            get(get_v1_acp_session_item),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/items/:item_id/children",
+            get(get_v1_acp_item_children),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/turns",
+            get(get_v1_acp_session_turns),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/turns/:turn_id",
+            get(get_v1_acp_session_turn),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/plan/approve",
+            post(post_v1_acp_plan_approve),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/plan/reject",
+            post(post_v1_acp_plan_reject),
+        )
+        .route(
+            "/acp/:server_id/sessions/:session_id/compact",
+            post(post_v1_acp_session_compact),
+        )
+        .route("/approvals", get(get_v1_approvals))
+        .route("/approvals/callback", post(post_v1_approvals_callback))
+        .route("/approvals/reply", post(post_v1_approvals_reply))
+        .route("/audit", get(get_v1_audit))
+        .route("/blobs/:blob_id", get(get_v1_blob))
+        .route("/events/sse", get(get_v1_events_sse))
+        .route("/fanout", post(post_v1_fanout))
+        .route(
+            "/fanout/:fanout_id",
+            get(get_v1_fanout).delete(delete_v1_fanout),
         )
-        .with_state(shared.clone());
+        .route("/fanout/:fanout_id/events", get(get_v1_fanout_events))
+        .route("/runs", post(post_v1_runs))
+        .route("/chat/completions", post(post_v1_chat_completions))
+        .with_state(shared.clone())
+        // Body sizes are enforced by `limit_body` instead of axum's fixed 2 MiB default.
+        .layer(DefaultBodyLimit::disable())
+        .layer(axum::middleware::from_fn_with_state(
+            shared.clone(),
+            request_limits::limit_body,
+        ));
+
+    if shared.rate_limiter().is_some() {
+        v1_router = v1_router.layer(axum::middleware::from_fn_with_state(
+            shared.clone(),
+            request_limits::rate_limit,
+        ));
+    }
 
-    if shared.auth.token.is_some() {
+    if shared.auth.is_enabled() {
         v1_router = v1_router.layer(axum::middleware::from_fn_with_state(
             shared.clone(),
             require_token,
         ));
     }
 
-    let opencode_router = build_opencode_router(OpenCodeAdapterConfig {
-        auth_token: shared.auth.token.clone(),
+    // The OpenCode adapter only knows a single token; with named tokens it is left open
+    // and guarded by the same scoped check as /v1.
+    let scoped_tokens = !shared.auth.tokens.is_empty();
+    let mut opencode_router = build_opencode_router(OpenCodeAdapterConfig {
+        auth_token: if scoped_tokens {
+            None
+        } else {
+            shared.auth.token.clone()
+        },
         sqlite_path: std::env::var("OPENCODE_COMPAT_DB_PATH").ok(),
         native_proxy_base_url: std::env::var("OPENCODE_COMPAT_PROXY_URL").ok(),
         native_proxy_manager: Some(shared.opencode_server_manager()),
         acp_dispatch: Some(shared.acp_proxy() as Arc<dyn sandbox_agent_opencode_adapter::AcpDispatch>),
         provider_payload: Some(build_provider_payload_for_opencode(&shared)),
+        #[cfg(feature = "test-utils")]
+        proxy_fault: crate::chaos::configured()
+            .map(|chaos| chaos.proxy_fault(shared.opencode_server_manager())),
         ..OpenCodeAdapterConfig::default()
     })
     .unwrap_or_else(|err| {
         tracing::error!(error = %err, "failed to initialize opencode adapter router; using fallback");
         Router::new().fallback(opencode_unavailable)
     });
+    if scoped_tokens {
+        opencode_router = opencode_router.layer(axum::middleware::from_fn_with_state(
+            shared.clone(),
+            require_token,
+        ));
+    }
 
     let mut router = Router::new()
         .route("/", get(get_root))
@@ -281,8 +751,39 @@ async fn opencode_unavailable() -> Response {
         .into_response()
 }
 
+/// Load what earlier runs left on disk, then report persistence as loaded on `/v1/ready`.
+pub async fn load_persisted_state(state: &Arc<AppState>) {
+    let archive = state.acp_proxy().archive().clone();
+    match tokio::task::spawn_blocking(move || archive.load()).await {
+        Ok(archived) => tracing::info!(archived = archived, "loaded persisted state"),
+        Err(err) => tracing::warn!(error = %err, "failed to load persisted state"),
+    }
+    state.readiness().mark_persistence_loaded();
+}
+
+/// Keep live model lists current in the background; see `SANDBOX_AGENT_MODEL_CATALOG_TTL_SECS`.
+pub fn start_model_catalog_refresh(state: &Arc<AppState>) {
+    state
+        .model_catalog()
+        .spawn_refresh(state.opencode_server_manager());
+}
+
+/// Start publishing ACP events when an event sink is configured.
+pub fn start_event_sink(state: &Arc<AppState>) -> std::io::Result<()> {
+    if let Some(sink) = EventSink::start(&state.event_sink)? {
+        state.acp_proxy().set_event_sink(sink);
+    }
+    Ok(())
+}
+
+/// Pre-spawn idle agent processes configured via `SANDBOX_AGENT_ACP_WARM_POOL`.
+pub async fn prewarm_servers(state: &Arc<AppState>) {
+    state.acp_proxy().fill_warm_pool().await;
+}
+
 pub async fn shutdown_servers(state: &Arc<AppState>) {
     state.acp_proxy().shutdown_all().await;
+    state.exec().shutdown();
     state.opencode_server_manager().shutdown().await;
 }
 
@@ -290,9 +791,21 @@ pub async fn shutdown_servers(state: &Arc<AppState>) {
 #[openapi(
     paths(
         get_v1_health,
+        get_v1_ready,
+        get_v1_capabilities,
         get_v1_agents,
         get_v1_agent,
         post_v1_agent_install,
+        post_v1_agent_models_refresh,
+        post_v1_agent_capabilities_refresh,
+        post_v1_agent_server_start,
+        post_v1_agent_server_stop,
+        post_v1_agent_server_restart,
+        get_v1_models,
+        get_v1_agent_config,
+        put_v1_agent_config,
+        patch_v1_agent_config,
+        delete_v1_agent,
         get_v1_fs_entries,
         get_v1_fs_file,
         put_v1_fs_file,
@@ -300,31 +813,130 @@ pub async fn shutdown_servers(state: &Arc<AppState>) {
         post_v1_fs_mkdir,
         post_v1_fs_move,
         get_v1_fs_stat,
+        get_v1_fs_search,
+        get_v1_fs_archive,
+        get_v1_fs_watch,
         post_v1_fs_upload_batch,
+        post_v1_fs_upload_init,
+        get_v1_fs_upload,
+        put_v1_fs_upload_chunk,
+        post_v1_fs_upload_complete,
+        delete_v1_fs_upload,
+        post_v1_exec,
+        post_v1_exec_kill,
+        post_v1_git_push,
+        post_v1_git_pull,
+        post_v1_scm_github_clone,
+        proxy_v1_port,
+        get_v1_debug_logs,
+        get_v1_debug_unparsed,
+        get_v1_debug_rate_limits,
+        post_v1_mcp,
         get_v1_config_mcp,
         put_v1_config_mcp,
         delete_v1_config_mcp,
+        get_v1_config_mcp_oauth,
+        post_v1_config_mcp_oauth,
+        delete_v1_config_mcp_oauth,
         get_v1_config_skills,
         put_v1_config_skills,
         delete_v1_config_skills,
+        get_v1_skills,
+        post_v1_skills_install,
+        delete_v1_skill,
+        get_v1_instruction_templates,
+        get_v1_instruction_template,
+        put_v1_instruction_template,
+        delete_v1_instruction_template,
+        get_v1_workspaces,
+        post_v1_workspaces,
+        get_v1_workspace,
+        delete_v1_workspace,
         get_v1_acp_servers,
         post_v1_acp,
         get_v1_acp,
-        delete_v1_acp
+        patch_v1_acp,
+        get_v1_acp_events_jsonl,
+        get_v1_acp_events_ndjson,
+        post_v1_acp_fork,
+        post_v1_acp_replay,
+        get_v1_acp_usage,
+        get_v1_acp_turns,
+        get_v1_acp_turn_diff,
+        post_v1_acp_checkpoint,
+        get_v1_acp_checkpoints,
+        post_v1_acp_checkpoint_restore,
+        get_v1_acp_session_tasks,
+        post_v1_acp_session_ai_sdk,
+        get_v1_acp_session_items,
+        get_v1_acp_session_item,
+        get_v1_acp_session_turns,
+        get_v1_acp_session_turn,
+        get_v1_acp_session_export,
+        post_v1_acp_github_pr,
+        get_v1_acp_item_children,
+        post_v1_acp_plan_approve,
+        post_v1_acp_plan_reject,
+        post_v1_acp_session_compact,
+        delete_v1_acp,
+        get_v1_approvals,
+        post_v1_approvals_callback,
+        post_v1_approvals_reply,
+        get_v1_audit,
+        get_v1_blob,
+        get_v1_events_sse,
+        post_v1_fanout,
+        get_v1_fanout,
+        get_v1_fanout_events,
+        delete_v1_fanout,
+        post_v1_runs,
+        post_v1_chat_completions
     ),
     components(
         schemas(
             HealthResponse,
+            ReadinessResponse,
+            HealthStatus,
+            HealthComponents,
+            AcpOccupancy,
+            AgentOccupancy,
+            ManagedServerHealth,
+            AgentProcessHealth,
+            CredentialHealth,
+            DiskHealth,
+            CapabilitiesResponse,
+            BuildInfo,
+            FeatureInfo,
+            CapabilityLimits,
             ServerStatus,
             ServerStatusInfo,
+            ServerInstanceStatus,
             AgentCapabilities,
+            AgentCapabilitiesSource,
+            AgentCapabilitiesResponse,
             AgentInfo,
             AgentListResponse,
             AgentInstallRequest,
+            CodexProfile,
+            CodexModelProvider,
+            CodexConfig,
+            AgentConfig,
             AgentInstallArtifact,
             AgentInstallResponse,
+            ModelCatalogSource,
+            AgentModel,
+            AgentModelsResponse,
+            CatalogModel,
+            ModelListResponse,
+            AgentUninstallResponse,
             FsPathQuery,
             FsEntriesQuery,
+            FsSearchQuery,
+            FsArchiveQuery,
+            FsArchiveFormat,
+            FsWatchQuery,
+            FsWatchEvent,
+            FsSearchEvent,
             FsDeleteQuery,
             FsUploadBatchQuery,
             FsEntryType,
@@ -335,14 +947,127 @@ pub async fn shutdown_servers(state: &Arc<AppState>) {
             FsMoveResponse,
             FsActionResponse,
             FsUploadBatchResponse,
+            FsUploadInitRequest,
+            FsUploadStatus,
+            FsUploadChunkQuery,
+            FsUploadCompleteResponse,
+            ExecRequest,
+            DebugLogsQuery,
+            DebugLogRecord,
+            DebugLogsResponse,
+            UnparsedEventsQuery,
+            UnparsedEvent,
+            UnparsedAgentCount,
+            UnparsedServerCount,
+            UnparsedEventsResponse,
+            RateLimitEventsQuery,
+            RateLimitKind,
+            RateLimitEvent,
+            RateLimitAgentCount,
+            RateLimitServerCount,
+            RateLimitEventsResponse,
+            ExecEvent,
+            GitOperation,
+            GitCredentials,
+            GitSyncRequest,
+            GitEvent,
+            GithubCloneRequest,
+            GithubCloneResponse,
+            GithubPullRequestRequest,
+            GithubPullRequestResponse,
             AcpPostQuery,
+            AcpEventsExportQuery,
+            AcpEventsStreamQuery,
+            AcpEventsSseQuery,
+            AcpUsageInfo,
+            AcpUsageCostInfo,
+            AcpContextWindowInfo,
+            AcpCompactResponse,
+            AcpForkRequest,
+            AcpReplayRequest,
+            AcpReplayResponse,
+            AcpFanoutRequest,
+            AcpFanoutRunRequest,
+            AcpFanoutRunStatus,
+            AcpFanoutInfo,
+            AcpFanoutRunInfo,
+            AcpRunRequest,
+            AcpAiSdkChatRequest,
+            ChatCompletionRequest,
+            ChatCompletionStreamOptions,
+            ChatCompletionResponse,
+            ChatCompletionChoice,
+            ChatCompletionMessage,
+            ChatCompletionUsage,
+            AcpRunStatus,
+            AcpRunDiff,
+            AcpStructuredOutput,
+            AcpRunResponse,
+            AcpForkResponse,
+            AcpTurnFileChangeKind,
+            AcpTurnFileChange,
+            AcpTurnInfo,
+            AcpTurnListResponse,
+            AcpCheckpointRequest,
+            AcpCheckpointInfo,
+            AcpCheckpointListResponse,
+            AcpCheckpointRestoreResponse,
+            AcpTaskStatus,
+            AcpTask,
+            AcpTaskListResponse,
+            AcpItemKind,
+            AcpItemStatus,
+            AcpItem,
+            AcpItemListResponse,
+            AcpSessionTurnStatus,
+            AcpSessionTurn,
+            AcpSessionTurnListResponse,
+            AcpTranscriptFormat,
+            AcpTranscriptExportQuery,
+            AcpChildItem,
+            AcpItemChildrenResponse,
+            AcpPlanDecisionRequest,
+            ApprovalKind,
+            ApprovalInfo,
+            ApprovalListResponse,
+            ApprovalDecision,
+            ApprovalReply,
+            ApprovalCallbackRequest,
+            ApprovalReplyRequest,
+            ApprovalReplyResult,
+            ApprovalReplyResponse,
+            AuditKind,
+            AuditEntry,
+            AuditQuery,
+            AuditLogResponse,
+            AcpFirehoseQuery,
+            AcpFirehoseEventData,
             AcpServerInfo,
+            AcpServerListQuery,
             AcpServerListResponse,
+            AcpServerSort,
+            SortOrder,
+            AcpServerUpdateRequest,
+            AcpWarmPoolInfo,
             McpConfigQuery,
             SkillsConfigQuery,
             McpServerConfig,
+            McpOAuthConfigOrDisabled,
+            McpOAuthConfig,
+            McpOAuthFlow,
+            McpOAuthState,
+            McpOAuthStatus,
             SkillsConfig,
             SkillSource,
+            InstalledSkill,
+            SkillListResponse,
+            SkillInstallResponse,
+            InstructionTemplate,
+            InstructionTemplateListResponse,
+            InstructionTemplateRequest,
+            WorkspaceCreateRequest,
+            WorkspaceInfo,
+            WorkspaceListResponse,
             ProblemDetails,
             ErrorType,
             AcpEnvelope
@@ -376,12 +1101,19 @@ impl IntoResponse for ApiError {
         };
         let status =
             StatusCode::from_u16(problem.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-        (
+        let mut response = (
             status,
             [(header::CONTENT_TYPE, "application/problem+json")],
             Json(problem),
         )
-            .into_response()
+            .into_response();
+        let ApiError::Sandbox(error) = &self;
+        if let Some(secs) = error.retry_after_secs() {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
     }
 }
 
@@ -392,17 +1124,122 @@ async fn get_root() -> Json<Value> {
     }))
 }
 
+/// OpenAPI document for the v1 HTTP contracts, generated from [`ApiDoc`].
+async fn get_v1_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// AsyncAPI document for the SSE and JSONL event streams.
+async fn get_v1_asyncapi() -> Json<Value> {
+    Json(asyncapi_document())
+}
+
+/// Swagger UI over `/v1/openapi.json`. `required_scope` gates it behind the admin
+/// scope; the specs themselves are readable with any token.
+async fn get_v1_docs() -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        SWAGGER_UI_HTML,
+    )
+        .into_response()
+}
+
 #[utoipa::path(
     get,
     path = "/v1/health",
     tag = "v1",
+    params(
+        ("verbose" = Option<bool>, Query, description = "When true, include per-component detail")
+    ),
+    responses(
+        (status = 200, description = "Service health response", body = HealthResponse),
+        (status = 503, description = "A critical component is down", body = HealthResponse)
+    )
+)]
+async fn get_v1_health(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+) -> (StatusCode, Json<HealthResponse>) {
+    let health = crate::health::check(&state, query.verbose.unwrap_or(false)).await;
+    let status = if health.status == HealthStatus::Down {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(health))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/ready",
+    tag = "v1",
+    responses(
+        (status = 200, description = "Ready for traffic", body = ReadinessResponse),
+        (status = 503, description = "Still starting: not serving yet or persisted state not loaded", body = ReadinessResponse)
+    )
+)]
+async fn get_v1_ready(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadinessResponse>) {
+    let readiness = state.readiness().check();
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/capabilities",
+    tag = "v1",
     responses(
-        (status = 200, description = "Service health response", body = HealthResponse)
+        (status = 200, description = "Subsystems, limits and build info supported by this server", body = CapabilitiesResponse)
     )
 )]
-async fn get_v1_health() -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "ok".to_string(),
+async fn get_v1_capabilities(State(state): State<Arc<AppState>>) -> Json<CapabilitiesResponse> {
+    let features = Feature::ALL
+        .iter()
+        .map(|feature| {
+            (
+                feature.name().to_string(),
+                FeatureInfo {
+                    enabled: feature.enabled(&state),
+                    description: feature.description().to_string(),
+                },
+            )
+        })
+        .collect();
+    let resource_limits = state.acp_proxy().resource_limits();
+
+    Json(CapabilitiesResponse {
+        schema_version: CAPABILITIES_SCHEMA_VERSION,
+        build: BuildInfo {
+            version: crate::cli::build_version::VERSION.to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        },
+        features,
+        limits: CapabilityLimits {
+            acp_replay_buffer_events: acp_http_adapter::process::RING_BUFFER_SIZE,
+            acp_request_timeout_ms: state.acp_proxy().request_timeout().as_millis() as u64,
+            acp_idle_timeout_ms: state
+                .acp_proxy()
+                .idle_timeout()
+                .map(|timeout| timeout.as_millis() as u64),
+            acp_cpu_limit: resource_limits.cpu,
+            acp_memory_limit_mb: resource_limits.memory_mb,
+            acp_process_limit: resource_limits.processes,
+            fs_preview_bytes: FS_PREVIEW_READ_BYTES,
+            debug_log_records: LOG_BUFFER_CAPACITY,
+            acp_max_unparsed_per_turn: state.acp_proxy().max_unparsed_per_turn(),
+            acp_turn_timeout_ms: state
+                .acp_proxy()
+                .turn_timeout()
+                .map(|timeout| timeout.as_millis() as u64),
+            rate_limit_per_minute: state.request_limits().rate_limit_per_minute,
+            max_fs_body_bytes: state.request_limits().max_fs_body_bytes,
+            max_message_bytes: state.request_limits().max_message_bytes,
+        },
     })
 }
 
@@ -435,37 +1272,18 @@ async fn get_v1_agents(
     let has_openai = credentials.openai.is_some();
 
     let instances = state.acp_proxy().list_instances().await;
-    let mut active_by_agent = HashMap::<AgentId, Vec<i64>>::new();
-    for instance in instances {
-        active_by_agent
-            .entry(instance.agent)
-            .or_default()
-            .push(instance.created_at_ms);
-    }
+    let opencode = state.opencode_server_manager().status().await;
 
     let load_config = query.config.unwrap_or(false);
     let no_cache = query.no_cache.unwrap_or(false);
 
     let mut agents = Vec::new();
     for agent_id in AgentId::all().iter().copied() {
-        let capabilities = agent_capabilities_for(agent_id);
+        let resolved = resolve_agent_capabilities(&state, agent_id);
         let installed = state.agent_manager().is_installed(agent_id);
         let credentials_available = credentials_available_for(agent_id, has_anthropic, has_openai);
 
-        let server_status = active_by_agent.get(&agent_id).map(|created_times| {
-            let uptime_ms = created_times
-                .iter()
-                .min()
-                .map(|created| now_ms().saturating_sub(*created) as u64);
-            ServerStatusInfo {
-                status: if created_times.is_empty() {
-                    ServerStatus::Stopped
-                } else {
-                    ServerStatus::Running
-                },
-                uptime_ms,
-            }
-        });
+        let server_status = server_status_info(agent_id, &instances, &opencode);
 
         agents.push(AgentInfo {
             id: agent_id.as_str().to_string(),
@@ -473,7 +1291,9 @@ async fn get_v1_agents(
             credentials_available,
             version: None,
             path: None,
-            capabilities,
+            capabilities: resolved.capabilities,
+            capabilities_source: resolved.source,
+            capability_overrides: resolved.overrides,
             server_status,
             config_options: None,
             config_error: None,
@@ -531,7 +1351,8 @@ async fn get_v1_agents(
                 agent.version = cached.version.clone();
                 agent.path = cached.path.clone();
             }
-            let fallback = fallback_config_options(agent_id);
+            let mut fallback = fallback_config_options(agent_id);
+            state.model_catalog().apply(agent_id, &mut fallback);
             if !fallback.is_empty() {
                 agent.config_options = Some(fallback);
             }
@@ -577,28 +1398,13 @@ async fn get_v1_agent(
     let has_openai = credentials.openai.is_some();
 
     let instances = state.acp_proxy().list_instances().await;
-    let created_times: Vec<i64> = instances
-        .iter()
-        .filter(|i| i.agent == agent_id)
-        .map(|i| i.created_at_ms)
-        .collect();
+    let opencode = state.opencode_server_manager().status().await;
 
-    let capabilities = agent_capabilities_for(agent_id);
+    let resolved = resolve_agent_capabilities(&state, agent_id);
     let installed = state.agent_manager().is_installed(agent_id);
     let credentials_available = credentials_available_for(agent_id, has_anthropic, has_openai);
 
-    let server_status = if created_times.is_empty() {
-        None
-    } else {
-        let uptime_ms = created_times
-            .iter()
-            .min()
-            .map(|created| now_ms().saturating_sub(*created) as u64);
-        Some(ServerStatusInfo {
-            status: ServerStatus::Running,
-            uptime_ms,
-        })
-    };
+    let server_status = server_status_info(agent_id, &instances, &opencode);
 
     let mut info = AgentInfo {
         id: agent_id.as_str().to_string(),
@@ -606,7 +1412,9 @@ async fn get_v1_agent(
         credentials_available,
         version: None,
         path: None,
-        capabilities,
+        capabilities: resolved.capabilities,
+        capabilities_source: resolved.source,
+        capability_overrides: resolved.overrides,
         server_status,
         config_options: None,
         config_error: None,
@@ -645,8 +1453,9 @@ async fn get_v1_agent(
             state.version_cache.lock().unwrap().insert(agent_id, result);
         }
 
-        // Hardcoded config options
-        let fallback = fallback_config_options(agent_id);
+        // Hardcoded config options, with the live model list when one was fetched
+        let mut fallback = fallback_config_options(agent_id);
+        state.model_catalog().apply(agent_id, &mut fallback);
         if !fallback.is_empty() {
             info.config_options = Some(fallback);
         }
@@ -768,77 +1577,554 @@ async fn post_v1_agent_install(
 
     // Purge version cache so next ?config=true picks up the new version
     state.purge_version_cache(agent_id);
+    state.acp_proxy().agent_capabilities().forget(agent_id);
 
     Ok(Json(map_install_result(install_result)))
 }
 
 #[utoipa::path(
     get,
-    path = "/v1/fs/entries",
+    path = "/v1/models",
     tag = "v1",
-    params(
-        ("path" = Option<String>, Query, description = "Directory path")
-    ),
     responses(
-        (status = 200, description = "Directory entries", body = Vec<FsEntry>)
+        (status = 200, description = "Models of every installed agent", body = ModelListResponse)
     )
 )]
-async fn get_v1_fs_entries(
-    Query(query): Query<FsEntriesQuery>,
-) -> Result<Json<Vec<FsEntry>>, ApiError> {
-    let path = query.path.unwrap_or_else(|| ".".to_string());
-    let target = resolve_fs_path(&path)?;
-    let metadata = fs::metadata(&target).map_err(|err| map_fs_error(&target, err))?;
-    if !metadata.is_dir() {
-        return Err(SandboxError::InvalidRequest {
-            message: format!("path is not a directory: {}", target.display()),
+async fn get_v1_models(State(state): State<Arc<AppState>>) -> Json<ModelListResponse> {
+    let mut models = Vec::new();
+    for agent_id in AgentId::all().iter().copied() {
+        if !state.agent_manager().is_installed(agent_id) {
+            continue;
         }
-        .into());
-    }
-
-    let mut entries = Vec::new();
-    for entry in fs::read_dir(&target).map_err(|err| map_fs_error(&target, err))? {
-        let entry = entry.map_err(|err| SandboxError::StreamError {
-            message: err.to_string(),
-        })?;
-        let path = entry.path();
-        let metadata = entry.metadata().map_err(|err| SandboxError::StreamError {
-            message: err.to_string(),
-        })?;
-        let entry_type = if metadata.is_dir() {
-            FsEntryType::Directory
-        } else {
-            FsEntryType::File
-        };
-        let modified = metadata
-            .modified()
-            .ok()
-            .map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339());
-        entries.push(FsEntry {
-            name: entry.file_name().to_string_lossy().to_string(),
-            path: path.to_string_lossy().to_string(),
-            entry_type,
-            size: metadata.len(),
-            modified,
-        });
+        let listed = state
+            .model_catalog()
+            .models(agent_id, &fallback_config_options(agent_id));
+        models.extend(listed.models.into_iter().map(|model| CatalogModel {
+            agent: listed.agent.clone(),
+            default: listed.default_model.as_deref() == Some(model.id.as_str()),
+            source: listed.source,
+            id: model.id,
+            name: model.name,
+            context_window: model.context_window,
+            reasoning_variants: model.reasoning_variants,
+        }));
     }
-    Ok(Json(entries))
+    Json(ModelListResponse { models })
 }
 
 #[utoipa::path(
-    get,
-    path = "/v1/fs/file",
+    post,
+    path = "/v1/agents/{agent}/models/refresh",
     tag = "v1",
     params(
-        ("path" = String, Query, description = "File path")
+        ("agent" = String, Path, description = "Agent id")
     ),
     responses(
-        (status = 200, description = "File content")
+        (status = 200, description = "The agent's models, fetched now where it has a live source", body = AgentModelsResponse),
+        (status = 400, description = "Unknown agent", body = ProblemDetails),
+        (status = 502, description = "The agent's backend failed to list its models", body = ProblemDetails)
     )
 )]
-async fn get_v1_fs_file(Query(query): Query<FsPathQuery>) -> Result<Response, ApiError> {
-    let target = resolve_fs_path(&query.path)?;
-    let metadata = fs::metadata(&target).map_err(|err| map_fs_error(&target, err))?;
+async fn post_v1_agent_models_refresh(
+    State(state): State<Arc<AppState>>,
+    Path(agent): Path<String>,
+) -> Result<Json<AgentModelsResponse>, ApiError> {
+    let agent_id = AgentId::parse(&agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+        agent: agent.clone(),
+    })?;
+    let catalog = state.model_catalog();
+    catalog
+        .refresh(agent_id, &state.opencode_server_manager(), true)
+        .await?;
+    Ok(Json(
+        catalog.models(agent_id, &fallback_config_options(agent_id)),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/agents/{agent}/capabilities/refresh",
+    tag = "v1",
+    params(
+        ("agent" = String, Path, description = "Agent id")
+    ),
+    responses(
+        (status = 200, description = "The agent's capabilities after probing it now", body = AgentCapabilitiesResponse),
+        (status = 400, description = "Unknown agent", body = ProblemDetails),
+        (status = 502, description = "The agent's server could not be started or probed", body = ProblemDetails)
+    )
+)]
+async fn post_v1_agent_capabilities_refresh(
+    State(state): State<Arc<AppState>>,
+    Path(agent): Path<String>,
+) -> Result<Json<AgentCapabilitiesResponse>, ApiError> {
+    let agent_id = AgentId::parse(&agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+        agent: agent.clone(),
+    })?;
+    state.purge_version_cache(agent_id);
+    state
+        .acp_proxy()
+        .agent_capabilities()
+        .refresh(
+            agent_id,
+            state.agent_manager(),
+            &state.opencode_server_manager(),
+            true,
+        )
+        .await?;
+    let resolved = resolve_agent_capabilities(&state, agent_id);
+    Ok(Json(AgentCapabilitiesResponse {
+        agent: agent_id.as_str().to_string(),
+        capabilities: resolved.capabilities,
+        source: resolved.source,
+        probed_at_ms: resolved.probed_at_ms,
+        overrides: resolved.overrides,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/agents/{agent}/server/start",
+    tag = "v1",
+    params(
+        ("agent" = String, Path, description = "Agent id; only opencode has managed servers")
+    ),
+    responses(
+        (status = 200, description = "The agent's servers after starting them", body = ServerStatusInfo),
+        (status = 400, description = "Unknown agent or an agent without managed servers", body = ProblemDetails),
+        (status = 502, description = "A server could not be started", body = ProblemDetails)
+    )
+)]
+async fn post_v1_agent_server_start(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Path(agent): Path<String>,
+) -> Result<Json<ServerStatusInfo>, ApiError> {
+    managed_server_action(&state, actor, &agent, "start").await
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/agents/{agent}/server/stop",
+    tag = "v1",
+    params(
+        ("agent" = String, Path, description = "Agent id; only opencode has managed servers")
+    ),
+    responses(
+        (status = 200, description = "The agent's servers after stopping them", body = ServerStatusInfo),
+        (status = 400, description = "Unknown agent or an agent without managed servers", body = ProblemDetails)
+    )
+)]
+async fn post_v1_agent_server_stop(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Path(agent): Path<String>,
+) -> Result<Json<ServerStatusInfo>, ApiError> {
+    managed_server_action(&state, actor, &agent, "stop").await
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/agents/{agent}/server/restart",
+    tag = "v1",
+    params(
+        ("agent" = String, Path, description = "Agent id; only opencode has managed servers")
+    ),
+    responses(
+        (status = 200, description = "The agent's servers after the restart", body = ServerStatusInfo),
+        (status = 400, description = "Unknown agent or an agent without managed servers", body = ProblemDetails),
+        (status = 502, description = "A server could not be started", body = ProblemDetails)
+    )
+)]
+async fn post_v1_agent_server_restart(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Path(agent): Path<String>,
+) -> Result<Json<ServerStatusInfo>, ApiError> {
+    managed_server_action(&state, actor, &agent, "restart").await
+}
+
+/// Start, stop or restart the agent's managed servers and report them afterwards. Stopped
+/// servers stay down until started; start and restart also reset servers that crashed too
+/// often to be restarted automatically.
+async fn managed_server_action(
+    state: &AppState,
+    actor: Option<Extension<RequestActor>>,
+    agent: &str,
+    action: &str,
+) -> Result<Json<ServerStatusInfo>, ApiError> {
+    match AgentId::parse(agent) {
+        Some(AgentId::Opencode) => {}
+        Some(_) => {
+            return Err(SandboxError::InvalidRequest {
+                message: format!("{agent} has no managed server; only opencode does"),
+            }
+            .into())
+        }
+        None => {
+            return Err(SandboxError::UnsupportedAgent {
+                agent: agent.to_string(),
+            }
+            .into())
+        }
+    }
+    let mut entry = audit_entry(AuditKind::AgentServer, request_actor(actor));
+    entry.action = Some(format!("{action} {agent}"));
+    state.audit.record(entry);
+
+    let manager = state.opencode_server_manager();
+    let result = match action {
+        "stop" => {
+            manager.shutdown().await;
+            Ok(())
+        }
+        "start" => manager.start().await,
+        _ => manager.restart().await,
+    };
+    result.map_err(|err| SandboxError::StreamError {
+        message: format!("failed to {action} OpenCode server: {err}"),
+    })?;
+    let instances = state.acp_proxy().list_instances().await;
+    let opencode = manager.status().await;
+    let status =
+        server_status_info(AgentId::Opencode, &instances, &opencode).unwrap_or(ServerStatusInfo {
+            status: ServerStatus::Stopped,
+            uptime_ms: None,
+            instances: Vec::new(),
+        });
+    Ok(Json(status))
+}
+
+/// The agent's built-in capabilities with probed values and overrides applied, checked against
+/// its cached version when there is one.
+fn resolve_agent_capabilities(state: &AppState, agent_id: AgentId) -> ResolvedCapabilities {
+    let version = state
+        .version_cache
+        .lock()
+        .unwrap()
+        .get(&agent_id)
+        .and_then(|cached| cached.version.clone());
+    state.acp_proxy().agent_capabilities().resolve(
+        agent_id,
+        agent_capabilities_for(agent_id),
+        version.as_deref(),
+    )
+}
+
+/// Status of the agent's ACP servers and, for OpenCode, the managed OpenCode servers; `None`
+/// when the agent has neither.
+fn server_status_info(
+    agent_id: AgentId,
+    instances: &[AcpServerInstanceInfo],
+    opencode: &OpenCodeServerStatus,
+) -> Option<ServerStatusInfo> {
+    let now = now_ms();
+    let status = |running: bool| {
+        if running {
+            ServerStatus::Running
+        } else {
+            ServerStatus::Stopped
+        }
+    };
+    let mut servers = instances
+        .iter()
+        .filter(|instance| instance.agent == agent_id)
+        .map(|instance| ServerInstanceStatus {
+            id: instance.server_id.clone(),
+            status: status(!instance.exited),
+            uptime_ms: Some(now.saturating_sub(instance.created_at_ms) as u64),
+            directories: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    if agent_id == AgentId::Opencode {
+        servers.extend(opencode.instances.iter().map(|instance| {
+            ServerInstanceStatus {
+                id: format!("opencode-server-{}", instance.index),
+                status: if instance.failed {
+                    ServerStatus::Failed
+                } else {
+                    status(instance.running)
+                },
+                uptime_ms: instance.uptime.map(|uptime| uptime.as_millis() as u64),
+                directories: instance
+                    .keys
+                    .iter()
+                    .filter(|key| !key.is_empty())
+                    .cloned()
+                    .collect(),
+            }
+        }));
+    }
+    if servers.is_empty() {
+        return None;
+    }
+    let running = servers
+        .iter()
+        .filter(|server| matches!(server.status, ServerStatus::Running))
+        .collect::<Vec<_>>();
+    let failed = servers
+        .iter()
+        .any(|server| matches!(server.status, ServerStatus::Failed));
+    Some(ServerStatusInfo {
+        status: if running.is_empty() && failed {
+            ServerStatus::Failed
+        } else {
+            status(!running.is_empty())
+        },
+        uptime_ms: running.iter().filter_map(|server| server.uptime_ms).max(),
+        instances: servers,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/agents/{agent}/config",
+    tag = "v1",
+    params(("agent" = String, Path, description = "Agent id; codex or opencode")),
+    responses(
+        (status = 200, description = "Codex's managed settings (empty when the file does not exist), or the managed OpenCode server's config", body = AgentConfig),
+        (status = 400, description = "Unsupported agent or unparseable config file", body = ProblemDetails),
+        (status = 502, description = "The OpenCode server could not be started or reached", body = ProblemDetails)
+    )
+)]
+async fn get_v1_agent_config(
+    State(state): State<Arc<AppState>>,
+    Path(agent): Path<String>,
+) -> Result<Json<AgentConfig>, ApiError> {
+    let config = match config_agent(&agent)? {
+        AgentId::Opencode => AgentConfig::Opencode(
+            opencode_config_request(&state, reqwest::Method::GET, None).await?,
+        ),
+        _ => AgentConfig::Codex(read_codex_config(&codex_config_path())?),
+    };
+    Ok(Json(config))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/agents/{agent}/config",
+    tag = "v1",
+    params(("agent" = String, Path, description = "Agent id; only codex is supported")),
+    request_body = CodexConfig,
+    responses(
+        (status = 200, description = "Settings written; other keys and comments in the file are kept", body = CodexConfig),
+        (status = 400, description = "Unsupported agent, invalid settings, or unparseable config file", body = ProblemDetails)
+    )
+)]
+async fn put_v1_agent_config(
+    Path(agent): Path<String>,
+    Json(config): Json<CodexConfig>,
+) -> Result<Json<CodexConfig>, ApiError> {
+    if config_agent(&agent)? != AgentId::Codex {
+        return Err(SandboxError::InvalidRequest {
+            message: format!("{agent} config is updated with PATCH"),
+        }
+        .into());
+    }
+    let path = codex_config_path();
+    write_codex_config(&path, &config)?;
+    Ok(Json(read_codex_config(&path)?))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/v1/agents/{agent}/config",
+    tag = "v1",
+    params(("agent" = String, Path, description = "Agent id; only opencode is supported")),
+    request_body = Value,
+    responses(
+        (status = 200, description = "Config returned by the OpenCode server after the update", body = Value),
+        (status = 400, description = "Unsupported agent, or the OpenCode server rejected the update", body = ProblemDetails),
+        (status = 502, description = "The OpenCode server could not be started or reached", body = ProblemDetails)
+    )
+)]
+async fn patch_v1_agent_config(
+    State(state): State<Arc<AppState>>,
+    Path(agent): Path<String>,
+    Json(update): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    if config_agent(&agent)? != AgentId::Opencode {
+        return Err(SandboxError::InvalidRequest {
+            message: format!("{agent} config is replaced with PUT"),
+        }
+        .into());
+    }
+    if !update.is_object() {
+        return Err(SandboxError::InvalidRequest {
+            message: "config update must be a JSON object".to_string(),
+        }
+        .into());
+    }
+    Ok(Json(
+        opencode_config_request(&state, reqwest::Method::PATCH, Some(update)).await?,
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/agents/{agent}",
+    tag = "v1",
+    params(
+        ("agent" = String, Path, description = "Agent id")
+    ),
+    responses(
+        (status = 200, description = "Removed agent artifacts", body = AgentUninstallResponse),
+        (status = 400, description = "Unsupported agent", body = ProblemDetails),
+        (status = 409, description = "ACP servers are still running this agent", body = ProblemDetails),
+        (status = 500, description = "Uninstall failed", body = ProblemDetails)
+    )
+)]
+async fn delete_v1_agent(
+    State(state): State<Arc<AppState>>,
+    Path(agent): Path<String>,
+) -> Result<Json<AgentUninstallResponse>, ApiError> {
+    let agent_id = AgentId::parse(&agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+        agent: agent.clone(),
+    })?;
+
+    let active = state
+        .acp_proxy()
+        .list_instances()
+        .await
+        .into_iter()
+        .filter(|instance| instance.agent == agent_id)
+        .map(|instance| instance.server_id)
+        .collect::<Vec<_>>();
+    if !active.is_empty() {
+        return Err(SandboxError::Conflict {
+            message: format!(
+                "agent '{}' is in use by ACP servers: {}",
+                agent_id.as_str(),
+                active.join(", ")
+            ),
+        }
+        .into());
+    }
+
+    state.acp_proxy().drain_warm_pool(agent_id).await;
+
+    let manager = state.agent_manager();
+    let result = tokio::task::spawn_blocking(move || manager.uninstall(agent_id))
+        .await
+        .map_err(|err| SandboxError::InstallFailed {
+            agent,
+            stderr: Some(format!("uninstall task failed: {err}")),
+        })?
+        .map_err(|err| SandboxError::InstallFailed {
+            agent: agent_id.as_str().to_string(),
+            stderr: Some(err.to_string()),
+        })?;
+
+    state.purge_version_cache(agent_id);
+    state.acp_proxy().agent_capabilities().forget(agent_id);
+
+    Ok(Json(AgentUninstallResponse {
+        removed: result
+            .removed
+            .into_iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/fs/entries",
+    tag = "v1",
+    params(
+        ("path" = Option<String>, Query, description = "Directory path"),
+        ("respectGitignore" = Option<bool>, Query, description = "Skip paths ignored by the enclosing git repository (default true)"),
+        ("ignoreGlobs" = Option<String>, Query, description = "Comma-separated gitignore-style globs to skip"),
+        ("preview" = Option<bool>, Query, description = "Include mime type, binary flag, and first-line preview for files"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
+    ),
+    responses(
+        (status = 200, description = "Directory entries", body = Vec<FsEntry>),
+        (status = 400, description = "Path is not a directory", body = ProblemDetails),
+        (status = 404, description = "Path not found", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn get_v1_fs_entries(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FsEntriesQuery>,
+) -> Result<Json<Vec<FsEntry>>, ApiError> {
+    let path = query.path.unwrap_or_else(|| ".".to_string());
+    let target = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &path).await?;
+    let metadata = fs::metadata(&target).map_err(|err| map_fs_error(&target, err))?;
+    if !metadata.is_dir() {
+        return Err(SandboxError::NotADirectory {
+            path: target.display().to_string(),
+        }
+        .into());
+    }
+    let filter = FsIgnoreFilter::new(
+        &target,
+        query.respect_gitignore,
+        query.ignore_globs.as_deref(),
+    )?;
+    let want_preview = query.preview.unwrap_or(false);
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&target).map_err(|err| map_fs_error(&target, err))? {
+        let entry = entry.map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })?;
+        let path = entry.path();
+        let metadata = entry.metadata().map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })?;
+        if filter.is_ignored(&path, metadata.is_dir()) {
+            continue;
+        }
+        let entry_type = if metadata.is_dir() {
+            FsEntryType::Directory
+        } else {
+            FsEntryType::File
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339());
+        let preview = if want_preview && metadata.is_file() {
+            read_fs_preview(&path).ok()
+        } else {
+            None
+        };
+        entries.push(FsEntry {
+            name: entry.file_name().to_string_lossy().to_string(),
+            path: path.to_string_lossy().to_string(),
+            entry_type,
+            size: metadata.len(),
+            modified,
+            mime_type: preview.as_ref().map(|preview| preview.mime_type.clone()),
+            is_binary: preview.as_ref().map(|preview| preview.is_binary),
+            preview: preview.and_then(|preview| preview.preview),
+        });
+    }
+    Ok(Json(entries))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/fs/file",
+    tag = "v1",
+    params(
+        ("path" = String, Query, description = "File path"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
+    ),
+    responses(
+        (status = 200, description = "File content"),
+        (status = 404, description = "Path not found", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn get_v1_fs_file(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FsPathQuery>,
+) -> Result<Response, ApiError> {
+    let target = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &query.path).await?;
+    let metadata = fs::metadata(&target).map_err(|err| map_fs_error(&target, err))?;
     if !metadata.is_file() {
         return Err(SandboxError::InvalidRequest {
             message: format!("path is not a file: {}", target.display()),
@@ -858,22 +2144,29 @@ async fn get_v1_fs_file(Query(query): Query<FsPathQuery>) -> Result<Response, Ap
     path = "/v1/fs/file",
     tag = "v1",
     params(
-        ("path" = String, Query, description = "File path")
+        ("path" = String, Query, description = "File path"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
     ),
     request_body(content = String, description = "Raw file bytes"),
     responses(
-        (status = 200, description = "Write result", body = FsWriteResponse)
+        (status = 200, description = "Write result", body = FsWriteResponse),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
     )
 )]
 async fn put_v1_fs_file(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
     Query(query): Query<FsPathQuery>,
     body: Bytes,
 ) -> Result<Json<FsWriteResponse>, ApiError> {
-    let target = resolve_fs_path(&query.path)?;
+    let target = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &query.path).await?;
     if let Some(parent) = target.parent() {
         fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err))?;
     }
     fs::write(&target, &body).map_err(|err| map_fs_error(&target, err))?;
+    let mut entry = fs_audit_entry(AuditKind::FsWrite, actor, query.server_id, &target);
+    entry.details = json!({ "bytesWritten": body.len() });
+    state.audit().record(entry);
     Ok(Json(FsWriteResponse {
         path: target.to_string_lossy().to_string(),
         bytes_written: body.len() as u64,
@@ -886,16 +2179,20 @@ async fn put_v1_fs_file(
     tag = "v1",
     params(
         ("path" = String, Query, description = "File or directory path"),
-        ("recursive" = Option<bool>, Query, description = "Delete directory recursively")
+        ("recursive" = Option<bool>, Query, description = "Delete directory recursively"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
     ),
     responses(
-        (status = 200, description = "Delete result", body = FsActionResponse)
+        (status = 200, description = "Delete result", body = FsActionResponse),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
     )
 )]
 async fn delete_v1_fs_entry(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
     Query(query): Query<FsDeleteQuery>,
 ) -> Result<Json<FsActionResponse>, ApiError> {
-    let target = resolve_fs_path(&query.path)?;
+    let target = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &query.path).await?;
     let metadata = fs::metadata(&target).map_err(|err| map_fs_error(&target, err))?;
     if metadata.is_dir() {
         if query.recursive.unwrap_or(false) {
@@ -906,6 +2203,9 @@ async fn delete_v1_fs_entry(
     } else {
         fs::remove_file(&target).map_err(|err| map_fs_error(&target, err))?;
     }
+    let mut entry = fs_audit_entry(AuditKind::FsDelete, actor, query.server_id, &target);
+    entry.details = json!({ "directory": metadata.is_dir() });
+    state.audit().record(entry);
     Ok(Json(FsActionResponse {
         path: target.to_string_lossy().to_string(),
     }))
@@ -916,17 +2216,24 @@ async fn delete_v1_fs_entry(
     path = "/v1/fs/mkdir",
     tag = "v1",
     params(
-        ("path" = String, Query, description = "Directory path")
+        ("path" = String, Query, description = "Directory path"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
     ),
     responses(
-        (status = 200, description = "Directory created", body = FsActionResponse)
+        (status = 200, description = "Directory created", body = FsActionResponse),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
     )
 )]
 async fn post_v1_fs_mkdir(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
     Query(query): Query<FsPathQuery>,
 ) -> Result<Json<FsActionResponse>, ApiError> {
-    let target = resolve_fs_path(&query.path)?;
+    let target = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &query.path).await?;
     fs::create_dir_all(&target).map_err(|err| map_fs_error(&target, err))?;
+    let mut entry = fs_audit_entry(AuditKind::FsWrite, actor, query.server_id, &target);
+    entry.details = json!({ "directory": true });
+    state.audit().record(entry);
     Ok(Json(FsActionResponse {
         path: target.to_string_lossy().to_string(),
     }))
@@ -938,14 +2245,20 @@ async fn post_v1_fs_mkdir(
     tag = "v1",
     request_body = FsMoveRequest,
     responses(
-        (status = 200, description = "Move result", body = FsMoveResponse)
+        (status = 200, description = "Move result", body = FsMoveResponse),
+        (status = 404, description = "Source not found", body = ProblemDetails),
+        (status = 409, description = "Destination exists and overwrite is not set", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
     )
 )]
 async fn post_v1_fs_move(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
     Json(request): Json<FsMoveRequest>,
 ) -> Result<Json<FsMoveResponse>, ApiError> {
-    let from = resolve_fs_path(&request.from)?;
-    let to = resolve_fs_path(&request.to)?;
+    let server_id = request.server_id.as_deref();
+    let from = resolve_scoped_fs_path(&state, server_id, &request.from).await?;
+    let to = resolve_scoped_fs_path(&state, server_id, &request.to).await?;
 
     if to.exists() {
         if request.overwrite.unwrap_or(false) {
@@ -956,8 +2269,8 @@ async fn post_v1_fs_move(
                 fs::remove_file(&to).map_err(|err| map_fs_error(&to, err))?;
             }
         } else {
-            return Err(SandboxError::InvalidRequest {
-                message: format!("destination already exists: {}", to.display()),
+            return Err(SandboxError::AlreadyExists {
+                path: to.display().to_string(),
             }
             .into());
         }
@@ -967,6 +2280,9 @@ async fn post_v1_fs_move(
         fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err))?;
     }
     fs::rename(&from, &to).map_err(|err| map_fs_error(&from, err))?;
+    let mut entry = fs_audit_entry(AuditKind::FsMove, actor, request.server_id, &from);
+    entry.destination = Some(to.to_string_lossy().to_string());
+    state.audit().record(entry);
     Ok(Json(FsMoveResponse {
         from: from.to_string_lossy().to_string(),
         to: to.to_string_lossy().to_string(),
@@ -978,14 +2294,20 @@ async fn post_v1_fs_move(
     path = "/v1/fs/stat",
     tag = "v1",
     params(
-        ("path" = String, Query, description = "Path to stat")
+        ("path" = String, Query, description = "Path to stat"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
     ),
     responses(
-        (status = 200, description = "Path metadata", body = FsStat)
+        (status = 200, description = "Path metadata", body = FsStat),
+        (status = 404, description = "Path not found", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
     )
 )]
-async fn get_v1_fs_stat(Query(query): Query<FsPathQuery>) -> Result<Json<FsStat>, ApiError> {
-    let target = resolve_fs_path(&query.path)?;
+async fn get_v1_fs_stat(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FsPathQuery>,
+) -> Result<Json<FsStat>, ApiError> {
+    let target = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &query.path).await?;
     let metadata = fs::metadata(&target).map_err(|err| map_fs_error(&target, err))?;
     let entry_type = if metadata.is_dir() {
         FsEntryType::Directory
@@ -1005,18 +2327,258 @@ async fn get_v1_fs_stat(Query(query): Query<FsPathQuery>) -> Result<Json<FsStat>
 }
 
 #[utoipa::path(
-    post,
-    path = "/v1/fs/upload-batch",
+    get,
+    path = "/v1/fs/search",
     tag = "v1",
     params(
-        ("path" = Option<String>, Query, description = "Destination path")
+        ("q" = String, Query, description = "Text to search for, or a regular expression when regex=true"),
+        ("path" = Option<String>, Query, description = "File or directory to search (defaults to the workspace root)"),
+        ("regex" = Option<bool>, Query, description = "Treat q as a regular expression (default false)"),
+        ("caseSensitive" = Option<bool>, Query, description = "Match case exactly (default true)"),
+        ("maxResults" = Option<usize>, Query, description = "Stop after this many matching lines (default 1000, max 10000)"),
+        ("respectGitignore" = Option<bool>, Query, description = "Skip paths ignored by git (default true)"),
+        ("ignoreGlobs" = Option<String>, Query, description = "Comma-separated gitignore-style globs to skip"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
+    ),
+    responses(
+        (status = 200, description = "FsSearchEvent lines as NDJSON, or SSE events when Accept lists text/event-stream", body = FsSearchEvent, content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid query or pattern", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails),
+        (status = 404, description = "Path not found", body = ProblemDetails)
+    )
+)]
+async fn get_v1_fs_search(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FsSearchQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if query.q.is_empty() {
+        return Err(SandboxError::InvalidRequest {
+            message: "missing required 'q' query parameter".to_string(),
+        }
+        .into());
+    }
+    let max_results = query
+        .max_results
+        .unwrap_or(FS_SEARCH_DEFAULT_MAX_RESULTS)
+        .min(FS_SEARCH_MAX_RESULTS);
+    if max_results == 0 {
+        return Err(SandboxError::InvalidRequest {
+            message: "maxResults must be greater than 0".to_string(),
+        }
+        .into());
+    }
+    let source = if query.regex.unwrap_or(false) {
+        query.q.clone()
+    } else {
+        regex::escape(&query.q)
+    };
+    let pattern = regex::RegexBuilder::new(&source)
+        .case_insensitive(!query.case_sensitive.unwrap_or(true))
+        .build()
+        .map_err(|err| SandboxError::InvalidRequest {
+            message: format!("invalid search pattern: {err}"),
+        })?;
+
+    let path = query.path.unwrap_or_else(|| ".".to_string());
+    let root = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &path).await?;
+    let metadata = fs::metadata(&root).map_err(|err| map_fs_error(&root, err))?;
+    let filter_root = if metadata.is_dir() {
+        root.as_path()
+    } else {
+        root.parent().unwrap_or(&root)
+    };
+    let filter = FsIgnoreFilter::new(filter_root, Some(false), query.ignore_globs.as_deref())?;
+    let search = FsSearch {
+        root,
+        pattern,
+        max_results,
+        respect_gitignore: query.respect_gitignore.unwrap_or(true),
+        filter,
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+    tokio::task::spawn_blocking(move || run_fs_search(search, tx));
+    let events = tokio_stream::wrappers::ReceiverStream::new(rx);
+
+    if accept_lists(&headers, TEXT_EVENT_STREAM) {
+        let stream = events.map(|event| {
+            let name = match event {
+                FsSearchEvent::Match { .. } => "match",
+                FsSearchEvent::Done { .. } => "done",
+            };
+            Ok(axum::response::sse::Event::default()
+                .event(name)
+                .data(serde_json::to_string(&event).unwrap_or_default()))
+        });
+        return Ok(Sse::new(Box::pin(stream) as PinBoxSseStream).into_response());
+    }
+
+    let lines = events.map(|event| {
+        let mut line = serde_json::to_string(&event).unwrap_or_default();
+        line.push('\n');
+        Ok::<_, std::convert::Infallible>(Bytes::from(line))
+    });
+    let mut response = Body::from_stream(lines).into_response();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(APPLICATION_NDJSON),
+    );
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/fs/archive",
+    tag = "v1",
+    params(
+        ("path" = String, Query, description = "Directory to archive"),
+        ("format" = Option<FsArchiveFormat>, Query, description = "tar.gz (default) or zip"),
+        ("respectGitignore" = Option<bool>, Query, description = "Skip paths ignored by git (default false)"),
+        ("ignoreGlobs" = Option<String>, Query, description = "Comma-separated gitignore-style globs to skip"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
+    ),
+    responses(
+        (status = 200, description = "Archive of the directory", content_type = "application/gzip"),
+        (status = 400, description = "Path is not a directory", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails),
+        (status = 404, description = "Path not found", body = ProblemDetails)
+    )
+)]
+async fn get_v1_fs_archive(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FsArchiveQuery>,
+) -> Result<Response, ApiError> {
+    let root = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &query.path).await?;
+    let metadata = fs::metadata(&root).map_err(|err| map_fs_error(&root, err))?;
+    if !metadata.is_dir() {
+        return Err(SandboxError::NotADirectory {
+            path: root.display().to_string(),
+        }
+        .into());
+    }
+    let filter = FsIgnoreFilter::new(&root, Some(false), query.ignore_globs.as_deref())?;
+    let format = query.format.unwrap_or_default();
+    let (content_type, extension) = match format {
+        FsArchiveFormat::TarGz => ("application/gzip", "tar.gz"),
+        FsArchiveFormat::Zip => ("application/zip", "zip"),
+    };
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().replace(['"', '\\'], "_"))
+        .unwrap_or_else(|| "archive".to_string());
+    let archive = FsArchive {
+        root,
+        format,
+        respect_gitignore: query.respect_gitignore.unwrap_or(false),
+        filter,
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel(8);
+    tokio::task::spawn_blocking(move || run_fs_archive(archive, tx));
+    let mut response =
+        Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(content_type),
+    );
+    if let Ok(disposition) =
+        header::HeaderValue::from_str(&format!("attachment; filename=\"{name}.{extension}\""))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/fs/watch",
+    tag = "v1",
+    params(
+        ("path" = Option<String>, Query, description = "File or directory to watch (defaults to the workspace root)"),
+        ("recursive" = Option<bool>, Query, description = "Watch subdirectories too (default true)"),
+        ("respectGitignore" = Option<bool>, Query, description = "Skip paths ignored by git (default true)"),
+        ("ignoreGlobs" = Option<String>, Query, description = "Comma-separated gitignore-style globs to skip"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
+    ),
+    responses(
+        (status = 200, description = "SSE stream of FsWatchEvent payloads, each named after its type", body = FsWatchEvent, content_type = "text/event-stream"),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails),
+        (status = 404, description = "Path not found", body = ProblemDetails),
+        (status = 406, description = "Client does not accept SSE responses", body = ProblemDetails)
+    )
+)]
+async fn get_v1_fs_watch(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FsWatchQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<PinBoxSseStream>, ApiError> {
+    if !accept_allows(&headers, TEXT_EVENT_STREAM) {
+        return Err(SandboxError::NotAcceptable {
+            message: "accept must allow text/event-stream".to_string(),
+        }
+        .into());
+    }
+
+    let path = query.path.as_deref().unwrap_or(".");
+    let target = resolve_scoped_fs_path(&state, query.server_id.as_deref(), path).await?;
+    let metadata = fs::metadata(&target).map_err(|err| map_fs_error(&target, err))?;
+    let base = if metadata.is_dir() {
+        target.clone()
+    } else {
+        target
+            .parent()
+            .map(StdPath::to_path_buf)
+            .unwrap_or_else(|| target.clone())
+    };
+    let filter = FsIgnoreFilter::new(
+        &base,
+        query.respect_gitignore,
+        query.ignore_globs.as_deref(),
+    )?;
+    let watch = FsWatch::start(&target, query.recursive.unwrap_or(true), base, filter)?;
+
+    let stream = stream::unfold(watch, |mut watch| async move {
+        let event = watch.next().await?;
+        let name = match &event {
+            FsWatchEvent::Create { .. } => "create",
+            FsWatchEvent::Modify { .. } => "modify",
+            FsWatchEvent::Delete { .. } => "delete",
+            FsWatchEvent::Rescan => "rescan",
+        };
+        let sse = axum::response::sse::Event::default()
+            .event(name)
+            .data(serde_json::to_string(&event).unwrap_or_default());
+        Some((Ok(sse), watch))
+    });
+
+    Ok(Sse::new(Box::pin(stream) as PinBoxSseStream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("heartbeat"),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/fs/upload-batch",
+    tag = "v1",
+    params(
+        ("path" = Option<String>, Query, description = "Destination path"),
+        ("respectGitignore" = Option<bool>, Query, description = "Skip entries ignored by the destination's git repository (default true)"),
+        ("ignoreGlobs" = Option<String>, Query, description = "Comma-separated gitignore-style globs to skip"),
+        ("serverId" = Option<String>, Query, description = "Resolve paths inside this ACP server's working directory")
     ),
     request_body(content = String, description = "tar archive body"),
     responses(
-        (status = 200, description = "Upload/extract result", body = FsUploadBatchResponse)
+        (status = 200, description = "Upload/extract result", body = FsUploadBatchResponse),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
     )
 )]
 async fn post_v1_fs_upload_batch(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
     headers: HeaderMap,
     Query(query): Query<FsUploadBatchQuery>,
     body: Bytes,
@@ -1033,12 +2595,18 @@ async fn post_v1_fs_upload_batch(
     }
 
     let path = query.path.unwrap_or_else(|| ".".to_string());
-    let base = resolve_fs_path(&path)?;
+    let base = resolve_scoped_fs_path(&state, query.server_id.as_deref(), &path).await?;
     fs::create_dir_all(&base).map_err(|err| map_fs_error(&base, err))?;
+    let filter = FsIgnoreFilter::new(
+        &base,
+        query.respect_gitignore,
+        query.ignore_globs.as_deref(),
+    )?;
 
     let mut archive = Archive::new(Cursor::new(body));
     let mut extracted = Vec::new();
     let mut truncated = false;
+    let mut skipped = 0;
 
     for entry in archive.entries().map_err(|err| SandboxError::StreamError {
         message: err.to_string(),
@@ -1060,6 +2628,10 @@ async fn post_v1_fs_upload_batch(
             }
             .into());
         }
+        if filter.is_ignored(&dest, entry.header().entry_type().is_dir()) {
+            skipped += 1;
+            continue;
+        }
         if let Some(parent) = dest.parent() {
             fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err))?;
         }
@@ -1075,302 +2647,2915 @@ async fn post_v1_fs_upload_batch(
         }
     }
 
+    let mut entry = fs_audit_entry(AuditKind::FsWrite, actor, query.server_id, &base);
+    entry.details = json!({ "archive": true, "entries": extracted.len(), "truncated": truncated });
+    state.audit().record(entry);
     Ok(Json(FsUploadBatchResponse {
         paths: extracted,
         truncated,
+        skipped,
     }))
 }
 
 #[utoipa::path(
-    get,
-    path = "/v1/config/mcp",
+    post,
+    path = "/v1/fs/upload/init",
     tag = "v1",
-    params(
-        ("directory" = String, Query, description = "Target directory"),
-        ("mcpName" = String, Query, description = "MCP entry name")
-    ),
+    request_body = FsUploadInitRequest,
     responses(
-        (status = 200, description = "MCP entry", body = McpServerConfig),
-        (status = 404, description = "Entry not found", body = ProblemDetails)
+        (status = 200, description = "Upload started", body = FsUploadStatus),
+        (status = 400, description = "Invalid destination or checksum", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails),
+        (status = 409, description = "Destination exists and overwrite is not set", body = ProblemDetails)
     )
 )]
-async fn get_v1_config_mcp(
-    Query(query): Query<McpConfigQuery>,
-) -> Result<Json<McpServerConfig>, ApiError> {
-    validate_named_query(&query.directory, "directory")?;
-    validate_named_query(&query.mcp_name, "mcpName")?;
+async fn post_v1_fs_upload_init(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<FsUploadInitRequest>,
+) -> Result<Json<FsUploadStatus>, ApiError> {
+    let target =
+        resolve_scoped_fs_path(&state, request.server_id.as_deref(), &request.path).await?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|err| map_fs_error(parent, err))?;
+    }
+    let upload = state.fs_uploads().begin(
+        target,
+        request.size,
+        request.sha256,
+        request.overwrite.unwrap_or(false),
+    )?;
+    Ok(Json(upload_status(upload)))
+}
 
-    let path = config_file_path(&query.directory, "mcp.json")?;
-    let entries: BTreeMap<String, McpServerConfig> = read_named_config_map(&path)?;
-    let value =
-        entries
-            .get(&query.mcp_name)
-            .cloned()
-            .ok_or_else(|| SandboxError::SessionNotFound {
-                session_id: format!("mcp:{}", query.mcp_name),
-            })?;
-    Ok(Json(value))
+#[utoipa::path(
+    get,
+    path = "/v1/fs/upload/{upload_id}",
+    tag = "v1",
+    params(("upload_id" = String, Path, description = "Upload id")),
+    responses(
+        (status = 200, description = "Upload progress", body = FsUploadStatus),
+        (status = 404, description = "Unknown upload", body = ProblemDetails)
+    )
+)]
+async fn get_v1_fs_upload(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+) -> Result<Json<FsUploadStatus>, ApiError> {
+    let upload = state.fs_uploads().status(&upload_id).await?;
+    Ok(Json(upload_status(upload)))
 }
 
 #[utoipa::path(
     put,
-    path = "/v1/config/mcp",
+    path = "/v1/fs/upload/{upload_id}/chunk",
     tag = "v1",
     params(
-        ("directory" = String, Query, description = "Target directory"),
-        ("mcpName" = String, Query, description = "MCP entry name")
+        ("upload_id" = String, Path, description = "Upload id"),
+        ("offset" = u64, Query, description = "Byte offset of this chunk; may not exceed the bytes received"),
+        ("sha256" = Option<String>, Query, description = "Hex SHA-256 of this chunk")
     ),
-    request_body = McpServerConfig,
+    request_body(content = String, description = "Raw chunk bytes"),
+    responses(
+        (status = 200, description = "Chunk stored", body = FsUploadStatus),
+        (status = 400, description = "Chunk checksum mismatch or past the declared size", body = ProblemDetails),
+        (status = 404, description = "Unknown upload", body = ProblemDetails),
+        (status = 409, description = "Offset is past the bytes received", body = ProblemDetails)
+    )
+)]
+async fn put_v1_fs_upload_chunk(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    Query(query): Query<FsUploadChunkQuery>,
+    body: Body,
+) -> Result<Json<FsUploadStatus>, ApiError> {
+    let upload = state
+        .fs_uploads()
+        .write_chunk(
+            &upload_id,
+            query.offset,
+            query.sha256.as_deref(),
+            body.into_data_stream(),
+        )
+        .await?;
+    Ok(Json(upload_status(upload)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/fs/upload/{upload_id}/complete",
+    tag = "v1",
+    params(("upload_id" = String, Path, description = "Upload id")),
+    responses(
+        (status = 200, description = "File moved into place", body = FsUploadCompleteResponse),
+        (status = 400, description = "Checksum mismatch", body = ProblemDetails),
+        (status = 404, description = "Unknown upload", body = ProblemDetails),
+        (status = 409, description = "Upload is incomplete or the destination now exists", body = ProblemDetails)
+    )
+)]
+async fn post_v1_fs_upload_complete(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Path(upload_id): Path<String>,
+) -> Result<Json<FsUploadCompleteResponse>, ApiError> {
+    let completed = state.fs_uploads().complete(&upload_id).await?;
+    let mut entry = fs_audit_entry(AuditKind::FsWrite, actor, None, &completed.destination);
+    entry.details = json!({
+        "uploadId": upload_id,
+        "bytesWritten": completed.bytes_written,
+        "sha256": completed.sha256,
+    });
+    state.audit().record(entry);
+    Ok(Json(FsUploadCompleteResponse {
+        path: completed.destination.to_string_lossy().to_string(),
+        bytes_written: completed.bytes_written,
+        sha256: completed.sha256,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/fs/upload/{upload_id}",
+    tag = "v1",
+    params(("upload_id" = String, Path, description = "Upload id")),
+    responses(
+        (status = 200, description = "Upload aborted", body = FsActionResponse),
+        (status = 404, description = "Unknown upload", body = ProblemDetails)
+    )
+)]
+async fn delete_v1_fs_upload(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+) -> Result<Json<FsActionResponse>, ApiError> {
+    let destination = state.fs_uploads().abort(&upload_id).await?;
+    Ok(Json(FsActionResponse {
+        path: destination.to_string_lossy().to_string(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/exec",
+    tag = "v1",
+    request_body = ExecRequest,
+    responses(
+        (status = 200, description = "SSE stream of ExecEvent payloads, each named after its type", body = ExecEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid request or unknown command", body = ProblemDetails),
+        (status = 403, description = "Working directory is outside the permitted directories", body = ProblemDetails),
+        (status = 404, description = "Working directory not found", body = ProblemDetails),
+        (status = 406, description = "Client does not accept SSE responses", body = ProblemDetails),
+        (status = 409, description = "Too many commands running", body = ProblemDetails)
+    )
+)]
+async fn post_v1_exec(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    headers: HeaderMap,
+    Json(request): Json<ExecRequest>,
+) -> Result<Sse<PinBoxSseStream>, ApiError> {
+    if !accept_allows(&headers, TEXT_EVENT_STREAM) {
+        return Err(SandboxError::NotAcceptable {
+            message: "accept must allow text/event-stream".to_string(),
+        }
+        .into());
+    }
+    if request.command.trim().is_empty() {
+        return Err(SandboxError::InvalidRequest {
+            message: "command must not be empty".to_string(),
+        }
+        .into());
+    }
+    let timeout = request
+        .timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(EXEC_DEFAULT_TIMEOUT)
+        .min(EXEC_MAX_TIMEOUT);
+    if timeout.is_zero() {
+        return Err(SandboxError::InvalidRequest {
+            message: "timeoutMs must be greater than 0".to_string(),
+        }
+        .into());
+    }
+
+    let cwd = request.cwd.as_deref().unwrap_or(".");
+    let cwd = resolve_scoped_fs_path(&state, request.server_id.as_deref(), cwd).await?;
+    let metadata = fs::metadata(&cwd).map_err(|err| map_fs_error(&cwd, err))?;
+    if !metadata.is_dir() {
+        return Err(SandboxError::NotADirectory {
+            path: cwd.display().to_string(),
+        }
+        .into());
+    }
+
+    let mut entry = audit_entry(AuditKind::Exec, request_actor(actor));
+    entry.server_id = request.server_id.clone();
+    entry.action = Some(
+        std::iter::once(request.command.as_str())
+            .chain(request.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    entry.path = Some(cwd.to_string_lossy().to_string());
+    // Values can hold secrets; only the names are recorded.
+    let env_names = request.env.keys().cloned().collect::<Vec<_>>();
+    let run = state.exec().start(ExecSpec {
+        command: request.command,
+        args: request.args.clone(),
+        cwd: cwd.clone(),
+        env: request.env.into_iter().collect(),
+        timeout,
+        max_output_bytes: request
+            .max_output_bytes
+            .unwrap_or(EXEC_DEFAULT_MAX_OUTPUT_BYTES),
+        max_memory_bytes: request.max_memory_bytes,
+        max_cpu_seconds: request.max_cpu_seconds,
+    })?;
+    entry.details = json!({
+        "execId": run.id,
+        "pid": run.pid,
+        "args": request.args,
+        "env": env_names,
+    });
+    state.audit().record(entry);
+    let start = ExecEvent::Start {
+        exec_id: run.id,
+        pid: run.pid,
+        cwd: cwd.to_string_lossy().to_string(),
+    };
+
+    let events = stream::once(async move { start })
+        .chain(tokio_stream::wrappers::ReceiverStream::new(run.events));
+    let stream = events.map(|event| {
+        let name = match &event {
+            ExecEvent::Start { .. } => "start",
+            ExecEvent::Stdout { .. } => "stdout",
+            ExecEvent::Stderr { .. } => "stderr",
+            ExecEvent::Exit { .. } => "exit",
+        };
+        Ok(axum::response::sse::Event::default()
+            .event(name)
+            .data(serde_json::to_string(&event).unwrap_or_default()))
+    });
+
+    Ok(Sse::new(Box::pin(stream) as PinBoxSseStream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("heartbeat"),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/exec/{exec_id}/kill",
+    tag = "v1",
+    params(("exec_id" = String, Path, description = "Exec id from the stream's start event")),
+    responses(
+        (status = 204, description = "Kill requested; the stream ends with an exit event"),
+        (status = 404, description = "Unknown or finished command", body = ProblemDetails)
+    )
+)]
+async fn post_v1_exec_kill(
+    State(state): State<Arc<AppState>>,
+    Path(exec_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state.exec().kill(&exec_id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/git/push",
+    tag = "v1",
+    request_body = GitSyncRequest,
+    responses(
+        (status = 200, description = "SSE stream of GitEvent payloads, each named after its type", body = GitEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid remote or branch, or git is not installed", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails),
+        (status = 404, description = "Repository directory does not exist", body = ProblemDetails),
+        (status = 406, description = "Accept header excludes text/event-stream", body = ProblemDetails),
+        (status = 409, description = "Too many concurrent commands", body = ProblemDetails)
+    )
+)]
+async fn post_v1_git_push(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    headers: HeaderMap,
+    Json(request): Json<GitSyncRequest>,
+) -> Result<Sse<PinBoxSseStream>, ApiError> {
+    git_sync_stream(&state, actor, &headers, GitOperation::Push, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/git/pull",
+    tag = "v1",
+    request_body = GitSyncRequest,
+    responses(
+        (status = 200, description = "SSE stream of GitEvent payloads, each named after its type", body = GitEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid remote or branch, or git is not installed", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails),
+        (status = 404, description = "Repository directory does not exist", body = ProblemDetails),
+        (status = 406, description = "Accept header excludes text/event-stream", body = ProblemDetails),
+        (status = 409, description = "Too many concurrent commands", body = ProblemDetails)
+    )
+)]
+async fn post_v1_git_pull(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    headers: HeaderMap,
+    Json(request): Json<GitSyncRequest>,
+) -> Result<Sse<PinBoxSseStream>, ApiError> {
+    git_sync_stream(&state, actor, &headers, GitOperation::Pull, request).await
+}
+
+async fn git_sync_stream(
+    state: &Arc<AppState>,
+    actor: Option<Extension<RequestActor>>,
+    headers: &HeaderMap,
+    operation: GitOperation,
+    request: GitSyncRequest,
+) -> Result<Sse<PinBoxSseStream>, ApiError> {
+    if !accept_allows(headers, TEXT_EVENT_STREAM) {
+        return Err(SandboxError::NotAcceptable {
+            message: "accept must allow text/event-stream".to_string(),
+        }
+        .into());
+    }
+    let timeout = request
+        .timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(EXEC_DEFAULT_TIMEOUT)
+        .min(EXEC_MAX_TIMEOUT);
+    if timeout.is_zero() {
+        return Err(SandboxError::InvalidRequest {
+            message: "timeoutMs must be greater than 0".to_string(),
+        }
+        .into());
+    }
+    let path = request.path.as_deref().unwrap_or(".");
+    let cwd = resolve_scoped_fs_path(state, request.server_id.as_deref(), path).await?;
+    let metadata = fs::metadata(&cwd).map_err(|err| map_fs_error(&cwd, err))?;
+    if !metadata.is_dir() {
+        return Err(SandboxError::NotADirectory {
+            path: cwd.display().to_string(),
+        }
+        .into());
+    }
+
+    let command = git_sync::command(operation, &request, cwd.clone(), timeout)?;
+    let args = git_sync::redacted_args(&command.spec.args);
+    let run = state.exec().start(command.spec)?;
+    let mut entry = fs_audit_entry(AuditKind::Git, actor, request.server_id, &cwd);
+    entry.action = Some(format!("git {args}"));
+    // Credentials are never recorded, only which kind was used.
+    let credentials = request.credentials.unwrap_or_default();
+    entry.details = json!({
+        "execId": run.id,
+        "token": credentials.token.is_some(),
+        "sshKey": credentials.ssh_key.is_some(),
+    });
+    state.audit().record(entry);
+
+    let start = GitEvent::Start {
+        exec_id: run.id,
+        operation,
+        cwd: cwd.to_string_lossy().to_string(),
+        remote: command.remote,
+    };
+    let ssh_dir = command.ssh_dir;
+    let mut parser = ProgressParser::default();
+    let events = tokio_stream::wrappers::ReceiverStream::new(run.events)
+        .flat_map(move |event| stream::iter(parser.feed(event)))
+        .map(move |event| {
+            // The SSH key stays on disk until the stream is dropped.
+            let _ = &ssh_dir;
+            event
+        });
+    let stream = stream::once(async move { start })
+        .chain(events)
+        .map(|event| {
+            let name = match &event {
+                GitEvent::Start { .. } => "start",
+                GitEvent::Progress { .. } => "progress",
+                GitEvent::Output { .. } => "output",
+                GitEvent::Done { .. } => "done",
+            };
+            Ok(axum::response::sse::Event::default()
+                .event(name)
+                .data(serde_json::to_string(&event).unwrap_or_default()))
+        });
+
+    Ok(Sse::new(Box::pin(stream) as PinBoxSseStream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("heartbeat"),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/scm/github/clone",
+    tag = "v1",
+    request_body = GithubCloneRequest,
+    responses(
+        (status = 200, description = "Repository cloned", body = GithubCloneResponse),
+        (status = 400, description = "Invalid repo, or neither path nor serverId given", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails),
+        (status = 409, description = "Target directory is not empty", body = ProblemDetails),
+        (status = 502, description = "git clone or checkout failed", body = ProblemDetails)
+    )
+)]
+async fn post_v1_scm_github_clone(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Json(request): Json<GithubCloneRequest>,
+) -> Result<Json<GithubCloneResponse>, ApiError> {
+    if request.path.is_none() && request.server_id.is_none() {
+        return Err(SandboxError::InvalidRequest {
+            message: "path or serverId is required".to_string(),
+        }
+        .into());
+    }
+    let path = request.path.as_deref().unwrap_or(".");
+    let target = resolve_scoped_fs_path(&state, request.server_id.as_deref(), path).await?;
+    let token = scm_github::token(request.token.as_deref());
+    let commit = scm_github::clone_repo(
+        &request.repo,
+        request.git_ref.as_deref(),
+        token.as_deref(),
+        &target,
+    )
+    .await?;
+    let mut entry = fs_audit_entry(AuditKind::FsWrite, actor, request.server_id, &target);
+    entry.action = Some(format!("git clone {}", request.repo));
+    entry.details = json!({ "ref": request.git_ref, "commit": commit });
+    state.audit().record(entry);
+    Ok(Json(GithubCloneResponse {
+        repo: request.repo,
+        path: target.to_string_lossy().to_string(),
+        commit,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/debug/logs",
+    tag = "v1",
+    params(
+        ("level" = Option<String>, Query, description = "Least severe level to return: trace, debug, info, warn or error (default info)"),
+        ("limit" = Option<usize>, Query, description = "Newest records to return (default 500)")
+    ),
+    responses(
+        (status = 200, description = "Recent daemon log records, oldest first", body = DebugLogsResponse),
+        (status = 400, description = "Unknown level", body = ProblemDetails)
+    )
+)]
+async fn get_v1_debug_logs(
+    Query(query): Query<DebugLogsQuery>,
+) -> Result<Json<DebugLogsResponse>, ApiError> {
+    let level = match query.level.as_deref() {
+        None => tracing::Level::INFO,
+        Some(level) => level.parse().map_err(|_| SandboxError::InvalidRequest {
+            message: format!("unknown log level: {level}"),
+        })?,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEBUG_LOGS_DEFAULT_LIMIT)
+        .min(LOG_BUFFER_CAPACITY);
+    Ok(Json(DebugLogsResponse {
+        records: crate::debug_logs::recent(level, limit),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/debug/unparsed",
+    tag = "v1",
+    params(
+        ("serverId" = Option<String>, Query, description = "Only lines from this ACP server"),
+        ("limit" = Option<usize>, Query, description = "Newest lines to return (default 50)")
+    ),
+    responses(
+        (status = 200, description = "Counts of agent output lines that were not JSON and the newest of them, oldest first", body = UnparsedEventsResponse)
+    )
+)]
+async fn get_v1_debug_unparsed(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UnparsedEventsQuery>,
+) -> Json<UnparsedEventsResponse> {
+    let limit = query
+        .limit
+        .unwrap_or(UNPARSED_DEFAULT_LIMIT)
+        .min(crate::unparsed::UNPARSED_CAPACITY);
+    Json(
+        state
+            .acp_proxy()
+            .unparsed_events(query.server_id.as_deref(), limit)
+            .await,
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/debug/rate-limits",
+    tag = "v1",
+    params(
+        ("serverId" = Option<String>, Query, description = "Only rate limits hit by this ACP server"),
+        ("limit" = Option<usize>, Query, description = "Newest rate limits to return (default 50)")
+    ),
+    responses(
+        (status = 200, description = "Counts of provider rate limits agents reported and the newest of them, oldest first", body = RateLimitEventsResponse)
+    )
+)]
+async fn get_v1_debug_rate_limits(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RateLimitEventsQuery>,
+) -> Json<RateLimitEventsResponse> {
+    let limit = query
+        .limit
+        .unwrap_or(RATE_LIMITS_DEFAULT_LIMIT)
+        .min(crate::rate_limits::RATE_LIMIT_CAPACITY);
+    Json(
+        state
+            .acp_proxy()
+            .rate_limit_events(query.server_id.as_deref(), limit)
+            .await,
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/proxy/{port}/{path}",
+    tag = "v1",
+    params(
+        ("port" = u16, Path, description = "Port on 127.0.0.1 inside the sandbox"),
+        ("path" = String, Path, description = "Path forwarded upstream with the query string")
+    ),
+    responses(
+        (status = 200, description = "Upstream response, passed through unchanged. Every method is proxied and WebSocket upgrades are bridged"),
+        (status = 400, description = "Invalid port", body = ProblemDetails),
+        (status = 403, description = "Port is not in the proxy allowlist", body = ProblemDetails),
+        (status = 502, description = "Upstream server is unreachable", body = ProblemDetails)
+    )
+)]
+async fn proxy_v1_port(
+    State(state): State<Arc<AppState>>,
+    ws: Option<WebSocketUpgrade>,
+    request: Request<Body>,
+) -> Result<Response, ApiError> {
+    // Split the raw path rather than using the decoded params so percent-encoding survives.
+    let rest = request
+        .uri()
+        .path()
+        .strip_prefix("/proxy/")
+        .unwrap_or_default();
+    let (raw_port, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let port = raw_port
+        .parse::<u16>()
+        .map_err(|_| SandboxError::InvalidRequest {
+            message: format!("invalid port: {raw_port}"),
+        })?;
+    state.port_proxy().check_port(port)?;
+
+    let prefix = format!("/v1/proxy/{raw_port}");
+    let target = match request.uri().query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_string(),
+    };
+
+    if let Some(ws) = ws {
+        let (upstream, protocol) = connect_websocket(port, &target, request.headers()).await?;
+        let ws = match protocol {
+            Some(protocol) => ws.protocols([protocol]),
+            None => ws,
+        };
+        return Ok(ws.on_upgrade(move |socket| bridge_websocket(socket, upstream)));
+    }
+
+    Ok(state
+        .port_proxy()
+        .forward(port, &target, &prefix, request)
+        .await?)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/mcp",
+    tag = "v1",
+    request_body = AcpEnvelope,
+    responses(
+        (status = 200, description = "MCP JSON-RPC response", body = AcpEnvelope),
+        (status = 202, description = "MCP notification accepted")
+    )
+)]
+async fn post_v1_mcp(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    body: Bytes,
+) -> Response {
+    let message = match serde_json::from_slice::<Value>(&body) {
+        Ok(message) => message,
+        Err(err) => return Json(mcp_server::parse_error(err)).into_response(),
+    };
+    match mcp_server::handle(&state, request_actor(actor), message).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/config/mcp",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("mcpName" = String, Query, description = "MCP entry name")
+    ),
+    responses(
+        (status = 200, description = "MCP entry", body = McpServerConfig),
+        (status = 404, description = "Entry not found", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn get_v1_config_mcp(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<McpConfigQuery>,
+) -> Result<Json<McpServerConfig>, ApiError> {
+    validate_named_query(&query.directory, "directory")?;
+    validate_named_query(&query.mcp_name, "mcpName")?;
+
+    let path = config_file_path(state.fs_roots(), &query.directory, "mcp.json")?;
+    let entries: BTreeMap<String, McpServerConfig> = read_named_config_map(&path)?;
+    let value =
+        entries
+            .get(&query.mcp_name)
+            .cloned()
+            .ok_or_else(|| SandboxError::SessionNotFound {
+                session_id: format!("mcp:{}", query.mcp_name),
+            })?;
+    Ok(Json(value))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/config/mcp",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("mcpName" = String, Query, description = "MCP entry name")
+    ),
+    request_body = McpServerConfig,
+    responses(
+        (status = 204, description = "Stored"),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn put_v1_config_mcp(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<McpConfigQuery>,
+    Json(body): Json<McpServerConfig>,
+) -> Result<StatusCode, ApiError> {
+    validate_named_query(&query.directory, "directory")?;
+    validate_named_query(&query.mcp_name, "mcpName")?;
+
+    let path = config_file_path(state.fs_roots(), &query.directory, "mcp.json")?;
+    let mut entries: BTreeMap<String, McpServerConfig> = read_named_config_map(&path)?;
+    entries.insert(query.mcp_name, body);
+    write_named_config_map(&path, &entries)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/config/mcp",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("mcpName" = String, Query, description = "MCP entry name")
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn delete_v1_config_mcp(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<McpConfigQuery>,
+) -> Result<StatusCode, ApiError> {
+    validate_named_query(&query.directory, "directory")?;
+    validate_named_query(&query.mcp_name, "mcpName")?;
+
+    let path = config_file_path(state.fs_roots(), &query.directory, "mcp.json")?;
+    let mut entries: BTreeMap<String, McpServerConfig> = read_named_config_map(&path)?;
+    entries.remove(&query.mcp_name);
+    write_named_config_map(&path, &entries)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/config/mcp/oauth",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("mcpName" = String, Query, description = "MCP entry name")
+    ),
+    responses(
+        (status = 200, description = "OAuth token state of the MCP entry", body = McpOAuthStatus),
+        (status = 400, description = "Entry is not a remote server with an OAuth config", body = ProblemDetails),
+        (status = 404, description = "Entry not found", body = ProblemDetails)
+    )
+)]
+async fn get_v1_config_mcp_oauth(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<McpConfigQuery>,
+) -> Result<Json<McpOAuthStatus>, ApiError> {
+    let (url, _) = mcp_oauth_entry(&state, &query)?;
+    Ok(Json(state.acp_proxy().mcp_oauth().status(&url)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/config/mcp/oauth",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("mcpName" = String, Query, description = "MCP entry name")
+    ),
+    responses(
+        (status = 200, description = "Token acquired, or device authorization pending", body = McpOAuthStatus),
+        (status = 400, description = "Entry is not a remote server with an OAuth config", body = ProblemDetails),
+        (status = 404, description = "Entry not found", body = ProblemDetails),
+        (status = 502, description = "Authorization server request failed", body = ProblemDetails)
+    )
+)]
+async fn post_v1_config_mcp_oauth(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<McpConfigQuery>,
+) -> Result<Json<McpOAuthStatus>, ApiError> {
+    let (url, config) = mcp_oauth_entry(&state, &query)?;
+    let status = state
+        .acp_proxy()
+        .mcp_oauth()
+        .authorize(&url, &config)
+        .await?;
+    Ok(Json(status))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/config/mcp/oauth",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("mcpName" = String, Query, description = "MCP entry name")
+    ),
+    responses(
+        (status = 204, description = "Token forgotten"),
+        (status = 400, description = "Entry is not a remote server with an OAuth config", body = ProblemDetails),
+        (status = 404, description = "Entry not found", body = ProblemDetails)
+    )
+)]
+async fn delete_v1_config_mcp_oauth(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<McpConfigQuery>,
+) -> Result<StatusCode, ApiError> {
+    let (url, _) = mcp_oauth_entry(&state, &query)?;
+    state.acp_proxy().mcp_oauth().forget(&url)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// URL and OAuth config of a remote MCP entry.
+fn mcp_oauth_entry(
+    state: &AppState,
+    query: &McpConfigQuery,
+) -> Result<(String, McpOAuthConfig), ApiError> {
+    validate_named_query(&query.directory, "directory")?;
+    validate_named_query(&query.mcp_name, "mcpName")?;
+
+    let path = config_file_path(state.fs_roots(), &query.directory, "mcp.json")?;
+    let entries: BTreeMap<String, McpServerConfig> = read_named_config_map(&path)?;
+    match entries.get(&query.mcp_name) {
+        Some(McpServerConfig::Remote {
+            url,
+            oauth: Some(McpOAuthConfigOrDisabled::Config(config)),
+            ..
+        }) => Ok((url.clone(), config.clone())),
+        Some(_) => Err(SandboxError::InvalidRequest {
+            message: format!(
+                "MCP entry {} is not a remote server with an oauth config",
+                query.mcp_name
+            ),
+        }
+        .into()),
+        None => Err(SandboxError::SessionNotFound {
+            session_id: format!("mcp:{}", query.mcp_name),
+        }
+        .into()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/config/skills",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("skillName" = String, Query, description = "Skill entry name")
+    ),
+    responses(
+        (status = 200, description = "Skills entry", body = SkillsConfig),
+        (status = 404, description = "Entry not found", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn get_v1_config_skills(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SkillsConfigQuery>,
+) -> Result<Json<SkillsConfig>, ApiError> {
+    validate_named_query(&query.directory, "directory")?;
+    validate_named_query(&query.skill_name, "skillName")?;
+
+    let path = config_file_path(state.fs_roots(), &query.directory, "skills.json")?;
+    let entries: BTreeMap<String, SkillsConfig> = read_named_config_map(&path)?;
+    let value =
+        entries
+            .get(&query.skill_name)
+            .cloned()
+            .ok_or_else(|| SandboxError::SessionNotFound {
+                session_id: format!("skills:{}", query.skill_name),
+            })?;
+    Ok(Json(value))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/config/skills",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("skillName" = String, Query, description = "Skill entry name")
+    ),
+    request_body = SkillsConfig,
+    responses(
+        (status = 204, description = "Stored"),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn put_v1_config_skills(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SkillsConfigQuery>,
+    Json(body): Json<SkillsConfig>,
+) -> Result<StatusCode, ApiError> {
+    validate_named_query(&query.directory, "directory")?;
+    validate_named_query(&query.skill_name, "skillName")?;
+
+    let path = config_file_path(state.fs_roots(), &query.directory, "skills.json")?;
+    let mut entries: BTreeMap<String, SkillsConfig> = read_named_config_map(&path)?;
+    entries.insert(query.skill_name, body);
+    write_named_config_map(&path, &entries)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/config/skills",
+    tag = "v1",
+    params(
+        ("directory" = String, Query, description = "Target directory"),
+        ("skillName" = String, Query, description = "Skill entry name")
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn delete_v1_config_skills(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SkillsConfigQuery>,
+) -> Result<StatusCode, ApiError> {
+    validate_named_query(&query.directory, "directory")?;
+    validate_named_query(&query.skill_name, "skillName")?;
+
+    let path = config_file_path(state.fs_roots(), &query.directory, "skills.json")?;
+    let mut entries: BTreeMap<String, SkillsConfig> = read_named_config_map(&path)?;
+    entries.remove(&query.skill_name);
+    write_named_config_map(&path, &entries)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/skills",
+    tag = "v1",
+    responses(
+        (status = 200, description = "Installed skills", body = SkillListResponse)
+    )
+)]
+async fn get_v1_skills(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<SkillListResponse>, ApiError> {
+    let skills = state.skills().list()?;
+    Ok(Json(SkillListResponse { skills }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/skills/install",
+    tag = "v1",
+    request_body = SkillSource,
+    responses(
+        (status = 200, description = "Skills installed and synced", body = SkillInstallResponse),
+        (status = 400, description = "Unsupported source, bad archive or checksum, or no matching SKILL.md", body = ProblemDetails),
+        (status = 403, description = "Local path is outside the permitted directories", body = ProblemDetails),
+        (status = 502, description = "Clone or download failed", body = ProblemDetails)
+    )
+)]
+async fn post_v1_skills_install(
+    State(state): State<Arc<AppState>>,
+    Json(mut source): Json<SkillSource>,
+) -> Result<Json<SkillInstallResponse>, ApiError> {
+    let is_url = source.source.starts_with("http://") || source.source.starts_with("https://");
+    if source.source_type == "local" || (source.source_type == "archive" && !is_url) {
+        let path = resolve_scoped_fs_path(&state, None, &source.source).await?;
+        source.source = path.to_string_lossy().to_string();
+    }
+    Ok(Json(state.skills().install(&source).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/skills/{name}",
+    tag = "v1",
+    params(
+        ("name" = String, Path, description = "Installed skill name")
+    ),
+    responses(
+        (status = 204, description = "Skill and its synced copies removed"),
+        (status = 404, description = "Skill not installed", body = ProblemDetails)
+    )
+)]
+async fn delete_v1_skill(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state.skills().remove(&name).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/instructions/templates",
+    tag = "v1",
+    responses(
+        (status = 200, description = "Instruction file templates", body = InstructionTemplateListResponse)
+    )
+)]
+async fn get_v1_instruction_templates(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<InstructionTemplateListResponse>, ApiError> {
+    let templates = state.acp_proxy().instruction_templates().list()?;
+    Ok(Json(InstructionTemplateListResponse { templates }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/instructions/templates/{name}",
+    tag = "v1",
+    params(
+        ("name" = String, Path, description = "Template name")
+    ),
+    responses(
+        (status = 200, description = "Instruction file template", body = InstructionTemplate),
+        (status = 404, description = "Template not found", body = ProblemDetails)
+    )
+)]
+async fn get_v1_instruction_template(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<InstructionTemplate>, ApiError> {
+    Ok(Json(state.acp_proxy().instruction_templates().get(&name)?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/instructions/templates/{name}",
+    tag = "v1",
+    params(
+        ("name" = String, Path, description = "Template name")
+    ),
+    request_body = InstructionTemplateRequest,
+    responses(
+        (status = 200, description = "Template created or replaced", body = InstructionTemplate),
+        (status = 400, description = "Invalid template name", body = ProblemDetails)
+    )
+)]
+async fn put_v1_instruction_template(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(request): Json<InstructionTemplateRequest>,
+) -> Result<Json<InstructionTemplate>, ApiError> {
+    let template = state
+        .acp_proxy()
+        .instruction_templates()
+        .put(&name, request.content)?;
+    Ok(Json(template))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/instructions/templates/{name}",
+    tag = "v1",
+    params(
+        ("name" = String, Path, description = "Template name")
+    ),
+    responses(
+        (status = 204, description = "Template removed"),
+        (status = 404, description = "Template not found", body = ProblemDetails)
+    )
+)]
+async fn delete_v1_instruction_template(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state.acp_proxy().instruction_templates().remove(&name)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/workspaces",
+    tag = "v1",
+    responses(
+        (status = 200, description = "Registered workspaces", body = WorkspaceListResponse)
+    )
+)]
+async fn get_v1_workspaces(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<WorkspaceListResponse>, ApiError> {
+    let workspaces = state.workspaces().list()?;
+    Ok(Json(WorkspaceListResponse { workspaces }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/workspaces",
+    tag = "v1",
+    request_body = WorkspaceCreateRequest,
+    responses(
+        (status = 201, description = "Workspace registered", body = WorkspaceInfo),
+        (status = 400, description = "Invalid id or git URL, or neither path nor gitUrl", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails),
+        (status = 409, description = "Id or directory already registered, or clone target not empty", body = ProblemDetails),
+        (status = 502, description = "Clone failed", body = ProblemDetails)
+    )
+)]
+async fn post_v1_workspaces(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<WorkspaceCreateRequest>,
+) -> Result<(StatusCode, Json<WorkspaceInfo>), ApiError> {
+    let path = request
+        .path
+        .as_deref()
+        .map(|path| prepare_server_directory(state.fs_roots(), path))
+        .transpose()?;
+    let workspace = state.workspaces().create(&request, path).await?;
+    Ok((StatusCode::CREATED, Json(workspace)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/workspaces/{id}",
+    tag = "v1",
+    params(
+        ("id" = String, Path, description = "Workspace id")
+    ),
+    responses(
+        (status = 200, description = "Workspace", body = WorkspaceInfo),
+        (status = 404, description = "Unknown workspace", body = ProblemDetails)
+    )
+)]
+async fn get_v1_workspace(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<WorkspaceInfo>, ApiError> {
+    Ok(Json(state.workspaces().get(&id)?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/workspaces/{id}",
+    tag = "v1",
+    params(
+        ("id" = String, Path, description = "Workspace id")
+    ),
+    responses(
+        (status = 204, description = "Workspace unregistered, and removed when the daemon cloned it"),
+        (status = 404, description = "Unknown workspace", body = ProblemDetails),
+        (status = 409, description = "An ACP server is running in the workspace", body = ProblemDetails)
+    )
+)]
+async fn delete_v1_workspace(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let workspace = state.workspaces().get(&id)?;
+    let root = PathBuf::from(&workspace.path);
+    if let Some(server) = state
+        .acp_proxy()
+        .list_instances()
+        .await
+        .into_iter()
+        .find(|server| {
+            server
+                .directory
+                .as_ref()
+                .is_some_and(|dir| dir.starts_with(&root))
+        })
+    {
+        return Err(SandboxError::Conflict {
+            message: format!(
+                "workspace '{id}' is used by ACP server '{}'",
+                server.server_id
+            ),
+        }
+        .into());
+    }
+    state.workspaces().remove(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp",
+    tag = "v1",
+    params(
+        ("label" = Option<String>, Query, description = "Comma-separated key=value labels; only servers carrying all of them are listed"),
+        ("agent" = Option<String>, Query, description = "Only servers running this agent"),
+        ("createdAfterMs" = Option<i64>, Query, description = "Only servers created after this Unix time in milliseconds"),
+        ("limit" = Option<usize>, Query, description = "Servers per page (1-1000); every match when omitted"),
+        ("cursor" = Option<String>, Query, description = "nextCursor from the previous page"),
+        ("sort" = Option<AcpServerSort>, Query, description = "Sort by serverId (default) or createdAt"),
+        ("order" = Option<SortOrder>, Query, description = "asc (default) or desc"),
+        ("archived" = Option<bool>, Query, description = "List archived servers instead of running ones")
+    ),
+    responses(
+        (status = 200, description = "Active ACP server instances", body = AcpServerListResponse),
+        (status = 400, description = "Invalid label filter", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_servers(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AcpServerListQuery>,
+) -> Result<Json<AcpServerListResponse>, ApiError> {
+    let filter = query
+        .label
+        .as_deref()
+        .map(parse_label_query)
+        .transpose()?
+        .unwrap_or_default();
+    let agent = query
+        .agent
+        .as_deref()
+        .map(|agent| {
+            AgentId::parse(agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+                agent: agent.to_string(),
+            })
+        })
+        .transpose()?;
+    let archived = query.archived.unwrap_or(false);
+    let candidates = if archived {
+        let archive = state.acp_proxy().archive().clone();
+        tokio::task::spawn_blocking(move || archive.list())
+            .await
+            .map_err(|err| SandboxError::StreamError {
+                message: err.to_string(),
+            })?
+    } else {
+        state
+            .acp_proxy()
+            .list_instances()
+            .await
+            .into_iter()
+            .map(acp_server_info)
+            .collect()
+    };
+    let matching = candidates
+        .into_iter()
+        .filter(|server| agent.is_none_or(|agent| server.agent == agent.as_str()))
+        .filter(|server| {
+            query
+                .created_after_ms
+                .is_none_or(|after| server.created_at_ms > after)
+        })
+        .filter(|server| {
+            filter
+                .iter()
+                .all(|(key, value)| server.labels.get(key) == Some(value))
+        })
+        .collect::<Vec<_>>();
+    let (servers, next_cursor) = page_servers(matching, &query)?;
+
+    let warm_pool = if archived {
+        Vec::new()
+    } else {
+        state
+            .acp_proxy()
+            .list_warm_pool()
+            .await
+            .into_iter()
+            .map(|pool| AcpWarmPoolInfo {
+                agent: pool.agent.as_str().to_string(),
+                idle: pool.idle,
+                target: pool.target,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    Ok(Json(AcpServerListResponse {
+        servers,
+        next_cursor,
+        warm_pool,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("agent" = Option<String>, Query, description = "Agent id required for first POST"),
+        ("fromPool" = Option<bool>, Query, description = "Adopt an idle pre-spawned agent process on first POST when available"),
+        ("directory" = Option<String>, Query, description = "Working directory for the agent process on first POST; created if missing and used as the default session/new cwd"),
+        ("workspaceId" = Option<String>, Query, description = "Registered workspace to use as the working directory instead of directory"),
+        ("snapshotTurns" = Option<bool>, Query, description = "Record a workspace diff for session/prompt turns: every turn when set on the bootstrap POST, otherwise this request only"),
+        ("idleTimeoutMs" = Option<u64>, Query, description = "Shut the server down after this many milliseconds without activity, overriding the daemon default on first POST; 0 disables the timeout"),
+        ("cpuLimit" = Option<f64>, Query, description = "CPUs the agent process may use, overriding the daemon default on first POST; 0 removes the limit"),
+        ("memoryLimitMb" = Option<u64>, Query, description = "Memory limit for the agent process in MiB, overriding the daemon default on first POST; 0 removes the limit"),
+        ("processLimit" = Option<u64>, Query, description = "Maximum number of processes the agent may run, overriding the daemon default on first POST; 0 removes the limit"),
+        ("sandboxLevel" = Option<String>, Query, description = "Confine the agent process with Landlock and seccomp on Linux: none, workspace (writes limited to its working and config directories) or strict (reads limited too); overrides the daemon default on first POST"),
+        ("egressAllow" = Option<String>, Query, description = "Comma-separated domains (example.com, *.example.com) and CIDRs the agent may reach through its egress proxy, replacing the daemon list on first POST; empty allows everything not denied"),
+        ("egressDeny" = Option<String>, Query, description = "Comma-separated domains and CIDRs the agent may not reach, replacing the daemon list on first POST"),
+        ("webhookUrl" = Option<String>, Query, description = "http(s) URL that receives this server's webhook events in addition to the global webhook URLs; must resolve to a public address, not loopback, private or link-local; read on first POST"),
+        ("title" = Option<String>, Query, description = "Display title for the server; read on first POST"),
+        ("labels" = Option<String>, Query, description = "Comma-separated key=value labels for the server; read on first POST")
+    ),
+    request_body = AcpEnvelope,
+    responses(
+        (status = 200, description = "JSON-RPC response envelope", body = AcpEnvelope),
+        (status = 202, description = "JSON-RPC notification accepted"),
+        (status = 406, description = "Client does not accept JSON responses", body = ProblemDetails),
+        (status = 415, description = "Unsupported media type", body = ProblemDetails),
+        (status = 400, description = "Invalid ACP envelope", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
+        (status = 409, description = "ACP server bound to different agent or directory", body = ProblemDetails),
+        (status = 504, description = "ACP agent process response timeout", body = ProblemDetails),
+        (status = 403, description = "Path is outside the permitted directories", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Path(server_id): Path<String>,
+    Query(query): Query<AcpPostQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    if !content_type_is(&headers, APPLICATION_JSON) {
+        return Err(SandboxError::UnsupportedMediaType {
+            message: "content-type must be application/json".to_string(),
+        }
+        .into());
+    }
+    if !accept_allows(&headers, APPLICATION_JSON) {
+        return Err(SandboxError::NotAcceptable {
+            message: "accept must allow application/json".to_string(),
+        }
+        .into());
+    }
+
+    let payload =
+        serde_json::from_slice::<Value>(&body).map_err(|err| SandboxError::InvalidRequest {
+            message: format!("invalid JSON body: {err}"),
+        })?;
+
+    let bootstrap_agent = match query.agent {
+        Some(agent) => {
+            Some(
+                AgentId::parse(&agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+                    agent: agent.clone(),
+                })?,
+            )
+        }
+        None => None,
+    };
+
+    let from_pool = query.from_pool.unwrap_or(false);
+    let directory = match (query.workspace_id.as_deref(), query.directory.as_deref()) {
+        (Some(_), Some(_)) => {
+            return Err(SandboxError::InvalidRequest {
+                message: "pass either workspaceId or directory, not both".to_string(),
+            }
+            .into())
+        }
+        (Some(id), None) => Some(PathBuf::from(state.workspaces().get(id)?.path)),
+        (None, Some(directory)) => Some(prepare_server_directory(state.fs_roots(), directory)?),
+        (None, None) => None,
+    };
+    if let Some(url) = query.webhook_url.as_deref() {
+        crate::webhooks::validate_server_url(url)
+            .map_err(|message| SandboxError::InvalidRequest { message })?;
+    }
+    validate_title(query.title.as_deref())?;
+    if query
+        .cpu_limit
+        .is_some_and(|cpu| !cpu.is_finite() || cpu < 0.0)
+    {
+        return Err(SandboxError::InvalidRequest {
+            message: "cpuLimit must be a non-negative number".to_string(),
+        }
+        .into());
+    }
+    let sandbox_level = query
+        .sandbox_level
+        .as_deref()
+        .map(|level| {
+            SandboxLevel::parse(level).ok_or_else(|| SandboxError::InvalidRequest {
+                message: "sandboxLevel must be none, workspace or strict".to_string(),
+            })
+        })
+        .transpose()?;
+    let egress_rules = |name: &str, raw: Option<&str>| {
+        raw.map(|raw| {
+            egress::parse_rules(raw).map_err(|err| SandboxError::InvalidRequest {
+                message: format!("{name}: {err}"),
+            })
+        })
+        .transpose()
+    };
+    let egress_allow = egress_rules("egressAllow", query.egress_allow.as_deref())?;
+    let egress_deny = egress_rules("egressDeny", query.egress_deny.as_deref())?;
+    let metadata = AcpServerMetadata {
+        title: query.title.filter(|title| !title.is_empty()),
+        labels: query
+            .labels
+            .as_deref()
+            .map(parse_label_query)
+            .transpose()?
+            .unwrap_or_default(),
+    };
+
+    let bootstrap = AcpBootstrap {
+        agent: bootstrap_agent,
+        from_pool,
+        directory,
+        snapshot_turns: query.snapshot_turns.unwrap_or(false),
+        idle_timeout: query.idle_timeout_ms.map(Duration::from_millis),
+        webhook_url: query.webhook_url,
+        metadata,
+        resource_limits: ResourceLimits {
+            cpu: query.cpu_limit,
+            memory_mb: query.memory_limit_mb,
+            processes: query.process_limit,
+        },
+        sandbox_level,
+        egress_allow,
+        egress_deny,
+    };
+    // A JSON-RPC response may answer a pending permission or question; audit it once sent.
+    let answered = match payload.get("id") {
+        Some(id) if payload.get("method").is_none() => state
+            .acp_proxy()
+            .pending_approval(&server_id, id)
+            .await
+            .ok(),
+        _ => None,
+    };
+    let audited = answered.map(|approval| {
+        approval_audit_entry(request_actor(actor), &server_id, &approval, &payload)
+    });
+    let outcome = state
+        .acp_proxy()
+        .post(&server_id, bootstrap, payload)
+        .await?;
+    if let Some(entry) = audited {
+        state.audit().record(entry);
+    }
+    match outcome {
+        ProxyPostOutcome::Response(value) => Ok((StatusCode::OK, Json(value)).into_response()),
+        ProxyPostOutcome::Accepted => Ok(StatusCode::ACCEPTED.into_response()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("types" = Option<String>, Query, description = "Comma-separated JSON-RPC methods to include, or `response` for results and errors"),
+        ("kinds" = Option<String>, Query, description = "Comma-separated `sessionUpdate` kinds of `session/update` notifications to include"),
+        ("excludeSynthetic" = Option<bool>, Query, description = "Drop the `_sandboxagent/*` notifications the daemon adds to the stream")
+    ),
+    responses(
+        (status = 200, description = "SSE stream of ACP envelopes. Opens with a retry hint and sends heartbeat comments while idle. Event ids are strictly increasing; reconnecting with Last-Event-ID resumes after that id without duplicates"),
+        (status = 406, description = "Client does not accept SSE responses", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
+        (status = 400, description = "Invalid request", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp(
+    State(state): State<Arc<AppState>>,
+    Path(server_id): Path<String>,
+    Query(query): Query<AcpEventsSseQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<PinBoxSseStream>, ApiError> {
+    if !accept_allows(&headers, TEXT_EVENT_STREAM) {
+        return Err(SandboxError::NotAcceptable {
+            message: "accept must allow text/event-stream".to_string(),
+        }
+        .into());
+    }
+
+    let last_event_id = parse_last_event_id(&headers)?;
+    let filter = EventFilter::new(
+        query.types.as_deref(),
+        query.kinds.as_deref(),
+        query.exclude_synthetic.unwrap_or(false),
+    );
+    let stream = state
+        .acp_proxy()
+        .sse(&server_id, last_event_id, filter)
+        .await?;
+
+    Ok(event_stream_response(stream))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/fork",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "ACP server to fork")
+    ),
+    request_body = AcpForkRequest,
+    responses(
+        (status = 200, description = "New ACP server with the same agent and directory, seeded with the source's event history", body = AcpForkResponse),
+        (status = 400, description = "Invalid fork request", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
+        (status = 409, description = "Target server id already exists", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_fork(
+    State(state): State<Arc<AppState>>,
+    Path(server_id): Path<String>,
+    Json(request): Json<AcpForkRequest>,
+) -> Result<Json<AcpForkResponse>, ApiError> {
+    if request.server_id.trim().is_empty() {
+        return Err(SandboxError::InvalidRequest {
+            message: "serverId must not be empty".to_string(),
+        }
+        .into());
+    }
+    let fork = state
+        .acp_proxy()
+        .fork(&server_id, &request.server_id, request.up_to_sequence)
+        .await?;
+    Ok(Json(AcpForkResponse {
+        server_id: fork.server_id,
+        agent: fork.agent.as_str().to_string(),
+        forked_from: fork.forked_from,
+        history_events: fork.history_events,
+        last_event_id: fork.last_event_id,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/replay",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Running or archived ACP server whose prompts are replayed")
+    ),
+    request_body = AcpReplayRequest,
+    responses(
+        (status = 202, description = "New ACP server started; prompts are sent in the background", body = AcpReplayResponse),
+        (status = 400, description = "Invalid replay request or no recorded prompts", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
+        (status = 409, description = "Target server id already exists", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_replay(
+    State(state): State<Arc<AppState>>,
+    Path(server_id): Path<String>,
+    Json(request): Json<AcpReplayRequest>,
+) -> Result<(StatusCode, Json<AcpReplayResponse>), ApiError> {
+    if request.server_id.trim().is_empty() {
+        return Err(SandboxError::InvalidRequest {
+            message: "serverId must not be empty".to_string(),
+        }
+        .into());
+    }
+    let agent = request
+        .agent
+        .as_deref()
+        .map(|agent| {
+            AgentId::parse(agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+                agent: agent.to_string(),
+            })
+        })
+        .transpose()?;
+    let replay = state
+        .acp_proxy()
+        .replay(&server_id, &request.server_id, agent, request.model)
+        .await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(AcpReplayResponse {
+            server_id: replay.server_id,
+            agent: replay.agent.as_str().to_string(),
+            replayed_from: replay.replayed_from,
+            prompts: replay.prompts,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/usage",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id")
+    ),
+    responses(
+        (status = 200, description = "Token usage and reported cost accumulated by the server", body = AcpUsageInfo),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_usage(
+    State(state): State<Arc<AppState>>,
+    Path(server_id): Path<String>,
+) -> Result<Json<AcpUsageInfo>, ApiError> {
+    let usage = state.acp_proxy().usage(&server_id).await?;
+    Ok(Json(usage_info(usage)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/turns",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id")
+    ),
+    responses(
+        (status = 200, description = "Prompt turns recorded with snapshotTurns, oldest first", body = AcpTurnListResponse),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_turns(
+    State(state): State<Arc<AppState>>,
+    Path(server_id): Path<String>,
+) -> Result<Json<AcpTurnListResponse>, ApiError> {
+    let turns = state.acp_proxy().turns(&server_id).await?;
+    Ok(Json(AcpTurnListResponse {
+        turns: turns.into_iter().map(turn_info).collect(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/turns/{turn_id}/diff",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("turn_id" = u64, Path, description = "Turn id from /v1/acp/{server_id}/turns")
+    ),
+    responses(
+        (status = 200, description = "Unified diff of the files changed during the turn", body = String, content_type = "text/x-diff"),
+        (status = 404, description = "Unknown ACP server or turn", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_turn_diff(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, turn_id)): Path<(String, u64)>,
+) -> Result<Response, ApiError> {
+    let turn = state.acp_proxy().turn(&server_id, turn_id).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/x-diff; charset=utf-8")],
+        turn.diff,
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/checkpoints",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id")
+    ),
+    request_body = AcpCheckpointRequest,
+    responses(
+        (status = 201, description = "Checkpoint of the server directory", body = AcpCheckpointInfo),
+        (status = 403, description = "Server directory is outside the permitted directories", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_checkpoint(
+    State(state): State<Arc<AppState>>,
+    Path(server_id): Path<String>,
+    body: Option<Json<AcpCheckpointRequest>>,
+) -> Result<(StatusCode, Json<AcpCheckpointInfo>), ApiError> {
+    let label = body.and_then(|Json(body)| body.label);
+    let root = state.acp_proxy().workspace_root(&server_id).await?;
+    ensure_within_roots(state.fs_roots(), &root)?;
+    let store = state.checkpoints().clone();
+    let manifest = tokio::task::spawn_blocking(move || store.create(&server_id, &root, label))
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })??;
+    Ok((StatusCode::CREATED, Json(checkpoint_info(&manifest))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/checkpoints",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id")
+    ),
+    responses(
+        (status = 200, description = "Checkpoints of the server, oldest first", body = AcpCheckpointListResponse)
+    )
+)]
+async fn get_v1_acp_checkpoints(
+    State(state): State<Arc<AppState>>,
+    Path(server_id): Path<String>,
+) -> Result<Json<AcpCheckpointListResponse>, ApiError> {
+    let store = state.checkpoints().clone();
+    let manifests = tokio::task::spawn_blocking(move || store.list(&server_id))
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })??;
+    Ok(Json(AcpCheckpointListResponse {
+        checkpoints: manifests.iter().map(checkpoint_info).collect(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/checkpoints/{checkpoint_id}/restore",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("checkpoint_id" = String, Path, description = "Checkpoint id from /v1/acp/{server_id}/checkpoints")
+    ),
+    responses(
+        (status = 200, description = "Server directory restored to the checkpoint", body = AcpCheckpointRestoreResponse),
+        (status = 403, description = "Checkpoint directory is outside the permitted directories", body = ProblemDetails),
+        (status = 404, description = "Unknown checkpoint", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_checkpoint_restore(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, checkpoint_id)): Path<(String, String)>,
+) -> Result<Json<AcpCheckpointRestoreResponse>, ApiError> {
+    let store = state.checkpoints().clone();
+    let manifest = store.get(&server_id, &checkpoint_id)?;
+    ensure_within_roots(state.fs_roots(), &manifest.root)?;
+    let root = manifest.root.to_string_lossy().to_string();
+    let restored = tokio::task::spawn_blocking(move || store.restore(&manifest))
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })??;
+    Ok(Json(AcpCheckpointRestoreResponse {
+        checkpoint_id,
+        root,
+        restored: restored.restored,
+        deleted: restored.deleted,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/tasks",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id")
+    ),
+    responses(
+        (status = 200, description = "The session's latest todo list; empty until its agent sends one", body = AcpTaskListResponse),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_session_tasks(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+) -> Result<Json<AcpTaskListResponse>, ApiError> {
+    let (tasks, sequence) = match state.acp_proxy().task_list(&server_id, &session_id).await? {
+        Some(list) => (list.tasks, Some(list.sequence)),
+        None => (Vec::new(), None),
+    };
+    Ok(Json(AcpTaskListResponse {
+        session_id,
+        tasks,
+        sequence,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/messages/ai-sdk",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id")
+    ),
+    request_body = AcpAiSdkChatRequest,
+    responses(
+        (status = 200, description = "The turn as a Vercel AI SDK UI message stream: SSE data chunks ending with [DONE]"),
+        (status = 400, description = "No user message with text or file parts", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_session_ai_sdk(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+    Json(request): Json<AcpAiSdkChatRequest>,
+) -> Result<Response, ApiError> {
+    let prompt = prompt_from_messages(&request.messages)?;
+    let chunks = prompt_stream(state.acp_proxy(), server_id, session_id, prompt).await?;
+    let stream = chunks
+        .map(|chunk| chunk.to_string())
+        .chain(stream::once(async { "[DONE]".to_string() }))
+        .map(|data| Ok(axum::response::sse::Event::default().data(data)));
+    let sse = Sse::new(Box::pin(stream) as PinBoxSseStream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("heartbeat"),
+    );
+    Ok(([(UI_MESSAGE_STREAM_HEADER, "v1")], sse).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/items",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id")
+    ),
+    responses(
+        (status = 200, description = "Messages, reasoning and tool calls of the session in transcript order", body = AcpItemListResponse),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_session_items(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+) -> Result<Json<AcpItemListResponse>, ApiError> {
+    let items = state
+        .acp_proxy()
+        .session_items(&server_id, &session_id)
+        .await?;
+    Ok(Json(AcpItemListResponse { session_id, items }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/items/{item_id}",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id"),
+        ("item_id" = String, Path, description = "Item id from the item list")
+    ),
+    responses(
+        (status = 200, description = "The item", body = AcpItem),
+        (status = 404, description = "Unknown ACP server or item", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_session_item(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id, item_id)): Path<(String, String, String)>,
+) -> Result<Json<AcpItem>, ApiError> {
+    let item = state
+        .acp_proxy()
+        .session_item(&server_id, &session_id, &item_id)
+        .await?;
+    Ok(Json(item))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/turns",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id")
+    ),
+    responses(
+        (status = 200, description = "Prompt turns of the session with their items, oldest first", body = AcpSessionTurnListResponse),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_session_turns(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+) -> Result<Json<AcpSessionTurnListResponse>, ApiError> {
+    let turns = state
+        .acp_proxy()
+        .session_turns(&server_id, &session_id)
+        .await?;
+    Ok(Json(AcpSessionTurnListResponse { session_id, turns }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/turns/{turn_id}",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id"),
+        ("turn_id" = String, Path, description = "Turn id from the turn list")
+    ),
+    responses(
+        (status = 200, description = "The turn with its items", body = AcpSessionTurn),
+        (status = 404, description = "Unknown ACP server or turn", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_session_turn(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id, turn_id)): Path<(String, String, String)>,
+) -> Result<Json<AcpSessionTurn>, ApiError> {
+    let turn = state
+        .acp_proxy()
+        .session_turn(&server_id, &session_id, &turn_id)
+        .await?;
+    Ok(Json(turn))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/export",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id"),
+        ("format" = Option<AcpTranscriptFormat>, Query, description = "markdown (default), html or json; sarif or junit for the findings the agent reported")
+    ),
+    responses(
+        (status = 200, description = "The session transcript as an attachment", content_type = "text/markdown"),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_session_export(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+    Query(query): Query<AcpTranscriptExportQuery>,
+) -> Result<Response, ApiError> {
+    let items = state
+        .acp_proxy()
+        .session_items(&server_id, &session_id)
+        .await?;
+    let (body, content_type, extension) = match query.format.unwrap_or_default() {
+        AcpTranscriptFormat::Markdown => (
+            transcript::markdown(&session_id, &items),
+            "text/markdown; charset=utf-8",
+            "md",
+        ),
+        AcpTranscriptFormat::Html => (
+            transcript::html(&session_id, &items),
+            "text/html; charset=utf-8",
+            "html",
+        ),
+        AcpTranscriptFormat::Json => {
+            let list = AcpItemListResponse {
+                session_id: session_id.clone(),
+                items,
+            };
+            let json =
+                serde_json::to_string_pretty(&list).map_err(|err| SandboxError::StreamError {
+                    message: err.to_string(),
+                })?;
+            (json, "application/json", "json")
+        }
+        AcpTranscriptFormat::Sarif => {
+            let findings = state
+                .acp_proxy()
+                .session_findings(&server_id, &session_id)
+                .await?;
+            let sarif =
+                serde_json::to_string_pretty(&findings::sarif(&findings)).map_err(|err| {
+                    SandboxError::StreamError {
+                        message: err.to_string(),
+                    }
+                })?;
+            (sarif, "application/sarif+json", "sarif")
+        }
+        AcpTranscriptFormat::Junit => {
+            let findings = state
+                .acp_proxy()
+                .session_findings(&server_id, &session_id)
+                .await?;
+            (
+                findings::junit(&session_id, &findings),
+                "application/xml",
+                "xml",
+            )
+        }
+    };
+    let name = session_id.replace(['"', '\\', '/'], "_");
+    let mut response = body.into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(content_type),
+    );
+    if let Ok(disposition) =
+        header::HeaderValue::from_str(&format!("attachment; filename=\"{name}.{extension}\""))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok(response)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/scm/github/pr",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id")
+    ),
+    request_body = GithubPullRequestRequest,
+    responses(
+        (status = 200, description = "Branch pushed and pull request opened", body = GithubPullRequestResponse),
+        (status = 400, description = "No token, or the working directory is not a GitHub clone", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
+        (status = 409, description = "Nothing to commit, or a pull request for the branch exists", body = ProblemDetails),
+        (status = 502, description = "git or the GitHub API failed", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_github_pr(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+    Json(request): Json<GithubPullRequestRequest>,
+) -> Result<Json<GithubPullRequestResponse>, ApiError> {
+    let dir = state
+        .acp_proxy()
+        .directory(&server_id)
+        .await?
+        .ok_or_else(|| SandboxError::InvalidRequest {
+            message: format!("ACP server '{server_id}' has no working directory"),
+        })?;
+    let items = state
+        .acp_proxy()
+        .session_items(&server_id, &session_id)
+        .await?;
+    let pull_request = scm_github::open_pull_request(&dir, &session_id, &request, &items).await?;
+    let mut entry = audit_entry(AuditKind::PullRequest, request_actor(actor));
+    entry.server_id = Some(server_id);
+    entry.session_id = Some(session_id);
+    entry.action = Some(format!("{}#{}", pull_request.repo, pull_request.number));
+    entry.path = Some(dir.to_string_lossy().to_string());
+    entry.destination = Some(pull_request.url.clone());
+    entry.details = json!({
+        "branch": pull_request.branch,
+        "base": pull_request.base,
+        "commit": pull_request.commit,
+    });
+    state.audit().record(entry);
+    Ok(Json(pull_request))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/items/{item_id}/children",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id"),
+        ("item_id" = String, Path, description = "Tool call id of a sub-agent run")
+    ),
+    responses(
+        (status = 200, description = "The tool call and the tool calls its sub-agent made, oldest first", body = AcpItemChildrenResponse),
+        (status = 404, description = "Unknown ACP server or tool call", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_item_children(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id, item_id)): Path<(String, String, String)>,
+) -> Result<Json<AcpItemChildrenResponse>, ApiError> {
+    let (item, children) = state
+        .acp_proxy()
+        .item_children(&server_id, &session_id, &item_id)
+        .await?;
+    Ok(Json(AcpItemChildrenResponse {
+        session_id,
+        item,
+        children,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/plan/approve",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id")
+    ),
+    request_body = AcpPlanDecisionRequest,
+    responses(
+        (status = 204, description = "Plan approved: the agent's review request was allowed or the session left plan mode"),
+        (status = 400, description = "No mode to leave plan mode for", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
+        (status = 409, description = "The session has no plan to approve", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_plan_approve(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+    body: Option<Json<AcpPlanDecisionRequest>>,
+) -> Result<StatusCode, ApiError> {
+    let request = body.map(|Json(body)| body).unwrap_or_default();
+    let acp_proxy = state.acp_proxy();
+    match acp_proxy.plan_state(&server_id, &session_id).await? {
+        PlanState::Review { request_id } => {
+            answer_plan_review(
+                &state,
+                &request_actor(actor),
+                &server_id,
+                &request_id,
+                true,
+                request.option_id.as_deref(),
+            )
+            .await?;
+        }
+        PlanState::Planning { previous_mode } => {
+            let Some(mode) = request.mode.or(previous_mode) else {
+                return Err(SandboxError::InvalidRequest {
+                    message: format!(
+                        "the mode session '{session_id}' had before plan mode is not known; pass a mode"
+                    ),
+                }
+                .into());
+            };
+            let outcome = acp_proxy
+                .post(
+                    &server_id,
+                    AcpBootstrap::default(),
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": format!("sandboxagent-plan-{session_id}"),
+                        "method": "session/set_mode",
+                        "params": { "sessionId": session_id, "modeId": mode },
+                    }),
+                )
+                .await?;
+            if let ProxyPostOutcome::Response(response) = outcome {
+                if let Some(error) = response.get("error") {
+                    return Err(SandboxError::InvalidRequest {
+                        message: format!("the agent did not switch to mode '{mode}': {error}"),
+                    }
+                    .into());
+                }
+            }
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/plan/reject",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id")
+    ),
+    request_body = AcpPlanDecisionRequest,
+    responses(
+        (status = 204, description = "Plan rejected; the session keeps planning"),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
+        (status = 409, description = "The session has no plan to reject", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_plan_reject(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+    body: Option<Json<AcpPlanDecisionRequest>>,
+) -> Result<StatusCode, ApiError> {
+    let request = body.map(|Json(body)| body).unwrap_or_default();
+    let acp_proxy = state.acp_proxy();
+    match acp_proxy.plan_state(&server_id, &session_id).await? {
+        PlanState::Review { request_id } => {
+            answer_plan_review(
+                &state,
+                &request_actor(actor),
+                &server_id,
+                &request_id,
+                false,
+                request.option_id.as_deref(),
+            )
+            .await?;
+        }
+        PlanState::Planning { .. } => acp_proxy.reject_plan(&server_id, &session_id).await?,
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/acp/{server_id}/sessions/{session_id}/compact",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("session_id" = String, Path, description = "ACP session id")
+    ),
+    responses(
+        (status = 200, description = "The agent compacted the session's context", body = AcpCompactResponse),
+        (status = 400, description = "The agent has no native compaction", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
+        (status = 502, description = "The agent failed the compaction turn", body = ProblemDetails)
+    )
+)]
+async fn post_v1_acp_session_compact(
+    State(state): State<Arc<AppState>>,
+    Path((server_id, session_id)): Path<(String, String)>,
+) -> Result<Json<AcpCompactResponse>, ApiError> {
+    Ok(Json(
+        state.acp_proxy().compact(&server_id, &session_id).await?,
+    ))
+}
+
+/// Answer the agent's plan review permission request, like an approval reply.
+async fn answer_plan_review(
+    state: &AppState,
+    actor: &str,
+    server_id: &str,
+    request_id: &Value,
+    approve: bool,
+    option_id: Option<&str>,
+) -> Result<(), SandboxError> {
+    let acp_proxy = state.acp_proxy();
+    let approval = acp_proxy.pending_approval(server_id, request_id).await?;
+    let decision = match approve {
+        true => approvals::ApprovalDecision::Once,
+        false => approvals::ApprovalDecision::Reject,
+    };
+    let response = approval.response(decision, option_id, None);
+    let entry = approval_audit_entry(actor.to_string(), server_id, &approval, &response);
+    acp_proxy
+        .post(server_id, AcpBootstrap::default(), response)
+        .await?;
+    state.audit().record(entry);
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/approvals",
+    tag = "v1",
+    responses(
+        (status = 200, description = "Permission and question requests still waiting on a client across every ACP server, oldest first", body = ApprovalListResponse)
+    )
+)]
+async fn get_v1_approvals(State(state): State<Arc<AppState>>) -> Json<ApprovalListResponse> {
+    let now = now_ms();
+    let approvals = state
+        .acp_proxy()
+        .pending_approvals()
+        .await
+        .into_iter()
+        .map(|(server_id, agent, approval)| approval_info(server_id, agent, approval, now))
+        .collect();
+    Json(ApprovalListResponse { approvals })
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/approvals/reply",
+    tag = "v1",
+    request_body = ApprovalReplyRequest,
+    responses(
+        (status = 200, description = "One result per reply; replies that fail do not stop the others", body = ApprovalReplyResponse),
+        (status = 400, description = "Invalid request body", body = ProblemDetails)
+    )
+)]
+async fn post_v1_approvals_reply(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<RequestActor>>,
+    Json(request): Json<ApprovalReplyRequest>,
+) -> Json<ApprovalReplyResponse> {
+    let actor = request_actor(actor);
+    let mut results = Vec::with_capacity(request.replies.len());
+    for reply in request.replies {
+        let outcome = reply_to_approval(&state, &actor, &reply).await;
+        results.push(ApprovalReplyResult {
+            server_id: reply.server_id,
+            request_id: reply.request_id,
+            ok: outcome.is_ok(),
+            error: outcome.err().map(|err| problem_from_sandbox_error(&err)),
+        });
+    }
+    Json(ApprovalReplyResponse { results })
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/approvals/callback",
+    tag = "v1",
+    request_body = ApprovalCallbackRequest,
+    responses(
+        (status = 204, description = "Reply sent to the agent"),
+        (status = 400, description = "Approval routing is not configured", body = ProblemDetails),
+        (status = 401, description = "Invalid approval token", body = ProblemDetails),
+        (status = 404, description = "The request is no longer pending", body = ProblemDetails)
+    )
+)]
+async fn post_v1_approvals_callback(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ApprovalCallbackRequest>,
+) -> Result<StatusCode, ApiError> {
+    let acp_proxy = state.acp_proxy();
+    let (token, approval) = acp_proxy.release_routed_approval(&request.token).await?;
+    let decision = match request.reply {
+        ApprovalDecision::Once => approvals::ApprovalDecision::Once,
+        ApprovalDecision::Always => approvals::ApprovalDecision::Always,
+        ApprovalDecision::Reject => approvals::ApprovalDecision::Reject,
+    };
+    let response = approval.response(decision, request.option_id.as_deref(), None);
+    let entry = approval_audit_entry(
+        "approver".to_string(),
+        &token.server_id,
+        &approval,
+        &response,
+    );
+    if let Err(err) = acp_proxy
+        .post(&token.server_id, AcpBootstrap::default(), response)
+        .await
+    {
+        acp_proxy.restore_routed_approval(&token).await;
+        return Err(err.into());
+    }
+    state.audit().record(entry);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn reply_to_approval(
+    state: &AppState,
+    actor: &str,
+    reply: &ApprovalReply,
+) -> Result<(), SandboxError> {
+    let acp_proxy = state.acp_proxy();
+    let approval = acp_proxy
+        .pending_approval(&reply.server_id, &reply.request_id)
+        .await?;
+    let decision = match reply.reply {
+        ApprovalDecision::Once => approvals::ApprovalDecision::Once,
+        ApprovalDecision::Always => approvals::ApprovalDecision::Always,
+        ApprovalDecision::Reject => approvals::ApprovalDecision::Reject,
+    };
+    let response = approval.response(decision, reply.option_id.as_deref(), reply.answers.clone());
+    let entry = approval_audit_entry(actor.to_string(), &reply.server_id, &approval, &response);
+    acp_proxy
+        .post(&reply.server_id, AcpBootstrap::default(), response)
+        .await?;
+    state.audit().record(entry);
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/audit",
+    tag = "v1",
+    params(
+        ("serverId" = Option<String>, Query, description = "Only entries for this ACP server"),
+        ("sessionId" = Option<String>, Query, description = "Only entries for this ACP session"),
+        ("kind" = Option<AuditKind>, Query, description = "Only entries of this kind"),
+        ("sinceMs" = Option<i64>, Query, description = "Only entries at or after this Unix time in milliseconds"),
+        ("untilMs" = Option<i64>, Query, description = "Only entries before this Unix time in milliseconds"),
+        ("limit" = Option<usize>, Query, description = "Maximum entries to return, most recent kept (default 500, max 10000)")
+    ),
+    responses(
+        (status = 200, description = "Audit log entries, oldest first", body = AuditLogResponse),
+        (status = 400, description = "Invalid query", body = ProblemDetails)
+    )
+)]
+async fn get_v1_audit(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditLogResponse>, ApiError> {
+    let entries = tokio::task::spawn_blocking(move || state.audit().query(&query))
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })??;
+    Ok(Json(AuditLogResponse { entries }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/blobs/{blob_id}",
+    tag = "v1",
+    params(("blob_id" = String, Path, description = "SHA-256 blob id from an ACP message's `_meta[\"sandboxagent.dev\"].blobId`")),
+    responses(
+        (status = 200, description = "Blob content with the MIME type the agent reported"),
+        (status = 404, description = "Unknown blob", body = ProblemDetails)
+    )
+)]
+async fn get_v1_blob(
+    State(state): State<Arc<AppState>>,
+    Path(blob_id): Path<String>,
+) -> Result<Response, ApiError> {
+    let (bytes, mime) =
+        tokio::task::spawn_blocking(move || state.acp_proxy().blobs().read(&blob_id))
+            .await
+            .map_err(|err| SandboxError::StreamError {
+                message: err.to_string(),
+            })??;
+    let mime = mime.unwrap_or_else(|| "application/octet-stream".to_string());
+    Ok((
+        [
+            (header::CONTENT_TYPE, mime),
+            // Ids are content hashes, so a blob never changes.
+            (
+                header::CACHE_CONTROL,
+                "private, max-age=31536000, immutable".to_string(),
+            ),
+        ],
+        Bytes::from(bytes),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/events/sse",
+    tag = "v1",
+    params(
+        ("agents" = Option<String>, Query, description = "Comma-separated agent ids to include"),
+        ("types" = Option<String>, Query, description = "Comma-separated JSON-RPC methods to include, or `response` for results and errors"),
+        ("kinds" = Option<String>, Query, description = "Comma-separated `sessionUpdate` kinds of `session/update` notifications to include"),
+        ("excludeSynthetic" = Option<bool>, Query, description = "Drop the `_sandboxagent/*` notifications the daemon adds to the stream")
+    ),
+    responses(
+        (status = 200, description = "SSE stream of events from every ACP server, each data payload an AcpFirehoseEventData. Opens with a retry hint and sends heartbeat comments while idle. Event ids are daemon-wide and strictly increasing; reconnecting with Last-Event-ID resumes after that id from the most recent 1024 events"),
+        (status = 406, description = "Client does not accept SSE responses", body = ProblemDetails),
+        (status = 400, description = "Unknown agent in filter", body = ProblemDetails)
+    )
+)]
+async fn get_v1_events_sse(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AcpFirehoseQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<PinBoxSseStream>, ApiError> {
+    if !accept_allows(&headers, TEXT_EVENT_STREAM) {
+        return Err(SandboxError::NotAcceptable {
+            message: "accept must allow text/event-stream".to_string(),
+        }
+        .into());
+    }
+
+    let agents = match query.agents.as_deref() {
+        Some(raw) => Some(
+            split_csv(raw)
+                .map(|agent| {
+                    AgentId::parse(agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+                        agent: agent.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
+    let filter = EventFilter::new(
+        query.types.as_deref(),
+        query.kinds.as_deref(),
+        query.exclude_synthetic.unwrap_or(false),
+    );
+
+    let last_event_id = parse_last_event_id(&headers)?;
+    let stream = state
+        .acp_proxy()
+        .firehose()
+        .stream(last_event_id)
+        .filter_map(move |item| {
+            let event = match item {
+                FirehoseItem::Event(id, event) => Some(event)
+                    .filter(|event| {
+                        agents
+                            .as_ref()
+                            .is_none_or(|agents| agents.contains(&event.agent))
+                            && filter.matches(&event.message)
+                    })
+                    .map(firehose_event_data)
+                    .map(|data| {
+                        axum::response::sse::Event::default()
+                            .event("message")
+                            .id(id.to_string())
+                            .data(serde_json::to_string(&data).unwrap_or_default())
+                    }),
+                FirehoseItem::Resync { after, missed } => Some(
+                    axum::response::sse::Event::default()
+                        .event(RESYNC_EVENT)
+                        .data(json!({ "after": after, "missed": missed }).to_string()),
+                ),
+            };
+            async move { event.map(Ok) }
+        });
+
+    Ok(event_stream_response(Box::pin(stream)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/fanout",
+    tag = "v1",
+    request_body = AcpFanoutRequest,
     responses(
-        (status = 204, description = "Stored")
+        (status = 202, description = "Runs started; each sends the prompt in the background", body = AcpFanoutInfo),
+        (status = 400, description = "Invalid fanout request", body = ProblemDetails),
+        (status = 403, description = "Directory is outside the permitted directories", body = ProblemDetails),
+        (status = 409, description = "Fanout or server id already exists", body = ProblemDetails)
     )
 )]
-async fn put_v1_config_mcp(
-    Query(query): Query<McpConfigQuery>,
-    Json(body): Json<McpServerConfig>,
-) -> Result<StatusCode, ApiError> {
-    validate_named_query(&query.directory, "directory")?;
-    validate_named_query(&query.mcp_name, "mcpName")?;
+async fn post_v1_fanout(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<AcpFanoutRequest>,
+) -> Result<(StatusCode, Json<AcpFanoutInfo>), ApiError> {
+    if request.fanout_id.is_empty() {
+        return Err(SandboxError::InvalidRequest {
+            message: "fanoutId must not be empty".to_string(),
+        }
+        .into());
+    }
+    validate_label("fanout", Some(&request.fanout_id))?;
+    if request.prompt.is_empty() {
+        return Err(SandboxError::InvalidRequest {
+            message: "prompt must not be empty".to_string(),
+        }
+        .into());
+    }
+    if request.runs.is_empty() || request.runs.len() > MAX_FANOUT_RUNS {
+        return Err(SandboxError::InvalidRequest {
+            message: format!("runs must have 1 to {MAX_FANOUT_RUNS} entries"),
+        }
+        .into());
+    }
 
-    let path = config_file_path(&query.directory, "mcp.json")?;
-    let mut entries: BTreeMap<String, McpServerConfig> = read_named_config_map(&path)?;
-    entries.insert(query.mcp_name, body);
-    write_named_config_map(&path, &entries)?;
-    Ok(StatusCode::NO_CONTENT)
+    let mut specs = Vec::with_capacity(request.runs.len());
+    for (index, run) in request.runs.into_iter().enumerate() {
+        let agent = AgentId::parse(&run.agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+            agent: run.agent.clone(),
+        })?;
+        let directory = run
+            .directory
+            .or_else(|| request.directory.clone())
+            .map(|directory| prepare_server_directory(state.fs_roots(), &directory))
+            .transpose()?;
+        let server_id = run
+            .server_id
+            .filter(|server_id| !server_id.trim().is_empty())
+            .unwrap_or_else(|| format!("{}-{}", request.fanout_id, index + 1));
+        if specs
+            .iter()
+            .any(|spec: &FanoutRunSpec| spec.server_id == server_id)
+        {
+            return Err(SandboxError::InvalidRequest {
+                message: format!("server id '{server_id}' is used by more than one run"),
+            }
+            .into());
+        }
+        specs.push(FanoutRunSpec {
+            server_id,
+            agent,
+            model: run.model,
+            directory,
+        });
+    }
+
+    let fanout = start_fanout(
+        &state.acp_proxy(),
+        &request.fanout_id,
+        Value::Array(request.prompt),
+        specs,
+    )
+    .await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(fanout_info(&state, &fanout).await),
+    ))
 }
 
 #[utoipa::path(
-    delete,
-    path = "/v1/config/mcp",
+    get,
+    path = "/v1/fanout/{fanout_id}",
     tag = "v1",
-    params(
-        ("directory" = String, Query, description = "Target directory"),
-        ("mcpName" = String, Query, description = "MCP entry name")
-    ),
+    params(("fanout_id" = String, Path, description = "Fanout id")),
     responses(
-        (status = 204, description = "Deleted")
+        (status = 200, description = "Status, stop reason, duration and usage of each run", body = AcpFanoutInfo),
+        (status = 404, description = "Unknown fanout", body = ProblemDetails)
     )
 )]
-async fn delete_v1_config_mcp(Query(query): Query<McpConfigQuery>) -> Result<StatusCode, ApiError> {
-    validate_named_query(&query.directory, "directory")?;
-    validate_named_query(&query.mcp_name, "mcpName")?;
-
-    let path = config_file_path(&query.directory, "mcp.json")?;
-    let mut entries: BTreeMap<String, McpServerConfig> = read_named_config_map(&path)?;
-    entries.remove(&query.mcp_name);
-    write_named_config_map(&path, &entries)?;
-    Ok(StatusCode::NO_CONTENT)
+async fn get_v1_fanout(
+    State(state): State<Arc<AppState>>,
+    Path(fanout_id): Path<String>,
+) -> Result<Json<AcpFanoutInfo>, ApiError> {
+    let fanout = state.acp_proxy().fanouts().get(&fanout_id)?;
+    Ok(Json(fanout_info(&state, &fanout).await))
 }
 
 #[utoipa::path(
     get,
-    path = "/v1/config/skills",
+    path = "/v1/fanout/{fanout_id}/events",
     tag = "v1",
-    params(
-        ("directory" = String, Query, description = "Target directory"),
-        ("skillName" = String, Query, description = "Skill entry name")
-    ),
+    params(("fanout_id" = String, Path, description = "Fanout id")),
     responses(
-        (status = 200, description = "Skills entry", body = SkillsConfig),
-        (status = 404, description = "Entry not found", body = ProblemDetails)
+        (status = 200, description = "SSE stream of the runs' buffered and live events, each data payload an AcpFirehoseEventData"),
+        (status = 404, description = "Unknown fanout", body = ProblemDetails),
+        (status = 406, description = "Client does not accept SSE responses", body = ProblemDetails)
     )
 )]
-async fn get_v1_config_skills(
-    Query(query): Query<SkillsConfigQuery>,
-) -> Result<Json<SkillsConfig>, ApiError> {
-    validate_named_query(&query.directory, "directory")?;
-    validate_named_query(&query.skill_name, "skillName")?;
+async fn get_v1_fanout_events(
+    State(state): State<Arc<AppState>>,
+    Path(fanout_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Sse<PinBoxSseStream>, ApiError> {
+    if !accept_allows(&headers, TEXT_EVENT_STREAM) {
+        return Err(SandboxError::NotAcceptable {
+            message: "accept must allow text/event-stream".to_string(),
+        }
+        .into());
+    }
+    let fanout = state.acp_proxy().fanouts().get(&fanout_id)?;
+    let mut streams = Vec::new();
+    for run in &fanout.runs {
+        // Runs whose server has not started yet or was deleted are left out.
+        let Ok((agent, events)) = state
+            .acp_proxy()
+            .sequenced_events(&run.spec.server_id)
+            .await
+        else {
+            continue;
+        };
+        let server_id = run.spec.server_id.clone();
+        streams.push(Box::pin(events.map(move |(sequence, message)| {
+            let data = firehose_event_data(AcpFirehoseEvent {
+                server_id: server_id.clone(),
+                agent,
+                sequence,
+                message,
+            });
+            Ok(axum::response::sse::Event::default()
+                .event("message")
+                .data(serde_json::to_string(&data).unwrap_or_default()))
+        })));
+    }
+    let stream = stream::select_all(streams);
 
-    let path = config_file_path(&query.directory, "skills.json")?;
-    let entries: BTreeMap<String, SkillsConfig> = read_named_config_map(&path)?;
-    let value =
-        entries
-            .get(&query.skill_name)
-            .cloned()
-            .ok_or_else(|| SandboxError::SessionNotFound {
-                session_id: format!("skills:{}", query.skill_name),
-            })?;
-    Ok(Json(value))
+    Ok(Sse::new(Box::pin(stream) as PinBoxSseStream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("heartbeat"),
+    ))
 }
 
 #[utoipa::path(
-    put,
-    path = "/v1/config/skills",
+    delete,
+    path = "/v1/fanout/{fanout_id}",
     tag = "v1",
-    params(
-        ("directory" = String, Query, description = "Target directory"),
-        ("skillName" = String, Query, description = "Skill entry name")
-    ),
-    request_body = SkillsConfig,
+    params(("fanout_id" = String, Path, description = "Fanout id")),
     responses(
-        (status = 204, description = "Stored")
+        (status = 204, description = "Fanout forgotten and its running servers closed"),
+        (status = 404, description = "Unknown fanout", body = ProblemDetails)
     )
 )]
-async fn put_v1_config_skills(
-    Query(query): Query<SkillsConfigQuery>,
-    Json(body): Json<SkillsConfig>,
+async fn delete_v1_fanout(
+    State(state): State<Arc<AppState>>,
+    Path(fanout_id): Path<String>,
 ) -> Result<StatusCode, ApiError> {
-    validate_named_query(&query.directory, "directory")?;
-    validate_named_query(&query.skill_name, "skillName")?;
-
-    let path = config_file_path(&query.directory, "skills.json")?;
-    let mut entries: BTreeMap<String, SkillsConfig> = read_named_config_map(&path)?;
-    entries.insert(query.skill_name, body);
-    write_named_config_map(&path, &entries)?;
+    let fanout = state
+        .acp_proxy()
+        .fanouts()
+        .remove(&fanout_id)
+        .ok_or_else(|| SandboxError::NotFound {
+            path: format!("/v1/fanout/{fanout_id}"),
+        })?;
+    // Servers that already ended keep their archives.
+    let running = state.acp_proxy().list_instances().await;
+    for run in &fanout.runs {
+        if running
+            .iter()
+            .any(|info| info.server_id == run.spec.server_id)
+        {
+            state.acp_proxy().delete(&run.spec.server_id).await?;
+        }
+    }
     Ok(StatusCode::NO_CONTENT)
 }
 
 #[utoipa::path(
-    delete,
-    path = "/v1/config/skills",
+    post,
+    path = "/v1/runs",
     tag = "v1",
-    params(
-        ("directory" = String, Query, description = "Target directory"),
-        ("skillName" = String, Query, description = "Skill entry name")
-    ),
+    request_body = AcpRunRequest,
     responses(
-        (status = 204, description = "Deleted")
+        (status = 200, description = "The run ended; `status` says whether the turn completed, failed or timed out", body = AcpRunResponse),
+        (status = 400, description = "Invalid run request", body = ProblemDetails),
+        (status = 403, description = "Directory is outside the permitted directories", body = ProblemDetails),
+        (status = 409, description = "Server id already exists", body = ProblemDetails)
     )
 )]
-async fn delete_v1_config_skills(
-    Query(query): Query<SkillsConfigQuery>,
-) -> Result<StatusCode, ApiError> {
-    validate_named_query(&query.directory, "directory")?;
-    validate_named_query(&query.skill_name, "skillName")?;
+async fn post_v1_runs(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<AcpRunRequest>,
+) -> Result<Json<AcpRunResponse>, ApiError> {
+    if request.prompt.is_empty() {
+        return Err(SandboxError::InvalidRequest {
+            message: "prompt must not be empty".to_string(),
+        }
+        .into());
+    }
+    let agent = AgentId::parse(&request.agent).ok_or_else(|| SandboxError::UnsupportedAgent {
+        agent: request.agent.clone(),
+    })?;
+    let request_timeout = state.acp_proxy().request_timeout();
+    let timeout = request
+        .timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(request_timeout)
+        .min(request_timeout);
+    if timeout.is_zero() {
+        return Err(SandboxError::InvalidRequest {
+            message: "timeoutMs must be greater than 0".to_string(),
+        }
+        .into());
+    }
+    let directory = request
+        .directory
+        .map(|directory| prepare_server_directory(state.fs_roots(), &directory))
+        .transpose()?;
+    let server_id = request
+        .server_id
+        .filter(|server_id| !server_id.trim().is_empty())
+        .unwrap_or_else(next_run_server_id);
 
-    let path = config_file_path(&query.directory, "skills.json")?;
-    let mut entries: BTreeMap<String, SkillsConfig> = read_named_config_map(&path)?;
-    entries.remove(&query.skill_name);
-    write_named_config_map(&path, &entries)?;
-    Ok(StatusCode::NO_CONTENT)
+    let spec = RunSpec {
+        server_id,
+        agent,
+        model: request.model,
+        directory,
+        prompt: Value::Array(request.prompt),
+        output_schema: request.output_schema,
+        timeout,
+    };
+    let report = run_prompt(&state.acp_proxy(), &spec).await?;
+    if !request.keep_server {
+        state.acp_proxy().delete(&spec.server_id).await?;
+    }
+    Ok(Json(run_response(spec, report)))
 }
 
 #[utoipa::path(
-    get,
-    path = "/v1/acp",
+    post,
+    path = "/v1/chat/completions",
     tag = "v1",
+    request_body = ChatCompletionRequest,
     responses(
-        (status = 200, description = "Active ACP server instances", body = AcpServerListResponse)
+        (status = 200, description = "The agent's reply as an OpenAI chat completion, or with `stream: true` SSE chat.completion.chunk data ending with [DONE]", body = ChatCompletionResponse),
+        (status = 400, description = "No agent for the model, or the last message is not a user message", body = ProblemDetails),
+        (status = 502, description = "The agent failed to answer", body = ProblemDetails),
+        (status = 504, description = "The turn did not finish in time", body = ProblemDetails)
     )
 )]
-async fn get_v1_acp_servers(
+async fn post_v1_chat_completions(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<AcpServerListResponse>, ApiError> {
-    let servers = state
-        .acp_proxy()
-        .list_instances()
-        .await
-        .into_iter()
-        .map(|instance| AcpServerInfo {
-            server_id: instance.server_id,
-            agent: instance.agent.as_str().to_string(),
-            created_at_ms: instance.created_at_ms,
-        })
-        .collect::<Vec<_>>();
+    Json(request): Json<ChatCompletionRequest>,
+) -> Result<Response, ApiError> {
+    let (agent, model) = state.chat_defaults().resolve(request.model.as_deref())?;
+    let prompt = prompt_from_chat(&request.messages)?;
+    let model_name = request.model.clone().unwrap_or_else(|| match &model {
+        Some(model) => format!("{}:{model}", agent.as_str()),
+        None => agent.as_str().to_string(),
+    });
+    let spec = RunSpec {
+        server_id: next_run_server_id(),
+        agent,
+        model,
+        directory: None,
+        prompt: Value::Array(prompt),
+        output_schema: None,
+        timeout: state.acp_proxy().request_timeout(),
+    };
 
-    Ok(Json(AcpServerListResponse { servers }))
+    if request.stream {
+        let include_usage = request
+            .stream_options
+            .is_some_and(|options| options.include_usage);
+        let chunks = completion_stream(state.acp_proxy(), spec, model_name, include_usage).await?;
+        let stream = chunks
+            .map(|chunk| chunk.to_string())
+            .chain(stream::once(async { "[DONE]".to_string() }))
+            .map(|data| Ok(axum::response::sse::Event::default().data(data)));
+        let sse = Sse::new(Box::pin(stream) as PinBoxSseStream).keep_alive(
+            KeepAlive::new()
+                .interval(Duration::from_secs(15))
+                .text("heartbeat"),
+        );
+        return Ok(sse.into_response());
+    }
+
+    let response = completion(&state.acp_proxy(), &spec, model_name).await?;
+    Ok(Json(response).into_response())
 }
 
 #[utoipa::path(
-    post,
-    path = "/v1/acp/{server_id}",
+    get,
+    path = "/v1/acp/{server_id}/events.jsonl",
     tag = "v1",
     params(
         ("server_id" = String, Path, description = "Client-defined ACP server id"),
-        ("agent" = Option<String>, Query, description = "Agent id required for first POST")
+        ("after" = Option<u64>, Query, description = "Only export events with a sequence greater than this"),
+        ("types" = Option<String>, Query, description = "Comma-separated JSON-RPC methods to include, or `response` for results and errors"),
+        ("kinds" = Option<String>, Query, description = "Comma-separated `sessionUpdate` kinds of `session/update` notifications to include"),
+        ("excludeSynthetic" = Option<bool>, Query, description = "Drop the `_sandboxagent/*` notifications the daemon adds to the stream")
     ),
-    request_body = AcpEnvelope,
     responses(
-        (status = 200, description = "JSON-RPC response envelope", body = AcpEnvelope),
-        (status = 202, description = "JSON-RPC notification accepted"),
-        (status = 406, description = "Client does not accept JSON responses", body = ProblemDetails),
-        (status = 415, description = "Unsupported media type", body = ProblemDetails),
-        (status = 400, description = "Invalid ACP envelope", body = ProblemDetails),
-        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
-        (status = 409, description = "ACP server bound to different agent", body = ProblemDetails),
-        (status = 504, description = "ACP agent process response timeout", body = ProblemDetails)
+        (status = 200, description = "Buffered ACP envelopes as newline-delimited JSON, gzip-encoded when accepted; read from the archive for ended servers. Only the last 1024 events of a server are kept, so this is not a full history: when events after `after` had already been dropped, `x-sandbox-agent-truncated: true` is set and `x-sandbox-agent-first-sequence` names the first event sent", content_type = "application/x-ndjson",
+            headers(
+                ("x-sandbox-agent-first-sequence" = u64, description = "Sequence of the first event in the export, when there is one"),
+                ("x-sandbox-agent-truncated" = bool, description = "`true` when events after `after` were dropped before the export")
+            )
+        ),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
     )
 )]
-async fn post_v1_acp(
+async fn get_v1_acp_events_jsonl(
     State(state): State<Arc<AppState>>,
     Path(server_id): Path<String>,
-    Query(query): Query<AcpPostQuery>,
+    Query(query): Query<AcpEventsExportQuery>,
     headers: HeaderMap,
-    body: Bytes,
 ) -> Result<Response, ApiError> {
-    if !content_type_is(&headers, APPLICATION_JSON) {
-        return Err(SandboxError::UnsupportedMediaType {
-            message: "content-type must be application/json".to_string(),
+    let (first_sequence, events) = match state
+        .acp_proxy()
+        .buffered_event_stream(&server_id, query.after)
+        .await
+    {
+        Ok((first_sequence, events)) => (first_sequence, events.boxed()),
+        Err(SandboxError::SessionNotFound { .. }) if state.acp_proxy().archive().is_enabled() => {
+            let events = archived_events(&state, server_id, query.after).await?;
+            let first_sequence = events.first().map(|(sequence, _)| *sequence);
+            (first_sequence, stream::iter(events).boxed())
         }
-        .into());
-    }
-    if !accept_allows(&headers, APPLICATION_JSON) {
-        return Err(SandboxError::NotAcceptable {
-            message: "accept must allow application/json".to_string(),
+        Err(err) => return Err(err.into()),
+    };
+    let filter = EventFilter::new(
+        query.types.as_deref(),
+        query.kinds.as_deref(),
+        query.exclude_synthetic.unwrap_or(false),
+    );
+    let lines = events
+        .filter(move |(_, message)| std::future::ready(filter.matches(message)))
+        .map(|(sequence, message)| event_line(sequence, message).into_bytes());
+
+    let mut response = if accept_encoding_allows(&headers, "gzip") {
+        let mut response = Body::from_stream(gzip_stream(lines)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static("gzip"),
+        );
+        response
+    } else {
+        Body::from_stream(lines.map(|line| Ok::<_, std::convert::Infallible>(Bytes::from(line))))
+            .into_response()
+    };
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(APPLICATION_NDJSON),
+    );
+    if let Some(first_sequence) = first_sequence {
+        response
+            .headers_mut()
+            .insert(FIRST_SEQUENCE_HEADER, HeaderValue::from(first_sequence));
+        if first_sequence > query.after.unwrap_or(0) + 1 {
+            response
+                .headers_mut()
+                .insert(TRUNCATED_HEADER, HeaderValue::from_static("true"));
         }
-        .into());
     }
+    Ok(response)
+}
 
-    let payload =
-        serde_json::from_slice::<Value>(&body).map_err(|err| SandboxError::InvalidRequest {
-            message: format!("invalid JSON body: {err}"),
-        })?;
-
-    let bootstrap_agent = match query.agent {
-        Some(agent) => {
-            Some(
-                AgentId::parse(&agent).ok_or_else(|| SandboxError::UnsupportedAgent {
-                    agent: agent.clone(),
-                })?,
-            )
+#[utoipa::path(
+    get,
+    path = "/v1/acp/{server_id}/events/ndjson",
+    tag = "v1",
+    params(
+        ("server_id" = String, Path, description = "Client-defined ACP server id"),
+        ("offset" = Option<u64>, Query, description = "Only stream events with a sequence greater than this"),
+        ("types" = Option<String>, Query, description = "Comma-separated JSON-RPC methods to include, or `response` for results and errors"),
+        ("kinds" = Option<String>, Query, description = "Comma-separated `sessionUpdate` kinds of `session/update` notifications to include"),
+        ("excludeSynthetic" = Option<bool>, Query, description = "Drop the `_sandboxagent/*` notifications the daemon adds to the stream")
+    ),
+    responses(
+        (status = 200, description = "Buffered and then live ACP envelopes as newline-delimited JSON, one `{sequence, message}` object per line, ending when the server ends; read from the archive for ended servers", content_type = "application/x-ndjson"),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
+    )
+)]
+async fn get_v1_acp_events_ndjson(
+    State(state): State<Arc<AppState>>,
+    Path(server_id): Path<String>,
+    Query(query): Query<AcpEventsStreamQuery>,
+) -> Result<Response, ApiError> {
+    let events = match state.acp_proxy().event_log(&server_id, query.offset).await {
+        Ok(events) => events.boxed(),
+        Err(SandboxError::SessionNotFound { .. }) if state.acp_proxy().archive().is_enabled() => {
+            stream::iter(archived_events(&state, server_id, query.offset).await?).boxed()
         }
-        None => None,
+        Err(err) => return Err(err.into()),
     };
+    let filter = EventFilter::new(
+        query.types.as_deref(),
+        query.kinds.as_deref(),
+        query.exclude_synthetic.unwrap_or(false),
+    );
+    // The body is polled as the client reads it, so a slow reader holds the stream back
+    // instead of having events buffered for it.
+    let lines = events
+        .filter(move |(_, message)| std::future::ready(filter.matches(message)))
+        .map(|(sequence, message)| {
+            Ok::<_, std::convert::Infallible>(Bytes::from(event_line(sequence, message)))
+        });
+    let mut response = Body::from_stream(lines).into_response();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(APPLICATION_NDJSON),
+    );
+    Ok(response)
+}
 
-    match state
-        .acp_proxy()
-        .post(&server_id, bootstrap_agent, payload)
-        .await?
-    {
-        ProxyPostOutcome::Response(value) => Ok((StatusCode::OK, Json(value)).into_response()),
-        ProxyPostOutcome::Accepted => Ok(StatusCode::ACCEPTED.into_response()),
-    }
+async fn archived_events(
+    state: &AppState,
+    server_id: String,
+    after: Option<u64>,
+) -> Result<Vec<(u64, Value)>, SandboxError> {
+    let archive = state.acp_proxy().archive().clone();
+    tokio::task::spawn_blocking(move || archive.events(&server_id, after))
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: err.to_string(),
+        })?
+}
+
+fn event_line(sequence: u64, message: Value) -> String {
+    let mut line = json!({ "sequence": sequence, "message": message }).to_string();
+    line.push('\n');
+    line
 }
 
 #[utoipa::path(
-    get,
+    patch,
     path = "/v1/acp/{server_id}",
     tag = "v1",
     params(
         ("server_id" = String, Path, description = "Client-defined ACP server id")
     ),
+    request_body = AcpServerUpdateRequest,
     responses(
-        (status = 200, description = "SSE stream of ACP envelopes"),
-        (status = 406, description = "Client does not accept SSE responses", body = ProblemDetails),
-        (status = 404, description = "Unknown ACP server", body = ProblemDetails),
-        (status = 400, description = "Invalid request", body = ProblemDetails)
+        (status = 200, description = "Server with its updated title and labels", body = AcpServerInfo),
+        (status = 400, description = "Invalid title or labels", body = ProblemDetails),
+        (status = 404, description = "Unknown ACP server", body = ProblemDetails)
     )
 )]
-async fn get_v1_acp(
+async fn patch_v1_acp(
     State(state): State<Arc<AppState>>,
     Path(server_id): Path<String>,
-    headers: HeaderMap,
-) -> Result<Sse<PinBoxSseStream>, ApiError> {
-    if !accept_allows(&headers, TEXT_EVENT_STREAM) {
-        return Err(SandboxError::NotAcceptable {
-            message: "accept must allow text/event-stream".to_string(),
-        }
-        .into());
+    Json(request): Json<AcpServerUpdateRequest>,
+) -> Result<Json<AcpServerInfo>, ApiError> {
+    validate_title(request.title.as_deref())?;
+    let labels = request.labels.unwrap_or_default();
+    for (key, value) in &labels {
+        validate_label(key, value.as_deref())?;
     }
-
-    let last_event_id = parse_last_event_id(&headers)?;
-    let stream = state.acp_proxy().sse(&server_id, last_event_id).await?;
-
-    Ok(Sse::new(stream).keep_alive(
-        KeepAlive::new()
-            .interval(Duration::from_secs(15))
-            .text("heartbeat"),
-    ))
+    let instance = state
+        .acp_proxy()
+        .update_metadata(&server_id, request.title, labels)
+        .await?;
+    Ok(Json(acp_server_info(instance)))
 }
 
 #[utoipa::path(
@@ -1401,7 +5586,11 @@ fn validate_named_query(value: &str, field_name: &str) -> Result<(), SandboxErro
     Ok(())
 }
 
-fn config_file_path(directory: &str, filename: &str) -> Result<PathBuf, SandboxError> {
+fn config_file_path(
+    fs_roots: &[PathBuf],
+    directory: &str,
+    filename: &str,
+) -> Result<PathBuf, SandboxError> {
     if directory.trim().is_empty() {
         return Err(SandboxError::InvalidRequest {
             message: "missing required 'directory' query parameter".to_string(),
@@ -1418,11 +5607,17 @@ fn config_file_path(directory: &str, filename: &str) -> Result<PathBuf, SandboxE
             })?
             .join(base_dir)
     };
+    ensure_within_roots(fs_roots, &root)?;
+
+    Ok(config_dir(&root).join(filename))
+}
 
-    Ok(root.join(".sandbox-agent").join("config").join(filename))
+/// Where the `/v1/config/*` files of a directory live.
+pub(crate) fn config_dir(directory: &StdPath) -> PathBuf {
+    directory.join(".sandbox-agent").join("config")
 }
 
-fn read_named_config_map<T>(path: &StdPath) -> Result<BTreeMap<String, T>, SandboxError>
+pub(crate) fn read_named_config_map<T>(path: &StdPath) -> Result<BTreeMap<String, T>, SandboxError>
 where
     T: DeserializeOwned,
 {
@@ -1465,7 +5660,17 @@ where
     })
 }
 
-fn now_ms() -> i64 {
+/// 128 random bits, base64url encoded, for ids and nonces that must not be guessable.
+pub(crate) fn random_id() -> String {
+    use base64::Engine;
+
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("the OS random source is available");
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Milliseconds since the Unix epoch, 0 if the clock is before it.
+pub(crate) fn now_ms() -> i64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map(|duration| duration.as_millis() as i64)
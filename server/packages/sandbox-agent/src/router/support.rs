@@ -1,3 +1,6 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
 use super::*;
 
 pub(super) async fn not_found() -> Response {
@@ -20,26 +23,41 @@ pub(super) async fn not_found() -> Response {
 
 pub(super) async fn require_token(
     State(state): State<Arc<AppState>>,
-    request: Request<axum::body::Body>,
+    mut request: Request<axum::body::Body>,
     next: Next,
 ) -> Result<Response, ApiError> {
-    let Some(expected) = state.auth.token.as_ref() else {
+    if !state.auth.is_enabled() {
         return Ok(next.run(request).await);
-    };
+    }
 
     let bearer = request
         .headers()
         .get(header::AUTHORIZATION)
         .and_then(|value| value.to_str().ok())
         .and_then(|value| value.strip_prefix("Bearer "));
-
-    if bearer == Some(expected.as_str()) {
+    // Nested routers see the path without their prefix; scopes are keyed on the full path.
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path())
+        .unwrap_or_else(|| request.uri().path());
+    // External approvers authenticate with the signed token in the body instead.
+    if path == crate::approval_routing::CALLBACK_PATH {
+        request
+            .extensions_mut()
+            .insert(RequestActor("approver".to_string()));
         return Ok(next.run(request).await);
     }
+    let required = required_scope(request.method(), path);
 
-    Err(ApiError::Sandbox(SandboxError::TokenInvalid {
-        message: Some("missing or invalid bearer token".to_string()),
-    }))
+    let token = state.auth.authorize(bearer, required)?;
+    // Named tokens get their own rate limit bucket; the `--token` token has one of its own.
+    let actor = token.map_or_else(
+        || "admin".to_string(),
+        |token| format!("token:{}", token.name),
+    );
+    request.extensions_mut().insert(RequestActor(actor));
+    Ok(next.run(request).await)
 }
 
 pub(super) type PinBoxSseStream = crate::acp_proxy_runtime::PinBoxSseStream;
@@ -156,8 +174,9 @@ pub(super) fn fallback_config_options(agent: AgentId) -> Vec<Value> {
 /// Parse an agent config JSON file (from `scripts/agent-configs/resources/`) into
 /// ACP `SessionConfigOption` values. The JSON format is:
 /// ```json
-/// { "defaultModel": "...", "models": [{id, name}], "defaultMode?": "...", "modes?": [{id, name}] }
+/// { "defaultModel": "...", "models": [{id, name, variants?}], "defaultMode?": "...", "modes?": [{id, name}] }
 /// ```
+/// A model's reasoning `variants` go in its option's `_meta["sandboxagent.dev"].reasoningVariants`.
 fn parse_agent_config(json_str: &str) -> Vec<Value> {
     #[derive(serde::Deserialize)]
     struct AgentConfig {
@@ -172,6 +191,7 @@ fn parse_agent_config(json_str: &str) -> Vec<Value> {
     struct ModelEntry {
         id: String,
         name: String,
+        variants: Option<Vec<String>>,
     }
     #[derive(serde::Deserialize)]
     struct ModeEntry {
@@ -188,10 +208,16 @@ fn parse_agent_config(json_str: &str) -> Vec<Value> {
         "category": "model",
         "type": "select",
         "currentValue": config.default_model,
-        "options": config.models.iter().map(|m| json!({
-            "value": m.id,
-            "name": m.name,
-        })).collect::<Vec<_>>(),
+        "options": config.models.iter().map(|m| {
+            let mut option = json!({
+                "value": m.id,
+                "name": m.name,
+            });
+            if let Some(variants) = &m.variants {
+                option["_meta"] = json!({ "sandboxagent.dev": { "reasoningVariants": variants } });
+            }
+            option
+        }).collect::<Vec<_>>(),
     })];
 
     if let Some(modes) = config.modes {
@@ -410,21 +436,126 @@ pub(super) fn map_artifact_kind(kind: InstalledArtifactKind) -> String {
     .to_string()
 }
 
-pub(super) fn resolve_fs_path(raw_path: &str) -> Result<PathBuf, SandboxError> {
+/// Resolve an fs path. Relative paths are joined onto the first fs root, or the home
+/// directory when no roots are configured; the result must lie inside an fs root.
+pub(super) fn resolve_fs_path(
+    fs_roots: &[PathBuf],
+    raw_path: &str,
+) -> Result<PathBuf, SandboxError> {
     let path = PathBuf::from(raw_path);
-    if path.is_absolute() {
-        return Ok(path);
+    let resolved = if path.is_absolute() {
+        path
+    } else {
+        let base = match fs_roots.first() {
+            Some(root) => root.clone(),
+            None => std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(PathBuf::from)
+                .ok_or_else(|| SandboxError::InvalidRequest {
+                    message: "home directory unavailable".to_string(),
+                })?,
+        };
+        base.join(sanitize_relative_path(&path)?)
+    };
+    ensure_within_roots(fs_roots, &resolved)?;
+    Ok(resolved)
+}
+
+/// Fail with `PermissionDenied` unless `path` lies inside one of `roots`; an empty list allows
+/// everything. Symlinks are resolved through the deepest existing ancestor, so a link inside a
+/// root cannot point outside it.
+pub(super) fn ensure_within_roots(roots: &[PathBuf], path: &StdPath) -> Result<(), SandboxError> {
+    if roots.is_empty() {
+        return Ok(());
     }
+    let denied = || SandboxError::PermissionDenied {
+        message: Some(format!(
+            "path is outside the permitted directories: {}",
+            path.display()
+        )),
+    };
+    if path
+        .components()
+        .any(|component| matches!(component, std::path::Component::ParentDir))
+    {
+        return Err(denied());
+    }
+
+    let mut existing = path;
+    let mut missing = Vec::new();
+    let resolved = loop {
+        match fs::canonicalize(existing) {
+            Ok(mut resolved) => {
+                resolved.extend(missing.iter().rev());
+                break resolved;
+            }
+            Err(_) => match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name);
+                    existing = parent;
+                }
+                _ => break path.to_path_buf(),
+            },
+        }
+    };
 
-    let home = std::env::var_os("HOME")
-        .or_else(|| std::env::var_os("USERPROFILE"))
-        .map(PathBuf::from)
+    if roots.iter().any(|root| resolved.starts_with(root)) {
+        Ok(())
+    } else {
+        Err(denied())
+    }
+}
+
+/// Resolve an fs path, scoped to an ACP server's working directory when `server_id` is set.
+pub(crate) async fn resolve_scoped_fs_path(
+    state: &AppState,
+    server_id: Option<&str>,
+    raw_path: &str,
+) -> Result<PathBuf, SandboxError> {
+    let Some(server_id) = server_id else {
+        return resolve_fs_path(state.fs_roots(), raw_path);
+    };
+    let root = state
+        .acp_proxy()
+        .directory(server_id)
+        .await?
         .ok_or_else(|| SandboxError::InvalidRequest {
-            message: "home directory unavailable".to_string(),
+            message: format!("ACP server '{server_id}' has no working directory"),
         })?;
+    resolve_fs_path_within(&root, raw_path)
+}
+
+/// Relative paths are joined onto `root`; absolute paths must already point inside it.
+pub(super) fn resolve_fs_path_within(
+    root: &StdPath,
+    raw_path: &str,
+) -> Result<PathBuf, SandboxError> {
+    // Paths are compared after resolving symlinks, so the root must be too: with `/tmp`
+    // linked to `/private/tmp`, as on macOS, nothing would be inside the literal root.
+    let root = fs::canonicalize(root).map_err(|err| map_fs_error(root, err))?;
+    let path = PathBuf::from(raw_path);
+    let resolved = if path.is_absolute() {
+        path
+    } else {
+        root.join(sanitize_relative_path(&path)?)
+    };
+    ensure_within_roots(std::slice::from_ref(&root), &resolved)?;
+    Ok(resolved)
+}
 
-    let relative = sanitize_relative_path(&path)?;
-    Ok(home.join(relative))
+/// Resolve the `directory` an ACP server is bootstrapped with, creating it when missing.
+pub(super) fn prepare_server_directory(
+    fs_roots: &[PathBuf],
+    raw_path: &str,
+) -> Result<PathBuf, SandboxError> {
+    let directory = resolve_fs_path(fs_roots, raw_path)?;
+    if directory.exists() && !directory.is_dir() {
+        return Err(SandboxError::NotADirectory {
+            path: directory.display().to_string(),
+        });
+    }
+    fs::create_dir_all(&directory).map_err(|err| map_fs_error(&directory, err))?;
+    fs::canonicalize(&directory).map_err(|err| map_fs_error(&directory, err))
 }
 
 pub(super) fn sanitize_relative_path(path: &StdPath) -> Result<PathBuf, SandboxError> {
@@ -444,16 +575,658 @@ pub(super) fn sanitize_relative_path(path: &StdPath) -> Result<PathBuf, SandboxE
     Ok(sanitized)
 }
 
-pub(super) fn map_fs_error(path: &StdPath, err: std::io::Error) -> SandboxError {
-    if err.kind() == std::io::ErrorKind::NotFound {
-        SandboxError::InvalidRequest {
-            message: format!("path not found: {}", path.display()),
+pub(crate) fn map_fs_error(path: &StdPath, err: std::io::Error) -> SandboxError {
+    use std::io::ErrorKind;
+
+    let path = path.display().to_string();
+    match err.kind() {
+        ErrorKind::NotFound => SandboxError::NotFound { path },
+        ErrorKind::PermissionDenied => SandboxError::PermissionDenied {
+            message: Some(format!("permission denied: {path}")),
+        },
+        ErrorKind::NotADirectory => SandboxError::NotADirectory { path },
+        ErrorKind::AlreadyExists => SandboxError::AlreadyExists { path },
+        ErrorKind::StorageFull | ErrorKind::QuotaExceeded => SandboxError::QuotaExceeded { path },
+        _ => SandboxError::StreamError {
+            message: format!("{path}: {err}"),
+        },
+    }
+}
+
+/// Path filter shared by fs endpoints: `.gitignore` rules from the enclosing git
+/// repository plus caller-supplied ignore globs.
+pub(super) struct FsIgnoreFilter {
+    globs: Option<Gitignore>,
+    repository: Option<RepositoryGitignores>,
+}
+
+/// `.gitignore` rules for a root inside a git repository: the files from the root up to the
+/// repository root, plus those in directories below the root, loaded as paths reach them.
+struct RepositoryGitignores {
+    root: PathBuf,
+    ancestors: Vec<Gitignore>,
+    nested: Mutex<HashMap<PathBuf, Option<Gitignore>>>,
+}
+
+impl FsIgnoreFilter {
+    pub(super) fn new(
+        root: &StdPath,
+        respect_gitignore: Option<bool>,
+        ignore_globs: Option<&str>,
+    ) -> Result<Self, SandboxError> {
+        let globs = match ignore_globs {
+            Some(raw) => {
+                let mut builder = GitignoreBuilder::new(root);
+                for glob in raw
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|glob| !glob.is_empty())
+                {
+                    builder
+                        .add_line(None, glob)
+                        .map_err(|err| SandboxError::InvalidRequest {
+                            message: format!("invalid ignore glob {glob:?}: {err}"),
+                        })?;
+                }
+                let matcher = builder
+                    .build()
+                    .map_err(|err| SandboxError::InvalidRequest {
+                        message: format!("invalid ignore globs: {err}"),
+                    })?;
+                Some(matcher)
+            }
+            None => None,
+        };
+
+        let repository = if respect_gitignore.unwrap_or(true) {
+            load_repository_gitignores(root).map(|ancestors| RepositoryGitignores {
+                root: root.to_path_buf(),
+                ancestors,
+                nested: Mutex::new(HashMap::new()),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self { globs, repository })
+    }
+
+    /// `path` must be located under the root the filter was built for.
+    pub(super) fn is_ignored(&self, path: &StdPath, is_dir: bool) -> bool {
+        if let Some(globs) = &self.globs {
+            if globs.matched_path_or_any_parents(path, is_dir).is_ignore() {
+                return true;
+            }
         }
+        let Some(repository) = &self.repository else {
+            return false;
+        };
+        // Deeper .gitignore files take precedence over their ancestors.
+        let mut nested = repository
+            .nested
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner());
+        for dir in path
+            .parent()
+            .into_iter()
+            .flat_map(StdPath::ancestors)
+            .take_while(|dir| *dir != repository.root && dir.starts_with(&repository.root))
+        {
+            let gitignore = nested
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| load_gitignore(dir));
+            if let Some(decision) = gitignore
+                .as_ref()
+                .and_then(|gitignore| gitignore_decision(gitignore, path, is_dir))
+            {
+                return decision;
+            }
+        }
+        repository
+            .ancestors
+            .iter()
+            .find_map(|gitignore| gitignore_decision(gitignore, path, is_dir))
+            .unwrap_or(false)
+    }
+}
+
+/// `Some(true)` when `gitignore` ignores `path`, `Some(false)` when it re-includes it.
+fn gitignore_decision(gitignore: &Gitignore, path: &StdPath, is_dir: bool) -> Option<bool> {
+    let matched = gitignore.matched_path_or_any_parents(path, is_dir);
+    if matched.is_ignore() {
+        Some(true)
+    } else if matched.is_whitelist() {
+        Some(false)
     } else {
-        SandboxError::StreamError {
-            message: err.to_string(),
+        None
+    }
+}
+
+fn load_gitignore(dir: &StdPath) -> Option<Gitignore> {
+    let path = dir.join(".gitignore");
+    if !path.is_file() {
+        return None;
+    }
+    let (gitignore, err) = Gitignore::new(&path);
+    if let Some(err) = err {
+        tracing::warn!(path = %path.display(), error = %err, "failed to parse .gitignore");
+    }
+    Some(gitignore)
+}
+
+/// Collects `.gitignore` matchers from `root` up to the enclosing repository root,
+/// deepest first. Paths outside a git repository get `None`.
+fn load_repository_gitignores(root: &StdPath) -> Option<Vec<Gitignore>> {
+    let mut dirs = Vec::new();
+    let mut current = Some(root);
+    let mut in_repository = false;
+    while let Some(dir) = current {
+        dirs.push(dir);
+        if dir.join(".git").exists() {
+            in_repository = true;
+            break;
+        }
+        current = dir.parent();
+    }
+    if !in_repository {
+        return None;
+    }
+
+    Some(dirs.into_iter().filter_map(load_gitignore).collect())
+}
+
+const FS_ARCHIVE_CHUNK_BYTES: usize = 64 * 1024;
+
+pub(super) struct FsArchive {
+    pub(super) root: PathBuf,
+    pub(super) format: FsArchiveFormat,
+    pub(super) respect_gitignore: bool,
+    pub(super) filter: FsIgnoreFilter,
+}
+
+/// Writes an archive of `archive.root` into `tx` in chunks. Entries are named under the
+/// directory's own name and symlinks are stored as links, not followed. tar.gz streams as it
+/// is built; zip needs a seekable writer, so it is assembled in a temporary file first.
+pub(super) fn run_fs_archive(
+    archive: FsArchive,
+    tx: tokio::sync::mpsc::Sender<std::io::Result<Bytes>>,
+) {
+    let root = archive.root.clone();
+    let mut writer = ChannelWriter::new(tx.clone());
+    let result = match archive.format {
+        FsArchiveFormat::TarGz => write_tar_gz(archive, &mut writer),
+        FsArchiveFormat::Zip => write_zip(archive, &mut writer),
+    };
+    if let Err(err) = result.and_then(|()| std::io::Write::flush(&mut writer)) {
+        if err.kind() != std::io::ErrorKind::BrokenPipe {
+            tracing::warn!(path = %root.display(), error = %err, "fs archive failed");
+            let _ = tx.blocking_send(Err(err));
+        }
+    }
+}
+
+/// Lists the archive entries under `root` as (absolute path, archive name, file type).
+fn fs_archive_entries(archive: FsArchive) -> std::io::Result<Vec<(PathBuf, String, fs::FileType)>> {
+    let FsArchive {
+        root,
+        respect_gitignore,
+        filter,
+        ..
+    } = archive;
+    let base = root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "archive".to_string());
+    let filter = Arc::new(filter);
+    let walker = ignore::WalkBuilder::new(&root)
+        .standard_filters(false)
+        .git_ignore(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .parents(respect_gitignore)
+        .require_git(true)
+        .sort_by_file_name(|left, right| left.cmp(right))
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+            entry.depth() == 0 || !filter.is_ignored(entry.path(), is_dir)
+        })
+        .build();
+
+    let mut entries = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(std::io::Error::other)?;
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+        let mut name = base.clone();
+        for component in relative.components() {
+            name.push('/');
+            name.push_str(&component.as_os_str().to_string_lossy());
+        }
+        entries.push((entry.path().to_path_buf(), name, file_type));
+    }
+    Ok(entries)
+}
+
+fn write_tar_gz(archive: FsArchive, writer: &mut ChannelWriter) -> std::io::Result<()> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    let entries = fs_archive_entries(archive)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    builder.follow_symlinks(false);
+    for (path, name, file_type) in entries {
+        if file_type.is_dir() {
+            builder.append_dir(&name, &path)?;
+        } else if file_type.is_file() || file_type.is_symlink() {
+            builder.append_path_with_name(&path, &name)?;
+        }
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn write_zip(archive: FsArchive, writer: &mut ChannelWriter) -> std::io::Result<()> {
+    use std::io::{Seek, SeekFrom};
+    use zip::write::FileOptions;
+
+    let entries = fs_archive_entries(archive)?;
+    let mut spool = spool_file()?;
+    {
+        let mut zip = zip::ZipWriter::new(&mut spool);
+        for (path, name, file_type) in entries {
+            let mut options =
+                FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = fs::symlink_metadata(&path)?.permissions().mode();
+                options = options.unix_permissions(mode & 0o777);
+            }
+            if file_type.is_dir() {
+                zip.add_directory(name, options)?;
+            } else if file_type.is_symlink() {
+                let target = fs::read_link(&path)?;
+                zip.add_symlink(name, target.to_string_lossy(), options)?;
+            } else if file_type.is_file() {
+                zip.start_file(name, options)?;
+                std::io::copy(&mut fs::File::open(&path)?, &mut zip)?;
+            }
+        }
+        zip.finish()?;
+    }
+    spool.seek(SeekFrom::Start(0))?;
+    std::io::copy(&mut spool, writer)?;
+    Ok(())
+}
+
+/// An anonymous read/write file in the temp directory, unlinked once opened where the
+/// platform allows it.
+fn spool_file() -> std::io::Result<fs::File> {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    let path = std::env::temp_dir().join(format!(
+        "sandbox-agent-archive-{}-{nanos}.zip",
+        std::process::id()
+    ));
+    let file = fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let _ = fs::remove_file(&path);
+    Ok(file)
+}
+
+/// Blocking writer that forwards fixed-size chunks to an async response body. Writes fail with
+/// `BrokenPipe` once the client goes away.
+struct ChannelWriter {
+    tx: tokio::sync::mpsc::Sender<std::io::Result<Bytes>>,
+    buffer: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn new(tx: tokio::sync::mpsc::Sender<std::io::Result<Bytes>>) -> Self {
+        Self {
+            tx,
+            buffer: Vec::with_capacity(FS_ARCHIVE_CHUNK_BYTES),
+        }
+    }
+
+    fn send_buffer(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(FS_ARCHIVE_CHUNK_BYTES));
+        self.tx
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client disconnected"))
+    }
+}
+
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= FS_ARCHIVE_CHUNK_BYTES {
+            self.send_buffer()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+pub(super) const FS_SEARCH_DEFAULT_MAX_RESULTS: usize = 1000;
+pub(super) const FS_SEARCH_MAX_RESULTS: usize = 10_000;
+const FS_SEARCH_MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+const FS_SEARCH_SNIPPET_MAX_CHARS: usize = 400;
+
+pub(super) struct FsSearch {
+    pub(super) root: PathBuf,
+    pub(super) pattern: regex::Regex,
+    pub(super) max_results: usize,
+    pub(super) respect_gitignore: bool,
+    pub(super) filter: FsIgnoreFilter,
+}
+
+const FS_WATCH_QUEUE: usize = 1024;
+
+/// A live filesystem watch. Events under `.git` or matched by the ignore filter are dropped.
+/// If the queue overflows or the OS reports lost events, a single `Rescan` is emitted instead.
+pub(super) struct FsWatch {
+    _watcher: notify::RecommendedWatcher,
+    rx: tokio::sync::mpsc::Receiver<FsWatchEvent>,
+    overflowed: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl FsWatch {
+    pub(super) fn start(
+        path: &StdPath,
+        recursive: bool,
+        base: PathBuf,
+        filter: FsIgnoreFilter,
+    ) -> Result<Self, SandboxError> {
+        use notify::Watcher;
+        use std::sync::atomic::Ordering;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(FS_WATCH_QUEUE);
+        let overflowed = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = overflowed.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+                let events = match result {
+                    Ok(event) => fs_watch_events(event),
+                    Err(err) => {
+                        tracing::debug!(error = %err, "fs watch error");
+                        vec![FsWatchEvent::Rescan]
+                    }
+                };
+                for event in events {
+                    if fs_watch_ignored(&event, &base, &filter) {
+                        continue;
+                    }
+                    match tx.try_send(event) {
+                        Ok(()) => {}
+                        Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
+                            flag.store(true, Ordering::Relaxed);
+                        }
+                        Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => return,
+                    }
+                }
+            })
+            .map_err(|err| SandboxError::StreamError {
+                message: format!("failed to start watcher: {err}"),
+            })?;
+        let mode = if recursive {
+            notify::RecursiveMode::Recursive
+        } else {
+            notify::RecursiveMode::NonRecursive
+        };
+        watcher
+            .watch(path, mode)
+            .map_err(|err| SandboxError::StreamError {
+                message: format!("failed to watch {}: {err}", path.display()),
+            })?;
+
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+            overflowed,
+        })
+    }
+
+    pub(super) async fn next(&mut self) -> Option<FsWatchEvent> {
+        if self
+            .overflowed
+            .swap(false, std::sync::atomic::Ordering::Relaxed)
+        {
+            return Some(FsWatchEvent::Rescan);
+        }
+        self.rx.recv().await
+    }
+}
+
+fn fs_watch_events(event: notify::Event) -> Vec<FsWatchEvent> {
+    use notify::event::{EventKind, ModifyKind, RenameMode};
+
+    let path_string = |path: &StdPath| path.to_string_lossy().to_string();
+    let create = |path: &StdPath| FsWatchEvent::Create {
+        path: path_string(path),
+        is_dir: path.is_dir(),
+    };
+    let delete = |path: &StdPath| FsWatchEvent::Delete {
+        path: path_string(path),
+    };
+
+    let mut events = Vec::new();
+    if event.need_rescan() {
+        events.push(FsWatchEvent::Rescan);
+    }
+    match event.kind {
+        EventKind::Create(_) => events.extend(event.paths.iter().map(|path| create(path))),
+        EventKind::Remove(_) => events.extend(event.paths.iter().map(|path| delete(path))),
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            events.extend(event.paths.iter().map(|path| delete(path)))
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            events.extend(event.paths.iter().map(|path| create(path)))
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            if let [from, to] = event.paths.as_slice() {
+                events.push(delete(from));
+                events.push(create(to));
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(_)) => events.extend(event.paths.iter().map(|path| {
+            if path.exists() {
+                create(path)
+            } else {
+                delete(path)
+            }
+        })),
+        EventKind::Modify(_) => {
+            events.extend(event.paths.iter().map(|path| FsWatchEvent::Modify {
+                path: path_string(path),
+            }))
+        }
+        EventKind::Access(_) | EventKind::Any | EventKind::Other => {}
+    }
+    events
+}
+
+fn fs_watch_ignored(event: &FsWatchEvent, base: &StdPath, filter: &FsIgnoreFilter) -> bool {
+    let (path, is_dir) = match event {
+        FsWatchEvent::Create { path, is_dir } => (StdPath::new(path), *is_dir),
+        FsWatchEvent::Modify { path } | FsWatchEvent::Delete { path } => {
+            let path = StdPath::new(path);
+            (path, path.is_dir())
+        }
+        FsWatchEvent::Rescan => return false,
+    };
+    let Ok(relative) = path.strip_prefix(base) else {
+        return false;
+    };
+    relative
+        .components()
+        .any(|component| component.as_os_str() == ".git")
+        || filter.is_ignored(path, is_dir)
+}
+
+/// Walks `search.root` like ripgrep: gitignore-aware, symlinks not followed, `.git` and
+/// binary or oversized files skipped. Each matching line is sent as it is found, followed
+/// by one `Done` event. Stops early once the receiver is dropped.
+pub(super) fn run_fs_search(search: FsSearch, tx: tokio::sync::mpsc::Sender<FsSearchEvent>) {
+    let FsSearch {
+        root,
+        pattern,
+        max_results,
+        respect_gitignore,
+        filter,
+    } = search;
+    let filter = Arc::new(filter);
+    let walker = ignore::WalkBuilder::new(&root)
+        .standard_filters(false)
+        .git_ignore(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .parents(respect_gitignore)
+        .require_git(true)
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+            entry.file_name() != ".git" && !filter.is_ignored(entry.path(), is_dir)
+        })
+        .build();
+
+    let mut matches = 0;
+    let mut files_scanned = 0;
+    let mut truncated = false;
+    'walk: for entry in walker.flatten() {
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let Some(text) = read_searchable_text(path) else {
+            continue;
+        };
+        files_scanned += 1;
+
+        for (index, line) in text.lines().enumerate() {
+            let Some(found) = pattern.find(line) else {
+                continue;
+            };
+            if matches == max_results {
+                truncated = true;
+                break 'walk;
+            }
+            matches += 1;
+            let event = FsSearchEvent::Match {
+                path: path.to_string_lossy().to_string(),
+                line: index as u64 + 1,
+                column: found.start() as u64 + 1,
+                text: line
+                    .trim_end()
+                    .chars()
+                    .take(FS_SEARCH_SNIPPET_MAX_CHARS)
+                    .collect(),
+            };
+            if tx.blocking_send(event).is_err() {
+                return;
+            }
         }
     }
+
+    let _ = tx.blocking_send(FsSearchEvent::Done {
+        matches,
+        files_scanned,
+        truncated,
+    });
+}
+
+fn read_searchable_text(path: &StdPath) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.len() > FS_SEARCH_MAX_FILE_BYTES {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if bytes[..bytes.len().min(FS_PREVIEW_READ_BYTES)].contains(&0) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub(super) const FS_PREVIEW_READ_BYTES: usize = 4096;
+const FS_PREVIEW_MAX_CHARS: usize = 160;
+
+pub(super) struct FsPreview {
+    pub(super) mime_type: String,
+    pub(super) is_binary: bool,
+    pub(super) preview: Option<String>,
+}
+
+/// Sniffs the first few KiB of a file for a mime hint and a first-line preview.
+pub(super) fn read_fs_preview(path: &StdPath) -> std::io::Result<FsPreview> {
+    use std::io::Read;
+
+    let mut buffer = Vec::with_capacity(FS_PREVIEW_READ_BYTES);
+    fs::File::open(path)?
+        .take(FS_PREVIEW_READ_BYTES as u64)
+        .read_to_end(&mut buffer)?;
+
+    let text = match std::str::from_utf8(&buffer) {
+        Ok(text) => Some(text),
+        // A multi-byte character may straddle the read boundary.
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&buffer[..err.valid_up_to()]).ok()
+        }
+        Err(_) => None,
+    };
+    let text = text.filter(|text| !text.contains('\0'));
+    let is_binary = text.is_none();
+    let preview = text
+        .and_then(|text| text.lines().find(|line| !line.trim().is_empty()))
+        .map(|line| line.trim_end().chars().take(FS_PREVIEW_MAX_CHARS).collect());
+
+    Ok(FsPreview {
+        mime_type: guess_mime_type(path, is_binary).to_string(),
+        is_binary,
+        preview,
+    })
+}
+
+fn guess_mime_type(path: &StdPath, is_binary: bool) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("md" | "markdown") => "text/markdown",
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("csv") => "text/csv",
+        Some("js" | "mjs" | "cjs") => "text/javascript",
+        Some("ts" | "tsx") => "text/typescript",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("yaml" | "yml") => "application/yaml",
+        Some("toml") => "application/toml",
+        Some("sh") => "application/x-sh",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz" | "tgz") => "application/gzip",
+        Some("tar") => "application/x-tar",
+        Some("wasm") => "application/wasm",
+        _ if is_binary => "application/octet-stream",
+        _ => "text/plain",
+    }
 }
 
 pub(super) fn content_type_is(headers: &HeaderMap, expected: &str) -> bool {
@@ -479,6 +1252,63 @@ pub(super) fn accept_allows(headers: &HeaderMap, expected: &str) -> bool {
         .any(|value| media_type_matches(value, expected))
 }
 
+/// Like `accept_allows`, but only true when `expected` is listed explicitly rather than
+/// through a wildcard or a missing header.
+pub(super) fn accept_lists(headers: &HeaderMap, expected: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| media_type_eq(value, expected))
+}
+
+pub(super) fn accept_encoding_allows(headers: &HeaderMap, coding: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| {
+            let mut parts = value.split(';').map(str::trim);
+            let matches = parts
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case(coding));
+            let disabled = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            matches && !disabled
+        })
+}
+
+/// Gzip-compresses a chunk stream incrementally so large bodies are never buffered whole.
+pub(super) fn gzip_stream<S>(chunks: S) -> impl Stream<Item = std::io::Result<Bytes>> + Send
+where
+    S: Stream<Item = Vec<u8>> + Send + Unpin + 'static,
+{
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let encoder = GzEncoder::new(Vec::new(), Compression::default());
+    stream::unfold(Some((encoder, chunks)), |state| async move {
+        let (mut encoder, mut chunks) = state?;
+        match chunks.next().await {
+            Some(chunk) => {
+                if let Err(err) = encoder.write_all(&chunk) {
+                    return Some((Err(err), None));
+                }
+                let compressed = std::mem::take(encoder.get_mut());
+                Some((Ok(Bytes::from(compressed)), Some((encoder, chunks))))
+            }
+            None => Some((encoder.finish().map(Bytes::from), None)),
+        }
+    })
+}
+
 fn media_type_eq(raw: &str, expected: &str) -> bool {
     normalize_media_type(raw).as_deref() == Some(expected)
 }
@@ -528,6 +1358,444 @@ pub(super) fn parse_last_event_id(headers: &HeaderMap) -> Result<Option<u64>, Sa
     }
 }
 
+/// Reconnect delay suggested to `EventSource` clients of the event streams.
+pub(super) const SSE_RETRY: Duration = Duration::from_secs(2);
+
+/// An event stream response that opens with a `retry:` hint and sends a `: heartbeat`
+/// comment every 15 seconds, so proxies don't close it while it is idle.
+pub(super) fn event_stream_response(stream: PinBoxSseStream) -> Sse<PinBoxSseStream> {
+    let retry = stream::once(async { Ok(axum::response::sse::Event::default().retry(SSE_RETRY)) });
+    Sse::new(Box::pin(retry.chain(stream)) as PinBoxSseStream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("heartbeat"),
+    )
+}
+
+/// AsyncAPI 2.6 description of the streaming contracts that OpenAPI cannot
+/// express: the per-server SSE channel, its NDJSON stream and its JSONL export.
+pub(super) fn asyncapi_document() -> Value {
+    let envelope = serde_json::to_value(schemars::schema_for!(AcpEnvelope))
+        .unwrap_or_else(|_| json!({ "type": "object" }));
+    let server_id = json!({
+        "description": "Client-defined ACP server id",
+        "schema": { "type": "string" }
+    });
+
+    json!({
+        "asyncapi": "2.6.0",
+        "info": {
+            "title": "sandbox-agent",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Event streams exposed by the ACP proxy v1 API"
+        },
+        "servers": {
+            "local": {
+                "url": "localhost:2468",
+                "protocol": "http",
+                "description": "Default sandbox-agent listener"
+            }
+        },
+        "defaultContentType": APPLICATION_JSON,
+        "channels": {
+            "/v1/acp/{server_id}": {
+                "description": "Server-sent events carrying every ACP message emitted by the agent process, optionally filtered by the comma-separated `types` (JSON-RPC methods, or `response`) and `kinds` (`sessionUpdate` kinds) query parameters and `excludeSynthetic`, which drops the daemon's `_sandboxagent/*` notifications. Each event id is the message sequence; ids are strictly increasing and reconnecting with Last-Event-ID resumes after that id without duplicates. The stream opens with a `retry:` reconnect hint and sends a `: heartbeat` comment every 15 seconds while idle. A subscriber that falls behind gets a `stream.resync` event and then the messages it missed from the buffer.",
+                "parameters": { "server_id": server_id.clone() },
+                "subscribe": {
+                    "operationId": "streamAcpEvents",
+                    "bindings": {
+                        "http": {
+                            "type": "request",
+                            "method": "GET",
+                            "bindingVersion": "0.3.0"
+                        }
+                    },
+                    "message": {
+                        "oneOf": [
+                            { "$ref": "#/components/messages/AcpEvent" },
+                            { "$ref": "#/components/messages/StreamResync" }
+                        ]
+                    }
+                }
+            },
+            "/v1/acp/{server_id}/events.jsonl": {
+                "description": "Newline-delimited export of the buffered events, one object per line, optionally starting after a given sequence.",
+                "parameters": { "server_id": server_id.clone() },
+                "subscribe": {
+                    "operationId": "exportAcpEvents",
+                    "bindings": {
+                        "http": {
+                            "type": "request",
+                            "method": "GET",
+                            "bindingVersion": "0.3.0"
+                        }
+                    },
+                    "message": { "$ref": "#/components/messages/AcpEventRecord" }
+                }
+            },
+            "/v1/acp/{server_id}/events/ndjson": {
+                "description": "Newline-delimited stream of the buffered events followed by live ones, optionally starting after the sequence given as `offset`. Readers that fall behind get the events they missed from the buffer. The stream ends after the server's `_sandboxagent/session/ended` notification.",
+                "parameters": { "server_id": server_id },
+                "subscribe": {
+                    "operationId": "streamAcpEventsNdjson",
+                    "bindings": {
+                        "http": {
+                            "type": "request",
+                            "method": "GET",
+                            "bindingVersion": "0.3.0"
+                        }
+                    },
+                    "message": { "$ref": "#/components/messages/AcpEventRecord" }
+                }
+            },
+            "/v1/events/sse": {
+                "description": "Server-sent events from every ACP server, optionally filtered by the comma-separated `agents`, `types` and `kinds` query parameters and `excludeSynthetic`. Each event id is a daemon-wide firehose sequence; reconnecting with Last-Event-ID resumes after that id from the last 1024 events. The stream opens with a `retry:` reconnect hint and sends a `: heartbeat` comment every 15 seconds while idle. A subscriber that falls behind gets a `stream.resync` event and then the kept events it missed.",
+                "subscribe": {
+                    "operationId": "streamAllAcpEvents",
+                    "bindings": {
+                        "http": {
+                            "type": "request",
+                            "method": "GET",
+                            "bindingVersion": "0.3.0"
+                        }
+                    },
+                    "message": {
+                        "oneOf": [
+                            { "$ref": "#/components/messages/AcpFirehoseEvent" },
+                            { "$ref": "#/components/messages/StreamResync" }
+                        ]
+                    }
+                }
+            }
+        },
+        "components": {
+            "messages": {
+                "AcpEvent": {
+                    "name": "AcpEvent",
+                    "contentType": TEXT_EVENT_STREAM,
+                    "summary": "One SSE event; the `id` field carries the sequence and `data` holds the envelope",
+                    "payload": { "$ref": "#/components/schemas/AcpEnvelope" }
+                },
+                "AcpFirehoseEvent": {
+                    "name": "AcpFirehoseEvent",
+                    "contentType": TEXT_EVENT_STREAM,
+                    "summary": "One SSE event; the `id` field carries the firehose sequence, which differs from the per-server `sequence` in the payload",
+                    "payload": {
+                        "type": "object",
+                        "required": ["serverId", "agent", "sequence", "type", "message"],
+                        "properties": {
+                            "serverId": { "type": "string" },
+                            "agent": { "type": "string" },
+                            "sequence": { "type": "integer", "format": "uint64", "minimum": 1 },
+                            "type": { "type": "string", "description": "JSON-RPC method, or `response` for results and errors" },
+                            "message": { "$ref": "#/components/schemas/AcpEnvelope" }
+                        }
+                    }
+                },
+                "StreamResync": {
+                    "name": "stream.resync",
+                    "contentType": TEXT_EVENT_STREAM,
+                    "summary": "Sent without an id when the subscriber fell behind the live stream",
+                    "payload": {
+                        "type": "object",
+                        "required": ["after", "missed"],
+                        "properties": {
+                            "after": { "type": "integer", "format": "uint64", "description": "Last id delivered before falling behind; the buffered events after it follow" },
+                            "missed": { "type": "integer", "format": "uint64", "description": "Events that are no longer buffered and will not be delivered" }
+                        }
+                    }
+                },
+                "AcpEventRecord": {
+                    "name": "AcpEventRecord",
+                    "contentType": APPLICATION_NDJSON,
+                    "payload": {
+                        "type": "object",
+                        "required": ["sequence", "message"],
+                        "properties": {
+                            "sequence": { "type": "integer", "format": "uint64", "minimum": 1 },
+                            "message": { "$ref": "#/components/schemas/AcpEnvelope" }
+                        }
+                    }
+                }
+            },
+            "schemas": { "AcpEnvelope": envelope }
+        }
+    })
+}
+
+/// Swagger UI from an exact `swagger-ui-dist` release, so the page never picks up a newer
+/// build on its own. The bearer token from `#token=...`, kept in session storage, is sent with
+/// the spec fetch and with "Try it out" requests.
+pub(super) const SWAGGER_UI_HTML: &str = r##"<!doctype html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8" />
+    <title>sandbox-agent API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css" />
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js"></script>
+    <script>
+      const tokenKey = "sandbox-agent-token";
+      const fromHash = new URLSearchParams(location.hash.slice(1)).get("token");
+      if (fromHash) {
+        sessionStorage.setItem(tokenKey, fromHash);
+        history.replaceState(null, "", location.pathname + location.search);
+      }
+      const token = sessionStorage.getItem(tokenKey);
+      window.ui = SwaggerUIBundle({
+        url: "/v1/openapi.json",
+        dom_id: "#swagger-ui",
+        requestInterceptor: (request) => {
+          if (token && !request.headers.Authorization) {
+            request.headers.Authorization = `Bearer ${token}`;
+          }
+          return request;
+        },
+      });
+    </script>
+  </body>
+</html>
+"##;
+
+pub(super) fn split_csv(raw: &str) -> impl Iterator<Item = &str> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+pub(super) fn firehose_event_data(event: AcpFirehoseEvent) -> AcpFirehoseEventData {
+    let type_ = crate::event_filter::message_type(&event.message).to_string();
+    AcpFirehoseEventData {
+        server_id: event.server_id,
+        agent: event.agent.as_str().to_string(),
+        sequence: event.sequence,
+        type_,
+        message: event.message,
+    }
+}
+
+pub(super) fn usage_info(usage: AcpUsage) -> AcpUsageInfo {
+    AcpUsageInfo {
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        cached_read_tokens: usage.cached_read_tokens,
+        cached_write_tokens: usage.cached_write_tokens,
+        thought_tokens: usage.thought_tokens,
+        total_tokens: usage.total_tokens,
+        turns: usage.turns,
+        cost: usage
+            .costs()
+            .into_iter()
+            .map(|cost| AcpUsageCostInfo {
+                amount: cost.amount,
+                currency: cost.currency,
+            })
+            .collect(),
+        contexts: usage
+            .contexts()
+            .map(|(session_id, context)| AcpContextWindowInfo {
+                session_id: session_id.to_string(),
+                used: context.used,
+                size: context.size,
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn acp_server_info(instance: AcpServerInstanceInfo) -> AcpServerInfo {
+    AcpServerInfo {
+        server_id: instance.server_id,
+        agent: instance.agent.as_str().to_string(),
+        created_at_ms: instance.created_at_ms,
+        last_event_id: instance.last_event_id,
+        directory: instance
+            .directory
+            .map(|directory| directory.to_string_lossy().to_string()),
+        usage: usage_info(instance.usage),
+        title: instance.metadata.title,
+        labels: instance.metadata.labels,
+        archived: false,
+        ended_at_ms: None,
+    }
+}
+
+const MAX_SERVER_PAGE: usize = 1000;
+pub(super) const MAX_FANOUT_RUNS: usize = 16;
+
+pub(super) async fn fanout_info(state: &AppState, fanout: &Fanout) -> AcpFanoutInfo {
+    let mut runs = Vec::with_capacity(fanout.runs.len());
+    for run in &fanout.runs {
+        let run_state = run.state();
+        let usage = state.acp_proxy().usage(&run.spec.server_id).await.ok();
+        runs.push(AcpFanoutRunInfo {
+            server_id: run.spec.server_id.clone(),
+            agent: run.spec.agent.as_str().to_string(),
+            model: run.spec.model.clone(),
+            status: run_state.status,
+            session_id: run_state.session_id,
+            stop_reason: run_state.stop_reason,
+            error: run_state.error,
+            duration_ms: run_state
+                .ended_at_ms
+                .map(|ended_at_ms| ended_at_ms - fanout.created_at_ms),
+            usage: usage.map(usage_info),
+        });
+    }
+    AcpFanoutInfo {
+        fanout_id: fanout.id.clone(),
+        created_at_ms: fanout.created_at_ms,
+        runs,
+    }
+}
+
+pub(super) fn run_response(spec: RunSpec, report: RunReport) -> AcpRunResponse {
+    let diff = report.turn.map(|turn| {
+        let diff = turn.diff.clone();
+        let info = turn_info(turn);
+        AcpRunDiff {
+            files: info.files,
+            diff,
+            truncated: info.truncated,
+        }
+    });
+    AcpRunResponse {
+        server_id: spec.server_id,
+        agent: spec.agent.as_str().to_string(),
+        status: report.status,
+        session_id: report.session_id,
+        stop_reason: report.stop_reason,
+        error: report.error,
+        message: report.message,
+        items: report.items,
+        diff,
+        structured_output: report.structured_output,
+        usage: usage_info(report.usage),
+        duration_ms: report.duration.as_millis() as u64,
+    }
+}
+
+/// Sort `servers` as `query` asks and cut the page that follows `query.cursor`. Cursors
+/// name the last server of a page, so servers created or deleted between requests do not
+/// shift later pages.
+pub(super) fn page_servers(
+    mut servers: Vec<AcpServerInfo>,
+    query: &AcpServerListQuery,
+) -> Result<(Vec<AcpServerInfo>, Option<String>), SandboxError> {
+    let sort = query.sort.unwrap_or_default();
+    let descending = query.order.unwrap_or_default() == SortOrder::Desc;
+    let key = |created_at_ms: i64, server_id: &str| match sort {
+        AcpServerSort::ServerId => (0, server_id.to_string()),
+        AcpServerSort::CreatedAt => (created_at_ms, server_id.to_string()),
+    };
+    servers.sort_by_cached_key(|server| key(server.created_at_ms, &server.server_id));
+    if descending {
+        servers.reverse();
+    }
+    if let Some(cursor) = query.cursor.as_deref() {
+        let (created_at_ms, server_id) = decode_server_cursor(cursor)?;
+        let after = key(created_at_ms, &server_id);
+        servers.retain(|server| {
+            let current = key(server.created_at_ms, &server.server_id);
+            if descending {
+                current < after
+            } else {
+                current > after
+            }
+        });
+    }
+    let Some(limit) = query.limit.map(|limit| limit.clamp(1, MAX_SERVER_PAGE)) else {
+        return Ok((servers, None));
+    };
+    if servers.len() <= limit {
+        return Ok((servers, None));
+    }
+    servers.truncate(limit);
+    let next_cursor = servers
+        .last()
+        .map(|last| encode_server_cursor(last.created_at_ms, &last.server_id));
+    Ok((servers, next_cursor))
+}
+
+fn encode_server_cursor(created_at_ms: i64, server_id: &str) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{created_at_ms}:{server_id}"))
+}
+
+fn decode_server_cursor(cursor: &str) -> Result<(i64, String), SandboxError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|raw| String::from_utf8(raw).ok())
+        .and_then(|raw| {
+            let (created_at_ms, server_id) = raw.split_once(':')?;
+            Some((created_at_ms.parse().ok()?, server_id.to_string()))
+        })
+        .ok_or_else(|| SandboxError::InvalidRequest {
+            message: "invalid cursor".to_string(),
+        })
+}
+
+const MAX_LABEL_KEY_LEN: usize = 63;
+const MAX_LABEL_VALUE_LEN: usize = 256;
+const MAX_TITLE_LEN: usize = 256;
+
+/// Parse comma-separated `key=value` labels from a query parameter.
+pub(super) fn parse_label_query(raw: &str) -> Result<BTreeMap<String, String>, SandboxError> {
+    let mut labels = BTreeMap::new();
+    for pair in split_csv(raw) {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| SandboxError::InvalidRequest {
+                message: format!("label '{pair}' must be key=value"),
+            })?;
+        let (key, value) = (key.trim(), value.trim());
+        validate_label(key, Some(value))?;
+        labels.insert(key.to_string(), value.to_string());
+    }
+    if labels.len() > MAX_SERVER_LABELS {
+        return Err(SandboxError::InvalidRequest {
+            message: format!("a server can have at most {MAX_SERVER_LABELS} labels"),
+        });
+    }
+    Ok(labels)
+}
+
+/// Check a label key, and its value unless the label is being removed.
+pub(super) fn validate_label(key: &str, value: Option<&str>) -> Result<(), SandboxError> {
+    let key_valid = !key.is_empty()
+        && key.len() <= MAX_LABEL_KEY_LEN
+        && key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-' | '/'));
+    if !key_valid {
+        return Err(SandboxError::InvalidRequest {
+            message: format!(
+                "label key '{key}' must be 1-{MAX_LABEL_KEY_LEN} characters of letters, digits, '.', '_', '-' or '/'"
+            ),
+        });
+    }
+    if let Some(value) = value {
+        if value.len() > MAX_LABEL_VALUE_LEN || value.contains(',') {
+            return Err(SandboxError::InvalidRequest {
+                message: format!(
+                    "label '{key}' value must be at most {MAX_LABEL_VALUE_LEN} bytes without commas"
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn validate_title(title: Option<&str>) -> Result<(), SandboxError> {
+    if title.is_some_and(|title| title.len() > MAX_TITLE_LEN) {
+        return Err(SandboxError::InvalidRequest {
+            message: format!("title must be at most {MAX_TITLE_LEN} bytes"),
+        });
+    }
+    Ok(())
+}
+
 pub(super) fn problem_from_sandbox_error(error: &SandboxError) -> ProblemDetails {
     let mut problem = error.to_problem_details();
 
@@ -654,3 +1922,185 @@ fn capitalize_first(s: &str) -> String {
         Some(c) => c.to_uppercase().collect::<String>() + chars.as_str(),
     }
 }
+
+pub(super) fn upload_status(upload: crate::fs_upload::FsUploadSnapshot) -> FsUploadStatus {
+    FsUploadStatus {
+        upload_id: upload.id,
+        path: upload.destination.to_string_lossy().to_string(),
+        received_bytes: upload.received,
+        size: upload.size,
+    }
+}
+
+pub(super) fn turn_info(turn: crate::turn_diff::TurnRecord) -> AcpTurnInfo {
+    use crate::turn_diff::TurnFileChangeKind;
+
+    AcpTurnInfo {
+        turn_id: turn.turn_id,
+        session_id: turn.session_id,
+        root: turn.root.to_string_lossy().to_string(),
+        started_at_ms: turn.started_at_ms,
+        ended_at_ms: turn.ended_at_ms,
+        files: turn
+            .files
+            .into_iter()
+            .map(|file| AcpTurnFileChange {
+                path: file.path,
+                change: match file.change {
+                    TurnFileChangeKind::Added => AcpTurnFileChangeKind::Added,
+                    TurnFileChangeKind::Modified => AcpTurnFileChangeKind::Modified,
+                    TurnFileChangeKind::Deleted => AcpTurnFileChangeKind::Deleted,
+                },
+            })
+            .collect(),
+        truncated: turn.truncated,
+    }
+}
+
+pub(super) fn approval_info(
+    server_id: String,
+    agent: AgentId,
+    approval: crate::approvals::PendingApproval,
+    now: i64,
+) -> ApprovalInfo {
+    use crate::approvals::ApprovalKind as PendingKind;
+
+    ApprovalInfo {
+        server_id,
+        agent: agent.as_str().to_string(),
+        kind: match approval.kind {
+            PendingKind::Permission => ApprovalKind::Permission,
+            PendingKind::Question => ApprovalKind::Question,
+        },
+        action: approval.action(),
+        request_id: approval.request_id,
+        session_id: approval.session_id,
+        sequence: approval.sequence,
+        requested_at_ms: approval.requested_at_ms,
+        age_ms: now.saturating_sub(approval.requested_at_ms),
+        params: approval.params,
+        routed: approval.route_nonce.is_some(),
+    }
+}
+
+/// Agents whose config `/v1/agents/{agent}/config` manages: Codex's `config.toml`, and the
+/// managed OpenCode server's `/config`.
+pub(super) fn config_agent(agent: &str) -> Result<AgentId, SandboxError> {
+    match AgentId::parse(agent) {
+        Some(agent_id @ (AgentId::Codex | AgentId::Opencode)) => Ok(agent_id),
+        Some(_) => Err(SandboxError::InvalidRequest {
+            message: format!(
+                "config management is only available for codex and opencode, not {agent}"
+            ),
+        }),
+        None => Err(SandboxError::UnsupportedAgent {
+            agent: agent.to_string(),
+        }),
+    }
+}
+
+/// Send `method` to the managed OpenCode server's `/config`, starting the server if needed.
+/// Client credentials are never forwarded; the server only listens on loopback.
+pub(super) async fn opencode_config_request(
+    state: &AppState,
+    method: reqwest::Method,
+    body: Option<Value>,
+) -> Result<Value, SandboxError> {
+    static CLIENT: std::sync::LazyLock<reqwest::Client> = std::sync::LazyLock::new(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default()
+    });
+
+    let base_url = state
+        .opencode_server_manager()
+        .ensure_server()
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: format!("failed to start OpenCode server: {err}"),
+        })?;
+    let mut request = CLIENT.request(method, format!("{base_url}/config"));
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let unreachable = |err: reqwest::Error| SandboxError::StreamError {
+        message: format!("OpenCode server request failed: {err}"),
+    };
+    let response = request.send().await.map_err(unreachable)?;
+    let status = response.status();
+    let text = response.text().await.map_err(unreachable)?;
+    if status.is_client_error() {
+        return Err(SandboxError::InvalidRequest {
+            message: format!("OpenCode server rejected the config ({status}): {text}"),
+        });
+    }
+    if !status.is_success() {
+        return Err(SandboxError::StreamError {
+            message: format!("OpenCode server returned {status}: {text}"),
+        });
+    }
+    serde_json::from_str(&text).map_err(|err| SandboxError::StreamError {
+        message: format!("OpenCode server returned invalid JSON: {err}"),
+    })
+}
+
+pub(super) fn request_actor(actor: Option<Extension<RequestActor>>) -> String {
+    actor.map_or_else(
+        || RequestActor::ANONYMOUS.to_string(),
+        |Extension(actor)| actor.0,
+    )
+}
+
+pub(super) fn fs_audit_entry(
+    kind: AuditKind,
+    actor: Option<Extension<RequestActor>>,
+    server_id: Option<String>,
+    path: &StdPath,
+) -> AuditEntry {
+    let mut entry = audit_entry(kind, request_actor(actor));
+    entry.server_id = server_id;
+    entry.path = Some(path.to_string_lossy().to_string());
+    entry
+}
+
+/// Audit entry for `response` answering `approval` on `server_id`.
+pub(super) fn approval_audit_entry(
+    actor: String,
+    server_id: &str,
+    approval: &crate::approvals::PendingApproval,
+    response: &Value,
+) -> AuditEntry {
+    use crate::approvals::ApprovalKind as PendingKind;
+
+    let kind = match approval.kind {
+        PendingKind::Permission => AuditKind::PermissionReply,
+        PendingKind::Question => AuditKind::QuestionAnswer,
+    };
+    let (decision, mut details) = approval.reply_decision(response);
+    if !details.is_object() {
+        details = json!({});
+    }
+    details["requestId"] = approval.request_id.clone();
+    let mut entry = audit_entry(kind, actor);
+    entry.server_id = Some(server_id.to_string());
+    entry.session_id = approval.session_id.clone();
+    entry.action = approval.action();
+    entry.decision = Some(decision);
+    entry.details = details;
+    entry
+}
+
+pub(super) fn checkpoint_info(
+    manifest: &crate::checkpoints::CheckpointManifest,
+) -> AcpCheckpointInfo {
+    AcpCheckpointInfo {
+        checkpoint_id: manifest.checkpoint_id.clone(),
+        root: manifest.root.to_string_lossy().to_string(),
+        label: manifest.label.clone(),
+        created_at_ms: manifest.created_at_ms,
+        file_count: manifest.files.len(),
+        total_bytes: manifest.files.iter().map(|file| file.size).sum(),
+        truncated: manifest.truncated,
+    }
+}
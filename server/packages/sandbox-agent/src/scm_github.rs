@@ -0,0 +1,469 @@
+//! GitHub repositories for autonomous coding runs.
+//!
+//! `POST /v1/scm/github/clone` clones a repository into a directory, usually an ACP server's
+//! working directory. `POST /v1/acp/{server_id}/sessions/{session_id}/scm/github/pr` commits
+//! that server's working tree on a branch, pushes it and opens a pull request whose body
+//! summarizes the session.
+//!
+//! Both shell out to `git`. Tokens come from the request or `GITHUB_TOKEN`/`GH_TOKEN` and reach
+//! git as an HTTP header in its environment, so they are never written to the clone's config.
+//! `SANDBOX_AGENT_GITHUB_URL` and `SANDBOX_AGENT_GITHUB_API_URL` point at GitHub Enterprise.
+
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header;
+use sandbox_agent_api_types::{
+    AcpItem, AcpItemKind, AcpItemStatus, GithubPullRequestRequest, GithubPullRequestResponse,
+};
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+use tokio::process::Command;
+
+use crate::transcript;
+
+const GITHUB_URL_ENV: &str = "SANDBOX_AGENT_GITHUB_URL";
+const GITHUB_API_URL_ENV: &str = "SANDBOX_AGENT_GITHUB_API_URL";
+const DEFAULT_GITHUB_URL: &str = "https://github.com";
+const DEFAULT_GITHUB_API_URL: &str = "https://api.github.com";
+const TOKEN_ENVS: [&str; 2] = ["GITHUB_TOKEN", "GH_TOKEN"];
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+/// GitHub rejects pull request bodies over 65536 characters.
+const MAX_BODY_CHARS: usize = 60_000;
+const MAX_TITLE_CHARS: usize = 72;
+/// Identity for commits when git has none configured.
+const COMMIT_NAME: &str = "sandbox-agent";
+const COMMIT_EMAIL: &str = "sandbox-agent@users.noreply.github.com";
+
+/// The request's token, or the one in the environment.
+pub(crate) fn token(explicit: Option<&str>) -> Option<String> {
+    explicit
+        .map(str::to_string)
+        .or_else(|| TOKEN_ENVS.iter().find_map(|key| std::env::var(key).ok()))
+        .filter(|token| !token.is_empty())
+}
+
+/// Clone `repo` into `target`, which must be missing or empty, and check out `git_ref`.
+/// Returns the checked out commit.
+pub(crate) async fn clone_repo(
+    repo: &str,
+    git_ref: Option<&str>,
+    token: Option<&str>,
+    target: &Path,
+) -> Result<String, SandboxError> {
+    validate_repo(repo)?;
+    if target.exists() {
+        if !target.is_dir() {
+            return Err(SandboxError::NotADirectory {
+                path: target.display().to_string(),
+            });
+        }
+        let empty = fs::read_dir(target)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(false);
+        if !empty {
+            return Err(SandboxError::Conflict {
+                message: format!("{} is not empty", target.display()),
+            });
+        }
+    }
+    fs::create_dir_all(target).map_err(|err| SandboxError::StreamError {
+        message: format!("failed to create {}: {err}", target.display()),
+    })?;
+    let url = format!(
+        "{}/{repo}.git",
+        base_url(GITHUB_URL_ENV, DEFAULT_GITHUB_URL)
+    );
+    git(target, &["clone", "--quiet", &url, "."], token).await?;
+    if let Some(git_ref) = git_ref {
+        git(target, &["checkout", "--quiet", git_ref], None).await?;
+    }
+    git(target, &["rev-parse", "HEAD"], None).await
+}
+
+/// Commit the working tree of `dir` on a branch, push it and open a pull request.
+pub(crate) async fn open_pull_request(
+    dir: &Path,
+    session_id: &str,
+    request: &GithubPullRequestRequest,
+    items: &[AcpItem],
+) -> Result<GithubPullRequestResponse, SandboxError> {
+    let token = token(request.token.as_deref()).ok_or_else(|| SandboxError::InvalidRequest {
+        message: "a GitHub token is required; pass token or set GITHUB_TOKEN".to_string(),
+    })?;
+    let not_a_clone = |_| SandboxError::InvalidRequest {
+        message: format!("{} is not a clone with an origin remote", dir.display()),
+    };
+    let remote = git(dir, &["remote", "get-url", "origin"], None)
+        .await
+        .map_err(not_a_clone)?;
+    let repo = repo_from_remote(&remote).ok_or_else(|| SandboxError::InvalidRequest {
+        message: format!("origin {remote} is not a GitHub repository"),
+    })?;
+    let base = match &request.base {
+        Some(base) => base.clone(),
+        None => default_base(dir).await?,
+    };
+    let branch = request
+        .branch
+        .clone()
+        .unwrap_or_else(|| format!("sandbox-agent/{}", branch_name(session_id)));
+    if branch == base {
+        return Err(SandboxError::InvalidRequest {
+            message: format!("branch and base are both {base}"),
+        });
+    }
+
+    git(dir, &["add", "--all"], None).await?;
+    let changed = !git(dir, &["status", "--porcelain"], None).await?.is_empty();
+    // A base that was never fetched is left for GitHub to check.
+    let ahead = git(
+        dir,
+        &["rev-list", "--count", &format!("origin/{base}..HEAD")],
+        None,
+    )
+    .await
+    .map_or(true, |count| count != "0");
+    if !changed && !ahead {
+        return Err(SandboxError::Conflict {
+            message: format!("no changes over {base}"),
+        });
+    }
+    let title = request
+        .title
+        .clone()
+        .unwrap_or_else(|| default_title(session_id, items));
+    git(dir, &["checkout", "--quiet", "-B", &branch], None).await?;
+    if changed {
+        let message = request.commit_message.as_deref().unwrap_or(&title);
+        commit(dir, message).await?;
+    }
+    git(
+        dir,
+        &[
+            "push",
+            "--quiet",
+            "origin",
+            &format!("HEAD:refs/heads/{branch}"),
+        ],
+        Some(&token),
+    )
+    .await?;
+    let commit = git(dir, &["rev-parse", "HEAD"], None).await?;
+
+    let body = request
+        .body
+        .clone()
+        .unwrap_or_else(|| summary(session_id, items));
+    let (number, url) = create_pull_request(
+        &repo,
+        &token,
+        json!({
+            "title": title,
+            "head": branch,
+            "base": base,
+            "body": body,
+            "draft": request.draft.unwrap_or(false),
+        }),
+    )
+    .await?;
+    Ok(GithubPullRequestResponse {
+        repo,
+        number,
+        url,
+        branch,
+        base,
+        commit,
+    })
+}
+
+/// The branch `origin/HEAD` points at, which a clone sets to the default branch, or else the
+/// checked out branch.
+async fn default_base(dir: &Path) -> Result<String, SandboxError> {
+    if let Ok(head) = git(
+        dir,
+        &["symbolic-ref", "--short", "refs/remotes/origin/HEAD"],
+        None,
+    )
+    .await
+    {
+        if let Some(branch) = head.strip_prefix("origin/") {
+            return Ok(branch.to_string());
+        }
+    }
+    git(dir, &["symbolic-ref", "--short", "HEAD"], None)
+        .await
+        .map_err(|_| SandboxError::InvalidRequest {
+            message: "HEAD is detached; set base".to_string(),
+        })
+}
+
+async fn commit(dir: &Path, message: &str) -> Result<(), SandboxError> {
+    let mut args = Vec::new();
+    if git(dir, &["config", "user.email"], None).await.is_err() {
+        args.extend([
+            "-c".to_string(),
+            format!("user.name={COMMIT_NAME}"),
+            "-c".to_string(),
+            format!("user.email={COMMIT_EMAIL}"),
+        ]);
+    }
+    args.extend(["commit", "--quiet", "-m", message].map(str::to_string));
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    git(dir, &args, None).await.map(drop)
+}
+
+async fn create_pull_request(
+    repo: &str,
+    token: &str,
+    body: Value,
+) -> Result<(u64, String), SandboxError> {
+    let url = format!(
+        "{}/repos/{repo}/pulls",
+        base_url(GITHUB_API_URL_ENV, DEFAULT_GITHUB_API_URL)
+    );
+    let failed = |message: String| SandboxError::StreamError {
+        message: format!("GitHub pull request failed: {message}"),
+    };
+    let response = reqwest::Client::builder()
+        .timeout(API_TIMEOUT)
+        .build()
+        .map_err(|err| failed(err.to_string()))?
+        .post(url)
+        .bearer_auth(token)
+        .header(header::ACCEPT, "application/vnd.github+json")
+        .header(header::USER_AGENT, "sandbox-agent")
+        .json(&body)
+        .send()
+        .await
+        .map_err(|err| failed(err.to_string()))?;
+    let status = response.status();
+    let reply = response.json::<Value>().await.unwrap_or_default();
+    if !status.is_success() {
+        let mut message = reply
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or("no message")
+            .to_string();
+        if let Some(detail) = reply.pointer("/errors/0/message").and_then(Value::as_str) {
+            message = format!("{message}: {detail}");
+        }
+        // 422 is how GitHub reports an existing pull request for the branch.
+        if status == reqwest::StatusCode::UNPROCESSABLE_ENTITY {
+            return Err(SandboxError::Conflict { message });
+        }
+        return Err(failed(format!("{status}: {message}")));
+    }
+    match (
+        reply.get("number").and_then(Value::as_u64),
+        reply.get("html_url").and_then(Value::as_str),
+    ) {
+        (Some(number), Some(url)) => Ok((number, url.to_string())),
+        _ => Err(failed("the response has no number or html_url".to_string())),
+    }
+}
+
+pub(crate) async fn git(
+    dir: &Path,
+    args: &[&str],
+    token: Option<&str>,
+) -> Result<String, SandboxError> {
+    let mut command = Command::new("git");
+    command
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(token) = token {
+        let credentials = STANDARD.encode(format!("x-access-token:{token}"));
+        command
+            .env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "http.extraHeader")
+            .env(
+                "GIT_CONFIG_VALUE_0",
+                format!("Authorization: Basic {credentials}"),
+            );
+    }
+    let output = command
+        .output()
+        .await
+        .map_err(|err| SandboxError::StreamError {
+            message: format!("failed to run git: {err}"),
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(SandboxError::StreamError {
+            message: format!("git {} failed: {}", args.join(" "), stderr.trim()),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn base_url(env: &str, default: &str) -> String {
+    std::env::var(env)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| default.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn validate_repo(repo: &str) -> Result<(), SandboxError> {
+    let valid = repo.split_once('/').is_some_and(|(owner, name)| {
+        [owner, name].iter().all(|part| {
+            !part.is_empty()
+                && !part.starts_with('.')
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(SandboxError::InvalidRequest {
+            message: format!("repo must be owner/name, got '{repo}'"),
+        })
+    }
+}
+
+/// `owner/name` from the last two segments of an HTTPS, SSH or scp-style remote URL.
+fn repo_from_remote(remote: &str) -> Option<String> {
+    let path = remote.trim_end_matches('/').trim_end_matches(".git");
+    let mut segments = path.rsplit(['/', ':']);
+    let name = segments.next()?;
+    let owner = segments.next()?;
+    let repo = format!("{owner}/{name}");
+    validate_repo(&repo).ok().map(|()| repo)
+}
+
+fn branch_name(session_id: &str) -> String {
+    session_id
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || matches!(c, '-' | '_') => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+fn default_title(session_id: &str, items: &[AcpItem]) -> String {
+    let line = items
+        .iter()
+        .filter(|item| item.kind == AcpItemKind::UserMessage)
+        .find_map(|item| {
+            item.text
+                .as_deref()?
+                .lines()
+                .find(|line| !line.trim().is_empty())
+        })
+        .map(str::trim);
+    match line {
+        Some(line) if line.chars().count() > MAX_TITLE_CHARS => {
+            let cut = line.chars().take(MAX_TITLE_CHARS - 1).collect::<String>();
+            format!("{}…", cut.trim_end())
+        }
+        Some(line) => line.to_string(),
+        None => format!("Changes from session {session_id}"),
+    }
+}
+
+/// The pull request body: the agent's last message, the prompts and the transcript, which is
+/// left out when the body would be too long.
+fn summary(session_id: &str, items: &[AcpItem]) -> String {
+    let mut out = String::new();
+    if let Some(text) = items
+        .iter()
+        .rev()
+        .filter(|item| item.kind == AcpItemKind::AgentMessage)
+        .find_map(|item| item.text.as_deref())
+    {
+        out.push_str(&format!("## Summary\n\n{}\n\n", text.trim()));
+    }
+    let prompts = items
+        .iter()
+        .filter(|item| item.kind == AcpItemKind::UserMessage)
+        .filter_map(|item| item.text.as_deref())
+        .collect::<Vec<_>>();
+    if !prompts.is_empty() {
+        out.push_str("## Prompts\n\n");
+        for prompt in prompts {
+            for line in prompt.trim().lines() {
+                out.push_str(format!("> {line}").trim_end());
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+    }
+    let tools = items
+        .iter()
+        .filter(|item| item.kind == AcpItemKind::ToolCall)
+        .count();
+    let failed = items
+        .iter()
+        .filter(|item| item.kind == AcpItemKind::ToolCall && item.status == AcpItemStatus::Failed)
+        .count();
+    let transcript = transcript::markdown(session_id, items);
+    let footer = format!(
+        "_Opened by sandbox-agent from session `{session_id}`: {tools} tool calls, {failed} failed._\n"
+    );
+    if out.len() + transcript.len() + footer.len() < MAX_BODY_CHARS {
+        out.push_str("<details>\n<summary>Transcript</summary>\n\n");
+        out.push_str(&transcript);
+        out.push_str("\n</details>\n\n");
+    }
+    out.push_str(&footer);
+    if out.chars().count() > MAX_BODY_CHARS {
+        out = out.chars().take(MAX_BODY_CHARS).collect();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remotes_and_titles() {
+        for remote in [
+            "https://github.com/rivet-dev/sandbox-agent.git",
+            "git@github.com:rivet-dev/sandbox-agent.git",
+            "ssh://git@github.example.com/rivet-dev/sandbox-agent",
+            "file:///tmp/remotes/rivet-dev/sandbox-agent.git/",
+        ] {
+            assert_eq!(
+                repo_from_remote(remote).as_deref(),
+                Some("rivet-dev/sandbox-agent"),
+                "{remote}"
+            );
+        }
+        assert!(repo_from_remote("sandbox-agent").is_none());
+        assert!(validate_repo("../etc").is_err());
+        assert!(validate_repo("owner/name/extra").is_err());
+        assert_eq!(branch_name("ses_01/a b"), "ses_01-a-b");
+
+        let prompt = AcpItem {
+            item_id: "prompt-1".to_string(),
+            kind: AcpItemKind::UserMessage,
+            status: AcpItemStatus::Completed,
+            text: Some(format!("\n{}\nmore", "Fix the flaky test ".repeat(5))),
+            content: Vec::new(),
+            title: None,
+            tool_kind: None,
+            raw_input: None,
+            raw_output: None,
+            parent_tool_call_id: None,
+            sequence: 0,
+            updated_sequence: 0,
+        };
+        let title = default_title("s-1", &[prompt]);
+        assert!(title.chars().count() <= MAX_TITLE_CHARS);
+        assert!(title.starts_with("Fix the flaky test Fix") && title.ends_with('…'));
+        assert_eq!(default_title("s-1", &[]), "Changes from session s-1");
+    }
+}
@@ -0,0 +1,504 @@
+//! Session transcripts folded from ACP events into items.
+//!
+//! - `user_message_chunk`, `agent_message_chunk` and `agent_thought_chunk` updates in a row
+//!   join into one user message, agent message or reasoning item. The item is completed when
+//!   another item starts or the prompt's turn ends.
+//! - `tool_call` starts a tool call item under its `toolCallId`, and `tool_call_update`s
+//!   replace its status, title, content and raw input and output.
+//! - `session/prompt` requests sent through the daemon add a user message item. Replayed
+//!   history from `session/load` arrives as user message chunks instead.
+//!
+//! Text blocks are joined into the item's `text`; other content blocks are kept as they came.
+//!
+//! The parsed values of `_sandboxagent/structured_output` events are kept beside the items for
+//! the findings export.
+//!
+//! Each `session/prompt` sent through the daemon also starts a turn, which ends with the
+//! prompt's response. A turn holds its prompt and the items that started after it and before
+//! its response.
+
+use std::collections::HashMap;
+
+use sandbox_agent_api_types::{
+    AcpItem, AcpItemKind, AcpItemStatus, AcpSessionTurn, AcpSessionTurnStatus,
+};
+use serde_json::Value;
+
+#[derive(Debug, Default)]
+struct SessionItems {
+    /// Items with their sort key: twice the sequence, plus one for prompts so a prompt comes
+    /// after the event it was sent after.
+    items: Vec<(u64, AcpItem)>,
+    /// Index of the message or reasoning item still receiving chunks.
+    open: Option<usize>,
+    tool_calls: HashMap<String, usize>,
+    /// Structured outputs of the session's turns, oldest first.
+    outputs: Vec<Value>,
+    turns: Vec<Turn>,
+}
+
+#[derive(Debug, Clone)]
+struct Turn {
+    turn_id: String,
+    prompt_item_id: String,
+    /// Sequence of the last event before the prompt.
+    after: u64,
+    /// Sequence of the prompt's response.
+    ended_sequence: Option<u64>,
+    started_at_ms: i64,
+    ended_at_ms: Option<i64>,
+    status: AcpSessionTurnStatus,
+    stop_reason: Option<String>,
+    usage: Option<Value>,
+}
+
+impl Turn {
+    /// Whether the item sorted under `key` belongs to this turn, which `next` follows.
+    fn contains(&self, next: Option<&Turn>, key: u64, item: &AcpItem) -> bool {
+        if item.item_id == self.prompt_item_id {
+            return true;
+        }
+        key > self.after * 2 + 1
+            && next.is_none_or(|next| key < next.after * 2 + 1)
+            && self
+                .ended_sequence
+                .is_none_or(|ended| item.sequence <= ended)
+    }
+
+    fn end(&mut self, sequence: u64, response: &Value, now_ms: i64) {
+        self.ended_sequence = Some(sequence);
+        self.ended_at_ms = Some(now_ms);
+        let result = response.get("result");
+        self.stop_reason = result
+            .and_then(|result| result.get("stopReason"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        self.usage = result
+            .and_then(|result| result.get("usage"))
+            .filter(|usage| usage.is_object())
+            .cloned();
+        self.status = match (result, self.stop_reason.as_deref()) {
+            (None, _) => AcpSessionTurnStatus::Failed,
+            (Some(_), Some("cancelled")) => AcpSessionTurnStatus::Cancelled,
+            (Some(_), _) => AcpSessionTurnStatus::Completed,
+        };
+    }
+}
+
+impl SessionItems {
+    fn close(&mut self) {
+        if let Some(index) = self.open.take() {
+            self.items[index].1.status = AcpItemStatus::Completed;
+        }
+    }
+
+    fn push(&mut self, key: u64, item: AcpItem) -> usize {
+        self.items.push((key, item));
+        self.items.len() - 1
+    }
+}
+
+/// The items of every session on one ACP server.
+#[derive(Debug, Default)]
+pub(crate) struct SessionItemLog {
+    sessions: HashMap<String, SessionItems>,
+    /// Session and turn index of each prompt still running, keyed by the JSON text of its
+    /// request id.
+    prompts: HashMap<String, (String, usize)>,
+    prompt_count: u64,
+}
+
+impl SessionItemLog {
+    /// Add the user message and turn of a `session/prompt` a client sent after event `after`.
+    pub(crate) fn record_prompt(&mut self, after: u64, request: &Value, now_ms: i64) {
+        let Some(session_id) = request.pointer("/params/sessionId").and_then(Value::as_str) else {
+            return;
+        };
+        self.prompt_count += 1;
+        let session = self.sessions.entry(session_id.to_string()).or_default();
+        let prompt_item_id = format!("prompt-{}", self.prompt_count);
+        session.turns.push(Turn {
+            turn_id: format!("turn-{}", self.prompt_count),
+            prompt_item_id: prompt_item_id.clone(),
+            after,
+            ended_sequence: None,
+            started_at_ms: now_ms,
+            ended_at_ms: None,
+            status: AcpSessionTurnStatus::Running,
+            stop_reason: None,
+            usage: None,
+        });
+        if let Some(id) = request.get("id") {
+            self.prompts.insert(
+                id.to_string(),
+                (session_id.to_string(), session.turns.len() - 1),
+            );
+        }
+        let mut item = new_item(prompt_item_id, AcpItemKind::UserMessage, after);
+        item.status = AcpItemStatus::Completed;
+        for block in request
+            .pointer("/params/prompt")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            append(&mut item, block);
+        }
+        session.close();
+        session.push(after * 2 + 1, item);
+    }
+
+    /// Fold an event into the items of its session.
+    pub(crate) fn record(&mut self, sequence: u64, message: &Value, now_ms: i64) {
+        if message.get("method").is_none() {
+            // A prompt's response ends its turn.
+            let Some((session_id, turn)) = message
+                .get("id")
+                .and_then(|id| self.prompts.remove(&id.to_string()))
+            else {
+                return;
+            };
+            if let Some(session) = self.sessions.get_mut(&session_id) {
+                session.close();
+                if let Some(turn) = session.turns.get_mut(turn) {
+                    turn.end(sequence, message, now_ms);
+                }
+            }
+            return;
+        }
+        match message.get("method").and_then(Value::as_str) {
+            Some("session/update") => {}
+            Some("_sandboxagent/structured_output") => {
+                if let (Some(session_id), Some(output)) = (
+                    message.pointer("/params/sessionId").and_then(Value::as_str),
+                    message.pointer("/params/output"),
+                ) {
+                    let session = self.sessions.entry(session_id.to_string()).or_default();
+                    session.outputs.push(output.clone());
+                }
+                return;
+            }
+            _ => return,
+        }
+        let (Some(session_id), Some(update)) = (
+            message.pointer("/params/sessionId").and_then(Value::as_str),
+            message.pointer("/params/update"),
+        ) else {
+            return;
+        };
+        let session = self.sessions.entry(session_id.to_string()).or_default();
+        let kind = match update.get("sessionUpdate").and_then(Value::as_str) {
+            Some("user_message_chunk") => AcpItemKind::UserMessage,
+            Some("agent_message_chunk") => AcpItemKind::AgentMessage,
+            Some("agent_thought_chunk") => AcpItemKind::Reasoning,
+            Some("tool_call" | "tool_call_update") => {
+                record_tool_call(session, sequence, update);
+                return;
+            }
+            _ => return,
+        };
+        let index = match session.open {
+            Some(index) if session.items[index].1.kind == kind => index,
+            _ => {
+                session.close();
+                let prefix = match kind {
+                    AcpItemKind::UserMessage => "user_message",
+                    AcpItemKind::AgentMessage => "agent_message",
+                    _ => "reasoning",
+                };
+                let item = new_item(format!("{prefix}-{sequence}"), kind, sequence);
+                let index = session.push(sequence * 2, item);
+                session.open = Some(index);
+                index
+            }
+        };
+        let item = &mut session.items[index].1;
+        item.updated_sequence = sequence;
+        if let Some(content) = update.get("content") {
+            append(item, content);
+        }
+    }
+
+    /// Items of `session_id` in transcript order.
+    pub(crate) fn list(&self, session_id: &str) -> Vec<AcpItem> {
+        let Some(session) = self.sessions.get(session_id) else {
+            return Vec::new();
+        };
+        let mut items = session.items.iter().collect::<Vec<_>>();
+        items.sort_by_key(|(key, _)| *key);
+        items.into_iter().map(|(_, item)| item.clone()).collect()
+    }
+
+    /// Turns of `session_id`, oldest first.
+    pub(crate) fn turns(&self, session_id: &str) -> Vec<AcpSessionTurn> {
+        let Some(session) = self.sessions.get(session_id) else {
+            return Vec::new();
+        };
+        (0..session.turns.len())
+            .map(|index| session_turn(session, index))
+            .collect()
+    }
+
+    pub(crate) fn turn(&self, session_id: &str, turn_id: &str) -> Option<AcpSessionTurn> {
+        let session = self.sessions.get(session_id)?;
+        let index = session
+            .turns
+            .iter()
+            .position(|turn| turn.turn_id == turn_id)?;
+        Some(session_turn(session, index))
+    }
+
+    pub(crate) fn outputs(&self, session_id: &str) -> Vec<Value> {
+        self.sessions
+            .get(session_id)
+            .map(|session| session.outputs.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn get(&self, session_id: &str, item_id: &str) -> Option<AcpItem> {
+        self.sessions
+            .get(session_id)?
+            .items
+            .iter()
+            .find(|(_, item)| item.item_id == item_id)
+            .map(|(_, item)| item.clone())
+    }
+}
+
+fn session_turn(session: &SessionItems, index: usize) -> AcpSessionTurn {
+    let turn = &session.turns[index];
+    let next = session.turns.get(index + 1);
+    let mut items = session
+        .items
+        .iter()
+        .filter(|(key, item)| turn.contains(next, *key, item))
+        .collect::<Vec<_>>();
+    items.sort_by_key(|(key, _)| *key);
+    AcpSessionTurn {
+        turn_id: turn.turn_id.clone(),
+        status: turn.status,
+        started_at_ms: turn.started_at_ms,
+        ended_at_ms: turn.ended_at_ms,
+        prompt_item_id: turn.prompt_item_id.clone(),
+        stop_reason: turn.stop_reason.clone(),
+        usage: turn.usage.clone(),
+        items: items.into_iter().map(|(_, item)| item.clone()).collect(),
+    }
+}
+
+fn record_tool_call(session: &mut SessionItems, sequence: u64, update: &Value) {
+    let Some(tool_call_id) = update.get("toolCallId").and_then(Value::as_str) else {
+        return;
+    };
+    let index = match session.tool_calls.get(tool_call_id) {
+        Some(index) => *index,
+        None => {
+            session.close();
+            let item = new_item(tool_call_id.to_string(), AcpItemKind::ToolCall, sequence);
+            let index = session.push(sequence * 2, item);
+            session.tool_calls.insert(tool_call_id.to_string(), index);
+            index
+        }
+    };
+    let item = &mut session.items[index].1;
+    item.updated_sequence = sequence;
+    let string = |key: &str| update.get(key).and_then(Value::as_str).map(str::to_string);
+    if let Some(status) = string("status") {
+        item.status = match status.as_str() {
+            "completed" => AcpItemStatus::Completed,
+            "failed" => AcpItemStatus::Failed,
+            _ => AcpItemStatus::InProgress,
+        };
+    }
+    if let Some(title) = string("title") {
+        item.title = Some(title);
+    }
+    if let Some(kind) = string("kind") {
+        item.tool_kind = Some(kind);
+    }
+    if let Some(content) = update.get("content").and_then(Value::as_array) {
+        item.content = content.clone();
+    }
+    if let Some(raw_input) = update.get("rawInput") {
+        item.raw_input = Some(raw_input.clone());
+    }
+    if let Some(raw_output) = update.get("rawOutput") {
+        item.raw_output = Some(raw_output.clone());
+    }
+}
+
+fn new_item(item_id: String, kind: AcpItemKind, sequence: u64) -> AcpItem {
+    AcpItem {
+        item_id,
+        kind,
+        status: AcpItemStatus::InProgress,
+        text: None,
+        content: Vec::new(),
+        title: None,
+        tool_kind: None,
+        raw_input: None,
+        raw_output: None,
+        parent_tool_call_id: None,
+        sequence,
+        updated_sequence: sequence,
+    }
+}
+
+/// Join a text block into the item's text, or keep another block as it came.
+fn append(item: &mut AcpItem, block: &Value) {
+    match block.get("text").and_then(Value::as_str) {
+        Some(text) if block.get("type").and_then(Value::as_str) == Some("text") => {
+            item.text.get_or_insert_with(String::new).push_str(text);
+        }
+        _ => item.content.push(block.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn update(update: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": "s-1", "update": update },
+        })
+    }
+
+    fn chunk(kind: &str, text: &str) -> Value {
+        update(json!({ "sessionUpdate": kind, "content": { "type": "text", "text": text } }))
+    }
+
+    #[test]
+    fn events_fold_into_a_transcript() {
+        let mut log = SessionItemLog::default();
+        log.record_prompt(
+            0,
+            &json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "session/prompt",
+                "params": { "sessionId": "s-1", "prompt": [{ "type": "text", "text": "fix it" }] },
+            }),
+            1_000,
+        );
+        log.record(1, &chunk("agent_thought_chunk", "Looking"), 0);
+        log.record(2, &chunk("agent_message_chunk", "On "), 0);
+        log.record(3, &chunk("agent_message_chunk", "it"), 0);
+        log.record(
+            4,
+            &update(json!({
+                "sessionUpdate": "tool_call",
+                "toolCallId": "t1",
+                "title": "Run tests",
+                "kind": "execute",
+                "status": "pending",
+            })),
+            0,
+        );
+        log.record(5, &chunk("agent_message_chunk", "Running tests"), 0);
+        log.record(
+            6,
+            &update(json!({
+                "sessionUpdate": "tool_call_update",
+                "toolCallId": "t1",
+                "status": "failed",
+                "content": [{ "type": "content", "content": { "type": "text", "text": "1 failed" } }],
+            })),
+            0,
+        );
+        log.record(
+            7,
+            &json!({ "jsonrpc": "2.0", "id": 2, "result": { "stopReason": "end_turn" } }),
+            0,
+        );
+
+        let items = log.list("s-1");
+        let kinds = items.iter().map(|item| item.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                AcpItemKind::UserMessage,
+                AcpItemKind::Reasoning,
+                AcpItemKind::AgentMessage,
+                AcpItemKind::ToolCall,
+                AcpItemKind::AgentMessage,
+            ]
+        );
+        assert_eq!(items[0].text.as_deref(), Some("fix it"));
+        assert_eq!(items[2].text.as_deref(), Some("On it"));
+        assert_eq!(items[2].item_id, "agent_message-2");
+        assert_eq!(items[2].updated_sequence, 3);
+        assert!(items
+            .iter()
+            .filter(|item| item.kind != AcpItemKind::ToolCall)
+            .all(|item| item.status == AcpItemStatus::Completed));
+
+        let tool = log.get("s-1", "t1").expect("tool call");
+        assert_eq!(tool.status, AcpItemStatus::Failed);
+        assert_eq!(tool.title.as_deref(), Some("Run tests"));
+        assert_eq!(tool.content.len(), 1);
+        assert!(log.get("s-1", "missing").is_none());
+        assert!(log.list("s-2").is_empty());
+    }
+
+    #[test]
+    fn prompts_and_responses_bound_turns() {
+        let prompt = |id: u64, text: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "session/prompt",
+                "params": { "sessionId": "s-1", "prompt": [{ "type": "text", "text": text }] },
+            })
+        };
+        let mut log = SessionItemLog::default();
+        log.record_prompt(0, &prompt(2, "fix it"), 1_000);
+        log.record(1, &chunk("agent_message_chunk", "Done"), 1_100);
+        log.record(
+            2,
+            &json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "result": { "stopReason": "end_turn", "usage": { "totalTokens": 42 } },
+            }),
+            1_200,
+        );
+        log.record_prompt(2, &prompt(3, "again"), 2_000);
+        log.record(3, &chunk("agent_message_chunk", "Stopping"), 2_100);
+
+        let turns = log.turns("s-1");
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].turn_id, "turn-1");
+        assert_eq!(turns[0].status, AcpSessionTurnStatus::Completed);
+        assert_eq!(turns[0].stop_reason.as_deref(), Some("end_turn"));
+        assert_eq!(turns[0].usage, Some(json!({ "totalTokens": 42 })));
+        assert_eq!(
+            (turns[0].started_at_ms, turns[0].ended_at_ms),
+            (1_000, Some(1_200))
+        );
+        let ids = |turn: &AcpSessionTurn| {
+            turn.items
+                .iter()
+                .map(|item| item.item_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&turns[0]), ["prompt-1", "agent_message-1"]);
+        assert_eq!(turns[1].status, AcpSessionTurnStatus::Running);
+        assert_eq!(ids(&turns[1]), ["prompt-2", "agent_message-3"]);
+
+        log.record(
+            4,
+            &json!({ "jsonrpc": "2.0", "id": 3, "error": { "code": -32603, "message": "boom" } }),
+            2_200,
+        );
+        let turn = log.turn("s-1", "turn-2").expect("turn");
+        assert_eq!(turn.status, AcpSessionTurnStatus::Failed);
+        assert_eq!(turn.ended_at_ms, Some(2_200));
+        assert!(log.turn("s-1", "turn-3").is_none());
+        assert!(log.turns("s-2").is_empty());
+    }
+}
@@ -0,0 +1,743 @@
+//! Skills installed independently of sessions.
+//!
+//! `POST /v1/skills/install` fetches a `github`, `git`, `archive` or `local` source, finds the
+//! directories under it that hold a `SKILL.md` and copies each into the store
+//! (`SANDBOX_AGENT_SKILLS_DIR`, by default `sandbox-agent/skills` in the data directory). Every
+//! installed skill is then copied into the skill directory of each agent that reads one:
+//!
+//! - Claude: `$CLAUDE_CONFIG_DIR/skills`, or `~/.claude/skills`
+//! - Codex: `$CODEX_HOME/skills`, or `~/.codex/skills`
+//! - OpenCode: `$OPENCODE_CONFIG_DIR/skills`, or `~/.config/opencode/skills`
+//!
+//! Each copy carries the skill's metadata file. Installs and `DELETE /v1/skills/{name}` only
+//! replace or remove copies that have it, so skills written into those directories by hand are
+//! left alone.
+//!
+//! Fetched `github`, `git` and `archive` sources are kept in a cache (`--skills-cache-dir` or
+//! `SANDBOX_AGENT_SKILLS_CACHE_DIR`, by default `sandbox-agent/skills-cache`), one directory per
+//! SHA-256 of the source's type, location and ref. Sources pinned to content, an archive with
+//! `sha256` or a `ref` that is a full commit hash, are installed from the cache without touching
+//! the network; `reuse` does the same for any cached source. Archives pinned with `sha256` are
+//! keyed by that digest, so the same archive served from different URLs is fetched once.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+use sandbox_agent_api_types::{InstalledSkill, SkillInstallResponse, SkillSource};
+use sandbox_agent_error::SandboxError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::router::{map_fs_error, now_ms};
+use crate::scm_github;
+
+pub const SKILLS_DIR_ENV: &str = "SANDBOX_AGENT_SKILLS_DIR";
+pub const SKILLS_CACHE_DIR_ENV: &str = "SANDBOX_AGENT_SKILLS_CACHE_DIR";
+/// Written next to `SKILL.md` in the store and in every synced copy.
+const METADATA_FILE: &str = ".sandbox-agent-skill.json";
+const STAGING_DIR: &str = ".staging";
+/// Written next to the fetched tree in each cache entry.
+const CACHE_ENTRY_FILE: &str = "entry.json";
+const MAX_ARCHIVE_BYTES: usize = 64 * 1024 * 1024;
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+/// Directories deeper than this below the source root are not searched for `SKILL.md`.
+const MAX_SEARCH_DEPTH: usize = 4;
+
+static STAGING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn default_skills_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("sandbox-agent").join("skills"))
+        .unwrap_or_else(|| PathBuf::from(".").join(".sandbox-agent").join("skills"))
+}
+
+pub fn default_skills_cache_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("sandbox-agent").join("skills-cache"))
+        .unwrap_or_else(|| {
+            PathBuf::from(".")
+                .join(".sandbox-agent")
+                .join("skills-cache")
+        })
+}
+
+/// Agent skill directories that installed skills are copied into. Resolved on every call so a
+/// changed environment is picked up without a restart.
+fn sync_targets() -> Vec<(&'static str, PathBuf)> {
+    let config_dir = |env: &str, fallback: &[&str]| {
+        std::env::var_os(env)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+                fallback.iter().fold(home, |dir, part| dir.join(part))
+            })
+    };
+    vec![
+        (
+            "claude",
+            config_dir("CLAUDE_CONFIG_DIR", &[".claude"]).join("skills"),
+        ),
+        (
+            "codex",
+            config_dir("CODEX_HOME", &[".codex"]).join("skills"),
+        ),
+        (
+            "opencode",
+            config_dir("OPENCODE_CONFIG_DIR", &[".config", "opencode"]).join("skills"),
+        ),
+    ]
+}
+
+#[derive(Debug)]
+pub(crate) struct SkillStore {
+    dir: PathBuf,
+    cache_dir: PathBuf,
+    /// Serializes installs and removals.
+    lock: Mutex<()>,
+}
+
+impl SkillStore {
+    pub(crate) fn from_env() -> Self {
+        let dir = std::env::var_os(SKILLS_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(default_skills_dir);
+        let cache_dir = std::env::var_os(SKILLS_CACHE_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(default_skills_cache_dir);
+        Self {
+            dir,
+            cache_dir,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn with_cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir = cache_dir;
+        self
+    }
+
+    pub(crate) fn list(&self) -> Result<Vec<InstalledSkill>, SandboxError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(map_fs_error(&self.dir, err)),
+        };
+        let mut skills: Vec<InstalledSkill> = entries
+            .flatten()
+            .filter_map(|entry| read_metadata(&entry.path()))
+            .collect();
+        skills.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(skills)
+    }
+
+    /// Install every skill found in `source`, replacing installed skills of the same name.
+    pub(crate) async fn install(
+        &self,
+        source: &SkillSource,
+    ) -> Result<SkillInstallResponse, SandboxError> {
+        let _guard = self.lock.lock().await;
+        let staging = self.dir.join(STAGING_DIR).join(format!(
+            "{}-{}",
+            std::process::id(),
+            STAGING_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&staging).map_err(|err| map_fs_error(&staging, err))?;
+        let result = self.install_staged(source, &staging).await;
+        let _ = fs::remove_dir_all(&staging);
+        // Only succeeds once no other staging directory is left.
+        let _ = fs::remove_dir(self.dir.join(STAGING_DIR));
+        result
+    }
+
+    async fn install_staged(
+        &self,
+        source: &SkillSource,
+        staging: &Path,
+    ) -> Result<SkillInstallResponse, SandboxError> {
+        let (base, source_version, cached) = if source.source_type == "local" {
+            let (base, version) = fetch(source, &staging.join("source"), None).await?;
+            (base, version, false)
+        } else {
+            self.fetch_cached(source).await?
+        };
+        let root = match source.subpath.as_deref() {
+            Some(subpath) => base.join(relative_subpath(subpath)?),
+            None => base.clone(),
+        };
+        if !root.is_dir() {
+            return Err(SandboxError::InvalidRequest {
+                message: format!("{} is not a directory in the source", root.display()),
+            });
+        }
+
+        let mut found = Vec::new();
+        find_skills(&root, 0, &mut found);
+        let mut selected: Vec<(String, Frontmatter, PathBuf)> = Vec::new();
+        for dir in found {
+            let frontmatter = read_frontmatter(&dir.join("SKILL.md"));
+            let name = match frontmatter.name.clone() {
+                Some(name) => name,
+                None if dir == base => source_name(&source.source),
+                None => dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            };
+            let wanted = source
+                .skills
+                .as_ref()
+                .is_none_or(|skills| skills.iter().any(|skill| is_requested(skill, &name, &dir)));
+            if !wanted {
+                continue;
+            }
+            validate_name(&name)?;
+            if selected.iter().any(|(other, _, _)| *other == name) {
+                return Err(SandboxError::InvalidRequest {
+                    message: format!("source contains more than one skill named '{name}'"),
+                });
+            }
+            selected.push((name, frontmatter, dir));
+        }
+        if let Some(skills) = &source.skills {
+            let missing: Vec<&str> = skills
+                .iter()
+                .filter(|skill| {
+                    !selected
+                        .iter()
+                        .any(|(name, _, dir)| is_requested(skill, name, dir))
+                })
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                return Err(SandboxError::InvalidRequest {
+                    message: format!("skills not found in source: {}", missing.join(", ")),
+                });
+            }
+        }
+        if selected.is_empty() {
+            return Err(SandboxError::InvalidRequest {
+                message: format!("no SKILL.md found in {}", source.source),
+            });
+        }
+
+        let mut installed = Vec::new();
+        for (name, frontmatter, dir) in selected {
+            let staged = staging.join("skills").join(&name);
+            copy_dir(&dir, &staged).map_err(|err| map_fs_error(&staged, err))?;
+            let dest = self.dir.join(&name);
+            let mut skill = InstalledSkill {
+                name: name.clone(),
+                description: frontmatter.description,
+                source: source.clone(),
+                version: frontmatter.version.or_else(|| source_version.clone()),
+                installed_at_ms: now_ms(),
+                path: dest.display().to_string(),
+                synced_to: Vec::new(),
+            };
+            let metadata =
+                serde_json::to_vec_pretty(&skill).map_err(|err| SandboxError::StreamError {
+                    message: err.to_string(),
+                })?;
+            let metadata_path = staged.join(METADATA_FILE);
+            fs::write(&metadata_path, metadata).map_err(|err| map_fs_error(&metadata_path, err))?;
+            if dest.exists() {
+                fs::remove_dir_all(&dest).map_err(|err| map_fs_error(&dest, err))?;
+            }
+            fs::rename(&staged, &dest).map_err(|err| map_fs_error(&dest, err))?;
+            skill.synced_to = sync(&name, &dest);
+            installed.push(skill);
+        }
+        Ok(SkillInstallResponse { installed, cached })
+    }
+
+    /// The cached tree of `source`, fetched first unless it is pinned or `reuse` is set.
+    async fn fetch_cached(
+        &self,
+        source: &SkillSource,
+    ) -> Result<(PathBuf, Option<String>, bool), SandboxError> {
+        let expected = expected_sha256(source)?;
+        let entry = self.cache_dir.join(cache_key(source, expected.as_deref()));
+        let tree = entry.join("tree");
+        if let Some(cached) = read_cache_entry(&entry) {
+            let pinned = match source.source_type.as_str() {
+                "archive" => expected.is_some(),
+                _ => source.git_ref.as_deref().is_some_and(|git_ref| {
+                    is_commit_hash(git_ref) && cached.version.as_deref() == Some(git_ref)
+                }),
+            };
+            if tree.is_dir() && (pinned || source.reuse == Some(true)) {
+                return Ok((tree, cached.version, true));
+            }
+        }
+
+        let staging = self.cache_dir.join(format!(
+            "{STAGING_DIR}-{}-{}",
+            std::process::id(),
+            STAGING_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = async {
+            let (_, version) = fetch(source, &staging.join("tree"), expected.as_deref()).await?;
+            let record = CacheEntry {
+                version: version.clone(),
+                fetched_at_ms: now_ms(),
+            };
+            let record =
+                serde_json::to_vec_pretty(&record).map_err(|err| SandboxError::StreamError {
+                    message: err.to_string(),
+                })?;
+            let record_path = staging.join(CACHE_ENTRY_FILE);
+            fs::write(&record_path, record).map_err(|err| map_fs_error(&record_path, err))?;
+            if entry.exists() {
+                fs::remove_dir_all(&entry).map_err(|err| map_fs_error(&entry, err))?;
+            }
+            fs::rename(&staging, &entry).map_err(|err| map_fs_error(&entry, err))?;
+            Ok(version)
+        }
+        .await;
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&staging);
+        }
+        Ok((tree, result?, false))
+    }
+
+    /// Remove an installed skill and the copies synced from it.
+    pub(crate) async fn remove(&self, name: &str) -> Result<(), SandboxError> {
+        validate_name(name)?;
+        let _guard = self.lock.lock().await;
+        let dir = self.dir.join(name);
+        if read_metadata(&dir).is_none() {
+            return Err(SandboxError::SessionNotFound {
+                session_id: format!("skill:{name}"),
+            });
+        }
+        for (agent, root) in sync_targets() {
+            let copy = root.join(name);
+            if copy.join(METADATA_FILE).is_file() {
+                if let Err(err) = fs::remove_dir_all(&copy) {
+                    tracing::warn!(agent, path = %copy.display(), error = %err, "skills: failed to remove synced copy");
+                }
+            }
+        }
+        fs::remove_dir_all(&dir).map_err(|err| map_fs_error(&dir, err))
+    }
+}
+
+/// Fetch `source` into `target`. Returns the directory to search and the source's version.
+/// An archive whose SHA-256 is not `expected_sha256` is rejected before it is unpacked.
+async fn fetch(
+    source: &SkillSource,
+    target: &Path,
+    expected_sha256: Option<&str>,
+) -> Result<(PathBuf, Option<String>), SandboxError> {
+    let git_ref = source.git_ref.as_deref();
+    if git_ref.is_some_and(|git_ref| git_ref.starts_with('-')) {
+        return Err(SandboxError::InvalidRequest {
+            message: "ref may not start with '-'".to_string(),
+        });
+    }
+    match source.source_type.as_str() {
+        "github" => {
+            let token = scm_github::token(None);
+            let commit =
+                scm_github::clone_repo(&source.source, git_ref, token.as_deref(), target).await?;
+            Ok((target.to_path_buf(), Some(commit)))
+        }
+        "git" => {
+            if source.source.starts_with('-') {
+                return Err(SandboxError::InvalidRequest {
+                    message: "source may not start with '-'".to_string(),
+                });
+            }
+            fs::create_dir_all(target).map_err(|err| map_fs_error(target, err))?;
+            scm_github::git(target, &["clone", "--quiet", &source.source, "."], None).await?;
+            if let Some(git_ref) = git_ref {
+                scm_github::git(target, &["checkout", "--quiet", git_ref], None).await?;
+            }
+            let commit = scm_github::git(target, &["rev-parse", "HEAD"], None).await?;
+            Ok((target.to_path_buf(), Some(commit)))
+        }
+        "archive" => {
+            let bytes =
+                if source.source.starts_with("http://") || source.source.starts_with("https://") {
+                    download(&source.source).await?
+                } else {
+                    let path = Path::new(&source.source);
+                    fs::read(path).map_err(|err| map_fs_error(path, err))?
+                };
+            let digest = format!("{:x}", Sha256::digest(&bytes));
+            if let Some(expected) = expected_sha256 {
+                if digest != expected {
+                    return Err(SandboxError::InvalidRequest {
+                        message: format!(
+                            "archive sha256 mismatch: expected {expected}, got {digest}"
+                        ),
+                    });
+                }
+            }
+            unpack(&bytes, target)?;
+            Ok((target.to_path_buf(), Some(format!("sha256:{digest}"))))
+        }
+        "local" => {
+            let path = PathBuf::from(&source.source);
+            if !path.is_dir() {
+                return Err(SandboxError::NotADirectory {
+                    path: path.display().to_string(),
+                });
+            }
+            Ok((path, None))
+        }
+        other => Err(SandboxError::InvalidRequest {
+            message: format!(
+                "unsupported skill source type '{other}'; expected github, git, archive or local"
+            ),
+        }),
+    }
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, SandboxError> {
+    let stream_error = |err: reqwest::Error| SandboxError::StreamError {
+        message: format!("failed to download {url}: {err}"),
+    };
+    let client = reqwest::Client::builder()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()
+        .map_err(stream_error)?;
+    let mut response = client.get(url).send().await.map_err(stream_error)?;
+    if !response.status().is_success() {
+        return Err(SandboxError::StreamError {
+            message: format!("GET {url} returned {}", response.status()),
+        });
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(stream_error)? {
+        if bytes.len() + chunk.len() > MAX_ARCHIVE_BYTES {
+            return Err(SandboxError::InvalidRequest {
+                message: format!("archive is larger than {MAX_ARCHIVE_BYTES} bytes"),
+            });
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Unpack a zip, gzipped tar or plain tar archive, told apart by their leading bytes.
+fn unpack(bytes: &[u8], target: &Path) -> Result<(), SandboxError> {
+    fs::create_dir_all(target).map_err(|err| map_fs_error(target, err))?;
+    let invalid = |err: &dyn std::fmt::Display| SandboxError::InvalidRequest {
+        message: format!("failed to unpack archive: {err}"),
+    };
+    if bytes.starts_with(b"PK\x03\x04") {
+        zip::ZipArchive::new(Cursor::new(bytes))
+            .and_then(|mut archive| archive.extract(target))
+            .map_err(|err| invalid(&err))
+    } else if bytes.starts_with(&[0x1f, 0x8b]) {
+        tar::Archive::new(GzDecoder::new(bytes))
+            .unpack(target)
+            .map_err(|err| invalid(&err))
+    } else {
+        tar::Archive::new(bytes)
+            .unpack(target)
+            .map_err(|err| invalid(&err))
+    }
+}
+
+/// Copy `name` from the store into each agent's skill directory. Returns the agents it was
+/// copied for.
+fn sync(name: &str, skill_dir: &Path) -> Vec<String> {
+    let mut synced = Vec::new();
+    for (agent, root) in sync_targets() {
+        let copy = root.join(name);
+        if copy.exists() && !copy.join(METADATA_FILE).is_file() {
+            tracing::warn!(agent, path = %copy.display(), "skills: not replacing a skill that was not installed by sandbox-agent");
+            continue;
+        }
+        let result = if copy.exists() {
+            fs::remove_dir_all(&copy)
+        } else {
+            Ok(())
+        }
+        .and_then(|()| copy_dir(skill_dir, &copy));
+        match result {
+            Ok(()) => synced.push(agent.to_string()),
+            Err(err) => {
+                tracing::warn!(agent, path = %copy.display(), error = %err, "skills: failed to sync skill")
+            }
+        }
+    }
+    synced
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    version: Option<String>,
+    fetched_at_ms: i64,
+}
+
+fn read_cache_entry(entry: &Path) -> Option<CacheEntry> {
+    let raw = fs::read(entry.join(CACHE_ENTRY_FILE)).ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+/// Cache directory name for a source: the SHA-256 of its type, location and ref, or of the
+/// archive digest for archives pinned with `sha256`.
+fn cache_key(source: &SkillSource, expected_sha256: Option<&str>) -> String {
+    let identity = match expected_sha256 {
+        Some(digest) => format!("archive\0sha256:{digest}"),
+        None => format!(
+            "{}\0{}\0{}",
+            source.source_type,
+            source.source,
+            source.git_ref.as_deref().unwrap_or_default()
+        ),
+    };
+    format!("{:x}", Sha256::digest(identity))
+}
+
+/// `sha256` of an archive source as lowercase hex.
+fn expected_sha256(source: &SkillSource) -> Result<Option<String>, SandboxError> {
+    let Some(raw) = source.sha256.as_deref() else {
+        return Ok(None);
+    };
+    if source.source_type != "archive" {
+        return Err(SandboxError::InvalidRequest {
+            message: "sha256 is only supported for archive sources".to_string(),
+        });
+    }
+    let digest = raw
+        .trim()
+        .trim_start_matches("sha256:")
+        .to_ascii_lowercase();
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(SandboxError::InvalidRequest {
+            message: format!("sha256 must be 64 hex digits, got '{raw}'"),
+        });
+    }
+    Ok(Some(digest))
+}
+
+fn is_commit_hash(git_ref: &str) -> bool {
+    git_ref.len() == 40 && git_ref.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn read_metadata(dir: &Path) -> Option<InstalledSkill> {
+    let raw = fs::read(dir.join(METADATA_FILE)).ok()?;
+    let mut skill: InstalledSkill = serde_json::from_slice(&raw).ok()?;
+    skill.path = dir.display().to_string();
+    skill.synced_to = sync_targets()
+        .into_iter()
+        .filter(|(_, root)| root.join(&skill.name).join(METADATA_FILE).is_file())
+        .map(|(agent, _)| agent.to_string())
+        .collect();
+    Some(skill)
+}
+
+/// Collect directories holding a `SKILL.md`, without descending into them, hidden
+/// directories, `node_modules` or symlinks.
+fn find_skills(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    if dir.join("SKILL.md").is_file() {
+        found.push(dir.to_path_buf());
+        return;
+    }
+    if depth >= MAX_SEARCH_DEPTH {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut children: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            !name.starts_with('.') && name != "node_modules"
+        })
+        .map(|entry| entry.path())
+        .collect();
+    children.sort();
+    for child in children {
+        find_skills(&child, depth + 1, found);
+    }
+}
+
+/// Copy a directory tree, skipping `.git` and symlinks.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let kind = entry.file_type()?;
+        let dest = to.join(entry.file_name());
+        if kind.is_dir() {
+            if entry.file_name() != ".git" {
+                copy_dir(&entry.path(), &dest)?;
+            }
+        } else if kind.is_file() {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Frontmatter {
+    name: Option<String>,
+    description: Option<String>,
+    version: Option<String>,
+}
+
+/// Top-level `name`, `description` and `version` from the YAML frontmatter of a `SKILL.md`.
+fn read_frontmatter(path: &Path) -> Frontmatter {
+    let mut frontmatter = Frontmatter::default();
+    let Ok(text) = fs::read_to_string(path) else {
+        return frontmatter;
+    };
+    let mut lines = text.trim_start_matches('\u{feff}').lines();
+    if lines.next().map(str::trim_end) != Some("---") {
+        return frontmatter;
+    }
+    for line in lines {
+        if line.trim_end() == "---" {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().trim_matches(['"', '\'']).trim();
+        if value.is_empty() {
+            continue;
+        }
+        let field = match key.trim() {
+            "name" => &mut frontmatter.name,
+            "description" => &mut frontmatter.description,
+            "version" => &mut frontmatter.version,
+            _ => continue,
+        };
+        *field = Some(value.to_string());
+    }
+    frontmatter
+}
+
+/// `skills` entries match a skill's name or its directory name.
+fn is_requested(requested: &str, name: &str, dir: &Path) -> bool {
+    requested == name
+        || dir
+            .file_name()
+            .is_some_and(|dir_name| dir_name == requested)
+}
+
+/// Name for a skill at the root of its source: the last path segment without `.git` or an
+/// archive extension.
+fn source_name(source: &str) -> String {
+    let last = source
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or(source);
+    [".git", ".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find_map(|suffix| last.strip_suffix(suffix))
+        .unwrap_or(last)
+        .to_string()
+}
+
+fn relative_subpath(subpath: &str) -> Result<PathBuf, SandboxError> {
+    let path = Path::new(subpath);
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path.to_path_buf())
+    } else {
+        Err(SandboxError::InvalidRequest {
+            message: format!("subpath must stay inside the source, got '{subpath}'"),
+        })
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), SandboxError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(SandboxError::InvalidRequest {
+            message: format!("invalid skill name '{name}'"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frontmatter_and_names() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("SKILL.md");
+        fs::write(
+            &path,
+            "---\nname: review\ndescription: \"Review a diff\"\nmetadata:\n  version: 9\nversion: 1.2.0\n---\n# Review\nname: ignored\n",
+        )
+        .expect("write");
+        let frontmatter = read_frontmatter(&path);
+        assert_eq!(frontmatter.name.as_deref(), Some("review"));
+        assert_eq!(frontmatter.description.as_deref(), Some("Review a diff"));
+        assert_eq!(frontmatter.version.as_deref(), Some("1.2.0"));
+
+        assert_eq!(source_name("https://example.com/skills/lint.git"), "lint");
+        assert_eq!(source_name("/tmp/review.tar.gz"), "review");
+        assert_eq!(source_name("git@example.com:team/docs"), "docs");
+        assert!(validate_name("pdf-tools_2").is_ok());
+        assert!(validate_name("../escape").is_err());
+        assert!(validate_name(".hidden").is_err());
+        assert!(relative_subpath("skills/./pdf").is_ok());
+        assert!(relative_subpath("../pdf").is_err());
+        assert!(relative_subpath("/etc").is_err());
+    }
+
+    #[test]
+    fn archive_digests_and_cache_keys() {
+        let archive = |url: &str, sha256: Option<&str>| SkillSource {
+            source_type: "archive".to_string(),
+            source: url.to_string(),
+            skills: None,
+            git_ref: None,
+            subpath: None,
+            sha256: sha256.map(str::to_string),
+            reuse: None,
+        };
+        let digest = "AB".repeat(32);
+        let expected = expected_sha256(&archive("a", Some(&format!("sha256:{digest}"))))
+            .expect("valid digest");
+        assert_eq!(expected, Some("ab".repeat(32)));
+        assert!(expected_sha256(&archive("a", Some("abc"))).is_err());
+
+        // Pinned archives share an entry across URLs; unpinned ones are keyed by URL.
+        assert_eq!(
+            cache_key(&archive("a", None), expected.as_deref()),
+            cache_key(&archive("b", None), expected.as_deref())
+        );
+        assert_ne!(
+            cache_key(&archive("a", None), None),
+            cache_key(&archive("b", None), None)
+        );
+        assert!(is_commit_hash(&"0f".repeat(20)));
+        assert!(!is_commit_hash("main"));
+    }
+}
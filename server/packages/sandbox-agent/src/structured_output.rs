@@ -0,0 +1,228 @@
+//! Schema-constrained turns: `outputSchema` under `_meta["sandboxagent.dev"]` on
+//! `session/prompt`.
+//!
+//! No ACP adapter takes an output schema per prompt, so the daemon appends the schema to the
+//! prompt as an instruction and reads the answer back from the turn's `agent_message_chunk`
+//! text. The parsed value, or why there is none, is returned in the prompt result's
+//! `_meta["sandboxagent.dev"].structuredOutput` and emitted on the event stream as
+//! `_sandboxagent/structured_output`.
+//!
+//! The answer is checked against the schema's `type`, `enum`, `const`, `properties`,
+//! `required`, `additionalProperties: false` and `items`; other keywords are not enforced.
+
+use sandbox_agent_api_types::AcpStructuredOutput;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+
+/// Take the `outputSchema` off a `session/prompt` and add it to the prompt as an instruction.
+pub(crate) fn take_output_schema(payload: &mut Value) -> Result<Option<Value>, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/prompt") {
+        return Ok(None);
+    }
+    let schema = match payload
+        .pointer_mut("/params/_meta/sandboxagent.dev")
+        .and_then(Value::as_object_mut)
+        .and_then(|ours| ours.remove("outputSchema"))
+    {
+        None | Some(Value::Null) => return Ok(None),
+        Some(schema @ Value::Object(_)) => schema,
+        Some(_) => {
+            return Err(SandboxError::InvalidRequest {
+                message: "outputSchema must be a JSON Schema object".to_string(),
+            })
+        }
+    };
+    let prompt = payload
+        .pointer_mut("/params/prompt")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| SandboxError::InvalidRequest {
+            message: "session/prompt requires a params.prompt array".to_string(),
+        })?;
+    prompt.push(json!({
+        "type": "text",
+        "text": format!(
+            "Reply with only a JSON value that matches this JSON Schema, without code fences \
+             or any other text:\n{schema}"
+        ),
+    }));
+    Ok(Some(schema))
+}
+
+/// The value the agent's `reply` holds, checked against `schema`.
+pub(crate) fn parse_output(schema: &Value, reply: &str) -> AcpStructuredOutput {
+    let Some(value) = extract_json(reply) else {
+        return AcpStructuredOutput {
+            output: None,
+            error: Some("the reply holds no JSON value".to_string()),
+        };
+    };
+    match validate(schema, &value, "$") {
+        Ok(()) => AcpStructuredOutput {
+            output: Some(value),
+            error: None,
+        },
+        Err(error) => AcpStructuredOutput {
+            output: None,
+            error: Some(error),
+        },
+    }
+}
+
+/// The JSON in `reply`: all of it, a fenced block, or the first object or array in the text.
+fn extract_json(reply: &str) -> Option<Value> {
+    let reply = reply.trim();
+    if let Ok(value) = serde_json::from_str(reply) {
+        return Some(value);
+    }
+    if let Some((_, fenced)) = reply.split_once("```") {
+        let fenced = fenced.trim_start_matches("json");
+        if let Some((block, _)) = fenced.split_once("```") {
+            if let Ok(value) = serde_json::from_str(block.trim()) {
+                return Some(value);
+            }
+        }
+    }
+    reply.match_indices(['{', '[']).find_map(|(start, _)| {
+        serde_json::Deserializer::from_str(&reply[start..])
+            .into_iter::<Value>()
+            .next()?
+            .ok()
+    })
+}
+
+fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+    if let Some(expected) = schema.get("type") {
+        let types = match expected {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            other => other.as_str().into_iter().collect::<Vec<_>>(),
+        };
+        if !types.is_empty() && !types.iter().any(|kind| has_type(value, kind)) {
+            return Err(format!("{path} must be of type {}", types.join(" or ")));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!(
+                "{path} must be one of {}",
+                Value::from(allowed.clone())
+            ));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            return Err(format!("{path} must be {constant}"));
+        }
+    }
+    if let Some(object) = value.as_object() {
+        for key in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !object.contains_key(key) {
+                return Err(format!("{path}.{key} is required"));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, item) in object {
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => validate(property, item, &format!("{path}.{key}"))?,
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    return Err(format!("{path}.{key} is not allowed"));
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (index, item) in array.iter().enumerate() {
+            validate(items, item, &format!("{path}[{index}]"))?;
+        }
+    }
+    Ok(())
+}
+
+fn has_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["verdict", "issues"],
+            "additionalProperties": false,
+            "properties": {
+                "verdict": { "enum": ["pass", "fail"] },
+                "issues": { "type": "array", "items": { "type": "string" } },
+            },
+        })
+    }
+
+    #[test]
+    fn fenced_reply_is_parsed_and_checked() {
+        let parsed = parse_output(
+            &schema(),
+            "Here you go:\n```json\n{\"verdict\": \"fail\", \"issues\": [\"flaky test\"]}\n```",
+        );
+        assert_eq!(
+            parsed.output,
+            Some(json!({ "verdict": "fail", "issues": ["flaky test"] }))
+        );
+
+        let parsed = parse_output(&schema(), "{\"verdict\": \"maybe\", \"issues\": []}");
+        assert_eq!(parsed.output, None);
+        assert_eq!(
+            parsed.error.as_deref(),
+            Some("$.verdict must be one of [\"pass\",\"fail\"]")
+        );
+        assert!(
+            parse_output(&schema(), "{\"verdict\": \"pass\", \"issues\": [1]}")
+                .error
+                .is_some()
+        );
+        assert!(parse_output(&schema(), "no json here").error.is_some());
+        assert_eq!(
+            parse_output(
+                &schema(),
+                "{\"verdict\": \"pass\", \"issues\": []} and {more}"
+            )
+            .output,
+            Some(json!({ "verdict": "pass", "issues": [] }))
+        );
+    }
+
+    #[test]
+    fn schema_is_moved_into_the_prompt() {
+        let mut payload = json!({
+            "method": "session/prompt",
+            "params": {
+                "sessionId": "s-1",
+                "prompt": [{ "type": "text", "text": "review" }],
+                "_meta": { "sandboxagent.dev": { "outputSchema": schema() } },
+            },
+        });
+        assert_eq!(take_output_schema(&mut payload).unwrap(), Some(schema()));
+        assert_eq!(payload["params"]["prompt"].as_array().unwrap().len(), 2);
+        assert!(payload["params"]["_meta"]["sandboxagent.dev"]
+            .get("outputSchema")
+            .is_none());
+    }
+}
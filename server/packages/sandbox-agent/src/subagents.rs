@@ -0,0 +1,248 @@
+//! Sub-agent runs nested in the tool calls of a session.
+//!
+//! Claude's Task tool and OpenCode's task tool start a sub-agent as one tool call whose
+//! `rawInput.subagent_type` names the agent type. The tool calls the sub-agent makes reach the
+//! client as ordinary tool calls of the parent session. The daemon links each to the sub-agent
+//! that made it:
+//! - through the parent tool call id the adapter puts in `_meta.claudeCode.parentToolUseId`
+//!   or `_meta["sandboxagent.dev"].parentToolCallId`;
+//! - otherwise to the one sub-agent of the session that is still running. Tool calls made
+//!   while several sub-agents run in parallel stay unlinked, and so do sub-agents started
+//!   while another runs, which are taken for parallel ones.
+//!
+//! Each link is emitted as `_sandboxagent/subagent/child`, and the children of a tool call are
+//! served from `GET /v1/acp/{server_id}/sessions/{session_id}/items/{item_id}/children`.
+
+use std::collections::HashMap;
+
+use sandbox_agent_api_types::AcpChildItem;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone)]
+struct ToolItem {
+    item: AcpChildItem,
+    children: Vec<String>,
+}
+
+impl ToolItem {
+    fn running_subagent(&self) -> bool {
+        self.item.agent_type.is_some()
+            && !matches!(self.item.status.as_deref(), Some("completed" | "failed"))
+    }
+}
+
+/// The tool calls of every session on one ACP server, keyed by session and tool call id.
+#[derive(Debug, Default)]
+pub(crate) struct SubAgents {
+    sessions: HashMap<String, HashMap<String, ToolItem>>,
+}
+
+impl SubAgents {
+    /// Follow an agent message; returns `_sandboxagent/subagent/child` when it starts a tool
+    /// call made by a sub-agent.
+    pub(crate) fn record(&mut self, sequence: u64, message: &Value) -> Option<Value> {
+        if message.get("method").and_then(Value::as_str) != Some("session/update") {
+            return None;
+        }
+        let session_id = message.pointer("/params/sessionId")?.as_str()?;
+        let update = message.pointer("/params/update")?;
+        if !matches!(
+            update.get("sessionUpdate").and_then(Value::as_str),
+            Some("tool_call" | "tool_call_update")
+        ) {
+            return None;
+        }
+        let tool_call_id = update.get("toolCallId")?.as_str()?;
+        let items = self.sessions.entry(session_id.to_string()).or_default();
+        let started = !items.contains_key(tool_call_id);
+        let entry = items
+            .entry(tool_call_id.to_string())
+            .or_insert_with(|| ToolItem {
+                item: AcpChildItem {
+                    tool_call_id: tool_call_id.to_string(),
+                    title: None,
+                    kind: None,
+                    status: None,
+                    agent_type: None,
+                    sequence,
+                },
+                children: Vec::new(),
+            });
+        let string = |key: &str| update.get(key).and_then(Value::as_str).map(str::to_string);
+        if let Some(title) = string("title") {
+            entry.item.title = Some(title);
+        }
+        if let Some(kind) = string("kind") {
+            entry.item.kind = Some(kind);
+        }
+        if let Some(status) = string("status") {
+            entry.item.status = Some(status);
+        }
+        if let Some(agent_type) = update
+            .pointer("/rawInput/subagent_type")
+            .and_then(Value::as_str)
+        {
+            entry.item.agent_type = Some(agent_type.to_string());
+        }
+        let subagent = entry.item.agent_type.is_some();
+        if !started {
+            return None;
+        }
+
+        let parent = parent_id(update)
+            .filter(|parent| parent != tool_call_id && items.contains_key(parent))
+            .or_else(|| {
+                if subagent {
+                    return None;
+                }
+                let mut running = items
+                    .iter()
+                    .filter(|(id, item)| *id != tool_call_id && item.running_subagent());
+                match (running.next(), running.next()) {
+                    (Some((id, _)), None) => Some(id.clone()),
+                    _ => None,
+                }
+            })?;
+        items
+            .get_mut(&parent)?
+            .children
+            .push(tool_call_id.to_string());
+        Some(json!({
+            "jsonrpc": "2.0",
+            "method": "_sandboxagent/subagent/child",
+            "params": {
+                "sessionId": session_id,
+                "parentToolCallId": parent,
+                "toolCallId": tool_call_id,
+            },
+        }))
+    }
+
+    /// The sub-agent tool call each linked tool call of `session_id` belongs to.
+    pub(crate) fn parents(&self, session_id: &str) -> HashMap<String, String> {
+        let Some(items) = self.sessions.get(session_id) else {
+            return HashMap::new();
+        };
+        items
+            .iter()
+            .flat_map(|(parent, item)| {
+                item.children
+                    .iter()
+                    .map(move |child| (child.clone(), parent.clone()))
+            })
+            .collect()
+    }
+
+    /// The tool call `item_id` of `session_id` and the tool calls linked to it, oldest first.
+    pub(crate) fn children(
+        &self,
+        session_id: &str,
+        item_id: &str,
+    ) -> Option<(AcpChildItem, Vec<AcpChildItem>)> {
+        let items = self.sessions.get(session_id)?;
+        let parent = items.get(item_id)?;
+        let children = parent
+            .children
+            .iter()
+            .filter_map(|child| items.get(child))
+            .map(|child| child.item.clone())
+            .collect();
+        Some((parent.item.clone(), children))
+    }
+}
+
+fn parent_id(update: &Value) -> Option<String> {
+    update
+        .pointer("/_meta/claudeCode/parentToolUseId")
+        .or_else(|| update.pointer("/_meta/sandboxagent.dev/parentToolCallId"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_call(update: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": "s-1", "update": update },
+        })
+    }
+
+    #[test]
+    fn tool_calls_link_to_the_running_subagent() {
+        let mut subagents = SubAgents::default();
+        assert!(subagents
+            .record(
+                1,
+                &tool_call(json!({
+                    "sessionUpdate": "tool_call",
+                    "toolCallId": "task-1",
+                    "title": "Explore the repo",
+                    "status": "in_progress",
+                    "rawInput": { "subagent_type": "explore", "prompt": "..." },
+                })),
+            )
+            .is_none());
+        let linked = subagents
+            .record(
+                2,
+                &tool_call(json!({ "sessionUpdate": "tool_call", "toolCallId": "read-1", "title": "Read" })),
+            )
+            .expect("linked");
+        assert_eq!(linked["params"]["parentToolCallId"], "task-1");
+
+        subagents.record(
+            3,
+            &tool_call(json!({ "sessionUpdate": "tool_call_update", "toolCallId": "task-1", "status": "completed" })),
+        );
+        assert!(subagents
+            .record(
+                4,
+                &tool_call(json!({ "sessionUpdate": "tool_call", "toolCallId": "edit-1" })),
+            )
+            .is_none());
+
+        let (item, children) = subagents.children("s-1", "task-1").expect("item");
+        assert_eq!(item.agent_type.as_deref(), Some("explore"));
+        assert_eq!(item.status.as_deref(), Some("completed"));
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].tool_call_id, "read-1");
+        assert_eq!(children[0].sequence, 2);
+    }
+
+    #[test]
+    fn explicit_parent_wins_over_parallel_subagents() {
+        let mut subagents = SubAgents::default();
+        for (sequence, id) in [(1, "task-1"), (2, "task-2")] {
+            subagents.record(
+                sequence,
+                &tool_call(json!({
+                    "sessionUpdate": "tool_call",
+                    "toolCallId": id,
+                    "rawInput": { "subagent_type": "general" },
+                })),
+            );
+        }
+        assert!(subagents
+            .record(
+                3,
+                &tool_call(json!({ "sessionUpdate": "tool_call", "toolCallId": "read-1" })),
+            )
+            .is_none());
+        let linked = subagents
+            .record(
+                4,
+                &tool_call(json!({
+                    "sessionUpdate": "tool_call",
+                    "toolCallId": "read-2",
+                    "_meta": { "claudeCode": { "parentToolUseId": "task-2" } },
+                })),
+            )
+            .expect("linked");
+        assert_eq!(linked["params"]["parentToolCallId"], "task-2");
+        assert!(subagents.children("s-1", "missing").is_none());
+    }
+}
@@ -0,0 +1,149 @@
+//! The latest todo list of each session on an ACP server.
+//!
+//! Agents publish their todo lists in two shapes:
+//! - ACP `plan` updates, whose `entries` carry `content` and `status` (Claude's TodoWrite and
+//!   OpenCode's todowrite through their ACP adapters).
+//! - `tool_call` and `tool_call_update`s whose `rawInput.todos` holds the tool's own list, with
+//!   `content`, `status` and, for OpenCode, an `id`.
+//!
+//! Both are normalized to tasks with an `id`, `text` and `status`. Every change to a session's
+//! list is emitted as `_sandboxagent/tasks/updated` with the whole list, and the latest list is
+//! served from `GET /v1/acp/{server_id}/sessions/{session_id}/tasks`.
+
+use std::collections::HashMap;
+
+use sandbox_agent_api_types::{AcpTask, AcpTaskStatus};
+use serde_json::{json, Value};
+
+/// A session's latest list and the sequence of the event that set it.
+#[derive(Debug, Clone)]
+pub(crate) struct TaskList {
+    pub(crate) tasks: Vec<AcpTask>,
+    pub(crate) sequence: u64,
+}
+
+/// The todo list of every session on one ACP server.
+#[derive(Debug, Default)]
+pub(crate) struct TaskLists {
+    sessions: HashMap<String, TaskList>,
+}
+
+impl TaskLists {
+    /// Follow an agent message; returns `_sandboxagent/tasks/updated` when it changed a list.
+    pub(crate) fn record(&mut self, sequence: u64, message: &Value) -> Option<Value> {
+        if message.get("method").and_then(Value::as_str) != Some("session/update") {
+            return None;
+        }
+        let session_id = message.pointer("/params/sessionId")?.as_str()?;
+        let update = message.pointer("/params/update")?;
+        let items = match update.get("sessionUpdate").and_then(Value::as_str)? {
+            "plan" => update.get("entries")?,
+            "tool_call" | "tool_call_update" => update.pointer("/rawInput/todos")?,
+            _ => return None,
+        };
+        let tasks = items
+            .as_array()?
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| task(index, item))
+            .collect::<Vec<_>>();
+        if self
+            .sessions
+            .get(session_id)
+            .is_some_and(|list| list.tasks == tasks)
+        {
+            return None;
+        }
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "_sandboxagent/tasks/updated",
+            "params": { "sessionId": session_id, "tasks": tasks },
+        });
+        self.sessions
+            .insert(session_id.to_string(), TaskList { tasks, sequence });
+        Some(notification)
+    }
+
+    pub(crate) fn get(&self, session_id: &str) -> Option<TaskList> {
+        self.sessions.get(session_id).cloned()
+    }
+}
+
+fn task(index: usize, item: &Value) -> Option<AcpTask> {
+    let text = item.get("content").and_then(Value::as_str)?;
+    let id = match item.get("id") {
+        Some(Value::String(id)) => id.clone(),
+        Some(Value::Number(id)) => id.to_string(),
+        _ => (index + 1).to_string(),
+    };
+    let status = match item.get("status").and_then(Value::as_str) {
+        Some("in_progress") => AcpTaskStatus::InProgress,
+        Some("completed") => AcpTaskStatus::Completed,
+        Some("cancelled") => AcpTaskStatus::Cancelled,
+        _ => AcpTaskStatus::Pending,
+    };
+    Some(AcpTask {
+        id,
+        text: text.to_string(),
+        status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(update: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": "s-1", "update": update },
+        })
+    }
+
+    #[test]
+    fn plan_entries_and_todo_tools_are_normalized() {
+        let mut lists = TaskLists::default();
+        let plan = update(json!({
+            "sessionUpdate": "plan",
+            "entries": [
+                { "content": "Read the code", "priority": "high", "status": "completed" },
+                { "content": "Fix the bug", "priority": "high", "status": "in_progress" },
+            ],
+        }));
+        let event = lists.record(3, &plan).expect("tasks updated");
+        assert_eq!(event["method"], "_sandboxagent/tasks/updated");
+        assert_eq!(
+            event["params"]["tasks"][1],
+            json!({ "id": "2", "text": "Fix the bug", "status": "in_progress" })
+        );
+        assert!(lists.record(4, &plan).is_none());
+        assert_eq!(lists.get("s-1").unwrap().sequence, 3);
+
+        let todo = update(json!({
+            "sessionUpdate": "tool_call",
+            "toolCallId": "t1",
+            "title": "todowrite",
+            "rawInput": { "todos": [
+                { "id": "a", "content": "Ship it", "status": "cancelled", "priority": "low" },
+            ] },
+        }));
+        lists.record(5, &todo).expect("tasks updated");
+        let list = lists.get("s-1").unwrap();
+        assert_eq!(list.sequence, 5);
+        assert_eq!(
+            list.tasks,
+            [AcpTask {
+                id: "a".to_string(),
+                text: "Ship it".to_string(),
+                status: AcpTaskStatus::Cancelled,
+            }]
+        );
+        assert!(lists
+            .record(
+                6,
+                &update(json!({ "sessionUpdate": "tool_call", "toolCallId": "t2" }))
+            )
+            .is_none());
+    }
+}
@@ -29,6 +29,7 @@ use tokio::sync::{mpsc, Mutex};
 use tracing::{error, info, warn};
 use utoipa::ToSchema;
 
+use crate::auth_tokens::TokenScope;
 use crate::router::{ApiError, AppState, AuthConfig};
 
 // ---------------------------------------------------------------------------
@@ -664,21 +665,14 @@ pub async fn terminal_ws(
     // Validate auth — check query param token or Authorization header.
     // The normal require_token middleware doesn't run on WS upgrade for
     // browser clients that can't set headers, so we check here.
-    if let Some(ref expected) = state.auth().token {
+    if state.auth().is_enabled() {
         let provided = query.token.as_deref().or_else(|| {
             headers
                 .get("authorization")
                 .and_then(|v| v.to_str().ok())
                 .and_then(|v| v.strip_prefix("Bearer "))
         });
-        match provided {
-            Some(t) if t == expected => {}
-            _ => {
-                return Err(ApiError::Sandbox(SandboxError::TokenInvalid {
-                    message: Some("invalid or missing token".to_string()),
-                }));
-            }
-        }
+        state.auth().authorize(provided, TokenScope::Admin)?;
     }
 
     // Verify terminal exists
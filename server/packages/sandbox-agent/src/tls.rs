@@ -0,0 +1,157 @@
+//! HTTPS listener for `sandbox-agent server --tls-cert/--tls-key`.
+//!
+//! Connections are terminated with rustls and served over HTTP/1.1 with upgrades, so
+//! WebSocket routes keep working. With `--tls-client-ca`, clients must present a certificate
+//! signed by one of the CAs in that file (mTLS).
+
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const TLS_SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+const TLS_ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub(crate) struct TlsOptions {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Build the rustls config from PEM files, failing on unreadable or empty files.
+pub(crate) fn load_server_config(options: &TlsOptions) -> Result<Arc<ServerConfig>, String> {
+    let certs = read_certs(&options.cert)?;
+    let key = read_private_key(&options.key)?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &options.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots.add(&cert).map_err(|err| {
+                    format!("invalid CA certificate in {}: {err}", path.display())
+                })?;
+            }
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .map_err(|err| format!("invalid certificate or key: {err}"))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Accept TLS connections on `listener` until `shutdown` resolves, then give open
+/// connections a moment to finish.
+pub(crate) async fn serve(
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+    router: Router,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let acceptor = TlsAcceptor::from(config);
+    // Every connection holds a receiver; shutdown is signalled through it and the sender
+    // closes once the last connection is done.
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    tracing::warn!(error = %err, "tls: failed to accept connection");
+                    tokio::time::sleep(TLS_ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(router.clone());
+        let mut shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(err)) => {
+                        tracing::debug!(peer = %peer, error = %err, "tls: handshake failed");
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!(peer = %peer, "tls: handshake timed out");
+                        return;
+                    }
+                };
+            let connection = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades();
+            tokio::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = shutdown_rx.changed() => {
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(err) = result {
+                tracing::debug!(peer = %peer, error = %err, "tls: connection error");
+            }
+        });
+    }
+
+    drop(listener);
+    drop(shutdown_rx);
+    let _ = shutdown_tx.send(true);
+    let _ = tokio::time::timeout(TLS_SHUTDOWN_GRACE, shutdown_tx.closed()).await;
+    Ok(())
+}
+
+fn read_certs(path: &Path) -> Result<Vec<Certificate>, String> {
+    let mut reader = open_pem(path)?;
+    let certs = rustls_pemfile::certs(&mut reader)
+        .map_err(|err| format!("failed to parse {}: {err}", path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {}", path.display()));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKey, String> {
+    let mut reader = open_pem(path)?;
+    loop {
+        match rustls_pemfile::read_one(&mut reader)
+            .map_err(|err| format!("failed to parse {}: {err}", path.display()))?
+        {
+            Some(
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key),
+            ) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => return Err(format!("no private key found in {}", path.display())),
+        }
+    }
+}
+
+fn open_pem(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| format!("failed to read {}: {err}", path.display()))
+}
@@ -0,0 +1,336 @@
+//! Session transcripts rendered from session items for `GET .../sessions/{session_id}/export`.
+//!
+//! Messages are rendered as they came. Reasoning, tool call input and tool call output are
+//! collapsed in `<details>` blocks, which GitHub renders in Markdown too. Diffs a tool call
+//! reports as ACP `diff` content stay visible as unified diffs.
+
+use std::fmt::Write;
+
+use sandbox_agent_api_types::{AcpItem, AcpItemKind, AcpItemStatus};
+use serde_json::Value;
+use similar::TextDiff;
+
+/// What a tool call item shows besides its title.
+struct ToolParts {
+    input: Option<String>,
+    output: Vec<String>,
+    /// File path and unified diff.
+    diffs: Vec<(String, String)>,
+}
+
+pub(crate) fn markdown(session_id: &str, items: &[AcpItem]) -> String {
+    let mut out = format!("# Session `{session_id}`\n");
+    for item in items {
+        out.push('\n');
+        match item.kind {
+            AcpItemKind::UserMessage | AcpItemKind::AgentMessage => {
+                let _ = writeln!(out, "**{}**\n", speaker(item.kind));
+                out.push_str(&message_markdown(item));
+            }
+            AcpItemKind::Reasoning => {
+                out.push_str("<details>\n<summary>Reasoning</summary>\n\n");
+                out.push_str(&message_markdown(item));
+                out.push_str("\n</details>\n");
+            }
+            AcpItemKind::ToolCall => {
+                let parts = tool_parts(item);
+                let _ = writeln!(out, "**Tool call:** {}", tool_summary(item));
+                if parts.input.is_some() || !parts.output.is_empty() {
+                    out.push_str("\n<details>\n<summary>Input and output</summary>\n\n");
+                    if let Some(input) = &parts.input {
+                        out.push_str(&fenced(input, "json"));
+                        out.push('\n');
+                    }
+                    for output in &parts.output {
+                        out.push_str(&fenced(output, ""));
+                        out.push('\n');
+                    }
+                    out.push_str("</details>\n");
+                }
+                for (path, diff) in &parts.diffs {
+                    let _ = writeln!(out, "\n`{path}`\n");
+                    out.push_str(&fenced(diff, "diff"));
+                }
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn html(session_id: &str, items: &[AcpItem]) -> String {
+    let title = escape(&format!("Session {session_id}"));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>\nbody {{ font-family: sans-serif; max-width: 60rem; margin: 2rem auto; }}\n\
+         .item {{ margin: 1rem 0; }}\n\
+         pre {{ background: #f6f8fa; padding: 0.5rem; overflow-x: auto; white-space: pre-wrap; }}\n\
+         .diff .add {{ color: #116329; }}\n.diff .del {{ color: #82071e; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    for item in items {
+        let _ = writeln!(
+            out,
+            "<section class=\"item {}\" id=\"{}\">",
+            kind_class(item.kind),
+            escape(&item.item_id)
+        );
+        match item.kind {
+            AcpItemKind::UserMessage | AcpItemKind::AgentMessage => {
+                let _ = writeln!(out, "<h3>{}</h3>", speaker(item.kind));
+                out.push_str(&message_html(item));
+            }
+            AcpItemKind::Reasoning => {
+                out.push_str("<details>\n<summary>Reasoning</summary>\n");
+                out.push_str(&message_html(item));
+                out.push_str("</details>\n");
+            }
+            AcpItemKind::ToolCall => {
+                let parts = tool_parts(item);
+                let _ = writeln!(out, "<h4>Tool call: {}</h4>", escape(&tool_summary(item)));
+                if parts.input.is_some() || !parts.output.is_empty() {
+                    out.push_str("<details>\n<summary>Input and output</summary>\n");
+                    for block in parts.input.iter().chain(&parts.output) {
+                        let _ = writeln!(out, "<pre>{}</pre>", escape(block));
+                    }
+                    out.push_str("</details>\n");
+                }
+                for (path, diff) in &parts.diffs {
+                    let _ = writeln!(out, "<p><code>{}</code></p>", escape(path));
+                    out.push_str("<pre class=\"diff\">");
+                    for line in diff.lines() {
+                        let class = match line.as_bytes().first() {
+                            Some(b'+') if !line.starts_with("+++") => " class=\"add\"",
+                            Some(b'-') if !line.starts_with("---") => " class=\"del\"",
+                            _ => "",
+                        };
+                        let _ = writeln!(out, "<span{class}>{}</span>", escape(line));
+                    }
+                    out.push_str("</pre>\n");
+                }
+            }
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn speaker(kind: AcpItemKind) -> &'static str {
+    match kind {
+        AcpItemKind::UserMessage => "User",
+        _ => "Agent",
+    }
+}
+
+fn kind_class(kind: AcpItemKind) -> &'static str {
+    match kind {
+        AcpItemKind::UserMessage => "user_message",
+        AcpItemKind::AgentMessage => "agent_message",
+        AcpItemKind::Reasoning => "reasoning",
+        AcpItemKind::ToolCall => "tool_call",
+    }
+}
+
+fn tool_summary(item: &AcpItem) -> String {
+    let title = item.title.as_deref().unwrap_or(&item.item_id);
+    let status = match item.status {
+        AcpItemStatus::InProgress => "in progress",
+        AcpItemStatus::Completed => "completed",
+        AcpItemStatus::Failed => "failed",
+    };
+    match &item.tool_kind {
+        Some(kind) => format!("{title} ({kind}, {status})"),
+        None => format!("{title} ({status})"),
+    }
+}
+
+fn message_markdown(item: &AcpItem) -> String {
+    let mut out = String::new();
+    if let Some(text) = &item.text {
+        out.push_str(text.trim_end());
+        out.push('\n');
+    }
+    for block in &item.content {
+        out.push('\n');
+        out.push_str(&match block_link(block) {
+            Some((name, Some(uri))) => format!("[{name}]({uri})\n"),
+            Some((name, None)) => format!("_{name}_\n"),
+            None => String::new(),
+        });
+    }
+    out
+}
+
+fn message_html(item: &AcpItem) -> String {
+    let mut out = String::new();
+    if let Some(text) = &item.text {
+        let _ = writeln!(out, "<pre>{}</pre>", escape(text.trim_end()));
+    }
+    for block in &item.content {
+        match block_link(block) {
+            Some((name, Some(uri))) => {
+                let _ = writeln!(
+                    out,
+                    "<p><a href=\"{}\">{}</a></p>",
+                    escape(&uri),
+                    escape(&name)
+                );
+            }
+            Some((name, None)) => {
+                let _ = writeln!(out, "<p><em>{}</em></p>", escape(&name));
+            }
+            None => {}
+        }
+    }
+    out
+}
+
+/// Name and link of a non-text content block: resource links and embedded resources keep
+/// their uri, images and audio are named by their type.
+fn block_link(block: &Value) -> Option<(String, Option<String>)> {
+    let string = |value: Option<&Value>| value.and_then(Value::as_str).map(str::to_string);
+    let uri = string(block.get("uri")).or_else(|| string(block.pointer("/resource/uri")));
+    let name = string(block.get("name"))
+        .or_else(|| uri.clone())
+        .or_else(|| string(block.get("type")))?;
+    Some((name, uri))
+}
+
+fn tool_parts(item: &AcpItem) -> ToolParts {
+    let mut output = Vec::new();
+    let mut diffs = Vec::new();
+    for block in &item.content {
+        match block.get("type").and_then(Value::as_str) {
+            Some("diff") => {
+                let path = block
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let old = block
+                    .get("oldText")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let new = block
+                    .get("newText")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let diff = TextDiff::from_lines(old, new)
+                    .unified_diff()
+                    .header(&format!("a/{path}"), &format!("b/{path}"))
+                    .to_string();
+                diffs.push((path.to_string(), diff));
+            }
+            Some("content") => match block.pointer("/content/text").and_then(Value::as_str) {
+                Some(text) => output.push(text.to_string()),
+                None => output.push(pretty(&block["content"])),
+            },
+            _ => output.push(pretty(block)),
+        }
+    }
+    if output.is_empty() {
+        output.extend(item.raw_output.as_ref().map(pretty));
+    }
+    ToolParts {
+        input: item.raw_input.as_ref().map(pretty),
+        output,
+        diffs,
+    }
+}
+
+fn pretty(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => serde_json::to_string_pretty(value).unwrap_or_default(),
+    }
+}
+
+/// A Markdown code block whose fence is longer than any backtick run in `text`.
+fn fenced(text: &str, language: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!(
+        "{fence}{language}\n{}\n{fence}\n",
+        text.trim_end_matches('\n')
+    )
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn item(item_id: &str, kind: AcpItemKind, text: Option<&str>) -> AcpItem {
+        AcpItem {
+            item_id: item_id.to_string(),
+            kind,
+            status: AcpItemStatus::Completed,
+            text: text.map(str::to_string),
+            content: Vec::new(),
+            title: None,
+            tool_kind: None,
+            raw_input: None,
+            raw_output: None,
+            parent_tool_call_id: None,
+            sequence: 0,
+            updated_sequence: 0,
+        }
+    }
+
+    fn transcript() -> Vec<AcpItem> {
+        let mut tool = item("t1", AcpItemKind::ToolCall, None);
+        tool.title = Some("Edit <main.rs>".to_string());
+        tool.tool_kind = Some("edit".to_string());
+        tool.raw_input = Some(json!({ "path": "main.rs" }));
+        tool.content = vec![
+            json!({ "type": "content", "content": { "type": "text", "text": "```done```" } }),
+            json!({ "type": "diff", "path": "main.rs", "oldText": "a\n", "newText": "b\n" }),
+        ];
+        vec![
+            item("prompt-1", AcpItemKind::UserMessage, Some("fix it")),
+            item("reasoning-1", AcpItemKind::Reasoning, Some("Looking")),
+            tool,
+            item("agent_message-4", AcpItemKind::AgentMessage, Some("Fixed")),
+        ]
+    }
+
+    #[test]
+    fn markdown_collapses_tool_output_and_keeps_diffs() {
+        let out = markdown("s-1", &transcript());
+        assert!(out.starts_with("# Session `s-1`\n\n**User**\n\nfix it\n"));
+        assert!(out.contains("<summary>Reasoning</summary>\n\nLooking\n"));
+        assert!(out.contains("**Tool call:** Edit <main.rs> (edit, completed)\n"));
+        assert!(out.contains("````\n```done```\n````\n"));
+        assert!(out.contains("```diff\n--- a/main.rs\n+++ b/main.rs\n@@ -1 +1 @@\n-a\n+b\n```\n"));
+        assert!(out.ends_with("**Agent**\n\nFixed\n"));
+    }
+
+    #[test]
+    fn html_escapes_and_marks_diff_lines() {
+        let out = html("s-1", &transcript());
+        assert!(out.contains("<title>Session s-1</title>"));
+        assert!(out.contains("<h4>Tool call: Edit &lt;main.rs&gt; (edit, completed)</h4>"));
+        assert!(out.contains("<span class=\"del\">-a</span>\n<span class=\"add\">+b</span>"));
+        assert!(out.contains("<span>--- a/main.rs</span>"));
+        assert!(out.ends_with("</body>\n</html>\n"));
+    }
+}
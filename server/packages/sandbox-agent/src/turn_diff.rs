@@ -0,0 +1,237 @@
+//! Per-turn workspace diffs for ACP servers.
+//!
+//! When turn snapshots are enabled, the server directory is captured before and after each
+//! `session/prompt` and the difference is kept as a unified diff. Snapshots read the files
+//! directly, so this works whether or not the directory is a git repository. `.git` and
+//! gitignored paths are skipped and symlinks are not followed.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+
+/// Turns kept per server; older turns are dropped first.
+const MAX_TURNS: usize = 100;
+/// Files captured per snapshot. Larger trees are marked truncated.
+const MAX_SNAPSHOT_FILES: usize = 20_000;
+/// Files larger than this are compared by checksum only.
+const MAX_TEXT_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FileState {
+    Text(String),
+    Binary { sha256: String },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceSnapshot {
+    files: BTreeMap<String, FileState>,
+    truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnFileChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurnFileChange {
+    /// Path relative to the snapshot root, `/`-separated.
+    pub path: String,
+    pub change: TurnFileChangeKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct TurnRecord {
+    pub turn_id: u64,
+    pub session_id: Option<String>,
+    pub root: PathBuf,
+    pub started_at_ms: i64,
+    pub ended_at_ms: i64,
+    pub files: Vec<TurnFileChange>,
+    pub diff: String,
+    /// A snapshot hit the file limit, so changes beyond it are missing.
+    pub truncated: bool,
+}
+
+/// Regular files under `root` as (`/`-separated relative path, absolute path), skipping `.git`,
+/// gitignored paths and symlinks. The flag is set when the listing stopped at the file limit.
+pub fn workspace_files(root: &Path) -> (Vec<(String, PathBuf)>, bool) {
+    let walker = ignore::WalkBuilder::new(root)
+        .hidden(false)
+        .require_git(false)
+        .follow_links(false)
+        .filter_entry(|entry| entry.depth() == 0 || entry.file_name() != ".git")
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker.flatten() {
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+            continue;
+        }
+        if files.len() >= MAX_SNAPSHOT_FILES {
+            return (files, true);
+        }
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push((relative, entry.path().to_path_buf()));
+    }
+    (files, false)
+}
+
+impl WorkspaceSnapshot {
+    pub fn capture(root: &Path) -> Self {
+        let (files, truncated) = workspace_files(root);
+        let files = files
+            .into_iter()
+            .filter_map(|(relative, path)| Some((relative, read_file_state(&path)?)))
+            .collect();
+        Self { files, truncated }
+    }
+
+    /// Changed files and a git-style unified diff from `self` to `after`.
+    pub fn diff(&self, after: &Self) -> (Vec<TurnFileChange>, String) {
+        let paths = self
+            .files
+            .keys()
+            .chain(after.files.keys())
+            .collect::<BTreeSet<_>>();
+        let mut files = Vec::new();
+        let mut diff = String::new();
+        for path in paths {
+            let before = self.files.get(path);
+            let current = after.files.get(path);
+            let change = match (before, current) {
+                (None, Some(_)) => TurnFileChangeKind::Added,
+                (Some(_), None) => TurnFileChangeKind::Deleted,
+                (Some(before), Some(current)) if before != current => TurnFileChangeKind::Modified,
+                _ => continue,
+            };
+            write_file_diff(&mut diff, path, before, current);
+            files.push(TurnFileChange {
+                path: path.clone(),
+                change,
+            });
+        }
+        (files, diff)
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+fn read_file_state(path: &Path) -> Option<FileState> {
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.len() > MAX_TEXT_BYTES {
+        return sha256_file(path).map(|sha256| FileState::Binary { sha256 });
+    }
+    let bytes = match String::from_utf8(fs::read(path).ok()?) {
+        Ok(text) if !text.contains('\0') => return Some(FileState::Text(text)),
+        Ok(text) => text.into_bytes(),
+        Err(err) => err.into_bytes(),
+    };
+    Some(FileState::Binary {
+        sha256: format!("{:x}", Sha256::digest(&bytes)),
+    })
+}
+
+pub fn sha256_file(path: &Path) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).ok()?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+fn write_file_diff(
+    out: &mut String,
+    path: &str,
+    before: Option<&FileState>,
+    after: Option<&FileState>,
+) {
+    let old_name = if before.is_some() {
+        format!("a/{path}")
+    } else {
+        "/dev/null".to_string()
+    };
+    let new_name = if after.is_some() {
+        format!("b/{path}")
+    } else {
+        "/dev/null".to_string()
+    };
+    out.push_str(&format!("diff --git a/{path} b/{path}\n"));
+    match (before, after) {
+        (None, _) => out.push_str("new file\n"),
+        (_, None) => out.push_str("deleted file\n"),
+        _ => {}
+    }
+
+    match (text_of(before), text_of(after)) {
+        (Some(old), Some(new)) => {
+            let unified = TextDiff::from_lines(old, new)
+                .unified_diff()
+                .header(&old_name, &new_name)
+                .to_string();
+            out.push_str(&unified);
+        }
+        _ => out.push_str(&format!("Binary files {old_name} and {new_name} differ\n")),
+    }
+}
+
+/// Text content for diffing; an absent file diffs as empty and binary files have none.
+fn text_of(state: Option<&FileState>) -> Option<&str> {
+    match state {
+        None => Some(""),
+        Some(FileState::Text(text)) => Some(text),
+        Some(FileState::Binary { .. }) => None,
+    }
+}
+
+/// Recorded turns of one server, oldest first.
+#[derive(Debug, Default)]
+pub struct TurnLog {
+    next_turn_id: u64,
+    turns: VecDeque<TurnRecord>,
+}
+
+impl TurnLog {
+    /// Store a finished turn, assigning its id.
+    pub fn push(&mut self, mut record: TurnRecord) -> u64 {
+        self.next_turn_id += 1;
+        record.turn_id = self.next_turn_id;
+        if self.turns.len() >= MAX_TURNS {
+            self.turns.pop_front();
+        }
+        self.turns.push_back(record);
+        self.next_turn_id
+    }
+
+    pub fn list(&self) -> Vec<TurnRecord> {
+        self.turns.iter().cloned().collect()
+    }
+
+    pub fn get(&self, turn_id: u64) -> Option<TurnRecord> {
+        self.turns
+            .iter()
+            .find(|turn| turn.turn_id == turn_id)
+            .cloned()
+    }
+}
@@ -0,0 +1,226 @@
+//! Per-turn model, reasoning variant and mode overrides on `session/prompt`.
+//!
+//! Clients put them under `params._meta["sandboxagent.dev"]`:
+//! - `model`: a model id the agent offers for the session.
+//! - `variant`: a Codex reasoning effort, applied to `model` or to the session's model.
+//! - `agentMode`: a mode id the agent offers for the session.
+//!
+//! ACP has no per-prompt model or mode, so the daemon switches the session with
+//! `session/set_model` and `session/set_mode` before the prompt and switches it back once the
+//! turn ends. Switching back needs the session's own selection, which the daemon follows
+//! through `session/new`, `session/load` and client `session/set_model` and `session/set_mode`
+//! calls; an override of something it has not seen is rejected. Mode changes the agent makes
+//! on its own are not followed, so the switch back undoes them.
+
+use std::collections::HashMap;
+
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Map, Value};
+
+use crate::agent_config::{codex_variant_model, validate_variant};
+
+/// The model and mode a session runs with, as far as the daemon has seen.
+#[derive(Debug, Clone, Default)]
+struct Selection {
+    model: Option<String>,
+    mode: Option<String>,
+}
+
+/// The selection of every session on one ACP server.
+#[derive(Debug, Default)]
+pub(crate) struct Selections {
+    sessions: HashMap<String, Selection>,
+}
+
+/// Requests that switch a session for one turn, and the ones that switch it back.
+#[derive(Debug, Default)]
+pub(crate) struct TurnOverride {
+    pub(crate) apply: Vec<Value>,
+    pub(crate) restore: Vec<Value>,
+}
+
+impl Selections {
+    /// Follow the selection change `request` made, once the agent answered it with `response`.
+    pub(crate) fn observe(&mut self, request: &Value, response: &Value) {
+        let Some(result) = response.get("result") else {
+            return;
+        };
+        let Some(session_id) = result
+            .get("sessionId")
+            .or_else(|| request.pointer("/params/sessionId"))
+            .and_then(Value::as_str)
+        else {
+            return;
+        };
+        let string = |value: &Value, pointer: &str| {
+            value
+                .pointer(pointer)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        let selection = self.sessions.entry(session_id.to_string()).or_default();
+        match request.get("method").and_then(Value::as_str) {
+            Some("session/new" | "session/load") => {
+                selection.model = string(result, "/models/currentModelId");
+                selection.mode = string(result, "/modes/currentModeId");
+            }
+            Some("session/set_model") => {
+                selection.model = string(request, "/params/modelId");
+            }
+            Some("session/set_mode") => {
+                selection.mode = string(request, "/params/modeId");
+            }
+            _ => {}
+        }
+    }
+
+    /// Take the overrides off a `session/prompt`; `None` when it has none or they change
+    /// nothing.
+    pub(crate) fn take_override(
+        &self,
+        agent: AgentId,
+        payload: &mut Value,
+    ) -> Result<Option<TurnOverride>, SandboxError> {
+        if payload.get("method").and_then(Value::as_str) != Some("session/prompt") {
+            return Ok(None);
+        }
+        let Some(ours) = payload
+            .pointer_mut("/params/_meta/sandboxagent.dev")
+            .and_then(Value::as_object_mut)
+        else {
+            return Ok(None);
+        };
+        let model = take_string(ours, "model")?;
+        let variant = take_string(ours, "variant")?;
+        let mode = take_string(ours, "agentMode")?;
+        if model.is_none() && variant.is_none() && mode.is_none() {
+            return Ok(None);
+        }
+        if let Some(variant) = &variant {
+            validate_variant(agent, variant)?;
+        }
+        let session_id = payload
+            .pointer("/params/sessionId")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("session/prompt requires params.sessionId".to_string()))?;
+        let current = self.sessions.get(session_id).cloned().unwrap_or_default();
+
+        let mut turn = TurnOverride::default();
+        if model.is_some() || variant.is_some() {
+            let previous = current.model.ok_or_else(|| {
+                invalid(format!(
+                    "the model of session {session_id} is not known, so it cannot be overridden for one turn"
+                ))
+            })?;
+            let model = model.unwrap_or_else(|| previous.clone());
+            let model = match &variant {
+                Some(variant) => codex_variant_model(&model, variant),
+                None => model,
+            };
+            if model != previous {
+                turn.push(session_id, "model", model, previous);
+            }
+        }
+        if let Some(mode) = mode {
+            let previous = current.mode.ok_or_else(|| {
+                invalid(format!(
+                    "the mode of session {session_id} is not known, so it cannot be overridden for one turn"
+                ))
+            })?;
+            if mode != previous {
+                turn.push(session_id, "mode", mode, previous);
+            }
+        }
+        Ok((!turn.apply.is_empty()).then_some(turn))
+    }
+}
+
+impl TurnOverride {
+    fn push(&mut self, session_id: &str, setting: &str, value: String, previous: String) {
+        let request = |phase: &str, value: String| {
+            let (method, field) = match setting {
+                "model" => ("session/set_model", "modelId"),
+                _ => ("session/set_mode", "modeId"),
+            };
+            json!({
+                "jsonrpc": "2.0",
+                "id": format!("sandboxagent-turn-{session_id}-{setting}-{phase}"),
+                "method": method,
+                "params": { "sessionId": session_id, field: value },
+            })
+        };
+        self.apply.push(request("apply", value));
+        self.restore.push(request("restore", previous));
+    }
+}
+
+fn take_string(ours: &mut Map<String, Value>, key: &str) -> Result<Option<String>, SandboxError> {
+    match ours.remove(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) if !value.is_empty() => Ok(Some(value)),
+        Some(_) => Err(invalid(format!("{key} must be a non-empty string"))),
+    }
+}
+
+fn invalid(message: String) -> SandboxError {
+    SandboxError::InvalidRequest { message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(ours: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/prompt",
+            "params": { "sessionId": "s-1", "prompt": [], "_meta": { "sandboxagent.dev": ours } },
+        })
+    }
+
+    #[test]
+    fn overrides_switch_from_and_back_to_the_observed_selection() {
+        let mut selections = Selections::default();
+        selections.observe(
+            &json!({ "method": "session/new", "params": {} }),
+            &json!({ "result": {
+                "sessionId": "s-1",
+                "models": { "currentModelId": "gpt-5.3-codex/medium" },
+                "modes": { "currentModeId": "auto" },
+            } }),
+        );
+
+        let mut payload = prompt(json!({ "variant": "high", "agentMode": "read-only" }));
+        let turn = selections
+            .take_override(AgentId::Codex, &mut payload)
+            .unwrap()
+            .expect("override");
+        assert!(payload["params"]["_meta"]["sandboxagent.dev"]
+            .as_object()
+            .unwrap()
+            .is_empty());
+        assert_eq!(turn.apply[0]["params"]["modelId"], "gpt-5.3-codex/high");
+        assert_eq!(turn.restore[0]["params"]["modelId"], "gpt-5.3-codex/medium");
+        assert_eq!(turn.apply[1]["method"], "session/set_mode");
+        assert_eq!(turn.restore[1]["params"]["modeId"], "auto");
+
+        let mut payload = prompt(json!({ "agentMode": "auto" }));
+        assert!(selections
+            .take_override(AgentId::Codex, &mut payload)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn overrides_need_a_known_selection() {
+        let selections = Selections::default();
+        assert!(selections
+            .take_override(AgentId::Claude, &mut prompt(json!({ "model": "opus" })))
+            .is_err());
+        assert!(selections
+            .take_override(AgentId::Claude, &mut prompt(json!({ "variant": "high" })))
+            .is_err());
+    }
+}
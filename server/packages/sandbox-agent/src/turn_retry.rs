@@ -0,0 +1,183 @@
+//! Retries of turns that failed for a transient reason.
+//!
+//! A `session/prompt` opts in with `_meta["sandboxagent.dev"].retry`:
+//!
+//! ```json
+//! {"maxAttempts": 3, "backoffMs": 1000}
+//! ```
+//!
+//! When the agent answers with an error that looks transient (rate limits, overloaded or
+//! unavailable providers, reset connections and streams), the daemon sends the prompt again
+//! after `backoffMs`, doubled for every further attempt, until it succeeds or `maxAttempts`
+//! turns have run. Each retry is announced with a `_sandboxagent/turn/retry` notification whose
+//! `retryOf` is the attempt that failed.
+//!
+//! Turns lost to the agent process exiting or restarting are not retried: the session lived in
+//! that process, and the server's event streams are tied to it, so the daemon does not respawn
+//! the agent and `session/load` the session itself. Clients recover by recreating the server.
+
+use std::time::Duration;
+
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+
+use crate::rate_limits;
+use crate::turn_timeout::TURN_TIMEOUT_CODE;
+
+const MAX_ATTEMPTS: u64 = 10;
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// HTTP statuses of provider errors worth another attempt besides rate limits, matched as
+/// whole words.
+const TRANSIENT_STATUSES: &[&str] = &["502", "503"];
+/// Lowercase fragments of agent errors besides rate limits that are worth another attempt.
+const TRANSIENT_PATTERNS: &[&str] = &[
+    "bad gateway",
+    "service unavailable",
+    "connection reset",
+    "econnreset",
+    "socket hang up",
+    "stream reset",
+    "stream was reset",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// How long to wait before the attempt after `attempt`.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(MAX_BACKOFF)
+    }
+}
+
+/// Take the retry policy off a `session/prompt`.
+pub(crate) fn take_retry_policy(payload: &mut Value) -> Result<Option<RetryPolicy>, SandboxError> {
+    if payload.get("method").and_then(Value::as_str) != Some("session/prompt") {
+        return Ok(None);
+    }
+    let Some(retry) = payload
+        .pointer_mut("/params/_meta/sandboxagent.dev")
+        .and_then(Value::as_object_mut)
+        .and_then(|ours| ours.remove("retry"))
+    else {
+        return Ok(None);
+    };
+    if retry.is_null() {
+        return Ok(None);
+    }
+    let invalid = |message: &str| SandboxError::InvalidRequest {
+        message: format!("retry: {message}"),
+    };
+    let max_attempts = retry
+        .get("maxAttempts")
+        .and_then(Value::as_u64)
+        .filter(|attempts| (1..=MAX_ATTEMPTS).contains(attempts))
+        .ok_or_else(|| invalid(&format!("maxAttempts must be 1 to {MAX_ATTEMPTS}")))?;
+    let backoff = match retry.get("backoffMs") {
+        None | Some(Value::Null) => DEFAULT_BACKOFF,
+        Some(value) => value
+            .as_u64()
+            .map(Duration::from_millis)
+            .filter(|backoff| *backoff <= MAX_BACKOFF)
+            .ok_or_else(|| {
+                invalid(&format!(
+                    "backoffMs must be 0 to {}",
+                    MAX_BACKOFF.as_millis()
+                ))
+            })?,
+    };
+    Ok(Some(RetryPolicy {
+        max_attempts: max_attempts as u32,
+        backoff,
+    }))
+}
+
+/// The error of `response` when it failed for a reason worth another attempt.
+pub(crate) fn transient_error(response: &Value) -> Option<&Value> {
+    let error = response.get("error")?;
+    if error.pointer("/data/code").and_then(Value::as_str) == Some(TURN_TIMEOUT_CODE) {
+        return None;
+    }
+    let text = error.to_string().to_lowercase();
+    let status = text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| TRANSIENT_STATUSES.contains(&word));
+    (status
+        || rate_limits::classify(error).is_some()
+        || TRANSIENT_PATTERNS
+            .iter()
+            .any(|pattern| text.contains(pattern)))
+    .then_some(error)
+}
+
+/// Notification that the prompt with `request_id` is sent again as `attempt`.
+pub(crate) fn retry_event(
+    session_id: &str,
+    request_id: Option<&Value>,
+    attempt: u32,
+    delay: Duration,
+    error: &Value,
+) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "_sandboxagent/turn/retry",
+        "params": {
+            "sessionId": session_id,
+            "requestId": request_id,
+            "attempt": attempt,
+            "retryOf": attempt - 1,
+            "delayMs": delay.as_millis() as u64,
+            "error": error,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_policy_is_taken_from_prompt_meta() {
+        let mut prompt = json!({
+            "method": "session/prompt",
+            "params": { "_meta": { "sandboxagent.dev": { "retry": { "maxAttempts": 3 } } } },
+        });
+        let policy = take_retry_policy(&mut prompt)
+            .expect("valid")
+            .expect("policy");
+        assert_eq!(policy.max_attempts, 3);
+        assert_eq!(policy.delay(1), DEFAULT_BACKOFF);
+        assert_eq!(policy.delay(2), DEFAULT_BACKOFF * 2);
+        assert_eq!(policy.delay(20), MAX_BACKOFF);
+        assert!(prompt
+            .pointer("/params/_meta/sandboxagent.dev/retry")
+            .is_none());
+
+        let mut invalid = json!({
+            "method": "session/prompt",
+            "params": { "_meta": { "sandboxagent.dev": { "retry": { "maxAttempts": 0 } } } },
+        });
+        assert!(take_retry_policy(&mut invalid).is_err());
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let error = |message: &str| json!({ "error": { "code": -32000, "message": message } });
+        assert!(transient_error(&error("model overloaded")).is_some());
+        assert!(transient_error(&error("HTTP 429: Too Many Requests")).is_some());
+        assert!(transient_error(&error("invalid API key")).is_none());
+        assert!(transient_error(&error("prompt is 4290 tokens too long")).is_none());
+        assert!(transient_error(&json!({ "result": { "stopReason": "end_turn" } })).is_none());
+        let timeout = json!({
+            "error": { "code": -32603, "message": "turn timed out after 429 ms",
+                       "data": { "code": TURN_TIMEOUT_CODE } },
+        });
+        assert!(transient_error(&timeout).is_none());
+    }
+}
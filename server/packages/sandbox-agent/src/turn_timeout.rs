@@ -0,0 +1,188 @@
+//! Turn timeouts for `session/prompt`.
+//!
+//! A turn may run for `SANDBOX_AGENT_ACP_TURN_TIMEOUT_MS` by default, for
+//! `_meta["sandboxagent.dev"].turnTimeoutMs` of the `session/new` that created its session, or
+//! for the same field on the `session/prompt` itself; `0` turns the timeout off. When it runs
+//! out the daemon sends the agent `session/cancel` and gives it [`CANCEL_GRACE`] to end the
+//! turn, then stops an agent process that did not, unless another session of the server has a
+//! turn running: that process is left alone and only the timed-out prompt fails. The server's
+//! streams receive
+//! `_sandboxagent/turn/ended` with `phase: "timeout"` and an `_sandboxagent/error` with code
+//! `turn_timeout`, and the prompt is answered with a JSON-RPC error.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use sandbox_agent_error::SandboxError;
+use serde_json::{json, Value};
+
+const TURN_TIMEOUT_ENV: &str = "SANDBOX_AGENT_ACP_TURN_TIMEOUT_MS";
+/// How long an agent has to end a timed-out turn after `session/cancel`.
+pub(crate) const CANCEL_GRACE: Duration = Duration::from_secs(5);
+pub(crate) const TURN_TIMEOUT_CODE: &str = "turn_timeout";
+
+/// Daemon default turn timeout, from `SANDBOX_AGENT_ACP_TURN_TIMEOUT_MS`.
+pub(crate) fn default_from_env() -> Option<Duration> {
+    std::env::var(TURN_TIMEOUT_ENV)
+        .ok()
+        .and_then(|raw| raw.trim().parse::<u64>().ok())
+        .filter(|value| *value > 0)
+        .map(Duration::from_millis)
+}
+
+/// Take `turnTimeoutMs` off a `session/new` or `session/prompt`.
+pub(crate) fn take_turn_timeout(payload: &mut Value) -> Result<Option<Duration>, SandboxError> {
+    let method = payload.get("method").and_then(Value::as_str);
+    if !matches!(method, Some("session/new" | "session/prompt")) {
+        return Ok(None);
+    }
+    let Some(ours) = payload
+        .pointer_mut("/params/_meta/sandboxagent.dev")
+        .and_then(Value::as_object_mut)
+    else {
+        return Ok(None);
+    };
+    match ours.remove("turnTimeoutMs") {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(Duration::from_millis)
+            .map(Some)
+            .ok_or_else(|| SandboxError::InvalidRequest {
+                message: "turnTimeoutMs must be a non-negative integer".to_string(),
+            }),
+    }
+}
+
+/// Turn timeouts the sessions of one ACP server were created with.
+#[derive(Debug, Default)]
+pub(crate) struct SessionTimeouts {
+    sessions: HashMap<String, Duration>,
+}
+
+impl SessionTimeouts {
+    /// Remember `timeout` for the session the `session/new` `response` created.
+    pub(crate) fn observe(&mut self, timeout: Duration, response: &Value) {
+        if let Some(session_id) = response
+            .pointer("/result/sessionId")
+            .and_then(Value::as_str)
+        {
+            self.sessions.insert(session_id.to_string(), timeout);
+        }
+    }
+
+    /// The timeout of a turn of `session_id`: the prompt's own, the session's, or `default`.
+    pub(crate) fn resolve(
+        &self,
+        session_id: &str,
+        prompt: Option<Duration>,
+        default: Option<Duration>,
+    ) -> Option<Duration> {
+        prompt
+            .or_else(|| self.sessions.get(session_id).copied())
+            .or(default)
+            .filter(|timeout| !timeout.is_zero())
+    }
+}
+
+/// What became of a timed-out turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimeoutOutcome {
+    /// The agent ended it after `session/cancel`.
+    Cancelled,
+    /// The agent did not, and its process was stopped.
+    Stopped,
+    /// The agent did not, and its process was kept for other sessions' running turns.
+    Abandoned,
+}
+
+/// Notifications telling the server's streams that a turn of `session_id` timed out.
+pub(crate) fn timeout_events(
+    session_id: &str,
+    timeout: Duration,
+    outcome: TimeoutOutcome,
+) -> Vec<Value> {
+    let timeout_ms = timeout.as_millis() as u64;
+    let message = match outcome {
+        TimeoutOutcome::Cancelled => format!("turn timed out after {timeout_ms} ms and was cancelled"),
+        TimeoutOutcome::Stopped => format!(
+            "turn timed out after {timeout_ms} ms and the agent did not stop; its process was stopped"
+        ),
+        TimeoutOutcome::Abandoned => format!(
+            "turn timed out after {timeout_ms} ms and the agent did not stop; its process was kept for other sessions' turns"
+        ),
+    };
+    vec![
+        json!({
+            "jsonrpc": "2.0",
+            "method": "_sandboxagent/turn/ended",
+            "params": { "sessionId": session_id, "phase": "timeout", "timeoutMs": timeout_ms },
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "_sandboxagent/error",
+            "params": {
+                "sessionId": session_id,
+                "message": message,
+                "code": TURN_TIMEOUT_CODE,
+                "details": {
+                    "timeoutMs": timeout_ms,
+                    "agentStopped": outcome == TimeoutOutcome::Stopped,
+                    "abandoned": outcome == TimeoutOutcome::Abandoned,
+                },
+            },
+        }),
+    ]
+}
+
+/// The answer to a `session/prompt` with `id` whose turn timed out.
+pub(crate) fn timeout_response(id: Option<&Value>, timeout: Duration) -> Value {
+    let timeout_ms = timeout.as_millis() as u64;
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": -32603,
+            "message": format!("turn timed out after {timeout_ms} ms"),
+            "data": { "code": TURN_TIMEOUT_CODE, "timeoutMs": timeout_ms },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_timeouts_override_session_and_default() {
+        let mut prompt = json!({
+            "method": "session/prompt",
+            "params": { "_meta": { "sandboxagent.dev": { "turnTimeoutMs": 0 } } },
+        });
+        assert_eq!(
+            take_turn_timeout(&mut prompt).expect("valid"),
+            Some(Duration::ZERO)
+        );
+        assert!(prompt
+            .pointer("/params/_meta/sandboxagent.dev/turnTimeoutMs")
+            .is_none());
+        let mut invalid = json!({
+            "method": "session/new",
+            "params": { "_meta": { "sandboxagent.dev": { "turnTimeoutMs": "soon" } } },
+        });
+        assert!(take_turn_timeout(&mut invalid).is_err());
+
+        let mut timeouts = SessionTimeouts::default();
+        timeouts.observe(
+            Duration::from_secs(30),
+            &json!({"result": {"sessionId": "s-1"}}),
+        );
+        let default = Some(Duration::from_secs(600));
+        assert_eq!(
+            timeouts.resolve("s-1", None, default),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(timeouts.resolve("s-2", None, default), default);
+        assert_eq!(timeouts.resolve("s-1", Some(Duration::ZERO), default), None);
+    }
+}
@@ -119,6 +119,8 @@ pub enum SessionEndReason {
     Completed,
     Error,
     Terminated,
+    /// Shut down by the daemon after the session was idle for its idle timeout.
+    Timeout,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
@@ -0,0 +1,190 @@
+//! Agent output that could not be parsed, behind `GET /v1/debug/unparsed`.
+//!
+//! The ACP adapter forwards every agent stdout line that is not JSON as an
+//! `_adapter/invalid_stdout` notification, where it is easy to miss among session updates.
+//! Each one is also counted per agent for the daemon's lifetime and per server while the
+//! server is open, and the newest [`UNPARSED_CAPACITY`] lines are kept with their parse errors.
+//!
+//! With `SANDBOX_AGENT_ACP_MAX_UNPARSED_PER_TURN` set, a `session/prompt` whose turn produced
+//! more unparsed lines than that answers with a JSON-RPC error instead of the agent's result.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use sandbox_agent_agent_management::agents::AgentId;
+use sandbox_agent_api_types::{UnparsedAgentCount, UnparsedEvent};
+use serde_json::{json, Value};
+
+use crate::router::now_ms;
+
+pub const UNPARSED_CAPACITY: usize = 256;
+const INVALID_STDOUT_METHOD: &str = "_adapter/invalid_stdout";
+const MAX_PER_TURN_ENV: &str = "SANDBOX_AGENT_ACP_MAX_UNPARSED_PER_TURN";
+/// Longest prefix of a line that is kept.
+const MAX_RAW_BYTES: usize = 4096;
+
+#[derive(Debug, Default)]
+pub(crate) struct UnparsedLog {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    agents: BTreeMap<&'static str, u64>,
+    recent: VecDeque<UnparsedEvent>,
+}
+
+impl UnparsedLog {
+    /// Count `message` of `server_id` when it is an unparsed line; returns whether it was.
+    pub(crate) fn record(
+        &self,
+        server_id: &str,
+        agent: AgentId,
+        sequence: u64,
+        message: &Value,
+    ) -> bool {
+        if !is_unparsed(message) {
+            return false;
+        }
+        let text = |key: &str| {
+            message
+                .pointer(&format!("/params/{key}"))
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+        };
+        let event = UnparsedEvent {
+            server_id: server_id.to_string(),
+            agent: agent.as_str().to_string(),
+            sequence,
+            timestamp_ms: now_ms(),
+            error: text("error").to_string(),
+            raw: truncate(text("raw"), MAX_RAW_BYTES).to_string(),
+        };
+        let mut inner = self.inner.lock().expect("unparsed log poisoned");
+        *inner.agents.entry(agent.as_str()).or_default() += 1;
+        inner.recent.push_back(event);
+        while inner.recent.len() > UNPARSED_CAPACITY {
+            inner.recent.pop_front();
+        }
+        true
+    }
+
+    /// Lines since the daemon started, per agent.
+    pub(crate) fn agents(&self) -> Vec<UnparsedAgentCount> {
+        let inner = self.inner.lock().expect("unparsed log poisoned");
+        inner
+            .agents
+            .iter()
+            .map(|(agent, count)| UnparsedAgentCount {
+                agent: agent.to_string(),
+                count: *count,
+            })
+            .collect()
+    }
+
+    /// The newest `limit` lines, of `server_id` only when given, oldest first.
+    pub(crate) fn recent(&self, server_id: Option<&str>, limit: usize) -> Vec<UnparsedEvent> {
+        let inner = self.inner.lock().expect("unparsed log poisoned");
+        let mut events = inner
+            .recent
+            .iter()
+            .rev()
+            .filter(|event| server_id.is_none_or(|id| event.server_id == id))
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+        events.reverse();
+        events
+    }
+}
+
+pub(crate) fn is_unparsed(message: &Value) -> bool {
+    message.get("method").and_then(Value::as_str) == Some(INVALID_STDOUT_METHOD)
+}
+
+/// Unparsed lines allowed per turn, from `SANDBOX_AGENT_ACP_MAX_UNPARSED_PER_TURN`.
+pub(crate) fn max_per_turn_from_env() -> Option<u64> {
+    std::env::var(MAX_PER_TURN_ENV)
+        .ok()
+        .and_then(|raw| raw.trim().parse::<u64>().ok())
+        .filter(|limit| *limit > 0)
+}
+
+/// `response` to a `session/prompt` whose turn produced `count` unparsed lines, failed when
+/// that is over `limit`.
+pub(crate) fn check_turn(response: Value, count: u64, limit: u64) -> Value {
+    if count <= limit || response.get("result").is_none() {
+        return response;
+    }
+    json!({
+        "jsonrpc": "2.0",
+        "id": response.get("id"),
+        "error": {
+            "code": -32603,
+            "message": format!(
+                "agent output could not be parsed: {count} lines in this turn, limit {limit}"
+            ),
+            "data": { "unparsedLines": count, "limit": limit },
+        },
+    })
+}
+
+fn truncate(raw: &str, max: usize) -> &str {
+    if raw.len() <= max {
+        return raw;
+    }
+    let mut end = max;
+    while !raw.is_char_boundary(end) {
+        end -= 1;
+    }
+    &raw[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(raw: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": INVALID_STDOUT_METHOD,
+            "params": { "error": "expected value at line 1 column 1", "raw": raw },
+        })
+    }
+
+    #[test]
+    fn unparsed_lines_are_counted_and_kept() {
+        let log = UnparsedLog::default();
+        assert!(!log.record(
+            "a",
+            AgentId::Claude,
+            1,
+            &json!({"method": "session/update"})
+        ));
+        assert!(log.record("a", AgentId::Claude, 2, &invalid("Loading...")));
+        assert!(log.record("b", AgentId::Codex, 1, &invalid(&"é".repeat(3000))));
+
+        let agents = log
+            .agents()
+            .into_iter()
+            .map(|count| (count.agent, count.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            agents,
+            [("claude".to_string(), 1), ("codex".to_string(), 1)]
+        );
+        let recent = log.recent(Some("b"), 10);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].raw.len(), MAX_RAW_BYTES);
+        assert_eq!(log.recent(None, 1)[0].server_id, "b");
+    }
+
+    #[test]
+    fn turns_fail_over_the_limit() {
+        let response = json!({"jsonrpc": "2.0", "id": 7, "result": {"stopReason": "end_turn"}});
+        assert_eq!(check_turn(response.clone(), 2, 2), response);
+        let failed = check_turn(response, 3, 2);
+        assert_eq!(failed["id"], 7);
+        assert_eq!(failed["error"]["data"]["unparsedLines"], 3);
+    }
+}
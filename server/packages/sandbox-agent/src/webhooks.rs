@@ -0,0 +1,438 @@
+//! Webhook notifications for ACP server lifecycle, permission and question requests, and errors.
+//!
+//! Global URLs come from `SANDBOX_AGENT_WEBHOOK_URLS` (comma separated); a server can add its
+//! own with the `webhookUrl` bootstrap query parameter. Each matching event is POSTed as JSON
+//! to every URL, in order per server. With `SANDBOX_AGENT_WEBHOOK_SECRET` set, deliveries carry
+//! an HMAC-SHA256 signature of `{timestamp}.{body}` in `X-Sandbox-Agent-Signature`.
+//!
+//! Any client allowed to bootstrap a server picks its `webhookUrl`, so those URLs may only
+//! reach public addresses: loopback, private, link-local and other internal destinations are
+//! refused, both as IP literals and as what a host name resolves to when connecting. Redirects
+//! are never followed.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use sandbox_agent_agent_management::agents::AgentId;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+
+use crate::router::now_ms;
+
+const WEBHOOK_URLS_ENV: &str = "SANDBOX_AGENT_WEBHOOK_URLS";
+const WEBHOOK_SECRET_ENV: &str = "SANDBOX_AGENT_WEBHOOK_SECRET";
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const DELIVERY_ATTEMPTS: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const QUEUE_CAPACITY: usize = 256;
+
+pub(crate) const EVENT_HEADER: &str = "x-sandbox-agent-event";
+pub(crate) const TIMESTAMP_HEADER: &str = "x-sandbox-agent-timestamp";
+pub(crate) const SIGNATURE_HEADER: &str = "x-sandbox-agent-signature";
+
+#[derive(Debug)]
+pub(crate) struct Webhooks {
+    urls: Vec<String>,
+    secret: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub(crate) fn from_env() -> Self {
+        let urls: Vec<String> = std::env::var(WEBHOOK_URLS_ENV)
+            .ok()
+            .map(|raw| {
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|url| {
+                        let valid = validate_url(url).is_ok();
+                        if !url.is_empty() && !valid {
+                            tracing::warn!(url = url, "webhooks: ignoring invalid URL");
+                        }
+                        valid
+                    })
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let secret = std::env::var(WEBHOOK_SECRET_ENV)
+            .ok()
+            .filter(|secret| !secret.is_empty());
+        if secret.is_none() && !urls.is_empty() {
+            tracing::warn!("webhooks: {WEBHOOK_SECRET_ENV} is not set; deliveries are unsigned");
+        }
+        Self {
+            urls,
+            secret,
+            client: client_builder().build().unwrap_or_default(),
+        }
+    }
+
+    /// Start delivering events for one ACP server to the global URLs plus `server_url`.
+    /// Returns `None` when there is nowhere to deliver.
+    pub(crate) fn spawn_delivery(&self, server_url: Option<&str>) -> Option<WebhookQueue> {
+        let urls = self.urls.clone();
+        let server_url = server_url
+            .filter(|url| !urls.iter().any(|existing| existing == url))
+            .map(str::to_string);
+        if urls.is_empty() && server_url.is_none() {
+            return None;
+        }
+        let (sender, mut receiver) = mpsc::channel::<Value>(QUEUE_CAPACITY);
+        let client = self.client.clone();
+        let secret = self.secret.clone();
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                for url in &urls {
+                    deliver(&client, url, secret.as_deref(), &event).await;
+                }
+                if let Some(url) = &server_url {
+                    match public_client(url).await {
+                        Ok(client) => {
+                            deliver(&client, url, secret.as_deref(), &event).await;
+                        }
+                        Err(message) => {
+                            tracing::warn!(url = %url, "webhooks: delivery skipped: {message}")
+                        }
+                    }
+                }
+            }
+        });
+        Some(WebhookQueue {
+            sender,
+            ended: false,
+        })
+    }
+}
+
+/// Turns one server's ACP events into webhook events and queues them for delivery.
+#[derive(Debug)]
+pub(crate) struct WebhookQueue {
+    sender: mpsc::Sender<Value>,
+    /// A server reports `session.ended` once, even when the agent exits after a shutdown.
+    ended: bool,
+}
+
+impl WebhookQueue {
+    pub(crate) fn push(&mut self, server_id: &str, agent: AgentId, sequence: u64, message: &Value) {
+        let Some((event_type, session_id, data)) = classify(message) else {
+            return;
+        };
+        match event_type {
+            "session.started" => self.ended = false,
+            "session.ended" if self.ended => return,
+            "session.ended" => self.ended = true,
+            _ => {}
+        }
+        let event = json!({
+            "type": event_type,
+            "serverId": server_id,
+            "agent": agent.as_str(),
+            "sequence": sequence,
+            "sessionId": session_id,
+            "timestampMs": now_ms(),
+            "data": data,
+        });
+        if self.sender.try_send(event).is_err() {
+            tracing::warn!(
+                server_id = server_id,
+                event_type = event_type,
+                "webhooks: delivery queue full, dropping event"
+            );
+        }
+    }
+}
+
+/// Accept only absolute `http` and `https` URLs.
+pub(crate) fn validate_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        Ok(_) => Err(format!("webhook URL must use http or https: {url}")),
+        Err(err) => Err(format!("invalid webhook URL '{url}': {err}")),
+    }
+}
+
+/// Like [`validate_url`], and refuse hosts that are internal IP literals or `localhost`, for
+/// URLs a client chose.
+pub(crate) fn validate_server_url(url: &str) -> Result<(), String> {
+    validate_url(url)?;
+    let parsed = reqwest::Url::parse(url).map_err(|err| err.to_string())?;
+    let host = parsed.host_str().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let internal = match host.parse::<IpAddr>() {
+        Ok(ip) => !is_public(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.').to_ascii_lowercase();
+            domain.is_empty() || domain == "localhost" || domain.ends_with(".localhost")
+        }
+    };
+    if internal {
+        return Err(format!(
+            "webhook URL must point to a public address, not a loopback, private or link-local one: {url}"
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `ip` is reachable on the public internet, as opposed to loopback, private,
+/// link-local, shared, multicast or otherwise reserved space.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        // Shared address space (100.64.0.0/10) and reserved 240.0.0.0/4.
+        || (a == 100 && (64..128).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local (fc00::/7) and link-local (fe80::/10).
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+}
+
+/// A client for a client-chosen URL, pinned to the public addresses its host resolves to now,
+/// so the name cannot be pointed at an internal service after the URL was accepted.
+async fn public_client(url: &str) -> Result<reqwest::Client, String> {
+    validate_server_url(url)?;
+    let parsed = reqwest::Url::parse(url).map_err(|err| err.to_string())?;
+    let Some(host) = parsed.domain() else {
+        // An IP literal, already checked above.
+        return client_builder().build().map_err(|err| err.to_string());
+    };
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|err| format!("failed to resolve {host}: {err}"))?
+        .collect::<Vec<_>>();
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(format!("{host} does not resolve to a public address"));
+    }
+    client_builder()
+        .resolve_to_addrs(host, &addrs)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+/// Webhook event type, ACP session id, and data for ACP messages that trigger a webhook.
+fn classify(message: &Value) -> Option<(&'static str, Option<String>, Value)> {
+    let params = message.get("params");
+    let session_id = params
+        .and_then(|params| params.get("sessionId"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    match message.get("method").and_then(Value::as_str) {
+        Some("session/request_permission") => {
+            Some(("permission.requested", session_id, message.clone()))
+        }
+        Some("_sandboxagent/session/request_question") => {
+            Some(("question.requested", session_id, message.clone()))
+        }
+        Some("_sandboxagent/session/ended") => {
+            let params = params.cloned().unwrap_or_default();
+            let mut data = json!({
+                "reason": params.get("reason").cloned().unwrap_or(json!("terminated")),
+                "terminatedBy": params.get("terminated_by").cloned().unwrap_or(json!("daemon")),
+            });
+            if let Some(message) = params.get("message") {
+                data["message"] = message.clone();
+            }
+            Some(("session.ended", None, data))
+        }
+        Some("_adapter/agent_exited") => {
+            let success = params
+                .and_then(|params| params.get("success"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            let mut data = json!({
+                "reason": if success { "completed" } else { "error" },
+                "terminatedBy": "agent",
+            });
+            if !success {
+                data["exitCode"] = params
+                    .and_then(|params| params.get("code"))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+            }
+            Some(("session.ended", None, data))
+        }
+        Some("_adapter/invalid_stdout") => {
+            let error = params
+                .and_then(|params| params.get("error"))
+                .cloned()
+                .unwrap_or_default();
+            Some(("error", None, json!({ "message": error })))
+        }
+        Some(_) => None,
+        None => {
+            if let Some(error) = message.get("error") {
+                return Some(("error", None, error.clone()));
+            }
+            let session_id = message
+                .get("result")
+                .and_then(|result| result.get("sessionId"))
+                .and_then(Value::as_str)?;
+            Some((
+                "session.started",
+                Some(session_id.to_string()),
+                message.get("result").cloned().unwrap_or_default(),
+            ))
+        }
+    }
+}
+
+/// POST `event` to `url`, retrying server errors and rate limits. Returns whether it was
+/// accepted.
+pub(crate) async fn deliver(
+    client: &reqwest::Client,
+    url: &str,
+    secret: Option<&str>,
+    event: &Value,
+) -> bool {
+    let body = event.to_string();
+    let event_type = event["type"].as_str().unwrap_or_default();
+    for attempt in 1..=DELIVERY_ATTEMPTS {
+        let timestamp = now_ms() / 1000;
+        let mut request = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event_type)
+            .header(TIMESTAMP_HEADER, timestamp.to_string());
+        if let Some(secret) = secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, timestamp, &body));
+        }
+        let retry = match request.body(body.clone()).send().await {
+            Ok(response) if response.status().is_success() => return true,
+            Ok(response) => {
+                let status = response.status();
+                tracing::warn!(
+                    url = url,
+                    event_type = event_type,
+                    status = status.as_u16(),
+                    attempt = attempt,
+                    "webhooks: delivery rejected"
+                );
+                status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            Err(err) => {
+                tracing::warn!(
+                    url = url,
+                    event_type = event_type,
+                    attempt = attempt,
+                    error = %err,
+                    "webhooks: delivery failed"
+                );
+                true
+            }
+        };
+        if !retry || attempt == DELIVERY_ATTEMPTS {
+            return false;
+        }
+        tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(attempt - 1)).await;
+    }
+    false
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `{timestamp}.{body}`.
+pub(crate) fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_permission_and_question_requests() {
+        let permission = json!({
+            "jsonrpc": "2.0",
+            "id": 4,
+            "method": "session/request_permission",
+            "params": { "sessionId": "s-1", "toolCall": { "kind": "execute" } },
+        });
+        let (event_type, session_id, data) = classify(&permission).expect("permission event");
+        assert_eq!(event_type, "permission.requested");
+        assert_eq!(session_id.as_deref(), Some("s-1"));
+        assert_eq!(data, permission);
+
+        let question = json!({
+            "jsonrpc": "2.0",
+            "id": 5,
+            "method": "_sandboxagent/session/request_question",
+            "params": { "sessionId": "s-2", "question": "Which branch?" },
+        });
+        let (event_type, session_id, data) = classify(&question).expect("question event");
+        assert_eq!(event_type, "question.requested");
+        assert_eq!(session_id.as_deref(), Some("s-2"));
+        assert_eq!(data, question);
+
+        let update = json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": "s-1" },
+        });
+        assert!(classify(&update).is_none());
+    }
+
+    #[test]
+    fn server_urls_must_be_public() {
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "http://api.localhost./hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.1.2.3/hook",
+            "http://172.16.0.1/hook",
+            "http://192.168.1.1/hook",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "ftp://example.com/hook",
+        ] {
+            assert!(validate_server_url(url).is_err(), "{url}");
+        }
+        for url in [
+            "https://hooks.example.com/sandbox",
+            "http://93.184.216.34/hook",
+            "https://[2606:4700::1111]/hook",
+        ] {
+            assert!(validate_server_url(url).is_ok(), "{url}");
+        }
+    }
+}
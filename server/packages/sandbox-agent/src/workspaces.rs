@@ -0,0 +1,315 @@
+//! Workspaces behind `/v1/workspaces`: project directories, registered in place or cloned from
+//! git, that ACP servers are started in with `workspaceId`.
+//!
+//! The registry is `workspaces.json` in the workspaces directory (`--workspaces-dir`, by default
+//! `sandbox-agent/workspaces` in the data directory), which also holds the clones of
+//! repositories registered without a `path`. Each directory belongs to at most one workspace.
+//! Deleting a workspace removes clones in the workspaces directory and leaves every other
+//! directory on disk.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sandbox_agent_api_types::{WorkspaceCreateRequest, WorkspaceInfo};
+use sandbox_agent_error::SandboxError;
+use tokio::sync::Mutex;
+
+use crate::router::{map_fs_error, now_ms};
+use crate::scm_github;
+
+const REGISTRY_FILE: &str = "workspaces.json";
+const GIT_URL_PREFIXES: [&str; 4] = ["https://", "http://", "ssh://", "git://"];
+
+static WORKSPACE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn default_workspaces_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("sandbox-agent").join("workspaces"))
+        .unwrap_or_else(|| PathBuf::from(".").join(".sandbox-agent").join("workspaces"))
+}
+
+#[derive(Debug)]
+pub(crate) struct WorkspaceRegistry {
+    dir: PathBuf,
+    /// Serializes registrations and removals.
+    lock: Mutex<()>,
+}
+
+impl WorkspaceRegistry {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn list(&self) -> Result<Vec<WorkspaceInfo>, SandboxError> {
+        let path = self.dir.join(REGISTRY_FILE);
+        let raw = match fs::read(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(map_fs_error(&path, err)),
+        };
+        serde_json::from_slice(&raw).map_err(|err| SandboxError::StreamError {
+            message: format!("invalid workspace registry {}: {err}", path.display()),
+        })
+    }
+
+    pub(crate) fn get(&self, id: &str) -> Result<WorkspaceInfo, SandboxError> {
+        self.list()?
+            .into_iter()
+            .find(|workspace| workspace.id == id)
+            .ok_or_else(|| not_found(id))
+    }
+
+    /// Register `request` as a new workspace. `path` is the request's path, already resolved
+    /// and created; without a `gitUrl` it is required.
+    pub(crate) async fn create(
+        &self,
+        request: &WorkspaceCreateRequest,
+        path: Option<PathBuf>,
+    ) -> Result<WorkspaceInfo, SandboxError> {
+        let id = match request.id.as_deref() {
+            Some(id) => {
+                validate_id(id)?;
+                id.to_string()
+            }
+            None => next_workspace_id(),
+        };
+        if request.git_url.is_none() && request.git_ref.is_some() {
+            return Err(SandboxError::InvalidRequest {
+                message: "ref requires gitUrl".to_string(),
+            });
+        }
+        if let Some(url) = request.git_url.as_deref() {
+            validate_git_url(url)?;
+        }
+        if request
+            .git_ref
+            .as_deref()
+            .is_some_and(|git_ref| git_ref.starts_with('-'))
+        {
+            return Err(SandboxError::InvalidRequest {
+                message: "ref may not start with '-'".to_string(),
+            });
+        }
+
+        let _guard = self.lock.lock().await;
+        let mut workspaces = self.list()?;
+        if workspaces.iter().any(|workspace| workspace.id == id) {
+            return Err(SandboxError::Conflict {
+                message: format!("workspace '{id}' already exists"),
+            });
+        }
+        let managed = path.is_none();
+        let path = match path {
+            Some(path) => path,
+            None if request.git_url.is_some() => self.dir.join(&id),
+            None => {
+                return Err(SandboxError::InvalidRequest {
+                    message: "a workspace needs a path or a gitUrl".to_string(),
+                })
+            }
+        };
+        let display = path.display().to_string();
+        if let Some(existing) = workspaces
+            .iter()
+            .find(|workspace| workspace.path == display)
+        {
+            return Err(SandboxError::Conflict {
+                message: format!("{display} is already workspace '{}'", existing.id),
+            });
+        }
+
+        let commit = match request.git_url.as_deref() {
+            Some(url) => Some(clone(url, request.git_ref.as_deref(), &path, managed).await?),
+            None => None,
+        };
+        let workspace = WorkspaceInfo {
+            id,
+            path: display,
+            git_url: request.git_url.clone(),
+            git_ref: request.git_ref.clone(),
+            commit,
+            managed,
+            created_at_ms: now_ms(),
+        };
+        workspaces.push(workspace.clone());
+        self.write(&workspaces)?;
+        Ok(workspace)
+    }
+
+    /// Unregister `id`, removing its clone when it lives in the workspaces directory.
+    pub(crate) async fn remove(&self, id: &str) -> Result<(), SandboxError> {
+        let _guard = self.lock.lock().await;
+        let mut workspaces = self.list()?;
+        let index = workspaces
+            .iter()
+            .position(|workspace| workspace.id == id)
+            .ok_or_else(|| not_found(id))?;
+        let workspace = workspaces.remove(index);
+        self.write(&workspaces)?;
+        if workspace.managed {
+            if let Err(err) = fs::remove_dir_all(&workspace.path) {
+                tracing::warn!(
+                    workspace = id,
+                    path = %workspace.path,
+                    error = %err,
+                    "workspaces: failed to remove clone"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, workspaces: &[WorkspaceInfo]) -> Result<(), SandboxError> {
+        fs::create_dir_all(&self.dir).map_err(|err| map_fs_error(&self.dir, err))?;
+        let path = self.dir.join(REGISTRY_FILE);
+        let staged = self.dir.join(format!("{REGISTRY_FILE}.tmp"));
+        let body =
+            serde_json::to_vec_pretty(workspaces).map_err(|err| SandboxError::StreamError {
+                message: err.to_string(),
+            })?;
+        fs::write(&staged, body).map_err(|err| map_fs_error(&staged, err))?;
+        fs::rename(&staged, &path).map_err(|err| map_fs_error(&path, err))
+    }
+}
+
+/// Clone `url` into `target`, which must be missing or empty, and check out `git_ref`. On
+/// failure `target` is left as it was found. Returns the checked out commit.
+async fn clone(
+    url: &str,
+    git_ref: Option<&str>,
+    target: &Path,
+    managed: bool,
+) -> Result<String, SandboxError> {
+    let empty = fs::read_dir(target)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(true);
+    if !empty {
+        return Err(SandboxError::Conflict {
+            message: format!("{} is not empty", target.display()),
+        });
+    }
+    fs::create_dir_all(target).map_err(|err| map_fs_error(target, err))?;
+    let token = url
+        .starts_with("https://github.com/")
+        .then(|| scm_github::token(None))
+        .flatten();
+    let cloned = async {
+        scm_github::git(target, &["clone", "--quiet", url, "."], token.as_deref()).await?;
+        if let Some(git_ref) = git_ref {
+            scm_github::git(target, &["checkout", "--quiet", git_ref], None).await?;
+        }
+        scm_github::git(target, &["rev-parse", "HEAD"], None).await
+    }
+    .await;
+    if cloned.is_err() {
+        let _ = fs::remove_dir_all(target);
+        if !managed {
+            let _ = fs::create_dir_all(target);
+        }
+    }
+    cloned
+}
+
+fn validate_id(id: &str) -> Result<(), SandboxError> {
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(SandboxError::InvalidRequest {
+            message: format!("invalid workspace id '{id}'"),
+        })
+    }
+}
+
+/// Remote URLs only; local paths and `file://` could clone from outside `--fs-root`.
+fn validate_git_url(url: &str) -> Result<(), SandboxError> {
+    let scp_like = url
+        .split_once(':')
+        .is_some_and(|(host, path)| host.contains('@') && !path.starts_with("//"));
+    let valid = !url.starts_with('-')
+        && (GIT_URL_PREFIXES
+            .iter()
+            .any(|prefix| url.starts_with(prefix))
+            || scp_like);
+    if valid {
+        Ok(())
+    } else {
+        Err(SandboxError::InvalidRequest {
+            message: format!(
+                "unsupported gitUrl '{url}'; expected https://, http://, ssh://, git:// or user@host:path"
+            ),
+        })
+    }
+}
+
+fn next_workspace_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    let n = WORKSPACE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("ws-{millis}-{n}")
+}
+
+fn not_found(id: &str) -> SandboxError {
+    SandboxError::SessionNotFound {
+        session_id: format!("workspace:{id}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_urls_must_be_remote() {
+        assert!(validate_git_url("https://github.com/rivet-dev/sandbox-agent.git").is_ok());
+        assert!(validate_git_url("git@github.com:rivet-dev/sandbox-agent.git").is_ok());
+        assert!(validate_git_url("ssh://git@example.com/repo.git").is_ok());
+        assert!(validate_git_url("file:///etc").is_err());
+        assert!(validate_git_url("/srv/repo").is_err());
+        assert!(validate_git_url("--upload-pack=touch").is_err());
+    }
+
+    #[tokio::test]
+    async fn directories_belong_to_one_workspace() {
+        let root = tempfile::tempdir().expect("tempdir");
+        let registry = WorkspaceRegistry::new(root.path().join("registry"));
+        let project = root.path().join("project");
+        fs::create_dir_all(&project).expect("project dir");
+
+        let request = WorkspaceCreateRequest {
+            id: Some("app".to_string()),
+            ..WorkspaceCreateRequest::default()
+        };
+        let created = registry
+            .create(&request, Some(project.clone()))
+            .await
+            .expect("register");
+        assert!(!created.managed);
+        assert_eq!(registry.get("app").expect("get").path, created.path);
+
+        let duplicate = WorkspaceCreateRequest {
+            id: Some("other".to_string()),
+            ..WorkspaceCreateRequest::default()
+        };
+        assert!(matches!(
+            registry.create(&duplicate, Some(project.clone())).await,
+            Err(SandboxError::Conflict { .. })
+        ));
+
+        registry.remove("app").await.expect("remove");
+        assert!(project.is_dir());
+        assert!(registry.list().expect("list").is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 use std::fs;
 use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
 use axum::body::Body;
@@ -9,7 +10,12 @@ use axum::http::{header, HeaderMap, Method, Request, StatusCode};
 use axum::Router;
 use futures::StreamExt;
 use http_body_util::BodyExt;
-use sandbox_agent::router::{build_router, AppState, AuthConfig};
+use sandbox_agent::auth_tokens::parse_tokens;
+use sandbox_agent::request_limits::RequestLimits;
+use sandbox_agent::router::{
+    build_router, build_router_with_state, load_persisted_state, prewarm_servers, shutdown_servers,
+    AppState, AuthConfig,
+};
 use sandbox_agent_agent_management::agents::AgentManager;
 use serde_json::{json, Value};
 use serial_test::serial;
@@ -32,8 +38,23 @@ impl TestApp {
     {
         let install_dir = tempfile::tempdir().expect("create temp install dir");
         setup(install_dir.path());
-        let manager = AgentManager::new(install_dir.path()).expect("create agent manager");
-        let state = AppState::new(auth, manager);
+        let manager = test_agent_manager(install_dir.path());
+        let state = AppState::new(auth, manager)
+            .with_checkpoint_dir(install_dir.path().join("checkpoints"))
+            .with_audit_log(install_dir.path().join("audit.jsonl"))
+            .with_workspaces_dir(install_dir.path().join("workspaces"));
+        let app = build_router(state);
+        Self { app, install_dir }
+    }
+
+    fn with_fs_roots(auth: AuthConfig, roots: Vec<PathBuf>) -> Self {
+        let install_dir = tempfile::tempdir().expect("create temp install dir");
+        let manager = test_agent_manager(install_dir.path());
+        let state = AppState::new(auth, manager)
+            .with_fs_roots(roots)
+            .with_checkpoint_dir(install_dir.path().join("checkpoints"))
+            .with_audit_log(install_dir.path().join("audit.jsonl"))
+            .with_workspaces_dir(install_dir.path().join("workspaces"));
         let app = build_router(state);
         Self { app, install_dir }
     }
@@ -43,6 +64,13 @@ impl TestApp {
     }
 }
 
+/// The test binary is not sandbox-agent, so run the mock agent from the built binary.
+fn test_agent_manager(install_dir: &Path) -> AgentManager {
+    AgentManager::new(install_dir)
+        .expect("create agent manager")
+        .with_mock_agent_program(env!("CARGO_BIN_EXE_sandbox-agent"))
+}
+
 struct EnvVarGuard {
     key: &'static str,
     previous: Option<std::ffi::OsString>,
@@ -72,6 +100,17 @@ impl Drop for EnvVarGuard {
     }
 }
 
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("run git");
+    assert!(output.status.success(), "git {args:?}: {output:?}");
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
 fn write_executable(path: &Path, script: &str) {
     fs::write(path, script).expect("write executable");
     #[cfg(unix)]
@@ -268,6 +307,48 @@ async fn read_first_sse_data_with_last_id(
     .expect("timed out reading sse")
 }
 
+async fn read_sse_events(
+    app: &Router,
+    server_id: &str,
+    last_event_id: Option<u64>,
+    count: usize,
+) -> Vec<(u64, Value)> {
+    let mut builder = Request::builder()
+        .method(Method::GET)
+        .uri(format!("/v1/acp/{server_id}"));
+    if let Some(last_event_id) = last_event_id {
+        builder = builder.header("last-event-id", last_event_id.to_string());
+    }
+    let request = builder.body(Body::empty()).expect("build request");
+
+    let response = app.clone().oneshot(request).await.expect("sse response");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut stream = response.into_body().into_data_stream();
+    tokio::time::timeout(Duration::from_secs(5), async move {
+        let mut buffer = String::new();
+        let mut events = Vec::new();
+        while events.len() < count {
+            let bytes = stream
+                .next()
+                .await
+                .expect("SSE stream ended early")
+                .expect("stream chunk");
+            buffer.push_str(&String::from_utf8_lossy(&bytes));
+            while let Some(end) = buffer.find("\n\n") {
+                let frame = buffer[..end].to_string();
+                buffer.drain(..end + 2);
+                if frame.contains("data:") {
+                    events.push((parse_sse_event_id(&frame), parse_sse_data(&frame)));
+                }
+            }
+        }
+        events
+    })
+    .await
+    .expect("timed out reading sse")
+}
+
 fn parse_sse_data(chunk: &str) -> Value {
     let data = chunk
         .lines()
@@ -104,6 +104,18 @@ async fn acp_round_trip_and_replay() {
         "session/prompt"
     );
 
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed = parse_json(&body);
+    let server = parsed["servers"]
+        .as_array()
+        .expect("servers array")
+        .iter()
+        .find(|server| server["serverId"] == "server-replay")
+        .cloned()
+        .expect("server-replay listed");
+    assert!(server["lastEventId"].as_u64().expect("lastEventId") >= 2);
+
     let first_chunk = read_first_sse_data_with_last_id(&test_app.app, "server-replay", 0).await;
     let first_event_id = parse_sse_event_id(&first_chunk);
     let first_event = parse_sse_data(&first_chunk);
@@ -117,191 +129,4682 @@ async fn acp_round_trip_and_replay() {
 
 #[cfg(unix)]
 #[tokio::test]
-async fn acp_agent_mismatch_returns_conflict() {
+async fn acp_bootstrap_directory_sets_cwd_and_scopes_fs() {
     let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
         setup_stub_artifacts(install_dir, "codex");
-        setup_stub_artifacts(install_dir, "claude");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  printf '%s\n' "$line" >> requests.log
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  if [ -n "$id" ]; then
+    printf '{"jsonrpc":"2.0","id":%s,"result":{"pwd":"%s"}}\n' "$id" "$(pwd)"
+  fi
+done
+"#,
+        );
     });
+    let root = tempfile::tempdir().expect("create root dir");
+    let work = root.path().join("work");
+    let work_str = work.to_string_lossy().to_string();
 
-    bootstrap_server(&test_app.app, "server-mismatch", "codex").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/acp/server-dir?agent=codex&directory={work_str}"),
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let work = fs::canonicalize(&work).expect("directory created");
+    assert_eq!(
+        parse_json(&body)["result"]["pwd"],
+        work.to_string_lossy().as_ref()
+    );
 
-    let request = json!({
+    let session_new = json!({
         "jsonrpc": "2.0",
         "id": 2,
         "method": "session/new",
-        "params": {}
+        "params": {"mcpServers": []}
     });
-    let (status, _, body) = send_request(
+    let (status, _, _) = send_request(
         &test_app.app,
         Method::POST,
-        "/v1/acp/server-mismatch?agent=claude",
-        Some(request),
+        "/v1/acp/server-dir",
+        Some(session_new),
         &[],
     )
     .await;
+    assert_eq!(status, StatusCode::OK);
 
-    assert_eq!(status, StatusCode::CONFLICT);
-    assert_eq!(parse_json(&body)["status"], 409);
-}
-
-#[tokio::test]
-async fn acp_get_unknown_returns_not_found() {
-    let test_app = TestApp::new(AuthConfig::disabled());
-
-    let (status, _, body) =
-        send_request(&test_app.app, Method::GET, "/v1/acp/missing", None, &[]).await;
-    assert_eq!(status, StatusCode::NOT_FOUND);
-    assert_eq!(parse_json(&body)["status"], 404);
-}
-
-#[tokio::test]
-async fn acp_delete_is_idempotent() {
-    let test_app = TestApp::new(AuthConfig::disabled());
-
-    let (status, _, _) = send_request(
+    let (status, _, body) = send_request_raw(
         &test_app.app,
-        Method::DELETE,
-        "/v1/acp/server-delete",
+        Method::GET,
+        "/v1/fs/file?serverId=server-dir&path=requests.log",
         None,
         &[],
+        None,
     )
     .await;
-    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(status, StatusCode::OK);
+    let requests = String::from_utf8_lossy(&body)
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).expect("request json"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        requests[1]["params"]["cwd"],
+        work.to_string_lossy().as_ref()
+    );
 
     let (status, _, _) = send_request(
         &test_app.app,
-        Method::DELETE,
-        "/v1/acp/server-delete",
+        Method::GET,
+        "/v1/fs/stat?serverId=server-dir&path=/etc",
         None,
         &[],
     )
     .await;
-    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(status, StatusCode::FORBIDDEN);
 
-    let request = json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "initialize",
-        "params": {
-            "protocolVersion": "1.0",
-            "clientCapabilities": {}
-        }
-    });
-    let (status, _, body) = send_request(
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body)["servers"][0]["directory"],
+        work.to_string_lossy().as_ref()
+    );
+
+    let other = root.path().join("other").to_string_lossy().to_string();
+    let (status, _, _) = send_request(
         &test_app.app,
         Method::POST,
-        "/v1/acp/server-delete",
-        Some(request),
+        &format!("/v1/acp/server-dir?agent=codex&directory={other}"),
+        Some(initialize_payload()),
         &[],
     )
     .await;
-    assert_eq!(status, StatusCode::BAD_REQUEST);
-    assert_eq!(parse_json(&body)["status"], 400);
+    assert_eq!(status, StatusCode::CONFLICT);
 }
 
 #[cfg(unix)]
 #[tokio::test]
-async fn acp_list_servers_returns_active_instances() {
+async fn events_sse_multiplexes_servers_with_filters() {
     let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
         setup_stub_artifacts(install_dir, "codex");
     });
 
-    bootstrap_server(&test_app.app, "server-1", "codex").await;
-    bootstrap_server(&test_app.app, "server-2", "codex").await;
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/events/sse?agents=nope",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
 
-    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/acp", None, &[]).await;
-    assert_eq!(status, StatusCode::OK);
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/v1/events/sse?agents=codex&types=server/echo")
+        .body(Body::empty())
+        .expect("build request");
+    let response = test_app
+        .app
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("sse response");
+    assert_eq!(response.status(), StatusCode::OK);
 
-    let parsed = parse_json(&body);
-    let servers = parsed["servers"].as_array().expect("servers array");
-    assert!(servers
-        .iter()
-        .any(|server| server["serverId"] == "server-1"));
-    assert!(servers
+    bootstrap_server(&test_app.app, "firehose-a", "codex").await;
+    bootstrap_server(&test_app.app, "firehose-b", "codex").await;
+
+    let mut stream = response.into_body().into_data_stream();
+    let events = tokio::time::timeout(Duration::from_secs(5), async move {
+        let mut buffer = String::new();
+        let mut events = Vec::new();
+        while events.len() < 2 {
+            let bytes = stream
+                .next()
+                .await
+                .expect("SSE stream ended early")
+                .expect("stream chunk");
+            buffer.push_str(&String::from_utf8_lossy(&bytes));
+            while let Some(end) = buffer.find("\n\n") {
+                let frame = buffer[..end].to_string();
+                buffer.drain(..end + 2);
+                if frame.contains("data:") {
+                    events.push(parse_sse_data(&frame));
+                }
+            }
+        }
+        events
+    })
+    .await
+    .expect("timed out reading firehose");
+
+    let mut servers = events
         .iter()
-        .any(|server| server["serverId"] == "server-2"));
+        .map(|event| event["serverId"].as_str().expect("server id").to_string())
+        .collect::<Vec<_>>();
+    servers.sort();
+    assert_eq!(servers, vec!["firehose-a", "firehose-b"]);
+    for event in &events {
+        assert_eq!(event["agent"], "codex");
+        assert_eq!(event["type"], "server/echo");
+        assert_eq!(event["message"]["method"], "server/echo");
+        assert!(event["sequence"]
+            .as_u64()
+            .is_some_and(|sequence| sequence >= 1));
+    }
+}
+
+async fn read_firehose_frames(app: &Router, last_event_id: u64, count: usize) -> Vec<String> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/v1/events/sse")
+        .header("last-event-id", last_event_id.to_string())
+        .body(Body::empty())
+        .expect("build request");
+    let response = app.clone().oneshot(request).await.expect("sse response");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut stream = response.into_body().into_data_stream();
+    tokio::time::timeout(Duration::from_secs(5), async move {
+        let mut buffer = String::new();
+        let mut frames = Vec::new();
+        while frames.len() < count {
+            let bytes = stream
+                .next()
+                .await
+                .expect("SSE stream ended early")
+                .expect("stream chunk");
+            buffer.push_str(&String::from_utf8_lossy(&bytes));
+            while let Some(end) = buffer.find("\n\n") {
+                frames.push(buffer[..end].to_string());
+                buffer.drain(..end + 2);
+            }
+        }
+        frames
+    })
+    .await
+    .expect("timed out reading firehose")
 }
 
 #[cfg(unix)]
 #[tokio::test]
-async fn sandboxagent_methods_are_not_handled_specially() {
+async fn events_sse_resumes_after_last_event_id() {
     let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
         setup_stub_artifacts(install_dir, "codex");
     });
 
-    bootstrap_server(&test_app.app, "server-ext", "codex").await;
+    bootstrap_server(&test_app.app, "resume-a", "codex").await;
+    bootstrap_server(&test_app.app, "resume-b", "codex").await;
 
-    let request = json!({
-        "jsonrpc": "2.0",
-        "id": 22,
-        "method": "_sandboxagent/session/list",
-        "params": {}
-    });
+    // The stream opens with a reconnect hint, then every kept event after id 0.
+    let frames = read_firehose_frames(&test_app.app, 0, 3).await;
+    assert!(frames[0].starts_with("retry:"), "{}", frames[0]);
+    let first = parse_sse_event_id(&frames[1]);
+    let second = parse_sse_event_id(&frames[2]);
+    assert_eq!(second, first + 1);
+
+    // Reconnecting with the first id resumes exactly at the second event.
+    let resumed = read_firehose_frames(&test_app.app, first, 2).await;
+    assert_eq!(parse_sse_event_id(&resumed[1]), second);
+    assert_eq!(parse_sse_data(&resumed[1]), parse_sse_data(&frames[2]));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn acp_events_jsonl_flags_events_dropped_from_the_buffer() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "server-long", "mock").await;
     let (status, _, body) = send_request(
         &test_app.app,
         Method::POST,
-        "/v1/acp/server-ext",
-        Some(request),
+        "/v1/acp/server-long",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": { "cwd": "/", "mcpServers": [] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"].clone();
+    let script = (0..1100)
+        .map(|n| json!({ "type": "message", "text": format!("chunk {n}") }))
+        .collect::<Vec<_>>();
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-long",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "go" }],
+                "_meta": { "sandboxagent.dev": { "mockScript": script } }
+            }
+        })),
         &[],
     )
     .await;
+    assert_eq!(status, StatusCode::OK);
 
+    let (status, headers, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-long/events.jsonl",
+        None,
+        &[],
+        None,
+    )
+    .await;
     assert_eq!(status, StatusCode::OK);
+    let lines = String::from_utf8_lossy(&body)
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).expect("json line"))
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1024);
+    let first = lines[0]["sequence"].as_u64().expect("sequence");
+    assert!(first > 1);
     assert_eq!(
-        parse_json(&body)["result"]["echoedMethod"],
-        "_sandboxagent/session/list"
+        headers
+            .get("x-sandbox-agent-first-sequence")
+            .and_then(|value| value.to_str().ok()),
+        Some(first.to_string().as_str())
+    );
+    assert_eq!(
+        headers
+            .get("x-sandbox-agent-truncated")
+            .and_then(|value| value.to_str().ok()),
+        Some("true")
     );
 }
 
 #[tokio::test]
-async fn post_requires_json_content_type() {
-    let test_app = TestApp::new(AuthConfig::disabled());
-    let payload = br#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"1.0","clientCapabilities":{}}}"#
-        .to_vec();
-    let (status, _, body) = send_request_raw(
+async fn acp_events_jsonl_exports_buffered_events() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+
+    bootstrap_server(&test_app.app, "server-export", "codex").await;
+    let prompt = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "session/prompt",
+        "params": {
+            "sessionId": "s-1",
+            "prompt": [{"type": "text", "text": "hello"}]
+        }
+    });
+    let (status, _, _) = send_request(
         &test_app.app,
         Method::POST,
-        "/v1/acp/server-content?agent=mock",
-        Some(payload),
+        "/v1/acp/server-export",
+        Some(prompt),
         &[],
-        Some("text/plain"),
     )
     .await;
-    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
-    assert_eq!(parse_json(&body)["status"], 415);
-}
+    assert_eq!(status, StatusCode::OK);
 
-#[tokio::test]
-async fn sse_rejects_non_sse_accept() {
-    let test_app = TestApp::new(AuthConfig::disabled());
+    let parse_lines = |body: &[u8]| {
+        String::from_utf8_lossy(body)
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).expect("json line"))
+            .collect::<Vec<_>>()
+    };
 
-    let (status, _, body) = send_request(
+    let (status, headers, body) = send_request_raw(
         &test_app.app,
         Method::GET,
-        "/v1/acp/server-a",
+        "/v1/acp/server-export/events.jsonl",
+        None,
+        &[],
         None,
-        &[("accept", "application/json")],
     )
     .await;
-    assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
-    assert_eq!(parse_json(&body)["status"], 406);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+        Some("application/x-ndjson")
+    );
+    let events = parse_lines(&body);
+    assert!(events.len() >= 2);
+    assert!(events
+        .windows(2)
+        .all(|pair| pair[0]["sequence"].as_u64() < pair[1]["sequence"].as_u64()));
+    assert_eq!(events[0]["message"]["method"], "server/echo");
+
+    let first = events[0]["sequence"].as_u64().expect("sequence");
+    assert_eq!(
+        headers
+            .get("x-sandbox-agent-first-sequence")
+            .and_then(|value| value.to_str().ok()),
+        Some(first.to_string().as_str())
+    );
+    assert!(!headers.contains_key("x-sandbox-agent-truncated"));
+    let (status, _, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/acp/server-export/events.jsonl?after={first}"),
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_lines(&body).len(), events.len() - 1);
+
+    let (status, headers, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-export/events.jsonl",
+        None,
+        &[("accept-encoding", "gzip")],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers
+            .get(header::CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok()),
+        Some("gzip")
+    );
+    let mut decoded = Vec::new();
+    flate2::read::GzDecoder::new(body.as_slice())
+        .read_to_end(&mut decoded)
+        .expect("decode gzip");
+    assert_eq!(parse_lines(&decoded), events);
+
+    let (status, _, _) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/missing/events.jsonl",
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
 }
 
 #[tokio::test]
-async fn invalid_last_event_id_returns_bad_request() {
+async fn acp_events_ndjson_streams_live_events_until_the_server_ends() {
     let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "server-ndjson", "mock").await;
     let (status, _, body) = send_request(
         &test_app.app,
-        Method::GET,
-        "/v1/acp/server-a",
+        Method::POST,
+        "/v1/acp/server-ndjson",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": { "cwd": "/", "mcpServers": [] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/v1/acp/server-ndjson/events/ndjson?offset=1")
+        .body(Body::empty())
+        .expect("build request");
+    let response = test_app
+        .app
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("ndjson response");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+        Some("application/x-ndjson")
+    );
+    let reader = tokio::spawn(tokio::time::timeout(
+        Duration::from_secs(5),
+        response.into_body().collect(),
+    ));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-ndjson",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "hello" }]
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/server-ndjson",
         None,
-        &[("last-event-id", "not-a-number")],
+        &[],
     )
     .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
 
-    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let body = reader
+        .await
+        .expect("reader task")
+        .expect("stream ends with the server")
+        .expect("collect body")
+        .to_bytes();
+    let events: Vec<Value> = String::from_utf8_lossy(&body)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("json line"))
+        .collect();
+    let sequences: Vec<u64> = events
+        .iter()
+        .filter_map(|event| event["sequence"].as_u64())
+        .collect();
+    assert_eq!(sequences, (2..2 + events.len() as u64).collect::<Vec<_>>());
+    assert_eq!(events[0]["message"]["id"], 2);
+    assert!(events
+        .iter()
+        .any(|event| event["message"]["result"]["stopReason"] == "end_turn"));
     assert_eq!(
-        parse_json(&body)["detail"],
-        "invalid request: Last-Event-ID must be a positive integer"
+        events.last().expect("events")["message"]["method"],
+        "_sandboxagent/session/ended"
     );
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-ndjson/events/ndjson",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn acp_event_streams_filter_by_type_kind_and_origin() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "server-filter", "mock").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-filter",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": { "cwd": "/", "mcpServers": [] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/v1/acp/server-filter/events/ndjson?excludeSynthetic=true")
+        .body(Body::empty())
+        .expect("build request");
+    let response = test_app
+        .app
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("ndjson response");
+    assert_eq!(response.status(), StatusCode::OK);
+    let reader = tokio::spawn(tokio::time::timeout(
+        Duration::from_secs(5),
+        response.into_body().collect(),
+    ));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-filter",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "hello" }]
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let parse_lines = |body: &[u8]| {
+        String::from_utf8_lossy(body)
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).expect("json line"))
+            .collect::<Vec<_>>()
+    };
+    let (status, _, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-filter/events.jsonl?types=response",
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let responses = parse_lines(&body);
+    assert_eq!(responses.len(), 3);
+    assert!(responses
+        .iter()
+        .all(|event| event["message"].get("method").is_none()));
+
+    // `kinds` drops other session updates but leaves the responses alone.
+    let (status, _, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-filter/events.jsonl?kinds=tool_call",
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_lines(&body), responses);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/server-filter",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // The stream still ends with the server, without its `_sandboxagent/session/ended`.
+    let body = reader
+        .await
+        .expect("reader task")
+        .expect("stream ends with the server")
+        .expect("collect body")
+        .to_bytes();
+    let events = parse_lines(&body);
+    assert!(events.len() > responses.len());
+    assert!(events
+        .iter()
+        .all(|event| event["message"]["method"] != "_sandboxagent/session/ended"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn acp_sse_reconnect_delivers_each_sequence_once() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+
+    bootstrap_server(&test_app.app, "server-reconnect", "codex").await;
+    for id in 2..=4 {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": "s-1",
+                "prompt": [{"type": "text", "text": "hello"}]
+            }
+        });
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/server-reconnect",
+            Some(request),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // Each request yields an echo notification plus its broadcast response.
+    let full = read_sse_events(&test_app.app, "server-reconnect", None, 8).await;
+    let ids = full.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+
+    for resume_after in [0, 3, 7] {
+        let resumed = read_sse_events(
+            &test_app.app,
+            "server-reconnect",
+            Some(resume_after),
+            8 - resume_after as usize,
+        )
+        .await;
+        assert_eq!(resumed.as_slice(), &full[resume_after as usize..]);
+    }
+
+    // A reconnected subscriber sees replayed and live events exactly once, in order.
+    let app = test_app.app.clone();
+    let reader =
+        tokio::spawn(async move { read_sse_events(&app, "server-reconnect", Some(6), 4).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 5,
+        "method": "session/prompt",
+        "params": {
+            "sessionId": "s-1",
+            "prompt": [{"type": "text", "text": "again"}]
+        }
+    });
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-reconnect",
+        Some(request),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let live = reader.await.expect("reader task");
+    let ids = live.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![7, 8, 9, 10]);
+    assert_eq!(live[2].1["method"], "server/echo");
+    assert_eq!(live[3].1["id"], 5);
+}
+
+#[tokio::test]
+async fn acp_sse_subscriber_that_lags_is_resynced_from_the_buffer() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "server-lag", "mock").await;
+    // More updates than the live broadcast holds, but fewer than the replay buffer.
+    let script = (0..700)
+        .map(|index| json!({ "type": "message", "text": format!("chunk {index}") }))
+        .collect::<Vec<_>>();
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-lag",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": {
+                "cwd": "/",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": { "mockScript": script } }
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+
+    // Subscribe, then leave the stream unread while the whole turn is broadcast.
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/v1/acp/server-lag")
+        .header("last-event-id", "2")
+        .body(Body::empty())
+        .expect("build request");
+    let response = test_app
+        .app
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("sse response");
+    assert_eq!(response.status(), StatusCode::OK);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-lag",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "go" }]
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut stream = response.into_body().into_data_stream();
+    let mut text = String::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !text.contains("\"id\":3") {
+            let chunk = stream.next().await.expect("stream open").expect("chunk");
+            text.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    })
+    .await
+    .expect("timed out reading sse");
+
+    let mut ids = Vec::new();
+    let mut resyncs = Vec::new();
+    for frame in text.split("\n\n") {
+        let field = |name: &str| {
+            frame
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .map(str::trim)
+        };
+        match field("event:") {
+            Some("stream.resync") => {
+                resyncs.push(serde_json::from_str::<Value>(field("data:").unwrap()).unwrap())
+            }
+            Some("message") => ids.push(field("id:").unwrap().parse::<u64>().unwrap()),
+            _ => {}
+        }
+    }
+    assert_eq!(resyncs.len(), 1, "one resync marker: {resyncs:?}");
+    assert_eq!(resyncs[0]["missed"], 0);
+    assert_eq!(ids, (3..3 + ids.len() as u64).collect::<Vec<_>>());
+    assert!(ids.len() > 700);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn acp_agent_mismatch_returns_conflict() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        setup_stub_artifacts(install_dir, "claude");
+    });
+
+    bootstrap_server(&test_app.app, "server-mismatch", "codex").await;
+
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "session/new",
+        "params": {}
+    });
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-mismatch?agent=claude",
+        Some(request),
+        &[],
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(parse_json(&body)["status"], 409);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn agent_uninstall_refuses_active_servers_then_removes_artifacts() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+
+    bootstrap_server(&test_app.app, "uninstall-server", "codex").await;
+    let (status, _, body) =
+        send_request(&test_app.app, Method::DELETE, "/v1/agents/codex", None, &[]).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(parse_json(&body)["detail"]
+        .as_str()
+        .expect("detail")
+        .contains("uninstall-server"));
+    assert!(test_app.install_path().join("codex").exists());
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/uninstall-server",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::DELETE, "/v1/agents/codex", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body)["removed"]
+            .as_array()
+            .expect("removed")
+            .len(),
+        2
+    );
+    assert!(!test_app.install_path().join("codex").exists());
+    assert!(!test_app
+        .install_path()
+        .join("agent_processes/codex-acp")
+        .exists());
+
+    let (status, _, _) =
+        send_request(&test_app.app, Method::DELETE, "/v1/agents/nope", None, &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn acp_turn_snapshots_record_workspace_diffs() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+turns=0
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *session/prompt*)
+      turns=$((turns + 1))
+      if [ "$turns" = 1 ]; then
+        printf 'two\n' >> notes.txt
+        printf 'fresh\n' > new.txt
+      else
+        rm -f new.txt
+      fi
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id"
+      ;;
+    *)
+      if [ -n "$id" ]; then
+        printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id"
+      fi
+      ;;
+  esac
+done
+"#,
+        );
+    });
+    let workspace = tempfile::tempdir().expect("create workspace");
+    let root = fs::canonicalize(workspace.path()).expect("canonical workspace");
+    fs::write(root.join("notes.txt"), "one\n").expect("write notes");
+
+    let prompt = |id: u64, uri: String| {
+        let app = test_app.app.clone();
+        async move {
+            let payload = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "session/prompt",
+                "params": {
+                    "sessionId": "s-1",
+                    "prompt": [{"type": "text", "text": "edit"}]
+                }
+            });
+            let (status, _, _) = send_request(&app, Method::POST, &uri, Some(payload), &[]).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+    };
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!(
+            "/v1/acp/turns-server?agent=codex&snapshotTurns=true&directory={}",
+            root.display()
+        ),
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    prompt(2, "/v1/acp/turns-server".to_string()).await;
+    prompt(3, "/v1/acp/turns-server".to_string()).await;
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/turns-server/turns",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let turns = parse_json(&body)["turns"].clone();
+    assert_eq!(turns.as_array().expect("turns array").len(), 2);
+    assert_eq!(turns[0]["turnId"], 1);
+    assert_eq!(turns[0]["sessionId"], "s-1");
+    assert_eq!(turns[0]["root"], root.to_string_lossy().as_ref());
+    assert_eq!(
+        turns[0]["files"],
+        json!([
+            {"path": "new.txt", "change": "added"},
+            {"path": "notes.txt", "change": "modified"},
+        ])
+    );
+    assert_eq!(
+        turns[1]["files"],
+        json!([{"path": "new.txt", "change": "deleted"}])
+    );
+
+    let (status, headers, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/turns-server/turns/1/diff",
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/x-diff")));
+    let diff = String::from_utf8(body).expect("utf8 diff");
+    assert!(diff.contains("--- /dev/null\n+++ b/new.txt\n"), "{diff}");
+    assert!(diff.contains("+fresh\n"), "{diff}");
+    assert!(
+        diff.contains("--- a/notes.txt\n+++ b/notes.txt\n"),
+        "{diff}"
+    );
+    assert!(diff.contains(" one\n+two\n"), "{diff}");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/turns-server/turns/9/diff",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Without the bootstrap flag only prompts that ask for a snapshot are recorded.
+    let other = tempfile::tempdir().expect("create workspace");
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!(
+            "/v1/acp/plain-server?agent=codex&directory={}",
+            other.path().display()
+        ),
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    prompt(2, "/v1/acp/plain-server".to_string()).await;
+    prompt(3, "/v1/acp/plain-server?snapshotTurns=true".to_string()).await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/plain-server/turns",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let turns = parse_json(&body)["turns"].clone();
+    assert_eq!(turns.as_array().expect("turns array").len(), 1);
+    assert_eq!(
+        turns[0]["files"],
+        json!([{"path": "new.txt", "change": "deleted"}])
+    );
+}
+
+#[tokio::test]
+async fn acp_usage_aggregates_prompt_usage_and_cost() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+turns=0
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *session/prompt*)
+      turns=$((turns + 1))
+      printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":{"sessionUpdate":"usage_update","used":100,"size":1000,"cost":{"amount":0.0%s,"currency":"USD"}}}}\n' "$turns"
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn","usage":{"inputTokens":10,"outputTokens":5,"cachedReadTokens":2,"totalTokens":17}}}\n' "$id"
+      ;;
+    *)
+      if [ -n "$id" ]; then
+        printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id"
+      fi
+      ;;
+  esac
+done
+"#,
+        );
+    });
+
+    bootstrap_server(&test_app.app, "usage-server", "codex").await;
+    for id in [2, 3] {
+        let prompt = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": "s-1",
+                "prompt": [{"type": "text", "text": "hello"}]
+            }
+        });
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/usage-server",
+            Some(prompt),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let usage = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, _, body) = send_request(
+                &test_app.app,
+                Method::GET,
+                "/v1/acp/usage-server/usage",
+                None,
+                &[],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let usage = parse_json(&body);
+            if usage["turns"] == 2 && usage["cost"][0]["amount"] == 0.02 {
+                return usage;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("usage aggregated");
+    assert_eq!(usage["inputTokens"], 20);
+    assert_eq!(usage["outputTokens"], 10);
+    assert_eq!(usage["cachedReadTokens"], 4);
+    assert_eq!(usage["totalTokens"], 34);
+    assert_eq!(usage["cost"][0]["currency"], "USD");
+
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed = parse_json(&body);
+    let server = parsed["servers"]
+        .as_array()
+        .expect("servers array")
+        .iter()
+        .find(|server| server["serverId"] == "usage-server")
+        .cloned()
+        .expect("usage-server listed");
+    assert_eq!(server["usage"]["inputTokens"], 20);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/missing/usage",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn acp_fork_seeds_history_into_new_server() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+
+    bootstrap_server(&test_app.app, "fork-source", "codex").await;
+    let prompt = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "session/prompt",
+        "params": {
+            "sessionId": "s-1",
+            "prompt": [{"type": "text", "text": "hello"}]
+        }
+    });
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/fork-source",
+        Some(prompt),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let source_events = read_sse_events(&test_app.app, "fork-source", Some(0), 4).await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/fork-source/fork",
+        Some(json!({ "serverId": "fork-target", "upToSequence": source_events[1].0 })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let fork = parse_json(&body);
+    assert_eq!(fork["serverId"], "fork-target");
+    assert_eq!(fork["agent"], "codex");
+    assert_eq!(fork["forkedFrom"], "fork-source");
+    assert_eq!(fork["historyEvents"], 2);
+    assert_eq!(fork["lastEventId"], 2);
+
+    let forked_events = read_sse_events(&test_app.app, "fork-target", Some(0), 2).await;
+    let forked_payloads = forked_events
+        .iter()
+        .map(|(_, payload)| payload.clone())
+        .collect::<Vec<_>>();
+    let source_payloads = source_events[..2]
+        .iter()
+        .map(|(_, payload)| payload.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(forked_payloads, source_payloads);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/fork-target",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["echoedMethod"], "initialize");
+    let next = read_sse_events(&test_app.app, "fork-target", Some(2), 1).await;
+    assert!(next[0].0 > 2);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/fork-source/fork",
+        Some(json!({ "serverId": "fork-target" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/fork-source/fork",
+        Some(json!({ "serverId": "fork-other", "upToSequence": 1000 })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/missing/fork",
+        Some(json!({ "serverId": "fork-other" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn acp_get_unknown_returns_not_found() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/acp/missing", None, &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(parse_json(&body)["status"], 404);
+}
+
+#[tokio::test]
+async fn acp_delete_is_idempotent() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/server-delete",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/server-delete",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "1.0",
+            "clientCapabilities": {}
+        }
+    });
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-delete",
+        Some(request),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(parse_json(&body)["status"], 400);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn acp_list_servers_returns_active_instances() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+
+    bootstrap_server(&test_app.app, "server-1", "codex").await;
+    bootstrap_server(&test_app.app, "server-2", "codex").await;
+
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let parsed = parse_json(&body);
+    let servers = parsed["servers"].as_array().expect("servers array");
+    assert!(servers
+        .iter()
+        .any(|server| server["serverId"] == "server-1"));
+    assert!(servers
+        .iter()
+        .any(|server| server["serverId"] == "server-2"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn acp_idle_servers_are_shut_down() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-idle?agent=codex&idleTimeoutMs=200",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    bootstrap_server(&test_app.app, "server-kept", "codex").await;
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed = parse_json(&body);
+    let servers = parsed["servers"].as_array().expect("servers array");
+    assert!(!servers
+        .iter()
+        .any(|server| server["serverId"] == "server-idle"));
+    assert!(servers
+        .iter()
+        .any(|server| server["serverId"] == "server-kept"));
+
+    let (status, _, _) =
+        send_request(&test_app.app, Method::GET, "/v1/acp/server-idle", None, &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn acp_bootstrap_rejects_negative_cpu_limit() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-limits?agent=mock&cpuLimit=-1",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(parse_json(&body)["detail"]
+        .as_str()
+        .is_some_and(|detail| detail.contains("cpuLimit")));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-limits",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn acp_bootstrap_rejects_unknown_sandbox_level() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-sandbox?agent=mock&sandboxLevel=paranoid",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(parse_json(&body)["detail"]
+        .as_str()
+        .is_some_and(|detail| detail.contains("sandboxLevel")));
+}
+
+#[tokio::test]
+async fn acp_bootstrap_rejects_invalid_egress_rules() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-egress?agent=mock&egressDeny=10.0.0.0/40",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(parse_json(&body)["detail"]
+        .as_str()
+        .is_some_and(|detail| detail.contains("egressDeny")));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+#[serial]
+async fn acp_webhooks_deliver_session_ended_and_refuse_internal_urls() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind webhook receiver");
+    let address = listener.local_addr().expect("webhook address");
+    let (sender, receiver) = std::sync::mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        while let Ok((mut stream, _)) = listener.accept() {
+            let mut buffer = [0_u8; 8192];
+            let read = stream.read(&mut buffer).unwrap_or(0);
+            let _ = stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+            let _ = sender.send(String::from_utf8_lossy(&buffer[..read]).into_owned());
+        }
+    });
+
+    // Operator-configured URLs may be internal; a client-chosen `webhookUrl` may not.
+    let _urls = EnvVarGuard::set(
+        "SANDBOX_AGENT_WEBHOOK_URLS",
+        &format!("http://{address}/hook"),
+    );
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    for webhook_url in [
+        "ftp://example.com".to_string(),
+        format!("http://{address}/hook"),
+        "http://localhost/hook".to_string(),
+        "http://169.254.169.254/latest/meta-data".to_string(),
+        "http://10.0.0.1/hook".to_string(),
+        "http://[::1]/hook".to_string(),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            &format!("/v1/acp/server-hooked?agent=codex&webhookUrl={webhook_url}"),
+            Some(initialize_payload()),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{webhook_url}");
+    }
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-hooked?agent=codex",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/server-hooked",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let delivery = tokio::task::spawn_blocking(move || {
+        receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("webhook delivered")
+    })
+    .await
+    .expect("join webhook receiver");
+    assert!(delivery.starts_with("POST /hook "));
+    assert!(delivery.contains("x-sandbox-agent-event: session.ended"));
+    let body = delivery.split("\r\n\r\n").nth(1).expect("webhook body");
+    let event = parse_json(body.as_bytes());
+    assert_eq!(event["serverId"], "server-hooked");
+    assert_eq!(event["data"]["reason"], "terminated");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn session_new_hooks_are_validated_and_limited_to_claude() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "claude");
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    let session_new = |hooks: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/new",
+            "params": {
+                "cwd": "/tmp",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": { "hooks": hooks } }
+            }
+        })
+    };
+    let guard = json!({
+        "PreToolUse": [
+            { "matcher": "Bash", "hooks": [{ "type": "command", "command": "/opt/guard.sh" }] }
+        ]
+    });
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-hooks-codex?agent=codex",
+        Some(session_new(guard.clone())),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(parse_json(&body)["detail"]
+        .as_str()
+        .expect("detail")
+        .contains("only supported on claude"));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-hooks-claude?agent=claude",
+        Some(session_new(json!({ "BeforeEverything": [] }))),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-hooks-claude?agent=claude",
+        Some(session_new(guard)),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["echoedMethod"], "session/new");
+}
+
+#[tokio::test]
+async fn session_new_agent_config_is_validated_per_agent() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "claude");
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    let session_new = |ours: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/new",
+            "params": {
+                "cwd": "/tmp",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": ours }
+            }
+        })
+    };
+    let settings = json!({
+        "model": "claude-sonnet-4",
+        "env": { "DEBUG": "1" },
+        "permissions": { "deny": ["Bash(rm:*)"] }
+    });
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-config-codex?agent=codex",
+        Some(session_new(json!({ "agentConfig": settings.clone() }))),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(parse_json(&body)["detail"]
+        .as_str()
+        .expect("detail")
+        .contains("/v1/agents/codex/config"));
+
+    for invalid in [
+        json!({ "agentConfig": { "theme": "dark" } }),
+        json!({ "agentConfig": { "env": { "DEBUG": 1 } } }),
+        json!({ "agentConfig": { "hooks": {} }, "hooks": {} }),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/server-config-claude?agent=claude",
+            Some(session_new(invalid)),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-config-claude?agent=claude",
+        Some(session_new(json!({
+            "agentConfig": settings,
+            "hooks": { "Stop": [{ "hooks": [{ "type": "command", "command": "/opt/done.sh" }] }] }
+        }))),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["echoedMethod"], "session/new");
+}
+
+#[tokio::test]
+async fn session_new_variant_is_codex_only() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "claude");
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    let session_new = |variant: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/new",
+            "params": {
+                "cwd": "/tmp",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": { "variant": variant } }
+            }
+        })
+    };
+
+    for (server, variant) in [
+        ("server-variant-claude?agent=claude", json!("high")),
+        ("server-variant-codex?agent=codex", json!("extreme")),
+        ("server-variant-codex?agent=codex", json!(3)),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            &format!("/v1/acp/{server}"),
+            Some(session_new(variant)),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    // The stub reports no models, so the session is created without the variant.
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-variant-codex?agent=codex",
+        Some(session_new(json!("high"))),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed = parse_json(&body);
+    assert_eq!(parsed["result"]["echoedMethod"], "session/new");
+    assert!(parsed["result"]["_meta"]["sandboxagent.dev"]["variantError"].is_string());
+}
+
+#[tokio::test]
+async fn session_new_streaming_is_claude_only() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "claude");
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    let session_new = |streaming: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/new",
+            "params": {
+                "cwd": "/tmp",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": { "streaming": streaming } }
+            }
+        })
+    };
+
+    for (server, streaming) in [
+        ("server-streaming-codex?agent=codex", json!("tokens")),
+        ("server-streaming-claude?agent=claude", json!("words")),
+        ("server-streaming-claude?agent=claude", json!(true)),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            &format!("/v1/acp/{server}"),
+            Some(session_new(streaming)),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-streaming-claude?agent=claude",
+        Some(session_new(json!("messages"))),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["echoedMethod"], "session/new");
+}
+
+#[tokio::test]
+async fn session_new_system_prompt_is_claude_only() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "claude");
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    let session_new = |ours: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/new",
+            "params": {
+                "cwd": "/tmp",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": ours }
+            }
+        })
+    };
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-prompt-codex?agent=codex",
+        Some(session_new(
+            json!({ "appendSystemPrompt": "Never push to main." }),
+        )),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(parse_json(&body)["detail"]
+        .as_str()
+        .expect("detail")
+        .contains("developerInstructions"));
+
+    for invalid in [
+        json!({ "systemPrompt": "" }),
+        json!({ "appendSystemPrompt": ["Never push to main."] }),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/server-prompt-claude?agent=claude",
+            Some(session_new(invalid)),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-prompt-claude?agent=claude",
+        Some(session_new(json!({
+            "systemPrompt": "You are a release assistant.",
+            "appendSystemPrompt": "Never push to main."
+        }))),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["echoedMethod"], "session/new");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn session_prompt_overrides_apply_for_one_turn() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *session/new*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"sessionId":"s-1","models":{"currentModelId":"gpt-5.3-codex/medium","availableModels":[]},"modes":{"currentModeId":"auto","availableModes":[]}}}\n' "$id"
+      ;;
+    *session/set_model*|*session/set_mode*)
+      printf '%s\n' "$line" | sed -n 's/.*"mode\(l\)*Id":"\([^"]*\)".*/\2/p' >> switches.log
+      printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id"
+      ;;
+    *session/prompt*)
+      printf 'prompt\n' >> switches.log
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id"
+      ;;
+    *)
+      if [ -n "$id" ]; then
+        printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id"
+      fi
+      ;;
+  esac
+done
+"#,
+        );
+    });
+    let workspace = tempfile::tempdir().expect("create workspace");
+    let root = fs::canonicalize(workspace.path()).expect("canonical workspace");
+    let url = format!(
+        "/v1/acp/turn-server?agent=codex&directory={}",
+        root.display()
+    );
+    let prompt = |id: u64, ours: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": "s-1",
+                "prompt": [{ "type": "text", "text": "think hard" }],
+                "_meta": { "sandboxagent.dev": ours }
+            }
+        })
+    };
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &url,
+        Some(prompt(1, json!({ "model": "gpt-5.2" }))),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &url,
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": { "cwd": root, "mcpServers": [] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        &url,
+        Some(prompt(
+            3,
+            json!({ "variant": "high", "agentMode": "read-only" }),
+        )),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["stopReason"], "end_turn");
+    assert_eq!(
+        fs::read_to_string(root.join("switches.log")).expect("read switches"),
+        "gpt-5.3-codex/high\nread-only\nprompt\ngpt-5.3-codex/medium\nauto\n"
+    );
+}
+
+#[tokio::test]
+async fn plan_mode_is_approved_by_switching_modes() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/new"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"sessionId":"s-1","modes":{"currentModeId":"plan","availableModes":[]}}}\n' "$id" ;;
+    *'"session/set_mode"'*)
+      printf '%s\n' "$line" | sed -n 's/.*"modeId":"\([^"]*\)".*/\1/p' >> modes.log
+      printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let workspace = tempfile::tempdir().expect("create workspace");
+    let root = fs::canonicalize(workspace.path()).expect("canonical workspace");
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!(
+            "/v1/acp/plan-server?agent=codex&directory={}",
+            root.display()
+        ),
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/new",
+            "params": { "cwd": root, "mcpServers": [] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let approve = "/v1/acp/plan-server/sessions/s-1/plan/approve";
+    let (status, _, _) =
+        send_request(&test_app.app, Method::POST, approve, Some(json!({})), &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        approve,
+        Some(json!({ "mode": "auto" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(
+        fs::read_to_string(root.join("modes.log")).expect("read modes"),
+        "auto\n"
+    );
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/plan-server/sessions/s-1/plan/reject",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/plan-server/events.jsonl",
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let methods = String::from_utf8_lossy(&body)
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).expect("json line"))
+        .filter_map(|event| event["message"]["method"].as_str().map(str::to_string))
+        .filter(|method| method.starts_with("_sandboxagent/plan/"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        methods,
+        ["_sandboxagent/plan/started", "_sandboxagent/plan/approved"]
+    );
+}
+
+#[tokio::test]
+async fn session_tasks_follow_the_agent_todo_list() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/prompt"'*)
+      printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":{"sessionUpdate":"plan","entries":[{"content":"Read the code","priority":"high","status":"completed"},{"content":"Fix the bug","priority":"high","status":"in_progress"}]}}}\n'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/task-server?agent=codex",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/prompt",
+            "params": { "sessionId": "s-1", "prompt": [{ "type": "text", "text": "fix it" }] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let tasks = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, _, body) = send_request(
+                &test_app.app,
+                Method::GET,
+                "/v1/acp/task-server/sessions/s-1/tasks",
+                None,
+                &[],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let tasks = parse_json(&body);
+            if tasks["sequence"].is_u64() {
+                return tasks;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("tasks recorded");
+    assert_eq!(
+        tasks["tasks"],
+        json!([
+            { "id": "1", "text": "Read the code", "status": "completed" },
+            { "id": "2", "text": "Fix the bug", "status": "in_progress" }
+        ])
+    );
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/task-server/sessions/s-2/tasks",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body),
+        json!({ "sessionId": "s-2", "tasks": [] })
+    );
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/missing/sessions/s-1/tasks",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn subagent_tool_calls_are_listed_under_their_task() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+update() {
+  printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":%s}}\n' "$1"
+}
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/prompt"'*)
+      update '{"sessionUpdate":"tool_call","toolCallId":"task-1","title":"Explore","status":"in_progress","rawInput":{"subagent_type":"explore"}}'
+      update '{"sessionUpdate":"tool_call","toolCallId":"read-1","title":"Read README.md","kind":"read"}'
+      update '{"sessionUpdate":"tool_call_update","toolCallId":"task-1","status":"completed"}'
+      update '{"sessionUpdate":"tool_call","toolCallId":"edit-1","title":"Edit README.md","kind":"edit"}'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/subagent-server?agent=codex",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/prompt",
+            "params": { "sessionId": "s-1", "prompt": [{ "type": "text", "text": "explore" }] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let url = "/v1/acp/subagent-server/sessions/s-1/items/task-1/children";
+    let children = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, _, body) = send_request(&test_app.app, Method::GET, url, None, &[]).await;
+            if status == StatusCode::OK {
+                let children = parse_json(&body);
+                if children["item"]["status"] == "completed" {
+                    return children;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("sub-agent recorded");
+    assert_eq!(children["item"]["agentType"], "explore");
+    let children = children["children"].as_array().expect("children");
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0]["toolCallId"], "read-1");
+    assert_eq!(children[0]["kind"], "read");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/subagent-server/sessions/s-1/items/missing/children",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn session_items_fold_the_transcript() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+update() {
+  printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":%s}}\n' "$1"
+}
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/prompt"'*)
+      update '{"sessionUpdate":"agent_thought_chunk","content":{"type":"text","text":"Checking"}}'
+      update '{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"Running "}}'
+      update '{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"tests"}}'
+      update '{"sessionUpdate":"tool_call","toolCallId":"task-1","title":"Explore","status":"in_progress","rawInput":{"subagent_type":"explore"}}'
+      update '{"sessionUpdate":"tool_call","toolCallId":"run-1","title":"cargo test","kind":"execute","rawInput":{"command":"cargo test"}}'
+      update '{"sessionUpdate":"tool_call_update","toolCallId":"run-1","status":"completed"}'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/item-server?agent=codex",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/prompt",
+            "params": { "sessionId": "s-1", "prompt": [{ "type": "text", "text": "run the tests" }] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let items = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, _, body) = send_request(
+                &test_app.app,
+                Method::GET,
+                "/v1/acp/item-server/sessions/s-1/items",
+                None,
+                &[],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let items = parse_json(&body)["items"].clone();
+            if items
+                .as_array()
+                .and_then(|items| items.last())
+                .is_some_and(|item| item["status"] == "completed")
+            {
+                return items;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("items recorded");
+    let kinds = items
+        .as_array()
+        .expect("items")
+        .iter()
+        .map(|item| item["kind"].as_str().unwrap_or_default())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            "user_message",
+            "reasoning",
+            "agent_message",
+            "tool_call",
+            "tool_call"
+        ]
+    );
+    assert_eq!(items[0]["text"], "run the tests");
+    assert_eq!(items[2]["text"], "Running tests");
+    assert_eq!(items[2]["status"], "completed");
+    assert_eq!(items[4]["itemId"], "run-1");
+    assert_eq!(items[4]["parentToolCallId"], "task-1");
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/item-server/sessions/s-1/items/run-1",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let item = parse_json(&body);
+    assert_eq!(item["toolKind"], "execute");
+    assert_eq!(item["rawInput"], json!({ "command": "cargo test" }));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/item-server/sessions/s-1/items/missing",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn session_turns_group_items_by_prompt() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/prompt"'*)
+      printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"Done"}}}}\n'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn","usage":{"totalTokens":12}}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    for (id, text) in [(1, "first"), (2, "second")] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/turn-server?agent=codex",
+            Some(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "session/prompt",
+                "params": { "sessionId": "s-1", "prompt": [{ "type": "text", "text": text }] }
+            })),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let turns = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, _, body) = send_request(
+                &test_app.app,
+                Method::GET,
+                "/v1/acp/turn-server/sessions/s-1/turns",
+                None,
+                &[],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let turns = parse_json(&body)["turns"].clone();
+            let done = turns.as_array().is_some_and(|turns| {
+                turns.len() == 2 && turns.iter().all(|turn| turn["status"] == "completed")
+            });
+            if done {
+                return turns;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("turns recorded");
+    assert_eq!(turns[0]["turnId"], "turn-1");
+    assert_eq!(turns[0]["stopReason"], "end_turn");
+    assert_eq!(turns[0]["usage"], json!({ "totalTokens": 12 }));
+    assert!(turns[0]["endedAtMs"].is_i64());
+    let texts = |turn: &Value| {
+        turn["items"]
+            .as_array()
+            .expect("items")
+            .iter()
+            .map(|item| item["text"].as_str().unwrap_or_default().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(texts(&turns[0]), ["first", "Done"]);
+    assert_eq!(texts(&turns[1]), ["second", "Done"]);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/turn-server/sessions/s-1/turns/turn-2",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["promptItemId"], "prompt-2");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/turn-server/sessions/s-1/turns/turn-3",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn session_transcript_exports_in_each_format() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+update() {
+  printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":%s}}\n' "$1"
+}
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/prompt"'*)
+      update '{"sessionUpdate":"tool_call","toolCallId":"edit-1","title":"Edit a.txt","kind":"edit","status":"completed","content":[{"type":"diff","path":"a.txt","oldText":"old\n","newText":"new\n"}]}'
+      update '{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"Done <3"}}'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/export-server?agent=codex",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/prompt",
+            "params": { "sessionId": "s-1", "prompt": [{ "type": "text", "text": "edit a.txt" }] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let markdown = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, headers, body) = send_request(
+                &test_app.app,
+                Method::GET,
+                "/v1/acp/export-server/sessions/s-1/export",
+                None,
+                &[],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(
+                headers.get(header::CONTENT_DISPOSITION).unwrap(),
+                "attachment; filename=\"s-1.md\""
+            );
+            let markdown = String::from_utf8_lossy(&body).into_owned();
+            if markdown.contains("Done <3") {
+                return markdown;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("transcript recorded");
+    assert!(markdown.starts_with("# Session `s-1`\n\n**User**\n\nedit a.txt\n"));
+    assert!(markdown.contains("**Tool call:** Edit a.txt (edit, completed)"));
+    assert!(markdown.contains("```diff\n--- a/a.txt\n+++ b/a.txt\n@@ -1 +1 @@\n-old\n+new\n```"));
+
+    let (status, headers, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/export-server/sessions/s-1/export?format=html",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers.get(header::CONTENT_TYPE).unwrap(),
+        "text/html; charset=utf-8"
+    );
+    assert!(String::from_utf8_lossy(&body).contains("<pre>Done &lt;3</pre>"));
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/export-server/sessions/s-1/export?format=json",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let export = parse_json(&body);
+    assert_eq!(export["items"][1]["itemId"], "edit-1");
+    assert_eq!(export["items"][2]["text"], "Done <3");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/export-server/sessions/s-1/export?format=pdf",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn session_findings_export_as_sarif_and_junit() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+reply() {
+  printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"%s"}}}}\n' "$1"
+}
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/prompt"'*JSON\ Schema*)
+      reply '{\"findings\": [{\"message\": \"SQL injection\", \"severity\": \"high\", \"path\": \"src/db.rs\", \"line\": 40, \"ruleId\": \"sqli\"}]}'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *'"session/prompt"'*)
+      reply 'Also:\n[warning] src/main.rs:12: unwrap on user input'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    for (id, meta) in [
+        (
+            1,
+            json!({ "sandboxagent.dev": { "outputSchema": { "type": "object" } } }),
+        ),
+        (2, json!({})),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/findings-server?agent=codex",
+            Some(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "session/prompt",
+                "params": {
+                    "sessionId": "s-1",
+                    "prompt": [{ "type": "text", "text": "review the change" }],
+                    "_meta": meta
+                }
+            })),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let url = "/v1/acp/findings-server/sessions/s-1/export?format=sarif";
+    let sarif = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, headers, body) =
+                send_request(&test_app.app, Method::GET, url, None, &[]).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(
+                headers.get(header::CONTENT_TYPE).unwrap(),
+                "application/sarif+json"
+            );
+            let sarif = parse_json(&body);
+            if sarif["runs"][0]["results"].as_array().map(Vec::len) == Some(2) {
+                return sarif;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("findings recorded");
+    let results = &sarif["runs"][0]["results"];
+    assert_eq!(sarif["version"], "2.1.0");
+    assert_eq!(results[0]["ruleId"], "sqli");
+    assert_eq!(results[0]["level"], "error");
+    assert_eq!(
+        results[1]["locations"][0]["physicalLocation"],
+        json!({
+            "artifactLocation": { "uri": "src/main.rs" },
+            "region": { "startLine": 12, "endLine": 12 }
+        })
+    );
+
+    let (status, headers, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/findings-server/sessions/s-1/export?format=junit",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers.get(header::CONTENT_DISPOSITION).unwrap(),
+        "attachment; filename=\"s-1.xml\""
+    );
+    let report = String::from_utf8_lossy(&body);
+    assert!(report.contains("tests=\"2\" failures=\"2\""));
+    assert!(report.contains("src/db.rs:40: SQL injection</failure>"));
+}
+
+/// Answer one GitHub API request with `reply`, handing the request line, headers and body to
+/// the returned channel.
+fn serve_github_once(
+    status: &'static str,
+    reply: Value,
+) -> (String, std::sync::mpsc::Receiver<(String, Value)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind github server");
+    let address = listener.local_addr().expect("github address");
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let Ok((mut stream, _)) = listener.accept() else {
+            return;
+        };
+        let mut request = Vec::new();
+        let mut buffer = [0_u8; 4096];
+        let (head, body) = loop {
+            let read = stream.read(&mut buffer).expect("read github request");
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length || read == 0 {
+                    break (head.to_string(), body.to_string());
+                }
+            }
+        };
+        let reply = reply.to_string();
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+            reply.len()
+        );
+        let _ = stream.write_all(response.as_bytes());
+        let _ = sender.send((head, serde_json::from_str(&body).unwrap_or(Value::Null)));
+    });
+    (format!("http://{address}"), receiver)
+}
+
+#[tokio::test]
+#[serial]
+async fn github_clone_and_pull_request_from_a_session() {
+    let remotes = tempfile::tempdir().expect("create remotes");
+    let seed = tempfile::tempdir().expect("create seed");
+    let bare = remotes.path().join("octo/widget.git");
+    fs::create_dir_all(&bare).expect("create bare repo dir");
+    git(&bare, &["init", "--quiet", "--bare", "-b", "main"]);
+    git(seed.path(), &["init", "--quiet", "-b", "main"]);
+    fs::write(seed.path().join("README.md"), "widget\n").expect("write readme");
+    git(seed.path(), &["add", "README.md"]);
+    git(seed.path(), &["commit", "--quiet", "-m", "init"]);
+    git(
+        seed.path(),
+        &["push", "--quiet", &bare.to_string_lossy(), "main"],
+    );
+
+    let (api_url, requests) = serve_github_once(
+        "201 Created",
+        json!({ "number": 7, "html_url": "https://github.com/octo/widget/pull/7" }),
+    );
+    let _github = EnvVarGuard::set(
+        "SANDBOX_AGENT_GITHUB_URL",
+        &format!("file://{}", remotes.path().display()),
+    );
+    let _api = EnvVarGuard::set("SANDBOX_AGENT_GITHUB_API_URL", &api_url);
+    let _token = EnvVarGuard::set("GITHUB_TOKEN", "test-token");
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/prompt"'*)
+      printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"Added a changelog."}}}}\n'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let workspace = tempfile::tempdir().expect("create workspace");
+    let work = workspace.path().join("widget");
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/scm/github/clone",
+        Some(json!({ "repo": "octo/widget", "path": work.to_string_lossy() })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let clone = parse_json(&body);
+    assert_eq!(clone["commit"], git(&bare, &["rev-parse", "main"]));
+    assert!(work.join("README.md").exists());
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/scm/github/clone",
+        Some(json!({ "repo": "octo/widget", "path": work.to_string_lossy() })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!(
+            "/v1/acp/pr-server?agent=codex&directory={}",
+            work.to_string_lossy()
+        ),
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": "s-1",
+                "prompt": [{ "type": "text", "text": "Add a changelog\n\nKeep it short." }]
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (_, _, body) = send_request(
+                &test_app.app,
+                Method::GET,
+                "/v1/acp/pr-server/sessions/s-1/items",
+                None,
+                &[],
+            )
+            .await;
+            if parse_json(&body)["items"].as_array().map(Vec::len) == Some(2) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("agent message recorded");
+
+    let pr_url = "/v1/acp/pr-server/sessions/s-1/scm/github/pr";
+    let (status, _, _) =
+        send_request(&test_app.app, Method::POST, pr_url, Some(json!({})), &[]).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    fs::write(work.join("CHANGELOG.md"), "- widget\n").expect("write changelog");
+    let (status, _, body) =
+        send_request(&test_app.app, Method::POST, pr_url, Some(json!({})), &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let pull_request = parse_json(&body);
+    assert_eq!(pull_request["repo"], "octo/widget");
+    assert_eq!(pull_request["number"], 7);
+    assert_eq!(pull_request["branch"], "sandbox-agent/s-1");
+    assert_eq!(pull_request["base"], "main");
+    assert_eq!(
+        pull_request["commit"],
+        git(&bare, &["rev-parse", "sandbox-agent/s-1"])
+    );
+
+    let (head, request) = requests
+        .recv_timeout(Duration::from_secs(5))
+        .expect("pull request created");
+    assert!(head.starts_with("POST /repos/octo/widget/pulls "));
+    assert!(head
+        .to_ascii_lowercase()
+        .contains("authorization: bearer test-token"));
+    assert_eq!(request["title"], "Add a changelog");
+    assert_eq!(request["head"], "sandbox-agent/s-1");
+    assert_eq!(request["base"], "main");
+    let summary = request["body"].as_str().unwrap_or_default();
+    assert!(summary.starts_with("## Summary\n\nAdded a changelog.\n"));
+    assert!(summary.contains("> Keep it short.\n"));
+}
+
+#[tokio::test]
+async fn session_prompt_inline_attachments_are_written_to_the_workspace() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    let workspace = tempfile::tempdir().expect("create workspace");
+    let prompt = |id: u64, attachment: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": "s-1",
+                "prompt": [{ "type": "text", "text": "look" }, attachment]
+            }
+        })
+    };
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!(
+            "/v1/acp/server-inline?agent=codex&directory={}",
+            workspace.path().display()
+        ),
+        Some(prompt(
+            1,
+            json!({
+                "type": "resource_link",
+                "name": "shot.png",
+                "mimeType": "image/png",
+                "_meta": { "sandboxagent.dev": { "data": "aGVsbG8=" } }
+            }),
+        )),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let written = fs::read_dir(workspace.path().join(".sandbox-agent/attachments"))
+        .expect("attachments dir")
+        .map(|entry| entry.expect("entry").path())
+        .collect::<Vec<_>>();
+    assert_eq!(written.len(), 1);
+    assert!(written[0].to_string_lossy().ends_with("-shot.png"));
+    assert_eq!(fs::read(&written[0]).expect("read attachment"), b"hello");
+
+    for invalid in [
+        json!({ "type": "resource_link", "name": "a", "_meta": { "sandboxagent.dev": { "data": "%%%" } } }),
+        json!({
+            "type": "resource_link",
+            "name": "a",
+            "uri": "file:///etc/hosts",
+            "_meta": { "sandboxagent.dev": { "data": "aGk=" } }
+        }),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/server-inline",
+            Some(prompt(2, invalid)),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn large_image_content_is_moved_to_the_blob_store() {
+    let blob_dir = tempfile::tempdir().expect("create blob dir");
+    let test_app = {
+        let _dir = EnvVarGuard::set("SANDBOX_AGENT_BLOB_DIR", &blob_dir.path().to_string_lossy());
+        let _limit = EnvVarGuard::set("SANDBOX_AGENT_BLOB_INLINE_LIMIT_BYTES", "8");
+        TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+            setup_stub_artifacts(install_dir, "codex");
+            write_executable(
+                &install_dir.join("agent_processes/codex-acp"),
+                r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  if [ -n "$id" ]; then
+    printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"image","mimeType":"image/png","data":"aGVsbG8gd29ybGQ="},{"type":"image","mimeType":"image/png","data":"aGk="}]}}\n' "$id"
+  fi
+done
+"#,
+            );
+        })
+    };
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/blob-server?agent=codex",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let content = parse_json(&body)["result"]["content"].clone();
+    assert_eq!(content[0]["data"], "");
+    assert_eq!(content[0]["_meta"]["sandboxagent.dev"]["size"], 11);
+    assert_eq!(content[1]["data"], "aGk=");
+    let blob_url = content[0]["_meta"]["sandboxagent.dev"]["blobUrl"]
+        .as_str()
+        .expect("blob url")
+        .to_string();
+
+    let (status, headers, body) =
+        send_request(&test_app.app, Method::GET, &blob_url, None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+        Some("image/png")
+    );
+    assert_eq!(body, b"hello world".as_slice());
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/blobs/not-a-blob",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn acp_servers_carry_title_and_labels() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+
+    for (server_id, labels) in [
+        ("server-payments", "team=payments,env=dev"),
+        ("server-search", "team=search"),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            &format!("/v1/acp/{server_id}?agent=codex&title=Checkout&labels={labels}"),
+            Some(initialize_payload()),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp?label=team=payments",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let servers = parse_json(&body)["servers"].clone();
+    assert_eq!(servers.as_array().map(Vec::len), Some(1));
+    assert_eq!(servers[0]["serverId"], "server-payments");
+    assert_eq!(servers[0]["title"], "Checkout");
+    assert_eq!(
+        servers[0]["labels"],
+        json!({ "team": "payments", "env": "dev" })
+    );
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::PATCH,
+        "/v1/acp/server-payments",
+        Some(json!({ "title": "", "labels": { "env": null, "owner": "ana" } })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let server = parse_json(&body);
+    assert!(server.get("title").is_none());
+    assert_eq!(
+        server["labels"],
+        json!({ "team": "payments", "owner": "ana" })
+    );
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::PATCH,
+        "/v1/acp/server-payments",
+        Some(json!({ "labels": { "bad key": "x" } })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::PATCH,
+        "/v1/acp/missing",
+        Some(json!({ "title": "x" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) =
+        send_request(&test_app.app, Method::GET, "/v1/acp?label=team", None, &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn acp_server_list_pages_with_cursor() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    for server_id in ["server-a", "server-b", "server-c"] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            &format!("/v1/acp/{server_id}?agent=codex"),
+            Some(initialize_payload()),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let mut seen = Vec::new();
+    let mut uri = "/v1/acp?limit=2&order=desc".to_string();
+    loop {
+        let (status, _, body) = send_request(&test_app.app, Method::GET, &uri, None, &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        let page = parse_json(&body);
+        for server in page["servers"].as_array().expect("servers") {
+            seen.push(server["serverId"].as_str().expect("serverId").to_string());
+        }
+        match page["nextCursor"].as_str() {
+            Some(cursor) => uri = format!("/v1/acp?limit=2&order=desc&cursor={cursor}"),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ["server-c", "server-b", "server-a"]);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp?agent=claude",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["servers"], json!([]));
+
+    let (status, _, _) =
+        send_request(&test_app.app, Method::GET, "/v1/acp?cursor=nope", None, &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[serial]
+async fn deleted_servers_are_archived_read_only() {
+    let archive_dir = tempfile::tempdir().expect("create archive dir");
+    let test_app = {
+        let _dir = EnvVarGuard::set(
+            "SANDBOX_AGENT_ACP_ARCHIVE_DIR",
+            &archive_dir.path().to_string_lossy(),
+        );
+        TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+            setup_stub_artifacts(install_dir, "codex");
+        })
+    };
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-archived?agent=codex&labels=team=payments",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/server-archived",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp?archived=true&label=team=payments",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let servers = parse_json(&body)["servers"].clone();
+    assert_eq!(servers[0]["serverId"], "server-archived");
+    assert_eq!(servers[0]["archived"], true);
+    assert!(servers[0]["endedAtMs"].is_i64());
+
+    let (status, _, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-archived/events.jsonl",
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let last = String::from_utf8_lossy(&body)
+        .lines()
+        .last()
+        .map(|line| serde_json::from_str::<Value>(line).expect("event json"))
+        .expect("archived events");
+    assert_eq!(last["message"]["method"], "_sandboxagent/session/ended");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/server-archived",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-archived/events.jsonl",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn replay_sends_recorded_prompts_to_a_new_server() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+n=0
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/new"'*)
+      n=$((n+1)); printf '{"jsonrpc":"2.0","id":%s,"result":{"sessionId":"s-%s"}}\n' "$id" "$n" ;;
+    *'"session/prompt"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let prompt = |id: u64, text: &str| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "session/prompt",
+            "params": { "sessionId": "s-1", "prompt": [{ "type": "text", "text": text }] }
+        })
+    };
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/replay-source?agent=codex",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/replay-source/replay",
+        Some(json!({ "serverId": "replay-target" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    for (id, text) in [(1, "first"), (2, "second")] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/replay-source",
+            Some(prompt(id, text)),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/replay-source/replay",
+        Some(json!({ "serverId": "replay-target" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(parse_json(&body)["prompts"], 2);
+
+    let events = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, _, body) = send_request_raw(
+                &test_app.app,
+                Method::GET,
+                "/v1/acp/replay-target/events.jsonl",
+                None,
+                &[],
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let events = String::from_utf8_lossy(&body)
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(line).expect("event json"))
+                .map(|event| event["message"].clone())
+                .collect::<Vec<_>>();
+            if events
+                .iter()
+                .any(|event| event["method"] == "_sandboxagent/replay/completed")
+            {
+                return events;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("replay completed");
+    let progress = events
+        .iter()
+        .filter(|event| event["method"] == "_sandboxagent/replay/progress")
+        .collect::<Vec<_>>();
+    assert_eq!(progress.len(), 2);
+    assert_eq!(progress[1]["params"]["index"], 2);
+    assert_eq!(progress[1]["params"]["stopReason"], "end_turn");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/replay-source/replay",
+        Some(json!({ "serverId": "replay-target" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn fanout_runs_one_prompt_on_several_servers() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/new"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"sessionId":"s-1"}}\n' "$id" ;;
+    *'"session/prompt"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let request = json!({
+        "fanoutId": "race",
+        "prompt": [{ "type": "text", "text": "fix the failing test" }],
+        "runs": [{ "agent": "codex" }, { "agent": "codex", "serverId": "race-custom" }]
+    });
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/fanout",
+        Some(request.clone()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let body = parse_json(&body);
+    assert_eq!(body["runs"][0]["serverId"], "race-1");
+    assert_eq!(body["runs"][1]["serverId"], "race-custom");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/fanout",
+        Some(request),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let fanout = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, _, body) =
+                send_request(&test_app.app, Method::GET, "/v1/fanout/race", None, &[]).await;
+            assert_eq!(status, StatusCode::OK);
+            let fanout = parse_json(&body);
+            let done = fanout["runs"]
+                .as_array()
+                .expect("runs")
+                .iter()
+                .all(|run| run["status"] != "running");
+            if done {
+                return fanout;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("fanout finished");
+    for run in fanout["runs"].as_array().expect("runs") {
+        assert_eq!(run["status"], "completed", "{run}");
+        assert_eq!(run["sessionId"], "s-1");
+        assert_eq!(run["stopReason"], "end_turn");
+    }
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp?label=fanout=race",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body)["servers"].as_array().map(Vec::len),
+        Some(2)
+    );
+
+    let (status, _, _) =
+        send_request(&test_app.app, Method::DELETE, "/v1/fanout/race", None, &[]).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) =
+        send_request(&test_app.app, Method::GET, "/v1/fanout/race", None, &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn runs_wait_for_the_turn_and_report_the_result() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/new"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"sessionId":"s-1"}}\n' "$id" ;;
+    *'"session/prompt"'*)
+      case "$line" in *slow*) sleep 2 ;; esac
+      printf 'fixed\n' > notes.txt
+      printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"Done, "}}}}\n'
+      printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"tests pass."}}}}\n'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let workspace = tempfile::tempdir().expect("create workspace");
+    let root = fs::canonicalize(workspace.path()).expect("canonical workspace");
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/runs",
+        Some(json!({
+            "agent": "codex",
+            "serverId": "ci-run",
+            "directory": root.display().to_string(),
+            "prompt": [{ "type": "text", "text": "fix the failing test" }]
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let run = parse_json(&body);
+    assert_eq!(run["status"], "completed");
+    assert_eq!(run["sessionId"], "s-1");
+    assert_eq!(run["stopReason"], "end_turn");
+    assert_eq!(run["message"], "Done, tests pass.");
+    assert_eq!(run["items"].as_array().map(Vec::len), Some(2));
+    assert_eq!(run["diff"]["files"][0]["path"], "notes.txt");
+    assert_eq!(run["diff"]["files"][0]["change"], "added");
+    assert!(run["diff"]["diff"]
+        .as_str()
+        .is_some_and(|diff| diff.contains("+fixed")));
+
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["servers"], json!([]));
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/runs",
+        Some(json!({
+            "agent": "codex",
+            "serverId": "slow-run",
+            "timeoutMs": 200,
+            "keepServer": true,
+            "directory": root.display().to_string(),
+            "prompt": [{ "type": "text", "text": "slow" }]
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let run = parse_json(&body);
+    assert_eq!(run["status"], "timedOut");
+    assert!(run.get("diff").is_none());
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/runs",
+        Some(json!({
+            "agent": "codex",
+            "serverId": "slow-run",
+            "prompt": [{ "type": "text", "text": "again" }]
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn runs_with_an_output_schema_return_the_parsed_reply() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *'"session/new"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"sessionId":"s-1"}}\n' "$id" ;;
+    *'"session/prompt"'*JSON\ Schema*)
+      printf '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s-1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"{\\"verdict\\": \\"pass\\"}"}}}}\n'
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id" ;;
+    *) [ -n "$id" ] && printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#,
+        );
+    });
+    let run = |server_id: &str, required: &str| {
+        json!({
+            "agent": "codex",
+            "serverId": server_id,
+            "prompt": [{ "type": "text", "text": "review the change" }],
+            "outputSchema": {
+                "type": "object",
+                "required": [required],
+                "properties": { "verdict": { "enum": ["pass", "fail"] } }
+            }
+        })
+    };
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/runs",
+        Some(run("schema-run", "verdict")),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let result = parse_json(&body);
+    assert_eq!(result["status"], "completed");
+    assert_eq!(
+        result["structuredOutput"],
+        json!({ "output": { "verdict": "pass" } })
+    );
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/runs",
+        Some(run("schema-run-missing", "issues")),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body)["structuredOutput"],
+        json!({ "error": "$.issues is required" })
+    );
+}
+
+#[tokio::test]
+async fn approvals_list_and_reply_report_unknown_requests() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-approvals?agent=codex",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/approvals", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["approvals"], json!([]));
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/approvals/reply",
+        Some(json!({
+            "replies": [
+                { "serverId": "server-approvals", "requestId": "perm-1", "reply": "once" },
+                { "serverId": "missing", "requestId": 1, "reply": "reject" }
+            ]
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let results = parse_json(&body)["results"].clone();
+    assert_eq!(results[0]["ok"], false);
+    assert_eq!(results[0]["error"]["status"], 404);
+    assert_eq!(results[1]["ok"], false);
+    assert_eq!(results[1]["requestId"], 1);
+}
+
+#[tokio::test]
+#[serial]
+async fn approvals_callback_needs_routing_and_a_signed_token() {
+    let callback = json!({ "token": "eyJzZXJ2ZXJJZCI6Im1haW4iLCJyZXF1ZXN0SWQiOjF9.Zm9yZ2Vk", "reply": "once" });
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/approvals/callback",
+        Some(callback.clone()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let _url = EnvVarGuard::set(
+        "SANDBOX_AGENT_APPROVAL_ROUTE_URL",
+        "http://127.0.0.1:9/approvals",
+    );
+    let _secret = EnvVarGuard::set("SANDBOX_AGENT_APPROVAL_ROUTE_SECRET", "route-secret");
+    let test_app = TestApp::new(AuthConfig::with_token("secret-token".to_string()));
+    // No bearer token: the approval token is the only credential the callback takes.
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/approvals/callback",
+        Some(callback),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        parse_json(&body)["details"]["message"],
+        "invalid approval token"
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn approvals_callback_answers_the_escalated_request_once() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind approver");
+    let address = listener.local_addr().expect("approver address");
+    let (sender, receiver) = std::sync::mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        while let Ok((mut stream, _)) = listener.accept() {
+            let mut request = Vec::new();
+            let mut buffer = [0_u8; 8192];
+            // Read the headers, then the body up to its Content-Length.
+            while let Ok(read) = stream.read(&mut buffer) {
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request);
+                let complete = text.split_once("\r\n\r\n").is_some_and(|(head, body)| {
+                    head.lines()
+                        .find_map(|line| {
+                            line.to_ascii_lowercase()
+                                .strip_prefix("content-length:")
+                                .and_then(|length| length.trim().parse::<usize>().ok())
+                        })
+                        .is_some_and(|length| body.len() >= length)
+                });
+                if read == 0 || complete {
+                    break;
+                }
+            }
+            let _ = stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+            let _ = sender.send(String::from_utf8_lossy(&request).into_owned());
+        }
+    });
+    let _url = EnvVarGuard::set(
+        "SANDBOX_AGENT_APPROVAL_ROUTE_URL",
+        &format!("http://{address}/approve"),
+    );
+    let _secret = EnvVarGuard::set("SANDBOX_AGENT_APPROVAL_ROUTE_SECRET", "route-secret");
+    let _actions = EnvVarGuard::set("SANDBOX_AGENT_APPROVAL_ROUTE_ACTIONS", "*");
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "routed", "mock").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/routed",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": {
+                "cwd": "/",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": {
+                    "mockScript": [{ "type": "permission", "title": "Run tests" }]
+                } }
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+    let app = test_app.app.clone();
+    let turn = tokio::spawn(async move {
+        send_request(
+            &app,
+            Method::POST,
+            "/v1/acp/routed",
+            Some(json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "session/prompt",
+                "params": {
+                    "sessionId": session_id,
+                    "prompt": [{ "type": "text", "text": "run the tests" }]
+                }
+            })),
+            &[],
+        )
+        .await
+    });
+
+    let delivery = tokio::task::spawn_blocking(move || {
+        receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("escalation delivered")
+    })
+    .await
+    .expect("join approver");
+    let body = delivery.split("\r\n\r\n").nth(1).expect("escalation body");
+    let event = parse_json(body.as_bytes());
+    assert_eq!(event["type"], "permission.escalated");
+    let callback = json!({ "token": event["token"], "reply": "once" });
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/approvals/callback",
+        Some(callback.clone()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, body) = turn.await.expect("join turn");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["stopReason"], "end_turn");
+
+    // The request is no longer held under the token's nonce, so the token is spent.
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/approvals/callback",
+        Some(callback),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+#[serial]
+async fn acp_from_pool_adopts_prewarmed_process() {
+    let install_dir = tempfile::tempdir().expect("create temp install dir");
+    setup_stub_artifacts(install_dir.path(), "codex");
+
+    let state = {
+        let _pool = EnvVarGuard::set("SANDBOX_AGENT_ACP_WARM_POOL", "codex=1");
+        let manager = AgentManager::new(install_dir.path()).expect("create agent manager");
+        Arc::new(AppState::new(AuthConfig::disabled(), manager))
+    };
+    let (app, state) = build_router_with_state(state);
+    prewarm_servers(&state).await;
+
+    let (status, _, body) = send_request(&app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed = parse_json(&body);
+    assert_eq!(parsed["warmPool"][0]["agent"], "codex");
+    assert_eq!(parsed["warmPool"][0]["idle"], 1);
+    assert_eq!(parsed["warmPool"][0]["target"], 1);
+
+    let (status, _, body) = send_request(
+        &app,
+        Method::POST,
+        "/v1/acp/server-pooled?agent=codex&fromPool=true",
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["echoedMethod"], "initialize");
+
+    // Adoption schedules a background refill; wait for it before inspecting.
+    prewarm_servers(&state).await;
+
+    let (status, _, body) = send_request(&app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed = parse_json(&body);
+    assert!(parsed["servers"]
+        .as_array()
+        .expect("servers array")
+        .iter()
+        .any(|server| server["serverId"] == "server-pooled"));
+    assert_eq!(parsed["warmPool"][0]["idle"], 1);
+
+    shutdown_servers(&state).await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn sandboxagent_methods_are_not_handled_specially() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+    });
+
+    bootstrap_server(&test_app.app, "server-ext", "codex").await;
+
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 22,
+        "method": "_sandboxagent/session/list",
+        "params": {}
+    });
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-ext",
+        Some(request),
+        &[],
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body)["result"]["echoedMethod"],
+        "_sandboxagent/session/list"
+    );
+}
+
+#[tokio::test]
+async fn post_requires_json_content_type() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let payload = br#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"1.0","clientCapabilities":{}}}"#
+        .to_vec();
+    let (status, _, body) = send_request_raw(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/server-content?agent=mock",
+        Some(payload),
+        &[],
+        Some("text/plain"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(parse_json(&body)["status"], 415);
+}
+
+#[tokio::test]
+async fn sse_rejects_non_sse_accept() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-a",
+        None,
+        &[("accept", "application/json")],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(parse_json(&body)["status"], 406);
+}
+
+#[tokio::test]
+async fn invalid_last_event_id_returns_bad_request() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/server-a",
+        None,
+        &[("last-event-id", "not-a-number")],
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        parse_json(&body)["detail"],
+        "invalid request: Last-Event-ID must be a positive integer"
+    );
+}
+
+#[tokio::test]
+async fn acp_checkpoints_restore_workspace() {
+    let test_app = TestApp::with_setup(AuthConfig::disabled(), |install_dir| {
+        setup_stub_artifacts(install_dir, "codex");
+        write_executable(
+            &install_dir.join("agent_processes/codex-acp"),
+            r#"#!/usr/bin/env sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id"[[:space:]]*:[[:space:]]*\([^,}]*\).*/\1/p')
+  case "$line" in
+    *session/prompt*)
+      printf 'broken\n' > notes.txt
+      rm -f src/lib.txt
+      printf 'stray\n' > stray.txt
+      printf 'rebuilt\n' > build/out.txt
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"stopReason":"end_turn"}}\n' "$id"
+      ;;
+    *)
+      if [ -n "$id" ]; then
+        printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id"
+      fi
+      ;;
+  esac
+done
+"#,
+        );
+    });
+    let workspace = tempfile::tempdir().expect("create workspace");
+    let root = fs::canonicalize(workspace.path()).expect("canonical workspace");
+    fs::write(root.join("notes.txt"), "one\n").expect("write notes");
+    fs::create_dir(root.join("src")).expect("create src");
+    fs::write(root.join("src/lib.txt"), "lib\n").expect("write lib");
+    fs::write(root.join(".gitignore"), "build/\n").expect("write gitignore");
+    fs::create_dir(root.join("build")).expect("create build");
+    fs::write(root.join("build/out.txt"), "old\n").expect("write build output");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/acp/cp-server?agent=codex&directory={}", root.display()),
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/cp-server/checkpoints",
+        Some(json!({"label": "before prompt"})),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let checkpoint = parse_json(&body);
+    let checkpoint_id = checkpoint["checkpointId"]
+        .as_str()
+        .expect("checkpoint id")
+        .to_string();
+    assert_eq!(checkpoint["label"], "before prompt");
+    assert_eq!(checkpoint["root"], root.to_string_lossy().as_ref());
+    assert_eq!(checkpoint["fileCount"], 3);
+    assert_eq!(checkpoint["truncated"], false);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/cp-server",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": "s-1",
+                "prompt": [{"type": "text", "text": "edit"}]
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        fs::read_to_string(root.join("notes.txt")).expect("read notes"),
+        "broken\n"
+    );
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/cp-server/checkpoints",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let checkpoints = parse_json(&body)["checkpoints"].clone();
+    assert_eq!(checkpoints.as_array().expect("checkpoints").len(), 1);
+    assert_eq!(checkpoints[0]["checkpointId"], checkpoint_id.as_str());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/acp/cp-server/checkpoints/{checkpoint_id}/restore"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let restored = parse_json(&body);
+    assert_eq!(restored["restored"], json!(["notes.txt", "src/lib.txt"]));
+    assert_eq!(restored["deleted"], json!(["stray.txt"]));
+    assert_eq!(
+        fs::read_to_string(root.join("notes.txt")).expect("read notes"),
+        "one\n"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("src/lib.txt")).expect("read lib"),
+        "lib\n"
+    );
+    assert!(!root.join("stray.txt").exists());
+    // Ignored paths are outside the checkpoint and left as they are.
+    assert_eq!(
+        fs::read_to_string(root.join("build/out.txt")).expect("read build output"),
+        "rebuilt\n"
+    );
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/cp-server/checkpoints/cp_missing/restore",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn ai_sdk_messages_stream_the_turn_as_ui_message_chunks() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "ai-sdk", "mock").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/ai-sdk",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": {
+                "cwd": "/",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": { "mockScript": [
+                    { "type": "thought", "text": "thinking" },
+                    { "type": "toolCall", "toolCallId": "t1", "title": "Run tests", "kind": "execute" },
+                    { "type": "toolCallUpdate", "toolCallId": "t1", "status": "completed", "text": "12 passed" },
+                    { "type": "message", "text": "All tests pass." }
+                ] } }
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+
+    let chunks = |body: &[u8]| -> Vec<String> {
+        String::from_utf8_lossy(body)
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(str::to_string)
+            .collect()
+    };
+    let (status, headers, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/acp/ai-sdk/sessions/{session_id}/messages/ai-sdk"),
+        Some(json!({
+            "id": "chat",
+            "messages": [{ "id": "u1", "role": "user", "parts": [{ "type": "text", "text": "hello" }] }],
+            "trigger": "submit-message"
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers
+            .get("x-vercel-ai-ui-message-stream")
+            .and_then(|value| value.to_str().ok()),
+        Some("v1")
+    );
+    let chunks = chunks(&body);
+    assert_eq!(chunks.last().map(String::as_str), Some("[DONE]"));
+    let parsed: Vec<Value> = chunks[..chunks.len() - 1]
+        .iter()
+        .map(|chunk| serde_json::from_str(chunk).expect("json chunk"))
+        .collect();
+    let types: Vec<&str> = parsed
+        .iter()
+        .filter_map(|chunk| chunk["type"].as_str())
+        .collect();
+    assert_eq!(
+        types,
+        vec![
+            "start",
+            "start-step",
+            "reasoning-start",
+            "reasoning-delta",
+            "reasoning-end",
+            "tool-input-available",
+            "tool-output-available",
+            "text-start",
+            "text-delta",
+            "text-end",
+            "finish-step",
+            "finish"
+        ]
+    );
+    assert_eq!(parsed[5]["toolName"], "Run tests");
+    assert_eq!(parsed[6]["output"], "12 passed");
+    assert_eq!(parsed[8]["delta"], "All tests pass.");
+    assert_eq!(parsed[11]["messageMetadata"]["stopReason"], "end_turn");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/acp/ai-sdk/sessions/{session_id}/messages/ai-sdk"),
+        Some(json!({ "messages": [{ "role": "assistant", "content": "hi" }] })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/missing/sessions/s/messages/ai-sdk",
+        Some(json!({ "messages": [{ "role": "user", "content": "hi" }] })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn chat_completions_answer_with_a_temporary_agent_server() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/chat/completions",
+        Some(json!({
+            "model": "mock",
+            "temperature": 0.2,
+            "messages": [
+                { "role": "system", "content": "Be terse." },
+                { "role": "user", "content": [{ "type": "text", "text": "hello" }] }
+            ]
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let completion = parse_json(&body);
+    assert_eq!(completion["object"], "chat.completion");
+    assert_eq!(completion["model"], "mock");
+    assert_eq!(completion["choices"][0]["message"]["role"], "assistant");
+    assert_eq!(completion["choices"][0]["finish_reason"], "stop");
+    let content = completion["choices"][0]["message"]["content"]
+        .as_str()
+        .expect("content");
+    assert!(content.contains("System: Be terse."));
+    assert!(content.ends_with("hello"));
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/chat/completions",
+        Some(json!({
+            "model": "mock",
+            "stream": true,
+            "stream_options": { "include_usage": true },
+            "messages": [{ "role": "user", "content": "stream me" }]
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let chunks: Vec<String> = String::from_utf8_lossy(&body)
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(str::to_string)
+        .collect();
+    assert_eq!(chunks.last().map(String::as_str), Some("[DONE]"));
+    let parsed: Vec<Value> = chunks[..chunks.len() - 1]
+        .iter()
+        .map(|chunk| serde_json::from_str(chunk).expect("json chunk"))
+        .collect();
+    assert!(parsed
+        .iter()
+        .all(|chunk| chunk["object"] == "chat.completion.chunk"));
+    assert_eq!(parsed[0]["choices"][0]["delta"]["role"], "assistant");
+    let text: String = parsed
+        .iter()
+        .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(text, "stream me");
+    assert_eq!(
+        parsed[parsed.len() - 2]["choices"][0]["finish_reason"],
+        "stop"
+    );
+    assert_eq!(parsed[parsed.len() - 1]["choices"], json!([]));
+    assert!(parsed[parsed.len() - 1]["usage"].is_object());
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/chat/completions",
+        Some(json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": "hi" }]
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/acp", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["servers"], json!([]));
+}
+
+#[tokio::test]
+async fn session_turn_timeout_cancels_a_hung_turn() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "turn-timeout", "mock").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/turn-timeout",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": {
+                "cwd": "/",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": {
+                    "turnTimeoutMs": 200,
+                    "mockScript": [{ "type": "delay", "ms": 60000 }]
+                } }
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/turn-timeout",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "hang" }]
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed = parse_json(&body);
+    assert_eq!(parsed["id"], 3);
+    assert_eq!(parsed["error"]["data"]["code"], "turn_timeout");
+    assert_eq!(parsed["error"]["data"]["timeoutMs"], 200);
+
+    // The mock ends the cancelled turn, so the agent keeps serving the session.
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/turn-timeout",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 4,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "again" }],
+                "_meta": { "sandboxagent.dev": { "turnTimeoutMs": 0, "mockScript": [] } }
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["stopReason"], "end_turn");
+}
+
+#[tokio::test]
+async fn session_turn_timeout_keeps_a_process_other_sessions_are_using() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "turn-shared", "mock").await;
+    let mut session_ids = Vec::new();
+    for (id, meta) in [
+        (2, json!({ "turnTimeoutMs": 200 })),
+        (3, json!({ "turnTimeoutMs": 0 })),
+    ] {
+        let (status, _, body) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/turn-shared",
+            Some(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "session/new",
+                "params": { "cwd": "/", "mcpServers": [], "_meta": { "sandboxagent.dev": meta } }
+            })),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        session_ids.push(
+            parse_json(&body)["result"]["sessionId"]
+                .as_str()
+                .expect("session id")
+                .to_string(),
+        );
+    }
+    let prompt = |id: u64, session_id: &str, script: Value| {
+        let app = test_app.app.clone();
+        let payload = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "work" }],
+                "_meta": { "sandboxagent.dev": { "mockScript": script } }
+            }
+        });
+        tokio::spawn(async move {
+            send_request(
+                &app,
+                Method::POST,
+                "/v1/acp/turn-shared",
+                Some(payload),
+                &[],
+            )
+            .await
+        })
+    };
+
+    // The first turn ignores the cancel past the grace period while the second waits behind it.
+    let hung = prompt(
+        4,
+        &session_ids[0],
+        json!([{ "type": "delay", "ms": 7000, "ignoreCancel": true }]),
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let other = prompt(
+        5,
+        &session_ids[1],
+        json!([{ "type": "message", "text": "done" }]),
+    );
+
+    let (status, _, body) = hung.await.expect("join hung turn");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["error"]["data"]["code"], "turn_timeout");
+
+    // Its process was kept, so the other session's turn still completes.
+    let (status, _, body) = other.await.expect("join other turn");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["stopReason"], "end_turn");
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/turn-shared/events.jsonl",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let timeout_error = String::from_utf8_lossy(&body)
+        .lines()
+        .map(|line| parse_json(line.as_bytes())["message"].clone())
+        .find(|message| message["params"]["code"] == "turn_timeout")
+        .expect("turn_timeout error event");
+    assert_eq!(timeout_error["params"]["details"]["agentStopped"], false);
+    assert_eq!(timeout_error["params"]["details"]["abandoned"], true);
+}
+
+#[tokio::test]
+async fn session_question_policy_answers_unanswered_questions() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "question-timeout", "mock").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/question-timeout",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": {
+                "cwd": "/",
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": {
+                    "questionPolicy": { "timeoutMs": 100, "onTimeout": "default" },
+                    "mockScript": [{ "type": "question", "question": "Proceed?", "options": ["yes", "no"] }]
+                } }
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/question-timeout",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "ask me" }]
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["result"]["stopReason"], "end_turn");
+
+    let resolved = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let (_, _, body) = send_request_raw(
+                &test_app.app,
+                Method::GET,
+                "/v1/acp/question-timeout/events.jsonl",
+                None,
+                &[],
+                None,
+            )
+            .await;
+            let resolved = String::from_utf8_lossy(&body)
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(line).expect("json line"))
+                .find(|event| event["message"]["method"] == "_sandboxagent/question/resolved");
+            if let Some(event) = resolved {
+                return event["message"]["params"].clone();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("question resolved");
+    assert_eq!(resolved["sessionId"], session_id.as_str());
+    assert_eq!(resolved["timedOut"], true);
+    assert_eq!(resolved["outcome"], "answered");
+    assert_eq!(resolved["answers"], json!([["yes"]]));
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/approvals", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["approvals"], json!([]));
+}
+
+#[tokio::test]
+async fn session_prompt_retries_transient_agent_errors() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "turn-retry", "mock").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/turn-retry",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": { "cwd": "/", "mcpServers": [] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+
+    let prompt = |id: u64, message: &str| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "go" }],
+                "_meta": { "sandboxagent.dev": {
+                    "retry": { "maxAttempts": 3, "backoffMs": 10 },
+                    "mockScript": [{ "type": "error", "message": message }]
+                } }
+            }
+        })
+    };
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/turn-retry",
+        Some(prompt(3, "model overloaded")),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["error"]["message"], "model overloaded");
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/turn-retry",
+        Some(prompt(4, "invalid api key")),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["error"]["message"], "invalid api key");
+
+    let retries = test_app
+        .app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::GET)
+                .uri("/v1/acp/turn-retry?types=_sandboxagent/turn/retry")
+                .body(Body::empty())
+                .expect("build request"),
+        )
+        .await
+        .expect("sse response");
+    assert_eq!(retries.status(), StatusCode::OK);
+    let mut stream = retries.into_body().into_data_stream();
+    let mut text = String::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while text.matches("data:").count() < 2 {
+            let bytes = stream.next().await.expect("stream open").expect("chunk");
+            text.push_str(&String::from_utf8_lossy(&bytes));
+        }
+    })
+    .await
+    .expect("retry notifications");
+    let retries = text
+        .split("\n\n")
+        .filter(|frame| frame.contains("data:"))
+        .map(parse_sse_data)
+        .collect::<Vec<_>>();
+    assert_eq!(retries[0]["params"]["requestId"], 3);
+    assert_eq!(retries[0]["params"]["retryOf"], 1);
+    assert_eq!(retries[1]["params"]["attempt"], 3);
+    assert_eq!(retries[1]["params"]["delayMs"], 20);
+}
+
+#[tokio::test]
+async fn session_compact_reports_the_context_window() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "compact", "mock").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/compact",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": { "cwd": "/", "mcpServers": [] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"]
+        .as_str()
+        .expect("session id")
+        .to_string();
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/compact",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "go" }],
+                "_meta": { "sandboxagent.dev": { "mockScript": [{
+                    "type": "update",
+                    "update": { "sessionUpdate": "usage_update", "used": 150000, "size": 200000 }
+                }] } }
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/acp/compact/usage",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body)["contexts"],
+        json!([{ "sessionId": session_id, "used": 150000, "size": 200000 }])
+    );
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/acp/compact/sessions/{session_id}/compact"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let compacted = parse_json(&body);
+    assert_eq!(compacted["stopReason"], "end_turn");
+    assert_eq!(compacted["contextBefore"]["used"], 150000);
+
+    let events = read_sse_events(&test_app.app, "compact", None, 8).await;
+    let phases = events
+        .iter()
+        .filter(|(_, event)| event["method"] == "_sandboxagent/session/compaction")
+        .map(|(_, event)| event["params"]["phase"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(phases, [json!("started"), json!("completed")]);
+}
+
+#[tokio::test]
+#[serial]
+async fn session_new_writes_instruction_files_until_the_server_ends() {
+    let templates = tempfile::tempdir().expect("create templates dir");
+    let test_app = {
+        let _dir = EnvVarGuard::set(
+            "SANDBOX_AGENT_INSTRUCTIONS_DIR",
+            &templates.path().to_string_lossy(),
+        );
+        TestApp::new(AuthConfig::disabled())
+    };
+    let workspace = tempfile::tempdir().expect("create workspace");
+    let cwd = fs::canonicalize(workspace.path()).expect("canonical workspace");
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::PUT,
+        "/v1/instructions/templates/guardrails",
+        Some(json!({ "content": "{{team}} owns {{cwd}}." })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["variables"], json!(["cwd", "team"]));
+
+    bootstrap_server(&test_app.app, "instructions", "mock").await;
+    let session_new = |files: Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": {
+                "cwd": cwd,
+                "mcpServers": [],
+                "_meta": { "sandboxagent.dev": { "instructionFiles": files } }
+            }
+        })
+    };
+    let requested = json!([{ "template": "guardrails", "variables": { "team": "payments" } }]);
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/instructions",
+        Some(session_new(requested.clone())),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let agents_md = cwd.join("AGENTS.md");
+    assert_eq!(
+        parse_json(&body)["result"]["_meta"]["sandboxagent.dev"]["instructionFiles"],
+        json!([agents_md.display().to_string()])
+    );
+    assert_eq!(
+        fs::read_to_string(&agents_md).expect("read AGENTS.md"),
+        format!("payments owns {}.", cwd.display())
+    );
+
+    for (files, expected) in [
+        (requested, StatusCode::CONFLICT),
+        (
+            json!([{ "template": "guardrails", "path": "OTHER.md" }]),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            json!([{ "template": "guardrails", "path": "../AGENTS.md" }]),
+            StatusCode::BAD_REQUEST,
+        ),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/acp/instructions",
+            Some(session_new(files)),
+            &[],
+        )
+        .await;
+        assert_eq!(status, expected);
+    }
+    assert!(!cwd.join("OTHER.md").exists());
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/instructions",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(!agents_md.exists());
+}
+
+#[test]
+fn mock_agent_subcommand_speaks_json_rpc_and_exits_on_script() {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sandbox-agent"))
+        .arg("mock-agent")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn mock agent");
+    let mut stdin = child.stdin.take().expect("stdin");
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout"));
+    let mut exchange = |message: Value| {
+        writeln!(stdin, "{message}").expect("write request");
+        let mut line = String::new();
+        stdout.read_line(&mut line).expect("read response");
+        serde_json::from_str::<Value>(&line).expect("JSON-RPC line")
+    };
+
+    let initialized = exchange(initialize_payload());
+    assert_eq!(initialized["result"]["agentInfo"]["name"], "mock");
+    let created = exchange(json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "session/new",
+        "params": {
+            "cwd": "/",
+            "mcpServers": [],
+            "_meta": { "sandboxagent.dev": { "mockScript": [{ "type": "exit", "code": 3 }] } },
+        },
+    }));
+    assert_eq!(created["result"]["sessionId"], "mock-session-1");
+    writeln!(
+        stdin,
+        "{}",
+        json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": { "sessionId": "mock-session-1", "prompt": [] },
+        })
+    )
+    .expect("write prompt");
+    assert_eq!(child.wait().expect("mock agent exits").code(), Some(3));
 }
@@ -77,6 +77,190 @@ async fn mcp_config_crud_round_trip() {
     assert_eq!(parse_json(&body)["status"], 404);
 }
 
+/// Answer one request per connection with the next of `replies`, and send each request body.
+fn serve_oauth(replies: Vec<(&'static str, Value)>) -> (String, std::sync::mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind oauth server");
+    let address = listener.local_addr().expect("oauth address");
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for (status, reply) in replies {
+            let Ok((mut stream, _)) = listener.accept() else {
+                return;
+            };
+            let mut request = Vec::new();
+            let mut buffer = [0_u8; 4096];
+            let body = loop {
+                let read = stream.read(&mut buffer).expect("read oauth request");
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length || read == 0 {
+                        break body.to_string();
+                    }
+                }
+            };
+            let reply = reply.to_string();
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                reply.len()
+            );
+            let _ = stream.write_all(response.as_bytes());
+            let _ = sender.send(body);
+        }
+    });
+    (format!("http://{address}"), receiver)
+}
+
+#[tokio::test]
+#[serial]
+async fn mcp_oauth_client_credentials_and_device_flow() {
+    let store = tempfile::tempdir().expect("tempdir");
+    let _store = EnvVarGuard::set(
+        "SANDBOX_AGENT_MCP_OAUTH_DIR",
+        &store.path().to_string_lossy(),
+    );
+    let (base, requests) = serve_oauth(vec![
+        (
+            "200 OK",
+            json!({"access_token": "cc-token", "token_type": "Bearer", "expires_in": 3600}),
+        ),
+        (
+            "200 OK",
+            json!({
+                "device_code": "dev-1",
+                "user_code": "WDJB-MJHT",
+                "verification_uri": "https://auth.example/device",
+                "expires_in": 60,
+                "interval": 1
+            }),
+        ),
+        ("400 Bad Request", json!({"error": "authorization_pending"})),
+        (
+            "200 OK",
+            json!({"access_token": "device-token", "refresh_token": "r1", "expires_in": 3600}),
+        ),
+    ]);
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let project = tempfile::tempdir().expect("tempdir");
+    let directory = project.path().to_string_lossy().to_string();
+    let oauth_url =
+        |name: &str| format!("/v1/config/mcp/oauth?directory={directory}&mcpName={name}");
+
+    for (name, url, oauth) in [
+        (
+            "api",
+            "https://api.example/mcp",
+            json!({"clientId": "agent", "clientSecret": "s3cret", "scope": "tools", "tokenUrl": format!("{base}/token")}),
+        ),
+        (
+            "device",
+            "https://device.example/mcp",
+            json!({"clientId": "agent", "flow": "deviceCode", "tokenUrl": format!("{base}/token"), "deviceAuthorizationUrl": format!("{base}/device")}),
+        ),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::PUT,
+            &format!("/v1/config/mcp?directory={directory}&mcpName={name}"),
+            Some(json!({"type": "remote", "url": url, "oauth": oauth})),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::POST, &oauth_url("api"), None, &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let authorized = parse_json(&body);
+    assert_eq!(authorized["state"], "authorized");
+    assert_eq!(authorized["flow"], "clientCredentials");
+    assert_eq!(authorized["refreshable"], true);
+    assert!(authorized["expiresAtMs"].as_i64().is_some());
+    let form = requests.recv().expect("token request");
+    assert!(form.contains("grant_type=client_credentials"), "{form}");
+    assert!(form.contains("client_secret=s3cret"), "{form}");
+    assert!(form.contains("scope=tools"), "{form}");
+
+    let tokens = store.path().join("tokens.json");
+    assert!(fs::read_to_string(&tokens).unwrap().contains("cc-token"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&tokens).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::POST, &oauth_url("device"), None, &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let pending = parse_json(&body);
+    assert_eq!(pending["state"], "pending");
+    assert_eq!(pending["userCode"], "WDJB-MJHT");
+    assert_eq!(pending["verificationUri"], "https://auth.example/device");
+    assert!(requests
+        .recv()
+        .expect("device request")
+        .contains("client_id=agent"));
+
+    let mut state = Value::Null;
+    for _ in 0..50 {
+        let (_, _, body) =
+            send_request(&test_app.app, Method::GET, &oauth_url("device"), None, &[]).await;
+        state = parse_json(&body)["state"].clone();
+        if state != "pending" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(state, "authorized");
+    let polls = [requests.recv().unwrap(), requests.recv().unwrap()];
+    assert!(polls.iter().all(|form| form.contains("device_code=dev-1")));
+    assert!(fs::read_to_string(&tokens)
+        .unwrap()
+        .contains("device-token"));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        &oauth_url("device"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, _, body) =
+        send_request(&test_app.app, Method::GET, &oauth_url("device"), None, &[]).await;
+    assert_eq!(parse_json(&body)["state"], "none");
+    assert!(!fs::read_to_string(&tokens)
+        .unwrap()
+        .contains("device-token"));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::PUT,
+        &format!("/v1/config/mcp?directory={directory}&mcpName=local"),
+        Some(json!({"type": "local", "command": "docs-mcp"})),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) =
+        send_request(&test_app.app, Method::POST, &oauth_url("local"), None, &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) =
+        send_request(&test_app.app, Method::GET, &oauth_url("missing"), None, &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn skills_config_requires_directory_and_name() {
     let test_app = TestApp::new(AuthConfig::disabled());
@@ -150,3 +334,312 @@ async fn skills_config_crud_round_trip() {
     .await;
     assert_eq!(status, StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+#[serial]
+async fn skills_install_sync_and_remove() {
+    let root = tempfile::tempdir().expect("tempdir");
+    let dir = |name: &str| root.path().join(name).to_string_lossy().to_string();
+    let _store = EnvVarGuard::set("SANDBOX_AGENT_SKILLS_DIR", &dir("store"));
+    let _cache = EnvVarGuard::set("SANDBOX_AGENT_SKILLS_CACHE_DIR", &dir("cache"));
+    let _claude = EnvVarGuard::set("CLAUDE_CONFIG_DIR", &dir("claude"));
+    let _codex = EnvVarGuard::set("CODEX_HOME", &dir("codex"));
+    let _opencode = EnvVarGuard::set("OPENCODE_CONFIG_DIR", &dir("opencode"));
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    // A skill the user wrote by hand is never replaced.
+    let handwritten = root.path().join("claude/skills/review");
+    fs::create_dir_all(&handwritten).expect("create handwritten skill");
+    fs::write(handwritten.join("SKILL.md"), "mine").expect("write handwritten skill");
+
+    let pack = root.path().join("pack");
+    fs::create_dir_all(pack.join("review")).expect("create review");
+    fs::create_dir_all(pack.join("lint")).expect("create lint");
+    fs::write(
+        pack.join("review/SKILL.md"),
+        "---\nname: review\ndescription: Review a diff\nversion: 1.0.0\n---\nReview it.\n",
+    )
+    .expect("write review");
+    fs::write(pack.join("lint/SKILL.md"), "Run the linter.\n").expect("write lint");
+    fs::write(pack.join("lint/lint.sh"), "#!/bin/sh\n").expect("write lint script");
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/skills/install",
+        Some(json!({"type": "local", "source": pack.to_string_lossy()})),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let installed = parse_json(&body)["installed"].clone();
+    assert_eq!(installed[0]["name"], "lint");
+    assert_eq!(
+        installed[0]["syncedTo"],
+        json!(["claude", "codex", "opencode"])
+    );
+    assert_eq!(installed[1]["name"], "review");
+    assert_eq!(installed[1]["description"], "Review a diff");
+    assert_eq!(installed[1]["version"], "1.0.0");
+    assert_eq!(installed[1]["syncedTo"], json!(["codex", "opencode"]));
+    assert_eq!(
+        fs::read_to_string(handwritten.join("SKILL.md")).expect("read handwritten"),
+        "mine"
+    );
+    assert!(root.path().join("codex/skills/lint/lint.sh").is_file());
+    assert!(root
+        .path()
+        .join("opencode/skills/review/SKILL.md")
+        .is_file());
+
+    let repo = root.path().join("docs-skill");
+    fs::create_dir_all(&repo).expect("create repo");
+    fs::write(repo.join("SKILL.md"), "Write docs.\n").expect("write docs skill");
+    git(&repo, &["init", "--quiet"]);
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "--quiet", "-m", "docs skill"]);
+    let commit = git(&repo, &["rev-parse", "HEAD"]);
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/skills/install",
+        Some(json!({"type": "git", "source": repo.to_string_lossy()})),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    assert_eq!(parse_json(&body)["installed"][0]["name"], "docs-skill");
+    assert_eq!(parse_json(&body)["installed"][0]["version"], commit);
+
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/skills", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<String> = parse_json(&body)["skills"]
+        .as_array()
+        .expect("skills")
+        .iter()
+        .map(|skill| skill["name"].as_str().unwrap_or_default().to_string())
+        .collect();
+    assert_eq!(names, ["docs-skill", "lint", "review"]);
+
+    let (status, _, _) =
+        send_request(&test_app.app, Method::DELETE, "/v1/skills/lint", None, &[]).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(!root.path().join("codex/skills/lint").exists());
+    assert!(!root.path().join("store/lint").exists());
+    let (status, _, _) =
+        send_request(&test_app.app, Method::DELETE, "/v1/skills/lint", None, &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/skills/install",
+        Some(json!({"type": "local", "source": pack.to_string_lossy(), "skills": ["missing"]})),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/skills/install",
+        Some(json!({"type": "svn", "source": "svn://example"})),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[serial]
+async fn skills_install_reuses_cached_sources() {
+    let root = tempfile::tempdir().expect("tempdir");
+    let dir = |name: &str| root.path().join(name).to_string_lossy().to_string();
+    let _store = EnvVarGuard::set("SANDBOX_AGENT_SKILLS_DIR", &dir("store"));
+    let _cache = EnvVarGuard::set("SANDBOX_AGENT_SKILLS_CACHE_DIR", &dir("cache"));
+    let _claude = EnvVarGuard::set("CLAUDE_CONFIG_DIR", &dir("claude"));
+    let _codex = EnvVarGuard::set("CODEX_HOME", &dir("codex"));
+    let _opencode = EnvVarGuard::set("OPENCODE_CONFIG_DIR", &dir("opencode"));
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let install = |source: Value| {
+        let app = test_app.app.clone();
+        async move {
+            let (status, _, body) =
+                send_request(&app, Method::POST, "/v1/skills/install", Some(source), &[]).await;
+            (status, parse_json(&body))
+        }
+    };
+
+    let repo = root.path().join("docs");
+    fs::create_dir_all(&repo).expect("create repo");
+    fs::write(repo.join("SKILL.md"), "Write docs.\n").expect("write skill");
+    git(&repo, &["init", "--quiet"]);
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "--quiet", "-m", "docs"]);
+    let commit = git(&repo, &["rev-parse", "HEAD"]);
+    let source = json!({"type": "git", "source": repo.to_string_lossy()});
+    let pinned = json!({"type": "git", "source": repo.to_string_lossy(), "ref": commit});
+
+    let (status, body) = install(source.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["cached"], false);
+    let (_, body) = install(pinned.clone()).await;
+    assert_eq!(body["cached"], false);
+
+    // Pinned and reused sources install without reaching the repository.
+    fs::remove_dir_all(&repo).expect("remove repo");
+    let (status, body) = install(pinned).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["cached"], true);
+    assert_eq!(body["installed"][0]["version"], commit);
+    let mut reuse = source.clone();
+    reuse["reuse"] = json!(true);
+    let (_, body) = install(reuse).await;
+    assert_eq!(body["cached"], true);
+    let (status, _) = install(source).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    let archive = root.path().join("lint.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).expect("create archive"));
+    let content = b"Run the linter.\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "lint/SKILL.md", &content[..])
+        .expect("append skill");
+    builder.finish().expect("finish archive");
+    drop(builder);
+    let digest = format!(
+        "{:x}",
+        <sha2::Sha256 as sha2::Digest>::digest(fs::read(&archive).expect("read archive"))
+    );
+
+    let (status, body) = install(json!({
+        "type": "archive",
+        "source": archive.to_string_lossy(),
+        "sha256": "0".repeat(64),
+    }))
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    let (status, body) = install(json!({
+        "type": "archive",
+        "source": archive.to_string_lossy(),
+        "sha256": digest,
+    }))
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["installed"][0]["version"], format!("sha256:{digest}"));
+    fs::remove_file(&archive).expect("remove archive");
+    let (_, body) = install(json!({
+        "type": "archive",
+        "source": "https://mirror.invalid/lint.tar",
+        "sha256": digest,
+    }))
+    .await;
+    assert_eq!(body["cached"], true);
+}
+
+#[tokio::test]
+async fn workspaces_register_resolve_and_remove() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let project = test_app.install_path().join("project");
+    let project_path = project.to_string_lossy().to_string();
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/workspaces",
+        Some(json!({"id": "app", "path": project_path})),
+        &[],
+    )
+    .await;
+    assert_eq!(
+        status,
+        StatusCode::CREATED,
+        "{}",
+        String::from_utf8_lossy(&body)
+    );
+    assert_eq!(parse_json(&body)["managed"], false);
+    assert!(project.is_dir());
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/workspaces",
+        Some(json!({"id": "copy", "path": project_path})),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    for invalid in [
+        json!({"id": "empty"}),
+        json!({"id": "../escape", "path": project_path}),
+        json!({"gitUrl": "file:///etc"}),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::POST,
+            "/v1/workspaces",
+            Some(invalid),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/workspaces", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["workspaces"][0]["id"], "app");
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/workspaces/missing",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let initialize = json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": 1}});
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/in-missing?agent=mock&workspaceId=missing",
+        Some(initialize.clone()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/acp/in-both?agent=mock&workspaceId=app&directory={project_path}"),
+        Some(initialize),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/workspaces/app",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(project.is_dir());
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/workspaces/app",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -17,6 +17,74 @@ async fn v1_health_removed_legacy_and_opencode_unmounted() {
     assert_eq!(status, StatusCode::OK);
 }
 
+#[tokio::test]
+async fn v1_health_verbose_reports_components() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/health", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let health = parse_json(&body);
+    assert_eq!(health["activeServers"], 0);
+    assert!(health.get("components").is_none());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/health?verbose=true",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let components = &parse_json(&body)["components"];
+    assert_eq!(components["opencodeServer"]["running"], false);
+    assert_eq!(components["agentProcesses"], json!([]));
+    let disks = components["disks"].as_array().expect("disks");
+    assert_eq!(
+        disks
+            .iter()
+            .map(|disk| disk["name"].as_str())
+            .collect::<Vec<_>>(),
+        vec![Some("workspace"), Some("logs")]
+    );
+    let providers = components["credentials"]
+        .as_array()
+        .expect("credentials")
+        .iter()
+        .filter_map(|credential| credential["provider"].as_str())
+        .collect::<Vec<_>>();
+    assert!(providers.contains(&"anthropic") && providers.contains(&"openai"));
+}
+
+#[tokio::test]
+async fn v1_ready_waits_for_serving_and_persisted_state() {
+    let install_dir = tempfile::tempdir().expect("create temp install dir");
+    let manager = AgentManager::new(install_dir.path()).expect("create agent manager");
+    let (app, state) =
+        build_router_with_state(Arc::new(AppState::new(AuthConfig::disabled(), manager)));
+
+    let (status, _, body) = send_request(&app, Method::GET, "/v1/ready", None, &[]).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        parse_json(&body),
+        json!({"ready": false, "serving": false, "persistenceLoaded": false})
+    );
+
+    // Liveness does not wait for startup.
+    let (status, _, _) = send_request(&app, Method::GET, "/v1/health", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    load_persisted_state(&state).await;
+    let (status, _, body) = send_request(&app, Method::GET, "/v1/ready", None, &[]).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(parse_json(&body)["persistenceLoaded"], true);
+
+    state.mark_serving();
+    let (status, _, body) = send_request(&app, Method::GET, "/v1/ready", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["ready"], true);
+}
+
 #[tokio::test]
 async fn v1_auth_enforced_when_token_configured() {
     let test_app = TestApp::new(AuthConfig::with_token("secret-token".to_string()));
@@ -36,6 +104,183 @@ async fn v1_auth_enforced_when_token_configured() {
     assert_eq!(parse_json(&body)["status"], "ok");
 }
 
+#[tokio::test]
+async fn v1_named_tokens_are_limited_to_their_scopes() {
+    let tokens = parse_tokens(
+        r#"{"tokens": [
+            {"name": "dashboard", "token": "read-token", "scopes": ["read-only"]},
+            {"name": "files", "token": "fs-token", "scopes": ["fs:write"]},
+            {"name": "ops", "token": "admin-token", "scopes": ["admin"]}
+        ]}"#,
+    )
+    .expect("parse tokens");
+    let test_app = TestApp::new(AuthConfig::disabled().with_tokens(tokens));
+    let read = [("authorization", "Bearer read-token")];
+    let files = [("authorization", "Bearer fs-token")];
+    let admin = [("authorization", "Bearer admin-token")];
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let mkdir = format!("/v1/fs/mkdir?path={}/created", dir.path().display());
+
+    let (status, _, _) = send_request(&test_app.app, Method::GET, "/v1/health", None, &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _, _) = send_request(&test_app.app, Method::GET, "/v1/health", None, &read).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The specs are readable with any scope; the Swagger UI needs admin.
+    let (status, _, _) =
+        send_request(&test_app.app, Method::GET, "/v1/openapi.json", None, &read).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_request(&test_app.app, Method::GET, "/v1/docs", None, &read).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = send_request(&test_app.app, Method::GET, "/v1/docs", None, &admin).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Files, config, the audit log and debug output can hold credentials.
+    let secret = dir.path().join("tokens.json");
+    fs::write(&secret, "{}").expect("write secret");
+    let file = format!("/v1/fs/file?path={}", secret.display());
+    for path in [
+        file.as_str(),
+        "/v1/config/mcp?directory=/tmp",
+        "/v1/audit",
+        "/v1/debug/logs",
+    ] {
+        let (status, _, _) = send_request(&test_app.app, Method::GET, path, None, &read).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{path}");
+    }
+    let (status, _, _) = send_request(&test_app.app, Method::GET, &file, None, &files).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_request(&test_app.app, Method::GET, "/v1/audit", None, &admin).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, body) = send_request(&test_app.app, Method::POST, &mkdir, None, &read).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(parse_json(&body)["details"]["message"]
+        .as_str()
+        .is_some_and(|detail| detail.contains("fs:write")));
+
+    let (status, _, _) = send_request(&test_app.app, Method::POST, &mkdir, None, &files).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        "/v1/acp/server-scoped",
+        None,
+        &files,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _, _) =
+        send_request(&test_app.app, Method::GET, "/opencode/session", None, &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn v1_request_limits_reject_bursts_and_large_bodies() {
+    let install_dir = tempfile::tempdir().expect("create temp install dir");
+    let manager = AgentManager::new(install_dir.path()).expect("create agent manager");
+    let state = AppState::new(AuthConfig::with_token("secret-token".to_string()), manager)
+        .with_request_limits(RequestLimits {
+            rate_limit_per_minute: Some(2),
+            max_fs_body_bytes: 16,
+            max_message_bytes: 8,
+        });
+    let app = build_router(state);
+    let auth = [("authorization", "Bearer secret-token")];
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let file = format!("/v1/fs/file?path={}/big.txt", dir.path().display());
+
+    let (status, _, body) = send_request_raw(
+        &app,
+        Method::PUT,
+        &file,
+        Some(vec![b'a'; 32]),
+        &auth,
+        Some("application/octet-stream"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    let problem = parse_json(&body);
+    assert_eq!(problem["type"], "urn:sandbox-agent:error:payload_too_large");
+    assert_eq!(problem["details"]["limitBytes"], 16);
+
+    // The rejected upload used the first of two requests in the bucket.
+    let (status, _, _) = send_request(&app, Method::GET, "/v1/health", None, &auth).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, headers, body) = send_request(&app, Method::GET, "/v1/health", None, &auth).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(headers.contains_key(header::RETRY_AFTER));
+    assert_eq!(
+        parse_json(&body)["type"],
+        "urn:sandbox-agent:error:rate_limited"
+    );
+}
+
+#[tokio::test]
+async fn v1_capabilities_reports_features_and_limits() {
+    let test_app = TestApp::new(AuthConfig::with_token("secret-token".to_string()));
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/capabilities",
+        None,
+        &[("authorization", "Bearer secret-token")],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let capabilities = parse_json(&body);
+    assert_eq!(capabilities["schemaVersion"], 1);
+    assert!(capabilities["build"]["version"].as_str().is_some());
+    assert_eq!(capabilities["features"]["acp"]["enabled"], true);
+    assert_eq!(capabilities["features"]["auth"]["enabled"], true);
+    assert_eq!(capabilities["features"]["acpWarmPool"]["enabled"], false);
+    assert_eq!(capabilities["limits"]["acpReplayBufferEvents"], 1024);
+    assert!(capabilities["limits"]["acpRequestTimeoutMs"]
+        .as_u64()
+        .is_some_and(|timeout| timeout > 0));
+}
+
+#[tokio::test]
+async fn v1_serves_api_documents_behind_token() {
+    let test_app = TestApp::new(AuthConfig::with_token("secret-token".to_string()));
+    let auth = [("authorization", "Bearer secret-token")];
+
+    let (status, _, _) = send_request(&test_app.app, Method::GET, "/v1/docs", None, &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/openapi.json", None, &auth).await;
+    assert_eq!(status, StatusCode::OK);
+    let openapi = parse_json(&body);
+    assert!(openapi["openapi"].as_str().is_some());
+    assert!(openapi["paths"]["/v1/acp/{server_id}"].is_object());
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/asyncapi.json", None, &auth).await;
+    assert_eq!(status, StatusCode::OK);
+    let asyncapi = parse_json(&body);
+    assert_eq!(asyncapi["asyncapi"], "2.6.0");
+    assert!(asyncapi["channels"]["/v1/acp/{server_id}"]["subscribe"].is_object());
+    assert!(asyncapi["components"]["schemas"]["AcpEnvelope"].is_object());
+
+    let (status, headers, body) =
+        send_request(&test_app.app, Method::GET, "/v1/docs", None, &auth).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html")));
+    let page = String::from_utf8_lossy(&body);
+    assert!(page.contains("/v1/openapi.json"));
+    assert!(page.contains("requestInterceptor"));
+    assert!(!page.contains("swagger-ui-dist@5/"));
+}
+
 #[tokio::test]
 async fn v1_filesystem_endpoints_round_trip() {
     let test_app = TestApp::new(AuthConfig::disabled());
@@ -134,6 +379,1725 @@ async fn v1_filesystem_endpoints_round_trip() {
     assert_eq!(status, StatusCode::OK);
 }
 
+#[tokio::test]
+async fn v1_filesystem_respects_gitignore_and_ignore_globs() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let repo = tempfile::tempdir().expect("create repo dir");
+    fs::create_dir_all(repo.path().join(".git")).expect("create .git");
+    fs::create_dir_all(repo.path().join("node_modules/pkg")).expect("create node_modules");
+    fs::write(repo.path().join(".gitignore"), "node_modules/\n*.log\n").expect("write gitignore");
+    fs::write(repo.path().join("debug.log"), "log").expect("write log");
+    fs::write(repo.path().join("main.rs"), "fn main() {}").expect("write source");
+    fs::write(repo.path().join("notes.md"), "notes").expect("write notes");
+    fs::create_dir_all(repo.path().join("src")).expect("create src");
+    fs::write(repo.path().join("src/.gitignore"), "generated.rs\n")
+        .expect("write nested gitignore");
+    let root = repo.path().to_string_lossy().to_string();
+
+    let list = |query: String| {
+        let app = test_app.app.clone();
+        async move {
+            let (status, _, body) = send_request(
+                &app,
+                Method::GET,
+                &format!("/v1/fs/entries?{query}"),
+                None,
+                &[],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let mut names = parse_json(&body)
+                .as_array()
+                .expect("array")
+                .iter()
+                .map(|entry| entry["name"].as_str().expect("name").to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        }
+    };
+
+    assert_eq!(
+        list(format!("path={root}")).await,
+        vec![".git", ".gitignore", "main.rs", "notes.md", "src"]
+    );
+    assert_eq!(
+        list(format!("path={root}&ignoreGlobs=*.md,.git")).await,
+        vec![".gitignore", "main.rs", "src"]
+    );
+    assert_eq!(
+        list(format!("path={root}&respectGitignore=false")).await,
+        vec![
+            ".git",
+            ".gitignore",
+            "debug.log",
+            "main.rs",
+            "node_modules",
+            "notes.md",
+            "src"
+        ]
+    );
+
+    let mut builder = tar::Builder::new(Vec::new());
+    for (name, contents) in [
+        ("node_modules/pkg/index.js", "module.exports = {}"),
+        ("src/lib.rs", "pub fn lib() {}"),
+        ("src/trace.log", "trace"),
+        ("src/generated.rs", "// generated"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, name, contents.as_bytes())
+            .expect("append tar entry");
+    }
+    let archive = builder.into_inner().expect("finish tar");
+
+    let (status, _, body) = send_request_raw(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/fs/upload-batch?path={root}"),
+        Some(archive),
+        &[],
+        Some("application/x-tar"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed = parse_json(&body);
+    assert_eq!(parsed["skipped"], 3);
+    assert_eq!(parsed["paths"].as_array().expect("paths").len(), 1);
+    assert!(repo.path().join("src/lib.rs").exists());
+    assert!(!repo.path().join("src/generated.rs").exists());
+    assert!(!repo.path().join("src/trace.log").exists());
+    assert!(!repo.path().join("node_modules/pkg/index.js").exists());
+}
+
+#[tokio::test]
+async fn v1_filesystem_entries_preview() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let dir = tempfile::tempdir().expect("create temp dir");
+    fs::write(dir.path().join("README.md"), "\n# Title\nbody\n").expect("write readme");
+    fs::write(dir.path().join("blob.bin"), [0u8, 159, 146, 150]).expect("write binary");
+    fs::create_dir_all(dir.path().join("nested")).expect("create nested");
+    let root = dir.path().to_string_lossy().to_string();
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/entries?path={root}&preview=true"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = parse_json(&body).as_array().cloned().expect("array");
+    let entry = |name: &str| {
+        entries
+            .iter()
+            .find(|entry| entry["name"] == name)
+            .cloned()
+            .expect("entry present")
+    };
+
+    let readme = entry("README.md");
+    assert_eq!(readme["mimeType"], "text/markdown");
+    assert_eq!(readme["isBinary"], false);
+    assert_eq!(readme["preview"], "# Title");
+
+    let blob = entry("blob.bin");
+    assert_eq!(blob["mimeType"], "application/octet-stream");
+    assert_eq!(blob["isBinary"], true);
+    assert!(blob.get("preview").is_none());
+
+    let nested = entry("nested");
+    assert!(nested.get("mimeType").is_none());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/entries?path={root}"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(parse_json(&body)
+        .as_array()
+        .expect("array")
+        .iter()
+        .all(|entry| entry.get("preview").is_none() && entry.get("mimeType").is_none()));
+}
+
+#[tokio::test]
+async fn v1_filesystem_errors_are_typed() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let dir = tempfile::tempdir().expect("create temp dir");
+    fs::write(dir.path().join("a.txt"), "a").expect("write a");
+    fs::write(dir.path().join("b.txt"), "b").expect("write b");
+    let root = dir.path().to_string_lossy().to_string();
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/stat?path={root}/missing.txt"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let problem = parse_json(&body);
+    assert_eq!(problem["type"], "urn:sandbox-agent:error:not_found");
+    assert_eq!(problem["details"]["path"], format!("{root}/missing.txt"));
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/entries?path={root}/a.txt"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        parse_json(&body)["type"],
+        "urn:sandbox-agent:error:not_a_directory"
+    );
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/fs/move",
+        Some(json!({
+            "from": format!("{root}/a.txt"),
+            "to": format!("{root}/b.txt"),
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let problem = parse_json(&body);
+    assert_eq!(problem["type"], "urn:sandbox-agent:error:already_exists");
+    assert_eq!(problem["details"]["path"], format!("{root}/b.txt"));
+}
+
+#[tokio::test]
+async fn v1_filesystem_search_streams_matches() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let repo = tempfile::tempdir().expect("create repo dir");
+    fs::create_dir_all(repo.path().join(".git")).expect("create .git");
+    fs::create_dir_all(repo.path().join("sub")).expect("create sub");
+    fs::write(repo.path().join(".gitignore"), "ignored.txt\n").expect("write .gitignore");
+    fs::write(
+        repo.path().join("a.txt"),
+        "hello world\nnope\nsay Hello again\n",
+    )
+    .expect("write a");
+    fs::write(repo.path().join("sub/b.rs"), "fn hello() {}\n").expect("write b");
+    fs::write(repo.path().join("ignored.txt"), "hello\n").expect("write ignored");
+    fs::write(repo.path().join("blob.bin"), b"\0hello\n").expect("write blob");
+    let root = repo.path().to_string_lossy().to_string();
+
+    let search = |params: String| {
+        let app = test_app.app.clone();
+        async move {
+            let (status, headers, body) = send_request_raw(
+                &app,
+                Method::GET,
+                &format!("/v1/fs/search?{params}"),
+                None,
+                &[],
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{params}");
+            assert_eq!(
+                headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok()),
+                Some("application/x-ndjson")
+            );
+            String::from_utf8_lossy(&body)
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(line).expect("search line"))
+                .collect::<Vec<_>>()
+        }
+    };
+
+    let events = search(format!("q=hello&path={root}")).await;
+    let mut found = events
+        .iter()
+        .filter(|event| event["type"] == "match")
+        .map(|event| {
+            let path = event["path"].as_str().expect("path");
+            let name = path.strip_prefix(&format!("{root}/")).expect("under root");
+            (name.to_string(), event["line"].as_u64().expect("line"))
+        })
+        .collect::<Vec<_>>();
+    found.sort();
+    assert_eq!(
+        found,
+        vec![("a.txt".to_string(), 1), ("sub/b.rs".to_string(), 1)]
+    );
+    let done = events.last().expect("done event");
+    assert_eq!(done["type"], "done");
+    assert_eq!(done["matches"], 2);
+    assert_eq!(done["truncated"], false);
+
+    let events = search(format!("q=hello&caseSensitive=false&path={root}/a.txt")).await;
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[1]["line"], 3);
+    assert_eq!(events[1]["column"], 5);
+    assert_eq!(events[1]["text"], "say Hello again");
+
+    let events = search(format!("q=h%5Bea%5Dllo%5C(&regex=true&path={root}")).await;
+    assert_eq!(events.len(), 2);
+    assert!(events[0]["path"].as_str().expect("path").ends_with("b.rs"));
+
+    let events = search(format!("q=hello&maxResults=1&path={root}")).await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1]["truncated"], true);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/search?q=(&regex=true&path={root}"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, headers, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/search?q=world&path={root}"),
+        None,
+        &[("accept", "text/event-stream")],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream")));
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("event: match"));
+    assert!(body.contains("event: done"));
+}
+
+#[tokio::test]
+async fn v1_filesystem_archive_downloads_tar_gz_and_zip() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let temp = tempfile::tempdir().expect("create temp dir");
+    let out = temp.path().join("out");
+    fs::create_dir_all(out.join("nested")).expect("create nested");
+    fs::create_dir_all(out.join("skip")).expect("create skip");
+    fs::write(out.join("report.txt"), "done\n").expect("write report");
+    fs::write(out.join("nested/data.json"), "{}").expect("write data");
+    fs::write(out.join("skip/tmp.log"), "noise").expect("write log");
+    let root = out.to_string_lossy().to_string();
+
+    let (status, headers, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/archive?path={root}&ignoreGlobs=skip"),
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+        Some("application/gzip")
+    );
+    assert_eq!(
+        headers
+            .get(header::CONTENT_DISPOSITION)
+            .and_then(|value| value.to_str().ok()),
+        Some("attachment; filename=\"out.tar.gz\"")
+    );
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(body.as_slice()));
+    let mut files = Vec::new();
+    for entry in archive.entries().expect("tar entries") {
+        let mut entry = entry.expect("tar entry");
+        if entry.header().entry_type().is_file() {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).expect("read entry");
+            let path = entry.path().expect("entry path").display().to_string();
+            files.push((path, contents));
+        }
+    }
+    files.sort();
+    assert_eq!(
+        files,
+        vec![
+            ("out/nested/data.json".to_string(), "{}".to_string()),
+            ("out/report.txt".to_string(), "done\n".to_string()),
+        ]
+    );
+
+    let (status, headers, body) = send_request_raw(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/archive?path={root}&format=zip"),
+        None,
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+        Some("application/zip")
+    );
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(body)).expect("zip archive");
+    let mut names = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "out/",
+            "out/nested/",
+            "out/nested/data.json",
+            "out/report.txt",
+            "out/skip/",
+            "out/skip/tmp.log",
+        ]
+    );
+    let mut report = String::new();
+    archive
+        .by_name("out/report.txt")
+        .expect("report entry")
+        .read_to_string(&mut report)
+        .expect("read report");
+    assert_eq!(report, "done\n");
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/archive?path={root}/report.txt"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/archive?path={root}/missing"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn v1_filesystem_watch_streams_changes() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let repo = tempfile::tempdir().expect("create repo dir");
+    fs::create_dir_all(repo.path().join(".git")).expect("create .git");
+    fs::write(repo.path().join(".gitignore"), "ignored.txt\n").expect("write .gitignore");
+    let root = fs::canonicalize(repo.path()).expect("canonical root");
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(format!("/v1/fs/watch?path={}", root.display()))
+        .header("accept", "text/event-stream")
+        .body(Body::empty())
+        .expect("build request");
+    let response = test_app
+        .app
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("watch response");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    fs::write(root.join("ignored.txt"), "skip").expect("write ignored");
+    fs::write(root.join(".git/index"), "skip").expect("write git index");
+    fs::write(root.join("a.txt"), "one").expect("write a");
+    fs::create_dir(root.join("sub")).expect("create sub");
+    fs::remove_file(root.join("a.txt")).expect("remove a");
+
+    let a_path = root.join("a.txt").to_string_lossy().to_string();
+    let mut stream = response.into_body().into_data_stream();
+    let events = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut buffer = String::new();
+        let mut events = Vec::new();
+        loop {
+            let bytes = stream
+                .next()
+                .await
+                .expect("SSE stream ended early")
+                .expect("stream chunk");
+            buffer.push_str(&String::from_utf8_lossy(&bytes));
+            while let Some(end) = buffer.find("\n\n") {
+                let frame = buffer[..end].to_string();
+                buffer.drain(..end + 2);
+                if frame.contains("data:") {
+                    let event = parse_sse_data(&frame);
+                    let done = event["type"] == "delete" && event["path"] == a_path.as_str();
+                    events.push(event);
+                    if done {
+                        return events;
+                    }
+                }
+            }
+        }
+    })
+    .await
+    .expect("timed out reading watch events");
+
+    assert!(events.contains(&json!({ "type": "create", "path": a_path, "isDir": false })));
+    assert!(events
+        .iter()
+        .any(|event| event["type"] == "modify" && event["path"] == a_path.as_str()));
+    assert!(events.contains(&json!({
+        "type": "create",
+        "path": root.join("sub").to_string_lossy(),
+        "isDir": true,
+    })));
+    assert!(
+        events.iter().all(|event| {
+            let path = event["path"].as_str().unwrap_or_default();
+            !path.ends_with("ignored.txt") && !path.contains("/.git/")
+        }),
+        "{events:?}"
+    );
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/watch?path={}/missing", root.display()),
+        None,
+        &[("accept", "text/event-stream")],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn v1_filesystem_chunked_upload_resumes_and_verifies_checksums() {
+    use sha2::{Digest, Sha256};
+
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let temp = tempfile::tempdir().expect("create temp dir");
+    let dest = temp.path().join("data/set.bin");
+    let payload = (0..200_000u32)
+        .map(|value| (value % 251) as u8)
+        .collect::<Vec<_>>();
+    let sha = |bytes: &[u8]| format!("{:x}", Sha256::digest(bytes));
+    // Left behind by an upload of an earlier server process.
+    let orphan = temp.path().join("data/.set.bin.upload_stale.upload");
+    std::fs::create_dir_all(temp.path().join("data")).expect("create data dir");
+    std::fs::write(&orphan, b"partial").expect("write orphaned staging file");
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/fs/upload/init",
+        Some(json!({
+            "path": dest.to_string_lossy(),
+            "size": payload.len(),
+            "sha256": sha(&payload),
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let init = parse_json(&body);
+    let upload_id = init["uploadId"].as_str().expect("upload id").to_string();
+    assert_eq!(init["receivedBytes"], 0);
+    assert!(upload_id.len() > "upload_".len() + 16, "{upload_id}");
+    assert!(!orphan.exists());
+
+    let put_chunk = |offset: usize, bytes: Vec<u8>, checksum: String| {
+        let app = test_app.app.clone();
+        let uri = format!("/v1/fs/upload/{upload_id}/chunk?offset={offset}&sha256={checksum}");
+        async move {
+            let (status, _, body) = send_request_raw(
+                &app,
+                Method::PUT,
+                &uri,
+                Some(bytes),
+                &[],
+                Some("application/octet-stream"),
+            )
+            .await;
+            (status, body)
+        }
+    };
+
+    let first = payload[..120_000].to_vec();
+    let (status, body) = put_chunk(0, first.clone(), sha(&first)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["receivedBytes"], 120_000);
+
+    // A corrupted chunk is rejected and does not advance the upload.
+    let rest = payload[120_000..].to_vec();
+    let mut corrupted = rest.clone();
+    corrupted[0] ^= 0xff;
+    let (status, _) = put_chunk(120_000, corrupted, sha(&rest)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = put_chunk(150_000, rest.clone(), sha(&rest)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/fs/upload/{upload_id}/complete"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(
+        status,
+        StatusCode::CONFLICT,
+        "{}",
+        String::from_utf8_lossy(&body)
+    );
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/upload/{upload_id}"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let resume_at = parse_json(&body)["receivedBytes"]
+        .as_u64()
+        .expect("received bytes") as usize;
+    assert_eq!(resume_at, 120_000);
+
+    // Resending from an earlier offset overwrites the overlap.
+    let tail = payload[100_000..].to_vec();
+    let (status, body) = put_chunk(100_000, tail.clone(), sha(&tail)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["receivedBytes"], payload.len());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/fs/upload/{upload_id}/complete"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let completed = parse_json(&body);
+    assert_eq!(completed["bytesWritten"], payload.len());
+    assert_eq!(completed["sha256"], sha(&payload));
+    assert_eq!(fs::read(&dest).expect("read upload"), payload);
+    assert_eq!(
+        fs::read_dir(dest.parent().expect("parent"))
+            .expect("list data dir")
+            .count(),
+        1,
+        "staging file is moved into place"
+    );
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/fs/upload/{upload_id}"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/fs/upload/init",
+        Some(json!({ "path": dest.to_string_lossy() })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/fs/upload/init",
+        Some(json!({ "path": dest.to_string_lossy(), "overwrite": true })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let upload_id = parse_json(&body)["uploadId"]
+        .as_str()
+        .expect("upload id")
+        .to_string();
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        &format!("/v1/fs/upload/{upload_id}"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fs::read(&dest).expect("read upload"), payload);
+    assert_eq!(
+        fs::read_dir(dest.parent().expect("parent"))
+            .expect("list data dir")
+            .count(),
+        1,
+        "aborting removes the staging file"
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn v1_filesystem_confined_to_fs_roots() {
+    let root = tempfile::tempdir().expect("create root dir");
+    let outside = tempfile::tempdir().expect("create outside dir");
+    fs::write(outside.path().join("secret.txt"), "secret").expect("write secret");
+    std::os::unix::fs::symlink(outside.path(), root.path().join("escape")).expect("symlink");
+    let test_app = TestApp::with_fs_roots(AuthConfig::disabled(), vec![root.path().to_path_buf()]);
+    let root_path = fs::canonicalize(root.path()).expect("canonical root");
+    let outside_str = outside.path().to_string_lossy().to_string();
+
+    let (status, _, _) = send_request_raw(
+        &test_app.app,
+        Method::PUT,
+        "/v1/fs/file?path=notes/a.txt",
+        Some(b"hello".to_vec()),
+        &[],
+        Some("application/octet-stream"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(root_path.join("notes/a.txt").exists());
+
+    let denied = [
+        format!("/v1/fs/stat?path={outside_str}/secret.txt"),
+        format!("/v1/fs/stat?path={}/../x", root_path.display()),
+        "/v1/fs/file?path=escape/secret.txt".to_string(),
+        format!("/v1/config/mcp?directory={outside_str}&mcpName=x"),
+    ];
+    for uri in denied {
+        let (status, _, body) = send_request(&test_app.app, Method::GET, &uri, None, &[]).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{uri}");
+        assert_eq!(
+            parse_json(&body)["type"],
+            "urn:sandbox-agent:error:permission_denied"
+        );
+    }
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        &format!("/v1/acp/outside?agent=mock&directory={outside_str}/work"),
+        Some(initialize_payload()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(!outside.path().join("work").exists());
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/capabilities", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["features"]["fsRoots"]["enabled"], true);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn v1_audit_records_fs_mutations_and_exec() {
+    let tokens =
+        parse_tokens(r#"{"tokens": [{"name": "ci", "token": "ci-token", "scopes": ["admin"]}]}"#)
+            .expect("parse tokens");
+    let test_app = TestApp::new(AuthConfig::disabled().with_tokens(tokens));
+    let auth = [("authorization", "Bearer ci-token")];
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let root = fs::canonicalize(dir.path()).expect("canonical dir");
+
+    let (status, _, _) = send_request_raw(
+        &test_app.app,
+        Method::PUT,
+        &format!("/v1/fs/file?path={}/a.txt", root.display()),
+        Some(b"hello".to_vec()),
+        &auth,
+        Some("application/octet-stream"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let move_body = json!({
+        "from": root.join("a.txt"),
+        "to": root.join("b.txt")
+    });
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/fs/move",
+        Some(move_body),
+        &auth,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::DELETE,
+        &format!("/v1/fs/entry?path={}/b.txt", root.display()),
+        None,
+        &auth,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let exec_headers = [auth[0], ("accept", "text/event-stream")];
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/exec",
+        Some(json!({ "command": "true", "cwd": root, "env": { "SECRET": "hunter2" } })),
+        &exec_headers,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/audit", None, &auth).await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = parse_json(&body)["entries"]
+        .as_array()
+        .cloned()
+        .expect("entries");
+    let kinds = entries
+        .iter()
+        .map(|entry| entry["kind"].as_str().expect("kind"))
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["fsWrite", "fsMove", "fsDelete", "exec"]);
+    assert!(entries.iter().all(|entry| entry["actor"] == "token:ci"));
+    assert_eq!(entries[0]["id"], 1);
+    assert_eq!(
+        entries[1]["destination"],
+        root.join("b.txt").to_string_lossy().as_ref()
+    );
+    assert_eq!(entries[3]["action"], "true");
+    assert_eq!(entries[3]["details"]["env"], json!(["SECRET"]));
+    assert!(!body.windows(7).any(|window| window == b"hunter2"));
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/audit?kind=fsMove&limit=5",
+        None,
+        &auth,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body)["entries"].as_array().map(Vec::len),
+        Some(1)
+    );
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/audit?sinceMs=99999999999999",
+        None,
+        &auth,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["entries"], json!([]));
+}
+
+#[tokio::test]
+#[serial]
+async fn v1_codex_config_round_trips_and_keeps_other_keys() {
+    let codex_home = tempfile::tempdir().expect("create codex home");
+    let config_path = codex_home.path().join("config.toml");
+    fs::write(
+        &config_path,
+        "# managed by hand\nmodel = \"o3\"\n\n[mcp_servers.docs]\ncommand = \"docs-mcp\"\n",
+    )
+    .expect("write config");
+    let _codex_home = EnvVarGuard::set("CODEX_HOME", &codex_home.path().to_string_lossy());
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/agents/codex/config",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["model"], "o3");
+
+    let config = json!({
+        "model": "gpt-5",
+        "approvalPolicy": "on-request",
+        "developerInstructions": "Never push to main.",
+        "profile": "fast",
+        "profiles": { "fast": { "model": "gpt-5-mini", "modelReasoningEffort": "low" } },
+        "modelProviders": {
+            "proxy": { "baseUrl": "https://llm.example.com/v1", "envKey": "PROXY_KEY", "wireApi": "chat" }
+        }
+    });
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::PUT,
+        "/v1/agents/codex/config",
+        Some(config.clone()),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body), config);
+    let written = fs::read_to_string(&config_path).expect("read config");
+    assert!(written.starts_with("# managed by hand\nmodel = \"gpt-5\""));
+    assert!(written.contains("[mcp_servers.docs]"));
+    assert!(written.contains("[profiles.fast]"));
+    assert!(written.contains("wire_api = \"chat\""));
+    assert!(written.contains("developer_instructions = \"Never push to main.\""));
+
+    for invalid in [
+        json!({ "sandboxMode": "everything" }),
+        json!({ "profile": "missing" }),
+        json!({ "modelProviders": { "bad": { "baseUrl": "ftp://example.com" } } }),
+    ] {
+        let (status, _, _) = send_request(
+            &test_app.app,
+            Method::PUT,
+            "/v1/agents/codex/config",
+            Some(invalid),
+            &[],
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+    assert_eq!(
+        fs::read_to_string(&config_path).expect("read config"),
+        written
+    );
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/agents/claude/config",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn v1_opencode_config_uses_patch_and_needs_the_server() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::PUT,
+        "/v1/agents/opencode/config",
+        Some(json!({})),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::PATCH,
+        "/v1/agents/codex/config",
+        Some(json!({ "model": "gpt-5" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // OpenCode is not installed, so the managed server cannot start.
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::PATCH,
+        "/v1/agents/opencode/config",
+        Some(json!({ "model": "openai/gpt-5" })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert!(parse_json(&body)["detail"]
+        .as_str()
+        .is_some_and(|detail| detail.contains("failed to start OpenCode server")));
+}
+
+#[tokio::test]
+async fn v1_agent_models_refresh_falls_back_to_builtin_lists() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/agents/mock/models/refresh",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body),
+        json!({
+            "agent": "mock",
+            "source": "builtin",
+            "defaultModel": "mock",
+            "models": [{ "id": "mock", "name": "Mock" }]
+        })
+    );
+
+    // Only installed agents are listed; the mock agent is built in.
+    let (status, _, body) = send_request(&test_app.app, Method::GET, "/v1/models", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        parse_json(&body)["models"],
+        json!([{
+            "agent": "mock",
+            "id": "mock",
+            "name": "Mock",
+            "default": true,
+            "source": "builtin"
+        }])
+    );
+
+    // OpenCode is not installed, so its model list cannot be fetched.
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/agents/opencode/models/refresh",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/agents/nope/models/refresh",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[serial]
+async fn v1_agent_capabilities_follow_initialize_and_overrides() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let overrides = dir.path().join("capabilities.json");
+    std::fs::write(&overrides, r#"{"mock": {"planMode": false}}"#).expect("write overrides");
+    let _overrides = EnvVarGuard::set(
+        "SANDBOX_AGENT_AGENT_CAPABILITIES_FILE",
+        overrides.to_str().expect("utf-8 path"),
+    );
+    let test_app = TestApp::new(AuthConfig::disabled());
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/agents/mock", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let agent = parse_json(&body);
+    assert_eq!(agent["capabilitiesSource"], "builtin");
+    assert_eq!(agent["capabilities"]["images"], true);
+    assert_eq!(agent["capabilities"]["planMode"], false);
+    assert_eq!(agent["capabilityOverrides"], json!(["planMode"]));
+
+    // The mock agent's initialize response reports no image or embedded-context support.
+    bootstrap_server(&test_app.app, "caps", "mock").await;
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/agents/mock", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let agent = parse_json(&body);
+    assert_eq!(agent["capabilitiesSource"], "probed");
+    assert_eq!(agent["capabilities"]["images"], false);
+    assert_eq!(agent["capabilities"]["fileAttachments"], false);
+    assert_eq!(agent["capabilities"]["planMode"], false);
+
+    // The mock agent has no CLI or server to probe; the response still reflects initialize.
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/agents/mock/capabilities/refresh",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let refreshed = parse_json(&body);
+    assert_eq!(refreshed["agent"], "mock");
+    assert_eq!(refreshed["source"], "probed");
+    assert_eq!(refreshed["capabilities"]["images"], false);
+    assert_eq!(refreshed["overrides"], json!(["planMode"]));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/agents/nope/capabilities/refresh",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn v1_agent_server_restart_lists_server_instances() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "restart", "mock").await;
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/agents/mock", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let server_status = &parse_json(&body)["serverStatus"];
+    assert_eq!(server_status["status"], "running");
+    assert_eq!(server_status["instances"][0]["id"], "restart");
+
+    // Only OpenCode has managed servers to start, stop or restart.
+    for agent in ["mock", "nope"] {
+        for action in ["start", "stop", "restart"] {
+            let (status, _, _) = send_request(
+                &test_app.app,
+                Method::POST,
+                &format!("/v1/agents/{agent}/server/{action}"),
+                None,
+                &[],
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/agents/opencode/server/stop",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["status"], "stopped");
+}
+
+#[tokio::test]
+async fn v1_exec_streams_output_and_enforces_limits() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let workdir = tempfile::tempdir().expect("create workdir");
+    let root = fs::canonicalize(workdir.path()).expect("canonical workdir");
+    let sse = [("accept", "text/event-stream")];
+
+    let exec = |request: Value| {
+        let app = test_app.app.clone();
+        async move {
+            let (status, _, body) =
+                send_request(&app, Method::POST, "/v1/exec", Some(request), &sse).await;
+            assert_eq!(status, StatusCode::OK);
+            String::from_utf8_lossy(&body)
+                .split("\n\n")
+                .filter(|frame| frame.contains("data:"))
+                .map(parse_sse_data)
+                .collect::<Vec<_>>()
+        }
+    };
+    let output = |events: &[Value], kind: &str| {
+        events
+            .iter()
+            .filter(|event| event["type"] == kind)
+            .map(|event| event["data"].as_str().expect("data").to_string())
+            .collect::<String>()
+    };
+
+    let events = exec(json!({
+        "command": "sh",
+        "args": ["-c", "pwd; echo oops >&2; echo \"$GREETING\"; exit 3"],
+        "cwd": root,
+        "env": { "GREETING": "hi" }
+    }))
+    .await;
+    assert_eq!(events[0]["type"], "start");
+    assert_eq!(events[0]["cwd"], root.to_string_lossy().as_ref());
+    assert_eq!(
+        output(&events, "stdout"),
+        format!("{}\nhi\n", root.display())
+    );
+    assert_eq!(output(&events, "stderr"), "oops\n");
+    let exit = events.last().expect("exit event");
+    assert_eq!(exit["type"], "exit");
+    assert_eq!(exit["exitCode"], 3);
+    assert_eq!(exit["timedOut"], false);
+    assert_eq!(exit["outputTruncated"], false);
+
+    let events = exec(json!({
+        "command": "sh",
+        "args": ["-c", "printf 0123456789"],
+        "cwd": root,
+        "maxOutputBytes": 4
+    }))
+    .await;
+    assert_eq!(output(&events, "stdout"), "0123");
+    assert_eq!(events.last().expect("exit event")["outputTruncated"], true);
+
+    let events = exec(json!({
+        "command": "sleep",
+        "args": ["30"],
+        "cwd": root,
+        "timeoutMs": 100
+    }))
+    .await;
+    let exit = events.last().expect("exit event");
+    assert_eq!(exit["timedOut"], true);
+    assert_eq!(exit["exitCode"], Value::Null);
+    assert_eq!(exit["signal"], 9);
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/v1/exec")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("accept", "text/event-stream")
+        .body(Body::from(
+            json!({ "command": "sleep", "args": ["30"], "cwd": root }).to_string(),
+        ))
+        .expect("build request");
+    let response = test_app
+        .app
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("exec response");
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut stream = response.into_body().into_data_stream();
+    let exit = tokio::time::timeout(Duration::from_secs(10), async {
+        let mut buffer = String::new();
+        loop {
+            let bytes = stream
+                .next()
+                .await
+                .expect("SSE stream ended early")
+                .expect("stream chunk");
+            buffer.push_str(&String::from_utf8_lossy(&bytes));
+            while let Some(end) = buffer.find("\n\n") {
+                let frame = buffer[..end].to_string();
+                buffer.drain(..end + 2);
+                if !frame.contains("data:") {
+                    continue;
+                }
+                let event = parse_sse_data(&frame);
+                if event["type"] == "start" {
+                    let exec_id = event["execId"].as_str().expect("exec id");
+                    let (status, _, _) = send_request(
+                        &test_app.app,
+                        Method::POST,
+                        &format!("/v1/exec/{exec_id}/kill"),
+                        None,
+                        &[],
+                    )
+                    .await;
+                    assert_eq!(status, StatusCode::NO_CONTENT);
+                } else if event["type"] == "exit" {
+                    return event;
+                }
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for killed command");
+    assert_eq!(exit["killed"], true);
+    assert_eq!(exit["timedOut"], false);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/exec/exec_missing/kill",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/exec",
+        Some(json!({ "command": "definitely-not-a-command", "cwd": root })),
+        &sse,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/exec",
+        Some(json!({ "command": "true", "cwd": root.join("missing") })),
+        &sse,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/exec",
+        Some(json!({ "command": "true", "cwd": root })),
+        &[("accept", "application/json")],
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+}
+
+#[tokio::test]
+async fn v1_git_push_and_pull_stream_progress() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let scratch = tempfile::tempdir().expect("create scratch dir");
+    let root = fs::canonicalize(scratch.path()).expect("canonical scratch dir");
+    let remote = root.join("remote.git");
+    fs::create_dir(&remote).expect("create remote");
+    git(&remote, &["init", "--quiet", "--bare", "-b", "main"]);
+    let work = root.join("work");
+    let other = root.join("other");
+    for dir in [&work, &other] {
+        fs::create_dir(dir).expect("create clone");
+        git(dir, &["init", "--quiet", "-b", "main"]);
+        git(dir, &["remote", "add", "origin", &remote.to_string_lossy()]);
+    }
+    fs::write(work.join("README.md"), "hello\n").expect("write readme");
+    git(&work, &["add", "README.md"]);
+    git(&work, &["commit", "--quiet", "-m", "init"]);
+    let sse = [("accept", "text/event-stream")];
+
+    let sync = |path: &str, request: Value| {
+        let app = test_app.app.clone();
+        let path = path.to_string();
+        async move {
+            let (status, _, body) =
+                send_request(&app, Method::POST, &path, Some(request), &sse).await;
+            assert_eq!(status, StatusCode::OK);
+            String::from_utf8_lossy(&body)
+                .split("\n\n")
+                .filter(|frame| frame.contains("data:"))
+                .map(parse_sse_data)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    let events = sync(
+        "/v1/git/push",
+        json!({
+            "path": work,
+            "branch": "main",
+            "credentials": { "token": "not-needed-for-local-remotes" }
+        }),
+    )
+    .await;
+    assert_eq!(events[0]["type"], "start");
+    assert_eq!(events[0]["operation"], "push");
+    assert_eq!(events[0]["remote"], "origin");
+    assert!(events
+        .iter()
+        .any(|event| event["type"] == "progress" && event["phase"] == "Writing objects"));
+    let done = events.last().expect("done event");
+    assert_eq!(done["type"], "done");
+    assert_eq!(done["success"], true);
+    assert_eq!(
+        git(&remote, &["rev-parse", "main"]),
+        git(&work, &["rev-parse", "HEAD"])
+    );
+    assert!(!fs::read_to_string(work.join(".git/config"))
+        .expect("read config")
+        .contains("extraHeader"));
+
+    let events = sync("/v1/git/pull", json!({ "path": other, "branch": "main" })).await;
+    assert_eq!(events.last().expect("done event")["success"], true);
+    assert_eq!(
+        fs::read_to_string(other.join("README.md")).expect("pulled readme"),
+        "hello\n"
+    );
+
+    let events = sync(
+        "/v1/git/pull",
+        json!({ "path": other, "remote": "missing" }),
+    )
+    .await;
+    let done = events.last().expect("done event");
+    assert_eq!(done["success"], false);
+    assert_eq!(done["exitCode"], 1);
+    assert!(events.iter().any(|event| event["type"] == "output"
+        && event["line"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("missing")));
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/git/push",
+        Some(json!({ "path": work, "remote": "--receive-pack=touch pwned" })),
+        &sse,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn v1_mcp_serves_sandbox_tools() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let scratch = tempfile::tempdir().expect("create scratch dir");
+    let root = fs::canonicalize(scratch.path()).expect("canonical scratch dir");
+    git(&root, &["init", "--quiet", "-b", "main"]);
+    fs::write(root.join("notes.txt"), "one\n").expect("write notes");
+    git(&root, &["add", "notes.txt"]);
+    git(&root, &["commit", "--quiet", "-m", "init"]);
+
+    let rpc = |id: u64, method: &str, params: Value| json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    let call = |id: u64, name: &str, arguments: Value| {
+        rpc(
+            id,
+            "tools/call",
+            json!({ "name": name, "arguments": arguments }),
+        )
+    };
+    let post = |message: Value| {
+        let app = test_app.app.clone();
+        async move {
+            let (status, _, body) =
+                send_request(&app, Method::POST, "/v1/mcp", Some(message), &[]).await;
+            (
+                status,
+                if body.is_empty() {
+                    Value::Null
+                } else {
+                    parse_json(&body)
+                },
+            )
+        }
+    };
+
+    let (status, initialized) = post(rpc(
+        1,
+        "initialize",
+        json!({ "protocolVersion": "2025-03-26" }),
+    ))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(initialized["result"]["protocolVersion"], "2025-03-26");
+    assert_eq!(initialized["result"]["serverInfo"]["name"], "sandbox-agent");
+
+    let (status, _) =
+        post(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let (_, listed) = post(rpc(2, "tools/list", json!({}))).await;
+    let names = listed["result"]["tools"]
+        .as_array()
+        .expect("tools")
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap_or_default().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "read_file",
+            "write_file",
+            "exec",
+            "git_diff",
+            "session_events"
+        ]
+    );
+
+    let notes = root.join("notes.txt").to_string_lossy().to_string();
+    let (_, written) = post(call(
+        3,
+        "write_file",
+        json!({ "path": notes, "content": "one\ntwo\n" }),
+    ))
+    .await;
+    assert_eq!(written["result"]["isError"], false, "{written}");
+    let (_, read) = post(call(4, "read_file", json!({ "path": notes }))).await;
+    assert_eq!(read["result"]["content"][0]["text"], "one\ntwo\n");
+
+    let (_, diff) = post(call(5, "git_diff", json!({ "path": root }))).await;
+    let diff = diff["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    assert!(diff.contains("+two"), "{diff}");
+
+    let (_, ran) = post(call(
+        6,
+        "exec",
+        json!({ "command": "sh", "args": ["-c", "echo built; exit 2"], "cwd": root }),
+    ))
+    .await;
+    assert_eq!(ran["result"]["isError"], true);
+    assert_eq!(
+        ran["result"]["content"][0]["text"],
+        "built\n\n[exit code 2]"
+    );
+
+    let (_, missing) = post(call(7, "read_file", json!({ "path": root.join("absent") }))).await;
+    assert_eq!(missing["result"]["isError"], true);
+    let (_, events) = post(call(8, "session_events", json!({ "serverId": "unknown" }))).await;
+    assert_eq!(events["result"]["isError"], true);
+
+    let (_, unknown) = post(call(9, "rm_rf", json!({}))).await;
+    assert_eq!(unknown["error"]["code"], -32602);
+    let (_, unknown) = post(rpc(10, "resources/list", json!({}))).await;
+    assert_eq!(unknown["error"]["code"], -32601);
+
+    let (_, _, body) = send_request(&test_app.app, Method::GET, "/v1/audit", None, &[]).await;
+    let kinds = parse_json(&body)["entries"]
+        .as_array()
+        .expect("audit entries")
+        .iter()
+        .filter(|entry| entry["details"]["mcpTool"].is_string())
+        .map(|entry| entry["kind"].as_str().unwrap_or_default().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["fsWrite", "exec"]);
+}
+
+#[tokio::test]
+async fn v1_proxy_forwards_to_allowlisted_ports() {
+    let upstream = TcpListener::bind("127.0.0.1:0").expect("bind upstream");
+    let port = upstream.local_addr().expect("upstream address").port();
+    std::thread::spawn(move || {
+        if let Ok((mut stream, _)) = upstream.accept() {
+            let mut buffer = [0_u8; 4096];
+            let read = stream.read(&mut buffer).unwrap_or(0);
+            let head = String::from_utf8_lossy(&buffer[..read]).to_lowercase();
+            let body = head
+                .split("\r\n\r\n")
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            let response = format!(
+                "HTTP/1.1 201 Created\r\nX-Upstream: yes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    let closed_port = {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind closed port");
+        listener.local_addr().expect("closed address").port()
+    };
+
+    let install_dir = tempfile::tempdir().expect("create temp install dir");
+    let manager = AgentManager::new(install_dir.path()).expect("create agent manager");
+    let state = AppState::new(AuthConfig::with_token("secret-token".to_string()), manager)
+        .with_proxy_ports(vec![port..=port, closed_port..=closed_port]);
+    let app = build_router(state);
+    let auth = [("authorization", "Bearer secret-token")];
+
+    let (status, headers, body) = send_request(
+        &app,
+        Method::GET,
+        &format!("/v1/proxy/{port}/preview/a%20b?x=1"),
+        None,
+        &auth,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(headers.get("x-upstream").unwrap(), "yes");
+    let forwarded = String::from_utf8_lossy(&body);
+    assert!(forwarded.starts_with("get /preview/a%20b?x=1 http/1.1"));
+    assert!(forwarded.contains(&format!("x-forwarded-prefix: /v1/proxy/{port}")));
+    assert!(!forwarded.contains("secret-token"));
+
+    let (status, _, _) = send_request(
+        &app,
+        Method::GET,
+        &format!("/v1/proxy/{closed_port}/"),
+        None,
+        &auth,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    let (status, _, _) = send_request(&app, Method::GET, "/v1/proxy/1/", None, &auth).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _, _) = send_request(&app, Method::GET, "/v1/proxy/not-a-port", None, &auth).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        &format!("/v1/proxy/{port}/"),
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn v1_debug_logs_returns_recent_records() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let subscriber = tracing_subscriber::registry().with(sandbox_agent::debug_logs::layer());
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let span = tracing::info_span!(target: "sandbox_agent::test", "probe_span", request_id = 7);
+    span.in_scope(|| {
+        tracing::debug!(target: "sandbox_agent::test", probe = "debug-logs", "buffered debug record");
+    });
+
+    let test_app = TestApp::new(AuthConfig::disabled());
+    let probe = |body: &[u8]| {
+        parse_json(body)["records"]
+            .as_array()
+            .expect("records")
+            .iter()
+            .find(|record| record["fields"]["probe"] == "debug-logs")
+            .cloned()
+    };
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/debug/logs?level=debug&limit=500",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let record = probe(&body).expect("debug record is buffered");
+    assert_eq!(record["level"], "debug");
+    assert_eq!(record["target"], "sandbox_agent::test");
+    assert_eq!(record["message"], "buffered debug record");
+    assert_eq!(record["fields"]["request_id"], 7);
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/debug/logs", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(probe(&body).is_none());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/debug/logs?level=trace&limit=1",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse_json(&body)["records"].as_array().unwrap().len(), 1);
+
+    let (status, _, _) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/debug/logs?level=loud",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn v1_debug_unparsed_reports_counts_per_server() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "parsed", "mock").await;
+
+    let (status, _, body) =
+        send_request(&test_app.app, Method::GET, "/v1/debug/unparsed", None, &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let unparsed = parse_json(&body);
+    assert_eq!(unparsed["total"], 0);
+    assert_eq!(unparsed["servers"], json!([]));
+    assert_eq!(unparsed["events"], json!([]));
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/debug/unparsed?serverId=parsed",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let servers = &parse_json(&body)["servers"];
+    assert_eq!(servers[0]["serverId"], "parsed");
+    assert_eq!(servers[0]["count"], 0);
+}
+
+#[tokio::test]
+async fn v1_debug_rate_limits_counts_agent_rate_limits() {
+    let test_app = TestApp::new(AuthConfig::disabled());
+    bootstrap_server(&test_app.app, "limited", "mock").await;
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/limited",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "session/new",
+            "params": { "cwd": "/", "mcpServers": [] }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = parse_json(&body)["result"]["sessionId"].clone();
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::POST,
+        "/v1/acp/limited",
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "session/prompt",
+            "params": {
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": "go" }],
+                "_meta": { "sandboxagent.dev": { "mockScript": [{
+                    "type": "error",
+                    "message": "429 Too Many Requests, retry after 12 seconds"
+                }] } }
+            }
+        })),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(parse_json(&body)["error"].is_object());
+
+    let (status, _, body) = send_request(
+        &test_app.app,
+        Method::GET,
+        "/v1/debug/rate-limits?serverId=limited",
+        None,
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let limits = parse_json(&body);
+    assert_eq!(limits["total"], 1);
+    assert_eq!(limits["agents"][0]["agent"], "mock");
+    assert_eq!(limits["servers"][0]["rateLimited"], 1);
+    assert_eq!(limits["events"][0]["kind"], "rate_limit");
+    assert_eq!(limits["events"][0]["retryAfterMs"], 12_000);
+    assert_eq!(limits["events"][0]["sessionId"], session_id);
+}
+
 #[tokio::test]
 #[serial]
 async fn require_preinstall_blocks_missing_agent() {